@@ -0,0 +1,38 @@
+/*
+ * Example of using the C ABI. Build and run with:
+ *
+ *   cargo build --lib --no-default-features --features ffi
+ *   cc -Iinclude examples/ffi.c -Ltarget/debug -ltransaction_processor -o ffi
+ *   LD_LIBRARY_PATH=target/debug ./ffi
+ */
+
+#include <stdio.h>
+
+#include "transaction_processor.h"
+
+int main(void) {
+    TxpProcessor *processor = txp_processor_new();
+
+    /* 1.5 and 2.0, in minor units */
+    txp_processor_transact(processor, TXP_TRANSACTION_DEPOSIT, 1, 1, 15000);
+    TxpStatus status =
+        txp_processor_transact(processor, TXP_TRANSACTION_WITHDRAWAL, 1, 2, 20000);
+    if (status != TXP_OK) {
+        printf("Withdrawal failed: %s\n", txp_status_message(status));
+    }
+
+    TxpReport *report = txp_report_new(processor);
+    TxpReportEntry entry;
+    while (txp_report_next(report, &entry) == TXP_OK) {
+        printf("client %llu: available %lld, held %lld, total %lld, locked %d\n",
+               (unsigned long long)entry.client,
+               (long long)entry.available,
+               (long long)entry.held,
+               (long long)entry.total,
+               entry.locked);
+    }
+
+    txp_report_free(report);
+    txp_processor_free(processor);
+    return 0;
+}
@@ -0,0 +1,106 @@
+/*
+ * C ABI of the transaction processor, built with `--features ffi`.
+ *
+ * Link against the `cdylib` (e.g. `libtransaction_processor.so`). Amounts are
+ * in minor units with 4 decimal places, e.g. `15000` for `1.5`. Processors and
+ * reports are not thread-safe: each must only be used by one thread at a time.
+ */
+
+#ifndef TRANSACTION_PROCESSOR_H
+#define TRANSACTION_PROCESSOR_H
+
+#include <stdbool.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* The result of every call which can fail. Values never change. */
+typedef enum TxpStatus {
+    TXP_OK = 0,
+    /* `txp_report_next` has returned every entry. */
+    TXP_END_OF_REPORT = 1,
+    TXP_NULL_POINTER = -1,
+    TXP_INVALID_TRANSACTION_TYPE = -2,
+    /* An amount was negative, or an amount in the report does not fit in an
+     * `int64_t`. */
+    TXP_INVALID_AMOUNT = -3,
+    TXP_TRANSACTION_DOES_NOT_EXIST = -10,
+    TXP_TRANSACTION_ALREADY_EXISTS = -11,
+    TXP_DISPUTE_ALREADY_EXISTS = -12,
+    TXP_DISPUTE_DOES_NOT_EXIST = -13,
+    TXP_CURRENCY_ERROR = -14,
+    TXP_ACCOUNT_IS_LOCKED = -15,
+    TXP_NOT_ENOUGH_FUNDS = -16,
+    TXP_REPLAYED = -17,
+    TXP_BALANCE_CAP_EXCEEDED = -18,
+    TXP_TRANSACTION_LIMIT_REACHED = -19,
+    TXP_ACCOUNT_IS_FROZEN = -20,
+    TXP_DISPUTE_NOT_ALLOWED = -21,
+    TXP_UNKNOWN_CLIENT = -22,
+    TXP_CLIENT_IDS_EXHAUSTED = -23,
+} TxpStatus;
+
+#define TXP_TRANSACTION_DEPOSIT 0u
+#define TXP_TRANSACTION_WITHDRAWAL 1u
+#define TXP_TRANSACTION_DISPUTE 2u
+#define TXP_TRANSACTION_RESOLVE 3u
+#define TXP_TRANSACTION_CHARGEBACK 4u
+
+/* Client and transaction IDs. Define `TXP_WIDE_IDS` if the library was
+ * built with `--features wide-ids`. */
+#ifdef TXP_WIDE_IDS
+typedef uint64_t TxpClientId;
+typedef uint64_t TxpTransactionId;
+#else
+typedef uint16_t TxpClientId;
+typedef uint32_t TxpTransactionId;
+#endif
+
+typedef struct TxpProcessor TxpProcessor;
+typedef struct TxpReport TxpReport;
+
+typedef struct TxpReportEntry {
+    TxpClientId client;
+    int64_t available;
+    int64_t held;
+    int64_t total;
+    bool locked;
+} TxpReportEntry;
+
+/* Creates a processor with no client accounts. */
+TxpProcessor *txp_processor_new(void);
+
+/* Frees a processor. Does nothing if `processor` is null. */
+void txp_processor_free(TxpProcessor *processor);
+
+/* Applies a transaction. `transaction_type` is one of the
+ * `TXP_TRANSACTION_*` constants, and `amount` is only used for deposits and
+ * withdrawals. */
+TxpStatus txp_processor_transact(TxpProcessor *processor,
+                                 uint32_t transaction_type,
+                                 TxpClientId client,
+                                 TxpTransactionId tx,
+                                 int64_t amount);
+
+/* Creates an iterator over the current report, ordered by client ID. Later
+ * transactions don't affect it. Returns null if `processor` is null. */
+TxpReport *txp_report_new(const TxpProcessor *processor);
+
+/* Writes the next entry to `entry`, or returns `TXP_END_OF_REPORT`. An entry
+ * whose amounts don't fit in an `int64_t` is skipped with
+ * `TXP_INVALID_AMOUNT`. */
+TxpStatus txp_report_next(TxpReport *report, TxpReportEntry *entry);
+
+/* Frees a report. Does nothing if `report` is null. */
+void txp_report_free(TxpReport *report);
+
+/* A static description of `status`, which must not be freed. */
+const char *txp_status_message(int32_t status);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* TRANSACTION_PROCESSOR_H */
@@ -0,0 +1,403 @@
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use tokio::sync::{mpsc, oneshot};
+use tokio::task::JoinHandle;
+
+use crate::{
+    ClientId, MergeError, ReportEntry, Transaction, TransactionError, TransactionProcessor,
+};
+
+/// The number of transactions a client's actor can have waiting, by default
+/// (see [`ActorTransactionProcessor::with_channel_capacity`]).
+pub const DEFAULT_CHANNEL_CAPACITY: usize = 1024;
+
+/// Error returned by an [`ActorTransactionProcessor`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum ActorError {
+    /// The transaction failed to be applied.
+    TransactionError(TransactionError),
+    /// The actor of this client stopped (e.g. it panicked) before replying,
+    /// so its account is lost.
+    ActorFailed(ClientId),
+    /// The accounts of the actors could not be combined.
+    MergeError(MergeError),
+}
+
+impl From<TransactionError> for ActorError {
+    fn from(err: TransactionError) -> Self {
+        ActorError::TransactionError(err)
+    }
+}
+
+impl Display for ActorError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ActorError::TransactionError(err) => write!(f, "{}", err),
+            ActorError::ActorFailed(client) => write!(f, "The actor of client {} failed", client),
+            ActorError::MergeError(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+/// A request to a client's actor.
+enum Message {
+    Transact(Transaction, oneshot::Sender<Result<(), TransactionError>>),
+    Report(oneshot::Sender<Option<ReportEntry>>),
+}
+
+/// A client's actor: the task which owns its account, and the channel of
+/// requests to it.
+struct Actor {
+    messages: mpsc::Sender<Message>,
+    task: JoinHandle<TransactionProcessor>,
+}
+
+/// The outcome of a transaction sent to an [`ActorTransactionProcessor`],
+/// which resolves once the client's actor has applied it. It can be dropped
+/// if the outcome isn't needed, and the transaction is still applied.
+#[must_use = "the outcome of the transaction is only known once awaited"]
+pub struct TransactionOutcome {
+    client: ClientId,
+    outcome: oneshot::Receiver<Result<(), TransactionError>>,
+}
+
+impl Future for TransactionOutcome {
+    type Output = Result<(), ActorError>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let client = self.client;
+        Pin::new(&mut self.outcome).poll(cx).map(|outcome| {
+            // The outcome is only dropped unsent if the actor stopped
+            outcome
+                .map_err(|_| ActorError::ActorFailed(client))?
+                .map_err(ActorError::from)
+        })
+    }
+}
+
+/// An async processor in which each client's account is owned by an actor:
+/// a tokio task which applies the client's transactions as they are
+/// received over a channel. As each actor applies its transactions in the
+/// order they were sent, the transactions of a client keep their order,
+/// while those of different clients are applied in parallel (on a
+/// multi-threaded runtime), without any locking.
+///
+/// Each actor's channel is bounded, so sending a transaction to a client
+/// with [`ActorTransactionProcessor::with_channel_capacity`] transactions
+/// already waiting waits for the actor to catch up, slowing down ingestion
+/// rather than buffering without limit.
+///
+/// An actor is spawned for each client on its first transaction, so the
+/// processor must be used within a tokio runtime. A new client key gets the
+/// lowest client ID which has no account or actor yet.
+pub struct ActorTransactionProcessor {
+    /// The options of the actors' processors, and the accounts which don't
+    /// have an actor yet.
+    template: TransactionProcessor,
+    actors: HashMap<ClientId, Actor>,
+    /// The clients whose accounts were created for a client key, by key.
+    client_keys: HashMap<String, ClientId>,
+    channel_capacity: usize,
+}
+
+impl ActorTransactionProcessor {
+    /// Creates a processor with the accounts and options of
+    /// `transaction_processor`.
+    #[must_use]
+    pub fn new(mut transaction_processor: TransactionProcessor) -> Self {
+        let client_keys = std::mem::take(&mut transaction_processor.client_keys);
+        Self {
+            template: transaction_processor,
+            actors: HashMap::new(),
+            client_keys,
+            channel_capacity: DEFAULT_CHANNEL_CAPACITY,
+        }
+    }
+
+    /// Sets the number of transactions each client's actor can have waiting
+    /// (at least one) before sending another waits.
+    #[must_use]
+    pub fn with_channel_capacity(mut self, channel_capacity: usize) -> Self {
+        self.channel_capacity = channel_capacity.max(1);
+        self
+    }
+
+    /// The number of actors spawned so far, one per client.
+    #[must_use]
+    pub fn actors(&self) -> usize {
+        self.actors.len()
+    }
+
+    /// Sends a transaction to its client's actor, spawning the actor if the
+    /// client doesn't have one. Waits while the actor's channel is full.
+    ///
+    /// Returns the outcome of the transaction, which can be awaited for the
+    /// result of [`TransactionProcessor::transact`], or
+    /// [`ActorError::ActorFailed`] if the client's actor has stopped.
+    pub async fn send(&mut self, transaction: Transaction) -> TransactionOutcome {
+        let client = match transaction.client_key() {
+            Some(key) => match self.client_keys.get(key) {
+                Some(client) => Some(*client),
+                None => self.free_client().inspect(|client| {
+                    self.client_keys.insert(key.to_string(), *client);
+                    self.template.next_keyed_client = client.saturating_add(1);
+                }),
+            },
+            None => Some(transaction.client()),
+        };
+
+        // Without an ID, the error is sent straight away and the ID is unused
+        let outcome_client = client.unwrap_or(transaction.client());
+        let (outcome, outcome_receiver) = oneshot::channel();
+        match client {
+            Some(client) => {
+                let messages = self.actor(client).messages.clone();
+                // If the actor has stopped, the outcome is dropped with the
+                // message
+                let _ = messages.send(Message::Transact(transaction, outcome)).await;
+            }
+            None => {
+                let _ = outcome.send(Err(TransactionError::ClientIdsExhausted));
+            }
+        }
+        TransactionOutcome {
+            client: outcome_client,
+            outcome: outcome_receiver,
+        }
+    }
+
+    /// Sends a transaction to its client's actor (see
+    /// [`ActorTransactionProcessor::send`]), and waits for it to be applied.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error from [`TransactionProcessor::transact`] if the
+    /// transaction fails to be applied, or [`ActorError::ActorFailed`] if the
+    /// client's actor has stopped.
+    pub async fn transact(&mut self, transaction: Transaction) -> Result<(), ActorError> {
+        self.send(transaction).await.await
+    }
+
+    /// Returns the report entry for a single client account (see
+    /// [`TransactionProcessor::account_report`]), once the client's actor
+    /// has applied the transactions already sent to it.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ActorError::ActorFailed`] if the client's actor has
+    /// stopped.
+    pub async fn account_report(
+        &self,
+        client: ClientId,
+    ) -> Result<Option<ReportEntry>, ActorError> {
+        let Some(actor) = self.actors.get(&client) else {
+            return Ok(self.template.account_report(client));
+        };
+        let (report, report_receiver) = oneshot::channel();
+        let _ = actor.messages.send(Message::Report(report)).await;
+        report_receiver
+            .await
+            .map_err(|_| ActorError::ActorFailed(client))
+    }
+
+    /// Waits for every actor to apply the transactions sent to it, and
+    /// combines their accounts into a single processor.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ActorError::ActorFailed`] if an actor stopped before
+    /// applying all of its transactions (the lowest such client is
+    /// reported), or [`ActorError::MergeError`] if the accounts could not be
+    /// combined.
+    pub async fn finish(self) -> Result<TransactionProcessor, ActorError> {
+        let mut transaction_processor = self.template;
+        let mut actors = self.actors.into_iter().collect::<Vec<_>>();
+        actors.sort_unstable_by_key(|(client, _)| *client);
+        for (client, actor) in actors {
+            drop(actor.messages);
+            let accounts = actor
+                .task
+                .await
+                .map_err(|_| ActorError::ActorFailed(client))?;
+            transaction_processor
+                .merge(accounts)
+                .map_err(ActorError::MergeError)?;
+        }
+
+        // A key's ID is only kept if its account was created
+        let clients = &transaction_processor.clients;
+        transaction_processor.client_keys = self
+            .client_keys
+            .into_iter()
+            .filter(|(_, client)| clients.contains_key(client))
+            .collect();
+        Ok(transaction_processor)
+    }
+
+    /// The lowest client ID which may be given to a new client key.
+    fn free_client(&self) -> Option<ClientId> {
+        (self.template.next_keyed_client..=ClientId::MAX).find(|client| {
+            !self.actors.contains_key(client) && !self.template.clients.contains_key(client)
+        })
+    }
+
+    /// The actor of a client, which is spawned with the client's account if
+    /// it doesn't have one.
+    fn actor(&mut self, client: ClientId) -> &Actor {
+        let template = &mut self.template;
+        let channel_capacity = self.channel_capacity;
+        self.actors.entry(client).or_insert_with(|| {
+            let mut transaction_processor = template.new_like();
+            if let Some(account) = template.clients.remove(&client) {
+                if let Some(key) = &account.key {
+                    transaction_processor
+                        .client_keys
+                        .insert(key.clone(), client);
+                }
+                transaction_processor.clients.insert(client, account);
+            }
+
+            let (messages, receiver) = mpsc::channel(channel_capacity);
+            Actor {
+                messages,
+                task: tokio::spawn(run_actor(transaction_processor, client, receiver)),
+            }
+        })
+    }
+}
+
+/// Applies the requests to the account of `client` until the channel is
+/// closed, returning the processor holding the account.
+async fn run_actor(
+    mut transaction_processor: TransactionProcessor,
+    client: ClientId,
+    mut messages: mpsc::Receiver<Message>,
+) -> TransactionProcessor {
+    while let Some(message) = messages.recv().await {
+        match message {
+            Message::Transact(transaction, outcome) => {
+                let _ = outcome.send(transaction_processor.transact_for(client, &transaction));
+            }
+            Message::Report(report) => {
+                let _ = report.send(transaction_processor.account_report(client));
+            }
+        }
+    }
+    transaction_processor
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+
+    use crate::actor::{ActorError, ActorTransactionProcessor};
+    use crate::numeric::CurrencyAmount;
+    use crate::{Transaction, TransactionError, TransactionProcessor, TransactionType};
+
+    #[test]
+    fn test_actor_transaction_processor() {
+        let deposit = |client, tx| {
+            Transaction::new(
+                client,
+                tx,
+                TransactionType::Deposit {
+                    amount: CurrencyAmount::from_str("2").unwrap(),
+                },
+            )
+        };
+        let mut transaction_processor = TransactionProcessor::new();
+        transaction_processor.transact(&deposit(3, 1)).unwrap();
+        transaction_processor
+            .transact(&deposit(0, 1).with_client_key("acme"))
+            .unwrap();
+
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(2)
+            .build()
+            .unwrap();
+        let (report, transaction_processor) = runtime.block_on(async {
+            let mut actors =
+                ActorTransactionProcessor::new(transaction_processor).with_channel_capacity(2);
+
+            // The transactions of each client are applied in order
+            let mut outcomes = Vec::new();
+            for tx in 2..50 {
+                for client in 1..=4 {
+                    outcomes.push(actors.send(deposit(client, tx)).await);
+                }
+                let dispute = Transaction::new(1, tx, TransactionType::Dispute);
+                outcomes.push(actors.send(dispute).await);
+            }
+            for outcome in outcomes {
+                outcome.await.unwrap();
+            }
+
+            // Existing accounts and keys are kept, and new keys get free IDs
+            assert_eq!(
+                Err(ActorError::TransactionError(
+                    TransactionError::TransactionAlreadyExists { client: 0, tx: 1 }
+                )),
+                actors.transact(deposit(7, 1).with_client_key("acme")).await
+            );
+            actors
+                .transact(deposit(7, 1).with_client_key("new"))
+                .await
+                .unwrap();
+            assert_eq!(6, actors.actors());
+
+            let report = actors.account_report(1).await.unwrap().unwrap();
+            (report, actors.finish().await.unwrap())
+        });
+
+        assert_eq!(CurrencyAmount::from_str("96").unwrap(), report.held);
+        assert_eq!(CurrencyAmount::ZERO, report.available);
+        let report = transaction_processor.generate_report_as_vec();
+        assert_eq!(
+            vec![0, 1, 2, 3, 4, 5],
+            report.iter().map(|entry| entry.client).collect::<Vec<_>>()
+        );
+        assert_eq!(CurrencyAmount::from_str("98").unwrap(), report[3].available);
+        assert_eq!(Some("new"), report[5].client_key());
+        assert_eq!(Some(&5), transaction_processor.client_keys.get("new"));
+    }
+
+    #[test]
+    fn test_failed_actor() {
+        let deposit = |tx| {
+            Transaction::new(
+                1,
+                tx,
+                TransactionType::Deposit {
+                    amount: CurrencyAmount::from_str("2").unwrap(),
+                },
+            )
+        };
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        runtime.block_on(async {
+            let mut actors = ActorTransactionProcessor::new(TransactionProcessor::new());
+            actors.transact(deposit(1)).await.unwrap();
+            actors.actors[&1].task.abort();
+
+            // The outcomes are errors rather than panics
+            assert_eq!(
+                Err(ActorError::ActorFailed(1)),
+                actors.transact(deposit(2)).await
+            );
+            assert_eq!(
+                Err(ActorError::ActorFailed(1)),
+                actors.account_report(1).await
+            );
+            assert_eq!(Ok(None), actors.account_report(2).await);
+            assert_eq!(
+                Some(ActorError::ActorFailed(1)),
+                actors.finish().await.err()
+            );
+        });
+    }
+}
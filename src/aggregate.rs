@@ -0,0 +1,536 @@
+use std::collections::BTreeMap;
+use std::num::NonZeroU64;
+
+#[cfg(feature = "serde")]
+use serde::ser::SerializeStruct;
+#[cfg(feature = "serde")]
+use serde::{Serialize, Serializer};
+
+use crate::numeric::{CurrencyAmount, CurrencyError};
+use crate::timestamp::Timestamp;
+use crate::{ClientId, Transaction, TransactionError, TransactionProcessor, TransactionType};
+
+/// The length of the periods which an [`Aggregator`] totals transactions
+/// over. Periods are in UTC.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub enum Period {
+    /// Hours, starting on the hour.
+    Hour,
+    /// Days, starting at midnight.
+    #[default]
+    Day,
+}
+
+impl Period {
+    /// The length of the period in seconds.
+    #[must_use]
+    pub const fn seconds(&self) -> NonZeroU64 {
+        match self {
+            Period::Hour => NonZeroU64::new(3600).unwrap(),
+            Period::Day => NonZeroU64::new(86_400).unwrap(),
+        }
+    }
+
+    /// The name of the period, e.g. `"day"`.
+    #[must_use]
+    pub const fn name(&self) -> &'static str {
+        match self {
+            Period::Hour => "hour",
+            Period::Day => "day",
+        }
+    }
+
+    /// The start of the period which `timestamp` falls in.
+    #[must_use]
+    pub const fn start(&self, timestamp: Timestamp) -> Timestamp {
+        timestamp.truncate(self.seconds())
+    }
+}
+
+/// The totals of the transactions applied in a period (see [`Aggregator`]).
+///
+/// Amounts are as applied (e.g. after rounding). Withdrawals are totalled as
+/// positive amounts, and chargebacks by the amount which was held, so a
+/// chargeback of a disputed withdrawal counts negatively.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Aggregate {
+    deposits: CurrencyAmount,
+    deposit_count: u64,
+    withdrawals: CurrencyAmount,
+    withdrawal_count: u64,
+    disputes: u64,
+    resolves: u64,
+    chargebacks: u64,
+    charged_back: CurrencyAmount,
+}
+
+impl Default for Aggregate {
+    fn default() -> Self {
+        Self {
+            deposits: CurrencyAmount::ZERO,
+            deposit_count: 0,
+            withdrawals: CurrencyAmount::ZERO,
+            withdrawal_count: 0,
+            disputes: 0,
+            resolves: 0,
+            chargebacks: 0,
+            charged_back: CurrencyAmount::ZERO,
+        }
+    }
+}
+
+impl Aggregate {
+    /// The total amount deposited.
+    #[must_use]
+    pub const fn deposits(&self) -> CurrencyAmount {
+        self.deposits
+    }
+
+    /// The number of deposits.
+    #[must_use]
+    pub const fn deposit_count(&self) -> u64 {
+        self.deposit_count
+    }
+
+    /// The total amount withdrawn.
+    #[must_use]
+    pub const fn withdrawals(&self) -> CurrencyAmount {
+        self.withdrawals
+    }
+
+    /// The number of withdrawals.
+    #[must_use]
+    pub const fn withdrawal_count(&self) -> u64 {
+        self.withdrawal_count
+    }
+
+    /// The number of disputes.
+    #[must_use]
+    pub const fn disputes(&self) -> u64 {
+        self.disputes
+    }
+
+    /// The number of resolves.
+    #[must_use]
+    pub const fn resolves(&self) -> u64 {
+        self.resolves
+    }
+
+    /// The number of chargebacks.
+    #[must_use]
+    pub const fn chargebacks(&self) -> u64 {
+        self.chargebacks
+    }
+
+    /// The total amount charged back.
+    #[must_use]
+    pub const fn charged_back(&self) -> CurrencyAmount {
+        self.charged_back
+    }
+
+    /// Returns these totals with all amounts formatted to exactly `scale`
+    /// decimal places (see [`CurrencyAmount::with_scale`]).
+    #[must_use]
+    pub fn with_scale(self, scale: u32) -> Self {
+        Self {
+            deposits: self.deposits.with_scale(scale),
+            withdrawals: self.withdrawals.with_scale(scale),
+            charged_back: self.charged_back.with_scale(scale),
+            ..self
+        }
+    }
+
+    /// Adds an applied transaction of `amount` (as stored by the processor,
+    /// or held by a dispute) to the totals.
+    fn record(
+        &mut self,
+        transaction_type: &TransactionType,
+        amount: CurrencyAmount,
+    ) -> Result<(), CurrencyError> {
+        match transaction_type {
+            TransactionType::Deposit { .. } => {
+                self.deposit_count += 1;
+                self.deposits.checked_add_assign(amount)
+            }
+            // Stored as negative amounts
+            TransactionType::Withdrawal { .. } => {
+                self.withdrawal_count += 1;
+                self.withdrawals.checked_sub_assign(amount)
+            }
+            TransactionType::Dispute => {
+                self.disputes += 1;
+                Ok(())
+            }
+            TransactionType::Resolve => {
+                self.resolves += 1;
+                Ok(())
+            }
+            TransactionType::Chargeback => {
+                self.chargebacks += 1;
+                self.charged_back.checked_add_assign(amount)
+            }
+        }
+    }
+}
+
+/// The totals of one period, for one client or all of them (see
+/// [`Aggregator::entries`]).
+///
+/// It is serialized with the fields `period` (the start of the period: its
+/// date for [`Period::Day`], or its timestamp for [`Period::Hour`], or empty
+/// for transactions without a timestamp), `client` (empty for the totals of
+/// all clients), `deposits`, `deposit_count`, `withdrawals`,
+/// `withdrawal_count`, `disputes`, `resolves`, `chargebacks` and
+/// `charged_back`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct AggregateEntry {
+    period: Period,
+    start: Option<Timestamp>,
+    client: Option<ClientId>,
+    aggregate: Aggregate,
+}
+
+impl AggregateEntry {
+    /// The length of the period.
+    #[must_use]
+    pub const fn period(&self) -> Period {
+        self.period
+    }
+
+    /// The start of the period, or `None` for the transactions without a
+    /// timestamp.
+    #[must_use]
+    pub const fn start(&self) -> Option<Timestamp> {
+        self.start
+    }
+
+    /// The client the totals are for, or `None` if they are for all clients.
+    #[must_use]
+    pub const fn client(&self) -> Option<ClientId> {
+        self.client
+    }
+
+    /// The totals.
+    #[must_use]
+    pub const fn aggregate(&self) -> &Aggregate {
+        &self.aggregate
+    }
+
+    /// Returns this entry with all amounts formatted to exactly `scale`
+    /// decimal places (see [`CurrencyAmount::with_scale`]).
+    #[must_use]
+    pub fn with_scale(self, scale: u32) -> Self {
+        Self {
+            aggregate: self.aggregate.with_scale(scale),
+            ..self
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl Serialize for AggregateEntry {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let start = self.start.map(|start| match self.period {
+            Period::Hour => start.to_string(),
+            Period::Day => start.date().to_string(),
+        });
+        let aggregate = &self.aggregate;
+
+        let mut state = serializer.serialize_struct("AggregateEntry", 10)?;
+        state.serialize_field("period", &start)?;
+        state.serialize_field("client", &self.client)?;
+        state.serialize_field("deposits", &aggregate.deposits)?;
+        state.serialize_field("deposit_count", &aggregate.deposit_count)?;
+        state.serialize_field("withdrawals", &aggregate.withdrawals)?;
+        state.serialize_field("withdrawal_count", &aggregate.withdrawal_count)?;
+        state.serialize_field("disputes", &aggregate.disputes)?;
+        state.serialize_field("resolves", &aggregate.resolves)?;
+        state.serialize_field("chargebacks", &aggregate.chargebacks)?;
+        state.serialize_field("charged_back", &aggregate.charged_back)?;
+        state.end()
+    }
+}
+
+/// Totals the transactions applied to a processor by period, using their
+/// timestamps (see [`Transaction::timestamp`]), e.g. for daily settlement
+/// figures: the volume of deposits and withdrawals, and the number of
+/// disputes and chargebacks, for every client or for all of them together.
+///
+/// Transactions without a timestamp are totalled separately. A processor
+/// only keeps what it needs to apply later transactions, so transactions
+/// must be applied through the aggregator to be totalled.
+#[derive(Clone, Debug, Default)]
+pub struct Aggregator {
+    period: Period,
+    per_client: bool,
+    /// The totals by period start (`None` without a timestamp) and client
+    /// (`None` unless `per_client` is set).
+    aggregates: BTreeMap<(Option<Timestamp>, Option<ClientId>), Aggregate>,
+    /// The first overflow of a total, which is reported by
+    /// [`Aggregator::entries`].
+    error: Option<CurrencyError>,
+}
+
+impl Aggregator {
+    /// Creates an aggregator which totals all clients' transactions by
+    /// `period`.
+    #[must_use]
+    pub fn new(period: Period) -> Self {
+        Self {
+            period,
+            ..Self::default()
+        }
+    }
+
+    /// Returns this aggregator with separate totals for every client, if
+    /// `per_client` is set.
+    #[must_use]
+    pub fn with_per_client(self, per_client: bool) -> Self {
+        Self { per_client, ..self }
+    }
+
+    /// The length of the periods totalled over.
+    #[must_use]
+    pub const fn period(&self) -> Period {
+        self.period
+    }
+
+    /// True if every client's transactions are totalled separately.
+    #[must_use]
+    pub const fn per_client(&self) -> bool {
+        self.per_client
+    }
+
+    /// Applies `transaction` to `transaction_processor`, and adds it to the
+    /// totals if it was applied.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error from [`TransactionProcessor::transact`], in which
+    /// case nothing is added.
+    pub fn transact(
+        &mut self,
+        transaction_processor: &mut TransactionProcessor,
+        transaction: &Transaction,
+    ) -> Result<(), TransactionError> {
+        self.transact_with(
+            transaction_processor,
+            transaction,
+            TransactionProcessor::transact,
+        )
+    }
+
+    /// Like [`Aggregator::transact`], but applies the transaction with
+    /// `apply`, which must call [`TransactionProcessor::transact`] (e.g. to
+    /// also post it to a [`crate::ledger::Ledger`]).
+    ///
+    /// # Errors
+    ///
+    /// Returns the error from `apply`, in which case nothing is added.
+    pub fn transact_with(
+        &mut self,
+        transaction_processor: &mut TransactionProcessor,
+        transaction: &Transaction,
+        apply: impl FnOnce(&mut TransactionProcessor, &Transaction) -> Result<(), TransactionError>,
+    ) -> Result<(), TransactionError> {
+        let stored_amount = |transaction_processor: &TransactionProcessor| {
+            transaction_processor
+                .clients
+                .get(&transaction.client)
+                .and_then(|account| account.transactions.get(&transaction.tx))
+                .copied()
+        };
+
+        // A chargeback forgets the transaction, so its amount is found first
+        let disputed = stored_amount(transaction_processor);
+        apply(transaction_processor, transaction)?;
+        let amount = match transaction.transaction_type {
+            TransactionType::Deposit { .. } | TransactionType::Withdrawal { .. } => {
+                stored_amount(transaction_processor)
+            }
+            TransactionType::Dispute | TransactionType::Resolve | TransactionType::Chargeback => {
+                disputed
+            }
+        };
+        // Always set once a transaction has been applied
+        let Some(amount) = amount else {
+            return Ok(());
+        };
+
+        let start = transaction
+            .timestamp
+            .map(|timestamp| self.period.start(timestamp));
+        let client = self.per_client.then_some(transaction.client);
+        let result = self
+            .aggregates
+            .entry((start, client))
+            .or_default()
+            .record(&transaction.transaction_type, amount);
+        if let Err(err) = result {
+            self.error.get_or_insert(err);
+        }
+        Ok(())
+    }
+
+    /// The totals of every period with applied transactions, in order of the
+    /// period's start and then the client, followed by those of the
+    /// transactions without a timestamp.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a total overflowed.
+    pub fn entries(&self) -> Result<Vec<AggregateEntry>, CurrencyError> {
+        if let Some(err) = &self.error {
+            return Err(err.clone());
+        }
+
+        // `None` sorts first, but the untimed totals are listed last
+        let (untimed, timed): (Vec<_>, Vec<_>) = self
+            .aggregates
+            .iter()
+            .map(|(&(start, client), aggregate)| AggregateEntry {
+                period: self.period,
+                start,
+                client,
+                aggregate: *aggregate,
+            })
+            .partition(|entry| entry.start.is_none());
+        Ok(timed.into_iter().chain(untimed).collect())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+
+    use crate::aggregate::{Aggregator, Period};
+    use crate::numeric::CurrencyAmount;
+    use crate::timestamp::Timestamp;
+    use crate::{Transaction, TransactionError, TransactionProcessor, TransactionType};
+
+    #[test]
+    fn test_aggregator() {
+        let amount = |amount| CurrencyAmount::from_str(amount).unwrap();
+        let at = |timestamp| Timestamp::from_str(timestamp).unwrap();
+        let transactions = [
+            Transaction::new(
+                1,
+                1,
+                TransactionType::Deposit {
+                    amount: amount("10"),
+                },
+            )
+            .with_timestamp(at("2024-03-01T09:00:00Z")),
+            Transaction::new(
+                2,
+                2,
+                TransactionType::Deposit {
+                    amount: amount("5"),
+                },
+            )
+            .with_timestamp(at("2024-03-01T17:30:00Z")),
+            Transaction::new(
+                1,
+                3,
+                TransactionType::Withdrawal {
+                    amount: amount("4"),
+                },
+            )
+            .with_timestamp(at("2024-03-01T23:59:59Z")),
+            Transaction::new(1, 1, TransactionType::Dispute)
+                .with_timestamp(at("2024-03-02T00:00:00Z")),
+            Transaction::new(1, 1, TransactionType::Chargeback)
+                .with_timestamp(at("2024-03-02T01:00:00Z")),
+            Transaction::new(
+                2,
+                4,
+                TransactionType::Deposit {
+                    amount: amount("1"),
+                },
+            ),
+        ];
+
+        let mut tp = TransactionProcessor::new();
+        let mut aggregator = Aggregator::new(Period::Day);
+        for transaction in &transactions {
+            aggregator.transact(&mut tp, transaction).unwrap();
+        }
+        // Rejected transactions aren't totalled
+        assert_eq!(
+            Err(TransactionError::AccountIsLocked {
+                client: 1,
+                transaction_type: TransactionType::Deposit {
+                    amount: amount("1")
+                },
+            }),
+            aggregator.transact(
+                &mut tp,
+                &Transaction::new(
+                    1,
+                    5,
+                    TransactionType::Deposit {
+                        amount: amount("1")
+                    }
+                )
+                .with_timestamp(at("2024-03-02T02:00:00Z"))
+            )
+        );
+
+        let entries = aggregator.entries().unwrap();
+        assert_eq!(
+            vec![
+                Some(at("2024-03-01T00:00:00Z")),
+                Some(at("2024-03-02T00:00:00Z")),
+                None
+            ],
+            entries
+                .iter()
+                .map(|entry| entry.start())
+                .collect::<Vec<_>>()
+        );
+        let day = entries[0].aggregate();
+        assert_eq!(
+            (amount("15"), 2, amount("4"), 1),
+            (
+                day.deposits(),
+                day.deposit_count(),
+                day.withdrawals(),
+                day.withdrawal_count()
+            )
+        );
+        let day = entries[1].aggregate();
+        assert_eq!(
+            (1, 1, amount("10"), amount("0")),
+            (
+                day.disputes(),
+                day.chargebacks(),
+                day.charged_back(),
+                day.deposits()
+            )
+        );
+        assert_eq!(amount("1"), entries[2].aggregate().deposits());
+
+        // Per client and hour
+        let mut tp = TransactionProcessor::new();
+        let mut aggregator = Aggregator::new(Period::Hour).with_per_client(true);
+        for transaction in &transactions[..3] {
+            aggregator.transact(&mut tp, transaction).unwrap();
+        }
+        assert_eq!(
+            vec![
+                (Some(at("2024-03-01T09:00:00Z")), Some(1)),
+                (Some(at("2024-03-01T17:00:00Z")), Some(2)),
+                (Some(at("2024-03-01T23:00:00Z")), Some(1)),
+            ],
+            aggregator
+                .entries()
+                .unwrap()
+                .iter()
+                .map(|entry| (entry.start(), entry.client()))
+                .collect::<Vec<_>>()
+        );
+    }
+}
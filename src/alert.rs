@@ -0,0 +1,413 @@
+use std::collections::HashMap;
+use std::fmt::{Debug, Display, Formatter};
+
+use crate::middleware::{Next, TransactionMiddleware};
+use crate::numeric::CurrencyAmount;
+use crate::{
+    ClientId, ReportEntry, Transaction, TransactionError, TransactionId, TransactionProcessor,
+};
+
+/// The `log` target of alerts logged by the CLI, so that they can be
+/// filtered (or routed) separately.
+pub const ALERT_LOG_TARGET: &str = "transaction_processor::alerts";
+
+/// The balance thresholds of an account: a floor under its available funds,
+/// and a ceiling over its held funds. Either may be unset.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct BalanceThreshold {
+    floor: Option<CurrencyAmount>,
+    held_ceiling: Option<CurrencyAmount>,
+}
+
+impl BalanceThreshold {
+    /// Creates a threshold with neither a floor nor a ceiling.
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            floor: None,
+            held_ceiling: None,
+        }
+    }
+
+    /// Sets the floor which the available funds mustn't drop below.
+    #[must_use]
+    pub const fn with_floor(mut self, floor: CurrencyAmount) -> Self {
+        self.floor = Some(floor);
+        self
+    }
+
+    /// Sets the ceiling which the held funds mustn't rise above.
+    #[must_use]
+    pub const fn with_held_ceiling(mut self, held_ceiling: CurrencyAmount) -> Self {
+        self.held_ceiling = Some(held_ceiling);
+        self
+    }
+
+    /// The floor under the available funds, if set.
+    #[must_use]
+    pub const fn floor(&self) -> Option<CurrencyAmount> {
+        self.floor
+    }
+
+    /// The ceiling over the held funds, if set.
+    #[must_use]
+    pub const fn held_ceiling(&self) -> Option<CurrencyAmount> {
+        self.held_ceiling
+    }
+}
+
+/// The balance thresholds of every account: a global threshold, and
+/// per-client thresholds which replace it for their clients.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct BalanceThresholds {
+    global: BalanceThreshold,
+    clients: HashMap<ClientId, BalanceThreshold>,
+}
+
+impl BalanceThresholds {
+    /// Creates thresholds which never raise an alert.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the threshold of the clients without their own.
+    #[must_use]
+    pub fn with_global(mut self, threshold: BalanceThreshold) -> Self {
+        self.global = threshold;
+        self
+    }
+
+    /// Sets the threshold of a client, in place of the global one.
+    #[must_use]
+    pub fn with_client(mut self, client: ClientId, threshold: BalanceThreshold) -> Self {
+        self.clients.insert(client, threshold);
+        self
+    }
+
+    /// The threshold of a client.
+    #[must_use]
+    pub fn for_client(&self, client: ClientId) -> BalanceThreshold {
+        self.clients.get(&client).copied().unwrap_or(self.global)
+    }
+
+    /// True if no threshold has a floor or a ceiling.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.global == BalanceThreshold::new()
+            && self
+                .clients
+                .values()
+                .all(|threshold| *threshold == BalanceThreshold::new())
+    }
+}
+
+/// A balance threshold crossed by a transaction, raised by a
+/// [`BalanceMonitor`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum BalanceAlert {
+    /// The available funds dropped below the floor.
+    AvailableBelowFloor {
+        /// The transaction which crossed the threshold.
+        tx: TransactionId,
+        /// The state of the account after the transaction.
+        account: ReportEntry,
+        /// The floor.
+        floor: CurrencyAmount,
+    },
+    /// The held funds rose above the ceiling.
+    HeldAboveCeiling {
+        /// The transaction which crossed the threshold.
+        tx: TransactionId,
+        /// The state of the account after the transaction.
+        account: ReportEntry,
+        /// The ceiling.
+        ceiling: CurrencyAmount,
+    },
+}
+
+impl BalanceAlert {
+    /// The transaction which crossed the threshold.
+    #[must_use]
+    pub const fn tx(&self) -> TransactionId {
+        match self {
+            BalanceAlert::AvailableBelowFloor { tx, .. }
+            | BalanceAlert::HeldAboveCeiling { tx, .. } => *tx,
+        }
+    }
+
+    /// The state of the account after the transaction.
+    #[must_use]
+    pub const fn account(&self) -> &ReportEntry {
+        match self {
+            BalanceAlert::AvailableBelowFloor { account, .. }
+            | BalanceAlert::HeldAboveCeiling { account, .. } => account,
+        }
+    }
+}
+
+impl Display for BalanceAlert {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BalanceAlert::AvailableBelowFloor { tx, account, floor } => write!(
+                f,
+                "Available funds of client {} dropped below {} to {} (transaction {})",
+                account.client(),
+                floor,
+                account.available(),
+                tx
+            ),
+            BalanceAlert::HeldAboveCeiling {
+                tx,
+                account,
+                ceiling,
+            } => write!(
+                f,
+                "Held funds of client {} rose above {} to {} (transaction {})",
+                account.client(),
+                ceiling,
+                account.held(),
+                tx
+            ),
+        }
+    }
+}
+
+/// Raises a [`BalanceAlert`] to an observer whenever a transaction takes an
+/// account across one of its [`BalanceThresholds`], e.g. so that treasury is
+/// alerted rather than having to scan the report.
+///
+/// An alert is only raised when the threshold is crossed, not again for each
+/// later transaction while the account stays across it. A new account is
+/// taken to start within its thresholds, so e.g. an account opened with a
+/// deposit below a positive floor raises an alert.
+pub struct BalanceMonitor<F> {
+    thresholds: BalanceThresholds,
+    observer: F,
+}
+
+impl<F: FnMut(BalanceAlert)> BalanceMonitor<F> {
+    /// Creates a monitor which gives the alerts of `thresholds` to
+    /// `observer`.
+    pub fn new(thresholds: BalanceThresholds, observer: F) -> Self {
+        Self {
+            thresholds,
+            observer,
+        }
+    }
+
+    /// The thresholds which raise alerts.
+    #[must_use]
+    pub fn thresholds(&self) -> &BalanceThresholds {
+        &self.thresholds
+    }
+
+    /// Applies `transaction` to `transaction_processor`, raising any alerts
+    /// it causes.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error from [`TransactionProcessor::transact`].
+    pub fn transact(
+        &mut self,
+        transaction_processor: &mut TransactionProcessor,
+        transaction: &Transaction,
+    ) -> Result<(), TransactionError> {
+        self.transact_with(
+            transaction_processor,
+            transaction,
+            TransactionProcessor::transact,
+        )
+    }
+
+    /// Like [`BalanceMonitor::transact`], but applies the transaction with
+    /// `apply`, which must call [`TransactionProcessor::transact`] (e.g. to
+    /// also post it to a [`crate::ledger::Ledger`]).
+    ///
+    /// # Errors
+    ///
+    /// Returns the error from `apply`.
+    pub fn transact_with(
+        &mut self,
+        transaction_processor: &mut TransactionProcessor,
+        transaction: &Transaction,
+        apply: impl FnOnce(&mut TransactionProcessor, &Transaction) -> Result<(), TransactionError>,
+    ) -> Result<(), TransactionError> {
+        let before = account(transaction_processor, transaction);
+        apply(transaction_processor, transaction)?;
+        self.check(
+            transaction,
+            before,
+            account(transaction_processor, transaction),
+        );
+        Ok(())
+    }
+
+    /// Raises the alerts for the thresholds which the account crossed from
+    /// `before` to `after`, its report entries before and after `transaction`
+    /// was applied (`None` before its first transaction). This is for
+    /// callers which apply transactions themselves, e.g. so that the monitor
+    /// is only locked while checking.
+    pub fn check(
+        &mut self,
+        transaction: &Transaction,
+        before: Option<ReportEntry>,
+        after: Option<ReportEntry>,
+    ) {
+        // The account's total overflowed, which has already been logged
+        let Some(after) = after else {
+            return;
+        };
+        let threshold = self.thresholds.for_client(after.client());
+
+        let tx = transaction.tx();
+        if let Some(floor) = threshold.floor {
+            let was_above = before
+                .as_ref()
+                .is_none_or(|entry| entry.available() >= floor);
+            if was_above && after.available() < floor {
+                (self.observer)(BalanceAlert::AvailableBelowFloor {
+                    tx,
+                    account: after.clone(),
+                    floor,
+                });
+            }
+        }
+        if let Some(ceiling) = threshold.held_ceiling {
+            let was_below = before.as_ref().is_none_or(|entry| entry.held() <= ceiling);
+            if was_below && after.held() > ceiling {
+                (self.observer)(BalanceAlert::HeldAboveCeiling {
+                    tx,
+                    account: after,
+                    ceiling,
+                });
+            }
+        }
+    }
+}
+
+impl<F> Debug for BalanceMonitor<F> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("BalanceMonitor")
+            .field("thresholds", &self.thresholds)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Raises the alerts of the transactions passed through a
+/// [`crate::middleware::MiddlewareChain`], as [`BalanceMonitor::transact`]
+/// does.
+impl<F: FnMut(BalanceAlert)> TransactionMiddleware for BalanceMonitor<F> {
+    fn handle(
+        &mut self,
+        transaction: Transaction,
+        next: &mut dyn Next,
+    ) -> Result<(), TransactionError> {
+        let before = account(next.processor(), &transaction);
+        next.run(transaction.clone())?;
+        let after = account(next.processor(), &transaction);
+        self.check(&transaction, before, after);
+        Ok(())
+    }
+}
+
+/// The report entry of the account which a transaction applies to, if it
+/// has one.
+fn account(
+    transaction_processor: &TransactionProcessor,
+    transaction: &Transaction,
+) -> Option<ReportEntry> {
+    transaction_processor
+        .transaction_client(transaction)
+        .and_then(|client| transaction_processor.account_report(client))
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+
+    use crate::alert::{BalanceAlert, BalanceMonitor, BalanceThreshold, BalanceThresholds};
+    use crate::middleware::MiddlewareChain;
+    use crate::numeric::CurrencyAmount;
+    use crate::{Transaction, TransactionProcessor, TransactionType};
+
+    #[test]
+    fn test_balance_monitor() {
+        let amount = |amount| CurrencyAmount::from_str(amount).unwrap();
+        let deposit = |client, tx, value| {
+            Transaction::new(
+                client,
+                tx,
+                TransactionType::Deposit {
+                    amount: amount(value),
+                },
+            )
+        };
+        let withdrawal = |client, tx, value| {
+            Transaction::new(
+                client,
+                tx,
+                TransactionType::Withdrawal {
+                    amount: amount(value),
+                },
+            )
+        };
+        let thresholds = BalanceThresholds::new()
+            .with_global(BalanceThreshold::new().with_floor(amount("10")))
+            .with_client(2, BalanceThreshold::new().with_held_ceiling(amount("3")));
+        assert_eq!(None, thresholds.for_client(2).floor());
+
+        let mut alerts = Vec::new();
+        let mut transaction_processor = TransactionProcessor::new();
+        let mut monitor = BalanceMonitor::new(thresholds, |alert| alerts.push(alert));
+        for transaction in [
+            deposit(1, 1, "15"),
+            // Crosses the floor, and then stays below it
+            withdrawal(1, 2, "6"),
+            withdrawal(1, 3, "1"),
+            deposit(1, 4, "5"),
+            // Fails, so crosses nothing
+            withdrawal(1, 5, "100"),
+            // Client 2 only has a ceiling
+            deposit(2, 1, "4"),
+            withdrawal(2, 2, "4"),
+            deposit(2, 3, "4"),
+            Transaction::new(2, 3, TransactionType::Dispute),
+            // A new account can open below the floor
+            deposit(3, 1, "5"),
+        ] {
+            let _ = monitor.transact(&mut transaction_processor, &transaction);
+        }
+        assert!(!monitor.thresholds().is_empty());
+
+        assert_eq!(3, alerts.len());
+        assert!(matches!(
+            &alerts[0],
+            BalanceAlert::AvailableBelowFloor { tx: 2, account, .. }
+                if account.available() == amount("9")
+        ));
+        assert_eq!(
+            "Held funds of client 2 rose above 3 to 4 (transaction 3)",
+            alerts[1].to_string()
+        );
+        assert!(matches!(
+            &alerts[2],
+            BalanceAlert::AvailableBelowFloor { tx: 1, account, .. } if account.client() == 3
+        ));
+
+        // As a middleware
+        let mut alerts = Vec::new();
+        let mut chain = MiddlewareChain::new().with(BalanceMonitor::new(
+            BalanceThresholds::new().with_global(BalanceThreshold::new().with_floor(amount("10"))),
+            |alert: BalanceAlert| alerts.push(alert.tx()),
+        ));
+        chain
+            .transact(&mut transaction_processor, &withdrawal(1, 6, "4"))
+            .unwrap();
+        chain
+            .transact(&mut transaction_processor, &withdrawal(1, 7, "1"))
+            .unwrap();
+        drop(chain);
+        assert_eq!(vec![6], alerts);
+    }
+}
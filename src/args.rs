@@ -1,59 +1,2287 @@
 #![allow(clippy::module_name_repetitions)]
 
+use std::collections::HashSet;
+use std::ffi::OsString;
 use std::fmt::{Display, Formatter};
+#[cfg(any(feature = "server", feature = "grpc"))]
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
 
-use clap::{arg, Arg, Command};
+use clap::Command;
+use clap::{CommandFactory, FromArgMatches, Parser, Subcommand};
+use log::LevelFilter;
+use transaction_processor::aggregate::{Aggregator, Period};
+use transaction_processor::alert::BalanceThreshold;
+use transaction_processor::crypto::HmacKey;
+use transaction_processor::cutoff::Cutoff;
+use transaction_processor::idempotency;
+use transaction_processor::io::compression::Compression;
+use transaction_processor::io::csv::{
+    CSVWriterOptions, LineTerminator, QuoteStyle, REPORT_COLUMNS,
+};
+use transaction_processor::io::detect::DetectedFormat;
+use transaction_processor::io::ColumnMapping;
+use transaction_processor::numeric::{CurrencyAmount, LocaleFormat, PrecisionPolicy};
+use transaction_processor::report::{ClientSet, ReportFilter, ReportOrder};
+#[cfg(feature = "encryption")]
+use transaction_processor::snapshot::SnapshotKey;
+use transaction_processor::timestamp::Timestamp;
+use transaction_processor::{
+    AccountCreationPolicy, ClientId, LockedAccountPolicy, RedisputePolicy, TransactionLimitAction,
+};
+
+use crate::exit_code::ExitCode;
+use crate::inputs::{expand_inputs, ExpandError};
+#[cfg(feature = "kafka")]
+use crate::kafka::{KafkaOptions, KafkaPayload};
+use crate::output::ExistingOutput;
+#[cfg(feature = "server")]
+use crate::rate_limit::RateLimit;
+
+/// The largest number of decimal places that can be requested for the report.
+const MAX_OUTPUT_SCALE: u32 = 28;
+
+/// How often the report is written in `--follow` mode, if not specified.
+const DEFAULT_REPORT_INTERVAL_SECS: u64 = 5;
+
+/// The address the `serve` subcommand listens on, if not specified.
+#[cfg(feature = "server")]
+const DEFAULT_LISTEN_ADDRESS: &str = "127.0.0.1:8080";
+
+/// The address the `serve-grpc` subcommand listens on, if not specified.
+#[cfg(feature = "grpc")]
+const DEFAULT_GRPC_LISTEN_ADDRESS: &str = "127.0.0.1:50051";
+
+/// How often the `consume-kafka` subcommand checkpoints, if not specified.
+#[cfg(feature = "kafka")]
+const DEFAULT_CHECKPOINT_INTERVAL_SECS: u64 = 5;
+
+/// A long-running service to run instead of processing input files.
+#[cfg(any(feature = "server", feature = "grpc", feature = "kafka"))]
+#[derive(Clone, Debug)]
+pub enum Service {
+    /// The REST API server (`serve`), listening on the address, with the
+    /// per-client rate limit if any.
+    #[cfg(feature = "server")]
+    Rest(SocketAddr, Option<RateLimit>),
+    /// The gRPC server (`serve-grpc`), listening on the address.
+    #[cfg(feature = "grpc")]
+    Grpc(SocketAddr),
+    /// The Kafka consumer (`consume-kafka`).
+    #[cfg(feature = "kafka")]
+    Kafka(KafkaOptions),
+}
+
+/// The environment variable holding the key for `--verify-signatures`.
+pub const ROW_KEY_VARIABLE: &str = "TRANSACTION_PROCESSOR_ROW_KEY";
+
+/// The environment variable holding the key for `--report-signature`, if
+/// `--report-key-file` is not given.
+pub const REPORT_KEY_VARIABLE: &str = "TRANSACTION_PROCESSOR_REPORT_KEY";
+
+/// The environment variable holding the key for `--encrypt-snapshots`, if
+/// `--snapshot-key-file` is not given.
+#[cfg(feature = "encryption")]
+pub const SNAPSHOT_KEY_VARIABLE: &str = "TRANSACTION_PROCESSOR_SNAPSHOT_KEY";
+
+/// The format of the input files.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum InputFormat {
+    /// CSV with the columns `type,client,tx,amount`.
+    Csv,
+    /// Tab-separated values, with the same columns as CSV.
+    Tsv,
+    /// JSON Lines, with one transaction object per line.
+    Jsonl,
+    /// Avro object container files, using the published transaction schema.
+    #[cfg(feature = "avro")]
+    Avro,
+    /// Parquet with the columns `type`, `client`, `tx`, and `amount`.
+    #[cfg(feature = "parquet")]
+    Parquet,
+}
+
+impl InputFormat {
+    /// Guesses the format from the file extension (see
+    /// [`DetectedFormat::from_path`]), or CSV if it is not recognised.
+    pub fn from_path(path: &str) -> Self {
+        Self::from_extension(path).unwrap_or(InputFormat::Csv)
+    }
+
+    /// The format given by the file extension, if it is recognised (see
+    /// [`DetectedFormat::from_path`]).
+    pub fn from_extension(path: &str) -> Option<Self> {
+        DetectedFormat::from_path(path).map(Self::from)
+    }
+
+    /// Guesses the format from the start of the decompressed data (see
+    /// [`DetectedFormat::from_content`]), or CSV if there is nothing to go
+    /// by.
+    pub fn from_content(data: &[u8]) -> Self {
+        DetectedFormat::from_content(data).map_or(InputFormat::Csv, Self::from)
+    }
+
+    /// The separator of the fields of a CSV or TSV file.
+    pub fn delimiter(self) -> Option<u8> {
+        match self {
+            InputFormat::Csv => Some(b','),
+            InputFormat::Tsv => Some(b'\t'),
+            _ => None,
+        }
+    }
+}
+
+impl From<DetectedFormat> for InputFormat {
+    /// Formats which aren't enabled are read as CSV.
+    fn from(format: DetectedFormat) -> Self {
+        match format {
+            DetectedFormat::Tsv => InputFormat::Tsv,
+            DetectedFormat::Jsonl => InputFormat::Jsonl,
+            #[cfg(feature = "avro")]
+            DetectedFormat::Avro => InputFormat::Avro,
+            #[cfg(feature = "parquet")]
+            DetectedFormat::Parquet => InputFormat::Parquet,
+            _ => InputFormat::Csv,
+        }
+    }
+}
+
+/// The format of the report.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum OutputFormat {
+    /// CSV with a header row.
+    #[default]
+    Csv,
+    /// One JSON object per account, per line.
+    Jsonl,
+    /// Avro, using the published report entry schema.
+    #[cfg(feature = "avro")]
+    Avro,
+    /// Parquet, with amounts stored as decimals.
+    #[cfg(feature = "parquet")]
+    Parquet,
+    /// A binary snapshot of the accounts, for the `snapshot` subcommand
+    /// (rather than `--output-format`).
+    Snapshot,
+    /// A client's statement as CSV, for the `statement` subcommand.
+    Statement,
+}
+
+/// The values accepted by `--format`, depending on the enabled features.
+fn input_formats() -> Vec<&'static str> {
+    ["csv", "tsv", "jsonl"]
+        .into_iter()
+        .chain(cfg!(feature = "avro").then_some("avro"))
+        .chain(cfg!(feature = "parquet").then_some("parquet"))
+        .collect()
+}
+
+/// The values accepted by `--output-format`, depending on the enabled
+/// features.
+fn output_formats() -> Vec<&'static str> {
+    ["csv", "jsonl"]
+        .into_iter()
+        .chain(cfg!(feature = "avro").then_some("avro"))
+        .chain(cfg!(feature = "parquet").then_some("parquet"))
+        .collect()
+}
+
+/// Which log messages are written to stderr: everything up to `level`, except
+/// for the modules (or `log` targets) in `modules`, which have their own
+/// levels.
+///
+/// Parsed from the same syntax as `RUST_LOG`, e.g.
+/// `warn,transaction_processor::parse=off`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct LogFilter {
+    pub level: LevelFilter,
+    pub modules: Vec<(String, LevelFilter)>,
+}
+
+impl LogFilter {
+    /// The filter used without `-v`, `-q` or `--log-level`.
+    pub const DEFAULT_LEVEL: LevelFilter = LevelFilter::Info;
+
+    /// Logs everything up to `level`.
+    pub const fn new(level: LevelFilter) -> Self {
+        Self {
+            level,
+            modules: Vec::new(),
+        }
+    }
+}
+
+impl FromStr for LogFilter {
+    type Err = ArgsError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let mut filter = Self::new(Self::DEFAULT_LEVEL);
+
+        for directive in value.split(',').map(str::trim) {
+            match directive.split_once('=') {
+                Some((module, level)) if !module.is_empty() => {
+                    let level = level
+                        .parse()
+                        .map_err(|_| ArgsError::InvalidLogLevel(value.to_string()))?;
+                    filter.modules.push((module.to_string(), level));
+                }
+                Some(_) => return Err(ArgsError::InvalidLogLevel(value.to_string())),
+                None => match directive.parse() {
+                    Ok(level) => filter.level = level,
+                    Err(_) if !directive.is_empty() => {
+                        // A module on its own logs everything, like RUST_LOG
+                        filter
+                            .modules
+                            .push((directive.to_string(), LevelFilter::Trace));
+                    }
+                    Err(_) => return Err(ArgsError::InvalidLogLevel(value.to_string())),
+                },
+            }
+        }
+
+        Ok(filter)
+    }
+}
+
+/// What the CLI has been asked to do, from the subcommand.
+#[derive(Clone, Debug)]
+pub enum Mode {
+    /// Process the input files and write the report (`process`, or no
+    /// subcommand).
+    Process,
+    /// Read and apply the input files, printing the summary instead of
+    /// writing a report (`validate`).
+    Validate,
+    /// Process the input files and write a snapshot of the accounts instead
+    /// of the report (`snapshot`). The output format is
+    /// [`OutputFormat::Snapshot`].
+    Snapshot,
+    /// Write the report of the accounts in the snapshot file (`report`).
+    Report(PathBuf),
+    /// Process the input files and write the client's statement instead of
+    /// the report (`statement`). The output format is
+    /// [`OutputFormat::Statement`].
+    Statement(ClientId),
+    /// Write the clients whose accounts differ between the report file and
+    /// the [`DiffTarget`] (`diff`).
+    Diff(PathBuf, DiffTarget),
+    /// Run a service instead of processing input files.
+    #[cfg(any(feature = "server", feature = "grpc", feature = "kafka"))]
+    Service(Service),
+}
+
+/// What a report is compared with by the `diff` subcommand.
+#[derive(Clone, Debug)]
+pub enum DiffTarget {
+    /// A later report file.
+    Report(PathBuf),
+    /// The accounts in a snapshot file.
+    Snapshot(PathBuf),
+}
 
 /// Command line arguments for the CLI interface.
 pub struct Args {
+    mode: Mode,
     input_files: Vec<String>,
-    verbose: bool,
+    log_filter: LogFilter,
+    precision_policy: Option<PrecisionPolicy>,
+    strict_amounts: bool,
+    amount_locale: Option<LocaleFormat>,
+    output_scale: Option<u32>,
+    saturate_totals: bool,
+    idempotent_replays: bool,
+    idempotency_cache: usize,
+    max_balance: Option<CurrencyAmount>,
+    balance_threshold: BalanceThreshold,
+    transaction_limit: Option<(usize, TransactionLimitAction)>,
+    redispute_policy: RedisputePolicy,
+    locked_account_policy: LockedAccountPolicy,
+    account_creation_policy: AccountCreationPolicy,
+    registered_clients: Option<ClientSet>,
+    input_format: Option<InputFormat>,
+    output_format: OutputFormat,
+    csv_output: CSVWriterOptions,
+    report_filter: ReportFilter,
+    report_order: ReportOrder,
+    report_summary: Option<PathBuf>,
+    disputes_output: Option<PathBuf>,
+    locked_output: Option<PathBuf>,
+    negative_output: Option<PathBuf>,
+    journal: Option<PathBuf>,
+    ledger: Option<PathBuf>,
+    aggregate: Option<(PathBuf, Aggregator)>,
+    checkpoint: Option<(PathBuf, u64)>,
+    resume: Option<PathBuf>,
+    cutoff: Option<(Cutoff, PathBuf)>,
+    column_mapping: Option<ColumnMapping>,
+    no_header: bool,
+    output: Option<PathBuf>,
+    existing_output: ExistingOutput,
+    rejects: Option<PathBuf>,
+    strict: bool,
+    summary_json: Option<PathBuf>,
+    follow: Option<Duration>,
+    jobs: Option<usize>,
+    reorder_window: usize,
+    verify_checksum: bool,
+    signature_key: Option<HmacKey>,
+    client_keys: bool,
+    report_signature: Option<(PathBuf, HmacKey)>,
+    dry_run: bool,
+    #[cfg(feature = "tracing")]
+    trace_level: Option<tracing::Level>,
+    #[cfg(feature = "webhooks")]
+    webhooks: Vec<String>,
+    #[cfg(feature = "tui")]
+    tui: bool,
+    #[cfg(feature = "encryption")]
+    snapshot_key: Option<SnapshotKey>,
 }
 
 impl Args {
-    /// The list of CSV input files specified.
+    /// What to do, from the subcommand.
+    pub fn mode(&self) -> &Mode {
+        &self.mode
+    }
+
+    /// If the deprecated `--dry-run` was given in place of `validate`.
+    pub fn dry_run(&self) -> bool {
+        self.dry_run
+    }
+
+    /// The list of input files specified. Empty for the subcommands which
+    /// don't read input files.
     pub fn input_files(&self) -> &[String] {
         self.input_files.as_slice()
     }
 
-    /// True if verbose logging is enabled.
-    pub fn verbose(&self) -> bool {
-        self.verbose
+    /// Which log messages to write to stderr.
+    pub fn log_filter(&self) -> &LogFilter {
+        &self.log_filter
+    }
+
+    /// The most verbose level of tracing spans to print, if any.
+    #[cfg(feature = "tracing")]
+    pub fn trace_level(&self) -> Option<tracing::Level> {
+        self.trace_level
+    }
+
+    /// The policy for amounts with more than four decimal places, if any.
+    pub fn precision_policy(&self) -> Option<PrecisionPolicy> {
+        self.precision_policy
+    }
+
+    /// True if amounts must be in plain decimal notation.
+    pub fn strict_amounts(&self) -> bool {
+        self.strict_amounts
+    }
+
+    /// The locale conventions used by amounts, if not plain decimals.
+    pub fn amount_locale(&self) -> Option<LocaleFormat> {
+        self.amount_locale
+    }
+
+    /// The fixed number of decimal places for report amounts, if any.
+    pub fn output_scale(&self) -> Option<u32> {
+        self.output_scale
+    }
+
+    /// True if overflowing report totals should saturate.
+    pub fn saturate_totals(&self) -> bool {
+        self.saturate_totals
+    }
+
+    /// True if exact replays of deposits and withdrawals should be skipped.
+    pub fn idempotent_replays(&self) -> bool {
+        self.idempotent_replays
+    }
+
+    /// The number of idempotency keys to remember, or 0 to ignore them.
+    pub fn idempotency_cache(&self) -> usize {
+        self.idempotency_cache
+    }
+
+    /// The maximum total funds of an account, if any.
+    pub fn max_balance(&self) -> Option<CurrencyAmount> {
+        self.max_balance
+    }
+
+    /// The floor under every account's available funds, and the ceiling over
+    /// its held funds, which raise an alert when a transaction crosses them.
+    pub fn balance_threshold(&self) -> BalanceThreshold {
+        self.balance_threshold
+    }
+
+    /// The maximum number of transactions of an account, and what to do
+    /// once it is reached, if any.
+    pub fn transaction_limit(&self) -> Option<(usize, TransactionLimitAction)> {
+        self.transaction_limit
+    }
+
+    /// Whether transactions can be disputed again once settled.
+    pub fn redispute_policy(&self) -> RedisputePolicy {
+        self.redispute_policy
+    }
+
+    /// Which transactions locked accounts may still process.
+    pub fn locked_account_policy(&self) -> LockedAccountPolicy {
+        self.locked_account_policy
+    }
+
+    /// Which transactions may create accounts.
+    pub fn account_creation_policy(&self) -> AccountCreationPolicy {
+        self.account_creation_policy
+    }
+
+    /// The clients whose accounts may always be created, if any.
+    pub fn registered_clients(&self) -> Option<&ClientSet> {
+        self.registered_clients.as_ref()
+    }
+
+    /// The format of all input files, if specified. Otherwise, the format
+    /// should be detected for each file.
+    pub fn input_format(&self) -> Option<InputFormat> {
+        self.input_format
+    }
+
+    /// The format of the report.
+    pub fn output_format(&self) -> OutputFormat {
+        self.output_format
+    }
+
+    /// How a CSV report is formatted.
+    pub fn csv_output(&self) -> &CSVWriterOptions {
+        &self.csv_output
+    }
+
+    /// Which accounts to include in the report.
+    pub fn report_filter(&self) -> &ReportFilter {
+        &self.report_filter
+    }
+
+    /// The order of the accounts in the report.
+    pub fn report_order(&self) -> ReportOrder {
+        self.report_order
+    }
+
+    /// The file to write the grand totals of the report to, if any.
+    pub fn report_summary(&self) -> Option<&Path> {
+        self.report_summary.as_deref()
+    }
+
+    /// The file to write the active disputes to, if any.
+    pub fn disputes_output(&self) -> Option<&Path> {
+        self.disputes_output.as_deref()
+    }
+
+    /// The file to write the locked accounts and their lock reasons to, if
+    /// any.
+    pub fn locked_output(&self) -> Option<&Path> {
+        self.locked_output.as_deref()
+    }
+
+    /// The file to write the accounts with negative available funds to, if
+    /// any.
+    pub fn negative_output(&self) -> Option<&Path> {
+        self.negative_output.as_deref()
+    }
+
+    /// The file to write every applied transaction and the resulting
+    /// balances to, if any.
+    pub fn journal(&self) -> Option<&Path> {
+        self.journal.as_deref()
+    }
+
+    /// The file to write the double-entry postings of every applied
+    /// transaction to, if any.
+    pub fn ledger(&self) -> Option<&Path> {
+        self.ledger.as_deref()
+    }
+
+    /// The file to write the per-period totals of the applied transactions
+    /// to, and the (empty) aggregator to total them with, if any.
+    pub fn aggregate(&self) -> Option<(&Path, &Aggregator)> {
+        self.aggregate
+            .as_ref()
+            .map(|(path, aggregator)| (path.as_path(), aggregator))
+    }
+
+    /// The file to write checkpoints of the run to, and how many rows are
+    /// read between them, if any.
+    pub fn checkpoint(&self) -> Option<(&Path, u64)> {
+        self.checkpoint
+            .as_ref()
+            .map(|(path, every)| (path.as_path(), *every))
+    }
+
+    /// The checkpoint to resume an interrupted run from, if any.
+    pub fn resume(&self) -> Option<&Path> {
+        self.resume.as_deref()
+    }
+
+    /// The cutoff of a settlement batch, and the snapshot it carries the
+    /// later transactions over in, if any.
+    pub fn cutoff(&self) -> Option<(&Cutoff, &Path)> {
+        self.cutoff
+            .as_ref()
+            .map(|(cutoff, path)| (cutoff, path.as_path()))
+    }
+
+    /// The names of the CSV input columns, if not the standard names.
+    pub fn column_mapping(&self) -> Option<&ColumnMapping> {
+        self.column_mapping.as_ref()
+    }
+
+    /// True if CSV input files have no header row.
+    pub fn no_header(&self) -> bool {
+        self.no_header
+    }
+
+    /// The file to write the report to, if not stdout.
+    pub fn output(&self) -> Option<&Path> {
+        self.output.as_deref()
+    }
+
+    /// What to do if the output file already exists.
+    pub fn existing_output(&self) -> ExistingOutput {
+        self.existing_output
+    }
+
+    /// The file to write rejected rows to, if any.
+    pub fn rejects(&self) -> Option<&Path> {
+        self.rejects.as_deref()
+    }
+
+    /// The URLs to notify of chargebacks and locked accounts.
+    #[cfg(feature = "webhooks")]
+    pub fn webhooks(&self) -> &[String] {
+        &self.webhooks
+    }
+
+    /// True if the progress should be shown in a terminal dashboard.
+    #[cfg(feature = "tui")]
+    pub fn tui(&self) -> bool {
+        self.tui
+    }
+
+    /// The key which snapshot files are encrypted with, if any.
+    #[cfg(feature = "encryption")]
+    pub fn snapshot_key(&self) -> Option<&SnapshotKey> {
+        self.snapshot_key.as_ref()
+    }
+
+    /// True if processing should stop at the first row which fails to be
+    /// read or applied.
+    pub fn strict(&self) -> bool {
+        self.strict
+    }
+
+    /// The file to write the processing summary to as JSON, if any.
+    pub fn summary_json(&self) -> Option<&Path> {
+        self.summary_json.as_deref()
+    }
+
+    /// If the input file should be followed as it grows, how often the
+    /// report should be written.
+    pub fn follow(&self) -> Option<Duration> {
+        self.follow
+    }
+
+    /// If the input files should be processed in parallel, the number of
+    /// threads to use.
+    pub fn jobs(&self) -> Option<usize> {
+        self.jobs
+    }
+
+    /// The number of rows with sequence numbers which may be held back to
+    /// put them back in order (0 if they are only checked).
+    pub fn reorder_window(&self) -> usize {
+        self.reorder_window
+    }
+
+    /// True if every input file must be verified against a sidecar checksum
+    /// file.
+    pub fn verify_checksum(&self) -> bool {
+        self.verify_checksum
+    }
+
+    /// True if the client column of CSV input files holds client keys
+    /// rather than IDs.
+    pub fn client_keys(&self) -> bool {
+        self.client_keys
+    }
+
+    /// The key which every CSV row must be signed with, if any.
+    pub fn signature_key(&self) -> Option<&HmacKey> {
+        self.signature_key.as_ref()
+    }
+
+    /// The file to write a detached signature of the report to, and the
+    /// key to sign it with, if any.
+    pub fn report_signature(&self) -> Option<(&Path, &HmacKey)> {
+        self.report_signature
+            .as_ref()
+            .map(|(path, key)| (path.as_path(), key))
     }
 }
 
 #[derive(Clone, Debug)]
 pub enum ArgsError {
-    NoInputFilesSpecified,
+    InvalidInputFiles(ExpandError),
+    InvalidLogLevel(String),
+    InvalidPrecisionPolicy(String),
+    InvalidAmountLocale(String),
+    InvalidOutputScale(String),
+    InvalidIdempotencyCache(String),
+    InvalidMaxBalance(String),
+    InvalidAlertThreshold(String, String),
+    InvalidMaxTransactions(String),
+    InvalidLockedAllow(String),
+    InvalidRegisteredClients(String, String),
+    InvalidInputFormat(String),
+    InvalidOutputFormat(String),
+    InvalidOutputColumns(String),
+    InvalidClients(String, String),
+    InvalidColumnMapping(String),
+    AppendRequiresCsvOutput,
+    InvalidReportInterval(String),
+    FollowRequiresSingleInput,
+    CannotFollow(String),
+    InvalidJobs(String),
+    InvalidReorderWindow(String),
+    InvalidCheckpointEvery(String),
+    InvalidCutoff(String),
+    CutoffRequiresCsvOrJsonlOutput,
+    MissingSignatureKey,
+    CannotVerifySignatures(String),
+    CannotReadClientKeys(String),
+    ClientKeysRequireCsvOutput,
+    MissingReportKey,
+    InvalidReportKeyFile(String),
+    InvalidSigningKey(String),
+    #[cfg(any(feature = "server", feature = "grpc"))]
+    InvalidListenAddress(String),
+    #[cfg(feature = "server")]
+    InvalidRateLimit(String),
+    #[cfg(feature = "server")]
+    InvalidRateBurst(String),
+    #[cfg(feature = "kafka")]
+    InvalidCheckpointInterval(String),
+    #[cfg(feature = "encryption")]
+    InvalidSnapshotKey(String),
 }
 
 impl Display for ArgsError {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        f.write_str(match self {
-            ArgsError::NoInputFilesSpecified => "No input files specified",
+        f.write_str(&match self {
+            ArgsError::InvalidInputFiles(err) => err.to_string(),
+            ArgsError::InvalidPrecisionPolicy(policy) => {
+                format!("Invalid precision policy '{}'", policy)
+            }
+            ArgsError::InvalidLogLevel(filter) => format!("Invalid log level '{}'", filter),
+            ArgsError::InvalidAmountLocale(locale) => {
+                format!("Invalid amount locale '{}'", locale)
+            }
+            ArgsError::InvalidOutputScale(scale) => format!(
+                "Invalid output scale '{}' (must be between 0 and {})",
+                scale, MAX_OUTPUT_SCALE
+            ),
+            ArgsError::InvalidIdempotencyCache(keys) => {
+                format!("Invalid --idempotency-cache '{}'", keys)
+            }
+            ArgsError::InvalidMaxBalance(amount) => {
+                format!("Invalid --max-balance '{}' (must not be negative)", amount)
+            }
+            ArgsError::InvalidAlertThreshold(option, amount) => {
+                format!("Invalid --{} '{}'", option, amount)
+            }
+            ArgsError::InvalidMaxTransactions(count) => {
+                format!("Invalid --max-transactions '{}' (must be positive)", count)
+            }
+            ArgsError::InvalidRegisteredClients(clients, err) => {
+                format!("Invalid --register-clients '{}': {}", clients, err)
+            }
+            ArgsError::InvalidLockedAllow(types) => format!(
+                "Invalid --locked-allow '{}' (must be 'none', or a list of 'deposit', 'withdrawal' and 'dispute')",
+                types
+            ),
+            ArgsError::InvalidInputFormat(format) => format!("Invalid input format '{}'", format),
+            ArgsError::InvalidOutputFormat(format) => {
+                format!("Invalid output format '{}'", format)
+            }
+            ArgsError::InvalidOutputColumns(columns) => format!(
+                "Invalid --output-columns '{}' (must be a list of distinct columns from '{}')",
+                columns,
+                REPORT_COLUMNS.join(",")
+            ),
+            ArgsError::InvalidClients(clients, err) => {
+                format!("Invalid --clients '{}': {}", clients, err)
+            }
+            ArgsError::InvalidColumnMapping(err) => format!("Invalid --columns: {}", err),
+            ArgsError::InvalidReportInterval(interval) => {
+                format!("Invalid report interval '{}'", interval)
+            }
+            ArgsError::FollowRequiresSingleInput => {
+                "--follow requires exactly one input file".to_string()
+            }
+            ArgsError::CannotFollow(path) => format!(
+                "Cannot follow '{}': only local, uncompressed CSV and JSON Lines files can be followed",
+                path
+            ),
+            ArgsError::InvalidJobs(jobs) => {
+                format!("Invalid number of jobs '{}' (must be at least 1)", jobs)
+            }
+            ArgsError::InvalidReorderWindow(rows) => {
+                format!("Invalid --reorder-window '{}'", rows)
+            }
+            ArgsError::InvalidCheckpointEvery(rows) => {
+                format!("Invalid --checkpoint-every '{}' (must be at least 1)", rows)
+            }
+            ArgsError::InvalidCutoff(cutoff) => format!("Invalid --cutoff '{}'", cutoff),
+            ArgsError::CutoffRequiresCsvOrJsonlOutput => {
+                "--cutoff is only supported for CSV and JSON Lines output".to_string()
+            }
+            ArgsError::MissingSignatureKey => format!(
+                "--verify-signatures requires the key to be set in {}",
+                ROW_KEY_VARIABLE
+            ),
+            ArgsError::CannotVerifySignatures(path) => format!(
+                "Cannot verify signatures in '{}': only CSV rows can be signed",
+                path
+            ),
+            ArgsError::CannotReadClientKeys(path) => format!(
+                "Cannot read client keys from '{}': only CSV input can have client keys",
+                path
+            ),
+            ArgsError::ClientKeysRequireCsvOutput => {
+                "--client-keys is only supported for CSV output".to_string()
+            }
+            ArgsError::MissingReportKey => format!(
+                "--report-signature requires --report-key-file, or the key to be set in {}",
+                REPORT_KEY_VARIABLE
+            ),
+            ArgsError::InvalidReportKeyFile(err) => format!("Invalid --report-key-file: {}", err),
+            ArgsError::InvalidSigningKey(err) => format!("Invalid signing key: {}", err),
+            #[cfg(any(feature = "server", feature = "grpc"))]
+            ArgsError::InvalidListenAddress(address) => {
+                format!("Invalid listen address '{}'", address)
+            }
+            #[cfg(feature = "server")]
+            ArgsError::InvalidRateLimit(rate) => format!("Invalid rate limit '{}'", rate),
+            #[cfg(feature = "server")]
+            ArgsError::InvalidRateBurst(burst) => format!("Invalid rate burst '{}'", burst),
+            #[cfg(feature = "kafka")]
+            ArgsError::InvalidCheckpointInterval(interval) => {
+                format!("Invalid checkpoint interval '{}'", interval)
+            }
+            #[cfg(feature = "encryption")]
+            ArgsError::InvalidSnapshotKey(err) => err.clone(),
+            ArgsError::AppendRequiresCsvOutput => {
+                "--append is only supported for CSV output".to_string()
+            }
+        })
+    }
+}
+
+/// Applies the transactions in the input files to client accounts, and writes
+/// the state of every account as a report.
+#[derive(Parser)]
+#[clap(name = "transaction-processor", arg_required_else_help = true)]
+struct Cli {
+    #[clap(flatten)]
+    global: GlobalArgs,
+    #[clap(subcommand)]
+    command: CliCommand,
+}
+
+#[derive(Subcommand)]
+enum CliCommand {
+    /// Process the input files and write the report (the default if no
+    /// subcommand is given).
+    Process(Box<ProcessArgs>),
+    /// Read and apply the input files, printing the summary instead of the
+    /// report.
+    Validate(ValidateArgs),
+    /// Process the input files and write a snapshot of the accounts instead
+    /// of the report.
+    Snapshot(SnapshotArgs),
+    /// Write the report of the accounts in a snapshot.
+    Report(ReportArgs),
+    /// Process the input files and write one client's transactions, with
+    /// the running balances, instead of the report.
+    Statement(StatementArgs),
+    /// Compare two reports, or a report with the accounts in a snapshot,
+    /// writing the clients whose accounts differ.
+    Diff(DiffArgs),
+    /// Run a REST API server instead of processing input files.
+    #[cfg(feature = "server")]
+    Serve(ServeArgs),
+    /// Run a gRPC server instead of processing input files.
+    #[cfg(feature = "grpc")]
+    ServeGrpc(GrpcArgs),
+    /// Apply transactions from a Kafka topic instead of processing input
+    /// files.
+    #[cfg(feature = "kafka")]
+    ConsumeKafka(KafkaArgs),
+}
+
+/// Options for logging and for handling amounts, which can be given before
+/// or after any subcommand.
+#[derive(clap::Args)]
+struct GlobalArgs {
+    /// Also log debug messages to stderr (-vv: and trace messages).
+    #[clap(
+        short,
+        long,
+        env = "TXP_VERBOSE",
+        global = true,
+        parse(from_occurrences)
+    )]
+    verbose: u64,
+    /// Only log errors to stderr.
+    #[clap(
+        short,
+        long,
+        env = "TXP_QUIET",
+        global = true,
+        conflicts_with = "verbose"
+    )]
+    quiet: bool,
+    /// Log up to this level (info by default), optionally per module, e.g.
+    /// warn,transaction_processor::parse=off.
+    #[clap(
+        long,
+        env = "TXP_LOG_LEVEL",
+        global = true,
+        value_name = "FILTER",
+        conflicts_with_all = &["verbose", "quiet"]
+    )]
+    log_level: Option<String>,
+    /// Print tracing spans up to this level to stderr (info: files and
+    /// reports, trace: every transaction).
+    #[cfg(feature = "tracing")]
+    #[clap(
+        long,
+        env = "TXP_TRACE",
+        global = true,
+        value_name = "LEVEL",
+        possible_values = ["error", "warn", "info", "debug", "trace"]
+    )]
+    trace: Option<String>,
+    /// How to handle amounts with more than four decimal places.
+    #[clap(
+        long,
+        env = "TXP_PRECISION",
+        global = true,
+        possible_values = ["reject", "round", "truncate"]
+    )]
+    precision: Option<String>,
+    /// Reject amounts with a leading '+', separators, or exponents.
+    #[clap(long, env = "TXP_STRICT_AMOUNTS", global = true)]
+    strict_amounts: bool,
+    /// Parse amounts like 1,234.56 (point-decimal) or 1.234,56
+    /// (comma-decimal).
+    #[clap(
+        long,
+        env = "TXP_AMOUNT_LOCALE",
+        global = true,
+        possible_values = ["point-decimal", "comma-decimal"],
+        conflicts_with = "strict-amounts"
+    )]
+    amount_locale: Option<String>,
+    /// Format report amounts with a fixed number of decimal places.
+    #[clap(
+        long,
+        env = "TXP_OUTPUT_SCALE",
+        global = true,
+        value_name = "DECIMAL_PLACES"
+    )]
+    output_scale: Option<String>,
+    /// Report overflowing totals as the maximum amount instead of skipping
+    /// them.
+    #[clap(long, env = "TXP_SATURATE_TOTALS", global = true)]
+    saturate_totals: bool,
+    /// Skip a deposit or withdrawal identical to one already applied, instead
+    /// of rejecting it as a duplicate.
+    #[clap(long, env = "TXP_IDEMPOTENT_REPLAYS", global = true)]
+    idempotent_replays: bool,
+    /// The number of idempotency keys to remember the outcomes of (0 ignores
+    /// the keys).
+    #[clap(
+        long,
+        env = "TXP_IDEMPOTENCY_CACHE",
+        global = true,
+        value_name = "KEYS"
+    )]
+    idempotency_cache: Option<String>,
+    /// Reject deposits which would take an account's total funds above this
+    /// amount.
+    #[clap(long, env = "TXP_MAX_BALANCE", global = true, value_name = "AMOUNT")]
+    max_balance: Option<String>,
+    /// Log an alert (and notify any webhooks) when a transaction takes an
+    /// account's available funds below this amount.
+    #[clap(long, env = "TXP_ALERT_FLOOR", global = true, value_name = "AMOUNT")]
+    alert_floor: Option<String>,
+    /// Log an alert (and notify any webhooks) when a transaction takes an
+    /// account's held funds above this amount.
+    #[clap(
+        long,
+        env = "TXP_ALERT_HELD_CEILING",
+        global = true,
+        value_name = "AMOUNT"
+    )]
+    alert_held_ceiling: Option<String>,
+    /// Limit the number of deposits and withdrawals kept for each account,
+    /// for disputes and duplicate detection.
+    #[clap(
+        long,
+        env = "TXP_MAX_TRANSACTIONS",
+        global = true,
+        value_name = "COUNT"
+    )]
+    max_transactions: Option<String>,
+    /// What to do with a deposit or withdrawal once an account has
+    /// --max-transactions (rejecting it by default).
+    #[clap(
+        long,
+        env = "TXP_ON_TRANSACTION_LIMIT",
+        global = true,
+        value_name = "ACTION",
+        possible_values = ["reject", "evict-oldest"],
+        requires = "max-transactions"
+    )]
+    on_transaction_limit: Option<String>,
+    /// Whether a transaction can be disputed again once its dispute is
+    /// resolved or charged back (only after a resolve by default).
+    #[clap(
+        long,
+        env = "TXP_REDISPUTE",
+        global = true,
+        value_name = "POLICY",
+        possible_values = ["never", "after-resolve", "always"]
+    )]
+    redispute: Option<String>,
+    /// The transactions which locked accounts still process, as a list of
+    /// 'deposit', 'withdrawal' and 'dispute' (which includes resolves and
+    /// chargebacks), or 'none'. Only disputes by default.
+    #[clap(long, env = "TXP_LOCKED_ALLOW", global = true, value_name = "TYPES")]
+    locked_allow: Option<String>,
+    /// Which transactions may create the account of a new client (any by
+    /// default).
+    #[clap(
+        long,
+        env = "TXP_ACCOUNT_CREATION",
+        global = true,
+        value_name = "POLICY",
+        possible_values = ["auto", "reject-unknown", "on-deposit"]
+    )]
+    account_creation: Option<String>,
+    /// Clients whose accounts may be created by any transaction, as a list
+    /// of IDs and ranges (e.g. '1,5-10').
+    #[clap(
+        long,
+        env = "TXP_REGISTER_CLIENTS",
+        global = true,
+        value_name = "IDS",
+        requires = "account-creation"
+    )]
+    register_clients: Option<String>,
+    /// POST a JSON notification to this URL for every chargeback and locked
+    /// account.
+    #[cfg(feature = "webhooks")]
+    #[clap(
+        long = "webhook",
+        env = "TXP_WEBHOOK",
+        global = true,
+        value_name = "URL",
+        // So that TXP_WEBHOOK can hold several URLs, which can't contain
+        // spaces
+        value_delimiter = ' '
+    )]
+    webhooks: Vec<String>,
+    /// Encrypt the snapshot files which are written, and decrypt those which
+    /// are read, using the 64 hex digit key in
+    /// TRANSACTION_PROCESSOR_SNAPSHOT_KEY.
+    #[cfg(feature = "encryption")]
+    #[clap(long, env = "TXP_ENCRYPT_SNAPSHOTS", global = true)]
+    encrypt_snapshots: bool,
+    /// Read the --encrypt-snapshots key from a file instead.
+    #[cfg(feature = "encryption")]
+    #[clap(
+        long,
+        env = "TXP_SNAPSHOT_KEY_FILE",
+        global = true,
+        value_name = "FILE",
+        parse(from_os_str),
+        requires = "encrypt-snapshots"
+    )]
+    snapshot_key_file: Option<PathBuf>,
+}
+
+/// Options for reading the input files.
+#[derive(clap::Args)]
+struct InputArgs {
+    /// Input file format (detected from the file extension by default).
+    #[clap(long, env = "TXP_FORMAT", possible_values = input_formats())]
+    format: Option<String>,
+    /// CSV column names, e.g. type=transaction_type,client=client_id,tx=txn_id.
+    #[clap(long, env = "TXP_COLUMNS", value_name = "FIELD=COLUMN,...")]
+    columns: Option<String>,
+    /// CSV input files have no header row (columns are type,client,tx,amount).
+    #[clap(long, env = "TXP_NO_HEADER", conflicts_with = "columns")]
+    no_header: bool,
+    /// Write rows which fail to be read or applied to a CSV file, with the
+    /// reason.
+    #[clap(long, env = "TXP_REJECTS", value_name = "FILE", parse(from_os_str))]
+    rejects: Option<PathBuf>,
+    /// Stop at the first row which fails to be read or applied, without a
+    /// report.
+    #[clap(long, env = "TXP_STRICT")]
+    strict: bool,
+    /// Write a summary of the rows read, applied and rejected to a JSON file.
+    #[clap(
+        long,
+        env = "TXP_SUMMARY_JSON",
+        value_name = "FILE",
+        parse(from_os_str)
+    )]
+    summary_json: Option<PathBuf>,
+    /// Process input files in parallel; no client may appear in more than one
+    /// file.
+    #[clap(short, long, env = "TXP_JOBS", value_name = "THREADS")]
+    jobs: Option<String>,
+    /// Hold back up to this many rows with sequence numbers while waiting
+    /// for a missing one, to put them back in order.
+    #[clap(long, env = "TXP_REORDER_WINDOW", value_name = "ROWS")]
+    reorder_window: Option<String>,
+    /// Require every input file to match a sidecar SHA-256 file (e.g.
+    /// input.csv.sha256).
+    #[clap(long, env = "TXP_VERIFY_CHECKSUM")]
+    verify_checksum: bool,
+    /// Reject CSV rows without a valid HMAC-SHA256 'signature' column, using
+    /// the key in TRANSACTION_PROCESSOR_ROW_KEY.
+    #[clap(long, env = "TXP_VERIFY_SIGNATURES")]
+    verify_signatures: bool,
+    /// The CSV client column holds client keys (e.g. UUIDs) rather than IDs,
+    /// which are reported in place of the IDs given to their accounts.
+    #[clap(long, env = "TXP_CLIENT_KEYS", conflicts_with = "jobs")]
+    client_keys: bool,
+    /// Show progress, the largest and locked accounts, and recent errors in a
+    /// dashboard on stderr while processing.
+    #[cfg(feature = "tui")]
+    #[clap(long, env = "TXP_TUI")]
+    tui: bool,
+    /// Input file, directory, or glob pattern (e.g. 'data/*.csv').
+    #[clap(value_name = "INPUT", required = true)]
+    inputs: Vec<String>,
+}
+
+/// Options for writing the report.
+#[derive(clap::Args)]
+struct OutputArgs {
+    /// Write the report to a file instead of stdout.
+    #[clap(
+        short,
+        long,
+        env = "TXP_OUTPUT",
+        value_name = "FILE",
+        parse(from_os_str)
+    )]
+    output: Option<PathBuf>,
+    /// Replace the output file (and the --rejects file) if it already exists.
+    #[clap(long, env = "TXP_OVERWRITE")]
+    overwrite: bool,
+    /// Append the report to the output file if it already exists.
+    #[clap(
+        long,
+        env = "TXP_APPEND",
+        requires = "output",
+        conflicts_with = "overwrite"
+    )]
+    append: bool,
+    /// Report format (csv by default).
+    #[clap(long, env = "TXP_OUTPUT_FORMAT", possible_values = output_formats())]
+    output_format: Option<String>,
+    /// Which fields of a CSV report are quoted: only those which need it
+    /// (the default), all of them, those which aren't numbers, or none.
+    #[clap(
+        long,
+        env = "TXP_OUTPUT_QUOTE",
+        value_name = "STYLE",
+        possible_values = ["necessary", "always", "non-numeric", "never"]
+    )]
+    output_quote: Option<String>,
+    /// End the rows of a CSV report with LF (the default) or CRLF.
+    #[clap(
+        long,
+        env = "TXP_OUTPUT_LINE_TERMINATOR",
+        value_name = "TERMINATOR",
+        possible_values = ["lf", "crlf"]
+    )]
+    output_line_terminator: Option<String>,
+    /// Don't write a header row in a CSV report.
+    #[clap(long, env = "TXP_NO_OUTPUT_HEADER")]
+    no_output_header: bool,
+    /// Write these columns of a CSV report, in this order (e.g.
+    /// 'client,total'), instead of client,available,held,total,locked.
+    #[clap(long, env = "TXP_OUTPUT_COLUMNS", value_name = "COLUMNS")]
+    output_columns: Option<String>,
+    /// Only report these clients, as a list of IDs and ranges (e.g.
+    /// '1,5-10').
+    #[clap(long, env = "TXP_CLIENTS", value_name = "IDS")]
+    clients: Option<String>,
+    /// Only report locked accounts.
+    #[clap(long, env = "TXP_LOCKED_ONLY")]
+    locked_only: bool,
+    /// Only report accounts with non-zero available or held funds.
+    #[clap(long, env = "TXP_NON_ZERO_ONLY")]
+    non_zero_only: bool,
+    /// Report order: by client ID (the default), or the largest total or
+    /// held funds first.
+    #[clap(long, env = "TXP_SORT", possible_values = ["client", "total", "held"])]
+    sort: Option<String>,
+    /// Also write the grand totals of the report, and the number of locked
+    /// accounts, to a file as CSV.
+    #[clap(long, env = "TXP_SUMMARY", value_name = "FILE", parse(from_os_str))]
+    summary: Option<PathBuf>,
+    /// Also write every active dispute (client, tx and held amount) to a
+    /// file as CSV.
+    #[clap(
+        long,
+        env = "TXP_DISPUTES_OUTPUT",
+        value_name = "FILE",
+        parse(from_os_str)
+    )]
+    disputes_output: Option<PathBuf>,
+    /// Also write every locked account, with why it was locked, to a file as
+    /// CSV.
+    #[clap(
+        long,
+        env = "TXP_LOCKED_OUTPUT",
+        value_name = "FILE",
+        parse(from_os_str)
+    )]
+    locked_output: Option<PathBuf>,
+    /// Also write every account with negative available funds, with the
+    /// dispute or chargeback which may have caused it, to a file as CSV.
+    #[clap(
+        long,
+        env = "TXP_NEGATIVE_OUTPUT",
+        value_name = "FILE",
+        parse(from_os_str)
+    )]
+    negative_output: Option<PathBuf>,
+    /// Write the hex HMAC-SHA256 of the report to a file, using the key in
+    /// TRANSACTION_PROCESSOR_REPORT_KEY.
+    #[clap(
+        long,
+        env = "TXP_REPORT_SIGNATURE",
+        value_name = "FILE",
+        parse(from_os_str),
+        conflicts_with = "append"
+    )]
+    report_signature: Option<PathBuf>,
+    /// Read the --report-signature key from a file instead.
+    #[clap(
+        long,
+        env = "TXP_REPORT_KEY_FILE",
+        value_name = "FILE",
+        parse(from_os_str),
+        requires = "report-signature"
+    )]
+    report_key_file: Option<PathBuf>,
+}
+
+#[derive(clap::Args)]
+struct ProcessArgs {
+    #[clap(flatten)]
+    output: OutputArgs,
+    /// Keep reading the input file as it grows, writing the report
+    /// periodically.
+    #[clap(
+        long,
+        env = "TXP_FOLLOW",
+        conflicts_with_all = &[
+            "append",
+            "rejects",
+            "summary-json",
+            "report-signature",
+            "jobs",
+            "verify-checksum",
+            "journal",
+            "ledger",
+            "aggregate",
+            "checkpoint",
+            "resume",
+            "cutoff",
+        ]
+    )]
+    follow: bool,
+    /// How often to write the report with --follow (5 seconds by default).
+    #[clap(
+        long,
+        env = "TXP_REPORT_INTERVAL",
+        value_name = "SECONDS",
+        requires = "follow"
+    )]
+    report_interval: Option<String>,
+    /// Also write every applied transaction, with the resulting balances,
+    /// to a file (as JSON Lines for a .jsonl file, or CSV).
+    #[clap(long, env = "TXP_JOURNAL", value_name = "FILE", parse(from_os_str))]
+    journal: Option<PathBuf>,
+    /// Also post every applied transaction to a double-entry ledger, check
+    /// that the books balance once processing finishes, and write the
+    /// postings to a file as CSV.
+    #[clap(long, env = "TXP_LEDGER", value_name = "FILE", parse(from_os_str))]
+    ledger: Option<PathBuf>,
+    /// Also write the totals of the applied transactions in each period (by
+    /// their timestamps) to a file as CSV, e.g. for daily settlement figures.
+    #[clap(long, env = "TXP_AGGREGATE", value_name = "FILE", parse(from_os_str))]
+    aggregate: Option<PathBuf>,
+    /// The length of the periods of --aggregate (days by default).
+    #[clap(
+        long,
+        env = "TXP_AGGREGATE_PERIOD",
+        value_name = "PERIOD",
+        possible_values = ["day", "hour"],
+        requires = "aggregate"
+    )]
+    aggregate_period: Option<String>,
+    /// Total each client's transactions separately with --aggregate.
+    #[clap(long, env = "TXP_AGGREGATE_PER_CLIENT", requires = "aggregate")]
+    aggregate_per_client: bool,
+    /// Write a checkpoint of the accounts, and of how far the run has got,
+    /// to a file every --checkpoint-every rows, for --resume.
+    #[clap(
+        long,
+        env = "TXP_CHECKPOINT",
+        value_name = "FILE",
+        parse(from_os_str),
+        requires = "checkpoint-every",
+        conflicts_with_all = &["jobs", "reorder-window"]
+    )]
+    checkpoint: Option<PathBuf>,
+    /// How many input rows are read between checkpoints.
+    #[clap(
+        long,
+        env = "TXP_CHECKPOINT_EVERY",
+        value_name = "ROWS",
+        requires = "checkpoint"
+    )]
+    checkpoint_every: Option<String>,
+    /// Continue an interrupted run of the same input files from a
+    /// --checkpoint, skipping the rows it had already applied.
+    #[clap(
+        long,
+        env = "TXP_RESUME",
+        value_name = "CHECKPOINT",
+        parse(from_os_str),
+        conflicts_with_all = &["jobs", "ledger", "aggregate", "reorder-window"]
+    )]
+    resume: Option<PathBuf>,
+    /// Run as an end-of-day settlement batch, applying only the transactions
+    /// timestamped before this time, and stamping the report with the
+    /// business date. Later transactions are carried over to the next run
+    /// with --carry-over.
+    #[clap(
+        long,
+        env = "TXP_CUTOFF",
+        value_name = "TIMESTAMP",
+        requires = "carry-over",
+        conflicts_with_all = &["jobs", "checkpoint", "resume"]
+    )]
+    cutoff: Option<String>,
+    /// The snapshot which a --cutoff batch starts from (if it exists), which
+    /// is replaced with the accounts and the transactions carried over once
+    /// the report has been written.
+    #[clap(
+        long,
+        env = "TXP_CARRY_OVER",
+        value_name = "SNAPSHOT",
+        parse(from_os_str),
+        requires = "cutoff"
+    )]
+    carry_over: Option<PathBuf>,
+    /// Deprecated: the same as `validate`.
+    #[clap(
+        long,
+        env = "TXP_DRY_RUN",
+        hide = true,
+        conflicts_with_all = &[
+            "output",
+            "summary",
+            "disputes-output",
+            "locked-output",
+            "negative-output",
+            "report-signature",
+            "follow",
+            "journal",
+            "ledger",
+            "aggregate",
+            "checkpoint",
+            "resume",
+            "cutoff",
+        ]
+    )]
+    dry_run: bool,
+    #[clap(flatten)]
+    input: InputArgs,
+}
+
+#[derive(clap::Args)]
+struct ValidateArgs {
+    /// Replace the --rejects file if it already exists.
+    #[clap(long, env = "TXP_OVERWRITE")]
+    overwrite: bool,
+    #[clap(flatten)]
+    input: InputArgs,
+}
+
+#[derive(clap::Args)]
+struct SnapshotArgs {
+    /// Write the snapshot to this file.
+    #[clap(
+        short,
+        long,
+        env = "TXP_OUTPUT",
+        value_name = "FILE",
+        parse(from_os_str)
+    )]
+    output: PathBuf,
+    /// Replace the snapshot file (and the --rejects file) if it already
+    /// exists.
+    #[clap(long, env = "TXP_OVERWRITE")]
+    overwrite: bool,
+    #[clap(flatten)]
+    input: InputArgs,
+}
+
+#[derive(clap::Args)]
+struct ReportArgs {
+    #[clap(flatten)]
+    output: OutputArgs,
+    /// A snapshot written by the snapshot subcommand.
+    #[clap(value_name = "SNAPSHOT", parse(from_os_str))]
+    snapshot: PathBuf,
+}
+
+#[derive(clap::Args)]
+struct StatementArgs {
+    /// The client to list the transactions of.
+    #[clap(long, env = "TXP_CLIENT", value_name = "ID")]
+    client: ClientId,
+    /// Write the statement to a file instead of stdout.
+    #[clap(
+        short,
+        long,
+        env = "TXP_OUTPUT",
+        value_name = "FILE",
+        parse(from_os_str)
+    )]
+    output: Option<PathBuf>,
+    /// Replace the output file (and the --rejects file) if it already exists.
+    #[clap(long, env = "TXP_OVERWRITE")]
+    overwrite: bool,
+    #[clap(flatten)]
+    input: InputArgs,
+}
+
+#[derive(clap::Args)]
+struct DiffArgs {
+    /// The earlier report, as CSV.
+    #[clap(value_name = "BEFORE", parse(from_os_str))]
+    before: PathBuf,
+    /// The later report, as CSV.
+    #[clap(
+        value_name = "AFTER",
+        parse(from_os_str),
+        required_unless_present = "snapshot"
+    )]
+    after: Option<PathBuf>,
+    /// Compare the report with the accounts in this snapshot, instead of
+    /// with a later report.
+    #[clap(
+        long,
+        env = "TXP_SNAPSHOT",
+        value_name = "FILE",
+        parse(from_os_str),
+        conflicts_with = "after"
+    )]
+    snapshot: Option<PathBuf>,
+    /// Write the differences to a file instead of stdout.
+    #[clap(
+        short,
+        long,
+        env = "TXP_OUTPUT",
+        value_name = "FILE",
+        parse(from_os_str)
+    )]
+    output: Option<PathBuf>,
+    /// Replace the output file if it already exists.
+    #[clap(long, env = "TXP_OVERWRITE", requires = "output")]
+    overwrite: bool,
+}
+
+#[cfg(feature = "server")]
+#[derive(clap::Args)]
+struct ServeArgs {
+    /// The address and port to listen on.
+    #[clap(long, env = "TXP_LISTEN", value_name = "ADDRESS", default_value = DEFAULT_LISTEN_ADDRESS)]
+    listen: String,
+    /// Limit each client to this many transactions per second.
+    #[clap(long, env = "TXP_RATE_LIMIT", value_name = "TPS")]
+    rate_limit: Option<String>,
+    /// How many transactions each client can submit at once, before
+    /// --rate-limit applies (one second's worth by default).
+    #[clap(
+        long,
+        env = "TXP_RATE_BURST",
+        value_name = "TRANSACTIONS",
+        requires = "rate-limit"
+    )]
+    rate_burst: Option<String>,
+}
+
+#[cfg(feature = "grpc")]
+#[derive(clap::Args)]
+struct GrpcArgs {
+    /// The address and port to listen on.
+    #[clap(long, env = "TXP_LISTEN", value_name = "ADDRESS", default_value = DEFAULT_GRPC_LISTEN_ADDRESS)]
+    listen: String,
+}
+
+#[cfg(feature = "kafka")]
+#[derive(clap::Args)]
+struct KafkaArgs {
+    /// Comma-separated bootstrap brokers (e.g. localhost:9092).
+    #[clap(long, env = "TXP_BROKERS", value_name = "HOSTS")]
+    brokers: String,
+    /// The topic to consume.
+    #[clap(long, env = "TXP_TOPIC")]
+    topic: String,
+    /// The consumer group to commit offsets for.
+    #[clap(long, env = "TXP_GROUP", default_value = "transaction-processor")]
+    group: String,
+    /// The encoding of each message (a JSON Lines body, or an Avro file).
+    #[clap(
+        long,
+        env = "TXP_PAYLOAD",
+        default_value = "json",
+        possible_values = ["json"].into_iter().chain(cfg!(feature = "avro").then_some("avro"))
+    )]
+    payload: String,
+    /// How often to write the snapshot and report and commit offsets (5
+    /// seconds by default).
+    #[clap(long, env = "TXP_CHECKPOINT_INTERVAL", value_name = "SECONDS")]
+    checkpoint_interval: Option<String>,
+    /// Restore from this snapshot on startup, and update it at each
+    /// checkpoint.
+    #[clap(long, env = "TXP_SNAPSHOT", value_name = "FILE", parse(from_os_str))]
+    snapshot: Option<PathBuf>,
+}
+
+/// Parses the command line `arguments`, starting with the program's name,
+/// and the environment variables. Exits with a usage message if they don't
+/// match the syntax.
+pub fn parse_args(arguments: Vec<OsString>) -> Result<Args, ArgsError> {
+    let exit_codes = ExitCode::help();
+    let command = Cli::command().after_help(exit_codes.as_str());
+    let arguments = with_default_subcommand(&command, arguments);
+    let matches = command.get_matches_from(arguments);
+    let cli = Cli::from_arg_matches(&matches).unwrap_or_else(|err| err.exit());
+    let global = cli.global;
+
+    let log_filter = match &global.log_level {
+        Some(filter) => filter.parse()?,
+        None if global.verbose > 1 => LogFilter::new(LevelFilter::Trace),
+        // A single -v, or TXP_VERBOSE, which is present without counting as
+        // an occurrence
+        None if matches.is_present("verbose") => LogFilter::new(LevelFilter::Debug),
+        None if global.quiet => LogFilter::new(LevelFilter::Error),
+        None => LogFilter::new(LogFilter::DEFAULT_LEVEL),
+    };
+
+    let amount_locale = global
+        .amount_locale
+        .map(|locale| match locale.as_str() {
+            "point-decimal" => Ok(LocaleFormat::PointDecimal),
+            "comma-decimal" => Ok(LocaleFormat::CommaDecimal),
+            _ => Err(ArgsError::InvalidAmountLocale(locale)),
+        })
+        .transpose()?;
+
+    let output_scale = global
+        .output_scale
+        .map(|scale| match scale.parse::<u32>() {
+            Ok(scale) if scale <= MAX_OUTPUT_SCALE => Ok(scale),
+            _ => Err(ArgsError::InvalidOutputScale(scale)),
+        })
+        .transpose()?;
+
+    let idempotency_cache = match global.idempotency_cache {
+        Some(keys) => keys
+            .parse::<usize>()
+            .map_err(|_| ArgsError::InvalidIdempotencyCache(keys))?,
+        None => idempotency::DEFAULT_CAPACITY,
+    };
+
+    let max_balance = global
+        .max_balance
+        .map(|amount| match CurrencyAmount::from_str(&amount) {
+            Ok(max_balance) if !max_balance.is_negative() => Ok(max_balance),
+            _ => Err(ArgsError::InvalidMaxBalance(amount)),
+        })
+        .transpose()?;
+
+    let alert_amount = |option: &str, amount: Option<String>| {
+        amount
+            .map(|amount| {
+                CurrencyAmount::from_str(&amount)
+                    .map_err(|_| ArgsError::InvalidAlertThreshold(option.to_string(), amount))
+            })
+            .transpose()
+    };
+    let mut balance_threshold = BalanceThreshold::new();
+    if let Some(floor) = alert_amount("alert-floor", global.alert_floor)? {
+        balance_threshold = balance_threshold.with_floor(floor);
+    }
+    if let Some(ceiling) = alert_amount("alert-held-ceiling", global.alert_held_ceiling)? {
+        balance_threshold = balance_threshold.with_held_ceiling(ceiling);
+    }
+
+    let transaction_limit = global
+        .max_transactions
+        .map(|count| match count.parse::<usize>() {
+            Ok(max) if max > 0 => {
+                let action = match global.on_transaction_limit.as_deref() {
+                    Some("evict-oldest") => TransactionLimitAction::EvictOldest,
+                    _ => TransactionLimitAction::Reject,
+                };
+                Ok((max, action))
+            }
+            _ => Err(ArgsError::InvalidMaxTransactions(count)),
+        })
+        .transpose()?;
+
+    let redispute_policy = match global.redispute.as_deref() {
+        Some("never") => RedisputePolicy::Never,
+        Some("always") => RedisputePolicy::Always,
+        _ => RedisputePolicy::AfterResolve,
+    };
+
+    let locked_account_policy = global
+        .locked_allow
+        .map(|types| parse_locked_allow(&types).ok_or(ArgsError::InvalidLockedAllow(types)))
+        .transpose()?
+        .unwrap_or_default();
+
+    let account_creation_policy = match global.account_creation.as_deref() {
+        Some("reject-unknown") => AccountCreationPolicy::RejectUnknown,
+        Some("on-deposit") => AccountCreationPolicy::OnDeposit,
+        _ => AccountCreationPolicy::AutoCreate,
+    };
+
+    let registered_clients = global
+        .register_clients
+        .map(|clients| {
+            clients.parse::<ClientSet>().map_err(|err| {
+                ArgsError::InvalidRegisteredClients(clients.clone(), err.to_string())
+            })
+        })
+        .transpose()?;
+
+    let precision_policy = global
+        .precision
+        .map(|policy| match policy.as_str() {
+            "reject" => Ok(PrecisionPolicy::Reject),
+            "round" => Ok(PrecisionPolicy::RoundHalfUp),
+            "truncate" => Ok(PrecisionPolicy::Truncate),
+            _ => Err(ArgsError::InvalidPrecisionPolicy(policy)),
         })
+        .transpose()?;
+
+    let mut args = Args {
+        mode: Mode::Process,
+        input_files: Vec::new(),
+        log_filter,
+        precision_policy,
+        strict_amounts: global.strict_amounts,
+        amount_locale,
+        output_scale,
+        saturate_totals: global.saturate_totals,
+        idempotent_replays: global.idempotent_replays,
+        idempotency_cache,
+        max_balance,
+        balance_threshold,
+        transaction_limit,
+        redispute_policy,
+        locked_account_policy,
+        account_creation_policy,
+        registered_clients,
+        input_format: None,
+        output_format: OutputFormat::default(),
+        csv_output: CSVWriterOptions::new(),
+        report_filter: ReportFilter::new(),
+        report_order: ReportOrder::default(),
+        report_summary: None,
+        disputes_output: None,
+        locked_output: None,
+        negative_output: None,
+        journal: None,
+        ledger: None,
+        aggregate: None,
+        checkpoint: None,
+        resume: None,
+        cutoff: None,
+        column_mapping: None,
+        no_header: false,
+        output: None,
+        existing_output: ExistingOutput::Fail,
+        rejects: None,
+        strict: false,
+        summary_json: None,
+        follow: None,
+        jobs: None,
+        reorder_window: 0,
+        verify_checksum: false,
+        signature_key: None,
+        client_keys: false,
+        report_signature: None,
+        // The possible values are all valid levels
+        dry_run: false,
+        #[cfg(feature = "tracing")]
+        trace_level: global.trace.and_then(|level| level.parse().ok()),
+        #[cfg(feature = "webhooks")]
+        webhooks: global.webhooks,
+        #[cfg(feature = "tui")]
+        tui: false,
+        #[cfg(feature = "encryption")]
+        snapshot_key: global
+            .encrypt_snapshots
+            .then(|| match &global.snapshot_key_file {
+                Some(path) => SnapshotKey::from_file(path),
+                None => SnapshotKey::from_env(SNAPSHOT_KEY_VARIABLE),
+            })
+            .transpose()
+            .map_err(|err| ArgsError::InvalidSnapshotKey(err.to_string()))?,
+    };
+
+    match cli.command {
+        // The options which would write files conflict with it
+        CliCommand::Process(process) if process.dry_run => {
+            args.mode = Mode::Validate;
+            args.dry_run = true;
+            args.set_input(process.input)?;
+        }
+        CliCommand::Process(process) => {
+            args.set_input(process.input)?;
+            args.set_report_output(process.output)?;
+            args.journal = process.journal;
+            args.ledger = process.ledger;
+            args.aggregate = process.aggregate.map(|path| {
+                let period = match process.aggregate_period.as_deref() {
+                    Some("hour") => Period::Hour,
+                    // The possible values are checked by clap
+                    _ => Period::Day,
+                };
+                let aggregator =
+                    Aggregator::new(period).with_per_client(process.aggregate_per_client);
+                (path, aggregator)
+            });
+            args.resume = process.resume;
+            if let (Some(path), Some(every)) = (process.checkpoint, process.checkpoint_every) {
+                let every = match every.parse::<u64>() {
+                    Ok(every) if every > 0 => every,
+                    _ => return Err(ArgsError::InvalidCheckpointEvery(every)),
+                };
+                args.checkpoint = Some((path, every));
+            }
+            if let (Some(cutoff), Some(path)) = (process.cutoff, process.carry_over) {
+                let cutoff = match cutoff.parse::<Timestamp>() {
+                    Ok(timestamp) => Cutoff::new(timestamp),
+                    Err(_) => return Err(ArgsError::InvalidCutoff(cutoff)),
+                };
+                // The report is stamped with the business date
+                if !matches!(args.output_format, OutputFormat::Csv | OutputFormat::Jsonl) {
+                    return Err(ArgsError::CutoffRequiresCsvOrJsonlOutput);
+                }
+                args.cutoff = Some((cutoff, path));
+            }
+            if process.follow {
+                args.set_follow(process.report_interval)?;
+            }
+        }
+        CliCommand::Validate(validate) => {
+            args.mode = Mode::Validate;
+            args.set_input(validate.input)?;
+            if validate.overwrite {
+                args.existing_output = ExistingOutput::Overwrite;
+            }
+        }
+        CliCommand::Snapshot(snapshot) => {
+            args.mode = Mode::Snapshot;
+            args.set_input(snapshot.input)?;
+            args.output = Some(snapshot.output);
+            args.output_format = OutputFormat::Snapshot;
+            if snapshot.overwrite {
+                args.existing_output = ExistingOutput::Overwrite;
+            }
+        }
+        CliCommand::Report(report) => {
+            args.mode = Mode::Report(report.snapshot);
+            args.set_report_output(report.output)?;
+        }
+        CliCommand::Statement(statement) => {
+            args.mode = Mode::Statement(statement.client);
+            args.set_input(statement.input)?;
+            args.output = statement.output;
+            args.output_format = OutputFormat::Statement;
+            if statement.overwrite {
+                args.existing_output = ExistingOutput::Overwrite;
+            }
+        }
+        CliCommand::Diff(diff) => {
+            let target = match (diff.after, diff.snapshot) {
+                (_, Some(snapshot)) => DiffTarget::Snapshot(snapshot),
+                // clap requires one of the two
+                (after, None) => DiffTarget::Report(after.unwrap_or_default()),
+            };
+            args.mode = Mode::Diff(diff.before, target);
+            args.output = diff.output;
+            if diff.overwrite {
+                args.existing_output = ExistingOutput::Overwrite;
+            }
+        }
+        #[cfg(feature = "server")]
+        CliCommand::Serve(serve) => {
+            args.mode = Mode::Service(Service::Rest(
+                listen_address(serve.listen)?,
+                rate_limit(serve.rate_limit, serve.rate_burst)?,
+            ));
+        }
+        #[cfg(feature = "grpc")]
+        CliCommand::ServeGrpc(grpc) => {
+            args.mode = Mode::Service(Service::Grpc(listen_address(grpc.listen)?));
+        }
+        #[cfg(feature = "kafka")]
+        CliCommand::ConsumeKafka(kafka) => {
+            args.mode = Mode::Service(Service::Kafka(kafka_options(kafka)?));
+        }
+    }
+
+    Ok(args)
+}
+
+impl Args {
+    /// Sets the input files and the options for reading them.
+    fn set_input(&mut self, input: InputArgs) -> Result<(), ArgsError> {
+        // Directories and glob patterns are expanded here, so that patterns
+        // can be quoted to avoid the shell's argument limit
+        self.input_files = expand_inputs(input.inputs.iter().map(String::as_str))
+            .map_err(ArgsError::InvalidInputFiles)?;
+
+        self.input_format = input
+            .format
+            .map(|format| match format.as_str() {
+                "csv" => Ok(InputFormat::Csv),
+                "tsv" => Ok(InputFormat::Tsv),
+                "jsonl" => Ok(InputFormat::Jsonl),
+                #[cfg(feature = "avro")]
+                "avro" => Ok(InputFormat::Avro),
+                #[cfg(feature = "parquet")]
+                "parquet" => Ok(InputFormat::Parquet),
+                _ => Err(ArgsError::InvalidInputFormat(format)),
+            })
+            .transpose()?;
+
+        self.column_mapping = input
+            .columns
+            .map(|columns| {
+                columns
+                    .parse::<ColumnMapping>()
+                    .map_err(|err| ArgsError::InvalidColumnMapping(err.to_string()))
+            })
+            .transpose()?;
+
+        self.jobs = input
+            .jobs
+            .map(|jobs| match jobs.parse::<usize>() {
+                Ok(jobs) if jobs > 0 => Ok(jobs),
+                _ => Err(ArgsError::InvalidJobs(jobs)),
+            })
+            .transpose()?;
+
+        if let Some(rows) = input.reorder_window {
+            self.reorder_window = rows
+                .parse::<usize>()
+                .map_err(|_| ArgsError::InvalidReorderWindow(rows))?;
+        }
+
+        if input.verify_signatures {
+            if let Some(path) = self.input_files.iter().find(|path| {
+                self.input_format
+                    .unwrap_or_else(|| InputFormat::from_path(path))
+                    .delimiter()
+                    .is_none()
+            }) {
+                return Err(ArgsError::CannotVerifySignatures(path.clone()));
+            }
+
+            self.signature_key = match std::env::var_os(ROW_KEY_VARIABLE) {
+                Some(key) if !key.is_empty() => Some(
+                    HmacKey::new(key.into_encoded_bytes())
+                        .map_err(|err| ArgsError::InvalidSigningKey(err.to_string()))?,
+                ),
+                _ => return Err(ArgsError::MissingSignatureKey),
+            };
+        }
+
+        if input.client_keys {
+            if let Some(path) = self.input_files.iter().find(|path| {
+                self.input_format
+                    .unwrap_or_else(|| InputFormat::from_path(path))
+                    .delimiter()
+                    .is_none()
+            }) {
+                return Err(ArgsError::CannotReadClientKeys(path.clone()));
+            }
+            self.client_keys = true;
+        }
+
+        self.no_header = input.no_header;
+        self.rejects = input.rejects;
+        self.strict = input.strict;
+        self.summary_json = input.summary_json;
+        self.verify_checksum = input.verify_checksum;
+        #[cfg(feature = "tui")]
+        {
+            self.tui = input.tui;
+        }
+        Ok(())
+    }
+
+    /// Sets where and how the report is written.
+    fn set_report_output(&mut self, output: OutputArgs) -> Result<(), ArgsError> {
+        self.output_format = output
+            .output_format
+            .map(|format| match format.as_str() {
+                "csv" => Ok(OutputFormat::Csv),
+                "jsonl" => Ok(OutputFormat::Jsonl),
+                #[cfg(feature = "avro")]
+                "avro" => Ok(OutputFormat::Avro),
+                #[cfg(feature = "parquet")]
+                "parquet" => Ok(OutputFormat::Parquet),
+                _ => Err(ArgsError::InvalidOutputFormat(format)),
+            })
+            .transpose()?
+            .unwrap_or_default();
+
+        // The possible values are checked by clap
+        let quote_style = match output.output_quote.as_deref() {
+            Some("always") => QuoteStyle::Always,
+            Some("non-numeric") => QuoteStyle::NonNumeric,
+            Some("never") => QuoteStyle::Never,
+            _ => QuoteStyle::Necessary,
+        };
+        let line_terminator = match output.output_line_terminator.as_deref() {
+            Some("crlf") => LineTerminator::Crlf,
+            _ => LineTerminator::Lf,
+        };
+        let columns = output
+            .output_columns
+            .map(|columns| {
+                let parsed = columns
+                    .split(',')
+                    .map(|column| column.trim().to_string())
+                    .collect::<Vec<_>>();
+                let known = parsed
+                    .iter()
+                    .all(|column| REPORT_COLUMNS.contains(&column.as_str()));
+                let distinct = parsed.iter().collect::<HashSet<_>>().len() == parsed.len();
+                if known && distinct {
+                    Ok(parsed)
+                } else {
+                    Err(ArgsError::InvalidOutputColumns(columns))
+                }
+            })
+            .transpose()?;
+        self.csv_output = CSVWriterOptions::new()
+            .with_quote_style(quote_style)
+            .with_line_terminator(line_terminator)
+            .with_headers(!output.no_output_header)
+            .with_columns(columns);
+
+        self.report_order = match output.sort.as_deref() {
+            Some("total") => ReportOrder::TotalDescending,
+            Some("held") => ReportOrder::HeldDescending,
+            // The possible values are checked by clap
+            _ => ReportOrder::ClientId,
+        };
+
+        self.report_filter = ReportFilter::new()
+            .with_locked_only(output.locked_only)
+            .with_non_zero_only(output.non_zero_only);
+        if let Some(clients) = output.clients {
+            let client_set = clients
+                .parse::<ClientSet>()
+                .map_err(|err| ArgsError::InvalidClients(clients.clone(), err.to_string()))?;
+            self.report_filter = self.report_filter.clone().with_clients(client_set);
+        }
+
+        self.existing_output = if output.append {
+            ExistingOutput::Append
+        } else if output.overwrite {
+            ExistingOutput::Overwrite
+        } else {
+            ExistingOutput::Fail
+        };
+        if self.existing_output == ExistingOutput::Append && self.output_format != OutputFormat::Csv
+        {
+            return Err(ArgsError::AppendRequiresCsvOutput);
+        }
+        // Only CSV reports can hold keys in place of client IDs
+        if self.client_keys && self.output_format != OutputFormat::Csv {
+            return Err(ArgsError::ClientKeysRequireCsvOutput);
+        }
+
+        self.report_signature = output
+            .report_signature
+            .map(|path| {
+                let key = match &output.report_key_file {
+                    Some(key_file) => read_key_file(key_file)?,
+                    None => match std::env::var_os(REPORT_KEY_VARIABLE) {
+                        Some(key) if !key.is_empty() => key.into_encoded_bytes(),
+                        _ => return Err(ArgsError::MissingReportKey),
+                    },
+                };
+                HmacKey::new(key)
+                    .map(|key| (path, key))
+                    .map_err(|err| ArgsError::InvalidSigningKey(err.to_string()))
+            })
+            .transpose()?;
+
+        self.report_summary = output.summary;
+        self.disputes_output = output.disputes_output;
+        self.locked_output = output.locked_output;
+        self.negative_output = output.negative_output;
+        self.output = output.output;
+        Ok(())
+    }
+
+    /// Sets `--follow`, once the input files are known.
+    fn set_follow(&mut self, report_interval: Option<String>) -> Result<(), ArgsError> {
+        let path = match self.input_files.as_slice() {
+            [path] => path,
+            _ => return Err(ArgsError::FollowRequiresSingleInput),
+        };
+
+        let format = self
+            .input_format
+            .unwrap_or_else(|| InputFormat::from_path(path));
+        if !matches!(
+            format,
+            InputFormat::Csv | InputFormat::Tsv | InputFormat::Jsonl
+        ) || Compression::from_path(Path::new(path)).is_some()
+            || path.contains("://")
+        {
+            return Err(ArgsError::CannotFollow(path.to_string()));
+        }
+
+        let interval = report_interval
+            .map(|interval| match interval.parse::<u64>() {
+                Ok(interval) if interval > 0 => Ok(interval),
+                _ => Err(ArgsError::InvalidReportInterval(interval)),
+            })
+            .transpose()?
+            .unwrap_or(DEFAULT_REPORT_INTERVAL_SECS);
+        self.follow = Some(Duration::from_secs(interval));
+        Ok(())
+    }
+}
+
+/// Inserts the `process` subcommand if `arguments` don't start with one, so
+/// that `transaction-processor [OPTIONS] <INPUT>...` keeps working. Options
+/// before the first input file are skipped (with their values), so that
+/// e.g. `-o report transactions.csv` writes to a file named `report`.
+fn with_default_subcommand(command: &Command, mut arguments: Vec<OsString>) -> Vec<OsString> {
+    let takes_value = |option: &str| {
+        command
+            .get_arguments()
+            .chain(
+                command
+                    .find_subcommand("process")
+                    .into_iter()
+                    .flat_map(Command::get_arguments),
+            )
+            .filter(|arg| arg.is_takes_value_set())
+            .any(|arg| match option.strip_prefix("--") {
+                Some(long) => arg.get_long() == Some(long),
+                None => arg
+                    .get_short()
+                    .is_some_and(|short| option == format!("-{}", short)),
+            })
+    };
+
+    let mut rest = arguments.iter().skip(1);
+    while let Some(argument) = rest.next() {
+        let argument = argument.to_string_lossy();
+        if argument.starts_with('-') && argument.len() > 1 && argument != "--" {
+            if takes_value(&argument) {
+                rest.next();
+            }
+            continue;
+        }
+
+        if argument != "help" && command.find_subcommand(&argument).is_none() {
+            arguments.insert(1, OsString::from("process"));
+        }
+        break;
+    }
+    // Without an input file (e.g. for `--help`), clap reports the missing
+    // subcommand
+    arguments
+}
+
+/// Reads a raw key from a file. A single trailing newline is removed, as
+/// most editors add one.
+fn read_key_file(path: &Path) -> Result<Vec<u8>, ArgsError> {
+    let mut key = std::fs::read(path)
+        .map_err(|err| ArgsError::InvalidReportKeyFile(format!("{}: {}", path.display(), err)))?;
+
+    if key.ends_with(b"\n") {
+        key.pop();
+        if key.ends_with(b"\r") {
+            key.pop();
+        }
+    }
+
+    if key.is_empty() {
+        return Err(ArgsError::InvalidReportKeyFile(format!(
+            "{} is empty",
+            path.display()
+        )));
     }
+    Ok(key)
 }
 
-pub fn parse_args() -> Result<Args, ArgsError> {
-    let arg_matches = Command::new("transaction-processor")
-        .trailing_var_arg(true)
-        .arg(Arg::new("verbose").short('v'))
-        .arg(arg!(<input> ... "input csv file"))
-        .get_matches();
+/// The policy given with `--locked-allow`, if the types are valid.
+fn parse_locked_allow(types: &str) -> Option<LockedAccountPolicy> {
+    let mut policy = LockedAccountPolicy::new()
+        .with_deposits(false)
+        .with_withdrawals(false)
+        .with_disputes(false);
+    if types.trim() == "none" {
+        return Some(policy);
+    }
 
-    let input_files: Vec<_> = arg_matches
-        .values_of("input")
-        .ok_or(ArgsError::NoInputFilesSpecified)?
-        .collect();
+    for transaction_type in types.split(',').map(str::trim) {
+        policy = match transaction_type {
+            "deposit" => policy.with_deposits(true),
+            "withdrawal" => policy.with_withdrawals(true),
+            "dispute" => policy.with_disputes(true),
+            _ => return None,
+        };
+    }
+    Some(policy)
+}
 
-    let verbose = arg_matches.is_present("verbose");
+/// The address given to a server subcommand.
+#[cfg(any(feature = "server", feature = "grpc"))]
+fn listen_address(address: String) -> Result<SocketAddr, ArgsError> {
+    address
+        .parse()
+        .map_err(|_| ArgsError::InvalidListenAddress(address))
+}
 
-    Ok(Args {
-        input_files: input_files
-            .iter()
-            .map(|input_file| (*input_file).to_string())
+/// The per-client rate limit given to the `serve` subcommand, if any.
+#[cfg(feature = "server")]
+fn rate_limit(
+    rate_limit: Option<String>,
+    rate_burst: Option<String>,
+) -> Result<Option<RateLimit>, ArgsError> {
+    let per_second = match rate_limit {
+        Some(rate) => match rate.parse::<f64>() {
+            Ok(rate) if rate.is_finite() && rate > 0.0 => rate,
+            _ => return Err(ArgsError::InvalidRateLimit(rate)),
+        },
+        None => return Ok(None),
+    };
+
+    let burst = rate_burst
+        .map(|burst| match burst.parse::<u32>() {
+            Ok(burst) if burst > 0 => Ok(f64::from(burst)),
+            _ => Err(ArgsError::InvalidRateBurst(burst)),
+        })
+        .transpose()?
+        // At least one transaction must be allowed
+        .unwrap_or_else(|| per_second.ceil());
+
+    Ok(Some(RateLimit { per_second, burst }))
+}
+
+/// The options given to the `consume-kafka` subcommand.
+#[cfg(feature = "kafka")]
+fn kafka_options(kafka: KafkaArgs) -> Result<KafkaOptions, ArgsError> {
+    let checkpoint_interval = kafka
+        .checkpoint_interval
+        .map(|interval| match interval.parse::<u64>() {
+            Ok(interval) if interval > 0 => Ok(interval),
+            _ => Err(ArgsError::InvalidCheckpointInterval(interval)),
+        })
+        .transpose()?
+        .unwrap_or(DEFAULT_CHECKPOINT_INTERVAL_SECS);
+
+    Ok(KafkaOptions {
+        brokers: kafka
+            .brokers
+            .split(',')
+            .map(|broker| broker.trim().to_string())
             .collect(),
-        verbose,
+        topic: kafka.topic,
+        group: kafka.group,
+        payload: match kafka.payload.as_str() {
+            #[cfg(feature = "avro")]
+            "avro" => KafkaPayload::Avro,
+            _ => KafkaPayload::Json,
+        },
+        checkpoint_interval: Duration::from_secs(checkpoint_interval),
+        snapshot: kafka.snapshot,
     })
 }
+
+#[cfg(test)]
+mod test {
+    use log::LevelFilter;
+
+    use std::ffi::OsString;
+
+    use clap::CommandFactory;
+
+    use transaction_processor::LockedAccountPolicy;
+
+    use crate::args::{parse_locked_allow, with_default_subcommand, Cli, InputFormat, LogFilter};
+
+    #[test]
+    fn test_cli() {
+        Cli::command().debug_assert();
+    }
+
+    #[test]
+    fn test_with_default_subcommand() {
+        let command = Cli::command();
+        let with_default = |arguments: &[&str]| {
+            let arguments = arguments.iter().map(OsString::from).collect();
+            with_default_subcommand(&command, arguments)
+                .into_iter()
+                .map(|argument| argument.into_string().unwrap())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            vec!["txp", "process", "-v", "tx.csv"],
+            with_default(&["txp", "-v", "tx.csv"])
+        );
+        // Option values are not taken for subcommands
+        assert_eq!(
+            vec![
+                "txp",
+                "process",
+                "--precision",
+                "round",
+                "-o",
+                "report",
+                "tx.csv"
+            ],
+            with_default(&["txp", "--precision", "round", "-o", "report", "tx.csv"])
+        );
+        assert_eq!(
+            vec!["txp", "process", "--", "report"],
+            with_default(&["txp", "--", "report"])
+        );
+        assert_eq!(
+            vec!["txp", "--precision", "round", "report", "accounts.snapshot"],
+            with_default(&["txp", "--precision", "round", "report", "accounts.snapshot"])
+        );
+        assert_eq!(vec!["txp", "--help"], with_default(&["txp", "--help"]));
+        assert_eq!(
+            vec!["txp", "help", "validate"],
+            with_default(&["txp", "help", "validate"])
+        );
+    }
+
+    #[test]
+    fn test_input_format_from_path() {
+        assert_eq!(InputFormat::Csv, InputFormat::from_path("transactions.csv"));
+        assert_eq!(InputFormat::Csv, InputFormat::from_path("transactions"));
+        assert_eq!(
+            InputFormat::Jsonl,
+            InputFormat::from_path("transactions.jsonl")
+        );
+        assert_eq!(InputFormat::Jsonl, InputFormat::from_path("data/tx.NDJSON"));
+        assert_eq!(
+            InputFormat::Jsonl,
+            InputFormat::from_path("data/tx.jsonl.gz")
+        );
+        assert_eq!(InputFormat::Csv, InputFormat::from_path("data/tx.csv.zst"));
+        assert_eq!(InputFormat::Tsv, InputFormat::from_path("data/tx.tab"));
+        assert_eq!(None, InputFormat::from_extension("data/tx.gz"));
+        assert_eq!(
+            InputFormat::Jsonl,
+            InputFormat::from_path("https://example.com/tx.jsonl.gz?token=a.csv")
+        );
+        #[cfg(feature = "avro")]
+        assert_eq!(InputFormat::Avro, InputFormat::from_path("topic-dump.avro"));
+        #[cfg(feature = "parquet")]
+        assert_eq!(
+            InputFormat::Parquet,
+            InputFormat::from_path("transactions.parquet")
+        );
+    }
+
+    #[test]
+    fn test_log_filter_from_str() {
+        assert_eq!(LogFilter::new(LevelFilter::Warn), "warn".parse().unwrap());
+        assert_eq!(
+            LogFilter {
+                level: LevelFilter::Error,
+                modules: vec![
+                    ("transaction_processor::parse".to_string(), LevelFilter::Off),
+                    (
+                        "transaction_processor::webhook".to_string(),
+                        LevelFilter::Trace
+                    ),
+                ],
+            },
+            "transaction_processor::parse=off, ERROR,transaction_processor::webhook"
+                .parse()
+                .unwrap()
+        );
+        assert_eq!(
+            LogFilter {
+                level: LogFilter::DEFAULT_LEVEL,
+                modules: vec![("transaction_processor".to_string(), LevelFilter::Debug)],
+            },
+            "transaction_processor=debug".parse().unwrap()
+        );
+        assert!("transaction_processor=loud".parse::<LogFilter>().is_err());
+        assert!("warn,".parse::<LogFilter>().is_err());
+        assert!("=warn".parse::<LogFilter>().is_err());
+    }
+
+    #[test]
+    fn test_parse_locked_allow() {
+        let none = LockedAccountPolicy::new()
+            .with_deposits(false)
+            .with_withdrawals(false)
+            .with_disputes(false);
+        assert_eq!(Some(none), parse_locked_allow("none"));
+        assert_eq!(
+            Some(none.with_deposits(true).with_disputes(true)),
+            parse_locked_allow("deposit, dispute")
+        );
+        assert_eq!(None, parse_locked_allow("deposit,resolve"));
+        assert_eq!(None, parse_locked_allow(""));
+    }
+}
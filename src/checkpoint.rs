@@ -0,0 +1,170 @@
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, PoisonError};
+
+use transaction_processor::snapshot::{write_checkpoint, Checkpoint};
+use transaction_processor::TransactionProcessor;
+
+use crate::output::{AtomicFile, ExistingOutput};
+
+/// The position of the run, which is advanced as rows are read.
+struct CheckpointerState {
+    /// The index of the input file being read.
+    file: usize,
+    /// The number of rows read from the input file, including any skipped
+    /// when resuming.
+    rows: u64,
+    /// The number of rows read since the last checkpoint was written.
+    pending: u64,
+}
+
+/// Writes a checkpoint of the accounts, and how far the run has got through
+/// its input files, to the `--checkpoint` file every `--checkpoint-every`
+/// rows, so that a failed run can be resumed with `--resume`.
+///
+/// Each checkpoint replaces the last atomically, so the file always holds a
+/// complete checkpoint.
+#[derive(Clone)]
+pub struct Checkpointer {
+    path: PathBuf,
+    every: u64,
+    inputs: Vec<String>,
+    state: Arc<Mutex<CheckpointerState>>,
+}
+
+impl std::fmt::Debug for Checkpointer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Checkpointer")
+            .field("path", &self.path)
+            .field("every", &self.every)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Checkpointer {
+    /// Creates a checkpointer for a run of `inputs`, which writes to `path`
+    /// every `every` rows.
+    pub fn new(path: PathBuf, every: u64, inputs: Vec<String>) -> Self {
+        Self {
+            path,
+            every,
+            inputs,
+            state: Arc::new(Mutex::new(CheckpointerState {
+                file: 0,
+                rows: 0,
+                pending: 0,
+            })),
+        }
+    }
+
+    /// Records that the input file at index `file` is being read, starting
+    /// after its first `rows` rows.
+    pub fn start_file(&self, file: usize, rows: u64) {
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        state.file = file;
+        state.rows = rows;
+    }
+
+    /// Records that a row has been read. This must be called before the row
+    /// is applied (see [`Checkpointer::after_transaction`]).
+    pub fn record_row(&self) {
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        state.rows += 1;
+        state.pending += 1;
+    }
+
+    /// Writes a checkpoint if enough rows have been read since the last one.
+    /// `transaction_processor` must have applied every row read so far.
+    pub fn after_transaction(&self, transaction_processor: &TransactionProcessor) {
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        if state.pending < self.every {
+            return;
+        }
+        state.pending = 0;
+        let checkpoint = Checkpoint::new(self.inputs.clone(), state.file, state.rows);
+        self.write(transaction_processor, &checkpoint);
+    }
+
+    /// Writes the final checkpoint, once every input file has been read, so
+    /// that resuming from it applies nothing again.
+    pub fn finish(&self, transaction_processor: &TransactionProcessor) {
+        let checkpoint = Checkpoint::new(self.inputs.clone(), self.inputs.len(), 0);
+        self.write(transaction_processor, &checkpoint);
+    }
+
+    /// Replaces the checkpoint file. A failure is logged rather than
+    /// returned, so that a long run isn't abandoned because a checkpoint
+    /// couldn't be written; the previous checkpoint is left in place.
+    fn write(&self, transaction_processor: &TransactionProcessor, checkpoint: &Checkpoint) {
+        let result = AtomicFile::create(&self.path, ExistingOutput::Overwrite)
+            .map_err(|err| err.to_string())
+            .and_then(|mut file| {
+                write_checkpoint(transaction_processor, checkpoint, &mut file)
+                    .map_err(|err| err.to_string())?;
+                file.commit().map_err(|err| err.to_string())
+            });
+
+        match result {
+            Ok(()) => log::debug!(
+                "Wrote checkpoint at row {} of input {}",
+                checkpoint.rows(),
+                checkpoint.files() + 1
+            ),
+            Err(err) => log::error!(
+                "Failed to write checkpoint '{}': {}",
+                self.path.display(),
+                err
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+
+    use transaction_processor::numeric::CurrencyAmount;
+    use transaction_processor::snapshot::{read_checkpoint, Checkpoint};
+    use transaction_processor::{
+        Transaction, TransactionId, TransactionProcessor, TransactionType,
+    };
+
+    use crate::checkpoint::Checkpointer;
+    use crate::test::TempDir;
+
+    #[test]
+    fn test_checkpointer() {
+        let directory = TempDir::new("checkpoint");
+        let path = directory.join("checkpoint");
+        let inputs = vec!["a.csv".to_string(), "b.csv".to_string()];
+        let checkpointer = Checkpointer::new(path.clone(), 2, inputs.clone());
+        let read = || {
+            let (transaction_processor, checkpoint) =
+                read_checkpoint(std::fs::File::open(&path).unwrap()).unwrap();
+            (
+                transaction_processor.generate_report_as_vec().len(),
+                checkpoint,
+            )
+        };
+
+        let mut transaction_processor = TransactionProcessor::new();
+        checkpointer.start_file(1, 5);
+        for client in 1..=3 {
+            checkpointer.record_row();
+            transaction_processor
+                .transact(&Transaction::new(
+                    client,
+                    TransactionId::from(client),
+                    TransactionType::Deposit {
+                        amount: CurrencyAmount::from_str("1").unwrap(),
+                    },
+                ))
+                .unwrap();
+            checkpointer.after_transaction(&transaction_processor);
+        }
+
+        // Written after the second row, but not the third
+        assert_eq!((2, Checkpoint::new(inputs.clone(), 1, 7)), read());
+        checkpointer.finish(&transaction_processor);
+        assert_eq!((3, Checkpoint::new(inputs, 2, 0)), read());
+    }
+}
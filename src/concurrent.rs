@@ -0,0 +1,266 @@
+use std::collections::HashMap;
+use std::num::NonZeroUsize;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+use crate::{
+    ClientId, MergeError, ReportEntry, Transaction, TransactionError, TransactionProcessor,
+};
+
+/// The number of shards used by [`ConcurrentTransactionProcessor::from`].
+pub const DEFAULT_SHARDS: NonZeroUsize = NonZeroUsize::new(64).unwrap();
+
+/// The clients whose accounts were created for a client key, shared by the
+/// shards.
+#[derive(Debug, Default)]
+struct ClientKeys {
+    clients: HashMap<String, ClientId>,
+    /// The lowest client ID which may be free for a new client key.
+    next: ClientId,
+}
+
+/// A processor which can apply transactions from several threads at once
+/// (it is `Send` and `Sync`), e.g. for a server handling concurrent
+/// requests.
+///
+/// The accounts are split between shards by client ID, each a
+/// [`TransactionProcessor`] behind its own lock, so transactions for clients
+/// in different shards never contend. The transactions of each client are
+/// applied one at a time, in the order the threads get the lock, with the
+/// same results as [`TransactionProcessor::transact`].
+///
+/// A transaction with a new client key briefly locks the shards in turn to
+/// find a free client ID, as it gets the lowest one which no account has.
+pub struct ConcurrentTransactionProcessor {
+    /// The options of the shards' processors, without any accounts.
+    template: TransactionProcessor,
+    shards: Box<[Mutex<TransactionProcessor>]>,
+    client_keys: Mutex<ClientKeys>,
+}
+
+impl From<TransactionProcessor> for ConcurrentTransactionProcessor {
+    fn from(transaction_processor: TransactionProcessor) -> Self {
+        Self::new(transaction_processor, DEFAULT_SHARDS)
+    }
+}
+
+impl ConcurrentTransactionProcessor {
+    /// Creates a processor with the accounts and options of
+    /// `transaction_processor`, split between `shards` shards.
+    #[must_use]
+    pub fn new(mut transaction_processor: TransactionProcessor, shards: NonZeroUsize) -> Self {
+        let mut shards = (0..shards.get())
+            .map(|_| transaction_processor.new_like())
+            .collect::<Vec<_>>();
+        let count = shards.len();
+        for (client, account) in std::mem::take(&mut transaction_processor.clients) {
+            let shard = &mut shards[Self::shard_index(client, count)];
+            if let Some(key) = &account.key {
+                shard.client_keys.insert(key.clone(), client);
+            }
+            shard.clients.insert(client, account);
+        }
+
+        Self {
+            shards: shards.into_iter().map(Mutex::new).collect(),
+            client_keys: Mutex::new(ClientKeys {
+                clients: std::mem::take(&mut transaction_processor.client_keys),
+                next: transaction_processor.next_keyed_client,
+            }),
+            template: transaction_processor,
+        }
+    }
+
+    /// The number of shards.
+    #[must_use]
+    pub fn shards(&self) -> usize {
+        self.shards.len()
+    }
+
+    fn shard_index(client: ClientId, shards: usize) -> usize {
+        client as usize % shards
+    }
+
+    /// Locks the shard of a client's account.
+    fn shard(&self, client: ClientId) -> MutexGuard<'_, TransactionProcessor> {
+        self.shards[Self::shard_index(client, self.shards.len())]
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Attempts to apply the specified transaction, blocking while another
+    /// thread applies a transaction for a client in the same shard.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error from [`TransactionProcessor::transact`] if the
+    /// transaction fails to be applied.
+    pub fn transact(&self, transaction: &Transaction) -> Result<(), TransactionError> {
+        let Some(key) = transaction.client_key() else {
+            return self.shard(transaction.client()).transact(transaction);
+        };
+
+        // Held until the key's account is created, so that it is only
+        // created once
+        let mut client_keys = self
+            .client_keys
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        if let Some(client) = client_keys.clients.get(key).copied() {
+            drop(client_keys);
+            return self.shard(client).transact_for(client, transaction);
+        }
+
+        // The shard stays locked from finding the ID free to creating the
+        // account, so a transaction without a key can't take it in between
+        for client in client_keys.next..=ClientId::MAX {
+            let mut shard = self.shard(client);
+            if shard.clients.contains_key(&client) {
+                continue;
+            }
+            let result = shard.transact_for(client, transaction);
+            if shard.clients.contains_key(&client) {
+                client_keys.clients.insert(key.to_string(), client);
+                client_keys.next = client.saturating_add(1);
+            }
+            return result;
+        }
+        Err(TransactionError::ClientIdsExhausted)
+    }
+
+    /// Returns the report entry for a single client account (see
+    /// [`TransactionProcessor::account_report`]).
+    #[must_use]
+    pub fn account_report(&self, client: ClientId) -> Option<ReportEntry> {
+        self.shard(client).account_report(client)
+    }
+
+    /// Generates a report of all client accounts, in client ID order (see
+    /// [`TransactionProcessor::generate_report_as_vec`]).
+    ///
+    /// The shards are locked in turn rather than all at once, so other
+    /// threads can keep applying transactions, but the report may include
+    /// some transactions applied while it was generated and not others.
+    #[must_use]
+    pub fn generate_report_as_vec(&self) -> Vec<ReportEntry> {
+        let mut report = self
+            .shards
+            .iter()
+            .flat_map(|shard| {
+                shard
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .generate_report_as_vec()
+            })
+            .collect::<Vec<_>>();
+        report.sort_unstable_by_key(|entry| entry.client);
+        report
+    }
+
+    /// Combines the shards back into a single processor, e.g. to write a
+    /// snapshot of it.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error from [`TransactionProcessor::merge`] if the shards'
+    /// accounts could not be combined.
+    pub fn into_inner(self) -> Result<TransactionProcessor, MergeError> {
+        let mut transaction_processor = self.template;
+        for shard in self.shards.into_vec() {
+            transaction_processor
+                .merge(shard.into_inner().unwrap_or_else(PoisonError::into_inner))?;
+        }
+        transaction_processor.next_keyed_client = self
+            .client_keys
+            .into_inner()
+            .unwrap_or_else(PoisonError::into_inner)
+            .next;
+        Ok(transaction_processor)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::num::NonZeroUsize;
+    use std::str::FromStr;
+    use std::thread;
+
+    use crate::concurrent::ConcurrentTransactionProcessor;
+    use crate::numeric::CurrencyAmount;
+    use crate::{Transaction, TransactionProcessor, TransactionType};
+
+    #[test]
+    fn test_concurrent_transaction_processor() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<ConcurrentTransactionProcessor>();
+
+        let deposit = |client, tx| {
+            Transaction::new(
+                client,
+                tx,
+                TransactionType::Deposit {
+                    amount: CurrencyAmount::from_str("1.5").unwrap(),
+                },
+            )
+        };
+        let mut transaction_processor = TransactionProcessor::new();
+        for client in 1..=10 {
+            transaction_processor.transact(&deposit(client, 1)).unwrap();
+        }
+        transaction_processor
+            .transact(&deposit(0, 2).with_client_key("acme"))
+            .unwrap();
+
+        // Threads apply transactions for the same and different clients,
+        // and for new client keys, at once
+        let concurrent = ConcurrentTransactionProcessor::new(
+            transaction_processor,
+            NonZeroUsize::new(4).unwrap(),
+        );
+        thread::scope(|scope| {
+            for thread in 0..8 {
+                let concurrent = &concurrent;
+                scope.spawn(move || {
+                    let mut tx = thread * 100 + 10;
+                    for _ in 0..10 {
+                        for client in 1..=10 {
+                            tx += 1;
+                            concurrent.transact(&deposit(client, tx)).unwrap();
+                        }
+                    }
+                    concurrent
+                        .transact(&deposit(0, 1).with_client_key(format!("key-{}", thread)))
+                        .unwrap();
+                    concurrent
+                        .transact(&deposit(0, 2).with_client_key("acme"))
+                        .unwrap_err();
+                });
+            }
+        });
+
+        let report = concurrent.generate_report_as_vec();
+        assert_eq!(
+            Some(CurrencyAmount::from_str("121.5").unwrap()),
+            concurrent.account_report(1).map(|entry| entry.available)
+        );
+        assert_eq!(
+            Some("acme"),
+            concurrent.account_report(0).unwrap().client_key()
+        );
+
+        // The new keys got distinct clients, which had no accounts
+        assert_eq!(
+            (11..=18).collect::<Vec<_>>(),
+            report
+                .iter()
+                .filter(|entry| entry
+                    .client_key()
+                    .is_some_and(|key| key.starts_with("key-")))
+                .map(|entry| entry.client)
+                .collect::<Vec<_>>()
+        );
+
+        let transaction_processor = concurrent.into_inner().unwrap();
+        assert_eq!(report, transaction_processor.generate_report_as_vec());
+        assert!(transaction_processor.check_invariants().is_empty());
+    }
+}
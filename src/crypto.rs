@@ -0,0 +1,166 @@
+use std::fmt::{Debug, Formatter};
+use std::io::{self, Write};
+
+use hmac::digest::InvalidLength;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+/// A secret key for HMAC-SHA256 signatures. The key is not shown when
+/// debug-formatted.
+#[derive(Clone)]
+pub struct HmacKey(Hmac<Sha256>);
+
+impl HmacKey {
+    /// Creates a key from its raw bytes. Keys should be at least 32 bytes.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error from HMAC if it rejects the key.
+    pub fn new(key: impl AsRef<[u8]>) -> Result<Self, InvalidLength> {
+        Hmac::<Sha256>::new_from_slice(key.as_ref()).map(Self)
+    }
+
+    /// Signs a message, returning the signature as lowercase hex.
+    #[must_use]
+    pub fn sign(&self, message: &[u8]) -> String {
+        let mut mac = self.mac();
+        mac.update(message);
+        to_hex(&mac.finalize().into_bytes())
+    }
+
+    /// Checks a hex signature of a message (in either case), in constant
+    /// time.
+    #[must_use]
+    pub fn verify(&self, message: &[u8], signature: &str) -> bool {
+        match from_hex(signature) {
+            Some(signature) => {
+                let mut mac = self.mac();
+                mac.update(message);
+                mac.verify_slice(&signature).is_ok()
+            }
+            None => false,
+        }
+    }
+
+    fn mac(&self) -> Hmac<Sha256> {
+        self.0.clone()
+    }
+}
+
+impl Debug for HmacKey {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str("HmacKey(..)")
+    }
+}
+
+/// Passes data through to another writer, signing it as it is written, so
+/// that a detached signature can be produced without buffering the data.
+pub struct SigningWriter<W: Write> {
+    writer: W,
+    mac: Hmac<Sha256>,
+}
+
+impl<W: Write> SigningWriter<W> {
+    /// Creates a writer which signs everything written to `writer` with
+    /// `key`.
+    pub fn new(writer: W, key: &HmacKey) -> Self {
+        Self {
+            writer,
+            mac: key.mac(),
+        }
+    }
+
+    /// The signature of everything written so far, as lowercase hex. This
+    /// is the same as [`HmacKey::sign`] of the data.
+    #[must_use]
+    pub fn signature(&self) -> String {
+        to_hex(&self.mac.clone().finalize().into_bytes())
+    }
+
+    /// Returns the underlying writer.
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+impl<W: Write> Write for SigningWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // Only the bytes accepted by the writer are signed
+        let written = self.writer.write(buf)?;
+        self.mac.update(&buf[..written]);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+/// Formats bytes as lowercase hex.
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Parses hex digits (in either case) into bytes.
+fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) || !hex.is_ascii() {
+        return None;
+    }
+
+    (0..hex.len())
+        .step_by(2)
+        .map(|index| u8::from_str_radix(&hex[index..index + 2], 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Write;
+
+    use crate::crypto::{from_hex, HmacKey, SigningWriter};
+
+    #[test]
+    fn test_hmac() {
+        // RFC 4231 test case 2
+        let key = HmacKey::new("Jefe").unwrap();
+        let signature = "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843";
+        assert_eq!(signature, key.sign(b"what do ya want for nothing?"));
+
+        assert!(key.verify(b"what do ya want for nothing?", signature));
+        assert!(key.verify(
+            b"what do ya want for nothing?",
+            &signature.to_ascii_uppercase()
+        ));
+        assert!(!key.verify(b"what do ya want for nothing!", signature));
+        assert!(!key.verify(b"what do ya want for nothing?", &signature[2..]));
+        assert!(!key.verify(b"what do ya want for nothing?", "not hex"));
+
+        assert_eq!("HmacKey(..)", format!("{:?}", key));
+    }
+
+    #[test]
+    fn test_signing_writer() {
+        let key = HmacKey::new("Jefe").unwrap();
+        let mut writer = SigningWriter::new(Vec::new(), &key);
+        assert_eq!(key.sign(b""), writer.signature());
+
+        writer.write_all(b"what do ya ").unwrap();
+        writer.write_all(b"want for nothing?").unwrap();
+        assert_eq!(
+            key.sign(b"what do ya want for nothing?"),
+            writer.signature()
+        );
+        assert_eq!(
+            b"what do ya want for nothing?".as_slice(),
+            writer.into_inner().as_slice()
+        );
+    }
+
+    #[test]
+    fn test_from_hex() {
+        assert_eq!(Some(vec![0x01, 0xab, 0xff]), from_hex("01abFF"));
+        assert_eq!(Some(Vec::new()), from_hex(""));
+        assert_eq!(None, from_hex("abc"));
+        assert_eq!(None, from_hex("zz"));
+    }
+}
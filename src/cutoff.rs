@@ -0,0 +1,257 @@
+use std::collections::HashSet;
+#[cfg(any(feature = "csv", feature = "jsonl"))]
+use std::io;
+
+#[cfg(feature = "serde")]
+use serde::ser::SerializeStruct;
+#[cfg(feature = "serde")]
+use serde::{Serialize, Serializer};
+
+#[cfg(feature = "csv")]
+use crate::io::csv::CSVWriter;
+#[cfg(feature = "jsonl")]
+use crate::io::jsonl::JsonlWriter;
+#[cfg(any(feature = "csv", feature = "jsonl"))]
+use crate::report::ReportSink;
+use crate::timestamp::{Timestamp, TimestampDate};
+#[cfg(feature = "serde")]
+use crate::ReportEntry;
+use crate::{ClientId, Transaction, TransactionId, TransactionType};
+
+/// The column of a report which [`DatedEntry`] stamps with the business
+/// date.
+pub const BUSINESS_DATE_COLUMN: &str = "business_date";
+
+/// Divides the transactions of an end-of-day settlement batch between those
+/// before its cutoff time, which are applied in this batch, and those at or
+/// after it, which are deferred to the next one (e.g. by carrying them over
+/// with [`crate::snapshot::write_snapshot_with_deferred`]).
+///
+/// Transactions without a timestamp are applied, unless they are disputes,
+/// resolutions or chargebacks of a deferred deposit or withdrawal, which are
+/// deferred with it.
+#[derive(Clone, Debug)]
+pub struct Cutoff {
+    cutoff: Timestamp,
+    deferred: Vec<Transaction>,
+    /// The clients and IDs of the deferred deposits and withdrawals.
+    deferred_ids: HashSet<(ClientId, TransactionId)>,
+}
+
+impl Cutoff {
+    /// Creates a cutoff at `cutoff`, which has deferred nothing yet.
+    #[must_use]
+    pub fn new(cutoff: Timestamp) -> Self {
+        Self {
+            cutoff,
+            deferred: Vec::new(),
+            deferred_ids: HashSet::new(),
+        }
+    }
+
+    /// The time from which transactions are deferred.
+    #[must_use]
+    pub const fn cutoff(&self) -> Timestamp {
+        self.cutoff
+    }
+
+    /// The business date of the batch: the date of the last second before
+    /// the cutoff, so that a cutoff at midnight (e.g. `2024-03-02`) closes
+    /// the day before it.
+    #[must_use]
+    pub fn business_date(&self) -> TimestampDate {
+        Timestamp::from_unix_seconds(self.cutoff.unix_seconds() - 1)
+            .unwrap_or(self.cutoff)
+            .date()
+    }
+
+    /// Defers `transaction` to the next batch, returning true, if it is at
+    /// or after the cutoff, or refers to a deferred transaction. Otherwise,
+    /// returns false, and it should be applied.
+    pub fn defer(&mut self, transaction: &Transaction) -> bool {
+        let id = (transaction.client(), transaction.tx());
+        let creates = matches!(
+            transaction.transaction_type(),
+            TransactionType::Deposit { .. } | TransactionType::Withdrawal { .. }
+        );
+        let after_cutoff = transaction
+            .timestamp()
+            .is_some_and(|timestamp| timestamp >= self.cutoff);
+        if !after_cutoff && (creates || !self.deferred_ids.contains(&id)) {
+            return false;
+        }
+
+        if creates {
+            self.deferred_ids.insert(id);
+        }
+        self.deferred.push(transaction.clone());
+        true
+    }
+
+    /// The transactions deferred so far, in the order they were deferred.
+    #[must_use]
+    pub fn deferred(&self) -> &[Transaction] {
+        &self.deferred
+    }
+
+    /// Returns the deferred transactions, for the next batch.
+    #[must_use]
+    pub fn into_deferred(self) -> Vec<Transaction> {
+        self.deferred
+    }
+}
+
+/// A report entry stamped with the business date of its batch, which is
+/// serialized before the entry's fields as [`BUSINESS_DATE_COLUMN`].
+#[cfg(feature = "serde")]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct DatedEntry {
+    business_date: TimestampDate,
+    entry: ReportEntry,
+}
+
+#[cfg(feature = "serde")]
+impl DatedEntry {
+    /// Stamps `entry` with `business_date`.
+    #[must_use]
+    pub const fn new(business_date: TimestampDate, entry: ReportEntry) -> Self {
+        Self {
+            business_date,
+            entry,
+        }
+    }
+
+    /// The business date of the batch.
+    #[must_use]
+    pub const fn business_date(&self) -> TimestampDate {
+        self.business_date
+    }
+
+    /// The report entry.
+    #[must_use]
+    pub const fn entry(&self) -> &ReportEntry {
+        &self.entry
+    }
+}
+
+#[cfg(feature = "serde")]
+impl Serialize for DatedEntry {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut entry = serializer.serialize_struct("DatedEntry", 6)?;
+        entry.serialize_field(BUSINESS_DATE_COLUMN, &self.business_date.to_string())?;
+        self.entry.serialize_fields(&mut entry)?;
+        entry.end()
+    }
+}
+
+/// Writes each entry of a report as a [`DatedEntry`] with the business date,
+/// e.g. with [`crate::pipeline::write_report`]. It is a [`ReportSink`] for the
+/// CSV and JSONL writers.
+#[derive(Debug)]
+pub struct DatedSink<W> {
+    writer: W,
+    business_date: TimestampDate,
+}
+
+impl<W> DatedSink<W> {
+    /// Creates a sink stamping the entries written to `writer` with
+    /// `business_date`.
+    pub const fn new(writer: W, business_date: TimestampDate) -> Self {
+        Self {
+            writer,
+            business_date,
+        }
+    }
+
+    /// The business date which the entries are stamped with.
+    #[must_use]
+    pub const fn business_date(&self) -> TimestampDate {
+        self.business_date
+    }
+
+    /// Returns the writer.
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+#[cfg(feature = "csv")]
+impl<W: io::Write> ReportSink for DatedSink<CSVWriter<W>> {
+    fn write_entry(&mut self, entry: ReportEntry) -> io::Result<()> {
+        self.writer
+            .write(DatedEntry::new(self.business_date, entry))
+            .map_err(|err| io::Error::other(err.to_string()))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        ReportSink::flush(&mut self.writer)
+    }
+}
+
+#[cfg(feature = "jsonl")]
+impl<W: io::Write> ReportSink for DatedSink<JsonlWriter<W>> {
+    fn write_entry(&mut self, entry: ReportEntry) -> io::Result<()> {
+        self.writer
+            .write(DatedEntry::new(self.business_date, entry))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+#[cfg(all(test, feature = "csv"))]
+mod test {
+    use std::str::FromStr;
+
+    use crate::cutoff::{Cutoff, DatedSink};
+    use crate::io::csv::CSVWriter;
+    use crate::numeric::CurrencyAmount;
+    use crate::pipeline::{self, PipelineOptions};
+    use crate::timestamp::Timestamp;
+    use crate::{Transaction, TransactionProcessor, TransactionType};
+
+    #[test]
+    fn test_cutoff() {
+        let timestamp = |timestamp| Timestamp::from_str(timestamp).unwrap();
+        let deposit = |client, tx, at| {
+            Transaction::new(
+                client,
+                tx,
+                TransactionType::Deposit {
+                    amount: CurrencyAmount::from_str("1").unwrap(),
+                },
+            )
+            .with_timestamp(timestamp(at))
+        };
+
+        let mut cutoff = Cutoff::new(timestamp("2024-03-02"));
+        assert_eq!("2024-03-01", cutoff.business_date().to_string());
+
+        let transactions = [
+            deposit(1, 1, "2024-03-01T23:59:59Z"),
+            deposit(1, 2, "2024-03-02T00:00:00Z"),
+            // Without a timestamp, a dispute is deferred with the deposit
+            Transaction::new(1, 2, TransactionType::Dispute),
+            Transaction::new(1, 1, TransactionType::Dispute),
+        ];
+        let mut transaction_processor = TransactionProcessor::new();
+        for transaction in &transactions {
+            if !cutoff.defer(transaction) {
+                let _ = transaction_processor.transact(transaction);
+            }
+        }
+        assert_eq!(&transactions[1..3], cutoff.deferred());
+
+        // The report is stamped with the business date
+        let writer = DatedSink::new(CSVWriter::new(Vec::new()), cutoff.business_date());
+        let writer =
+            pipeline::write_report(&transaction_processor, &PipelineOptions::new(), writer)
+                .unwrap();
+        assert_eq!(
+            "business_date,client,available,held,total,locked\n\
+             2024-03-01,1,0,1,1,false\n",
+            String::from_utf8(writer.into_inner().into_inner().unwrap()).unwrap()
+        );
+    }
+}
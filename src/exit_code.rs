@@ -0,0 +1,157 @@
+use crate::TransactionProcessorCLIError;
+
+/// The status the process exits with, by the class of failure, so that
+/// wrapper scripts can branch on it. The values are part of the CLI's
+/// interface (see README.md), so must never be changed or reused.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[repr(u8)]
+pub enum ExitCode {
+    /// The run completed, although rows may have been rejected (without
+    /// `--strict`).
+    Success = 0,
+    /// The arguments or environment variables are invalid. clap also exits
+    /// with this for usage errors.
+    InvalidArguments = 2,
+    /// An input file could not be opened or read, or failed checksum
+    /// verification.
+    UnreadableInput = 3,
+    /// With `--strict`, a row could not be read as a transaction.
+    ParseError = 4,
+    /// With `--strict`, a transaction failed to be applied.
+    TransactionRejected = 5,
+    /// The report, or another output file, could not be written.
+    OutputFailed = 6,
+    /// With `--jobs`, a client appears in more than one input file.
+    FilesNotIndependent = 7,
+    /// A server or the Kafka consumer failed.
+    ServiceFailed = 8,
+    /// An internal invariant was violated, i.e. there is a bug. This is the
+    /// status Rust exits with for a panic.
+    InternalError = 101,
+    /// The `--tui` dashboard was closed before processing finished. This is
+    /// the status shells use for `SIGINT`.
+    Interrupted = 130,
+}
+
+impl ExitCode {
+    /// Every exit code, in order.
+    const ALL: [ExitCode; 10] = [
+        ExitCode::Success,
+        ExitCode::InvalidArguments,
+        ExitCode::UnreadableInput,
+        ExitCode::ParseError,
+        ExitCode::TransactionRejected,
+        ExitCode::OutputFailed,
+        ExitCode::FilesNotIndependent,
+        ExitCode::ServiceFailed,
+        ExitCode::InternalError,
+        ExitCode::Interrupted,
+    ];
+
+    /// A short description, for `--help`.
+    const fn description(self) -> &'static str {
+        match self {
+            ExitCode::Success => "Success (rows may have been rejected without --strict)",
+            ExitCode::InvalidArguments => "Invalid arguments or environment variables",
+            ExitCode::UnreadableInput => "An input file could not be read or verified",
+            ExitCode::ParseError => "A row could not be read, with --strict",
+            ExitCode::TransactionRejected => "A transaction failed to be applied, with --strict",
+            ExitCode::OutputFailed => "The report or another output file could not be written",
+            ExitCode::FilesNotIndependent => "A client appears in more than one file, with --jobs",
+            ExitCode::ServiceFailed => "A server or the Kafka consumer failed",
+            ExitCode::InternalError => "Internal error (a bug)",
+            ExitCode::Interrupted => "Interrupted from the --tui dashboard",
+        }
+    }
+
+    /// The table of exit codes shown at the end of `--help`.
+    pub fn help() -> String {
+        let mut help = "EXIT CODES:".to_string();
+        for code in ExitCode::ALL {
+            help.push_str(&format!("\n    {:<5}{}", code as u8, code.description()));
+        }
+        help
+    }
+
+    /// Exits the process with this status.
+    pub fn exit(self) -> ! {
+        std::process::exit(i32::from(self as u8))
+    }
+}
+
+impl From<&TransactionProcessorCLIError> for ExitCode {
+    fn from(err: &TransactionProcessorCLIError) -> Self {
+        match err {
+            TransactionProcessorCLIError::FailedToOpenFile { .. }
+            | TransactionProcessorCLIError::ChecksumFailed { .. } => ExitCode::UnreadableInput,
+            TransactionProcessorCLIError::InvalidInputFile { .. } => ExitCode::UnreadableInput,
+            TransactionProcessorCLIError::InvalidRecord { .. } => ExitCode::ParseError,
+            TransactionProcessorCLIError::TransactionFailed { .. } => ExitCode::TransactionRejected,
+            TransactionProcessorCLIError::FailedToCreateOutput { .. } => ExitCode::OutputFailed,
+            TransactionProcessorCLIError::FailedToWriteReport(_) => ExitCode::OutputFailed,
+            TransactionProcessorCLIError::FilesNotIndependent { .. } => {
+                ExitCode::FilesNotIndependent
+            }
+            TransactionProcessorCLIError::LedgerUnbalanced(_) => ExitCode::InternalError,
+            #[cfg(any(feature = "server", feature = "grpc"))]
+            TransactionProcessorCLIError::ServerFailed { .. } => ExitCode::ServiceFailed,
+            #[cfg(feature = "kafka")]
+            TransactionProcessorCLIError::KafkaFailed(_)
+            | TransactionProcessorCLIError::SnapshotFailed { .. } => ExitCode::ServiceFailed,
+            #[cfg(feature = "tui")]
+            TransactionProcessorCLIError::FailedToShowDashboard(_) => ExitCode::OutputFailed,
+            #[cfg(feature = "tui")]
+            TransactionProcessorCLIError::Interrupted => ExitCode::Interrupted,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::exit_code::ExitCode;
+    use crate::TransactionProcessorCLIError;
+
+    #[test]
+    fn test_from_error() {
+        let failed = |parse_error| {
+            let (path, record, error) = ("input.csv".to_string(), 1, String::new());
+            if parse_error {
+                TransactionProcessorCLIError::InvalidRecord {
+                    path,
+                    record,
+                    error,
+                }
+            } else {
+                TransactionProcessorCLIError::TransactionFailed {
+                    path,
+                    record,
+                    error,
+                }
+            }
+        };
+        assert_eq!(ExitCode::ParseError, ExitCode::from(&failed(true)));
+        assert_eq!(
+            ExitCode::TransactionRejected,
+            ExitCode::from(&failed(false))
+        );
+        assert_eq!(
+            ExitCode::UnreadableInput,
+            ExitCode::from(&TransactionProcessorCLIError::FailedToOpenFile {
+                path: "input.csv".to_string(),
+                error: std::io::ErrorKind::NotFound.into(),
+            })
+        );
+
+        #[cfg(feature = "tui")]
+        assert_eq!(
+            ExitCode::Interrupted,
+            ExitCode::from(&TransactionProcessorCLIError::Interrupted)
+        );
+
+        // The values are documented, so must not change
+        assert_eq!(
+            [0, 2, 3, 4, 5, 6, 7, 8, 101, 130],
+            ExitCode::ALL.map(|code| code as u8)
+        );
+    }
+}
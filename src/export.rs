@@ -0,0 +1,383 @@
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::fmt::{Display, Formatter};
+use std::io;
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+
+use crate::numeric::CurrencyAmount;
+use crate::{ClientAccount, ClientId, LockReason, TransactionId, TransactionProcessor};
+
+/// The version of the JSON structure written by
+/// [`TransactionProcessor::export_json`], in its `version` field. This must
+/// be incremented whenever the structure changes shape, so that older exports
+/// are rejected (or migrated) rather than misread.
+pub const EXPORT_VERSION: u64 = 1;
+
+/// Error returned when exporting or importing processor state as JSON.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum ExportError {
+    /// The state could not be encoded or written.
+    WriteError(String),
+    /// The data is not valid JSON, is missing a field, or has a field of the
+    /// wrong type or which isn't part of the structure.
+    ReadError(String),
+    /// The data was written by an unsupported version of the structure.
+    UnsupportedVersion(u64),
+    /// The data was read successfully, but describes an impossible state
+    /// (e.g. a dispute of a transaction which does not exist).
+    InvalidState(String),
+}
+
+impl Display for ExportError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&match self {
+            ExportError::WriteError(err) => format!("Export write error: {}", err),
+            ExportError::ReadError(err) => format!("Export read error: {}", err),
+            ExportError::UnsupportedVersion(version) => {
+                format!("Unsupported export version {}", version)
+            }
+            ExportError::InvalidState(err) => format!("Invalid exported state: {}", err),
+        })
+    }
+}
+
+/// The exported state of every client account, in client ID order.
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ExportedState {
+    version: u64,
+    accounts: Vec<ExportedAccount>,
+}
+
+/// The exported state of a single client account. Collections are sorted,
+/// so that the same state always produces the same JSON. When importing,
+/// the fields which are empty by default may be left out.
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ExportedAccount {
+    client: ClientId,
+    #[serde(default)]
+    key: Option<String>,
+    available: CurrencyAmount,
+    held: CurrencyAmount,
+    #[serde(default)]
+    lock: Option<ExportedLock>,
+    #[serde(default)]
+    frozen: bool,
+    #[serde(default)]
+    transactions: Vec<ExportedTransaction>,
+    #[serde(default)]
+    disputes: Vec<TransactionId>,
+    #[serde(default)]
+    resolved: Vec<TransactionId>,
+}
+
+/// Why an exported account is locked, e.g. `{"reason": "chargeback", "tx":
+/// 3}` or `{"reason": "admin"}`.
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "reason", rename_all = "lowercase", deny_unknown_fields)]
+enum ExportedLock {
+    Chargeback { tx: TransactionId },
+    Admin,
+    Unknown,
+}
+
+impl From<LockReason> for ExportedLock {
+    fn from(reason: LockReason) -> Self {
+        match reason {
+            LockReason::Chargeback(tx) => ExportedLock::Chargeback { tx },
+            LockReason::Admin => ExportedLock::Admin,
+            LockReason::Unknown => ExportedLock::Unknown,
+        }
+    }
+}
+
+impl From<ExportedLock> for LockReason {
+    fn from(lock: ExportedLock) -> Self {
+        match lock {
+            ExportedLock::Chargeback { tx } => LockReason::Chargeback(tx),
+            ExportedLock::Admin => LockReason::Admin,
+            ExportedLock::Unknown => LockReason::Unknown,
+        }
+    }
+}
+
+/// A deposit or withdrawal recorded by an exported account: the amount is
+/// positive for a deposit, and negative for a withdrawal.
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ExportedTransaction {
+    tx: TransactionId,
+    amount: CurrencyAmount,
+}
+
+impl ExportedAccount {
+    fn new(client: ClientId, account: &ClientAccount) -> Self {
+        let mut transactions: Vec<_> = account
+            .transactions
+            .iter()
+            .map(|(&tx, &amount)| ExportedTransaction { tx, amount })
+            .collect();
+        transactions.sort_unstable_by_key(|transaction| transaction.tx);
+
+        let mut disputes: Vec<_> = account.active_disputes.iter().copied().collect();
+        disputes.sort_unstable();
+
+        let mut resolved: Vec<_> = account.resolved.iter().copied().collect();
+        resolved.sort_unstable();
+
+        Self {
+            client,
+            key: account.key.clone(),
+            available: account.available,
+            held: account.held,
+            lock: account.lock.map(ExportedLock::from),
+            frozen: account.frozen,
+            transactions,
+            disputes,
+            resolved,
+        }
+    }
+
+    fn into_account(self) -> Result<ClientAccount, ExportError> {
+        let mut transactions = HashMap::with_capacity(self.transactions.len());
+        for transaction in self.transactions {
+            if transactions
+                .insert(transaction.tx, transaction.amount)
+                .is_some()
+            {
+                return Err(ExportError::InvalidState(format!(
+                    "Client {} has transaction {} more than once",
+                    self.client, transaction.tx
+                )));
+            }
+        }
+
+        let active_disputes: HashSet<_> = self.disputes.into_iter().collect();
+        if let Some(tx) = active_disputes
+            .iter()
+            .find(|tx| !transactions.contains_key(tx))
+        {
+            return Err(ExportError::InvalidState(format!(
+                "Client {} disputes unknown transaction {}",
+                self.client, tx
+            )));
+        }
+
+        Ok(ClientAccount {
+            available: self.available,
+            held: self.held,
+            transactions,
+            active_disputes,
+            resolved: self.resolved.into_iter().collect(),
+            order: VecDeque::new(),
+            lock: self.lock.map(LockReason::from),
+            frozen: self.frozen,
+            key: self.key,
+            version: 0,
+        })
+    }
+}
+
+/// Writes the state of every client account as pretty-printed JSON, for
+/// [`TransactionProcessor::export_json`].
+pub(crate) fn write_state(
+    transaction_processor: &TransactionProcessor,
+    mut writer: impl io::Write,
+) -> Result<(), ExportError> {
+    let state = ExportedState {
+        version: EXPORT_VERSION,
+        accounts: transaction_processor
+            .clients
+            .iter()
+            .map(|(client, account)| ExportedAccount::new(*client, account))
+            .collect(),
+    };
+    serde_json::to_writer_pretty(&mut writer, &state)
+        .map_err(io::Error::from)
+        .and_then(|_| writer.write_all(b"\n"))
+        .map_err(|err| ExportError::WriteError(format!("{}", err)))
+}
+
+/// Restores a processor, with default options, from JSON written by
+/// [`write_state`], for [`TransactionProcessor::import_json`].
+pub(crate) fn read_state(reader: impl io::Read) -> Result<TransactionProcessor, ExportError> {
+    let read_error = |err: serde_json::Error| ExportError::ReadError(format!("{}", err));
+    let value: serde_json::Value = serde_json::from_reader(reader).map_err(read_error)?;
+
+    // The version is checked first, so that the rest of a newer export isn't
+    // reported as malformed
+    match value.get("version").and_then(serde_json::Value::as_u64) {
+        Some(EXPORT_VERSION) => {}
+        Some(version) => return Err(ExportError::UnsupportedVersion(version)),
+        None => {
+            return Err(ExportError::ReadError(
+                "missing or invalid field `version`".to_string(),
+            ))
+        }
+    }
+    let state: ExportedState = serde_json::from_value(value).map_err(read_error)?;
+
+    let mut clients = BTreeMap::new();
+    let mut client_keys = HashMap::new();
+    for account in state.accounts {
+        let client = account.client;
+        if let Some(key) = &account.key {
+            if client_keys.insert(key.clone(), client).is_some() {
+                return Err(ExportError::InvalidState(format!(
+                    "Client key {} appears more than once",
+                    key
+                )));
+            }
+        }
+        if clients
+            .insert(client, Arc::new(account.into_account()?))
+            .is_some()
+        {
+            return Err(ExportError::InvalidState(format!(
+                "Client {} appears more than once",
+                client
+            )));
+        }
+    }
+
+    let restored_clients = clients.keys().copied().collect::<Vec<_>>();
+    let mut transaction_processor = TransactionProcessor {
+        clients,
+        client_keys,
+        ..TransactionProcessor::new()
+    };
+    // Every imported account is new to the processor
+    for client in restored_clients {
+        transaction_processor.record_change(client);
+    }
+    Ok(transaction_processor)
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+
+    use crate::export::ExportError;
+    use crate::numeric::CurrencyAmount;
+    use crate::{RedisputePolicy, Transaction, TransactionProcessor, TransactionType};
+
+    #[test]
+    fn test_export_json() {
+        let amount = |amount| CurrencyAmount::from_str(amount).unwrap();
+        let mut tp = TransactionProcessor::new().with_redispute_policy(RedisputePolicy::Never);
+        for transaction in [
+            Transaction::new(
+                1,
+                1,
+                TransactionType::Deposit {
+                    amount: amount("3"),
+                },
+            ),
+            Transaction::new(
+                1,
+                2,
+                TransactionType::Withdrawal {
+                    amount: amount("1"),
+                },
+            ),
+            Transaction::new(1, 1, TransactionType::Dispute),
+            Transaction::new(
+                2,
+                3,
+                TransactionType::Deposit {
+                    amount: amount("2"),
+                },
+            ),
+            Transaction::new(2, 3, TransactionType::Dispute),
+            Transaction::new(2, 3, TransactionType::Resolve),
+            Transaction::new(
+                3,
+                4,
+                TransactionType::Deposit {
+                    amount: amount("1"),
+                },
+            ),
+            Transaction::new(3, 4, TransactionType::Dispute),
+            Transaction::new(3, 4, TransactionType::Chargeback),
+            Transaction::new(
+                0,
+                5,
+                TransactionType::Deposit {
+                    amount: amount("1"),
+                },
+            )
+            .with_client_key("acct-a"),
+        ] {
+            tp.transact(&transaction).unwrap();
+        }
+        tp.freeze_account(2);
+
+        let mut json = Vec::new();
+        tp.export_json(&mut json).unwrap();
+        let json = String::from_utf8(json).unwrap();
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(
+            serde_json::json!({
+                "client": 1,
+                "key": null,
+                "available": "-1",
+                "held": "3",
+                "lock": null,
+                "frozen": false,
+                "transactions": [{"tx": 1, "amount": "3"}, {"tx": 2, "amount": "-1"}],
+                "disputes": [1],
+                "resolved": []
+            }),
+            value["accounts"][1]
+        );
+        assert_eq!(
+            serde_json::json!({"reason": "chargeback", "tx": 4}),
+            value["accounts"][3]["lock"]
+        );
+
+        // The imported state is the same, and is exported the same way again
+        let imported = TransactionProcessor::import_json(json.as_bytes()).unwrap();
+        assert_eq!(
+            tp.generate_report_as_vec(),
+            imported.generate_report_as_vec()
+        );
+        let mut exported = Vec::new();
+        imported.export_json(&mut exported).unwrap();
+        assert_eq!(json, String::from_utf8(exported).unwrap());
+
+        // A hand-written account only needs its balances
+        let imported = TransactionProcessor::import_json(
+            r#"{"version": 1, "accounts": [{"client": 7, "available": "2", "held": "0"}]}"#
+                .as_bytes(),
+        )
+        .unwrap();
+        assert_eq!(amount("2"), imported.account_report(7).unwrap().available);
+
+        for (json, err) in [
+            (
+                r#"{"version": 2, "accounts": []}"#,
+                ExportError::UnsupportedVersion(2),
+            ),
+            (
+                r#"{"version": 1, "accounts": [{"client": 7, "available": "0", "held": "1", "disputes": [1]}]}"#,
+                ExportError::InvalidState("Client 7 disputes unknown transaction 1".to_string()),
+            ),
+        ] {
+            assert_eq!(
+                err,
+                TransactionProcessor::import_json(json.as_bytes())
+                    .err()
+                    .unwrap()
+            );
+        }
+        assert!(matches!(
+            TransactionProcessor::import_json(
+                r#"{"version": 1, "accounts": [{"client": 7, "availible": "0", "held": "0"}]}"#
+                    .as_bytes()
+            ),
+            Err(ExportError::ReadError(_))
+        ));
+    }
+}
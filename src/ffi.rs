@@ -0,0 +1,413 @@
+use core::ffi::c_char;
+use std::ffi::CStr;
+
+use crate::numeric::{CurrencyAmount, CurrencyError};
+use crate::{
+    ClientId, ReportEntry, Transaction, TransactionError, TransactionId, TransactionProcessor,
+    TransactionType,
+};
+
+/// The result of an FFI call. Values are part of the ABI declared in
+/// `include/transaction_processor.h`, so must never be changed or reused.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum TxpStatus {
+    /// The call succeeded.
+    Ok = 0,
+    /// [`txp_report_next`] has returned every entry.
+    EndOfReport = 1,
+    /// A pointer argument was null.
+    NullPointer = -1,
+    /// The transaction type is not one of the `TXP_TRANSACTION_*` values.
+    InvalidTransactionType = -2,
+    /// An amount was negative, or an amount in the report does not fit in
+    /// an `int64_t`.
+    InvalidAmount = -3,
+    /// See [`TransactionError::TransactionDoesNotExist`].
+    TransactionDoesNotExist = -10,
+    /// See [`TransactionError::TransactionAlreadyExists`].
+    TransactionAlreadyExists = -11,
+    /// See [`TransactionError::DisputeAlreadyExists`].
+    DisputeAlreadyExists = -12,
+    /// See [`TransactionError::DisputeDoesNotExist`].
+    DisputeDoesNotExist = -13,
+    /// See [`TransactionError::CurrencyError`].
+    CurrencyError = -14,
+    /// See [`TransactionError::AccountIsLocked`].
+    AccountIsLocked = -15,
+    /// See [`TransactionError::NotEnoughFunds`].
+    NotEnoughFunds = -16,
+    /// See [`TransactionError::Replayed`].
+    Replayed = -17,
+    /// See [`TransactionError::BalanceCapExceeded`].
+    BalanceCapExceeded = -18,
+    /// See [`TransactionError::TransactionLimitReached`].
+    TransactionLimitReached = -19,
+    /// See [`TransactionError::AccountIsFrozen`].
+    AccountIsFrozen = -20,
+    /// See [`TransactionError::DisputeNotAllowed`].
+    DisputeNotAllowed = -21,
+    /// See [`TransactionError::UnknownClient`].
+    UnknownClient = -22,
+    /// See [`TransactionError::ClientIdsExhausted`].
+    ClientIdsExhausted = -23,
+}
+
+impl From<&TransactionError> for TxpStatus {
+    fn from(err: &TransactionError) -> Self {
+        match err {
+            TransactionError::TransactionDoesNotExist { .. } => TxpStatus::TransactionDoesNotExist,
+            TransactionError::TransactionAlreadyExists { .. } => {
+                TxpStatus::TransactionAlreadyExists
+            }
+            TransactionError::DisputeAlreadyExists { .. } => TxpStatus::DisputeAlreadyExists,
+            TransactionError::DisputeDoesNotExist { .. } => TxpStatus::DisputeDoesNotExist,
+            TransactionError::CurrencyError(_) => TxpStatus::CurrencyError,
+            TransactionError::AccountIsLocked { .. } => TxpStatus::AccountIsLocked,
+            TransactionError::NotEnoughFunds { .. } => TxpStatus::NotEnoughFunds,
+            TransactionError::Replayed(_) => TxpStatus::Replayed,
+            TransactionError::BalanceCapExceeded { .. } => TxpStatus::BalanceCapExceeded,
+            TransactionError::TransactionLimitReached { .. } => TxpStatus::TransactionLimitReached,
+            TransactionError::AccountIsFrozen { .. } => TxpStatus::AccountIsFrozen,
+            TransactionError::DisputeNotAllowed { .. } => TxpStatus::DisputeNotAllowed,
+            TransactionError::UnknownClient(_) => TxpStatus::UnknownClient,
+            TransactionError::ClientIdsExhausted => TxpStatus::ClientIdsExhausted,
+        }
+    }
+}
+
+impl TxpStatus {
+    /// Every status, for converting from the raw value.
+    const ALL: [TxpStatus; 19] = [
+        TxpStatus::Ok,
+        TxpStatus::EndOfReport,
+        TxpStatus::NullPointer,
+        TxpStatus::InvalidTransactionType,
+        TxpStatus::InvalidAmount,
+        TxpStatus::TransactionDoesNotExist,
+        TxpStatus::TransactionAlreadyExists,
+        TxpStatus::DisputeAlreadyExists,
+        TxpStatus::DisputeDoesNotExist,
+        TxpStatus::CurrencyError,
+        TxpStatus::AccountIsLocked,
+        TxpStatus::NotEnoughFunds,
+        TxpStatus::Replayed,
+        TxpStatus::BalanceCapExceeded,
+        TxpStatus::TransactionLimitReached,
+        TxpStatus::AccountIsFrozen,
+        TxpStatus::DisputeNotAllowed,
+        TxpStatus::UnknownClient,
+        TxpStatus::ClientIdsExhausted,
+    ];
+
+    fn message(self) -> &'static CStr {
+        match self {
+            TxpStatus::Ok => c"OK",
+            TxpStatus::EndOfReport => c"End of report",
+            TxpStatus::NullPointer => c"Null pointer",
+            TxpStatus::InvalidTransactionType => c"Invalid transaction type",
+            TxpStatus::InvalidAmount => c"Invalid amount",
+            TxpStatus::TransactionDoesNotExist => c"Transaction does not exist",
+            TxpStatus::TransactionAlreadyExists => c"Transaction already exists",
+            TxpStatus::DisputeAlreadyExists => c"Dispute already exists",
+            TxpStatus::DisputeDoesNotExist => c"Dispute does not exist",
+            TxpStatus::CurrencyError => c"Currency error",
+            TxpStatus::AccountIsLocked => c"Account is locked",
+            TxpStatus::NotEnoughFunds => c"Not enough funds",
+            TxpStatus::Replayed => c"Transaction is a replay",
+            TxpStatus::BalanceCapExceeded => c"Balance cap exceeded",
+            TxpStatus::TransactionLimitReached => c"Transaction limit reached",
+            TxpStatus::AccountIsFrozen => c"Account is frozen",
+            TxpStatus::DisputeNotAllowed => c"Dispute not allowed",
+            TxpStatus::UnknownClient => c"Unknown client",
+            TxpStatus::ClientIdsExhausted => c"No client IDs are left for a new client key",
+        }
+    }
+}
+
+/// `TXP_TRANSACTION_DEPOSIT`.
+pub const TXP_TRANSACTION_DEPOSIT: u32 = 0;
+/// `TXP_TRANSACTION_WITHDRAWAL`.
+pub const TXP_TRANSACTION_WITHDRAWAL: u32 = 1;
+/// `TXP_TRANSACTION_DISPUTE`.
+pub const TXP_TRANSACTION_DISPUTE: u32 = 2;
+/// `TXP_TRANSACTION_RESOLVE`.
+pub const TXP_TRANSACTION_RESOLVE: u32 = 3;
+/// `TXP_TRANSACTION_CHARGEBACK`.
+pub const TXP_TRANSACTION_CHARGEBACK: u32 = 4;
+
+/// A report entry. Amounts are in minor units with
+/// [`CurrencyAmount::MAX_DECIMAL_PLACES`] decimal places, e.g. `15000` for
+/// `1.5`.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct TxpReportEntry {
+    /// The ID of the client.
+    pub client: ClientId,
+    /// The amount of available funds.
+    pub available: i64,
+    /// The amount of held (i.e. disputed) funds.
+    pub held: i64,
+    /// The sum of the available and held funds.
+    pub total: i64,
+    /// Whether the account is locked.
+    pub locked: bool,
+}
+
+impl TryFrom<&ReportEntry> for TxpReportEntry {
+    type Error = CurrencyError;
+
+    fn try_from(entry: &ReportEntry) -> Result<Self, Self::Error> {
+        let minor_units =
+            |amount: CurrencyAmount| amount.to_minor_units(CurrencyAmount::MAX_DECIMAL_PLACES);
+        Ok(Self {
+            client: entry.client(),
+            available: minor_units(entry.available())?,
+            held: minor_units(entry.held())?,
+            total: minor_units(entry.total())?,
+            locked: entry.locked(),
+        })
+    }
+}
+
+/// An iterator over the report of a processor, as it was when the iterator
+/// was created.
+pub struct TxpReport(std::vec::IntoIter<ReportEntry>);
+
+/// Creates a processor with no client accounts. Free it with
+/// [`txp_processor_free`].
+#[no_mangle]
+pub extern "C" fn txp_processor_new() -> *mut TransactionProcessor {
+    Box::into_raw(Box::new(TransactionProcessor::new()))
+}
+
+/// Frees a processor created with [`txp_processor_new`]. Does nothing if
+/// `processor` is null.
+///
+/// # Safety
+///
+/// `processor` must be null, or a pointer returned by [`txp_processor_new`]
+/// which has not already been freed.
+#[no_mangle]
+pub unsafe extern "C" fn txp_processor_free(processor: *mut TransactionProcessor) {
+    if !processor.is_null() {
+        drop(Box::from_raw(processor));
+    }
+}
+
+/// Applies a transaction. `transaction_type` is one of the
+/// `TXP_TRANSACTION_*` constants. `amount` is only used for deposits and
+/// withdrawals, and is in minor units like the amounts of
+/// [`TxpReportEntry`], so that no precision is lost.
+///
+/// # Safety
+///
+/// `processor` must be null, or a valid pointer returned by
+/// [`txp_processor_new`] which is not in use by another thread.
+#[no_mangle]
+pub unsafe extern "C" fn txp_processor_transact(
+    processor: *mut TransactionProcessor,
+    transaction_type: u32,
+    client: ClientId,
+    tx: TransactionId,
+    amount: i64,
+) -> TxpStatus {
+    let Some(processor) = processor.as_mut() else {
+        return TxpStatus::NullPointer;
+    };
+
+    let transaction_type = match transaction_type {
+        TXP_TRANSACTION_DEPOSIT | TXP_TRANSACTION_WITHDRAWAL => {
+            let amount = match from_minor_units(amount) {
+                Ok(amount) => amount,
+                Err(status) => return status,
+            };
+            if transaction_type == TXP_TRANSACTION_DEPOSIT {
+                TransactionType::Deposit { amount }
+            } else {
+                TransactionType::Withdrawal { amount }
+            }
+        }
+        TXP_TRANSACTION_DISPUTE => TransactionType::Dispute,
+        TXP_TRANSACTION_RESOLVE => TransactionType::Resolve,
+        TXP_TRANSACTION_CHARGEBACK => TransactionType::Chargeback,
+        _ => return TxpStatus::InvalidTransactionType,
+    };
+
+    match processor.transact(&Transaction::new(client, tx, transaction_type)) {
+        Ok(()) => TxpStatus::Ok,
+        Err(err) => TxpStatus::from(&err),
+    }
+}
+
+fn from_minor_units(amount: i64) -> Result<CurrencyAmount, TxpStatus> {
+    if amount < 0 {
+        return Err(TxpStatus::InvalidAmount);
+    }
+    CurrencyAmount::from_minor_units(amount, CurrencyAmount::MAX_DECIMAL_PLACES)
+        .map_err(|_| TxpStatus::InvalidAmount)
+}
+
+/// Creates an iterator over the current report of `processor`, ordered by
+/// client ID. Later transactions don't affect it. Returns null if
+/// `processor` is null. Free it with [`txp_report_free`].
+///
+/// # Safety
+///
+/// `processor` must be null, or a valid pointer returned by
+/// [`txp_processor_new`] which is not being modified by another thread.
+#[no_mangle]
+pub unsafe extern "C" fn txp_report_new(processor: *const TransactionProcessor) -> *mut TxpReport {
+    match processor.as_ref() {
+        Some(processor) => Box::into_raw(Box::new(TxpReport(
+            processor.generate_report_as_vec().into_iter(),
+        ))),
+        None => core::ptr::null_mut(),
+    }
+}
+
+/// Writes the next report entry to `entry`. Returns
+/// [`TxpStatus::EndOfReport`] (leaving `entry` unchanged) once every entry
+/// has been returned.
+///
+/// If an amount in the entry does not fit in an `int64_t`, returns
+/// [`TxpStatus::InvalidAmount`] and skips the entry, leaving `entry`
+/// unchanged.
+///
+/// # Safety
+///
+/// `report` must be null, or a valid pointer returned by [`txp_report_new`].
+/// `entry` must be null, or valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn txp_report_next(
+    report: *mut TxpReport,
+    entry: *mut TxpReportEntry,
+) -> TxpStatus {
+    let (Some(report), Some(entry)) = (report.as_mut(), entry.as_mut()) else {
+        return TxpStatus::NullPointer;
+    };
+
+    match report.0.next() {
+        Some(next) => match TxpReportEntry::try_from(&next) {
+            Ok(next) => {
+                *entry = next;
+                TxpStatus::Ok
+            }
+            Err(_) => TxpStatus::InvalidAmount,
+        },
+        None => TxpStatus::EndOfReport,
+    }
+}
+
+/// Frees a report created with [`txp_report_new`]. Does nothing if `report`
+/// is null.
+///
+/// # Safety
+///
+/// `report` must be null, or a pointer returned by [`txp_report_new`] which
+/// has not already been freed.
+#[no_mangle]
+pub unsafe extern "C" fn txp_report_free(report: *mut TxpReport) {
+    if !report.is_null() {
+        drop(Box::from_raw(report));
+    }
+}
+
+/// A description of `status` (a [`TxpStatus`]), as a static NUL-terminated
+/// string which must not be freed.
+#[no_mangle]
+pub extern "C" fn txp_status_message(status: i32) -> *const c_char {
+    // The status is taken as an integer, as C callers may pass any value
+    TxpStatus::ALL
+        .into_iter()
+        .find(|known| *known as i32 == status)
+        .map_or(c"Unknown status", TxpStatus::message)
+        .as_ptr()
+}
+
+#[cfg(test)]
+mod test {
+    use std::ffi::CStr;
+
+    use crate::ffi::{
+        txp_processor_free, txp_processor_new, txp_processor_transact, txp_report_free,
+        txp_report_new, txp_report_next, txp_status_message, TxpReportEntry, TxpStatus,
+        TXP_TRANSACTION_CHARGEBACK, TXP_TRANSACTION_DEPOSIT, TXP_TRANSACTION_DISPUTE,
+        TXP_TRANSACTION_WITHDRAWAL,
+    };
+
+    #[test]
+    fn test_processor() {
+        unsafe {
+            let processor = txp_processor_new();
+            for (status, transaction_type, client, tx, amount) in [
+                (TxpStatus::Ok, TXP_TRANSACTION_DEPOSIT, 1, 1, 15_000),
+                (TxpStatus::Ok, TXP_TRANSACTION_DEPOSIT, 2, 2, 20_000),
+                (
+                    TxpStatus::NotEnoughFunds,
+                    TXP_TRANSACTION_WITHDRAWAL,
+                    1,
+                    3,
+                    20_000,
+                ),
+                (TxpStatus::InvalidAmount, TXP_TRANSACTION_DEPOSIT, 1, 4, -1),
+                (TxpStatus::InvalidTransactionType, 5, 1, 5, 0),
+                (TxpStatus::Ok, TXP_TRANSACTION_DISPUTE, 2, 2, 0),
+                (TxpStatus::Ok, TXP_TRANSACTION_CHARGEBACK, 2, 2, 0),
+            ] {
+                assert_eq!(
+                    status,
+                    txp_processor_transact(processor, transaction_type, client, tx, amount)
+                );
+            }
+
+            let report = txp_report_new(processor);
+            // The report doesn't change after it is created
+            txp_processor_transact(processor, TXP_TRANSACTION_DEPOSIT, 1, 6, 1);
+
+            let mut entry = TxpReportEntry::default();
+            assert_eq!(TxpStatus::Ok, txp_report_next(report, &mut entry));
+            assert_eq!(
+                TxpReportEntry {
+                    client: 1,
+                    available: 15_000,
+                    held: 0,
+                    total: 15_000,
+                    locked: false,
+                },
+                entry
+            );
+            assert_eq!(TxpStatus::Ok, txp_report_next(report, &mut entry));
+            assert_eq!(
+                TxpReportEntry {
+                    client: 2,
+                    available: 0,
+                    held: 0,
+                    total: 0,
+                    locked: true,
+                },
+                entry
+            );
+            assert_eq!(TxpStatus::EndOfReport, txp_report_next(report, &mut entry));
+
+            txp_report_free(report);
+            txp_processor_free(processor);
+
+            assert_eq!(
+                TxpStatus::NullPointer,
+                txp_processor_transact(std::ptr::null_mut(), TXP_TRANSACTION_DEPOSIT, 1, 1, 1)
+            );
+            assert!(txp_report_new(std::ptr::null()).is_null());
+            assert_eq!(
+                "Not enough funds",
+                CStr::from_ptr(txp_status_message(TxpStatus::NotEnoughFunds as i32))
+                    .to_str()
+                    .unwrap()
+            );
+            assert_eq!(
+                "Unknown status",
+                CStr::from_ptr(txp_status_message(2)).to_str().unwrap()
+            );
+        }
+    }
+}
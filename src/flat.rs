@@ -0,0 +1,175 @@
+use core::fmt::{Display, Formatter};
+
+use serde::{Deserialize, Serialize};
+
+use crate::numeric::CurrencyAmount;
+use crate::{ClientId, Transaction, TransactionId, TransactionType};
+
+/// The type of a [`FlatTransaction`].
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum FlatTransactionType {
+    /// See [`TransactionType::Deposit`].
+    Deposit,
+    /// See [`TransactionType::Withdrawal`].
+    Withdrawal,
+    /// See [`TransactionType::Dispute`].
+    Dispute,
+    /// See [`TransactionType::Resolve`].
+    Resolve,
+    /// See [`TransactionType::Chargeback`].
+    Chargeback,
+}
+
+/// The flat representation of a [`Transaction`], using the same fields as
+/// the CSV input format: `type,client,tx,amount`.
+///
+/// Unlike [`Transaction`], this can represent invalid transactions (e.g. a
+/// deposit without an amount), so it must be converted using
+/// [`Transaction::try_from`] before use.
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq)]
+pub struct FlatTransaction {
+    /// The type of the transaction.
+    #[serde(rename = "type")]
+    pub transaction_type: FlatTransactionType,
+    /// The client account which the transaction applies to.
+    pub client: ClientId,
+    /// The transaction ID.
+    pub tx: TransactionId,
+    /// The amount, which is required for deposits and withdrawals and
+    /// ignored otherwise.
+    pub amount: Option<CurrencyAmount>,
+}
+
+/// Error returned when a [`FlatTransaction`] is not a valid [`Transaction`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum FlatTransactionError {
+    /// A deposit or withdrawal did not specify an amount.
+    MissingAmount,
+}
+
+impl Display for FlatTransactionError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        f.write_str(match self {
+            FlatTransactionError::MissingAmount => "Missing amount",
+        })
+    }
+}
+
+impl TryFrom<FlatTransaction> for Transaction {
+    type Error = FlatTransactionError;
+
+    fn try_from(value: FlatTransaction) -> Result<Self, Self::Error> {
+        let amount = value.amount.ok_or(FlatTransactionError::MissingAmount);
+
+        Ok(Self::new(
+            value.client,
+            value.tx,
+            match value.transaction_type {
+                FlatTransactionType::Deposit => TransactionType::Deposit { amount: amount? },
+                FlatTransactionType::Withdrawal => TransactionType::Withdrawal { amount: amount? },
+                FlatTransactionType::Dispute => TransactionType::Dispute,
+                FlatTransactionType::Resolve => TransactionType::Resolve,
+                FlatTransactionType::Chargeback => TransactionType::Chargeback,
+            },
+        ))
+    }
+}
+
+impl From<Transaction> for FlatTransaction {
+    fn from(value: Transaction) -> Self {
+        let (transaction_type, amount) = match value.transaction_type {
+            TransactionType::Deposit { amount } => (FlatTransactionType::Deposit, Some(amount)),
+            TransactionType::Withdrawal { amount } => {
+                (FlatTransactionType::Withdrawal, Some(amount))
+            }
+            TransactionType::Dispute => (FlatTransactionType::Dispute, None),
+            TransactionType::Resolve => (FlatTransactionType::Resolve, None),
+            TransactionType::Chargeback => (FlatTransactionType::Chargeback, None),
+        };
+
+        Self {
+            transaction_type,
+            client: value.client,
+            tx: value.tx,
+            amount,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+
+    use crate::flat::{FlatTransaction, FlatTransactionError, FlatTransactionType};
+    use crate::numeric::CurrencyAmount;
+    use crate::{Transaction, TransactionType};
+
+    #[test]
+    fn test_tagged_representation() {
+        let deposit = Transaction::new(
+            1,
+            2,
+            TransactionType::Deposit {
+                amount: CurrencyAmount::from_str("1.50").unwrap(),
+            },
+        );
+        let json = r#"{"client":1,"tx":2,"type":"deposit","amount":"1.50"}"#;
+
+        assert_eq!(json, serde_json::to_string(&deposit).unwrap());
+        assert_eq!(deposit, serde_json::from_str(json).unwrap());
+
+        let dispute = Transaction::new(3, 4, TransactionType::Dispute);
+        let json = r#"{"client":3,"tx":4,"type":"dispute"}"#;
+
+        assert_eq!(json, serde_json::to_string(&dispute).unwrap());
+        assert_eq!(dispute, serde_json::from_str(json).unwrap());
+
+        assert!(
+            serde_json::from_str::<Transaction>(r#"{"client":1,"tx":2,"type":"deposit"}"#).is_err()
+        );
+        assert!(
+            serde_json::from_str::<Transaction>(r#"{"client":1,"tx":2,"type":"unknown"}"#).is_err()
+        );
+    }
+
+    #[test]
+    fn test_flat_representation() {
+        let withdrawal = Transaction::new(
+            5,
+            6,
+            TransactionType::Withdrawal {
+                amount: CurrencyAmount::from_str("3").unwrap(),
+            },
+        );
+        let flat = FlatTransaction::from(withdrawal.clone());
+
+        assert_eq!(
+            r#"{"type":"withdrawal","client":5,"tx":6,"amount":"3"}"#,
+            serde_json::to_string(&flat).unwrap()
+        );
+        assert_eq!(Ok(withdrawal), Transaction::try_from(flat));
+
+        let resolve = FlatTransaction {
+            transaction_type: FlatTransactionType::Resolve,
+            client: 7,
+            tx: 8,
+            amount: Some(CurrencyAmount::from_str("1").unwrap()),
+        };
+        assert_eq!(
+            Ok(Transaction::new(7, 8, TransactionType::Resolve)),
+            Transaction::try_from(resolve)
+        );
+
+        let missing_amount = FlatTransaction {
+            transaction_type: FlatTransactionType::Deposit,
+            client: 7,
+            tx: 8,
+            amount: None,
+        };
+        assert_eq!(
+            Err(FlatTransactionError::MissingAmount),
+            Transaction::try_from(missing_amount)
+        );
+    }
+}
@@ -0,0 +1,232 @@
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Mutex, PoisonError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use transaction_processor::pipeline;
+use transaction_processor::summary::BatchResult;
+use transaction_processor::TransactionProcessor;
+
+use crate::args::InputFormat;
+use crate::{apply_transactions, replace_report, ProcessOptions, TransactionProcessorCLIError};
+
+/// How often a followed file is checked for new data once the end has been
+/// reached.
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Reads a file which is still being appended to, like `tail -f`. At the
+/// end of the file, waits for more data instead of returning end-of-file, so
+/// reading never completes.
+pub struct FollowReader<R: Read> {
+    reader: R,
+    poll_interval: Duration,
+}
+
+impl<R: Read> FollowReader<R> {
+    /// Creates a reader which follows the specified reader, checking for new
+    /// data every `poll_interval` once the end has been reached.
+    pub fn new(reader: R, poll_interval: Duration) -> Self {
+        Self {
+            reader,
+            poll_interval,
+        }
+    }
+}
+
+impl<R: Read> Read for FollowReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        loop {
+            match self.reader.read(buf)? {
+                0 => thread::sleep(self.poll_interval),
+                read => return Ok(read),
+            }
+        }
+    }
+}
+
+/// The processor shared between the thread applying transactions and the
+/// thread writing reports.
+struct FollowState {
+    transaction_processor: TransactionProcessor,
+    /// True if transactions have been applied since the last report.
+    changed: bool,
+}
+
+/// Applies transactions from the CSV or JSON Lines file at `path` as they
+/// are appended to it, writing the report to `output` (or stdout) every
+/// `report_interval` if it has changed.
+///
+/// Only returns on a fatal error (including any failed row, if
+/// `options.fail_fast` is set).
+pub fn follow(
+    transaction_processor: TransactionProcessor,
+    path: &str,
+    options: &ProcessOptions,
+    output: Option<&Path>,
+    report_interval: Duration,
+) -> Result<BatchResult, TransactionProcessorCLIError> {
+    let start = Instant::now();
+    let file =
+        File::open(path).map_err(|error| TransactionProcessorCLIError::FailedToOpenFile {
+            path: path.to_string(),
+            error,
+        })?;
+    let reader = FollowReader::new(file, POLL_INTERVAL);
+
+    let state = Mutex::new(FollowState {
+        transaction_processor,
+        changed: false,
+    });
+    let transact = |transaction: &_| {
+        let mut state = state.lock().unwrap_or_else(PoisonError::into_inner);
+        let result = options.transact(&mut state.transaction_processor, transaction);
+        state.changed |= result.is_ok();
+        result
+    };
+
+    let mut summary = BatchResult::new();
+    summary.record_file();
+    let mut rejects = None;
+
+    // Reports are written from a separate thread, as the reading thread is
+    // blocked waiting for new data once it reaches the end of the file
+    let (stop, stopped) = mpsc::channel::<()>();
+    let state = &state;
+    let result = thread::scope(|scope| {
+        scope.spawn(move || {
+            while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(report_interval) {
+                if let Err(err) = write_changed_report(state, options, output) {
+                    log::error!("{}", err);
+                }
+            }
+        });
+
+        let format = options
+            .input_format
+            .unwrap_or_else(|| InputFormat::from_path(path));
+        let result = match format {
+            InputFormat::Jsonl => apply_transactions(
+                transact,
+                path,
+                pipeline::jsonl_rows(&mut pipeline::jsonl_reader(
+                    io::BufReader::new(reader),
+                    &options.pipeline,
+                )),
+                options,
+                0,
+                &mut rejects,
+                &mut summary,
+            ),
+            // Other formats are rejected when parsing the arguments
+            _ => {
+                let delimiter = format.delimiter().unwrap_or(b',');
+                let mut reader =
+                    pipeline::delimited_reader(reader, path, delimiter, &options.pipeline);
+                let mut warnings = 0;
+                let result = apply_transactions(
+                    transact,
+                    path,
+                    pipeline::csv_rows(&mut reader, &mut warnings),
+                    options,
+                    0,
+                    &mut rejects,
+                    &mut summary,
+                );
+                summary.record_warnings(warnings);
+                result
+            }
+        };
+
+        drop(stop);
+        result
+    });
+
+    result?;
+    summary.set_elapsed(start.elapsed());
+    Ok(summary)
+}
+
+/// Writes the report if transactions have been applied since it was last
+/// written. A report file is replaced each time.
+fn write_changed_report(
+    state: &Mutex<FollowState>,
+    options: &ProcessOptions,
+    output: Option<&Path>,
+) -> Result<(), TransactionProcessorCLIError> {
+    let mut state = state.lock().unwrap_or_else(PoisonError::into_inner);
+    if !state.changed {
+        return Ok(());
+    }
+    state.changed = false;
+
+    replace_report(&state.transaction_processor, options, output)
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs::{File, OpenOptions};
+    use std::io::{Read, Write};
+    use std::thread;
+    use std::time::Duration;
+
+    use transaction_processor::pipeline::PipelineOptions;
+    use transaction_processor::TransactionProcessor;
+
+    use crate::follow::{follow, FollowReader};
+    use crate::test::TempDir;
+    use crate::{ProcessOptions, TransactionProcessorCLIError};
+
+    #[test]
+    fn test_follow_reader() {
+        let directory = TempDir::new("follow");
+        let path = directory.join("input.csv");
+        std::fs::write(&path, b"first\n").unwrap();
+
+        let mut reader = FollowReader::new(File::open(&path).unwrap(), Duration::from_millis(10));
+
+        let mut buf = [0; 6];
+        reader.read_exact(&mut buf).unwrap();
+        assert_eq!(b"first\n", &buf);
+
+        let writer = {
+            let path = path.clone();
+            thread::spawn(move || {
+                thread::sleep(Duration::from_millis(50));
+                let mut file = OpenOptions::new().append(true).open(path).unwrap();
+                file.write_all(b"second").unwrap();
+            })
+        };
+
+        // Waits for the appended data rather than ending
+        reader.read_exact(&mut buf).unwrap();
+        assert_eq!(b"second", &buf);
+
+        writer.join().unwrap();
+    }
+
+    #[test]
+    fn test_follow_fail_fast() {
+        let result = follow(
+            TransactionProcessor::new(),
+            "test_data/002_input.csv",
+            &ProcessOptions {
+                pipeline: PipelineOptions::new().with_fail_fast(true),
+                ..ProcessOptions::default()
+            },
+            None,
+            Duration::from_secs(60),
+        );
+
+        assert!(matches!(
+            result,
+            Err(TransactionProcessorCLIError::TransactionFailed { record: 6, .. })
+        ));
+    }
+}
@@ -0,0 +1,411 @@
+//! Generators of valid but adversarial transactions, for fuzzing and
+//! property testing integrations with the processor.
+//!
+//! With the `arbitrary` feature, [`CurrencyAmount`], [`TransactionType`],
+//! [`Transaction`] and [`TransactionStream`] implement
+//! [`arbitrary::Arbitrary`], e.g. for `cargo fuzz`. With the `proptest`
+//! feature, the functions of this module return the equivalent proptest
+//! strategies.
+//!
+//! # Generated values
+//!
+//! Every generated value is valid, in that it could have been read from an
+//! input file:
+//!
+//! * Deposit and withdrawal amounts are never negative, and have at most
+//!   [`CurrencyAmount::MAX_DECIMAL_PLACES`] decimal places, so they are
+//!   accepted by every [`crate::numeric::PrecisionPolicy`] and round trip
+//!   through `Display` and `FromStr` (and so CSV and JSON) unchanged.
+//! * Transactions only have a client, a transaction ID and a type, and no
+//!   idempotency key, sequence number, timestamp or client key.
+//!
+//! Within those limits, values are chosen to find edge cases:
+//!
+//! * Amounts include zero, the smallest unit (`0.0001`), and amounts up to
+//!   [`CurrencyAmount::MAX`], so balances can overflow.
+//! * A [`TransactionStream`] uses a few clients, so accounts see many
+//!   transactions. Most disputes, resolutions and chargebacks refer to an
+//!   earlier deposit or withdrawal, possibly of another client, and some
+//!   deposits and withdrawals reuse an earlier transaction ID.
+//!
+//! # Processor invariants
+//!
+//! Whatever the stream of transactions, [`TransactionProcessor::transact`]
+//! never panics, and:
+//!
+//! * Every report entry's total is its available plus held funds, unless
+//!   they overflow (when the account is left out of the report, or saturated
+//!   with [`TransactionProcessor::with_saturating_totals`]).
+//! * A transaction which fails leaves every report entry unchanged, other
+//!   than adding an empty account for a new client.
+//! * A transaction which would take a balance out of the range of
+//!   [`CurrencyAmount`] (which is far larger with the `wide-amounts`
+//!   feature, but still reached by sums of [`CurrencyAmount::MAX`]) fails
+//!   with [`CurrencyError::OutOfBounds`], rather than the balance being
+//!   rounded to fit.
+//! * Applying the same stream to two processors with the same options gives
+//!   the same report.
+//! * [`TransactionProcessor::check_invariants`] finds no violations, other
+//!   than overflowing totals.
+//!
+//! [`TransactionProcessor::transact`]: crate::TransactionProcessor::transact
+//! [`CurrencyError::OutOfBounds`]: crate::numeric::CurrencyError::OutOfBounds
+//! [`TransactionProcessor::check_invariants`]: crate::TransactionProcessor::check_invariants
+//! [`TransactionProcessor::with_saturating_totals`]: crate::TransactionProcessor::with_saturating_totals
+
+use rust_decimal::Decimal;
+
+use crate::numeric::CurrencyAmount;
+use crate::{ClientId, Transaction, TransactionId, TransactionType};
+
+/// The number of clients a [`TransactionStream`] has transactions for.
+pub const STREAM_CLIENTS: ClientId = 4;
+
+/// A sequence of transactions for a few clients, most of which refer to
+/// each other (see the [module documentation](self)).
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct TransactionStream(Vec<Transaction>);
+
+impl TransactionStream {
+    /// The transactions, in the order they should be applied.
+    #[must_use]
+    pub fn transactions(&self) -> &[Transaction] {
+        &self.0
+    }
+
+    /// Returns the transactions, in the order they should be applied.
+    #[must_use]
+    pub fn into_transactions(self) -> Vec<Transaction> {
+        self.0
+    }
+}
+
+/// How an amount is generated.
+#[derive(Clone, Copy, Debug)]
+enum AmountKind {
+    Zero,
+    SmallestUnit,
+    /// A number of minor units, with a scale of up to
+    /// [`CurrencyAmount::MAX_DECIMAL_PLACES`].
+    Units(u64, u32),
+    Max,
+}
+
+impl From<AmountKind> for CurrencyAmount {
+    fn from(kind: AmountKind) -> Self {
+        match kind {
+            AmountKind::Zero => CurrencyAmount::ZERO,
+            AmountKind::SmallestUnit => Decimal::new(1, CurrencyAmount::MAX_DECIMAL_PLACES).into(),
+            AmountKind::Units(units, scale) => {
+                Decimal::from_i128_with_scale(i128::from(units), scale).into()
+            }
+            AmountKind::Max => CurrencyAmount::MAX,
+        }
+    }
+}
+
+/// One transaction of a [`TransactionStream`], before its transaction ID is
+/// chosen.
+#[derive(Clone, Copy, Debug)]
+struct Step {
+    client: ClientId,
+    /// 0 to 3 for a deposit, 4 or 5 for a withdrawal, 6 or 7 for a dispute,
+    /// 8 for a resolution, and 9 for a chargeback.
+    kind: u8,
+    /// True to refer to an earlier transaction ID, if there is one: usually
+    /// for disputes, resolutions and chargebacks, and rarely for deposits
+    /// and withdrawals.
+    earlier: bool,
+    /// Picks the earlier transaction ID.
+    pick: usize,
+    amount: AmountKind,
+}
+
+/// Builds a stream from its steps, choosing the transaction IDs.
+fn build_stream(steps: impl IntoIterator<Item = Step>) -> TransactionStream {
+    let mut funded = Vec::new();
+    let mut next_tx: TransactionId = 1;
+
+    let transactions = steps
+        .into_iter()
+        .map(|step| {
+            let tx = match funded.len() {
+                n if step.earlier && n > 0 => funded[step.pick % n],
+                _ => {
+                    let tx = next_tx;
+                    next_tx = next_tx.wrapping_add(1);
+                    tx
+                }
+            };
+            let amount = CurrencyAmount::from(step.amount);
+            let transaction_type = match step.kind {
+                0..=3 => TransactionType::Deposit { amount },
+                4 | 5 => TransactionType::Withdrawal { amount },
+                6 | 7 => TransactionType::Dispute,
+                8 => TransactionType::Resolve,
+                _ => TransactionType::Chargeback,
+            };
+            if step.kind <= 5 {
+                funded.push(tx);
+            }
+            Transaction::new(step.client, tx, transaction_type)
+        })
+        .collect();
+
+    TransactionStream(transactions)
+}
+
+#[cfg(feature = "arbitrary")]
+mod arbitrary_impls {
+    use arbitrary::{Arbitrary, Result, Unstructured};
+
+    use super::{build_stream, AmountKind, Step, TransactionStream, STREAM_CLIENTS};
+    use crate::numeric::CurrencyAmount;
+    use crate::{Transaction, TransactionType};
+
+    impl<'a> Arbitrary<'a> for AmountKind {
+        fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+            Ok(match u.int_in_range(0..=15)? {
+                0 => AmountKind::Zero,
+                1 => AmountKind::SmallestUnit,
+                2 => AmountKind::Max,
+                // Mostly amounts which can be added up without overflowing
+                3..=12 => AmountKind::Units(
+                    u.int_in_range(0..=1_000_000)?,
+                    u.int_in_range(0..=CurrencyAmount::MAX_DECIMAL_PLACES)?,
+                ),
+                _ => AmountKind::Units(
+                    u.arbitrary()?,
+                    u.int_in_range(0..=CurrencyAmount::MAX_DECIMAL_PLACES)?,
+                ),
+            })
+        }
+    }
+
+    impl<'a> Arbitrary<'a> for CurrencyAmount {
+        fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+            AmountKind::arbitrary(u).map(CurrencyAmount::from)
+        }
+    }
+
+    impl<'a> Arbitrary<'a> for TransactionType {
+        fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+            Ok(match u.int_in_range(0..=4)? {
+                0 => TransactionType::Deposit {
+                    amount: u.arbitrary()?,
+                },
+                1 => TransactionType::Withdrawal {
+                    amount: u.arbitrary()?,
+                },
+                2 => TransactionType::Dispute,
+                3 => TransactionType::Resolve,
+                _ => TransactionType::Chargeback,
+            })
+        }
+    }
+
+    impl<'a> Arbitrary<'a> for Transaction {
+        fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+            Ok(Transaction::new(
+                u.arbitrary()?,
+                u.arbitrary()?,
+                u.arbitrary()?,
+            ))
+        }
+    }
+
+    impl<'a> Arbitrary<'a> for Step {
+        fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+            let kind = u.int_in_range(0..=9)?;
+            Ok(Step {
+                client: u.int_in_range(0..=STREAM_CLIENTS - 1)?,
+                kind,
+                earlier: if kind <= 5 {
+                    u.ratio(1, 10)?
+                } else {
+                    u.ratio(9, 10)?
+                },
+                pick: u.arbitrary()?,
+                amount: u.arbitrary()?,
+            })
+        }
+    }
+
+    impl<'a> Arbitrary<'a> for TransactionStream {
+        fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+            u.arbitrary_iter::<Step>()?
+                .collect::<Result<Vec<_>>>()
+                .map(build_stream)
+        }
+
+        fn arbitrary_take_rest(u: Unstructured<'a>) -> Result<Self> {
+            u.arbitrary_take_rest_iter::<Step>()?
+                .collect::<Result<Vec<_>>>()
+                .map(build_stream)
+        }
+    }
+}
+
+#[cfg(feature = "proptest")]
+mod strategies {
+    use proptest::prelude::*;
+
+    use super::{build_stream, AmountKind, Step, TransactionStream, STREAM_CLIENTS};
+    use crate::numeric::CurrencyAmount;
+    use crate::{ClientId, Transaction, TransactionId, TransactionType};
+
+    fn amount_kind() -> impl Strategy<Value = AmountKind> {
+        let scale = 0..=CurrencyAmount::MAX_DECIMAL_PLACES;
+        prop_oneof![
+            1 => Just(AmountKind::Zero),
+            1 => Just(AmountKind::SmallestUnit),
+            1 => Just(AmountKind::Max),
+            // Mostly amounts which can be added up without overflowing
+            10 => (0..=1_000_000_u64, scale.clone())
+                .prop_map(|(units, scale)| AmountKind::Units(units, scale)),
+            3 => (any::<u64>(), scale).prop_map(|(units, scale)| AmountKind::Units(units, scale)),
+        ]
+    }
+
+    /// Generates amounts (see the [module documentation](super)).
+    pub fn currency_amount() -> impl Strategy<Value = CurrencyAmount> {
+        amount_kind().prop_map(CurrencyAmount::from)
+    }
+
+    /// Generates transaction types, with amounts from [`currency_amount`].
+    pub fn transaction_type() -> impl Strategy<Value = TransactionType> {
+        prop_oneof![
+            currency_amount().prop_map(|amount| TransactionType::Deposit { amount }),
+            currency_amount().prop_map(|amount| TransactionType::Withdrawal { amount }),
+            Just(TransactionType::Dispute),
+            Just(TransactionType::Resolve),
+            Just(TransactionType::Chargeback),
+        ]
+    }
+
+    /// Generates single transactions for any client and transaction ID.
+    pub fn transaction() -> impl Strategy<Value = Transaction> {
+        (
+            any::<ClientId>(),
+            any::<TransactionId>(),
+            transaction_type(),
+        )
+            .prop_map(|(client, tx, transaction_type)| {
+                Transaction::new(client, tx, transaction_type)
+            })
+    }
+
+    fn step() -> impl Strategy<Value = Step> {
+        (0..=9_u8)
+            .prop_flat_map(|kind| {
+                let earlier = if kind <= 5 { 0.1 } else { 0.9 };
+                (
+                    0..STREAM_CLIENTS,
+                    Just(kind),
+                    proptest::bool::weighted(earlier),
+                    any::<usize>(),
+                    amount_kind(),
+                )
+            })
+            .prop_map(|(client, kind, earlier, pick, amount)| Step {
+                client,
+                kind,
+                earlier,
+                pick,
+                amount,
+            })
+    }
+
+    /// Generates streams of up to `max_len` transactions (see
+    /// [`TransactionStream`]).
+    pub fn transaction_stream(max_len: usize) -> impl Strategy<Value = TransactionStream> {
+        proptest::collection::vec(step(), 0..=max_len).prop_map(build_stream)
+    }
+}
+
+#[cfg(feature = "proptest")]
+pub use strategies::{currency_amount, transaction, transaction_stream, transaction_type};
+
+#[cfg(all(test, feature = "proptest"))]
+mod test {
+    use proptest::prelude::*;
+
+    use crate::generate::{currency_amount, transaction_stream};
+    use crate::numeric::{CurrencyAmount, CurrencyError, PrecisionPolicy};
+    use crate::{InvariantViolation, TransactionError, TransactionProcessor};
+
+    proptest! {
+        #[test]
+        fn test_amounts_are_valid(amount in currency_amount()) {
+            prop_assert!(!amount.is_negative());
+            prop_assert_eq!(Ok(amount), amount.with_precision(PrecisionPolicy::Reject));
+            prop_assert_eq!(Ok(amount), amount.to_string().parse::<CurrencyAmount>());
+        }
+
+        #[test]
+        fn test_processor_invariants(stream in transaction_stream(64)) {
+            let mut transaction_processor = TransactionProcessor::new();
+            let mut again = TransactionProcessor::new();
+            for transaction in stream.transactions() {
+                let before = transaction_processor.generate_report_as_vec();
+                let result = transaction_processor.transact(transaction);
+                prop_assert_eq!(&result, &again.transact(transaction));
+                // The amounts are valid, so the only currency error is an
+                // overflow
+                if let Err(TransactionError::CurrencyError(err)) = &result {
+                    prop_assert_eq!(&CurrencyError::OutOfBounds, err);
+                }
+
+                let after = transaction_processor.generate_report_as_vec();
+                for entry in &after {
+                    prop_assert_eq!(Ok(entry.total()), entry.available() + entry.held());
+                }
+                let violations = transaction_processor.check_invariants();
+                prop_assert!(
+                    violations
+                        .iter()
+                        .all(|violation| matches!(violation, InvariantViolation::TotalOverflow(_))),
+                    "{:?}",
+                    violations
+                );
+                if result.is_err() {
+                    let unchanged = after
+                        .iter()
+                        .filter(|entry| before.iter().all(|old| old.client() != entry.client()))
+                        .all(|entry| entry.total() == CurrencyAmount::ZERO)
+                        && before.iter().all(|old| after.contains(old));
+                    prop_assert!(unchanged, "{:?} changed the report", transaction);
+                }
+            }
+            prop_assert_eq!(
+                transaction_processor.generate_report_as_vec(),
+                again.generate_report_as_vec()
+            );
+        }
+    }
+}
+
+#[cfg(all(test, feature = "arbitrary"))]
+mod arbitrary_test {
+    use arbitrary::{Arbitrary, Unstructured};
+
+    use crate::generate::{TransactionStream, STREAM_CLIENTS};
+    use crate::TransactionType;
+
+    #[test]
+    fn test_arbitrary_stream() {
+        let bytes: Vec<u8> = (0..4096_u32)
+            .map(|i| ((i * 7919 + 1) % 251) as u8)
+            .collect();
+        let stream = TransactionStream::arbitrary_take_rest(Unstructured::new(&bytes)).unwrap();
+        assert!(!stream.transactions().is_empty());
+        for transaction in stream.transactions() {
+            assert!(transaction.client() < STREAM_CLIENTS);
+            if let TransactionType::Deposit { amount } | TransactionType::Withdrawal { amount } =
+                transaction.transaction_type()
+            {
+                assert!(!amount.is_negative());
+            }
+        }
+    }
+}
@@ -0,0 +1,255 @@
+use std::net::SocketAddr;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+use tonic::transport::Server;
+use tonic::{Request, Response, Status, Streaming};
+use transaction_processor::pipeline::TRANSACTION_LOG_TARGET;
+use transaction_processor::proto;
+use transaction_processor::proto::service::transaction_processor_service_server::{
+    TransactionProcessorService, TransactionProcessorServiceServer,
+};
+use transaction_processor::proto::service::{
+    GetReportRequest, GetReportResponse, Rejection, SubmitTransactionResponse,
+    SubmitTransactionsResponse,
+};
+use transaction_processor::summary::PARSE_ERROR_CODE;
+use transaction_processor::{Transaction, TransactionError, TransactionProcessor};
+
+use crate::{shutdown_signal, ProcessOptions, TransactionProcessorCLIError};
+
+/// Implements the gRPC service with a single shared processor.
+struct GrpcService {
+    transaction_processor: Mutex<TransactionProcessor>,
+    /// How report amounts are formatted, and how transactions are applied.
+    options: ProcessOptions,
+}
+
+impl GrpcService {
+    /// Converts and applies a transaction, giving the error code and a
+    /// description of why it failed if it was not applied. Returns false if
+    /// it was skipped as a replay.
+    fn transact(&self, transaction: proto::Transaction) -> Result<bool, (&'static str, String)> {
+        let transaction = Transaction::try_from(transaction)
+            .map_err(|err| (PARSE_ERROR_CODE, err.to_string()))?;
+        match self
+            .options
+            .transact(&mut self.transaction_processor(), &transaction)
+        {
+            Ok(()) => Ok(true),
+            Err(TransactionError::Replayed(_)) => Ok(false),
+            Err(err) => Err((err.code(), err.to_string())),
+        }
+    }
+
+    fn transaction_processor(&self) -> MutexGuard<'_, TransactionProcessor> {
+        self.transaction_processor
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+#[tonic::async_trait]
+impl TransactionProcessorService for GrpcService {
+    async fn submit_transaction(
+        &self,
+        request: Request<proto::Transaction>,
+    ) -> Result<Response<SubmitTransactionResponse>, Status> {
+        let (code, error) = match self.transact(request.into_inner()) {
+            Ok(_) => (None, None),
+            Err((code, error)) => (Some(code.to_string()), Some(error)),
+        };
+        Ok(Response::new(SubmitTransactionResponse { error, code }))
+    }
+
+    async fn submit_transactions(
+        &self,
+        request: Request<Streaming<proto::Transaction>>,
+    ) -> Result<Response<SubmitTransactionsResponse>, Status> {
+        let mut stream = request.into_inner();
+        let mut response = SubmitTransactionsResponse::default();
+
+        // The lock is only held while applying each transaction, so other
+        // requests are not blocked while waiting for the stream
+        let mut index = 0;
+        while let Some(transaction) = stream.message().await? {
+            match self.transact(transaction) {
+                Ok(true) => response.applied += 1,
+                Ok(false) => {}
+                Err((code, error)) => {
+                    log::warn!(
+                        target: TRANSACTION_LOG_TARGET,
+                        "Rejected transaction {}: {}",
+                        index,
+                        error
+                    );
+                    response.rejected.push(Rejection {
+                        index,
+                        error,
+                        code: code.to_string(),
+                    });
+                }
+            }
+            index += 1;
+        }
+
+        Ok(Response::new(response))
+    }
+
+    async fn get_report(
+        &self,
+        _request: Request<GetReportRequest>,
+    ) -> Result<Response<GetReportResponse>, Status> {
+        let report = self.transaction_processor().generate_report_as_vec();
+        let entries = report
+            .into_iter()
+            .map(|entry| match self.options.pipeline.output_scale() {
+                Some(scale) => entry.with_scale(scale),
+                None => entry,
+            })
+            .map(proto::ReportEntry::from)
+            .collect();
+
+        Ok(Response::new(GetReportResponse { entries }))
+    }
+}
+
+/// Runs a gRPC server for `TransactionProcessorService` (see
+/// `proto/transaction_processor_service.proto`) on `address` until
+/// interrupted, backed by a single shared processor. Report amounts are
+/// formatted according to `options`.
+pub fn serve_grpc(
+    transaction_processor: TransactionProcessor,
+    address: SocketAddr,
+    options: &ProcessOptions,
+) -> Result<(), TransactionProcessorCLIError> {
+    let service = GrpcService {
+        transaction_processor: Mutex::new(transaction_processor),
+        options: options.clone(),
+    };
+    let server_error = |error| TransactionProcessorCLIError::ServerFailed { address, error };
+
+    tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .map_err(server_error)?
+        .block_on(async {
+            log::info!("Listening for gRPC on {}", address);
+            Server::builder()
+                .add_service(TransactionProcessorServiceServer::new(service))
+                .serve_with_shutdown(address, shutdown_signal())
+                .await
+        })
+        .map_err(|err| server_error(std::io::Error::other(err)))
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Mutex;
+
+    use tonic::codegen::tokio_stream;
+    use tonic::transport::server::TcpIncoming;
+    use tonic::transport::Server;
+    use transaction_processor::proto::service::transaction_processor_service_client::TransactionProcessorServiceClient;
+    use transaction_processor::proto::service::transaction_processor_service_server::TransactionProcessorServiceServer;
+    use transaction_processor::proto::service::{GetReportRequest, Rejection};
+    use transaction_processor::proto::{self, ProtoTransactionType};
+    use transaction_processor::TransactionProcessor;
+
+    use crate::grpc::GrpcService;
+    use crate::ProcessOptions;
+
+    fn transaction(
+        transaction_type: ProtoTransactionType,
+        client: u64,
+        tx: u64,
+        amount: Option<&str>,
+    ) -> proto::Transaction {
+        proto::Transaction {
+            r#type: transaction_type.into(),
+            client,
+            tx,
+            amount: amount.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn test_grpc_service() {
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+
+        runtime.block_on(async {
+            let incoming = TcpIncoming::bind("127.0.0.1:0".parse().unwrap()).unwrap();
+            let address = incoming.local_addr().unwrap();
+            let service = GrpcService {
+                transaction_processor: Mutex::new(TransactionProcessor::new()),
+                options: ProcessOptions::default(),
+            };
+            tokio::spawn(
+                Server::builder()
+                    .add_service(TransactionProcessorServiceServer::new(service))
+                    .serve_with_incoming(incoming),
+            );
+
+            let mut client =
+                TransactionProcessorServiceClient::connect(format!("http://{}", address))
+                    .await
+                    .unwrap();
+
+            let response = client
+                .submit_transaction(transaction(ProtoTransactionType::Deposit, 1, 1, Some("5")))
+                .await
+                .unwrap()
+                .into_inner();
+            assert_eq!(None, response.error);
+            assert_eq!(None, response.code);
+
+            let response = client
+                .submit_transaction(transaction(ProtoTransactionType::Deposit, 1, 1, Some("5")))
+                .await
+                .unwrap()
+                .into_inner();
+            assert_eq!(
+                Some("Transaction 1 already exists for client 1"),
+                response.error.as_deref()
+            );
+            assert_eq!(Some("TX_EXISTS"), response.code.as_deref());
+
+            let response = client
+                .submit_transactions(tokio_stream::iter([
+                    transaction(ProtoTransactionType::Withdrawal, 1, 2, Some("2")),
+                    transaction(ProtoTransactionType::Deposit, 1, 3, None),
+                    transaction(ProtoTransactionType::Dispute, 1, 1, None),
+                ]))
+                .await
+                .unwrap()
+                .into_inner();
+            assert_eq!(2, response.applied);
+            assert_eq!(
+                vec![Rejection {
+                    index: 1,
+                    error: "Missing amount".to_string(),
+                    code: "PARSE_ERROR".to_string(),
+                }],
+                response.rejected
+            );
+
+            let report = client
+                .get_report(GetReportRequest {})
+                .await
+                .unwrap()
+                .into_inner();
+            assert_eq!(
+                vec![proto::ReportEntry {
+                    client: 1,
+                    available: "-2".to_string(),
+                    held: "5".to_string(),
+                    total: "3".to_string(),
+                    locked: false,
+                }],
+                report.entries
+            );
+        });
+    }
+}
@@ -0,0 +1,221 @@
+use std::collections::{HashMap, VecDeque};
+
+use crate::middleware::{Next, TransactionMiddleware};
+use crate::{Transaction, TransactionError, TransactionProcessor};
+
+/// The number of keys kept by [`IdempotencyCache::default`].
+pub const DEFAULT_CAPACITY: usize = 100_000;
+
+/// Remembers the outcome of each transaction with an idempotency key (see
+/// [`Transaction::idempotency_key`]), so that a transaction resubmitted with
+/// the same key gets the original outcome rather than being applied twice,
+/// e.g. when a client retries a request whose response was lost.
+///
+/// The cache is bounded: once it holds `capacity` keys, the oldest is
+/// forgotten for each new one, and a later resubmission with a forgotten key
+/// is applied as if it were new (so a deposit or withdrawal is then rejected
+/// as [`TransactionError::TransactionAlreadyExists`]). Transactions without a
+/// key are always applied.
+#[derive(Clone, Debug)]
+pub struct IdempotencyCache {
+    capacity: usize,
+    outcomes: HashMap<String, Result<(), TransactionError>>,
+    /// The keys in the order they were recorded, oldest first.
+    keys: VecDeque<String>,
+}
+
+impl Default for IdempotencyCache {
+    fn default() -> Self {
+        Self::new(DEFAULT_CAPACITY)
+    }
+}
+
+impl IdempotencyCache {
+    /// Creates an empty cache which keeps up to `capacity` keys.
+    #[must_use]
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            outcomes: HashMap::new(),
+            keys: VecDeque::new(),
+        }
+    }
+
+    /// The maximum number of keys kept.
+    #[must_use]
+    pub const fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// The number of keys currently kept.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    /// True if no keys are kept.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    /// The outcome of the transaction first submitted with `key`, if it is
+    /// still kept.
+    #[must_use]
+    pub fn outcome(&self, key: &str) -> Option<&Result<(), TransactionError>> {
+        self.outcomes.get(key)
+    }
+
+    /// Records the outcome of the transaction submitted with `key`, unless an
+    /// outcome is already kept for it. Forgets the oldest key if the cache is
+    /// full.
+    pub fn record(&mut self, key: &str, outcome: Result<(), TransactionError>) {
+        if self.capacity == 0 || self.outcomes.contains_key(key) {
+            return;
+        }
+        if self.keys.len() == self.capacity {
+            if let Some(oldest) = self.keys.pop_front() {
+                self.outcomes.remove(&oldest);
+            }
+        }
+        self.keys.push_back(key.to_string());
+        self.outcomes.insert(key.to_string(), outcome);
+    }
+
+    /// Applies `transaction` to `transaction_processor`, unless a
+    /// transaction with the same idempotency key has already been submitted,
+    /// in which case the original outcome is returned without applying it.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error from [`TransactionProcessor::transact`], or the
+    /// original error for a resubmission.
+    pub fn transact(
+        &mut self,
+        transaction_processor: &mut TransactionProcessor,
+        transaction: &Transaction,
+    ) -> Result<(), TransactionError> {
+        self.transact_with(
+            transaction_processor,
+            transaction,
+            TransactionProcessor::transact,
+        )
+    }
+
+    /// Like [`IdempotencyCache::transact`], but applies the transaction with
+    /// `apply`, which must call [`TransactionProcessor::transact`] (e.g. to
+    /// also post it to a [`crate::ledger::Ledger`]).
+    ///
+    /// # Errors
+    ///
+    /// Returns the error from `apply`, or the original error for a
+    /// resubmission.
+    pub fn transact_with(
+        &mut self,
+        transaction_processor: &mut TransactionProcessor,
+        transaction: &Transaction,
+        apply: impl FnOnce(&mut TransactionProcessor, &Transaction) -> Result<(), TransactionError>,
+    ) -> Result<(), TransactionError> {
+        let Some(key) = transaction.idempotency_key() else {
+            return apply(transaction_processor, transaction);
+        };
+        if let Some(outcome) = self.outcome(key) {
+            return outcome.clone();
+        }
+
+        let outcome = apply(transaction_processor, transaction);
+        self.record(key, outcome.clone());
+        outcome
+    }
+}
+
+/// Deduplicates the transactions passed through a
+/// [`crate::middleware::MiddlewareChain`], as [`IdempotencyCache::transact`]
+/// does.
+impl TransactionMiddleware for IdempotencyCache {
+    fn handle(
+        &mut self,
+        transaction: Transaction,
+        next: &mut dyn Next,
+    ) -> Result<(), TransactionError> {
+        let Some(key) = transaction.idempotency_key().map(str::to_string) else {
+            return next.run(transaction);
+        };
+        if let Some(outcome) = self.outcome(&key) {
+            return outcome.clone();
+        }
+
+        let outcome = next.run(transaction);
+        self.record(&key, outcome.clone());
+        outcome
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+
+    use crate::idempotency::IdempotencyCache;
+    use crate::numeric::CurrencyAmount;
+    use crate::{Transaction, TransactionError, TransactionProcessor, TransactionType};
+
+    #[test]
+    fn test_idempotency_cache() {
+        let withdrawal = |tx, amount| {
+            Transaction::new(
+                1,
+                tx,
+                TransactionType::Withdrawal {
+                    amount: CurrencyAmount::from_str(amount).unwrap(),
+                },
+            )
+        };
+
+        let mut tp = TransactionProcessor::new();
+        let mut cache = IdempotencyCache::new(2);
+        let deposit = Transaction::new(
+            1,
+            1,
+            TransactionType::Deposit {
+                amount: CurrencyAmount::from_str("5").unwrap(),
+            },
+        )
+        .with_idempotency_key("a");
+        let overdraft = withdrawal(2, "10").with_idempotency_key("b");
+
+        let not_enough_funds = TransactionError::NotEnoughFunds {
+            client: 1,
+            transaction_type: overdraft.transaction_type().clone(),
+            available: CurrencyAmount::from_str("5").unwrap(),
+        };
+
+        cache.transact(&mut tp, &deposit).unwrap();
+        assert_eq!(
+            Err(not_enough_funds.clone()),
+            cache.transact(&mut tp, &overdraft)
+        );
+
+        // Resubmissions get the original outcome, even if it would differ now
+        cache.transact(&mut tp, &deposit).unwrap();
+        cache
+            .transact(&mut tp, &withdrawal(3, "5").with_idempotency_key("c"))
+            .unwrap();
+        assert_eq!(Some(&Err(not_enough_funds)), cache.outcome("b"));
+        assert_eq!(2, cache.len());
+
+        // The oldest key was forgotten to make room for "c"
+        assert_eq!(None, cache.outcome("a"));
+        assert_eq!(
+            Err(TransactionError::TransactionAlreadyExists { client: 1, tx: 1 }),
+            cache.transact(&mut tp, &deposit)
+        );
+
+        // Transactions without a key are always applied
+        let mut tp = TransactionProcessor::new();
+        cache.transact(&mut tp, &withdrawal(1, "0")).unwrap();
+        assert_eq!(
+            Err(TransactionError::TransactionAlreadyExists { client: 1, tx: 1 }),
+            cache.transact(&mut tp, &withdrawal(1, "0"))
+        );
+    }
+}
@@ -0,0 +1,320 @@
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+/// Error returned when an input argument could not be expanded into files.
+#[derive(Clone, Debug)]
+pub enum ExpandError {
+    /// A glob pattern did not match any files, or a directory is empty.
+    NoMatchingFiles(String),
+    /// A directory could not be listed.
+    FailedToReadDirectory { path: String, error: String },
+}
+
+impl Display for ExpandError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&match self {
+            ExpandError::NoMatchingFiles(pattern) => {
+                format!("No input files match '{}'", pattern)
+            }
+            ExpandError::FailedToReadDirectory { path, error } => {
+                format!("Failed to read directory '{}': {}", path, error)
+            }
+        })
+    }
+}
+
+/// Expands each input argument into a list of files:
+///
+/// * A directory is replaced by the files directly inside it. Hidden files
+///   (starting with `.`) and subdirectories are skipped.
+/// * A glob pattern (containing `*`, `?` or `[...]`) is replaced by the files
+///   it matches.
+/// * Anything else (including URIs such as `https://...`) is used verbatim,
+///   whether or not it exists.
+///
+/// It is an error for a directory or pattern to expand to no files.
+///
+/// The files from each argument are sorted lexicographically, so the order
+/// does not depend on the filesystem. Arguments keep their relative order.
+pub fn expand_inputs<'a>(
+    inputs: impl IntoIterator<Item = &'a str>,
+) -> Result<Vec<String>, ExpandError> {
+    let mut files = Vec::new();
+
+    for input in inputs {
+        let path = Path::new(input);
+        let mut expanded = if input.contains("://") {
+            files.push(input.to_string());
+            continue;
+        } else if is_pattern(input) {
+            glob(path)?
+        } else if path.is_dir() {
+            list_files(path)?
+        } else {
+            files.push(input.to_string());
+            continue;
+        };
+
+        if expanded.is_empty() {
+            return Err(ExpandError::NoMatchingFiles(input.to_string()));
+        }
+        expanded.sort();
+        files.extend(
+            expanded
+                .into_iter()
+                .map(|path| path.to_string_lossy().into_owned()),
+        );
+    }
+
+    Ok(files)
+}
+
+/// True if `input` contains glob metacharacters.
+fn is_pattern(input: &str) -> bool {
+    input.contains(['*', '?', '['])
+}
+
+/// The non-hidden files directly inside `dir`.
+fn list_files(dir: &Path) -> Result<Vec<PathBuf>, ExpandError> {
+    Ok(read_dir(dir, false)?
+        .into_iter()
+        .filter(|path| path.is_file())
+        .collect())
+}
+
+/// The entries of `dir` which are hidden (starting with `.`), or those which
+/// are not.
+fn read_dir(dir: &Path, hidden: bool) -> Result<Vec<PathBuf>, ExpandError> {
+    let read_error = |error: io::Error| ExpandError::FailedToReadDirectory {
+        path: dir.display().to_string(),
+        error: error.to_string(),
+    };
+
+    let mut paths = Vec::new();
+    for entry in fs::read_dir(dir).map_err(read_error)? {
+        let entry = entry.map_err(read_error)?;
+        if entry.file_name().to_string_lossy().starts_with('.') == hidden {
+            paths.push(entry.path());
+        }
+    }
+    Ok(paths)
+}
+
+/// The files matching a glob pattern, in which any component of the path may
+/// contain wildcards. Hidden entries are only matched by components which
+/// start with a literal `.`.
+fn glob(pattern: &Path) -> Result<Vec<PathBuf>, ExpandError> {
+    // Start from the longest prefix without wildcards
+    let mut base = PathBuf::new();
+    let mut components = pattern.components().peekable();
+    while let Some(component) = components.peek() {
+        if is_pattern(&component.as_os_str().to_string_lossy()) {
+            break;
+        }
+        base.push(component);
+        components.next();
+    }
+
+    let mut candidates = vec![base];
+    for component in components {
+        let component = match component {
+            Component::Normal(component) => component.to_string_lossy(),
+            // `..` and `.` after a wildcard apply to every candidate
+            other => {
+                for candidate in &mut candidates {
+                    candidate.push(other);
+                }
+                continue;
+            }
+        };
+
+        let mut next = Vec::new();
+        for candidate in candidates {
+            let dir = if candidate.as_os_str().is_empty() {
+                Path::new(".")
+            } else {
+                candidate.as_path()
+            };
+            if !dir.is_dir() {
+                continue;
+            }
+
+            if !is_pattern(&component) {
+                next.push(candidate.join(component.as_ref()));
+                continue;
+            }
+
+            for entry in read_dir(dir, component.starts_with('.'))? {
+                let name = entry.file_name().unwrap_or_default().to_string_lossy();
+                if matches_pattern(&component, &name) {
+                    next.push(candidate.join(name.as_ref()));
+                }
+            }
+        }
+        candidates = next;
+    }
+
+    Ok(candidates
+        .into_iter()
+        .filter(|path| path.is_file())
+        .collect())
+}
+
+/// Matches a single path component against a pattern: `*` matches any
+/// sequence of characters, `?` matches any one character, and `[abc]`,
+/// `[a-z]` or `[!abc]` match one character from (or not from) a set. An
+/// unclosed `[` matches itself.
+fn matches_pattern(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+
+    // Position to resume from after the most recent `*`, if any
+    let mut backtrack: Option<(usize, usize)> = None;
+    let (mut p, mut n) = (0, 0);
+
+    while n < name.len() {
+        let step = match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, n));
+                p += 1;
+                continue;
+            }
+            Some('?') => Some(1),
+            Some('[') => match match_class(&pattern[p..], name[n]) {
+                Some((matched, len)) => matched.then_some(len),
+                None => (name[n] == '[').then_some(1),
+            },
+            Some(c) => (*c == name[n]).then_some(1),
+            None => None,
+        };
+
+        match (step, backtrack) {
+            (Some(len), _) => {
+                p += len;
+                n += 1;
+            }
+            // Let the last `*` consume one more character, and retry
+            (None, Some((star, start))) => {
+                backtrack = Some((star, start + 1));
+                p = star + 1;
+                n = start + 1;
+            }
+            (None, None) => return false,
+        }
+    }
+
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+/// Matches `c` against the character class at the start of `pattern`.
+/// Returns whether it matched and the length of the class, or `None` if the
+/// class is not closed.
+fn match_class(pattern: &[char], c: char) -> Option<(bool, usize)> {
+    let mut i = 1;
+    let negated = matches!(pattern.get(i), Some('!' | '^'));
+    if negated {
+        i += 1;
+    }
+
+    let mut matched = false;
+    let mut first = true;
+    loop {
+        match pattern.get(i)? {
+            // A `]` straight after the opening bracket is a literal
+            ']' if !first => return Some((matched != negated, i + 1)),
+            &start => {
+                if pattern.get(i + 1) == Some(&'-')
+                    && !matches!(pattern.get(i + 2), None | Some(']'))
+                {
+                    matched |= (start..=pattern[i + 2]).contains(&c);
+                    i += 3;
+                } else {
+                    matched |= start == c;
+                    i += 1;
+                }
+            }
+        }
+        first = false;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+
+    use crate::inputs::{expand_inputs, matches_pattern, ExpandError};
+    use crate::test::TempDir;
+
+    #[test]
+    fn test_matches_pattern() {
+        assert!(matches_pattern("*.csv", "day1.csv"));
+        assert!(matches_pattern("*.csv", ".csv"));
+        assert!(!matches_pattern("*.csv", "day1.csv.gz"));
+        assert!(matches_pattern("*.csv*", "day1.csv.gz"));
+        assert!(matches_pattern("day?.csv", "day1.csv"));
+        assert!(!matches_pattern("day?.csv", "day10.csv"));
+        assert!(matches_pattern("*a*b*", "xxaxxbxx"));
+        assert!(!matches_pattern("*a*b", "xxbxxa"));
+        assert!(matches_pattern("day[0-9].csv", "day5.csv"));
+        assert!(!matches_pattern("day[!0-9].csv", "day5.csv"));
+        assert!(matches_pattern("day[!0-9].csv", "dayx.csv"));
+        assert!(matches_pattern("[]]", "]"));
+        assert!(matches_pattern("day[1", "day[1"));
+        assert!(matches_pattern("", ""));
+        assert!(!matches_pattern("", "a"));
+    }
+
+    #[test]
+    fn test_expand_inputs() {
+        let dir = TempDir::new("inputs");
+        fs::create_dir_all(dir.join("nested")).unwrap();
+        for name in ["b.csv", "a.csv", "c.jsonl", ".hidden.csv", "nested/d.csv"] {
+            fs::write(dir.join(name), "").unwrap();
+        }
+
+        let path = |name: &str| dir.join(name).to_string_lossy().into_owned();
+        let expand = |inputs: &[&str]| expand_inputs(inputs.iter().copied());
+
+        // Directories are listed in order, skipping hidden files and
+        // subdirectories
+        assert_eq!(
+            vec![path("a.csv"), path("b.csv"), path("c.jsonl")],
+            expand(&[&path("")]).unwrap()
+        );
+
+        assert_eq!(
+            vec![path("a.csv"), path("b.csv")],
+            expand(&[&path("*.csv")]).unwrap()
+        );
+        assert_eq!(
+            vec![path("nested/d.csv")],
+            expand(&[&path("*/*.csv")]).unwrap()
+        );
+        assert_eq!(
+            vec![path(".hidden.csv")],
+            expand(&[&path(".*.csv")]).unwrap()
+        );
+
+        // Each argument is expanded in place, and other paths are kept as-is
+        assert_eq!(
+            vec![path("c.jsonl"), "missing.csv".to_string(), path("a.csv")],
+            expand(&[&path("*.jsonl"), "missing.csv", &path("[a].csv")]).unwrap()
+        );
+        assert_eq!(
+            vec!["https://example.com/tx.csv?a=*".to_string()],
+            expand(&["https://example.com/tx.csv?a=*"]).unwrap()
+        );
+
+        assert!(matches!(
+            expand(&[&path("*.parquet")]),
+            Err(ExpandError::NoMatchingFiles(_))
+        ));
+        fs::create_dir(dir.join("empty")).unwrap();
+        assert!(matches!(
+            expand(&[&path("empty")]),
+            Err(ExpandError::NoMatchingFiles(_))
+        ));
+    }
+}
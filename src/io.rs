@@ -0,0 +1,323 @@
+// With only the `checksum` or `compression` features, none of the readers use the shared
+// entry parsing below
+#![cfg_attr(
+    not(any(
+        feature = "avro",
+        feature = "csv",
+        feature = "jsonl",
+        feature = "parquet"
+    )),
+    allow(dead_code)
+)]
+
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+use serde::Deserialize;
+
+use crate::flat::FlatTransactionType;
+use crate::numeric::{CurrencyAmount, CurrencyAmountParseError, LocaleFormat};
+use crate::timestamp::{Timestamp, TimestampParseError};
+use crate::{ClientId, Transaction, TransactionId, TransactionType};
+
+/// Avro module: reads and writes records using the published schemas.
+#[cfg(feature = "avro")]
+pub mod avro;
+/// Checksum module: verifies the SHA-256 digest of input files as they are
+/// read.
+#[cfg(feature = "checksum")]
+pub mod checksum;
+/// Compression module: transparent decompression of input files.
+#[cfg(feature = "compression")]
+pub mod compression;
+/// CSV module: reads transactions from and writes reports to CSV.
+#[cfg(feature = "csv")]
+pub mod csv;
+/// Detect module: detects the format of input files from their extension
+/// or contents.
+pub mod detect;
+/// JSONL module: reads transactions from and writes reports to JSON Lines.
+#[cfg(feature = "jsonl")]
+pub mod jsonl;
+/// Parquet module: reads transactions from and writes reports to Parquet.
+#[cfg(feature = "parquet")]
+pub mod parquet;
+/// Remote module: streams input files from HTTP(S) URLs and S3.
+#[cfg(feature = "remote")]
+pub mod remote;
+
+#[cfg(feature = "compression")]
+pub use compression::{decompress_input, open_input, open_raw_input};
+
+/// How amounts in an input file are parsed.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum AmountFormat {
+    /// Accepts any amount accepted by `CurrencyAmount::from_str`.
+    #[default]
+    Standard,
+    /// Only accepts plain decimal notation (see
+    /// [`CurrencyAmount::from_str_strict`]).
+    Strict,
+    /// Accepts thousands separators and the decimal separator of the
+    /// specified locale (see [`CurrencyAmount::from_str_with_format`]).
+    Locale(LocaleFormat),
+}
+
+impl AmountFormat {
+    /// Parses an amount using this format.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the string is not a valid amount in this format.
+    pub fn parse(self, value: &str) -> Result<CurrencyAmount, CurrencyAmountParseError> {
+        match self {
+            AmountFormat::Standard => value.parse(),
+            AmountFormat::Strict => CurrencyAmount::from_str_strict(value),
+            AmountFormat::Locale(format) => CurrencyAmount::from_str_with_format(value, format),
+        }
+    }
+}
+
+/// The names of the input columns which hold each transaction field, for
+/// input files which don't use the standard `type,client,tx,amount` headers.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ColumnMapping {
+    transaction_type: String,
+    client: String,
+    tx: String,
+    amount: String,
+}
+
+impl Default for ColumnMapping {
+    fn default() -> Self {
+        Self {
+            transaction_type: "type".to_string(),
+            client: "client".to_string(),
+            tx: "tx".to_string(),
+            amount: "amount".to_string(),
+        }
+    }
+}
+
+impl ColumnMapping {
+    /// Creates a mapping which uses the standard column names.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the name of the column holding the transaction type.
+    #[must_use]
+    pub fn with_type_column(mut self, name: impl Into<String>) -> Self {
+        self.transaction_type = name.into();
+        self
+    }
+
+    /// Sets the name of the column holding the client ID.
+    #[must_use]
+    pub fn with_client_column(mut self, name: impl Into<String>) -> Self {
+        self.client = name.into();
+        self
+    }
+
+    /// Sets the name of the column holding the transaction ID.
+    #[must_use]
+    pub fn with_tx_column(mut self, name: impl Into<String>) -> Self {
+        self.tx = name.into();
+        self
+    }
+
+    /// Sets the name of the column holding the amount.
+    #[must_use]
+    pub fn with_amount_column(mut self, name: impl Into<String>) -> Self {
+        self.amount = name.into();
+        self
+    }
+
+    /// Returns the standard name for the specified input column, or `None`
+    /// if it is not mapped.
+    #[cfg_attr(not(feature = "csv"), allow(dead_code))]
+    pub(crate) fn standard_name(&self, column: &str) -> Option<&'static str> {
+        [
+            (&self.transaction_type, "type"),
+            (&self.client, "client"),
+            (&self.tx, "tx"),
+            (&self.amount, "amount"),
+        ]
+        .into_iter()
+        .find(|(name, _)| name.as_str() == column)
+        .map(|(_, standard_name)| standard_name)
+    }
+}
+
+/// Error returned when parsing a [`ColumnMapping`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum ColumnMappingParseError {
+    /// An entry is not of the form `field=column`.
+    InvalidEntry(String),
+    /// The field is not one of `type`, `client`, `tx`, or `amount`.
+    UnknownField(String),
+}
+
+impl Display for ColumnMappingParseError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&match self {
+            ColumnMappingParseError::InvalidEntry(entry) => {
+                format!("Invalid column mapping '{}' (expected field=column)", entry)
+            }
+            ColumnMappingParseError::UnknownField(field) => {
+                format!("Unknown transaction field '{}'", field)
+            }
+        })
+    }
+}
+
+impl FromStr for ColumnMapping {
+    type Err = ColumnMappingParseError;
+
+    /// Parses a comma-separated list of `field=column` entries, e.g.
+    /// `type=transaction_type,client=client_id`. Fields which aren't
+    /// listed keep their standard column name.
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        value
+            .split(',')
+            .map(str::trim)
+            .filter(|entry| !entry.is_empty())
+            .try_fold(Self::new(), |mapping, entry| {
+                let (field, column) = entry
+                    .split_once('=')
+                    .map(|(field, column)| (field.trim(), column.trim()))
+                    .filter(|(_, column)| !column.is_empty())
+                    .ok_or_else(|| ColumnMappingParseError::InvalidEntry(entry.to_string()))?;
+
+                match field {
+                    "type" => Ok(mapping.with_type_column(column)),
+                    "client" => Ok(mapping.with_client_column(column)),
+                    "tx" => Ok(mapping.with_tx_column(column)),
+                    "amount" => Ok(mapping.with_amount_column(column)),
+                    _ => Err(ColumnMappingParseError::UnknownField(field.to_string())),
+                }
+            })
+    }
+}
+
+/// A transaction as read from an input file. The amount is kept as a string
+/// so that it can be parsed according to the reader's [`AmountFormat`].
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq)]
+pub(crate) struct RawEntry {
+    #[serde(rename = "type")]
+    transaction_type: FlatTransactionType,
+    client: ClientId,
+    tx: TransactionId,
+    amount: Option<String>,
+    #[serde(default)]
+    idempotency_key: Option<String>,
+    #[serde(default)]
+    sequence: Option<u64>,
+    #[serde(default)]
+    timestamp: Option<String>,
+}
+
+/// Error returned when an entry in an input file is not a valid transaction.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum EntryConvertError {
+    /// A deposit or withdrawal did not specify an amount.
+    MissingAmount,
+    /// The amount could not be parsed.
+    InvalidAmount(CurrencyAmountParseError),
+    /// The timestamp could not be parsed.
+    InvalidTimestamp(TimestampParseError),
+}
+
+impl Display for EntryConvertError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&match self {
+            EntryConvertError::MissingAmount => "Missing amount".to_string(),
+            EntryConvertError::InvalidAmount(err) => format!("Invalid amount: {}", err),
+            EntryConvertError::InvalidTimestamp(err) => format!("Invalid timestamp: {}", err),
+        })
+    }
+}
+
+impl RawEntry {
+    pub(crate) fn into_transaction(
+        self,
+        amount_format: AmountFormat,
+    ) -> Result<Transaction, EntryConvertError> {
+        let amount = || -> Result<CurrencyAmount, EntryConvertError> {
+            amount_format
+                .parse(
+                    self.amount
+                        .as_deref()
+                        .ok_or(EntryConvertError::MissingAmount)?,
+                )
+                .map_err(EntryConvertError::InvalidAmount)
+        };
+
+        let transaction = Transaction::new(
+            self.client,
+            self.tx,
+            match self.transaction_type {
+                FlatTransactionType::Deposit => TransactionType::Deposit { amount: amount()? },
+                FlatTransactionType::Withdrawal => {
+                    TransactionType::Withdrawal { amount: amount()? }
+                }
+                FlatTransactionType::Dispute => TransactionType::Dispute,
+                FlatTransactionType::Resolve => TransactionType::Resolve,
+                FlatTransactionType::Chargeback => TransactionType::Chargeback,
+            },
+        );
+
+        // An empty timestamp is the same as none
+        let transaction = match self.timestamp.as_deref().filter(|value| !value.is_empty()) {
+            Some(value) => transaction.with_timestamp(
+                Timestamp::from_str(value).map_err(EntryConvertError::InvalidTimestamp)?,
+            ),
+            None => transaction,
+        };
+        let transaction = match self.sequence {
+            Some(sequence) => transaction.with_sequence(sequence),
+            None => transaction,
+        };
+        // An empty key (e.g. an empty CSV field) is the same as no key
+        Ok(match self.idempotency_key.filter(|key| !key.is_empty()) {
+            Some(key) => transaction.with_idempotency_key(key),
+            None => transaction,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+
+    use crate::io::{ColumnMapping, ColumnMappingParseError};
+
+    #[test]
+    fn test_column_mapping_from_str() {
+        assert_eq!(Ok(ColumnMapping::new()), ColumnMapping::from_str(""));
+        assert_eq!(
+            Ok(ColumnMapping::new()
+                .with_type_column("transaction_type")
+                .with_client_column("client_id")
+                .with_tx_column("txn_id")
+                .with_amount_column("value")),
+            ColumnMapping::from_str(
+                "type=transaction_type, client=client_id,tx=txn_id,amount = value"
+            )
+        );
+        assert_eq!(
+            Err(ColumnMappingParseError::InvalidEntry("client".to_string())),
+            ColumnMapping::from_str("type=kind,client")
+        );
+        assert_eq!(
+            Err(ColumnMappingParseError::UnknownField("id".to_string())),
+            ColumnMapping::from_str("id=txn_id")
+        );
+
+        let mapping = ColumnMapping::from_str("tx=txn_id").unwrap();
+        assert_eq!(Some("tx"), mapping.standard_name("txn_id"));
+        assert_eq!(Some("type"), mapping.standard_name("type"));
+        assert_eq!(None, mapping.standard_name("tx"));
+    }
+}
@@ -0,0 +1,378 @@
+use std::fmt::{Display, Formatter};
+use std::io;
+
+use apache_avro::Schema;
+use serde::Serialize;
+
+use crate::io::{AmountFormat, EntryConvertError, RawEntry};
+use crate::Transaction;
+
+/// The published Avro schema for transactions. Amounts are strings so that
+/// no precision is lost. See [`transaction_schema`].
+#[cfg(not(feature = "wide-ids"))]
+pub const TRANSACTION_SCHEMA: &str = include_str!("../../schemas/transaction.avsc");
+
+/// The published Avro schema for transactions, with `long` client IDs.
+/// Amounts are strings so that no precision is lost. See
+/// [`transaction_schema`].
+#[cfg(feature = "wide-ids")]
+pub const TRANSACTION_SCHEMA: &str = include_str!("../../schemas/wide-ids/transaction.avsc");
+
+/// The published Avro schema for report entries. See
+/// [`report_entry_schema`].
+#[cfg(not(feature = "wide-ids"))]
+pub const REPORT_ENTRY_SCHEMA: &str = include_str!("../../schemas/report_entry.avsc");
+
+/// The published Avro schema for report entries, with `long` client IDs.
+/// See [`report_entry_schema`].
+#[cfg(feature = "wide-ids")]
+pub const REPORT_ENTRY_SCHEMA: &str = include_str!("../../schemas/wide-ids/report_entry.avsc");
+
+/// Error returned when an Avro schema is invalid.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct AvroSchemaError(String);
+
+impl Display for AvroSchemaError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Invalid Avro schema: {}", self.0)
+    }
+}
+
+/// Error returned when reading a transaction from an Avro file.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum AvroReaderError {
+    /// The data is not a valid Avro object container file.
+    ReadError(String),
+    /// The record does not match the transaction schema.
+    SchemaMismatch(String),
+    /// The record matches the schema, but is not a valid transaction.
+    TransactionParseError(EntryConvertError),
+}
+
+impl Display for AvroReaderError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&match self {
+            AvroReaderError::ReadError(err) => format!("Avro read error: {}", err),
+            AvroReaderError::SchemaMismatch(err) => format!("Avro schema mismatch: {}", err),
+            AvroReaderError::TransactionParseError(err) => {
+                format!("Transaction parse error: {}", err)
+            }
+        })
+    }
+}
+
+/// Error returned when writing a record to an Avro file.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum AvroWriterError {
+    /// The record does not match the schema, or could not be written.
+    AvroWriteError(String),
+}
+
+impl Display for AvroWriterError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&match self {
+            AvroWriterError::AvroWriteError(err) => format!("Avro write error: {}", err),
+        })
+    }
+}
+
+/// Parses [`TRANSACTION_SCHEMA`].
+///
+/// # Errors
+///
+/// Never returns an error in practice, as the schema is checked by the unit
+/// tests.
+pub fn transaction_schema() -> Result<Schema, AvroSchemaError> {
+    Schema::parse_str(TRANSACTION_SCHEMA).map_err(|err| AvroSchemaError(format!("{}", err)))
+}
+
+/// Parses [`REPORT_ENTRY_SCHEMA`].
+///
+/// # Errors
+///
+/// Never returns an error in practice, as the schema is checked by the unit
+/// tests.
+pub fn report_entry_schema() -> Result<Schema, AvroSchemaError> {
+    Schema::parse_str(REPORT_ENTRY_SCHEMA).map_err(|err| AvroSchemaError(format!("{}", err)))
+}
+
+/// Reads transactions from an Avro object container file.
+///
+/// Records are resolved against [`TRANSACTION_SCHEMA`], so files written
+/// with a compatible schema (e.g. with extra fields, or an `int` transaction
+/// ID) can also be read. String amounts are parsed according to the reader's
+/// [`AmountFormat`].
+pub struct AvroReader<R: io::Read> {
+    reader: apache_avro::Reader<'static, R>,
+    schema: Schema,
+    amount_format: AmountFormat,
+}
+
+impl<R: io::Read> AvroReader<R> {
+    /// Creates a reader for Avro data, reading the file header.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the header is invalid.
+    pub fn new(reader: R) -> Result<Self, AvroReaderError> {
+        Ok(Self {
+            reader: apache_avro::Reader::new(reader)
+                .map_err(|err| AvroReaderError::ReadError(format!("{}", err)))?,
+            schema: transaction_schema()
+                .map_err(|err| AvroReaderError::SchemaMismatch(format!("{}", err)))?,
+            amount_format: AmountFormat::Standard,
+        })
+    }
+
+    /// Sets how amounts are parsed. Defaults to [`AmountFormat::Standard`].
+    #[must_use]
+    pub fn with_amount_format(mut self, amount_format: AmountFormat) -> Self {
+        self.amount_format = amount_format;
+        self
+    }
+
+    /// Returns an iterator over the transactions in the data. Records which
+    /// are not valid transactions result in an error, but do not end the
+    /// iteration. A corrupt block ends the iteration after its error.
+    pub fn read(&mut self) -> impl Iterator<Item = Result<Transaction, AvroReaderError>> + '_ {
+        let amount_format = self.amount_format;
+        let schema = &self.schema;
+
+        (&mut self.reader).map(move |value| {
+            let value = value
+                .map_err(|err| AvroReaderError::ReadError(format!("{}", err)))?
+                .resolve(schema)
+                .map_err(|err| AvroReaderError::SchemaMismatch(format!("{}", err)))?;
+
+            apache_avro::from_value::<RawEntry>(&value)
+                .map_err(|err| AvroReaderError::SchemaMismatch(format!("{}", err)))?
+                .into_transaction(amount_format)
+                .map_err(AvroReaderError::TransactionParseError)
+        })
+    }
+}
+
+/// Writes serializable records as an Avro object container file, such as
+/// [`crate::ReportEntry`] with [`report_entry_schema`], or
+/// [`crate::flat::FlatTransaction`] with [`transaction_schema`].
+pub struct AvroWriter<'a, W: io::Write> {
+    writer: apache_avro::Writer<'a, W>,
+}
+
+impl<'a, W: io::Write> AvroWriter<'a, W> {
+    /// Creates a writer outputting records with the given schema to the
+    /// specified destination.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the schema is not supported.
+    pub fn new(schema: &'a Schema, writer: W) -> Result<Self, AvroWriterError> {
+        Ok(Self {
+            writer: apache_avro::Writer::new(schema, writer)
+                .map_err(|err| AvroWriterError::AvroWriteError(format!("{}", err)))?,
+        })
+    }
+
+    /// Writes a single record. Records are buffered, so [`AvroWriter::finish`]
+    /// must be called once all records have been written.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the record does not match the schema, or could
+    /// not be written.
+    pub fn write(&mut self, record: impl Serialize) -> Result<(), AvroWriterError> {
+        #[cfg(not(feature = "wide-ids"))]
+        let written = self.writer.append_ser(record);
+        // Avro has no unsigned 64-bit integers, so IDs are written as longs
+        #[cfg(feature = "wide-ids")]
+        let written = self
+            .writer
+            .append_value(ids_to_longs(apache_avro::to_value(record).map_err(
+                |err| AvroWriterError::AvroWriteError(format!("{}", err)),
+            )?)?);
+
+        written
+            .map(|_| ())
+            .map_err(|err| AvroWriterError::AvroWriteError(format!("{}", err)))
+    }
+
+    /// Writes any buffered records, and returns the destination.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the records could not be written.
+    pub fn finish(self) -> Result<W, AvroWriterError> {
+        self.writer
+            .into_inner()
+            .map_err(|err| AvroWriterError::AvroWriteError(format!("{}", err)))
+    }
+}
+
+/// Converts the `u64` values of a record, which serde serializes as 8-byte
+/// fixed values, to longs.
+#[cfg(feature = "wide-ids")]
+fn ids_to_longs(
+    record: apache_avro::types::Value,
+) -> Result<apache_avro::types::Value, AvroWriterError> {
+    use apache_avro::types::Value;
+
+    match record {
+        Value::Record(fields) => fields
+            .into_iter()
+            .map(|(name, value)| match value {
+                Value::Fixed(8, bytes) => {
+                    let id = u64::from_le_bytes(bytes.try_into().unwrap_or_default());
+                    i64::try_from(id)
+                        .map(|id| (name, Value::Long(id)))
+                        .map_err(|_| {
+                            AvroWriterError::AvroWriteError(format!(
+                                "ID {} does not fit in an Avro long",
+                                id
+                            ))
+                        })
+                }
+                value => Ok((name, value)),
+            })
+            .collect::<Result<_, _>>()
+            .map(Value::Record),
+        value => Ok(value),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+
+    use serde::Serialize;
+
+    use crate::flat::{FlatTransaction, FlatTransactionType};
+    use crate::io::avro::{
+        report_entry_schema, transaction_schema, AvroReader, AvroReaderError, AvroWriter,
+    };
+    use crate::io::{AmountFormat, EntryConvertError};
+    use crate::numeric::{CurrencyAmount, LocaleFormat};
+    use crate::report::{ReportFilter, ReportOrder};
+    use crate::{ClientId, Transaction, TransactionId, TransactionProcessor, TransactionType};
+
+    #[test]
+    fn test_round_trip() {
+        let transactions = [
+            Transaction::new(
+                1,
+                1,
+                TransactionType::Deposit {
+                    amount: CurrencyAmount::from_str("1.5").unwrap(),
+                },
+            ),
+            Transaction::new(1, 1, TransactionType::Dispute),
+            Transaction::new(1, 1, TransactionType::Chargeback),
+        ];
+
+        let schema = transaction_schema().unwrap();
+        let mut writer = AvroWriter::new(&schema, Vec::new()).unwrap();
+        for transaction in transactions.iter().cloned() {
+            writer.write(FlatTransaction::from(transaction)).unwrap();
+        }
+        writer
+            .write(FlatTransaction {
+                transaction_type: FlatTransactionType::Withdrawal,
+                client: 2,
+                tx: 2,
+                amount: None,
+            })
+            .unwrap();
+        let data = writer.finish().unwrap();
+
+        let mut reader = AvroReader::new(data.as_slice()).unwrap();
+        let mut reader = reader.read();
+
+        for transaction in transactions {
+            assert_eq!(Ok(transaction), reader.next().unwrap());
+        }
+        assert_eq!(
+            Err(AvroReaderError::TransactionParseError(
+                EntryConvertError::MissingAmount
+            )),
+            reader.next().unwrap()
+        );
+        assert!(reader.next().is_none());
+    }
+
+    #[test]
+    fn test_read_locale_amounts() {
+        #[derive(Serialize)]
+        struct Entry {
+            #[serde(rename = "type")]
+            transaction_type: FlatTransactionType,
+            client: ClientId,
+            tx: TransactionId,
+            amount: Option<&'static str>,
+        }
+
+        let schema = transaction_schema().unwrap();
+        let mut writer = AvroWriter::new(&schema, Vec::new()).unwrap();
+        writer
+            .write(Entry {
+                transaction_type: FlatTransactionType::Deposit,
+                client: 1,
+                tx: 1,
+                amount: Some("1.234,5"),
+            })
+            .unwrap();
+        let data = writer.finish().unwrap();
+
+        let mut reader = AvroReader::new(data.as_slice())
+            .unwrap()
+            .with_amount_format(AmountFormat::Locale(LocaleFormat::CommaDecimal));
+
+        assert_eq!(
+            vec![Ok(Transaction::new(
+                1,
+                1,
+                TransactionType::Deposit {
+                    amount: CurrencyAmount::from_str("1234.5").unwrap()
+                }
+            ))],
+            reader.read().collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_write_report() {
+        let mut transaction_processor = TransactionProcessor::new();
+        transaction_processor
+            .transact(&Transaction::new(
+                7,
+                1,
+                TransactionType::Deposit {
+                    amount: CurrencyAmount::from_str("2.50").unwrap(),
+                },
+            ))
+            .unwrap();
+
+        let schema = report_entry_schema().unwrap();
+        let mut writer = AvroWriter::new(&schema, Vec::new()).unwrap();
+        for entry in
+            transaction_processor.generate_report(&ReportFilter::new(), ReportOrder::ClientId)
+        {
+            writer.write(entry).unwrap();
+        }
+        let data = writer.finish().unwrap();
+
+        let records = apache_avro::Reader::new(data.as_slice())
+            .unwrap()
+            .map(|value| apache_avro::from_value::<serde_json::Value>(&value.unwrap()).unwrap())
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            vec![serde_json::json!({
+                "client": 7,
+                "available": "2.50",
+                "held": "0",
+                "total": "2.50",
+                "locked": false,
+            })],
+            records
+        );
+    }
+}
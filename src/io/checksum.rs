@@ -0,0 +1,251 @@
+use std::fmt::{Display, Formatter};
+use std::io::{self, Read};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex, PoisonError};
+
+use sha2::{Digest, Sha256};
+
+/// The extension of a sidecar checksum file, e.g. `input.csv.sha256`.
+pub const SIDECAR_EXTENSION: &str = "sha256";
+
+/// A SHA-256 digest.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Sha256Digest([u8; 32]);
+
+impl Sha256Digest {
+    /// Computes the digest of some data.
+    #[must_use]
+    pub fn of(data: &[u8]) -> Self {
+        Self(Sha256::digest(data).into())
+    }
+
+    /// Parses the contents of a sidecar checksum file. Both a bare hex digest
+    /// and the `<digest>  <file name>` lines written by `sha256sum` are
+    /// accepted; only the first digest is used.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file does not start with a hex digest.
+    pub fn from_sidecar(contents: &str) -> Result<Self, ChecksumError> {
+        contents
+            .split_whitespace()
+            .next()
+            .ok_or(ChecksumError::InvalidDigest)?
+            .parse()
+    }
+}
+
+impl FromStr for Sha256Digest {
+    type Err = ChecksumError;
+
+    /// Parses a digest from 64 hex digits (in either case).
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.len() != 64 || !s.is_ascii() {
+            return Err(ChecksumError::InvalidDigest);
+        }
+
+        let mut digest = [0; 32];
+        for (index, byte) in digest.iter_mut().enumerate() {
+            // The string is ASCII, so any index is a character boundary
+            *byte = u8::from_str_radix(&s[index * 2..index * 2 + 2], 16)
+                .map_err(|_| ChecksumError::InvalidDigest)?;
+        }
+
+        Ok(Self(digest))
+    }
+}
+
+impl Display for Sha256Digest {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for byte in self.0 {
+            write!(f, "{:02x}", byte)?;
+        }
+        Ok(())
+    }
+}
+
+/// Error returned when a checksum could not be parsed or did not match.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum ChecksumError {
+    /// The expected checksum is not a 64 digit hex SHA-256 digest.
+    InvalidDigest,
+    /// The data read does not have the expected digest.
+    Mismatch {
+        /// The digest which the data should have had.
+        expected: Sha256Digest,
+        /// The digest of the data actually read.
+        actual: Sha256Digest,
+    },
+}
+
+impl Display for ChecksumError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ChecksumError::InvalidDigest => f.write_str("Invalid SHA-256 checksum"),
+            ChecksumError::Mismatch { expected, actual } => write!(
+                f,
+                "Checksum mismatch: expected SHA-256 {}, got {}",
+                expected, actual
+            ),
+        }
+    }
+}
+
+/// The result of verifying a [`ChecksumReader`], which can be checked after
+/// the reader has been passed to (and consumed by) another reader.
+#[derive(Clone, Debug, Default)]
+pub struct ChecksumHandle(Arc<Mutex<Option<Result<(), ChecksumError>>>>);
+
+impl ChecksumHandle {
+    /// The result of the verification, or `None` if the reader has not yet
+    /// reached the end of the data.
+    #[must_use]
+    pub fn result(&self) -> Option<Result<(), ChecksumError>> {
+        self.0
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+}
+
+/// Computes the SHA-256 digest of the data as it is read, and checks it
+/// against the expected digest once the end is reached. The data is streamed
+/// rather than read in advance, so a mismatch can only be detected at the
+/// end: callers must not treat anything read as trustworthy until
+/// [`ChecksumHandle::result`] succeeds.
+///
+/// On a mismatch, reading the end of the data fails with
+/// [`io::ErrorKind::InvalidData`]. The error is only returned once, and
+/// later reads return the end of the data, so that readers which carry on
+/// after an error (e.g. [`io::BufRead::lines`]) still stop.
+pub struct ChecksumReader<R: Read> {
+    reader: R,
+    hasher: Sha256,
+    expected: Sha256Digest,
+    handle: ChecksumHandle,
+    /// True once a mismatch has been returned as an error.
+    mismatch_reported: bool,
+}
+
+impl<R: Read> ChecksumReader<R> {
+    /// Creates a reader which checks that `reader` has the expected digest.
+    pub fn new(reader: R, expected: Sha256Digest) -> Self {
+        Self {
+            reader,
+            hasher: Sha256::new(),
+            expected,
+            handle: ChecksumHandle::default(),
+            mismatch_reported: false,
+        }
+    }
+
+    /// Returns a handle for checking the result of the verification.
+    #[must_use]
+    pub fn handle(&self) -> ChecksumHandle {
+        self.handle.clone()
+    }
+}
+
+impl<R: Read> Read for ChecksumReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.reader.read(buf)?;
+        self.hasher.update(&buf[..read]);
+
+        if read == 0 && !buf.is_empty() {
+            let mut result = self.handle.0.lock().unwrap_or_else(PoisonError::into_inner);
+            let result = result.get_or_insert_with(|| {
+                let actual = Sha256Digest(self.hasher.clone().finalize().into());
+                if actual == self.expected {
+                    Ok(())
+                } else {
+                    Err(ChecksumError::Mismatch {
+                        expected: self.expected,
+                        actual,
+                    })
+                }
+            });
+
+            if let Err(err) = result {
+                if self.mismatch_reported {
+                    return Ok(0);
+                }
+                self.mismatch_reported = true;
+                return Err(io::Error::new(io::ErrorKind::InvalidData, err.to_string()));
+            }
+        }
+
+        Ok(read)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::{ErrorKind, Read};
+    use std::str::FromStr;
+
+    use crate::io::checksum::{ChecksumError, ChecksumReader, Sha256Digest};
+
+    const DATA: &[u8] = b"type,client,tx,amount\ndeposit,1,1,1.0\n";
+
+    #[test]
+    fn test_digest() {
+        let digest = Sha256Digest::of(b"");
+        assert_eq!(
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855",
+            digest.to_string()
+        );
+        assert_eq!(
+            Ok(digest),
+            Sha256Digest::from_str(
+                "E3B0C44298FC1C149AFBF4C8996FB92427AE41E4649B934CA495991B7852B855"
+            )
+        );
+        assert_eq!(
+            Ok(digest),
+            Sha256Digest::from_sidecar(
+                "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855  input.csv\n"
+            )
+        );
+
+        assert_eq!(
+            Err(ChecksumError::InvalidDigest),
+            Sha256Digest::from_str("e3b0")
+        );
+        assert_eq!(
+            Err(ChecksumError::InvalidDigest),
+            Sha256Digest::from_str(&"g".repeat(64))
+        );
+        assert_eq!(
+            Err(ChecksumError::InvalidDigest),
+            Sha256Digest::from_sidecar("\n")
+        );
+    }
+
+    #[test]
+    fn test_checksum_reader() {
+        let mut reader = ChecksumReader::new(DATA, Sha256Digest::of(DATA));
+        let handle = reader.handle();
+        let mut output = Vec::new();
+
+        reader.read_exact(&mut [0; 4]).unwrap();
+        assert_eq!(None, handle.result());
+
+        reader.read_to_end(&mut output).unwrap();
+        assert_eq!(&DATA[4..], output.as_slice());
+        assert_eq!(Some(Ok(())), handle.result());
+
+        let expected = Sha256Digest::of(b"other");
+        let mut reader = ChecksumReader::new(DATA, expected);
+        let handle = reader.handle();
+        let err = reader.read_to_end(&mut Vec::new()).unwrap_err();
+        assert_eq!(ErrorKind::InvalidData, err.kind());
+        assert_eq!(0, reader.read(&mut [0; 4]).unwrap());
+        assert_eq!(
+            Some(Err(ChecksumError::Mismatch {
+                expected,
+                actual: Sha256Digest::of(DATA)
+            })),
+            handle.result()
+        );
+    }
+}
@@ -0,0 +1,212 @@
+use std::fs::File;
+use std::io;
+use std::io::BufRead;
+use std::path::Path;
+
+#[cfg(feature = "remote")]
+use crate::io::remote::{open_remote, RemoteLocation, S3Config};
+
+/// The magic bytes at the start of a gzip stream.
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+/// The magic bytes at the start of a zstd frame.
+const ZSTD_MAGIC: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd];
+
+/// The compression applied to an input file.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Compression {
+    /// The file is not compressed.
+    None,
+    /// The file is gzip compressed (possibly with multiple members, as
+    /// produced by concatenating `.gz` files).
+    Gzip,
+    /// The file is zstd compressed.
+    Zstd,
+}
+
+impl Compression {
+    /// Detects the compression from the file extension (`.gz` or `.zst`),
+    /// or `None` if the extension is not recognised.
+    pub fn from_path(path: impl AsRef<Path>) -> Option<Self> {
+        let extension = path.as_ref().extension()?.to_str()?;
+
+        if extension.eq_ignore_ascii_case("gz") {
+            Some(Compression::Gzip)
+        } else if extension.eq_ignore_ascii_case("zst") {
+            Some(Compression::Zstd)
+        } else {
+            None
+        }
+    }
+
+    /// Detects the compression from the magic bytes at the start of the
+    /// data.
+    pub fn from_magic_bytes(data: &[u8]) -> Self {
+        if data.starts_with(GZIP_MAGIC) {
+            Compression::Gzip
+        } else if data.starts_with(ZSTD_MAGIC) {
+            Compression::Zstd
+        } else {
+            Compression::None
+        }
+    }
+}
+
+/// Wraps a reader so that it is stream-decompressed using the specified
+/// compression.
+///
+/// # Errors
+///
+/// Returns an error if the decompressor could not be created.
+pub fn decompress<'a>(
+    reader: impl BufRead + Send + 'a,
+    compression: Compression,
+) -> io::Result<Box<dyn BufRead + Send + 'a>> {
+    Ok(match compression {
+        Compression::None => Box::new(reader),
+        Compression::Gzip => Box::new(io::BufReader::new(flate2::bufread::MultiGzDecoder::new(
+            reader,
+        ))),
+        Compression::Zstd => Box::new(io::BufReader::new(zstd::Decoder::with_buffer(reader)?)),
+    })
+}
+
+/// Opens an input file, transparently decompressing it if it is gzip or
+/// zstd compressed. The compression is detected from the file extension,
+/// or from the first few bytes of the file if the extension is not
+/// recognised.
+///
+/// With the `remote` feature, `http://`, `https://` and `s3://` URIs are
+/// also accepted, and streamed using `io::remote::open_remote` (with the S3
+/// configuration taken from the environment).
+///
+/// # Errors
+///
+/// Returns an error if the file could not be opened or read.
+pub fn open_input(path: impl AsRef<Path>) -> io::Result<Box<dyn BufRead + Send>> {
+    decompress_input(open_raw_input(path.as_ref())?, path)
+}
+
+/// Opens an input file (or, with the `remote` feature, URI) like
+/// [`open_input`], but without decompressing it. Useful for inspecting the
+/// bytes of the file as delivered, before passing them to
+/// [`decompress_input`].
+///
+/// # Errors
+///
+/// Returns an error if the file could not be opened.
+pub fn open_raw_input(path: impl AsRef<Path>) -> io::Result<Box<dyn io::Read + Send>> {
+    #[cfg(feature = "remote")]
+    if let Some(location) = path.as_ref().to_str().and_then(RemoteLocation::parse) {
+        return Ok(open_remote(&location, &S3Config::from_env())?);
+    }
+
+    Ok(Box::new(File::open(path.as_ref())?))
+}
+
+/// Decompresses the raw contents of the input file at `path`, detecting the
+/// compression in the same way as [`open_input`].
+///
+/// # Errors
+///
+/// Returns an error if the start of the file could not be read, or the
+/// decompressor could not be created.
+pub fn decompress_input<'a>(
+    reader: impl io::Read + Send + 'a,
+    path: impl AsRef<Path>,
+) -> io::Result<Box<dyn BufRead + Send + 'a>> {
+    #[cfg(feature = "remote")]
+    let compression = match path.as_ref().to_str().and_then(RemoteLocation::parse) {
+        Some(location) => Compression::from_path(location.path()),
+        None => Compression::from_path(path.as_ref()),
+    };
+    #[cfg(not(feature = "remote"))]
+    let compression = Compression::from_path(path.as_ref());
+
+    let mut reader = io::BufReader::new(reader);
+    let compression = match compression {
+        Some(compression) => compression,
+        None => Compression::from_magic_bytes(reader.fill_buf()?),
+    };
+
+    decompress(reader, compression)
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::{Read, Write};
+
+    use crate::io::compression::{decompress, open_input, Compression};
+
+    const DATA: &str = "type,client,tx,amount\ndeposit,1,1,1.0\n";
+
+    fn gzip(data: &[u8]) -> Vec<u8> {
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::fast());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    fn read_to_string(reader: &mut impl Read) -> String {
+        let mut output = String::new();
+        reader.read_to_string(&mut output).unwrap();
+        output
+    }
+
+    #[test]
+    fn test_detect() {
+        assert_eq!(
+            Some(Compression::Gzip),
+            Compression::from_path("input.csv.GZ")
+        );
+        assert_eq!(
+            Some(Compression::Zstd),
+            Compression::from_path("input.jsonl.zst")
+        );
+        assert_eq!(None, Compression::from_path("input.csv"));
+
+        assert_eq!(
+            Compression::Gzip,
+            Compression::from_magic_bytes(&gzip(DATA.as_bytes()))
+        );
+        assert_eq!(
+            Compression::Zstd,
+            Compression::from_magic_bytes(&zstd::encode_all(DATA.as_bytes(), 1).unwrap())
+        );
+        assert_eq!(
+            Compression::None,
+            Compression::from_magic_bytes(DATA.as_bytes())
+        );
+        assert_eq!(Compression::None, Compression::from_magic_bytes(&[]));
+    }
+
+    #[test]
+    fn test_decompress() {
+        // Concatenated gzip members are read as a single stream
+        let mut data = gzip(&DATA.as_bytes()[..10]);
+        data.extend(gzip(&DATA.as_bytes()[10..]));
+        assert_eq!(
+            DATA,
+            read_to_string(&mut decompress(data.as_slice(), Compression::Gzip).unwrap())
+        );
+
+        let data = zstd::encode_all(DATA.as_bytes(), 1).unwrap();
+        assert_eq!(
+            DATA,
+            read_to_string(&mut decompress(data.as_slice(), Compression::Zstd).unwrap())
+        );
+    }
+
+    #[test]
+    fn test_open_input() {
+        // The extension doesn't match, so the magic bytes are used instead
+        let path = std::env::temp_dir().join(format!(
+            "transaction-processor-compressed-{}.csv",
+            std::process::id()
+        ));
+        std::fs::write(&path, gzip(DATA.as_bytes())).unwrap();
+
+        let output = read_to_string(&mut open_input(&path).unwrap());
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(DATA, output);
+    }
+}
@@ -0,0 +1,1848 @@
+use std::fmt::{Display, Formatter};
+use std::io;
+
+use csv::{ErrorKind, Position, StringRecord, Trim};
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "crypto")]
+use crate::crypto::HmacKey;
+use crate::io::{AmountFormat, ColumnMapping, EntryConvertError, RawEntry};
+use crate::numeric::CurrencyAmount;
+use crate::reject::{RejectSink, RejectedRow};
+use crate::report::ReportSink;
+use crate::source::{SourceError, TransactionSource};
+use crate::{ClientId, ReportEntry, Transaction};
+
+/// The columns of a headerless CSV file, in order.
+const STANDARD_HEADERS: [&str; 4] = ["type", "client", "tx", "amount"];
+
+/// The column holding the signature of each row, if signatures are checked
+/// (see [`CSVReader::with_signature_key`]). In headerless files, this is the
+/// fifth column.
+pub const SIGNATURE_COLUMN: &str = "signature";
+
+/// The optional column holding each row's idempotency key (see
+/// [`Transaction::idempotency_key`]). It isn't read from headerless files.
+pub const IDEMPOTENCY_KEY_COLUMN: &str = "idempotency_key";
+
+/// The optional column holding each row's sequence number (see
+/// [`Transaction::sequence`]). It isn't read from headerless files.
+pub const SEQUENCE_COLUMN: &str = "sequence";
+
+/// The optional column holding each row's timestamp (see
+/// [`Transaction::timestamp`]). It isn't read from headerless files.
+pub const TIMESTAMP_COLUMN: &str = "timestamp";
+
+/// Error returned when reading a transaction from a CSV file.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum CSVReaderError {
+    /// The row is not valid CSV, or does not have the expected columns.
+    CSVParseError(String),
+    /// The row is valid CSV, but is not a valid transaction.
+    TransactionParseError(EntryConvertError),
+    /// The row's signature is missing or does not match its fields.
+    SignatureError(SignatureError),
+}
+
+/// Why a row's signature was rejected.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum SignatureError {
+    /// The row has no signature.
+    Missing,
+    /// The signature is not valid for the row's fields and the key.
+    Mismatch,
+}
+
+impl Display for SignatureError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            SignatureError::Missing => "Missing row signature",
+            SignatureError::Mismatch => "Invalid row signature",
+        })
+    }
+}
+
+impl Display for CSVReaderError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&match self {
+            CSVReaderError::CSVParseError(err) => format!("CSV parse error: {}", err),
+            CSVReaderError::TransactionParseError(err) => {
+                format!("Transaction parse error: {}", err)
+            }
+            CSVReaderError::SignatureError(err) => err.to_string(),
+        })
+    }
+}
+
+/// Where in a CSV file an error occurred.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct RecordLocation {
+    source_name: Option<String>,
+    line: u64,
+    byte: u64,
+    record: u64,
+    raw_record: Option<String>,
+}
+
+impl RecordLocation {
+    fn new(source_name: Option<&str>, position: &Position, raw_record: Option<String>) -> Self {
+        Self {
+            source_name: source_name.map(str::to_string),
+            line: position.line(),
+            byte: position.byte(),
+            record: position.record(),
+            raw_record,
+        }
+    }
+
+    /// The name of the file (or other source) being read, if set using
+    /// [`CSVReader::with_source_name`].
+    pub fn source_name(&self) -> Option<&str> {
+        self.source_name.as_deref()
+    }
+
+    /// The 1-based line number the record starts on.
+    pub fn line(&self) -> u64 {
+        self.line
+    }
+
+    /// The byte offset the record starts at.
+    pub fn byte(&self) -> u64 {
+        self.byte
+    }
+
+    /// The 0-based index of the record, counting the header row (if any).
+    pub fn record(&self) -> u64 {
+        self.record
+    }
+
+    /// The fields of the record, joined by commas with surrounding
+    /// whitespace removed. Not available if the record could not be parsed
+    /// as CSV at all.
+    pub fn raw_record(&self) -> Option<&str> {
+        self.raw_record.as_deref()
+    }
+}
+
+impl Display for RecordLocation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if let Some(source_name) = &self.source_name {
+            write!(f, "{} ", source_name)?;
+        }
+
+        write!(f, "line {} (byte {})", self.line, self.byte)
+    }
+}
+
+/// A [`CSVReaderError`] along with the location of the record it occurred
+/// in, where known. The raw record is not included when displayed, as it is
+/// usually written alongside the error (e.g. by [`RejectWriter`]).
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct CSVRecordError {
+    error: CSVReaderError,
+    location: Option<RecordLocation>,
+}
+
+impl CSVRecordError {
+    /// The error which occurred.
+    pub fn error(&self) -> &CSVReaderError {
+        &self.error
+    }
+
+    /// Where the error occurred. Only unavailable if the underlying reader
+    /// failed (e.g. an I/O error).
+    pub fn location(&self) -> Option<&RecordLocation> {
+        self.location.as_ref()
+    }
+
+    /// Discards the location, returning the error which occurred.
+    pub fn into_error(self) -> CSVReaderError {
+        self.error
+    }
+}
+
+impl Display for CSVRecordError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match &self.location {
+            Some(location) => write!(f, "{}: {}", location, self.error),
+            None => write!(f, "{}", self.error),
+        }
+    }
+}
+
+impl From<CSVRecordError> for SourceError {
+    fn from(err: CSVRecordError) -> Self {
+        SourceError::new(err.to_string())
+    }
+}
+
+/// How serious a [`Diagnostic`] is.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub enum Severity {
+    /// A recoverable issue, which did not prevent the row being read.
+    Warning,
+    /// The row could not be read.
+    Error,
+}
+
+impl Display for Severity {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        })
+    }
+}
+
+/// An issue found while reading CSV data, e.g. for counting or reporting
+/// warnings separately from errors.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Diagnostic {
+    severity: Severity,
+    location: Option<RecordLocation>,
+    message: String,
+}
+
+impl Diagnostic {
+    /// How serious the issue is.
+    pub fn severity(&self) -> Severity {
+        self.severity
+    }
+
+    /// Where the issue was found, if known.
+    pub fn location(&self) -> Option<&RecordLocation> {
+        self.location.as_ref()
+    }
+
+    /// A description of the issue.
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match &self.location {
+            Some(location) => write!(f, "{}: {}: {}", self.severity, location, self.message),
+            None => write!(f, "{}: {}", self.severity, self.message),
+        }
+    }
+}
+
+impl From<&CSVRecordError> for Diagnostic {
+    fn from(err: &CSVRecordError) -> Self {
+        Self {
+            severity: Severity::Error,
+            location: err.location.clone(),
+            message: err.error.to_string(),
+        }
+    }
+}
+
+/// An item read by [`CSVReader::read_with_diagnostics`].
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum CSVReadItem {
+    /// A row, with its fields verbatim and the transaction read from it.
+    Record {
+        /// The fields of the row, or empty if it could not be parsed as CSV
+        /// at all.
+        fields: Vec<String>,
+        /// The transaction, or the error which prevented it being read.
+        transaction: Result<Transaction, CSVRecordError>,
+        /// The 1-based line the row starts on, if known.
+        line: Option<u64>,
+    },
+    /// A warning about the following row, or the header row.
+    Diagnostic(Diagnostic),
+}
+
+/// Describes a csv crate error without its position, which is reported
+/// separately in a [`RecordLocation`].
+fn csv_error_message(err: &csv::Error) -> String {
+    match err.kind() {
+        ErrorKind::Deserialize { err, .. } => format!("{}", err),
+        ErrorKind::Utf8 { err, .. } => format!("invalid UTF-8: {}", err),
+        _ => format!("{}", err),
+    }
+}
+
+/// Checks the signature of a row against its signed fields, which are found
+/// at the given positions (any missing field is signed as empty).
+#[cfg(feature = "crypto")]
+fn verify_signature(
+    key: &HmacKey,
+    record: &StringRecord,
+    columns: &[Option<usize>; 4],
+    signature_column: Option<usize>,
+) -> Result<(), SignatureError> {
+    let signature = signature_column
+        .and_then(|column| record.get(column))
+        .filter(|signature| !signature.is_empty())
+        .ok_or(SignatureError::Missing)?;
+
+    let message = columns
+        .iter()
+        .map(|column| column.and_then(|column| record.get(column)).unwrap_or(""))
+        .collect::<Vec<_>>()
+        .join(",");
+
+    if key.verify(message.as_bytes(), signature) {
+        Ok(())
+    } else {
+        Err(SignatureError::Mismatch)
+    }
+}
+
+/// Error returned when writing a record to a CSV file.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum CSVWriterError {
+    /// The record could not be serialized or written.
+    CSVWriteError(String),
+    /// A column given to [`CSVWriterOptions::with_columns`] is not a field of
+    /// the records.
+    UnknownColumn(String),
+}
+
+impl Display for CSVWriterError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&match self {
+            CSVWriterError::CSVWriteError(err) => format!("CSV write error: {}", err),
+            CSVWriterError::UnknownColumn(column) => format!("Unknown column '{}'", column),
+        })
+    }
+}
+
+/// Reads transactions from CSV with the columns `type,client,tx,amount`.
+///
+/// Whitespace around fields is ignored, and the amount column may be omitted
+/// for disputes, resolutions, and chargebacks. Files with other column names
+/// can be read using [`CSVReader::with_column_mapping`].
+///
+/// Errors are returned with the [`RecordLocation`] they occurred at.
+pub struct CSVReader<R: io::Read> {
+    reader: csv::Reader<R>,
+    amount_format: AmountFormat,
+    /// Applied to the header row when reading starts.
+    column_mapping: Option<ColumnMapping>,
+    source_name: Option<String>,
+    /// If set, the client column holds client keys rather than IDs.
+    client_keys: bool,
+    /// If set, every row must be signed with this key.
+    #[cfg(feature = "crypto")]
+    signature_key: Option<HmacKey>,
+    /// The parser of the rows read as a [`TransactionSource`], once the
+    /// header row has been read.
+    parser: Option<RecordParser>,
+    /// The number of warnings logged by [`TransactionSource::next_row`].
+    warnings: usize,
+}
+
+impl<R: io::Read> CSVReader<R> {
+    /// Creates a reader for CSV data with a header row.
+    pub fn new(reader: R) -> Self {
+        Self::new_delimited(reader, b',', true)
+    }
+
+    /// Creates a reader for CSV data without a header row, where the
+    /// columns are always in the order `type,client,tx,amount`. Any
+    /// [`CSVReader::with_column_mapping`] is ignored.
+    pub fn new_headerless(reader: R) -> Self {
+        Self::new_delimited(reader, b',', false)
+    }
+
+    /// Creates a reader for data with fields separated by `delimiter`
+    /// rather than commas, e.g. `b'\t'` for TSV, with or without a header
+    /// row (see [`CSVReader::new_headerless`]).
+    pub fn new_delimited(reader: R, delimiter: u8, has_headers: bool) -> Self {
+        Self {
+            reader: csv::ReaderBuilder::new()
+                .trim(Trim::All)
+                .flexible(true)
+                .delimiter(delimiter)
+                .has_headers(has_headers)
+                .from_reader(reader),
+            amount_format: AmountFormat::Standard,
+            column_mapping: None,
+            source_name: None,
+            client_keys: false,
+            #[cfg(feature = "crypto")]
+            signature_key: None,
+            parser: None,
+            warnings: 0,
+        }
+    }
+
+    /// Sets how the amount column is parsed. Defaults to
+    /// [`AmountFormat::Standard`].
+    #[must_use]
+    pub fn with_amount_format(mut self, amount_format: AmountFormat) -> Self {
+        self.amount_format = amount_format;
+        self
+    }
+
+    /// Sets the names of the input columns, which are renamed to the
+    /// standard `type,client,tx,amount` before the rows are parsed.
+    #[must_use]
+    pub fn with_column_mapping(mut self, column_mapping: ColumnMapping) -> Self {
+        self.column_mapping = Some(column_mapping);
+        self
+    }
+
+    /// Sets the name of the file (or other source) being read, which is
+    /// included in the location of any errors.
+    #[must_use]
+    pub fn with_source_name(mut self, source_name: impl Into<String>) -> Self {
+        self.source_name = Some(source_name.into());
+        self
+    }
+
+    /// Reads the client column as client keys, such as UUIDs, rather than
+    /// client IDs (see [`Transaction::with_client_key`]). Every value is a
+    /// key, even if it looks like a number, and an empty value is an error.
+    #[must_use]
+    pub fn with_client_keys(mut self) -> Self {
+        self.client_keys = true;
+        self
+    }
+
+    /// Requires every row to have a `signature` column (see
+    /// [`SIGNATURE_COLUMN`]) containing the hex HMAC-SHA256, using `key`, of
+    /// its `type,client,tx,amount` fields. The fields are signed after any
+    /// column mapping and with surrounding whitespace removed, joined by
+    /// commas in that order (with an empty amount if there is none), e.g.
+    /// `dispute,1,2,`. Rows which are unsigned or have an invalid signature
+    /// fail with [`CSVReaderError::SignatureError`].
+    #[cfg(feature = "crypto")]
+    #[must_use]
+    pub fn with_signature_key(mut self, key: HmacKey) -> Self {
+        self.signature_key = Some(key);
+        self
+    }
+
+    /// Returns an iterator over the transactions in the CSV data. Invalid
+    /// rows result in an error, but do not end the iteration.
+    pub fn read(&mut self) -> impl Iterator<Item = Result<Transaction, CSVRecordError>> + '_ {
+        self.read_with_records().map(|(_, transaction)| transaction)
+    }
+
+    /// Like [`CSVReader::read`], but also returns the fields of each row
+    /// verbatim, e.g. for recording rejected rows. The fields are empty if
+    /// the row could not be parsed as CSV at all.
+    ///
+    /// Warnings are logged rather than returned; use
+    /// [`CSVReader::read_with_diagnostics`] to receive them.
+    pub fn read_with_records(
+        &mut self,
+    ) -> impl Iterator<Item = (Vec<String>, Result<Transaction, CSVRecordError>)> + '_ {
+        self.read_with_diagnostics().filter_map(|item| match item {
+            CSVReadItem::Record {
+                fields,
+                transaction,
+                ..
+            } => Some((fields, transaction)),
+            CSVReadItem::Diagnostic(diagnostic) => {
+                log::warn!("{}", diagnostic);
+                None
+            }
+        })
+    }
+
+    /// Like [`CSVReader::read_with_records`], but also yields warnings about
+    /// recoverable issues (such as unknown columns, or rows with extra
+    /// fields) which do not prevent the rows being read. Each warning is
+    /// yielded before the row it concerns.
+    pub fn read_with_diagnostics(&mut self) -> impl Iterator<Item = CSVReadItem> + '_ {
+        let (parser, header_diagnostics) = self.record_parser();
+        header_diagnostics
+            .into_iter()
+            .map(CSVReadItem::Diagnostic)
+            .chain(
+                self.reader
+                    .records()
+                    .flat_map(move |record| parser.parse(record)),
+            )
+    }
+
+    /// Prepares the header row (if any), returning the parser of the rows
+    /// which follow it, and any warnings about the header row.
+    fn record_parser(&mut self) -> (RecordParser, Vec<Diagnostic>) {
+        let has_headers = self.reader.has_headers();
+
+        // Headerless rows are deserialized as if they had the standard
+        // header row, so that a missing amount column is still allowed
+        let (headers, header_diagnostics) = if has_headers {
+            self.prepare_headers()
+        } else {
+            (
+                Some(StringRecord::from(STANDARD_HEADERS.to_vec())),
+                Vec::new(),
+            )
+        };
+
+        #[cfg(feature = "crypto")]
+        let signing = self.signature_key.clone().map(|key| {
+            if has_headers {
+                let position = |name| headers.as_ref()?.iter().position(|header| header == name);
+                (
+                    key,
+                    STANDARD_HEADERS.map(position),
+                    position(SIGNATURE_COLUMN),
+                )
+            } else {
+                (key, [0, 1, 2, 3].map(Some), Some(STANDARD_HEADERS.len()))
+            }
+        });
+        let client_column = self
+            .client_keys
+            .then(|| {
+                headers
+                    .as_ref()?
+                    .iter()
+                    .position(|header| header == STANDARD_HEADERS[1])
+            })
+            .flatten();
+
+        let parser = RecordParser {
+            amount_format: self.amount_format,
+            source_name: self.source_name.clone(),
+            has_headers,
+            headers,
+            #[cfg(feature = "crypto")]
+            signing,
+            client_column,
+        };
+        (parser, header_diagnostics)
+    }
+
+    /// Applies the column mapping (if any) to the header row. Returns the
+    /// resulting header row, and warnings if the header row looks like a
+    /// transaction or has unknown columns.
+    fn prepare_headers(&mut self) -> (Option<StringRecord>, Vec<Diagnostic>) {
+        let column_mapping = self.column_mapping.take();
+
+        // If the header row can't be read, the error is reported by the
+        // first call to the iterator instead
+        let headers = match self.reader.headers() {
+            Ok(headers) => headers,
+            Err(_) => return (None, Vec::new()),
+        };
+
+        let source_name = self.source_name.as_deref();
+        let location = headers.position().map(|position| {
+            let raw_record = headers.iter().collect::<Vec<_>>().join(",");
+            RecordLocation::new(source_name, position, Some(raw_record))
+        });
+        let warning = |message: String| Diagnostic {
+            severity: Severity::Warning,
+            location: location.clone(),
+            message,
+        };
+
+        let mut diagnostics = Vec::new();
+
+        if headers.get(0).is_some_and(|header| {
+            ["deposit", "withdrawal", "dispute", "resolve", "chargeback"]
+                .iter()
+                .any(|transaction_type| header.eq_ignore_ascii_case(transaction_type))
+        }) {
+            diagnostics.push(warning(
+                "The CSV header row looks like a transaction, and will be skipped. Headerless \
+                 files should be read with CSVReader::new_headerless (--no-header)."
+                    .to_string(),
+            ));
+        }
+
+        let headers: StringRecord = match column_mapping {
+            Some(column_mapping) => headers
+                .iter()
+                .map(|header| column_mapping.standard_name(header).unwrap_or(header))
+                .collect(),
+            None => headers.clone(),
+        };
+
+        #[cfg(feature = "crypto")]
+        let signed = self.signature_key.is_some();
+        #[cfg(not(feature = "crypto"))]
+        let signed = false;
+
+        for header in headers
+            .iter()
+            .filter(|header| {
+                !STANDARD_HEADERS.contains(header)
+                    && ![IDEMPOTENCY_KEY_COLUMN, SEQUENCE_COLUMN, TIMESTAMP_COLUMN].contains(header)
+            })
+            .filter(|header| !(signed && *header == SIGNATURE_COLUMN))
+        {
+            diagnostics.push(warning(format!("Unknown column '{}' is ignored", header)));
+        }
+
+        self.reader.set_headers(headers.clone());
+        (Some(headers), diagnostics)
+    }
+}
+
+/// Reads the rows one at a time, logging any warnings (as
+/// [`CSVReader::read_with_records`] does). It shouldn't be mixed with the
+/// reader's iterators.
+impl<R: io::Read> TransactionSource for CSVReader<R> {
+    fn next(&mut self) -> Option<Result<Transaction, SourceError>> {
+        self.next_row().map(|(_, transaction)| transaction)
+    }
+
+    fn next_row(&mut self) -> Option<(Vec<String>, Result<Transaction, SourceError>)> {
+        if self.parser.is_none() {
+            let (parser, diagnostics) = self.record_parser();
+            for diagnostic in diagnostics {
+                log::warn!("{}", diagnostic);
+                self.warnings += 1;
+            }
+            self.parser = Some(parser);
+        }
+
+        let mut record = StringRecord::new();
+        let record = match self.reader.read_record(&mut record) {
+            Ok(true) => Ok(record),
+            Ok(false) => return None,
+            Err(err) => Err(err),
+        };
+        let parser = self.parser.as_ref()?;
+        parser
+            .parse(record)
+            .into_iter()
+            .find_map(|item| match item {
+                CSVReadItem::Record {
+                    fields,
+                    transaction,
+                    ..
+                } => Some((fields, transaction.map_err(SourceError::from))),
+                CSVReadItem::Diagnostic(diagnostic) => {
+                    log::warn!("{}", diagnostic);
+                    self.warnings += 1;
+                    None
+                }
+            })
+    }
+
+    fn warnings(&self) -> usize {
+        self.warnings
+    }
+}
+
+/// Parses the rows of a [`CSVReader`] into transactions, once its header row
+/// has been prepared.
+struct RecordParser {
+    amount_format: AmountFormat,
+    source_name: Option<String>,
+    has_headers: bool,
+    headers: Option<StringRecord>,
+    /// The key, and the positions of the signed columns and the signature.
+    #[cfg(feature = "crypto")]
+    signing: Option<(HmacKey, [Option<usize>; 4], Option<usize>)>,
+    /// The position of the client column, if it holds keys.
+    client_column: Option<usize>,
+}
+
+impl RecordParser {
+    /// Parses a row, returning any warnings about it followed by the row
+    /// itself.
+    fn parse(&self, record: csv::Result<StringRecord>) -> Vec<CSVReadItem> {
+        let source_name = &self.source_name;
+        let record = match record {
+            Ok(record) => record,
+            Err(err) => {
+                return vec![CSVReadItem::Record {
+                    fields: Vec::new(),
+                    transaction: Err(CSVRecordError {
+                        error: CSVReaderError::CSVParseError(csv_error_message(&err)),
+                        location: err.position().map(|position| {
+                            RecordLocation::new(source_name.as_deref(), position, None)
+                        }),
+                    }),
+                    line: err.position().map(Position::line),
+                }]
+            }
+        };
+
+        let location = || {
+            record.position().map(|position| {
+                let raw_record = record.iter().collect::<Vec<_>>().join(",");
+                RecordLocation::new(source_name.as_deref(), position, Some(raw_record))
+            })
+        };
+
+        #[cfg(feature = "crypto")]
+        let signed = self.signing.is_some();
+        #[cfg(not(feature = "crypto"))]
+        let signed = false;
+
+        let mut items = Vec::new();
+
+        let expected_len = match &self.headers {
+            Some(headers) if self.has_headers => headers.len(),
+            _ if signed => STANDARD_HEADERS.len() + 1,
+            _ => STANDARD_HEADERS.len(),
+        };
+        if record.len() > expected_len {
+            items.push(CSVReadItem::Diagnostic(Diagnostic {
+                severity: Severity::Warning,
+                location: location(),
+                message: format!(
+                    "Row has {} fields, but only {} columns are expected. The extra fields are \
+                     ignored.",
+                    record.len(),
+                    expected_len
+                ),
+            }));
+        }
+
+        #[cfg(feature = "crypto")]
+        if let Some((key, columns, signature_column)) = &self.signing {
+            if let Err(error) = verify_signature(key, &record, columns, *signature_column) {
+                items.push(CSVReadItem::Record {
+                    fields: record.iter().map(str::to_string).collect(),
+                    transaction: Err(CSVRecordError {
+                        error: CSVReaderError::SignatureError(error),
+                        location: location(),
+                    }),
+                    line: record.position().map(Position::line),
+                });
+                return items;
+            }
+        }
+
+        // A key is replaced by a placeholder ID, so that the rest of the row
+        // is parsed as usual
+        let client_column = self.client_column;
+        let client_key = client_column
+            .and_then(|column| record.get(column))
+            .filter(|key| !key.is_empty());
+        let keyed_record = client_key.map(|_| {
+            record
+                .iter()
+                .enumerate()
+                .map(|(column, field)| {
+                    if Some(column) == client_column {
+                        "0"
+                    } else {
+                        field
+                    }
+                })
+                .collect::<StringRecord>()
+        });
+
+        let transaction = keyed_record
+            .as_ref()
+            .unwrap_or(&record)
+            .deserialize::<RawEntry>(self.headers.as_ref())
+            .map_err(|err| CSVReaderError::CSVParseError(csv_error_message(&err)))
+            .and_then(|entry| {
+                entry
+                    .into_transaction(self.amount_format)
+                    .map_err(CSVReaderError::TransactionParseError)
+            })
+            .map(|transaction| match client_key {
+                Some(key) => transaction.with_client_key(key),
+                None => transaction,
+            })
+            .map_err(|error| CSVRecordError {
+                error,
+                location: location(),
+            });
+
+        items.push(CSVReadItem::Record {
+            fields: record.iter().map(str::to_string).collect(),
+            transaction,
+            line: record.position().map(Position::line),
+        });
+        items
+    }
+}
+
+/// The columns of a report written by [`CSVWriter`], in their default order.
+pub const REPORT_COLUMNS: [&str; 5] = ["client", "available", "held", "total", "locked"];
+
+/// Which fields a [`CSVWriter`] puts in quotes.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum QuoteStyle {
+    /// Only fields containing a delimiter, quote or line break.
+    #[default]
+    Necessary,
+    /// Every field.
+    Always,
+    /// Every field which isn't a number.
+    NonNumeric,
+    /// No fields, even if that makes the output ambiguous.
+    Never,
+}
+
+/// What a [`CSVWriter`] ends each row with.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum LineTerminator {
+    /// `\n`.
+    #[default]
+    Lf,
+    /// `\r\n`, e.g. for systems which expect DOS line endings.
+    Crlf,
+}
+
+/// Options controlling how a [`CSVWriter`] formats its output.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct CSVWriterOptions {
+    quote_style: QuoteStyle,
+    line_terminator: LineTerminator,
+    has_headers: bool,
+    columns: Option<Vec<String>>,
+}
+
+impl Default for CSVWriterOptions {
+    fn default() -> Self {
+        Self {
+            quote_style: QuoteStyle::default(),
+            line_terminator: LineTerminator::default(),
+            has_headers: true,
+            columns: None,
+        }
+    }
+}
+
+impl CSVWriterOptions {
+    /// Creates options for writing every field of each record, in order,
+    /// after a header row, quoting fields only where necessary and ending
+    /// rows with `\n`.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets which fields are put in quotes.
+    #[must_use]
+    pub fn with_quote_style(mut self, quote_style: QuoteStyle) -> Self {
+        self.quote_style = quote_style;
+        self
+    }
+
+    /// Sets what each row ends with.
+    #[must_use]
+    pub fn with_line_terminator(mut self, line_terminator: LineTerminator) -> Self {
+        self.line_terminator = line_terminator;
+        self
+    }
+
+    /// Sets whether a header row is written before the first record.
+    #[must_use]
+    pub fn with_headers(mut self, has_headers: bool) -> Self {
+        self.has_headers = has_headers;
+        self
+    }
+
+    /// Sets the fields written, by name, in the order given, if not all of
+    /// them in the records' order. Fields can be left out, or repeated.
+    #[must_use]
+    pub fn with_columns(mut self, columns: Option<Vec<String>>) -> Self {
+        self.columns = columns;
+        self
+    }
+
+    /// Which fields are put in quotes.
+    #[must_use]
+    pub fn quote_style(&self) -> QuoteStyle {
+        self.quote_style
+    }
+
+    /// What each row ends with.
+    #[must_use]
+    pub fn line_terminator(&self) -> LineTerminator {
+        self.line_terminator
+    }
+
+    /// True if a header row is written before the first record.
+    #[must_use]
+    pub fn has_headers(&self) -> bool {
+        self.has_headers
+    }
+
+    /// The fields written, in order, if not all of them.
+    #[must_use]
+    pub fn columns(&self) -> Option<&[String]> {
+        self.columns.as_deref()
+    }
+}
+
+/// Writes serializable records (such as [`crate::ReportEntry`]) as CSV with
+/// a header row, formatted according to its [`CSVWriterOptions`].
+pub struct CSVWriter<W: io::Write> {
+    writer: csv::Writer<W>,
+    has_headers: bool,
+    columns: Option<Vec<String>>,
+    /// The positions of `columns` among the records' fields, once the first
+    /// record has been written.
+    positions: Option<Vec<usize>>,
+}
+
+impl<W: io::Write> CSVWriter<W> {
+    /// Creates a writer outputting to the specified destination.
+    pub fn new(writer: W) -> Self {
+        Self::with_options(writer, &CSVWriterOptions::new())
+    }
+
+    /// Creates a writer which does not write a header row, e.g. for
+    /// appending records to existing CSV data.
+    pub fn new_headerless(writer: W) -> Self {
+        Self::with_options(writer, &CSVWriterOptions::new().with_headers(false))
+    }
+
+    /// Creates a writer outputting to the specified destination, formatted
+    /// according to `options`.
+    pub fn with_options(writer: W, options: &CSVWriterOptions) -> Self {
+        let quote_style = match options.quote_style {
+            QuoteStyle::Necessary => csv::QuoteStyle::Necessary,
+            QuoteStyle::Always => csv::QuoteStyle::Always,
+            QuoteStyle::NonNumeric => csv::QuoteStyle::NonNumeric,
+            QuoteStyle::Never => csv::QuoteStyle::Never,
+        };
+        let terminator = match options.line_terminator {
+            LineTerminator::Lf => csv::Terminator::Any(b'\n'),
+            LineTerminator::Crlf => csv::Terminator::CRLF,
+        };
+        Self {
+            writer: csv::WriterBuilder::new()
+                // The header row of selected columns is written separately
+                .has_headers(options.has_headers && options.columns.is_none())
+                .quote_style(quote_style)
+                .terminator(terminator)
+                .from_writer(writer),
+            has_headers: options.has_headers,
+            columns: options.columns.clone(),
+            positions: None,
+        }
+    }
+
+    /// Writes a single record. The header row is written before the first
+    /// record.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the record could not be serialized or written.
+    pub fn write(&mut self, record: impl Serialize) -> Result<(), CSVWriterError> {
+        let error = |err: csv::Error| CSVWriterError::CSVWriteError(format!("{}", err));
+        let Some(columns) = &self.columns else {
+            return self.writer.serialize(record).map_err(error);
+        };
+
+        // Serialized with its header first, to find the fields by name
+        let mut serialized = csv::Writer::from_writer(Vec::new());
+        serialized.serialize(record).map_err(error)?;
+        let serialized = serialized
+            .into_inner()
+            .map_err(|err| CSVWriterError::CSVWriteError(format!("{}", err.error())))?;
+        let mut rows = csv::ReaderBuilder::new()
+            .has_headers(false)
+            .from_reader(serialized.as_slice())
+            .into_records();
+        let header = rows.next().transpose().map_err(error)?.unwrap_or_default();
+        let fields = rows.next().transpose().map_err(error)?.unwrap_or_default();
+
+        if self.positions.is_none() {
+            let positions = columns
+                .iter()
+                .map(|column| {
+                    header
+                        .iter()
+                        .position(|name| name == column)
+                        .ok_or_else(|| CSVWriterError::UnknownColumn(column.clone()))
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            if self.has_headers {
+                self.writer.write_record(columns).map_err(error)?;
+            }
+            self.positions = Some(positions);
+        }
+        let positions = self.positions.as_deref().unwrap_or_default();
+        self.writer
+            .write_record(
+                positions
+                    .iter()
+                    .map(|position| fields.get(*position).unwrap_or_default()),
+            )
+            .map_err(error)
+    }
+
+    /// Flushes any buffered records, and returns the destination.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the buffered records could not be written.
+    pub fn into_inner(self) -> Result<W, CSVWriterError> {
+        self.writer
+            .into_inner()
+            .map_err(|err| CSVWriterError::CSVWriteError(format!("{}", err.error())))
+    }
+}
+
+impl<W: io::Write> ReportSink for CSVWriter<W> {
+    fn write_entry(&mut self, entry: ReportEntry) -> io::Result<()> {
+        self.write(entry)
+            .map_err(|err| io::Error::other(err.to_string()))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+/// The columns of a report row which are read back. The total follows from
+/// the available and held funds.
+#[derive(Deserialize)]
+struct RawReportEntry {
+    client: ClientId,
+    available: CurrencyAmount,
+    held: CurrencyAmount,
+    locked: bool,
+}
+
+/// Reads a report written by [`CSVWriter`], with the columns
+/// `client,available,held,total,locked` (e.g. to compare it with another
+/// report). Whitespace around fields is ignored, as are other columns.
+pub struct ReportReader<R: io::Read> {
+    reader: csv::Reader<R>,
+}
+
+impl<R: io::Read> ReportReader<R> {
+    /// Creates a reader for a report with a header row.
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader: csv::ReaderBuilder::new()
+                .trim(Trim::All)
+                .from_reader(reader),
+        }
+    }
+
+    /// Returns an iterator over the entries in the report. Invalid rows
+    /// result in a [`CSVReaderError::CSVParseError`], but do not end the
+    /// iteration.
+    pub fn read(&mut self) -> impl Iterator<Item = Result<ReportEntry, CSVReaderError>> + '_ {
+        self.reader.deserialize::<RawReportEntry>().map(|record| {
+            let record = record.map_err(|err| CSVReaderError::CSVParseError(err.to_string()))?;
+            let mut total = record.available;
+            total
+                .checked_add_assign(record.held)
+                .map_err(|err| CSVReaderError::CSVParseError(err.to_string()))?;
+            Ok(ReportEntry {
+                client: record.client,
+                client_key: None,
+                available: record.available,
+                held: record.held,
+                total,
+                locked: record.locked,
+            })
+        })
+    }
+}
+
+/// Writes rejected rows as CSV, with the code and reason for the rejection
+/// (see [`crate::TransactionError::code`]) in the first two columns, followed
+/// by the fields of the row verbatim. Rows have as many columns as they were
+/// read with, so the header row (`code,error,type,client,tx,amount`) only
+/// describes rows in the standard layout.
+pub struct RejectWriter<W: io::Write> {
+    writer: csv::Writer<W>,
+    wrote_header: bool,
+}
+
+impl<W: io::Write> RejectWriter<W> {
+    /// Creates a writer outputting to the specified destination.
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer: csv::WriterBuilder::new().flexible(true).from_writer(writer),
+            wrote_header: false,
+        }
+    }
+
+    /// Creates a writer which never writes the header row, e.g. for rows
+    /// which will be combined with the output of another writer.
+    pub fn new_headerless(writer: W) -> Self {
+        Self {
+            writer: csv::WriterBuilder::new().flexible(true).from_writer(writer),
+            wrote_header: true,
+        }
+    }
+
+    /// Writes a rejected row. The header row is written before the first
+    /// rejected row.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the row could not be written.
+    pub fn write(
+        &mut self,
+        code: &str,
+        reason: &str,
+        fields: impl IntoIterator<Item = impl AsRef<str>>,
+    ) -> Result<(), CSVWriterError> {
+        let write_error = |err: csv::Error| CSVWriterError::CSVWriteError(format!("{}", err));
+
+        self.write_header()?;
+
+        let mut record = StringRecord::new();
+        record.push_field(code);
+        record.push_field(reason);
+        for field in fields {
+            record.push_field(field.as_ref());
+        }
+
+        self.writer.write_record(&record).map_err(write_error)
+    }
+
+    /// Writes the header row, if it has not been written yet. This happens
+    /// automatically before the first rejected row, so is only needed when
+    /// the rows are written to the destination separately.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the header could not be written.
+    pub fn write_header(&mut self) -> Result<(), CSVWriterError> {
+        if !self.wrote_header {
+            self.writer
+                .write_record(["code", "error"].iter().chain(STANDARD_HEADERS.iter()))
+                .map_err(|err| CSVWriterError::CSVWriteError(format!("{}", err)))?;
+            self.wrote_header = true;
+        }
+
+        Ok(())
+    }
+
+    /// Flushes any buffered rows.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the rows could not be written.
+    pub fn flush(&mut self) -> Result<(), CSVWriterError> {
+        self.writer
+            .flush()
+            .map_err(|err| CSVWriterError::CSVWriteError(format!("{}", err)))
+    }
+}
+
+/// Quarantines rejected rows in a CSV file (see [`RejectWriter::write`]).
+impl<W: io::Write> RejectSink for RejectWriter<W> {
+    fn reject(&mut self, row: RejectedRow) -> io::Result<()> {
+        self.write(row.code(), row.reason(), row.fields())
+            .map_err(|err| io::Error::other(err.to_string()))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        RejectWriter::flush(self).map_err(|err| io::Error::other(err.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+
+    use crate::io::csv::{
+        CSVReadItem, CSVReader, CSVReaderError, CSVRecordError, CSVWriter, CSVWriterError,
+        CSVWriterOptions, Diagnostic, LineTerminator, QuoteStyle, RejectWriter, ReportReader,
+        Severity,
+    };
+    use crate::io::{AmountFormat, ColumnMapping, EntryConvertError};
+    use crate::numeric::{CurrencyAmount, CurrencyAmountParseError, LocaleFormat};
+    use crate::report::{ReportFilter, ReportOrder};
+    use crate::summary::PARSE_ERROR_CODE;
+    use crate::timestamp::{Timestamp, TimestampParseError};
+    use crate::{ReportEntry, Transaction, TransactionProcessor, TransactionType};
+
+    #[test]
+    fn test_parse() {
+        let data = r###"
+            type, client, tx, amount
+            deposit, 1, 1, 1.0
+            withdrawal, 2, 5, 3.0,
+            dispute,7,10
+            resolve,8,11
+            chargeback,9,12
+        "###;
+
+        let mut reader = CSVReader::new(data.as_bytes());
+        let mut reader = reader.read();
+
+        assert_eq!(
+            Transaction::new(
+                1,
+                1,
+                TransactionType::Deposit {
+                    amount: CurrencyAmount::from_str("1.0").unwrap()
+                }
+            ),
+            reader.next().unwrap().unwrap()
+        );
+
+        assert_eq!(
+            Transaction::new(
+                2,
+                5,
+                TransactionType::Withdrawal {
+                    amount: CurrencyAmount::from_str("3.0").unwrap()
+                }
+            ),
+            reader.next().unwrap().unwrap()
+        );
+
+        assert_eq!(
+            Transaction::new(7, 10, TransactionType::Dispute),
+            reader.next().unwrap().unwrap()
+        );
+
+        assert_eq!(
+            Transaction::new(8, 11, TransactionType::Resolve),
+            reader.next().unwrap().unwrap()
+        );
+
+        assert_eq!(
+            Transaction::new(9, 12, TransactionType::Chargeback),
+            reader.next().unwrap().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_parse_fail() {
+        let data = r###"
+            type, client, tx, amount
+            deposit, 1, 1, 1.0
+            unknown, 2, 5, 3.0,
+            dispute,7,10
+            deposit,1,1
+            chargeback,9,12
+        "###;
+
+        let mut reader = CSVReader::new(data.as_bytes());
+        let mut reader = reader.read();
+
+        assert_eq!(
+            Transaction::new(
+                1,
+                1,
+                TransactionType::Deposit {
+                    amount: CurrencyAmount::from_str("1.0").unwrap()
+                }
+            ),
+            reader.next().unwrap().unwrap()
+        );
+
+        assert!(matches!(
+            reader.next().unwrap().map_err(CSVRecordError::into_error),
+            Err(CSVReaderError::CSVParseError(_))
+        ));
+
+        assert_eq!(
+            Transaction::new(7, 10, TransactionType::Dispute),
+            reader.next().unwrap().unwrap()
+        );
+
+        assert_eq!(
+            Err(CSVReaderError::TransactionParseError(
+                EntryConvertError::MissingAmount
+            )),
+            reader.next().unwrap().map_err(CSVRecordError::into_error)
+        );
+
+        assert_eq!(
+            Transaction::new(9, 12, TransactionType::Chargeback),
+            reader.next().unwrap().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_parse_strict_amounts() {
+        let data = r###"
+            type, client, tx, amount
+            deposit, 1, 1, 1.0
+            deposit, 1, 2, +1.0
+            deposit, 1, 3, 1_000
+        "###;
+
+        let mut reader = CSVReader::new(data.as_bytes());
+        assert_eq!(3, reader.read().filter(Result::is_ok).count());
+
+        let mut reader = CSVReader::new(data.as_bytes()).with_amount_format(AmountFormat::Strict);
+        let mut reader = reader.read();
+
+        assert_eq!(
+            Transaction::new(
+                1,
+                1,
+                TransactionType::Deposit {
+                    amount: CurrencyAmount::from_str("1.0").unwrap()
+                }
+            ),
+            reader.next().unwrap().unwrap()
+        );
+
+        for _ in 0..2 {
+            assert_eq!(
+                Err(CSVReaderError::TransactionParseError(
+                    EntryConvertError::InvalidAmount(CurrencyAmountParseError::InvalidNumericValue)
+                )),
+                reader.next().unwrap().map_err(CSVRecordError::into_error)
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_locale_amounts() {
+        let data = r###"
+            type, client, tx, amount
+            deposit,1,1,"1.234,56"
+            withdrawal,1,2,"0,5"
+        "###;
+
+        let mut reader = CSVReader::new(data.as_bytes())
+            .with_amount_format(AmountFormat::Locale(LocaleFormat::CommaDecimal));
+        let mut reader = reader.read();
+
+        assert_eq!(
+            Transaction::new(
+                1,
+                1,
+                TransactionType::Deposit {
+                    amount: CurrencyAmount::from_str("1234.56").unwrap()
+                }
+            ),
+            reader.next().unwrap().unwrap()
+        );
+
+        assert_eq!(
+            Transaction::new(
+                1,
+                2,
+                TransactionType::Withdrawal {
+                    amount: CurrencyAmount::from_str("0.5").unwrap()
+                }
+            ),
+            reader.next().unwrap().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_parse_column_mapping() {
+        let data = r###"
+            transaction_type, client_id, txn_id, value
+            deposit, 1, 1, 1.0
+            dispute, 1, 1,
+        "###;
+
+        let mut reader = CSVReader::new(data.as_bytes());
+        assert!(matches!(
+            reader
+                .read()
+                .next()
+                .unwrap()
+                .map_err(CSVRecordError::into_error),
+            Err(CSVReaderError::CSVParseError(_))
+        ));
+
+        let mut reader = CSVReader::new(data.as_bytes()).with_column_mapping(
+            ColumnMapping::new()
+                .with_type_column("transaction_type")
+                .with_client_column("client_id")
+                .with_tx_column("txn_id")
+                .with_amount_column("value"),
+        );
+        let mut reader = reader.read();
+
+        assert_eq!(
+            Transaction::new(
+                1,
+                1,
+                TransactionType::Deposit {
+                    amount: CurrencyAmount::from_str("1.0").unwrap()
+                }
+            ),
+            reader.next().unwrap().unwrap()
+        );
+
+        assert_eq!(
+            Transaction::new(1, 1, TransactionType::Dispute),
+            reader.next().unwrap().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_parse_headerless() {
+        let data = r###"
+            deposit, 1, 1, 1.0
+            dispute, 1, 1
+            withdrawal, 2, 2
+        "###;
+
+        let mut reader = CSVReader::new_headerless(data.as_bytes());
+        let mut reader = reader.read();
+
+        assert_eq!(
+            Transaction::new(
+                1,
+                1,
+                TransactionType::Deposit {
+                    amount: CurrencyAmount::from_str("1.0").unwrap()
+                }
+            ),
+            reader.next().unwrap().unwrap()
+        );
+
+        assert_eq!(
+            Transaction::new(1, 1, TransactionType::Dispute),
+            reader.next().unwrap().unwrap()
+        );
+
+        assert_eq!(
+            Err(CSVReaderError::TransactionParseError(
+                EntryConvertError::MissingAmount
+            )),
+            reader.next().unwrap().map_err(CSVRecordError::into_error)
+        );
+    }
+
+    #[test]
+    fn test_writer_options() {
+        let entry = ReportEntry {
+            client: 1,
+            client_key: None,
+            available: CurrencyAmount::from_str("1.5").unwrap(),
+            held: CurrencyAmount::ZERO,
+            total: CurrencyAmount::from_str("1.5").unwrap(),
+            locked: false,
+        };
+        let write = |options: &CSVWriterOptions| {
+            let mut writer = CSVWriter::with_options(Vec::new(), options);
+            writer.write(&entry).unwrap();
+            writer.write(&entry).unwrap();
+            String::from_utf8(writer.into_inner().unwrap()).unwrap()
+        };
+
+        assert_eq!(
+            "\"client\",\"available\",\"held\",\"total\",\"locked\"\r\n\
+             \"1\",\"1.5\",\"0\",\"1.5\",\"false\"\r\n\
+             \"1\",\"1.5\",\"0\",\"1.5\",\"false\"\r\n",
+            write(
+                &CSVWriterOptions::new()
+                    .with_quote_style(QuoteStyle::Always)
+                    .with_line_terminator(LineTerminator::Crlf)
+            )
+        );
+
+        // Columns can be reordered and left out, with or without the header
+        let columns = Some(vec!["total".to_string(), "client".to_string()]);
+        assert_eq!(
+            "total,client\n1.5,1\n1.5,1\n",
+            write(&CSVWriterOptions::new().with_columns(columns))
+        );
+        assert_eq!(
+            "\"false\",1.5\n\"false\",1.5\n",
+            write(
+                &CSVWriterOptions::new()
+                    .with_columns(Some(vec!["locked".to_string(), "available".to_string()]))
+                    .with_headers(false)
+                    .with_quote_style(QuoteStyle::NonNumeric)
+            )
+        );
+
+        let mut writer = CSVWriter::with_options(
+            Vec::new(),
+            &CSVWriterOptions::new().with_columns(Some(vec!["balance".to_string()])),
+        );
+        assert_eq!(
+            Err(CSVWriterError::UnknownColumn("balance".to_string())),
+            writer.write(&entry)
+        );
+    }
+
+    #[test]
+    fn test_reject_writer() {
+        let data = r###"
+            type, client, tx, amount
+            deposit, 1, 1
+            unknown,2,"5, 6"
+        "###;
+
+        let mut output = Vec::new();
+        let mut writer = RejectWriter::new(&mut output);
+
+        for (fields, transaction) in CSVReader::new(data.as_bytes()).read_with_records() {
+            writer
+                .write(
+                    PARSE_ERROR_CODE,
+                    &transaction.unwrap_err().to_string(),
+                    fields,
+                )
+                .unwrap();
+        }
+        drop(writer);
+
+        let output = String::from_utf8(output).unwrap();
+        let mut lines = output.lines();
+        assert_eq!(Some("code,error,type,client,tx,amount"), lines.next());
+        assert_eq!(
+            Some(
+                "PARSE_ERROR,line 3 (byte 38): Transaction parse error: Missing amount,deposit,1,1"
+            ),
+            lines.next()
+        );
+        assert!(lines.next().unwrap().ends_with(",unknown,2,\"5, 6\""));
+    }
+
+    #[test]
+    fn test_reject_writer_sink() {
+        let withdrawal = Transaction::new(
+            1,
+            1,
+            TransactionType::Withdrawal {
+                amount: CurrencyAmount::from_str("2.5").unwrap(),
+            },
+        );
+        let mut output = Vec::new();
+        let mut writer = RejectWriter::new(&mut output);
+        TransactionProcessor::new().transact_all([withdrawal], &mut writer);
+        drop(writer);
+        assert!(String::from_utf8(output).unwrap().starts_with(
+            "code,error,type,client,tx,amount\n\
+             INSUFFICIENT_FUNDS,Not enough funds for client 1 ("
+        ));
+    }
+
+    #[test]
+    fn test_headerless_reject_writer() {
+        let mut output = Vec::new();
+        let mut writer = RejectWriter::new_headerless(&mut output);
+        writer
+            .write(
+                "INSUFFICIENT_FUNDS",
+                "Not enough funds",
+                ["withdrawal", "1", "2", "3"],
+            )
+            .unwrap();
+        writer.write_header().unwrap();
+        drop(writer);
+        assert_eq!(
+            "INSUFFICIENT_FUNDS,Not enough funds,withdrawal,1,2,3\n",
+            String::from_utf8(output).unwrap()
+        );
+
+        // With nothing written yet, the header is written on request
+        let mut output = Vec::new();
+        let mut writer = RejectWriter::new(&mut output);
+        writer.write_header().unwrap();
+        writer
+            .write(
+                "INSUFFICIENT_FUNDS",
+                "Not enough funds",
+                ["withdrawal", "1", "2", "3"],
+            )
+            .unwrap();
+        drop(writer);
+        assert_eq!(
+            "code,error,type,client,tx,amount\n\
+             INSUFFICIENT_FUNDS,Not enough funds,withdrawal,1,2,3\n",
+            String::from_utf8(output).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_error_location() {
+        let data = "type,client,tx,amount\n\
+                    deposit,1,1,1.0\n\
+                    deposit, 1, 2\n\
+                    unknown,2,3,1.0\n";
+
+        let mut reader = CSVReader::new(data.as_bytes()).with_source_name("transactions.csv");
+        let mut reader = reader.read();
+        assert!(reader.next().unwrap().is_ok());
+
+        let err = reader.next().unwrap().unwrap_err();
+        assert_eq!(
+            &CSVReaderError::TransactionParseError(EntryConvertError::MissingAmount),
+            err.error()
+        );
+        let location = err.location().unwrap();
+        assert_eq!(Some("transactions.csv"), location.source_name());
+        assert_eq!(3, location.line());
+        assert_eq!(38, location.byte());
+        assert_eq!(2, location.record());
+        assert_eq!(Some("deposit,1,2"), location.raw_record());
+        assert_eq!(
+            "transactions.csv line 3 (byte 38): Transaction parse error: Missing amount",
+            err.to_string()
+        );
+
+        let err = reader.next().unwrap().unwrap_err();
+        assert!(matches!(err.error(), CSVReaderError::CSVParseError(_)));
+        assert_eq!(4, err.location().unwrap().line());
+        assert_eq!(
+            Some("unknown,2,3,1.0"),
+            err.location().unwrap().raw_record()
+        );
+        // The position is only reported once
+        assert_eq!(1, err.to_string().matches("line").count());
+    }
+
+    #[test]
+    fn test_parse_idempotency_keys() {
+        let data = "type,client,tx,amount,idempotency_key\n\
+                    deposit,1,1,1.0,a1\n\
+                    dispute,1,1,,\n";
+
+        let mut reader = CSVReader::new(data.as_bytes());
+        let items: Vec<_> = reader.read_with_diagnostics().collect();
+        let transactions: Vec<_> = items
+            .into_iter()
+            .map(|item| match item {
+                CSVReadItem::Record { transaction, .. } => transaction.unwrap(),
+                item => panic!("Expected record, got {:?}", item),
+            })
+            .collect();
+
+        assert_eq!(
+            vec![
+                Transaction::new(
+                    1,
+                    1,
+                    TransactionType::Deposit {
+                        amount: CurrencyAmount::from_str("1.0").unwrap()
+                    }
+                )
+                .with_idempotency_key("a1"),
+                Transaction::new(1, 1, TransactionType::Dispute),
+            ],
+            transactions
+        );
+    }
+
+    #[test]
+    fn test_parse_client_keys() {
+        let data = "type,client,tx,amount\n\
+                    deposit,3f2b9c1e-6d4a-4f7b-9a8e-1c2d3e4f5a6b,1,1.5\n\
+                    deposit,007,2,2\n\
+                    dispute,,1,\n";
+
+        let mut reader = CSVReader::new(data.as_bytes()).with_client_keys();
+        let transactions = reader.read().collect::<Vec<_>>();
+        assert_eq!(
+            Ok(Some("3f2b9c1e-6d4a-4f7b-9a8e-1c2d3e4f5a6b")),
+            transactions[0].as_ref().map(Transaction::client_key)
+        );
+        // Keys which look like IDs are kept verbatim
+        assert_eq!(
+            Ok(Some("007")),
+            transactions[1].as_ref().map(Transaction::client_key)
+        );
+        assert!(matches!(
+            transactions[2].as_ref().unwrap_err().error(),
+            CSVReaderError::CSVParseError(_)
+        ));
+
+        // Keys are reported in place of the IDs of their accounts
+        let mut transaction_processor = TransactionProcessor::new();
+        for transaction in transactions.iter().take(2) {
+            transaction_processor
+                .transact(transaction.as_ref().unwrap())
+                .unwrap();
+        }
+        let mut writer = CSVWriter::new(Vec::new());
+        for entry in
+            transaction_processor.generate_report(&ReportFilter::new(), ReportOrder::ClientId)
+        {
+            writer.write(entry).unwrap();
+        }
+        assert_eq!(
+            "client,available,held,total,locked\n\
+             3f2b9c1e-6d4a-4f7b-9a8e-1c2d3e4f5a6b,1.5,0,1.5,false\n\
+             007,2,0,2,false\n",
+            String::from_utf8(writer.into_inner().unwrap()).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_parse_timestamps() {
+        let data = "type,client,tx,amount,timestamp\n\
+                    dispute,1,1,,2024-03-01T12:30:00+01:00\n\
+                    dispute,1,2,,\n\
+                    dispute,1,3,,01/03/2024\n";
+
+        let mut reader = CSVReader::new(data.as_bytes());
+        let mut reader = reader.read();
+        assert_eq!(
+            Transaction::new(1, 1, TransactionType::Dispute)
+                .with_timestamp(Timestamp::from_unix_seconds(1_709_292_600).unwrap()),
+            reader.next().unwrap().unwrap()
+        );
+        assert_eq!(
+            Transaction::new(1, 2, TransactionType::Dispute),
+            reader.next().unwrap().unwrap()
+        );
+        assert_eq!(
+            &CSVReaderError::TransactionParseError(EntryConvertError::InvalidTimestamp(
+                TimestampParseError::InvalidFormat
+            )),
+            reader.next().unwrap().unwrap_err().error()
+        );
+    }
+
+    #[test]
+    fn test_diagnostics() {
+        let data = "type,client,tx,amount,note\n\
+                    deposit,1,1,1.0,\n\
+                    deposit,1,2,1.0,,extra\n\
+                    deposit,1,3,,\n";
+
+        let mut reader = CSVReader::new(data.as_bytes()).with_source_name("transactions.csv");
+        let items: Vec<_> = reader.read_with_diagnostics().collect();
+        assert_eq!(5, items.len());
+
+        let warning = match &items[0] {
+            CSVReadItem::Diagnostic(diagnostic) => diagnostic,
+            item => panic!("Expected diagnostic, got {:?}", item),
+        };
+        assert_eq!(Severity::Warning, warning.severity());
+        assert_eq!(1, warning.location().unwrap().line());
+        assert_eq!(
+            "warning: transactions.csv line 1 (byte 0): Unknown column 'note' is ignored",
+            warning.to_string()
+        );
+
+        assert!(matches!(
+            &items[1],
+            CSVReadItem::Record {
+                transaction: Ok(_),
+                ..
+            }
+        ));
+
+        let warning = match &items[2] {
+            CSVReadItem::Diagnostic(diagnostic) => diagnostic,
+            item => panic!("Expected diagnostic, got {:?}", item),
+        };
+        assert_eq!(Severity::Warning, warning.severity());
+        assert_eq!(3, warning.location().unwrap().line());
+        assert!(warning.message().starts_with("Row has 6 fields"));
+
+        assert!(matches!(
+            &items[3],
+            CSVReadItem::Record {
+                transaction: Ok(_),
+                ..
+            }
+        ));
+
+        let error = match &items[4] {
+            CSVReadItem::Record {
+                transaction: Err(err),
+                ..
+            } => Diagnostic::from(err),
+            item => panic!("Expected error, got {:?}", item),
+        };
+        assert_eq!(Severity::Error, error.severity());
+        assert_eq!(4, error.location().unwrap().line());
+        assert_eq!("Transaction parse error: Missing amount", error.message());
+
+        // Warnings are not returned by the other methods
+        let mut reader = CSVReader::new(data.as_bytes());
+        assert_eq!(3, reader.read_with_records().count());
+    }
+
+    #[cfg(feature = "crypto")]
+    #[test]
+    fn test_signatures() {
+        use crate::crypto::HmacKey;
+        use crate::io::csv::SignatureError;
+
+        let key = HmacKey::new("row signing key").unwrap();
+        let deposit = key.sign(b"deposit,1,1,1.5");
+        let dispute = key.sign(b"dispute,1,1,");
+        let data = format!(
+            "type,client,tx,amount,signature\n\
+             deposit,1,1,1.5,{deposit}\n\
+             dispute,1,1,,{dispute}\n\
+             deposit,1,2,1.5,{deposit}\n\
+             deposit,1,3,1.5\n"
+        );
+
+        let results: Vec<_> = CSVReader::new(data.as_bytes())
+            .with_signature_key(key.clone())
+            .read_with_diagnostics()
+            .collect();
+        let errors: Vec<_> = results
+            .iter()
+            .map(|item| match item {
+                CSVReadItem::Record { transaction, .. } => {
+                    transaction.as_ref().err().map(|err| err.error.clone())
+                }
+                item => panic!("Expected record, got {:?}", item),
+            })
+            .collect();
+        assert_eq!(
+            vec![
+                None,
+                None,
+                Some(CSVReaderError::SignatureError(SignatureError::Mismatch)),
+                Some(CSVReaderError::SignatureError(SignatureError::Missing)),
+            ],
+            errors
+        );
+
+        // Signatures use the standard column names and order
+        let data = format!("amount,signature,tx,client,type\n1.5,{deposit},1,1,deposit\n");
+        let mut reader = CSVReader::new(data.as_bytes()).with_signature_key(key.clone());
+        assert!(reader.read().next().unwrap().is_ok());
+
+        let data = format!("deposit,1,1,1.5,{deposit}\n");
+        let mut reader = CSVReader::new_headerless(data.as_bytes()).with_signature_key(key);
+        assert_eq!(1, reader.read_with_diagnostics().count());
+    }
+
+    #[test]
+    fn test_read_report() {
+        let mut transaction_processor = TransactionProcessor::new();
+        for transaction in [
+            Transaction::new(
+                1,
+                1,
+                TransactionType::Deposit {
+                    amount: CurrencyAmount::from_str("1.5").unwrap(),
+                },
+            ),
+            Transaction::new(
+                2,
+                2,
+                TransactionType::Deposit {
+                    amount: CurrencyAmount::from_str("2").unwrap(),
+                },
+            ),
+            Transaction::new(2, 2, TransactionType::Dispute),
+            Transaction::new(2, 2, TransactionType::Chargeback),
+        ] {
+            transaction_processor.transact(&transaction).unwrap();
+        }
+        let report = transaction_processor
+            .generate_report(&ReportFilter::new(), ReportOrder::ClientId)
+            .collect::<Vec<_>>();
+
+        let mut writer = CSVWriter::new(Vec::new());
+        for entry in &report {
+            writer.write(entry.clone().with_scale(4)).unwrap();
+        }
+        let data = writer.into_inner().unwrap();
+
+        let mut reader = ReportReader::new(data.as_slice());
+        assert_eq!(
+            report,
+            reader.read().collect::<Result<Vec<_>, _>>().unwrap()
+        );
+
+        let data = "client,available,held,total,locked\n1,x,0,0,false\n";
+        let mut reader = ReportReader::new(data.as_bytes());
+        let mut entries = reader.read();
+        assert!(matches!(
+            entries.next(),
+            Some(Err(CSVReaderError::CSVParseError(_)))
+        ));
+        assert_eq!(None, entries.next());
+    }
+}
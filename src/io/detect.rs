@@ -0,0 +1,170 @@
+use std::ffi::OsStr;
+use std::io::{self, BufRead};
+use std::path::Path;
+
+/// The magic bytes at the start of an Avro object container file.
+const AVRO_MAGIC: &[u8] = b"Obj\x01";
+/// The magic bytes at the start of a Parquet file.
+const PARQUET_MAGIC: &[u8] = b"PAR1";
+/// The UTF-8 byte order mark, which some tools write at the start of text
+/// files.
+const UTF8_BOM: &[u8] = b"\xef\xbb\xbf";
+
+/// The format of an input file, as detected by [`detect_format`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum DetectedFormat {
+    /// CSV, with fields separated by commas.
+    Csv,
+    /// Tab-separated values, with the same columns as CSV.
+    Tsv,
+    /// JSON Lines, with one transaction object per line.
+    Jsonl,
+    /// An Avro object container file.
+    Avro,
+    /// A Parquet file.
+    Parquet,
+}
+
+impl DetectedFormat {
+    /// Detects the format from the file extension: `.csv`, `.tsv` (or
+    /// `.tab`), `.jsonl` (or `.ndjson`), `.avro` and `.parquet`, ignoring
+    /// case. A trailing `.gz` or `.zst` extension is skipped, as compressed
+    /// files are decompressed first, and for URLs, so is any query string.
+    /// Returns `None` if the extension is not recognised.
+    pub fn from_path(path: &str) -> Option<Self> {
+        let path = match path.split_once("://") {
+            Some(_) => Path::new(path.split(['?', '#']).next().unwrap_or_default()),
+            None => Path::new(path),
+        };
+        let mut extension = path.extension().and_then(OsStr::to_str)?;
+        if extension.eq_ignore_ascii_case("gz") || extension.eq_ignore_ascii_case("zst") {
+            extension = Path::new(path.file_stem()?)
+                .extension()
+                .and_then(OsStr::to_str)?;
+        }
+
+        [
+            ("csv", DetectedFormat::Csv),
+            ("tsv", DetectedFormat::Tsv),
+            ("tab", DetectedFormat::Tsv),
+            ("jsonl", DetectedFormat::Jsonl),
+            ("ndjson", DetectedFormat::Jsonl),
+            ("avro", DetectedFormat::Avro),
+            ("parquet", DetectedFormat::Parquet),
+        ]
+        .into_iter()
+        .find(|(name, _)| extension.eq_ignore_ascii_case(name))
+        .map(|(_, format)| format)
+    }
+
+    /// Detects the format from the start of the (decompressed) data: Avro
+    /// and Parquet files by their magic bytes, JSON Lines if the first
+    /// non-blank line starts with `{`, and otherwise TSV if that line has
+    /// more tabs than commas, or CSV. Returns `None` if there are no
+    /// non-blank lines to go by.
+    pub fn from_content(data: &[u8]) -> Option<Self> {
+        if data.starts_with(AVRO_MAGIC) {
+            return Some(DetectedFormat::Avro);
+        }
+        if data.starts_with(PARQUET_MAGIC) {
+            return Some(DetectedFormat::Parquet);
+        }
+
+        let data = data.strip_prefix(UTF8_BOM).unwrap_or(data);
+        let line = data
+            .split(|byte| *byte == b'\n')
+            .map(<[u8]>::trim_ascii)
+            .find(|line| !line.is_empty())?;
+        let count = |separator| line.iter().filter(|byte| **byte == separator).count();
+        Some(if line.starts_with(b"{") {
+            DetectedFormat::Jsonl
+        } else if count(b'\t') > count(b',') {
+            DetectedFormat::Tsv
+        } else {
+            DetectedFormat::Csv
+        })
+    }
+
+    /// The separator of the fields of a CSV or TSV file, or `None` for the
+    /// other formats.
+    #[must_use]
+    pub const fn delimiter(self) -> Option<u8> {
+        match self {
+            DetectedFormat::Csv => Some(b','),
+            DetectedFormat::Tsv => Some(b'\t'),
+            _ => None,
+        }
+    }
+}
+
+/// Detects the format of the input at `path`, from its extension (see
+/// [`DetectedFormat::from_path`]), or if that isn't recognised, from the
+/// start of the data in `reader` (see [`DetectedFormat::from_content`]),
+/// which is left unread. Inputs which give nothing to go by are CSV.
+///
+/// `reader` should already be decompressed, e.g. by
+/// [`crate::io::decompress_input`], which detects gzip and zstd compressed
+/// files by their magic bytes.
+///
+/// # Errors
+///
+/// Returns an error if the start of the data could not be read.
+pub fn detect_format(path: &str, reader: &mut impl BufRead) -> io::Result<DetectedFormat> {
+    if let Some(format) = DetectedFormat::from_path(path) {
+        return Ok(format);
+    }
+    Ok(DetectedFormat::from_content(reader.fill_buf()?).unwrap_or(DetectedFormat::Csv))
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::BufRead;
+
+    use crate::io::detect::{detect_format, DetectedFormat};
+
+    #[test]
+    fn test_detect_format() {
+        assert_eq!(
+            Some(DetectedFormat::Tsv),
+            DetectedFormat::from_path("in/tx.TSV.gz")
+        );
+        assert_eq!(
+            Some(DetectedFormat::Jsonl),
+            DetectedFormat::from_path("https://example.com/tx.ndjson?token=a.csv")
+        );
+        assert_eq!(None, DetectedFormat::from_path("in/transactions"));
+        assert_eq!(None, DetectedFormat::from_path("in/transactions.gz"));
+
+        let detect = |data: &str| DetectedFormat::from_content(data.as_bytes());
+        assert_eq!(
+            Some(DetectedFormat::Csv),
+            detect("\u{feff}type,client,tx,amount\ndeposit,1,1,1.0\n")
+        );
+        assert_eq!(
+            Some(DetectedFormat::Tsv),
+            detect("type\tclient\ttx\tamount\ndeposit\t1\t1\t1,0\n")
+        );
+        assert_eq!(
+            Some(DetectedFormat::Jsonl),
+            detect("\n  {\"type\": \"deposit\", \"client\": 1, \"tx\": 1, \"amount\": \"1\"}")
+        );
+        assert_eq!(Some(DetectedFormat::Avro), detect("Obj\u{1}..."));
+        assert_eq!(None, detect(" \n\r\n"));
+
+        // The extension takes precedence, and the data is left unread
+        let mut reader = "{\"type\": \"dispute\", \"client\": 1, \"tx\": 1}".as_bytes();
+        assert_eq!(
+            DetectedFormat::Csv,
+            detect_format("tx.csv", &mut reader).unwrap()
+        );
+        assert_eq!(
+            DetectedFormat::Jsonl,
+            detect_format("tx", &mut reader).unwrap()
+        );
+        assert!(reader.fill_buf().unwrap().starts_with(b"{"));
+        assert_eq!(
+            DetectedFormat::Csv,
+            detect_format("tx", &mut "".as_bytes()).unwrap()
+        );
+    }
+}
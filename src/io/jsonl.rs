@@ -0,0 +1,335 @@
+use std::fmt::{Display, Formatter};
+use std::io;
+
+use serde::Serialize;
+
+use crate::io::{AmountFormat, EntryConvertError, RawEntry};
+use crate::report::ReportSink;
+use crate::source::{SourceError, TransactionSource};
+use crate::{ReportEntry, Transaction};
+
+/// Error returned when reading a transaction from a JSON Lines file.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum JsonlReaderError {
+    /// The line could not be read.
+    ReadError(String),
+    /// The line is not valid JSON, or does not have the expected fields.
+    JsonParseError(String),
+    /// The line is valid JSON, but is not a valid transaction.
+    TransactionParseError(EntryConvertError),
+}
+
+impl Display for JsonlReaderError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&match self {
+            JsonlReaderError::ReadError(err) => format!("Read error: {}", err),
+            JsonlReaderError::JsonParseError(err) => format!("JSON parse error: {}", err),
+            JsonlReaderError::TransactionParseError(err) => {
+                format!("Transaction parse error: {}", err)
+            }
+        })
+    }
+}
+
+impl From<JsonlReaderError> for SourceError {
+    fn from(err: JsonlReaderError) -> Self {
+        SourceError::new(err.to_string())
+    }
+}
+
+/// Reads transactions from JSON Lines (NDJSON), with one JSON object per
+/// line in the same representation as [`Transaction`]'s serde support, e.g.
+/// `{"type":"deposit","client":1,"tx":2,"amount":"1.5"}`.
+///
+/// Amounts must be strings, and are parsed according to the reader's
+/// [`AmountFormat`]. Blank lines are ignored.
+pub struct JsonlReader<R: io::BufRead> {
+    reader: R,
+    amount_format: AmountFormat,
+}
+
+impl<R: io::BufRead> JsonlReader<R> {
+    /// Creates a reader for JSON Lines data.
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            amount_format: AmountFormat::Standard,
+        }
+    }
+
+    /// Sets how amounts are parsed. Defaults to [`AmountFormat::Standard`].
+    #[must_use]
+    pub fn with_amount_format(mut self, amount_format: AmountFormat) -> Self {
+        self.amount_format = amount_format;
+        self
+    }
+
+    /// Returns an iterator over the transactions in the data. Invalid lines
+    /// result in an error, but do not end the iteration.
+    pub fn read(&mut self) -> impl Iterator<Item = Result<Transaction, JsonlReaderError>> + '_ {
+        self.read_with_lines().map(|(_, transaction)| transaction)
+    }
+
+    /// Like [`JsonlReader::read`], but also returns each line verbatim, e.g.
+    /// for recording rejected lines. The line is empty if it could not be
+    /// read.
+    pub fn read_with_lines(
+        &mut self,
+    ) -> impl Iterator<Item = (String, Result<Transaction, JsonlReaderError>)> + '_ {
+        self.read_with_line_numbers()
+            .map(|(_, line, transaction)| (line, transaction))
+    }
+
+    /// Like [`JsonlReader::read_with_lines`], but also returns the 1-based
+    /// number of each line, counting blank lines, e.g. for reporting where
+    /// a transaction failed.
+    pub fn read_with_line_numbers(
+        &mut self,
+    ) -> impl Iterator<Item = (usize, String, Result<Transaction, JsonlReaderError>)> + '_ {
+        let amount_format = self.amount_format;
+        numbered_lines(&mut self.reader).map(move |(number, line)| {
+            let (line, transaction) = parse_line(line, amount_format);
+            (number, line, transaction)
+        })
+    }
+}
+
+/// Reads the lines one at a time, as [`JsonlReader::read_with_lines`] does,
+/// with each line as the row's only field.
+impl<R: io::BufRead> TransactionSource for JsonlReader<R> {
+    fn next(&mut self) -> Option<Result<Transaction, SourceError>> {
+        self.next_row().map(|(_, transaction)| transaction)
+    }
+
+    fn next_row(&mut self) -> Option<(Vec<String>, Result<Transaction, SourceError>)> {
+        let (_, line) = numbered_lines(&mut self.reader).next()?;
+        let (line, transaction) = parse_line(line, self.amount_format);
+        Some((vec![line], transaction.map_err(SourceError::from)))
+    }
+}
+
+/// The lines of `reader` with their 1-based numbers, skipping blank lines.
+fn numbered_lines(reader: impl io::BufRead) -> impl Iterator<Item = (usize, io::Result<String>)> {
+    (1..)
+        .zip(reader.lines())
+        .filter(|(_, line)| !matches!(line, Ok(line) if line.trim().is_empty()))
+}
+
+/// Parses a line read from a JSON Lines file, returning it with the
+/// transaction. The line is empty if it could not be read.
+fn parse_line(
+    line: io::Result<String>,
+    amount_format: AmountFormat,
+) -> (String, Result<Transaction, JsonlReaderError>) {
+    match line {
+        Ok(line) => {
+            let transaction = serde_json::from_str::<RawEntry>(&line)
+                .map_err(|err| JsonlReaderError::JsonParseError(format!("{}", err)))
+                .and_then(|entry| {
+                    entry
+                        .into_transaction(amount_format)
+                        .map_err(JsonlReaderError::TransactionParseError)
+                });
+
+            (line, transaction)
+        }
+        Err(err) => (
+            String::new(),
+            Err(JsonlReaderError::ReadError(format!("{}", err))),
+        ),
+    }
+}
+
+/// Writes records as JSON Lines, one JSON object per line, e.g. a report as
+/// `{"client":1,"available":"1.5","held":"0","total":"1.5","locked":false}`.
+///
+/// Lines are buffered, and written out when the buffer fills, when
+/// [`JsonlWriter::flush`] is called, or by [`JsonlWriter::into_inner`].
+pub struct JsonlWriter<W: io::Write> {
+    writer: io::BufWriter<W>,
+}
+
+impl<W: io::Write> JsonlWriter<W> {
+    /// Creates a writer outputting to the specified destination.
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer: io::BufWriter::new(writer),
+        }
+    }
+
+    /// Writes a single record as a line.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the record could not be serialized or written.
+    pub fn write(&mut self, record: impl Serialize) -> io::Result<()> {
+        serde_json::to_writer(&mut self.writer, &record)?;
+        io::Write::write_all(&mut self.writer, b"\n")
+    }
+
+    /// Writes out any buffered lines.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the buffered lines could not be written.
+    pub fn flush(&mut self) -> io::Result<()> {
+        io::Write::flush(&mut self.writer)
+    }
+
+    /// Flushes any buffered lines, and returns the destination.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the buffered lines could not be written.
+    pub fn into_inner(self) -> io::Result<W> {
+        self.writer
+            .into_inner()
+            .map_err(io::IntoInnerError::into_error)
+    }
+}
+
+impl<W: io::Write> ReportSink for JsonlWriter<W> {
+    fn write_entry(&mut self, entry: ReportEntry) -> io::Result<()> {
+        self.write(entry)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        JsonlWriter::flush(self)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+
+    use crate::io::jsonl::{JsonlReader, JsonlReaderError, JsonlWriter};
+    use crate::io::{AmountFormat, EntryConvertError};
+    use crate::numeric::{CurrencyAmount, CurrencyAmountParseError};
+    use crate::report::ReportWriter;
+    use crate::{Transaction, TransactionProcessor, TransactionType};
+
+    #[test]
+    fn test_parse() {
+        let data = r###"
+            {"type": "deposit", "client": 1, "tx": 1, "amount": "1.0"}
+            {"type": "withdrawal", "client": 2, "tx": 5, "amount": "3.0"}
+
+            {"type": "dispute", "client": 7, "tx": 10}
+            {"type": "resolve", "client": 8, "tx": 11, "amount": null}
+            {"type": "chargeback", "client": 9, "tx": 12}
+        "###;
+
+        let mut reader = JsonlReader::new(data.as_bytes());
+        let transactions: Vec<_> = reader.read().map(Result::unwrap).collect();
+
+        assert_eq!(
+            vec![
+                Transaction::new(
+                    1,
+                    1,
+                    TransactionType::Deposit {
+                        amount: CurrencyAmount::from_str("1.0").unwrap()
+                    }
+                ),
+                Transaction::new(
+                    2,
+                    5,
+                    TransactionType::Withdrawal {
+                        amount: CurrencyAmount::from_str("3.0").unwrap()
+                    }
+                ),
+                Transaction::new(7, 10, TransactionType::Dispute),
+                Transaction::new(8, 11, TransactionType::Resolve),
+                Transaction::new(9, 12, TransactionType::Chargeback),
+            ],
+            transactions
+        );
+
+        // Blank lines are skipped, but still counted
+        let mut reader = JsonlReader::new(data.as_bytes());
+        let numbers: Vec<_> = reader
+            .read_with_line_numbers()
+            .map(|(number, _, _)| number)
+            .collect();
+        assert_eq!(vec![2, 3, 5, 6, 7], numbers);
+    }
+
+    #[test]
+    fn test_parse_fail() {
+        let data = r###"
+            {"type": "deposit", "client": 1, "tx": 1, "amount": "1.0"}
+            {"type": "unknown", "client": 2, "tx": 5, "amount": "3.0"}
+            not json
+            {"type": "deposit", "client": 1, "tx": 1}
+            {"type": "deposit", "client": 1, "tx": 1, "amount": "+1"}
+            {"type": "chargeback", "client": 9, "tx": 12}
+        "###;
+
+        let mut reader = JsonlReader::new(data.as_bytes()).with_amount_format(AmountFormat::Strict);
+        let mut reader = reader.read();
+
+        assert!(reader.next().unwrap().is_ok());
+
+        for _ in 0..2 {
+            assert!(matches!(
+                reader.next().unwrap(),
+                Err(JsonlReaderError::JsonParseError(_))
+            ));
+        }
+
+        assert_eq!(
+            Err(JsonlReaderError::TransactionParseError(
+                EntryConvertError::MissingAmount
+            )),
+            reader.next().unwrap()
+        );
+
+        assert_eq!(
+            Err(JsonlReaderError::TransactionParseError(
+                EntryConvertError::InvalidAmount(CurrencyAmountParseError::InvalidNumericValue)
+            )),
+            reader.next().unwrap()
+        );
+
+        assert_eq!(
+            Transaction::new(9, 12, TransactionType::Chargeback),
+            reader.next().unwrap().unwrap()
+        );
+
+        assert!(reader.next().is_none());
+    }
+
+    #[test]
+    fn test_write_report() {
+        let mut transaction_processor = TransactionProcessor::new();
+        for (client, tx) in [(2, 1), (1, 2)] {
+            transaction_processor
+                .transact(&Transaction::new(
+                    client,
+                    tx,
+                    TransactionType::Deposit {
+                        amount: CurrencyAmount::from_str("1.5").unwrap(),
+                    },
+                ))
+                .unwrap();
+        }
+        transaction_processor
+            .transact(&Transaction::new(2, 1, TransactionType::Dispute))
+            .unwrap();
+
+        let mut writer = ReportWriter::new(JsonlWriter::new(Vec::new())).with_flush_interval(1);
+        for entry in transaction_processor.generate_report_as_vec() {
+            writer.write(entry).unwrap();
+        }
+        let output = writer.finish().unwrap().into_inner().unwrap();
+        assert_eq!(
+            concat!(
+                r#"{"client":1,"available":"1.5","held":"0","total":"1.5","locked":false}"#,
+                "\n",
+                r#"{"client":2,"available":"0.0","held":"1.5","total":"1.5","locked":false}"#,
+                "\n",
+            ),
+            String::from_utf8(output).unwrap()
+        );
+    }
+}
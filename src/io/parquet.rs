@@ -0,0 +1,545 @@
+use std::fmt::{Display, Formatter};
+use std::fs::File;
+use std::io;
+use std::sync::Arc;
+
+use arrow_array::cast::AsArray;
+use arrow_array::types::ArrowPrimitiveType;
+#[cfg(feature = "wide-ids")]
+use arrow_array::types::UInt64Type;
+#[cfg(not(feature = "wide-ids"))]
+use arrow_array::types::{UInt16Type, UInt32Type};
+use arrow_array::{Array, ArrayRef, BooleanArray, Decimal128Array, PrimitiveArray, RecordBatch};
+use arrow_schema::{DataType, Field, Schema};
+use parquet::arrow::arrow_reader::{ParquetRecordBatchReader, ParquetRecordBatchReaderBuilder};
+use parquet::arrow::ArrowWriter;
+
+use crate::flat::FlatTransactionType;
+use crate::io::{AmountFormat, EntryConvertError, RawEntry};
+use crate::numeric::CurrencyAmount;
+use crate::{ReportEntry, Transaction};
+
+/// The number of report entries written per record batch.
+const WRITE_BATCH_SIZE: usize = 65_536;
+
+/// The Arrow type of client IDs, matching [`crate::ClientId`].
+#[cfg(not(feature = "wide-ids"))]
+type ClientIdType = UInt16Type;
+/// The Arrow type of client IDs, matching [`crate::ClientId`].
+#[cfg(feature = "wide-ids")]
+type ClientIdType = UInt64Type;
+/// The Arrow type of transaction IDs, matching [`crate::TransactionId`].
+#[cfg(not(feature = "wide-ids"))]
+type TransactionIdType = UInt32Type;
+/// The Arrow type of transaction IDs, matching [`crate::TransactionId`].
+#[cfg(feature = "wide-ids")]
+type TransactionIdType = UInt64Type;
+
+/// The maximum precision of an Arrow `Decimal128` column.
+const DECIMAL128_MAX_PRECISION: u8 = 38;
+
+/// Error returned when reading transactions from a Parquet file.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum ParquetReaderError {
+    /// The file could not be read, or is not valid Parquet.
+    ReadError(String),
+    /// A required column is missing, or cannot be converted to the expected
+    /// type.
+    SchemaError(String),
+    /// A row has a missing or invalid value.
+    InvalidRow(String),
+    /// The row is not a valid transaction.
+    TransactionParseError(EntryConvertError),
+}
+
+impl Display for ParquetReaderError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&match self {
+            ParquetReaderError::ReadError(err) => format!("Parquet read error: {}", err),
+            ParquetReaderError::SchemaError(err) => format!("Parquet schema error: {}", err),
+            ParquetReaderError::InvalidRow(err) => format!("Invalid row: {}", err),
+            ParquetReaderError::TransactionParseError(err) => {
+                format!("Transaction parse error: {}", err)
+            }
+        })
+    }
+}
+
+/// Error returned when writing a report to a Parquet file.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum ParquetWriterError {
+    /// An amount cannot be represented as a `Decimal128` at the report's
+    /// scale.
+    AmountOutOfRange(CurrencyAmount),
+    /// The report could not be encoded or written.
+    WriteError(String),
+}
+
+impl Display for ParquetWriterError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&match self {
+            ParquetWriterError::AmountOutOfRange(amount) => {
+                format!("Amount {} is out of range for Parquet output", amount)
+            }
+            ParquetWriterError::WriteError(err) => format!("Parquet write error: {}", err),
+        })
+    }
+}
+
+/// Reads transactions from a Parquet file with the columns `type`, `client`,
+/// `tx`, and `amount`.
+///
+/// The `client` and `tx` columns may be any integer type whose values fit in
+/// a [`crate::ClientId`] and [`crate::TransactionId`] respectively. The
+/// `amount` column may be a string (parsed according to the reader's
+/// [`AmountFormat`]), a decimal, or a float, and may be null for disputes,
+/// resolutions, and chargebacks.
+pub struct ParquetReader {
+    batches: ParquetRecordBatchReader,
+    amount_format: AmountFormat,
+}
+
+impl ParquetReader {
+    /// Opens a Parquet file for reading.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file is not valid Parquet.
+    pub fn new(file: File) -> Result<Self, ParquetReaderError> {
+        let batches = ParquetRecordBatchReaderBuilder::try_new(file)
+            .and_then(ParquetRecordBatchReaderBuilder::build)
+            .map_err(|err| ParquetReaderError::ReadError(format!("{}", err)))?;
+
+        Ok(Self {
+            batches,
+            amount_format: AmountFormat::Standard,
+        })
+    }
+
+    /// Sets how string amounts are parsed. Defaults to
+    /// [`AmountFormat::Standard`].
+    #[must_use]
+    pub fn with_amount_format(mut self, amount_format: AmountFormat) -> Self {
+        self.amount_format = amount_format;
+        self
+    }
+
+    /// Returns an iterator over the transactions in the file. Invalid rows
+    /// result in an error, but do not end the iteration.
+    pub fn read(&mut self) -> impl Iterator<Item = Result<Transaction, ParquetReaderError>> + '_ {
+        let amount_format = self.amount_format;
+
+        (&mut self.batches).flat_map(move |batch| {
+            let columns = batch
+                .map_err(|err| ParquetReaderError::ReadError(format!("{}", err)))
+                .and_then(|batch| TransactionColumns::new(&batch));
+
+            match columns {
+                Ok(columns) => (0..columns.len())
+                    .map(|row| columns.transaction(row, amount_format))
+                    .collect(),
+                Err(err) => vec![Err(err)],
+            }
+        })
+    }
+}
+
+/// The columns of a record batch of transactions, converted to the types
+/// used by [`Transaction`].
+struct TransactionColumns {
+    transaction_type: ArrayRef,
+    client: ArrayRef,
+    tx: ArrayRef,
+    amount: Option<ArrayRef>,
+}
+
+impl TransactionColumns {
+    fn new(batch: &RecordBatch) -> Result<Self, ParquetReaderError> {
+        let column = |name: &str, data_type: &DataType| {
+            batch
+                .column_by_name(name)
+                .map(|column| {
+                    arrow_cast::cast(column, data_type).map_err(|err| {
+                        ParquetReaderError::SchemaError(format!("Column '{}': {}", name, err))
+                    })
+                })
+                .transpose()
+        };
+        let required_column = |name: &str, data_type: &DataType| {
+            column(name, data_type)?.ok_or_else(|| {
+                ParquetReaderError::SchemaError(format!("Missing column '{}'", name))
+            })
+        };
+
+        Ok(Self {
+            transaction_type: required_column("type", &DataType::Utf8)?,
+            client: required_column("client", &ClientIdType::DATA_TYPE)?,
+            tx: required_column("tx", &TransactionIdType::DATA_TYPE)?,
+            amount: column("amount", &DataType::Utf8)?,
+        })
+    }
+
+    fn len(&self) -> usize {
+        self.client.len()
+    }
+
+    fn transaction(
+        &self,
+        row: usize,
+        amount_format: AmountFormat,
+    ) -> Result<Transaction, ParquetReaderError> {
+        // Casting replaces values which don't fit the target type with nulls
+        let invalid = |column| {
+            ParquetReaderError::InvalidRow(format!(
+                "Missing or invalid '{}' in row {}",
+                column, row
+            ))
+        };
+
+        let transaction_type = self.transaction_type.as_string::<i32>();
+        if transaction_type.is_null(row) {
+            return Err(invalid("type"));
+        }
+        let transaction_type = match transaction_type.value(row).to_lowercase().as_str() {
+            "deposit" => FlatTransactionType::Deposit,
+            "withdrawal" => FlatTransactionType::Withdrawal,
+            "dispute" => FlatTransactionType::Dispute,
+            "resolve" => FlatTransactionType::Resolve,
+            "chargeback" => FlatTransactionType::Chargeback,
+            _ => return Err(invalid("type")),
+        };
+
+        let client = self.client.as_primitive::<ClientIdType>();
+        let tx = self.tx.as_primitive::<TransactionIdType>();
+        if client.is_null(row) {
+            return Err(invalid("client"));
+        }
+        if tx.is_null(row) {
+            return Err(invalid("tx"));
+        }
+
+        let amount = self
+            .amount
+            .as_ref()
+            .map(|amount| amount.as_string::<i32>())
+            .filter(|amount| amount.is_valid(row))
+            .map(|amount| amount.value(row).to_string());
+
+        RawEntry {
+            transaction_type,
+            client: client.value(row),
+            tx: tx.value(row),
+            amount,
+            idempotency_key: None,
+            sequence: None,
+            timestamp: None,
+        }
+        .into_transaction(amount_format)
+        .map_err(ParquetReaderError::TransactionParseError)
+    }
+}
+
+/// Writes a report as Parquet, with the columns `client`, `available`,
+/// `held`, `total`, and `locked`.
+///
+/// Amounts are written as `Decimal128` with a fixed scale. If `scale` is not
+/// specified, the largest scale of any amount in the report is used, so that
+/// no precision is lost.
+///
+/// # Errors
+///
+/// Returns an error if an amount does not fit in a `Decimal128` at the
+/// chosen scale, or if the report could not be written.
+pub fn write_report(
+    writer: impl io::Write + Send,
+    entries: &[ReportEntry],
+    scale: Option<u32>,
+) -> Result<(), ParquetWriterError> {
+    let scale = scale.unwrap_or_else(|| {
+        entries
+            .iter()
+            .flat_map(|entry| [entry.available, entry.held, entry.total])
+            .map(|amount| amount.as_decimal().scale())
+            .max()
+            .unwrap_or(0)
+    });
+    let decimal_type = DataType::Decimal128(
+        DECIMAL128_MAX_PRECISION,
+        i8::try_from(scale)
+            .map_err(|_| ParquetWriterError::WriteError(format!("Invalid scale {}", scale)))?,
+    );
+
+    let schema = Arc::new(Schema::new(vec![
+        Field::new("client", ClientIdType::DATA_TYPE, false),
+        Field::new("available", decimal_type.clone(), false),
+        Field::new("held", decimal_type.clone(), false),
+        Field::new("total", decimal_type.clone(), false),
+        Field::new("locked", DataType::Boolean, false),
+    ]));
+
+    let write_error = |err: &dyn Display| ParquetWriterError::WriteError(format!("{}", err));
+
+    let mut writer =
+        ArrowWriter::try_new(writer, schema.clone(), None).map_err(|err| write_error(&err))?;
+
+    for chunk in entries.chunks(WRITE_BATCH_SIZE) {
+        let decimal_column = |amount: fn(&ReportEntry) -> CurrencyAmount| {
+            let values = chunk
+                .iter()
+                .map(|entry| to_decimal128(amount(entry), scale))
+                .collect::<Result<Vec<_>, _>>()?;
+
+            Ok::<ArrayRef, ParquetWriterError>(Arc::new(
+                Decimal128Array::from(values).with_data_type(decimal_type.clone()),
+            ))
+        };
+
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(PrimitiveArray::<ClientIdType>::from_iter_values(
+                    chunk.iter().map(|entry| entry.client),
+                )),
+                decimal_column(|entry| entry.available)?,
+                decimal_column(|entry| entry.held)?,
+                decimal_column(|entry| entry.total)?,
+                Arc::new(BooleanArray::from_iter(
+                    chunk.iter().map(|entry| Some(entry.locked)),
+                )),
+            ],
+        )
+        .map_err(|err| write_error(&err))?;
+
+        writer.write(&batch).map_err(|err| write_error(&err))?;
+    }
+
+    writer.close().map_err(|err| write_error(&err))?;
+
+    Ok(())
+}
+
+/// Converts an amount to the unscaled integer value of a `Decimal128` with
+/// the specified scale, rounding if the amount has more decimal places.
+fn to_decimal128(amount: CurrencyAmount, scale: u32) -> Result<i128, ParquetWriterError> {
+    let rescaled = amount.with_scale(scale).as_decimal();
+    let out_of_range = || ParquetWriterError::AmountOutOfRange(amount);
+
+    // Rescaling uses a smaller scale if the result would overflow
+    let multiplier = 10_i128
+        .checked_pow(scale - rescaled.scale())
+        .ok_or_else(out_of_range)?;
+    let value = rescaled
+        .mantissa()
+        .checked_mul(multiplier)
+        .ok_or_else(out_of_range)?;
+
+    if value.unsigned_abs() >= 10_u128.pow(u32::from(DECIMAL128_MAX_PRECISION)) {
+        return Err(out_of_range());
+    }
+
+    Ok(value)
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs::File;
+    use std::path::PathBuf;
+    use std::str::FromStr;
+    use std::sync::Arc;
+
+    use arrow_array::cast::AsArray;
+    use arrow_array::types::Decimal128Type;
+    use arrow_array::{Decimal128Array, Int64Array, RecordBatch, StringArray};
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+    use parquet::arrow::ArrowWriter;
+
+    use crate::io::parquet::{write_report, ClientIdType, ParquetReader, ParquetReaderError};
+    use crate::io::EntryConvertError;
+    use crate::numeric::CurrencyAmount;
+    use crate::{Transaction, TransactionProcessor, TransactionType};
+
+    /// Returns a path in the temporary directory that is unique to the test.
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!(
+            "transaction-processor-{}-{}.parquet",
+            name,
+            std::process::id()
+        ))
+    }
+
+    fn write_batch(name: &str, batch: &RecordBatch) -> PathBuf {
+        let path = temp_path(name);
+        let mut writer =
+            ArrowWriter::try_new(File::create(&path).unwrap(), batch.schema(), None).unwrap();
+        writer.write(batch).unwrap();
+        writer.close().unwrap();
+        path
+    }
+
+    #[test]
+    fn test_read() {
+        let batch = RecordBatch::try_from_iter([
+            (
+                "type",
+                Arc::new(StringArray::from(vec![
+                    "deposit",
+                    "withdrawal",
+                    "dispute",
+                    "deposit",
+                    "deposit",
+                    "unknown",
+                ])) as _,
+            ),
+            (
+                "client",
+                Arc::new(Int64Array::from(vec![1, 1, 1, -1, 2, 2])) as _,
+            ),
+            (
+                "tx",
+                Arc::new(Int64Array::from(vec![1, 2, 1, 3, 4, 5])) as _,
+            ),
+            (
+                "amount",
+                Arc::new(StringArray::from(vec![
+                    Some("1.5"),
+                    Some("0.5"),
+                    None,
+                    Some("1.0"),
+                    None,
+                    Some("1.0"),
+                ])) as _,
+            ),
+        ])
+        .unwrap();
+        let path = write_batch("read", &batch);
+
+        let mut reader = ParquetReader::new(File::open(&path).unwrap()).unwrap();
+        let transactions = reader.read().collect::<Vec<_>>();
+        std::fs::remove_file(path).unwrap();
+
+        assert_eq!(
+            Ok(Transaction::new(
+                1,
+                1,
+                TransactionType::Deposit {
+                    amount: CurrencyAmount::from_str("1.5").unwrap()
+                }
+            )),
+            transactions[0]
+        );
+        assert_eq!(
+            Ok(Transaction::new(
+                1,
+                2,
+                TransactionType::Withdrawal {
+                    amount: CurrencyAmount::from_str("0.5").unwrap()
+                }
+            )),
+            transactions[1]
+        );
+        assert_eq!(
+            Ok(Transaction::new(1, 1, TransactionType::Dispute)),
+            transactions[2]
+        );
+        assert!(matches!(
+            transactions[3],
+            Err(ParquetReaderError::InvalidRow(_))
+        ));
+        assert_eq!(
+            Err(ParquetReaderError::TransactionParseError(
+                EntryConvertError::MissingAmount
+            )),
+            transactions[4]
+        );
+        assert!(matches!(
+            transactions[5],
+            Err(ParquetReaderError::InvalidRow(_))
+        ));
+    }
+
+    #[test]
+    fn test_read_decimal_amounts() {
+        let batch = RecordBatch::try_from_iter([
+            ("type", Arc::new(StringArray::from(vec!["deposit"])) as _),
+            ("client", Arc::new(Int64Array::from(vec![1])) as _),
+            ("tx", Arc::new(Int64Array::from(vec![1])) as _),
+            (
+                "amount",
+                Arc::new(
+                    Decimal128Array::from(vec![12_345])
+                        .with_precision_and_scale(10, 4)
+                        .unwrap(),
+                ) as _,
+            ),
+        ])
+        .unwrap();
+        let path = write_batch("decimal", &batch);
+
+        let mut reader = ParquetReader::new(File::open(&path).unwrap()).unwrap();
+        let transactions = reader.read().collect::<Vec<_>>();
+        std::fs::remove_file(path).unwrap();
+
+        assert_eq!(
+            vec![Ok(Transaction::new(
+                1,
+                1,
+                TransactionType::Deposit {
+                    amount: CurrencyAmount::from_str("1.2345").unwrap()
+                }
+            ))],
+            transactions
+        );
+    }
+
+    #[test]
+    fn test_write_report() {
+        let mut transaction_processor = TransactionProcessor::new();
+        for (client, tx, amount) in [(2, 1, "1.5"), (1, 2, "0.25")] {
+            transaction_processor
+                .transact(&Transaction::new(
+                    client,
+                    tx,
+                    TransactionType::Deposit {
+                        amount: CurrencyAmount::from_str(amount).unwrap(),
+                    },
+                ))
+                .unwrap();
+        }
+
+        let path = temp_path("report");
+        write_report(
+            File::create(&path).unwrap(),
+            &transaction_processor.generate_report_as_vec(),
+            None,
+        )
+        .unwrap();
+
+        let batches = ParquetRecordBatchReaderBuilder::try_new(File::open(&path).unwrap())
+            .unwrap()
+            .build()
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        std::fs::remove_file(path).unwrap();
+
+        let batch = &batches[0];
+        assert_eq!(2, batch.num_rows());
+        assert_eq!(
+            vec![1, 2],
+            batch
+                .column_by_name("client")
+                .unwrap()
+                .as_primitive::<ClientIdType>()
+                .values()
+                .to_vec()
+        );
+
+        // The scale is the largest of any amount in the report
+        let available = batch
+            .column_by_name("available")
+            .unwrap()
+            .as_primitive::<Decimal128Type>();
+        assert_eq!(2, available.scale());
+        assert_eq!(vec![25, 150], available.values().to_vec());
+    }
+}
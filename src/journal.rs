@@ -0,0 +1,167 @@
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex, PoisonError};
+
+use transaction_processor::io::csv::CSVWriter;
+use transaction_processor::report::StatementEntry;
+use transaction_processor::{Transaction, TransactionProcessor};
+
+use crate::args::InputFormat;
+use crate::output::{AtomicFile, ExistingOutput};
+
+/// The destination of the journal, in its format.
+enum JournalWriter {
+    // The CSV writer has a large internal buffer
+    Csv(Box<CSVWriter<AtomicFile>>),
+    Jsonl(BufWriter<AtomicFile>),
+}
+
+/// The state of a journal, which is shared by the threads of `--jobs`.
+struct JournalState {
+    /// `None` once the journal has been finished.
+    writer: Option<JournalWriter>,
+    /// The first error writing an entry, which is returned when finishing.
+    error: Option<io::Error>,
+}
+
+/// Writes every applied transaction, with the resulting balances, to the
+/// `--journal` file. Cloning the journal shares the same file.
+///
+/// The file is only moved into place by [`Journal::finish`], so a failed run
+/// leaves no partial journal behind.
+#[derive(Clone)]
+pub struct Journal(Arc<Mutex<JournalState>>);
+
+impl std::fmt::Debug for Journal {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Journal(..)")
+    }
+}
+
+impl Journal {
+    /// Creates the journal file at `path`, replacing it if it exists. The
+    /// journal is JSON Lines if `path` has a `.jsonl` or `.ndjson`
+    /// extension, and CSV otherwise.
+    pub fn create(path: &Path) -> io::Result<Self> {
+        let file = AtomicFile::create(path, ExistingOutput::Overwrite)?;
+        let writer = match InputFormat::from_path(&path.to_string_lossy()) {
+            InputFormat::Jsonl => JournalWriter::Jsonl(BufWriter::new(file)),
+            _ => JournalWriter::Csv(Box::new(CSVWriter::new(file))),
+        };
+
+        Ok(Self(Arc::new(Mutex::new(JournalState {
+            writer: Some(writer),
+            error: None,
+        }))))
+    }
+
+    /// Records `transaction`, which has just been applied to
+    /// `transaction_processor`, with its account's balances (formatted to
+    /// `output_scale` decimal places, if set).
+    pub fn record(
+        &self,
+        transaction_processor: &TransactionProcessor,
+        transaction: &Transaction,
+        output_scale: Option<u32>,
+    ) {
+        let Some(account) = transaction_processor
+            .transaction_client(transaction)
+            .and_then(|client| transaction_processor.account_report(client))
+        else {
+            return;
+        };
+        let entry = StatementEntry::new(transaction, &account);
+        let entry = match output_scale {
+            Some(scale) => entry.with_scale(scale),
+            None => entry,
+        };
+
+        let mut state = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+        if state.error.is_some() {
+            return;
+        }
+        let result = match &mut state.writer {
+            Some(JournalWriter::Csv(writer)) => writer
+                .write(entry)
+                .map_err(|err| io::Error::other(err.to_string())),
+            Some(JournalWriter::Jsonl(writer)) => serde_json::to_writer(&mut *writer, &entry)
+                .map_err(io::Error::from)
+                .and_then(|()| writer.write_all(b"\n")),
+            None => Ok(()),
+        };
+        if let Err(err) = result {
+            log::error!("Failed to write journal entry: {}", err);
+            state.error = Some(err);
+        }
+    }
+
+    /// Flushes the journal, and moves the file into place. Returns the first
+    /// error writing an entry, if any.
+    pub fn finish(&self) -> io::Result<()> {
+        let mut state = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(err) = state.error.take() {
+            return Err(err);
+        }
+
+        let file = match state.writer.take() {
+            Some(JournalWriter::Csv(writer)) => (*writer)
+                .into_inner()
+                .map_err(|err| io::Error::other(err.to_string()))?,
+            Some(JournalWriter::Jsonl(writer)) => writer.into_inner().map_err(io::Error::from)?,
+            None => return Ok(()),
+        };
+        file.commit()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+
+    use transaction_processor::numeric::CurrencyAmount;
+    use transaction_processor::{Transaction, TransactionProcessor, TransactionType};
+
+    use crate::journal::Journal;
+    use crate::test::TempDir;
+
+    #[test]
+    fn test_journal() {
+        let deposit = Transaction::new(
+            1,
+            1,
+            TransactionType::Deposit {
+                amount: CurrencyAmount::from_str("2.5").unwrap(),
+            },
+        );
+        let dispute = Transaction::new(1, 1, TransactionType::Dispute);
+
+        for (extension, expected) in [
+            (
+                "csv",
+                "client,tx,type,amount,available,held,total,locked\n\
+                 1,1,deposit,2.50,2.50,0.00,2.50,false\n\
+                 1,1,dispute,,0.00,2.50,2.50,false\n",
+            ),
+            (
+                "jsonl",
+                "{\"client\":1,\"tx\":1,\"type\":\"deposit\",\"amount\":\"2.50\",\"available\":\"2.50\",\"held\":\"0.00\",\"total\":\"2.50\",\"locked\":false}\n\
+                 {\"client\":1,\"tx\":1,\"type\":\"dispute\",\"amount\":null,\"available\":\"0.00\",\"held\":\"2.50\",\"total\":\"2.50\",\"locked\":false}\n",
+            ),
+        ] {
+            let directory = TempDir::new("journal");
+            let path = directory.join(&format!("journal.{}", extension));
+            let journal = Journal::create(&path).unwrap();
+
+            let mut transaction_processor = TransactionProcessor::new();
+            for transaction in [&deposit, &dispute] {
+                transaction_processor.transact(transaction).unwrap();
+                journal.record(&transaction_processor, transaction, Some(2));
+            }
+
+            // Nothing is visible until the journal is finished
+            assert!(!path.exists());
+            journal.finish().unwrap();
+            assert_eq!(expected, std::fs::read_to_string(&path).unwrap());
+        }
+    }
+}
@@ -0,0 +1,411 @@
+use std::collections::btree_map;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use kafka::consumer::{Consumer, FetchOffset, GroupOffsetStorage};
+#[cfg(feature = "avro")]
+use transaction_processor::io::avro::AvroReader;
+use transaction_processor::pipeline;
+use transaction_processor::snapshot::StreamPositions;
+use transaction_processor::summary::BatchResult;
+use transaction_processor::{Transaction, TransactionError, TransactionProcessor};
+
+use crate::output::{AtomicFile, ExistingOutput};
+use crate::{
+    apply_transactions, decode_snapshot, encode_snapshot, replace_report, ProcessOptions,
+    TransactionProcessorCLIError,
+};
+
+/// The encoding of each Kafka message.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum KafkaPayload {
+    /// One or more transactions in the JSON Lines input format.
+    #[default]
+    Json,
+    /// An Avro object container file, using the published transaction
+    /// schema.
+    #[cfg(feature = "avro")]
+    Avro,
+}
+
+/// Where and how to consume transactions from Kafka.
+#[derive(Clone, Debug)]
+pub struct KafkaOptions {
+    /// The bootstrap brokers, as `host:port`.
+    pub brokers: Vec<String>,
+    pub topic: String,
+    /// The consumer group, which the committed offsets belong to.
+    pub group: String,
+    pub payload: KafkaPayload,
+    /// How often the snapshot and report are written, and offsets committed.
+    pub checkpoint_interval: Duration,
+    /// The snapshot file, which is restored from on startup (if it exists)
+    /// and written at every checkpoint.
+    pub snapshot: Option<PathBuf>,
+}
+
+/// Consumes transactions from a Kafka topic and applies them continuously.
+///
+/// Every `checkpoint_interval`, the snapshot (if any) is written, then the
+/// offsets of the messages applied so far are committed, then the report is
+/// written to `output` (or stdout) if anything has changed. As the snapshot
+/// is restored on startup, a consumer which is restarted resumes from the
+/// last checkpoint without applying any message twice: the snapshot records
+/// the offset reached in each partition, so messages which are delivered
+/// again because their offsets weren't committed before the consumer
+/// stopped are skipped. Without a snapshot, the restarted consumer still
+/// resumes from the committed offsets, but with empty accounts.
+///
+/// Messages which fail to be read or applied are skipped (and their offsets
+/// committed) like rejected rows. With `options.fail_fast`, the consumer
+/// instead stops at the first failure, without committing anything since
+/// the last checkpoint.
+///
+/// Only returns on a fatal error.
+pub fn consume_kafka(
+    transaction_processor: TransactionProcessor,
+    kafka: &KafkaOptions,
+    options: &ProcessOptions,
+    output: Option<&Path>,
+) -> Result<BatchResult, TransactionProcessorCLIError> {
+    let (mut transaction_processor, mut positions) = match &kafka.snapshot {
+        Some(path) => restore(transaction_processor, path, options)?,
+        None => (transaction_processor, StreamPositions::new()),
+    };
+
+    let kafka_error =
+        |err: kafka::Error| TransactionProcessorCLIError::KafkaFailed(err.to_string());
+    let mut consumer = Consumer::from_hosts(kafka.brokers.clone())
+        .with_topic(kafka.topic.clone())
+        .with_group(kafka.group.clone())
+        .with_fallback_offset(FetchOffset::Earliest)
+        .with_offset_storage(Some(GroupOffsetStorage::Kafka))
+        .create()
+        .map_err(kafka_error)?;
+    log::info!("Consuming topic {} as group {}", kafka.topic, kafka.group);
+
+    let mut summary = BatchResult::new();
+    let mut changed = false;
+    let mut last_checkpoint = Instant::now();
+
+    loop {
+        for message_set in consumer.poll().map_err(kafka_error)?.iter() {
+            let stream = format!("{}/{}", message_set.topic(), message_set.partition());
+            for message in message_set.messages() {
+                let source = format!("{}@{}", stream, message.offset);
+                if !advance(&mut positions, &stream, message.offset) {
+                    log::debug!("Skipping message {}, which is in the snapshot", source);
+                    continue;
+                }
+                apply_message(
+                    |transaction| {
+                        let result = options.transact(&mut transaction_processor, transaction);
+                        changed |= result.is_ok();
+                        result
+                    },
+                    &source,
+                    kafka.payload,
+                    message.value,
+                    options,
+                    &mut summary,
+                )?;
+            }
+
+            consumer
+                .consume_messageset(message_set)
+                .map_err(kafka_error)?;
+        }
+
+        if last_checkpoint.elapsed() >= kafka.checkpoint_interval {
+            if let Some(path) = &kafka.snapshot {
+                save(&transaction_processor, &positions, path, options)?;
+            }
+            consumer.commit_consumed().map_err(kafka_error)?;
+            if changed {
+                replace_report(&transaction_processor, options, output)?;
+                changed = false;
+            }
+            last_checkpoint = Instant::now();
+        }
+    }
+}
+
+/// Reads the transactions in a message, applying each using `transact` (see
+/// [`apply_transactions`]). `source` identifies the message in errors.
+fn apply_message(
+    transact: impl FnMut(&Transaction) -> Result<(), TransactionError>,
+    source: &str,
+    payload: KafkaPayload,
+    data: &[u8],
+    options: &ProcessOptions,
+    summary: &mut BatchResult,
+) -> Result<(), TransactionProcessorCLIError> {
+    summary.record_file();
+
+    match payload {
+        KafkaPayload::Json => apply_transactions(
+            transact,
+            source,
+            pipeline::jsonl_rows(&mut pipeline::jsonl_reader(data, &options.pipeline)),
+            options,
+            0,
+            &mut None,
+            summary,
+        ),
+        #[cfg(feature = "avro")]
+        KafkaPayload::Avro => match AvroReader::new(data) {
+            Ok(reader) => apply_transactions(
+                transact,
+                source,
+                pipeline::numbered_rows(
+                    reader
+                        .with_amount_format(options.pipeline.amount_format())
+                        .read()
+                        .map(|transaction| (Vec::new(), transaction)),
+                ),
+                options,
+                0,
+                &mut None,
+                summary,
+            ),
+            Err(err) => {
+                log::error!(
+                    target: pipeline::PARSE_LOG_TARGET,
+                    "Got error '{}' reading message {}. Skipping.",
+                    err,
+                    source
+                );
+                summary.record_parse_error();
+                if options.pipeline.fail_fast() {
+                    return Err(TransactionProcessorCLIError::InvalidInputFile {
+                        path: source.to_string(),
+                        error: err.to_string(),
+                    });
+                }
+                Ok(())
+            }
+        },
+    }
+}
+
+/// Records that the message at `offset` of `stream` (a topic's partition, as
+/// `topic/partition`) has been read. Returns false if it was before the
+/// stream's position, so had already been applied.
+fn advance(positions: &mut StreamPositions, stream: &str, offset: i64) -> bool {
+    // Offsets are never negative
+    let next = u64::try_from(offset).map_or(0, |offset| offset.saturating_add(1));
+    match positions.entry(stream.to_string()) {
+        btree_map::Entry::Occupied(entry) if *entry.get() >= next => false,
+        btree_map::Entry::Occupied(mut entry) => {
+            entry.insert(next);
+            true
+        }
+        btree_map::Entry::Vacant(entry) => {
+            entry.insert(next);
+            true
+        }
+    }
+}
+
+/// Restores the accounts and stream positions in the snapshot at `path`, if
+/// it exists, keeping the options of `transaction_processor`.
+fn restore(
+    transaction_processor: TransactionProcessor,
+    path: &Path,
+    options: &ProcessOptions,
+) -> Result<(TransactionProcessor, StreamPositions), TransactionProcessorCLIError> {
+    let snapshot_error = |error: String| TransactionProcessorCLIError::SnapshotFailed {
+        path: path.display().to_string(),
+        error,
+    };
+
+    let file = match std::fs::File::open(path) {
+        Ok(file) => file,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+            return Ok((transaction_processor, StreamPositions::new()))
+        }
+        Err(err) => return Err(snapshot_error(err.to_string())),
+    };
+
+    let (restored, _, positions) = decode_snapshot(std::io::BufReader::new(file), options)
+        .map_err(|err| snapshot_error(err.to_string()))?;
+    log::info!("Restored snapshot {}", path.display());
+
+    // The restored processor has default options, so the accounts are moved
+    // into one with the configured options
+    let mut transaction_processor = transaction_processor.new_like();
+    transaction_processor
+        .merge(restored)
+        .map_err(|err| snapshot_error(err.to_string()))?;
+    Ok((transaction_processor, positions))
+}
+
+/// Replaces the snapshot at `path` with the current state and stream
+/// positions.
+fn save(
+    transaction_processor: &TransactionProcessor,
+    positions: &StreamPositions,
+    path: &Path,
+    options: &ProcessOptions,
+) -> Result<(), TransactionProcessorCLIError> {
+    let snapshot_error = |error: String| TransactionProcessorCLIError::SnapshotFailed {
+        path: path.display().to_string(),
+        error,
+    };
+
+    let mut file = AtomicFile::create(path, ExistingOutput::Overwrite)
+        .map_err(|err| snapshot_error(err.to_string()))?;
+    let mut writer = std::io::BufWriter::new(&mut file);
+    encode_snapshot(transaction_processor, &[], positions, options, &mut writer)
+        .map_err(|err| snapshot_error(err.to_string()))?;
+    writer
+        .flush()
+        .map_err(|err| snapshot_error(err.to_string()))?;
+    drop(writer);
+    file.commit().map_err(|err| snapshot_error(err.to_string()))
+}
+
+#[cfg(test)]
+mod test {
+    use transaction_processor::numeric::PrecisionPolicy;
+    use transaction_processor::pipeline::PipelineOptions;
+    use transaction_processor::report::{ReportFilter, ReportOrder};
+    #[cfg(feature = "encryption")]
+    use transaction_processor::snapshot::SnapshotKey;
+    use transaction_processor::snapshot::StreamPositions;
+    use transaction_processor::summary::BatchResult;
+    use transaction_processor::TransactionProcessor;
+
+    use crate::kafka::{advance, apply_message, restore, save, KafkaPayload};
+    use crate::test::TempDir;
+    use crate::{ProcessOptions, TransactionProcessorCLIError};
+
+    const MESSAGE: &[u8] = b"{\"type\":\"deposit\",\"client\":1,\"tx\":1,\"amount\":\"2.5\"}\n\
+                             {\"type\":\"withdrawal\",\"client\":1,\"tx\":2,\"amount\":\"9\"}\n";
+
+    #[test]
+    fn test_apply_message() {
+        let mut transaction_processor = TransactionProcessor::new();
+        let mut summary = BatchResult::new();
+
+        apply_message(
+            |transaction| transaction_processor.transact(transaction),
+            "transactions/0@0",
+            KafkaPayload::Json,
+            MESSAGE,
+            &ProcessOptions::default(),
+            &mut summary,
+        )
+        .unwrap();
+        assert_eq!(1, summary.transactions_applied());
+        assert_eq!(1, summary.total_rejects());
+
+        let result = apply_message(
+            |transaction| transaction_processor.transact(transaction),
+            "transactions/0@1",
+            KafkaPayload::Json,
+            MESSAGE,
+            &ProcessOptions {
+                pipeline: PipelineOptions::new().with_fail_fast(true),
+                ..ProcessOptions::default()
+            },
+            &mut summary,
+        );
+        assert!(matches!(
+            result,
+            Err(TransactionProcessorCLIError::TransactionFailed { path, record: 1, .. })
+                if path == "transactions/0@1"
+        ));
+    }
+
+    #[test]
+    fn test_advance() {
+        let mut positions = StreamPositions::new();
+        assert!(advance(&mut positions, "transactions/0", 0));
+        assert!(advance(&mut positions, "transactions/0", 5));
+        assert!(advance(&mut positions, "transactions/1", 2));
+        assert_eq!(Some(&6), positions.get("transactions/0"));
+
+        // Messages before the position were already applied
+        assert!(!advance(&mut positions, "transactions/0", 5));
+        assert!(!advance(&mut positions, "transactions/0", 3));
+        assert!(advance(&mut positions, "transactions/0", 6));
+    }
+
+    #[test]
+    fn test_snapshot_checkpoint() {
+        let directory = TempDir::new("kafka");
+        let path = directory.join("kafka.snapshot");
+
+        // Without a snapshot, the processor is used as is
+        let (mut transaction_processor, mut positions) = restore(
+            TransactionProcessor::new(),
+            &path,
+            &ProcessOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            0,
+            transaction_processor
+                .generate_report(&ReportFilter::new(), ReportOrder::ClientId)
+                .count()
+        );
+        assert!(positions.is_empty());
+
+        assert!(advance(&mut positions, "transactions/0", 0));
+        apply_message(
+            |transaction| transaction_processor.transact(transaction),
+            "transactions/0@0",
+            KafkaPayload::Json,
+            MESSAGE,
+            &ProcessOptions::default(),
+            &mut BatchResult::new(),
+        )
+        .unwrap();
+        save(
+            &transaction_processor,
+            &positions,
+            &path,
+            &ProcessOptions::default(),
+        )
+        .unwrap();
+
+        // The restored consumer skips the message if it is delivered again
+        let (restored, mut restored_positions) = restore(
+            TransactionProcessor::new().with_precision_policy(PrecisionPolicy::Reject),
+            &path,
+            &ProcessOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            transaction_processor.generate_report_as_vec(),
+            restored.generate_report_as_vec()
+        );
+        assert_eq!(positions, restored_positions);
+        assert!(!advance(&mut restored_positions, "transactions/0", 0));
+
+        // An encrypted snapshot can only be restored with the key
+        #[cfg(feature = "encryption")]
+        {
+            let options = ProcessOptions {
+                snapshot_key: Some(SnapshotKey::generate()),
+                ..ProcessOptions::default()
+            };
+            save(&transaction_processor, &positions, &path, &options).unwrap();
+            assert!(matches!(
+                restore(
+                    TransactionProcessor::new(),
+                    &path,
+                    &ProcessOptions::default()
+                ),
+                Err(TransactionProcessorCLIError::SnapshotFailed { .. })
+            ));
+            let (restored, _) = restore(TransactionProcessor::new(), &path, &options).unwrap();
+            assert_eq!(
+                transaction_processor.generate_report_as_vec(),
+                restored.generate_report_as_vec()
+            );
+        }
+    }
+}
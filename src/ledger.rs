@@ -0,0 +1,444 @@
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+
+#[cfg(feature = "serde")]
+use serde::ser::SerializeStruct;
+#[cfg(feature = "serde")]
+use serde::{Serialize, Serializer};
+
+use crate::numeric::{CurrencyAmount, CurrencyError};
+use crate::{
+    ClientId, Transaction, TransactionError, TransactionId, TransactionProcessor, TransactionType,
+};
+
+/// An account in the ledger.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum LedgerAccount {
+    /// The funds available to a client.
+    Available(ClientId),
+    /// The funds held from a client by disputes, i.e. in suspense.
+    Held(ClientId),
+    /// The internal cash account, which funds enter by deposits and leave by
+    /// withdrawals and chargebacks.
+    Cash,
+}
+
+impl LedgerAccount {
+    /// The name of the account, as used when postings are exported (e.g.
+    /// `"available"`). The client is exported separately.
+    #[must_use]
+    pub const fn name(&self) -> &'static str {
+        match self {
+            LedgerAccount::Available(_) => "available",
+            LedgerAccount::Held(_) => "held",
+            LedgerAccount::Cash => "cash",
+        }
+    }
+}
+
+/// One side of a transaction in the ledger.
+///
+/// Positive amounts are credits and negative amounts are debits, so the
+/// balances of a client's accounts match the report, and the balance of the
+/// cash account is the negative of all funds owed to clients. The postings
+/// of every transaction sum to zero.
+///
+/// It is serialized with the fields `tx`, `client` (the client whose
+/// transaction it is, including for the cash account), `account` (the
+/// [`LedgerAccount::name`]) and `amount`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Posting {
+    tx: TransactionId,
+    client: ClientId,
+    account: LedgerAccount,
+    amount: CurrencyAmount,
+}
+
+impl Posting {
+    /// The ID of the transaction.
+    #[must_use]
+    pub const fn tx(&self) -> TransactionId {
+        self.tx
+    }
+
+    /// The ID of the client whose transaction it is.
+    #[must_use]
+    pub const fn client(&self) -> ClientId {
+        self.client
+    }
+
+    /// The account posted to.
+    #[must_use]
+    pub const fn account(&self) -> LedgerAccount {
+        self.account
+    }
+
+    /// The amount credited (if positive) or debited (if negative).
+    #[must_use]
+    pub const fn amount(&self) -> CurrencyAmount {
+        self.amount
+    }
+
+    /// Returns this posting with the amount formatted to exactly `scale`
+    /// decimal places (see [`CurrencyAmount::with_scale`]).
+    #[must_use]
+    pub fn with_scale(self, scale: u32) -> Self {
+        Self {
+            amount: self.amount.with_scale(scale),
+            ..self
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl Serialize for Posting {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut state = serializer.serialize_struct("Posting", 4)?;
+        state.serialize_field("tx", &self.tx)?;
+        state.serialize_field("client", &self.client)?;
+        state.serialize_field("account", self.account.name())?;
+        state.serialize_field("amount", &self.amount)?;
+        state.end()
+    }
+}
+
+/// Error returned when the books don't balance (see [`Ledger::check`]).
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum LedgerError {
+    /// The postings don't sum to zero, but to this amount.
+    Unbalanced(CurrencyAmount),
+    /// The balance of the account differs from the processor's state.
+    Mismatch(LedgerAccount),
+    /// A balance overflowed.
+    CurrencyError(CurrencyError),
+}
+
+impl Display for LedgerError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LedgerError::Unbalanced(amount) => {
+                write!(f, "The ledger is out of balance by {}", amount)
+            }
+            LedgerError::Mismatch(LedgerAccount::Cash) => {
+                f.write_str("The cash account does not match the client accounts")
+            }
+            LedgerError::Mismatch(
+                account @ (LedgerAccount::Available(client) | LedgerAccount::Held(client)),
+            ) => write!(
+                f,
+                "The {} funds of client {} do not match the ledger",
+                account.name(),
+                client
+            ),
+            LedgerError::CurrencyError(err) => write!(f, "Ledger balance error: {}", err),
+        }
+    }
+}
+
+impl From<CurrencyError> for LedgerError {
+    fn from(err: CurrencyError) -> Self {
+        Self::CurrencyError(err)
+    }
+}
+
+/// A double-entry representation of the transactions applied to a
+/// processor: every transaction is posted to two accounts, the client's
+/// available or held funds and the internal cash account, so that the books
+/// always balance.
+///
+/// | Transaction | Debit | Credit |
+/// | --- | --- | --- |
+/// | Deposit | Cash | Available |
+/// | Withdrawal | Available | Cash |
+/// | Dispute | Available | Held |
+/// | Resolve | Held | Available |
+/// | Chargeback | Held | Cash |
+///
+/// (A dispute of a withdrawal holds a negative amount, so swaps the sides.)
+///
+/// Transactions must be applied through the ledger, from when the processor
+/// is created, for its balances to match.
+#[derive(Clone, Debug, Default)]
+pub struct Ledger {
+    postings: Vec<Posting>,
+}
+
+impl Ledger {
+    /// Creates an empty ledger.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Applies `transaction` to `transaction_processor`, and posts it if it
+    /// was applied.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error from [`TransactionProcessor::transact`], in which
+    /// case nothing is posted.
+    pub fn transact(
+        &mut self,
+        transaction_processor: &mut TransactionProcessor,
+        transaction: &Transaction,
+    ) -> Result<(), TransactionError> {
+        self.transact_with(
+            transaction_processor,
+            transaction,
+            TransactionProcessor::transact,
+        )
+    }
+
+    /// Like [`Ledger::transact`], but applies the transaction with `apply`,
+    /// which must call [`TransactionProcessor::transact`] (e.g. to also
+    /// observe the transaction).
+    ///
+    /// # Errors
+    ///
+    /// Returns the error from `apply`, in which case nothing is posted.
+    pub fn transact_with(
+        &mut self,
+        transaction_processor: &mut TransactionProcessor,
+        transaction: &Transaction,
+        apply: impl FnOnce(&mut TransactionProcessor, &Transaction) -> Result<(), TransactionError>,
+    ) -> Result<(), TransactionError> {
+        let stored_amount = |transaction_processor: &TransactionProcessor| {
+            transaction_processor
+                .clients
+                .get(&transaction.client)
+                .and_then(|account| account.transactions.get(&transaction.tx))
+                .copied()
+        };
+
+        // A chargeback forgets the transaction, so its amount is found first
+        let disputed = stored_amount(transaction_processor);
+        apply(transaction_processor, transaction)?;
+        // Deposits and withdrawals are stored as applied (e.g. after
+        // rounding), with withdrawals negative
+        let amount = match transaction.transaction_type {
+            TransactionType::Deposit { .. } | TransactionType::Withdrawal { .. } => {
+                stored_amount(transaction_processor)
+            }
+            TransactionType::Dispute | TransactionType::Resolve | TransactionType::Chargeback => {
+                disputed
+            }
+        };
+        // Always set once a transaction has been applied
+        let Some(amount) = amount else {
+            return Ok(());
+        };
+
+        let client = transaction.client;
+        let (debit, credit) = match transaction.transaction_type {
+            TransactionType::Deposit { .. } | TransactionType::Withdrawal { .. } => {
+                (LedgerAccount::Cash, LedgerAccount::Available(client))
+            }
+            TransactionType::Dispute => (
+                LedgerAccount::Available(client),
+                LedgerAccount::Held(client),
+            ),
+            TransactionType::Resolve => (
+                LedgerAccount::Held(client),
+                LedgerAccount::Available(client),
+            ),
+            TransactionType::Chargeback => (LedgerAccount::Held(client), LedgerAccount::Cash),
+        };
+        // Negating zero would give "-0"
+        let debit_amount = if amount == CurrencyAmount::ZERO {
+            amount
+        } else {
+            -amount
+        };
+        for (account, amount) in [(debit, debit_amount), (credit, amount)] {
+            self.postings.push(Posting {
+                tx: transaction.tx,
+                client,
+                account,
+                amount,
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Every posting, in the order the transactions were applied.
+    #[must_use]
+    pub fn postings(&self) -> &[Posting] {
+        &self.postings
+    }
+
+    /// The balance of every account with postings.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a balance overflows.
+    pub fn balances(&self) -> Result<BTreeMap<LedgerAccount, CurrencyAmount>, CurrencyError> {
+        let mut balances = BTreeMap::new();
+        for posting in &self.postings {
+            balances
+                .entry(posting.account)
+                .or_insert(CurrencyAmount::ZERO)
+                .checked_add_assign(posting.amount)?;
+        }
+        Ok(balances)
+    }
+
+    /// Checks that the books balance: that the postings sum to zero, and
+    /// that every client's balances match the accounts of
+    /// `transaction_processor`, which the transactions were applied to.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first discrepancy found.
+    pub fn check(&self, transaction_processor: &TransactionProcessor) -> Result<(), LedgerError> {
+        let balances = self.balances()?;
+        let sum = CurrencyAmount::checked_sum(balances.values().copied())?;
+        if sum != CurrencyAmount::ZERO {
+            return Err(LedgerError::Unbalanced(sum));
+        }
+
+        let balance = |account| {
+            balances
+                .get(&account)
+                .copied()
+                .unwrap_or(CurrencyAmount::ZERO)
+        };
+        for (client, client_account) in &transaction_processor.clients {
+            if balance(LedgerAccount::Available(*client)) != client_account.available {
+                return Err(LedgerError::Mismatch(LedgerAccount::Available(*client)));
+            }
+            if balance(LedgerAccount::Held(*client)) != client_account.held {
+                return Err(LedgerError::Mismatch(LedgerAccount::Held(*client)));
+            }
+        }
+
+        // Postings for clients without an account
+        match balances.keys().find(|account| match account {
+            LedgerAccount::Available(client) | LedgerAccount::Held(client) => {
+                !transaction_processor.clients.contains_key(client)
+            }
+            LedgerAccount::Cash => false,
+        }) {
+            Some(account) => Err(LedgerError::Mismatch(*account)),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+
+    use crate::ledger::{Ledger, LedgerAccount, LedgerError};
+    use crate::numeric::CurrencyAmount;
+    use crate::{Transaction, TransactionProcessor, TransactionType};
+
+    fn amount(value: &str) -> CurrencyAmount {
+        CurrencyAmount::from_str(value).unwrap()
+    }
+
+    #[test]
+    fn test_ledger() {
+        let mut transaction_processor = TransactionProcessor::new();
+        let mut ledger = Ledger::new();
+        for transaction in [
+            Transaction::new(
+                1,
+                1,
+                TransactionType::Deposit {
+                    amount: amount("5"),
+                },
+            ),
+            Transaction::new(
+                1,
+                2,
+                TransactionType::Withdrawal {
+                    amount: amount("1.5"),
+                },
+            ),
+            Transaction::new(
+                2,
+                3,
+                TransactionType::Deposit {
+                    amount: amount("2"),
+                },
+            ),
+            Transaction::new(1, 2, TransactionType::Dispute),
+            Transaction::new(1, 2, TransactionType::Resolve),
+            Transaction::new(2, 3, TransactionType::Dispute),
+            Transaction::new(2, 3, TransactionType::Chargeback),
+        ] {
+            ledger
+                .transact(&mut transaction_processor, &transaction)
+                .unwrap();
+        }
+        // Rejected transactions aren't posted
+        ledger
+            .transact(
+                &mut transaction_processor,
+                &Transaction::new(
+                    1,
+                    4,
+                    TransactionType::Withdrawal {
+                        amount: amount("10"),
+                    },
+                ),
+            )
+            .unwrap_err();
+
+        assert_eq!(14, ledger.postings().len());
+        let postings = &ledger.postings()[..2];
+        assert_eq!(
+            [
+                (1, 1, LedgerAccount::Cash, amount("-5")),
+                (1, 1, LedgerAccount::Available(1), amount("5")),
+            ],
+            [0, 1].map(|i| (
+                postings[i].tx(),
+                postings[i].client(),
+                postings[i].account(),
+                postings[i].amount()
+            ))
+        );
+
+        let balances = ledger.balances().unwrap();
+        assert_eq!(amount("-3.5"), balances[&LedgerAccount::Cash]);
+        assert_eq!(amount("3.5"), balances[&LedgerAccount::Available(1)]);
+        assert_eq!(amount("0"), balances[&LedgerAccount::Held(1)]);
+        assert_eq!(amount("0"), balances[&LedgerAccount::Available(2)]);
+        assert_eq!(amount("0"), balances[&LedgerAccount::Held(2)]);
+        ledger.check(&transaction_processor).unwrap();
+    }
+
+    #[test]
+    fn test_ledger_mismatch() {
+        let mut transaction_processor = TransactionProcessor::new();
+        let mut ledger = Ledger::new();
+        let deposit = Transaction::new(
+            1,
+            1,
+            TransactionType::Deposit {
+                amount: amount("5"),
+            },
+        );
+        ledger
+            .transact(&mut transaction_processor, &deposit)
+            .unwrap();
+
+        // A transaction applied without the ledger
+        transaction_processor
+            .transact(&Transaction::new(1, 1, TransactionType::Dispute))
+            .unwrap();
+        assert_eq!(
+            Err(LedgerError::Mismatch(LedgerAccount::Available(1))),
+            ledger.check(&transaction_processor)
+        );
+        assert_eq!(
+            Err(LedgerError::Mismatch(LedgerAccount::Available(1))),
+            ledger.check(&TransactionProcessor::new())
+        );
+    }
+}
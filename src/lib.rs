@@ -2,204 +2,1184 @@
 //! CLI frontend.
 //!
 //! See README.md for more details.
+//!
+//! Without the `std` feature, the transaction processor itself is not
+//! available, but the remaining types (including the `numeric` module)
+//! compile under `no_std` + `alloc`.
+//!
+//! Here and in the CLI, a lock poisoned by a panicking thread is recovered
+//! with `PoisonError::into_inner` rather than unwrapped. Shared state is only
+//! changed in single steps (a transaction is applied, or a counter updated,
+//! all at once), so it is still valid after the panic, and the remaining
+//! threads can carry on.
 
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
 #![deny(missing_docs)]
 
-use std::collections::hash_map::Entry;
-use std::collections::{btree_map, BTreeMap, HashMap, HashSet};
-use std::fmt::{Display, Formatter};
+extern crate alloc;
 
-use serde::Serialize;
+use alloc::format;
+use alloc::string::{String, ToString};
+use core::fmt::{Display, Formatter};
+#[cfg(feature = "std")]
+use std::collections::{btree_map, BTreeMap, HashMap, HashSet, VecDeque};
+#[cfg(feature = "std")]
+use std::sync::atomic::{AtomicUsize, Ordering};
+#[cfg(feature = "std")]
+use std::sync::Arc;
 
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "std")]
+use crate::numeric::PrecisionPolicy;
 use crate::numeric::{CurrencyAmount, CurrencyError};
+#[cfg(feature = "std")]
+use crate::reject::{RejectSink, RejectedRow};
+#[cfg(feature = "std")]
+use crate::report::{
+    ClientSet, DisputeEntry, LockedEntry, NegativeBalanceCause, NegativeBalanceEntry, ReportDiff,
+    ReportFilter, ReportOrder, ReportTotals, ReportVersion,
+};
+#[cfg(feature = "std")]
+use crate::summary::BatchResult;
+use crate::timestamp::Timestamp;
 
+/// Actor module: an async processor in which each client's account is owned
+/// by a task.
+#[cfg(feature = "actors")]
+pub mod actor;
+/// Aggregate module: per-period totals of the applied transactions.
+#[cfg(feature = "std")]
+pub mod aggregate;
+/// Alert module: balance thresholds which raise alerts when a transaction
+/// crosses them.
+#[cfg(feature = "std")]
+pub mod alert;
+/// Concurrent module: a processor which applies transactions from several
+/// threads at once.
+#[cfg(feature = "std")]
+pub mod concurrent;
+/// Crypto module: keys for signing and verifying data.
+#[cfg(feature = "crypto")]
+pub mod crypto;
+/// Cutoff module: end-of-day settlement batches, which defer the
+/// transactions after a cutoff time to the next batch.
+#[cfg(feature = "std")]
+pub mod cutoff;
+/// Export module: the versioned JSON structure of processor state, for
+/// inspecting and editing it by hand.
+#[cfg(feature = "jsonl")]
+pub mod export;
+/// FFI module: a C ABI for embedding the processor in other languages.
+#[cfg(feature = "ffi")]
+pub mod ffi;
+/// Flat module: the CSV-style serde representation of transactions.
+#[cfg(feature = "serde")]
+pub mod flat;
+/// Generate module: arbitrary transactions and transaction streams, for
+/// fuzzing and property tests.
+#[cfg(any(feature = "arbitrary", feature = "proptest"))]
+pub mod generate;
+/// Idempotency module: the outcomes of transactions by idempotency key.
+#[cfg(feature = "std")]
+pub mod idempotency;
+/// IO module: readers and writers for the supported file formats.
+#[cfg(any(
+    feature = "avro",
+    feature = "checksum",
+    feature = "compression",
+    feature = "csv",
+    feature = "jsonl",
+    feature = "parquet"
+))]
+pub mod io;
+/// Ledger module: double-entry postings of the applied transactions.
+#[cfg(feature = "std")]
+pub mod ledger;
+/// Metrics module: Prometheus metrics for applied and rejected transactions.
+#[cfg(feature = "metrics")]
+pub mod metrics;
+/// Middleware module: chains of steps wrapped around applying transactions.
+#[cfg(feature = "std")]
+pub mod middleware;
 /// Numeric module: contains currency-related types.
 pub mod numeric;
+/// Pipeline module: reading input files, applying their transactions and
+/// writing the report, as the CLI does.
+#[cfg(all(feature = "csv", feature = "jsonl"))]
+pub mod pipeline;
+/// Proto module: protobuf encodings of transactions and report entries.
+#[cfg(feature = "protobuf")]
+pub mod proto;
+/// Reject module: the rows which failed to be read or applied, and sinks
+/// for quarantining them.
+#[cfg(feature = "std")]
+pub mod reject;
+/// Report module: filters and orderings for the accounts in a report.
+pub mod report;
+/// Retry module: a queue of transactions which failed as they arrived early,
+/// retried once later transactions have been applied.
+#[cfg(feature = "std")]
+pub mod retry;
+/// Sequence module: gap and reordering detection for sequence numbers.
+#[cfg(feature = "std")]
+pub mod sequence;
+/// Snapshot module: compact binary snapshots of processor state.
+#[cfg(feature = "snapshot")]
+pub mod snapshot;
+/// Source module: the trait of feeds which transactions are read from.
+#[cfg(feature = "std")]
+pub mod source;
+/// Summary module: counts of what a batch run read, applied and rejected.
+#[cfg(feature = "std")]
+pub mod summary;
+/// Tenant module: isolated processors for several tenants in one instance.
+#[cfg(feature = "std")]
+pub mod tenant;
+/// Testkit module: seedable generators of realistic transaction streams, for
+/// benchmark and load test fixtures.
+#[cfg(feature = "testkit")]
+pub mod testkit;
+/// Timestamp module: points in time, as read from input files.
+pub mod timestamp;
+/// Wasm module: JavaScript bindings built with wasm-bindgen.
+#[cfg(feature = "wasm")]
+pub mod wasm;
 
 /// Error returned when a transaction could not be applied to an account.
+///
+/// The errors of a transaction for an account carry its client ID (and, where
+/// relevant, the transaction), so that the message of a logged error
+/// identifies what was rejected. With the `redact` feature, the amounts in
+/// the messages are masked.
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub enum TransactionError {
     /// The specified transaction does not exist for this user.
-    TransactionDoesNotExist(TransactionId),
+    TransactionDoesNotExist {
+        /// The client ID of the account.
+        client: ClientId,
+        /// The ID of the transaction.
+        tx: TransactionId,
+    },
     /// Cannot create a transaction with this ID, as it already exists
     /// for this user.
-    TransactionAlreadyExists(TransactionId),
+    TransactionAlreadyExists {
+        /// The client ID of the account.
+        client: ClientId,
+        /// The ID of the transaction.
+        tx: TransactionId,
+    },
     /// This transaction is already disputed for this user.
-    DisputeAlreadyExists(TransactionId),
+    DisputeAlreadyExists {
+        /// The client ID of the account.
+        client: ClientId,
+        /// The ID of the disputed transaction.
+        tx: TransactionId,
+    },
     /// This dispute cannot be resolved as the transaction is not disputed.
-    DisputeDoesNotExist(TransactionId),
+    DisputeDoesNotExist {
+        /// The client ID of the account.
+        client: ClientId,
+        /// The ID of the transaction.
+        tx: TransactionId,
+    },
     /// An arithmetic error occurred (overflow/underflow) when calculating the
     /// account balances.
     CurrencyError(CurrencyError),
     /// This account is locked and cannot deposit/withdraw money.
-    AccountIsLocked,
-    /// This withdrawal would take the account balance below zero.
-    NotEnoughFunds,
+    AccountIsLocked {
+        /// The client ID of the account.
+        client: ClientId,
+        /// The type (and amount) of the rejected transaction.
+        transaction_type: TransactionType,
+    },
+    /// This transaction would take the available funds below zero (or
+    /// further below, if they already are).
+    NotEnoughFunds {
+        /// The client ID of the account.
+        client: ClientId,
+        /// The type and amount of the rejected transaction, usually a
+        /// withdrawal.
+        transaction_type: TransactionType,
+        /// The available funds of the account.
+        available: CurrencyAmount,
+    },
+    /// This deposit or withdrawal is identical to one already applied, so it
+    /// was skipped as a replay (see
+    /// [`TransactionProcessor::with_idempotent_replays`]).
+    Replayed(TransactionId),
+    /// This deposit would take the account's total funds above the maximum
+    /// balance (see [`TransactionProcessor::with_max_balance`]).
+    BalanceCapExceeded {
+        /// The client ID of the account.
+        client: ClientId,
+        /// The amount of the rejected deposit.
+        amount: CurrencyAmount,
+        /// The maximum balance.
+        max_balance: CurrencyAmount,
+    },
+    /// The account already holds the maximum number of transactions, and
+    /// none could be evicted (see
+    /// [`TransactionProcessor::with_transaction_limit`]).
+    TransactionLimitReached {
+        /// The client ID of the account.
+        client: ClientId,
+    },
+    /// Withdrawals from the account are not allowed, as it is frozen (see
+    /// [`TransactionProcessor::freeze_account`]).
+    AccountIsFrozen {
+        /// The client ID of the account.
+        client: ClientId,
+        /// The amount of the rejected withdrawal.
+        amount: CurrencyAmount,
+    },
+    /// The transaction's dispute has already been settled, and the
+    /// processor's [`RedisputePolicy`] doesn't allow it to be disputed again.
+    DisputeNotAllowed {
+        /// The client ID of the account.
+        client: ClientId,
+        /// The ID of the transaction.
+        tx: TransactionId,
+    },
+    /// The client has no account, and the processor's
+    /// [`AccountCreationPolicy`] doesn't allow the transaction to create one.
+    UnknownClient(ClientId),
+    /// The transaction's client key hasn't been seen before, and every
+    /// client ID already has an account (see [`Transaction::with_client_key`]).
+    ClientIdsExhausted,
+}
+
+/// Describes a transaction type for an error message, e.g. `withdrawal of 5`
+/// or `dispute`.
+fn describe_transaction_type(transaction_type: &TransactionType) -> String {
+    match transaction_type {
+        TransactionType::Deposit { amount } | TransactionType::Withdrawal { amount } => {
+            format!("{} of {}", transaction_type.name(), amount.for_log())
+        }
+        _ => transaction_type.name().to_string(),
+    }
 }
 
 impl Display for TransactionError {
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
         f.write_str(&match self {
-            TransactionError::TransactionDoesNotExist(tx) => {
-                format!("Transaction {} does not exist", tx)
+            TransactionError::TransactionDoesNotExist { client, tx } => {
+                format!("Transaction {} does not exist for client {}", tx, client)
             }
-            TransactionError::TransactionAlreadyExists(tx) => {
-                format!("Transaction {} already exists", tx)
+            TransactionError::TransactionAlreadyExists { client, tx } => {
+                format!("Transaction {} already exists for client {}", tx, client)
             }
-            TransactionError::DisputeAlreadyExists(tx) => {
-                format!("Dispute {} already exists", tx)
+            TransactionError::DisputeAlreadyExists { client, tx } => {
+                format!("Dispute {} already exists for client {}", tx, client)
             }
-            TransactionError::DisputeDoesNotExist(tx) => {
-                format!("Dispute {} does not exist", tx)
+            TransactionError::DisputeDoesNotExist { client, tx } => {
+                format!("Dispute {} does not exist for client {}", tx, client)
             }
             TransactionError::CurrencyError(err) => {
                 format!("Currency error: {}", err)
             }
-            TransactionError::AccountIsLocked => "Account is locked".to_string(),
-            TransactionError::NotEnoughFunds => "Not enough funds".to_string(),
+            TransactionError::AccountIsLocked {
+                client,
+                transaction_type,
+            } => format!(
+                "Account is locked for client {} ({})",
+                client,
+                describe_transaction_type(transaction_type)
+            ),
+            TransactionError::NotEnoughFunds {
+                client,
+                transaction_type,
+                available,
+            } => format!(
+                "Not enough funds for client {} ({} with {} available)",
+                client,
+                describe_transaction_type(transaction_type),
+                available.for_log()
+            ),
+            TransactionError::Replayed(tx) => {
+                format!("Transaction {} is a replay", tx)
+            }
+            TransactionError::BalanceCapExceeded {
+                client,
+                amount,
+                max_balance,
+            } => format!(
+                "Balance cap exceeded for client {} (deposit of {} with a cap of {})",
+                client,
+                amount.for_log(),
+                max_balance.for_log()
+            ),
+            TransactionError::TransactionLimitReached { client } => {
+                format!("Transaction limit reached for client {}", client)
+            }
+            TransactionError::AccountIsFrozen { client, amount } => format!(
+                "Account is frozen for client {} (withdrawal of {})",
+                client,
+                amount.for_log()
+            ),
+            TransactionError::DisputeNotAllowed { client, tx } => format!(
+                "Transaction {} for client {} can't be disputed again",
+                tx, client
+            ),
+            TransactionError::UnknownClient(client) => format!("Unknown client {}", client),
+            TransactionError::ClientIdsExhausted => {
+                "No client IDs are left for a new client key".to_string()
+            }
         })
     }
 }
 
+impl TransactionError {
+    /// A short, stable name for the kind of error, independent of the
+    /// transaction it occurred for. Suitable for grouping errors in
+    /// summaries and metrics.
+    pub fn category(&self) -> &'static str {
+        match self {
+            TransactionError::TransactionDoesNotExist { .. } => "transaction_does_not_exist",
+            TransactionError::TransactionAlreadyExists { .. } => "transaction_already_exists",
+            TransactionError::DisputeAlreadyExists { .. } => "dispute_already_exists",
+            TransactionError::DisputeDoesNotExist { .. } => "dispute_does_not_exist",
+            TransactionError::CurrencyError(_) => "currency_error",
+            TransactionError::AccountIsLocked { .. } => "account_is_locked",
+            TransactionError::NotEnoughFunds { .. } => "not_enough_funds",
+            TransactionError::Replayed(_) => "replayed",
+            TransactionError::BalanceCapExceeded { .. } => "balance_cap_exceeded",
+            TransactionError::TransactionLimitReached { .. } => "transaction_limit_reached",
+            TransactionError::AccountIsFrozen { .. } => "account_is_frozen",
+            TransactionError::DisputeNotAllowed { .. } => "dispute_not_allowed",
+            TransactionError::UnknownClient(_) => "unknown_client",
+            TransactionError::ClientIdsExhausted => "client_ids_exhausted",
+        }
+    }
+
+    /// A stable, machine-readable code for the kind of error, e.g.
+    /// `INSUFFICIENT_FUNDS`, for systems to branch on instead of the message
+    /// (which may change). Codes are never changed or reused once released.
+    pub fn code(&self) -> &'static str {
+        match self {
+            TransactionError::TransactionDoesNotExist { .. } => "TX_NOT_FOUND",
+            TransactionError::TransactionAlreadyExists { .. } => "TX_EXISTS",
+            TransactionError::DisputeAlreadyExists { .. } => "DISPUTE_EXISTS",
+            TransactionError::DisputeDoesNotExist { .. } => "DISPUTE_NOT_FOUND",
+            TransactionError::CurrencyError(_) => "CURRENCY_ERROR",
+            TransactionError::AccountIsLocked { .. } => "ACCOUNT_LOCKED",
+            TransactionError::NotEnoughFunds { .. } => "INSUFFICIENT_FUNDS",
+            TransactionError::Replayed(_) => "TX_REPLAYED",
+            TransactionError::BalanceCapExceeded { .. } => "BALANCE_CAP_EXCEEDED",
+            TransactionError::TransactionLimitReached { .. } => "TX_LIMIT_REACHED",
+            TransactionError::AccountIsFrozen { .. } => "ACCOUNT_FROZEN",
+            TransactionError::DisputeNotAllowed { .. } => "DISPUTE_NOT_ALLOWED",
+            TransactionError::UnknownClient(_) => "UNKNOWN_CLIENT",
+            TransactionError::ClientIdsExhausted => "CLIENT_IDS_EXHAUSTED",
+        }
+    }
+
+    /// True if the transaction may succeed if retried once later
+    /// transactions have been applied, as it may just have arrived early:
+    /// a dispute, resolve or chargeback of a transaction which doesn't exist
+    /// yet, or a resolve or chargeback of a transaction which isn't disputed
+    /// yet. Other errors are permanent.
+    ///
+    /// See [`crate::retry::RetryQueue`].
+    pub fn is_transient(&self) -> bool {
+        matches!(
+            self,
+            TransactionError::TransactionDoesNotExist { .. }
+                | TransactionError::DisputeDoesNotExist { .. }
+        )
+    }
+}
+
+/// Error returned when two processors could not be merged.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum MergeError {
+    /// Both processors have an account for this client, so their states
+    /// cannot be combined without knowing the order of the transactions.
+    ClientConflict(ClientId),
+}
+
+impl Display for MergeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match self {
+            MergeError::ClientConflict(client) => {
+                write!(f, "Client {} has transactions in both processors", client)
+            }
+        }
+    }
+}
+
+/// An inconsistency in an account, found by
+/// [`TransactionProcessor::check_invariants`]. Other than
+/// [`InvariantViolation::TotalOverflow`], any violation is a bug, as no
+/// sequence of transactions should cause one.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum InvariantViolation {
+    /// The held funds are not the sum of the amounts of the active
+    /// disputes. `disputed` is `None` if the sum overflows.
+    HeldMismatch {
+        /// The client whose account is inconsistent.
+        client: ClientId,
+        /// The held funds of the account.
+        held: CurrencyAmount,
+        /// The sum of the amounts of the active disputes.
+        disputed: Option<CurrencyAmount>,
+    },
+    /// The available funds are negative, although the account has no active
+    /// disputes and isn't locked. Only disputes, and then chargebacks (which
+    /// lock the account), may take the available funds below zero.
+    NegativeAvailable {
+        /// The client whose account is inconsistent.
+        client: ClientId,
+        /// The available funds of the account.
+        available: CurrencyAmount,
+    },
+    /// The total funds of the account overflow, so it is left out of reports
+    /// unless totals saturate. Unlike the other violations, this can be
+    /// caused by extreme amounts rather than a bug.
+    TotalOverflow(ClientId),
+    /// An active dispute refers to a transaction which isn't recorded.
+    UnknownDisputedTransaction {
+        /// The client whose account is inconsistent.
+        client: ClientId,
+        /// The disputed transaction.
+        tx: TransactionId,
+    },
+}
+
+impl Display for InvariantViolation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match self {
+            InvariantViolation::HeldMismatch {
+                client,
+                held,
+                disputed: Some(disputed),
+            } => write!(
+                f,
+                "Client {} has {} held funds, but {} is disputed",
+                client, held, disputed
+            ),
+            InvariantViolation::HeldMismatch {
+                client,
+                held,
+                disputed: None,
+            } => write!(
+                f,
+                "Client {} has {} held funds, but the disputed amounts overflow",
+                client, held
+            ),
+            InvariantViolation::NegativeAvailable { client, available } => write!(
+                f,
+                "Client {} has {} available funds without a dispute or lock",
+                client, available
+            ),
+            InvariantViolation::TotalOverflow(client) => {
+                write!(f, "Client {} has total funds which overflow", client)
+            }
+            InvariantViolation::UnknownDisputedTransaction { client, tx } => write!(
+                f,
+                "Client {} has an active dispute of unknown transaction {}",
+                client, tx
+            ),
+        }
+    }
+}
+
+/// Adds up amounts of either sign, returning `None` only if the sum doesn't
+/// fit. Negative amounts are added while the running sum isn't negative, and
+/// positive ones while it is, so no partial sum is further from zero than
+/// the largest amount or the result.
+#[cfg(feature = "std")]
+fn checked_sum(amounts: impl IntoIterator<Item = CurrencyAmount>) -> Option<CurrencyAmount> {
+    let (mut negative, mut positive): (Vec<_>, Vec<_>) =
+        amounts.into_iter().partition(CurrencyAmount::is_negative);
+    let mut sum = CurrencyAmount::ZERO;
+    loop {
+        let next = if sum.is_negative() {
+            positive.pop().or_else(|| negative.pop())
+        } else {
+            negative.pop().or_else(|| positive.pop())
+        };
+        match next {
+            Some(amount) => sum.checked_add_assign(amount).ok()?,
+            None => return Some(sum),
+        }
+    }
+}
+
 impl From<CurrencyError> for TransactionError {
     fn from(err: CurrencyError) -> Self {
         Self::CurrencyError(err)
     }
 }
 
+/// Why an account is locked.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum LockReason {
+    /// A chargeback of the transaction with this ID.
+    Chargeback(TransactionId),
+    /// An administrative action (see `TransactionProcessor::lock_account`).
+    Admin,
+    /// The account was locked before lock reasons were recorded, e.g. in a
+    /// snapshot written by an older version.
+    Unknown,
+}
+
+impl LockReason {
+    /// The name of the reason, as used in the locked-accounts report (e.g.
+    /// `"chargeback"`).
+    #[must_use]
+    pub const fn name(&self) -> &'static str {
+        match self {
+            LockReason::Chargeback(_) => "chargeback",
+            LockReason::Admin => "admin",
+            LockReason::Unknown => "unknown",
+        }
+    }
+
+    /// The transaction which caused the account to be locked, if any.
+    #[must_use]
+    pub const fn tx(&self) -> Option<TransactionId> {
+        match self {
+            LockReason::Chargeback(tx) => Some(*tx),
+            LockReason::Admin | LockReason::Unknown => None,
+        }
+    }
+}
+
+#[cfg(feature = "std")]
 #[derive(Clone, Copy, Debug)]
 enum DisputeResolution {
     Resolve,
     Chargeback,
 }
 
+impl TransactionType {
+    /// The name of the type, as used in the input formats (e.g. `"deposit"`).
+    #[must_use]
+    pub const fn name(&self) -> &'static str {
+        match self {
+            TransactionType::Deposit { .. } => "deposit",
+            TransactionType::Withdrawal { .. } => "withdrawal",
+            TransactionType::Dispute => "dispute",
+            TransactionType::Resolve => "resolve",
+            TransactionType::Chargeback => "chargeback",
+        }
+    }
+}
+
+/// What to do with a deposit or withdrawal for an account which already holds
+/// the maximum number of transactions (see
+/// [`TransactionProcessor::with_transaction_limit`]).
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum TransactionLimitAction {
+    /// Reject it with [`TransactionError::TransactionLimitReached`].
+    Reject,
+    /// Forget the account's oldest transaction which isn't disputed, to make
+    /// room for it.
+    EvictOldest,
+}
+
+/// Whether a transaction can be disputed again once its dispute is settled
+/// (see [`TransactionProcessor::with_redispute_policy`]).
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum RedisputePolicy {
+    /// A transaction can only be disputed once.
+    Never,
+    /// A resolved transaction can be disputed again, but a charged back one
+    /// can't.
+    #[default]
+    AfterResolve,
+    /// A transaction can be disputed again after it is resolved or charged
+    /// back.
+    Always,
+}
+
+/// Which transactions may create the account of a client who doesn't have
+/// one yet (see [`TransactionProcessor::with_account_creation_policy`]).
+/// Other transactions for the client are rejected with
+/// [`TransactionError::UnknownClient`].
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum AccountCreationPolicy {
+    /// Any transaction creates the account.
+    #[default]
+    AutoCreate,
+    /// Only the transactions of registered clients (see
+    /// [`TransactionProcessor::register_clients`]) create their accounts.
+    RejectUnknown,
+    /// Only deposits, or the transactions of registered clients, create the
+    /// account.
+    OnDeposit,
+}
+
+/// Which transactions a locked account may still process (see
+/// [`TransactionProcessor::with_locked_account_policy`]). The others are
+/// rejected with [`TransactionError::AccountIsLocked`].
+///
+/// By default, deposits and withdrawals are rejected, but disputes (and
+/// their resolves and chargebacks) are still processed.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct LockedAccountPolicy {
+    /// Whether deposits are processed.
+    deposits: bool,
+    /// Whether withdrawals are processed.
+    withdrawals: bool,
+    /// Whether disputes, resolves and chargebacks are processed.
+    disputes: bool,
+}
+
+impl LockedAccountPolicy {
+    /// Creates the default policy.
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            deposits: false,
+            withdrawals: false,
+            disputes: true,
+        }
+    }
+
+    /// Sets whether deposits are processed, e.g. so that customer funds
+    /// aren't bounced.
+    #[must_use]
+    pub const fn with_deposits(mut self, deposits: bool) -> Self {
+        self.deposits = deposits;
+        self
+    }
+
+    /// Sets whether withdrawals are processed.
+    #[must_use]
+    pub const fn with_withdrawals(mut self, withdrawals: bool) -> Self {
+        self.withdrawals = withdrawals;
+        self
+    }
+
+    /// Sets whether disputes, resolves and chargebacks are processed.
+    #[must_use]
+    pub const fn with_disputes(mut self, disputes: bool) -> Self {
+        self.disputes = disputes;
+        self
+    }
+
+    /// Whether a locked account may process a transaction of this type.
+    #[must_use]
+    pub const fn allows(&self, transaction_type: &TransactionType) -> bool {
+        match transaction_type {
+            TransactionType::Deposit { .. } => self.deposits,
+            TransactionType::Withdrawal { .. } => self.withdrawals,
+            TransactionType::Dispute | TransactionType::Resolve | TransactionType::Chargeback => {
+                self.disputes
+            }
+        }
+    }
+}
+
+impl Default for LockedAccountPolicy {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The rules a deposit or withdrawal is applied to an account under. If
+/// `replays` is true, a transaction identical to one already applied is
+/// skipped. If `max_balance` is set, a deposit which would take the total
+/// funds above it is rejected. If `limit` is set, the number of transactions
+/// is limited (see [`ClientAccount::check_room`]). A locked account is
+/// rejected unless `allow_locked` is true.
+#[cfg(feature = "std")]
+#[derive(Clone, Copy, Debug)]
+struct FundsPolicy {
+    replays: bool,
+    max_balance: Option<CurrencyAmount>,
+    limit: Option<(usize, TransactionLimitAction)>,
+    allow_locked: bool,
+}
+
+/// The rules a transaction is applied to an account under (see
+/// [`ClientAccount::apply`]): the precision policy of deposit and withdrawal
+/// amounts, whether transactions can be disputed again, and the
+/// [`FundsPolicy`], which also says whether a locked account may process it.
+#[cfg(feature = "std")]
+#[derive(Clone, Copy, Debug)]
+struct TransactionPolicy {
+    precision: Option<PrecisionPolicy>,
+    redispute: RedisputePolicy,
+    funds: FundsPolicy,
+}
+
+#[cfg(feature = "std")]
+#[derive(Clone)]
 struct ClientAccount {
     available: CurrencyAmount,
     held: CurrencyAmount,
     /// Positive CurrencyAmount for a deposit, negative for a withdrawal
     transactions: HashMap<TransactionId, CurrencyAmount>,
     active_disputes: HashSet<TransactionId>,
-    locked: bool,
+    /// The transactions whose disputes were resolved, if they can't be
+    /// disputed again ([`RedisputePolicy::Never`]).
+    resolved: HashSet<TransactionId>,
+    /// The IDs of `transactions` in the order they were applied, oldest
+    /// first, if they are evicted ([`TransactionLimitAction::EvictOldest`]).
+    /// It may also hold transactions which have since been charged back.
+    order: VecDeque<TransactionId>,
+    /// Why the account is locked, if it is.
+    lock: Option<LockReason>,
+    /// Whether withdrawals are held back, e.g. for a compliance review.
+    /// Unlike a lock, this can be lifted, and doesn't stop deposits.
+    frozen: bool,
+    /// The key the account was created for, if it was created by a
+    /// transaction with a client key.
+    key: Option<String>,
+    /// The processor's version when the account last changed (see
+    /// [`ReportVersion`]), or 0 if it hasn't been recorded.
+    version: u64,
+}
+
+/// A change to a [`ClientAccount`] which [`ClientAccount::check`] found can
+/// be made, for [`ClientAccount::commit`].
+#[cfg(feature = "std")]
+enum AccountChange {
+    /// A deposit or withdrawal of `amount` (negative for a withdrawal) is
+    /// recorded, leaving `available` funds, and `order` is changed if the
+    /// number of transactions is limited.
+    Funds {
+        tx: TransactionId,
+        amount: CurrencyAmount,
+        available: CurrencyAmount,
+        order: Option<OrderChange>,
+    },
+    /// The transaction is disputed, leaving the available and held `funds`.
+    Dispute {
+        tx: TransactionId,
+        funds: (CurrencyAmount, CurrencyAmount),
+    },
+    /// The dispute of the transaction is resolved or charged back, leaving
+    /// the available and held `funds`.
+    Resolution {
+        tx: TransactionId,
+        funds: (CurrencyAmount, CurrencyAmount),
+        resolution: DisputeResolution,
+        redispute: RedisputePolicy,
+    },
+}
+
+/// A change to the order of an account's transactions, to make room for
+/// another (see [`ClientAccount::check_room`]). If `rebuild` is true, the
+/// order is first rebuilt in ID order. The transactions at the `evicted`
+/// indices of the order are then evicted, and the new transaction is added
+/// to it if `record` is true.
+#[cfg(feature = "std")]
+struct OrderChange {
+    rebuild: bool,
+    evicted: Vec<usize>,
+    record: bool,
 }
 
+#[cfg(feature = "std")]
 impl ClientAccount {
     pub fn new() -> Self {
+        Self::with_capacity(0)
+    }
+
+    /// Creates an account with space for `transactions` deposits and
+    /// withdrawals.
+    pub fn with_capacity(transactions: usize) -> Self {
         Self {
             available: CurrencyAmount::ZERO,
             held: CurrencyAmount::ZERO,
-            transactions: HashMap::new(),
+            transactions: HashMap::with_capacity(transactions),
             active_disputes: HashSet::new(),
-            locked: false,
+            resolved: HashSet::new(),
+            order: VecDeque::new(),
+            lock: None,
+            frozen: false,
+            key: None,
+            version: 0,
         }
     }
 
+    /// An estimate of the heap memory used by the account, including its
+    /// entry in the processor's map of accounts.
+    fn heap_bytes(&self) -> usize {
+        // Hash tables have a control byte per bucket
+        let entry = size_of::<ClientId>() + size_of::<Arc<ClientAccount>>();
+        let counts = 2 * size_of::<usize>();
+        entry
+            + counts
+            + size_of::<ClientAccount>()
+            + self.transactions.capacity() * (size_of::<(TransactionId, CurrencyAmount)>() + 1)
+            + (self.active_disputes.capacity() + self.resolved.capacity())
+                * (size_of::<TransactionId>() + 1)
+            + self.order.capacity() * size_of::<TransactionId>()
+            + self.key.as_ref().map_or(0, String::capacity)
+    }
+
     pub fn total(&self) -> Result<CurrencyAmount, CurrencyError> {
         self.available + self.held
     }
 
-    /// Disputes the specified transaction in the user's account. All changes
-    /// occur atomically.
+    /// Applies a transaction to the account of `client` under `policy`. All
+    /// changes occur atomically.
+    fn apply(
+        &mut self,
+        client: ClientId,
+        transaction: &Transaction,
+        policy: TransactionPolicy,
+    ) -> Result<(), TransactionError> {
+        let change = self.check(client, transaction, policy)?;
+        self.commit(change);
+        Ok(())
+    }
+
+    /// Checks whether a transaction can be applied to the account of
+    /// `client` under `policy`, without changing it, returning the change
+    /// for [`ClientAccount::commit`] to make if so.
+    fn check(
+        &self,
+        client: ClientId,
+        transaction: &Transaction,
+        policy: TransactionPolicy,
+    ) -> Result<AccountChange, TransactionError> {
+        let apply_precision = |amount: CurrencyAmount| match policy.precision {
+            Some(precision) => amount.with_precision(precision),
+            None => Ok(amount),
+        };
+
+        let tx = transaction.tx;
+        match transaction.transaction_type {
+            TransactionType::Deposit { amount } => apply_precision(amount)
+                .map_err(TransactionError::from)
+                .and_then(|amount| self.check_deposit(client, tx, amount, policy.funds)),
+            TransactionType::Withdrawal { amount } => apply_precision(amount)
+                .map_err(TransactionError::from)
+                .and_then(|amount| self.check_withdrawal(client, tx, amount, policy.funds)),
+            _ if self.lock.is_some() && !policy.funds.allow_locked => {
+                Err(TransactionError::AccountIsLocked {
+                    client,
+                    transaction_type: transaction.transaction_type.clone(),
+                })
+            }
+            TransactionType::Dispute => self.check_dispute(client, tx),
+            TransactionType::Resolve => {
+                self.check_resolution(client, tx, DisputeResolution::Resolve, policy.redispute)
+            }
+            TransactionType::Chargeback => {
+                self.check_resolution(client, tx, DisputeResolution::Chargeback, policy.redispute)
+            }
+        }
+    }
+
+    /// Makes a change returned by [`ClientAccount::check`], which can't
+    /// fail.
+    fn commit(&mut self, change: AccountChange) {
+        match change {
+            AccountChange::Funds {
+                tx,
+                amount,
+                available,
+                order,
+            } => {
+                if let Some(order) = order {
+                    if order.rebuild {
+                        self.order = self.transaction_order().into();
+                    }
+                    // From the back, so that the earlier indices stay valid
+                    for index in order.evicted.into_iter().rev() {
+                        if let Some(tx) = self.order.remove(index) {
+                            self.transactions.remove(&tx);
+                            self.resolved.remove(&tx);
+                        }
+                    }
+                    if order.record {
+                        self.order.push_back(tx);
+                    }
+                }
+                self.transactions.insert(tx, amount);
+                self.available = available;
+            }
+            AccountChange::Dispute { tx, funds } => {
+                (self.available, self.held) = funds;
+                self.active_disputes.insert(tx);
+            }
+            AccountChange::Resolution {
+                tx,
+                funds,
+                resolution,
+                redispute,
+            } => {
+                (self.available, self.held) = funds;
+                self.active_disputes.remove(&tx);
+
+                if matches!(resolution, DisputeResolution::Resolve)
+                    && redispute == RedisputePolicy::Never
+                {
+                    self.resolved.insert(tx);
+                }
+                if matches!(resolution, DisputeResolution::Chargeback) {
+                    if redispute != RedisputePolicy::Always {
+                        // Ensure that this transaction cannot be disputed again
+                        self.transactions.remove(&tx);
+                    }
+                    // An account which is already locked keeps the original reason
+                    self.lock.get_or_insert(LockReason::Chargeback(tx));
+                }
+            }
+        }
+    }
+
+    /// Applies `update` to copies of the available and held funds, and
+    /// returns the results if it succeeds. This keeps multi-step balance
+    /// changes atomic.
+    fn updated_funds(
+        &self,
+        update: impl FnOnce(&mut CurrencyAmount, &mut CurrencyAmount) -> Result<(), TransactionError>,
+    ) -> Result<(CurrencyAmount, CurrencyAmount), TransactionError> {
+        let mut available = self.available;
+        let mut held = self.held;
+
+        update(&mut available, &mut held)?;
+
+        Ok((available, held))
+    }
+
+    /// Checks a dispute of the specified transaction in the user's account.
     ///
     /// This will transfer the value of the transaction from the available
     /// funds to the held funds, and mark the transaction as disputed.
-    fn create_dispute(&mut self, tx: TransactionId) -> Result<(), TransactionError> {
-        let amount = self
+    fn check_dispute(
+        &self,
+        client: ClientId,
+        tx: TransactionId,
+    ) -> Result<AccountChange, TransactionError> {
+        let amount = *self
             .transactions
             .get(&tx)
-            .ok_or(TransactionError::TransactionDoesNotExist(tx))?;
-
-        // Update these atomically in case of an error
-        let new_held = (self.held + *amount)?;
-        let new_available = (self.available - *amount)?;
+            .ok_or(TransactionError::TransactionDoesNotExist { client, tx })?;
 
-        if !self.active_disputes.insert(tx) {
-            return Err(TransactionError::DisputeAlreadyExists(tx));
+        if self.active_disputes.contains(&tx) {
+            return Err(TransactionError::DisputeAlreadyExists { client, tx });
+        }
+        if self.resolved.contains(&tx) {
+            return Err(TransactionError::DisputeNotAllowed { client, tx });
         }
 
-        self.held = new_held;
-        self.available = new_available;
+        let funds = self.updated_funds(|available, held| {
+            held.checked_add_assign(amount)?;
+            available.checked_sub_assign(amount)?;
+            Ok(())
+        })?;
 
-        Ok(())
+        Ok(AccountChange::Dispute { tx, funds })
     }
 
-    /// Resolves an existing dispute in the specified manner. The transaction
-    /// must already be marked as disputed.
-    fn resolve_dispute(
-        &mut self,
+    /// Checks the resolution of an existing dispute in the specified manner.
+    /// The transaction must already be marked as disputed. Whether it can be
+    /// disputed again afterwards depends on `policy`.
+    fn check_resolution(
+        &self,
+        client: ClientId,
         tx: TransactionId,
         resolution: DisputeResolution,
-    ) -> Result<(), TransactionError> {
-        let amount = self
+        policy: RedisputePolicy,
+    ) -> Result<AccountChange, TransactionError> {
+        let amount = *self
             .transactions
             .get(&tx)
-            .ok_or(TransactionError::TransactionDoesNotExist(tx))?;
+            .ok_or(TransactionError::TransactionDoesNotExist { client, tx })?;
 
-        let new_held = (self.held - *amount)?;
+        if !self.active_disputes.contains(&tx) {
+            return Err(TransactionError::DisputeDoesNotExist { client, tx });
+        }
 
-        let new_available = match resolution {
-            DisputeResolution::Resolve => (self.available + *amount)?,
-            DisputeResolution::Chargeback => self.available,
-        };
+        let funds = self.updated_funds(|available, held| {
+            held.checked_sub_assign(amount)?;
 
-        if !self.active_disputes.remove(&tx) {
-            return Err(TransactionError::DisputeDoesNotExist(tx));
-        }
+            if matches!(resolution, DisputeResolution::Resolve) {
+                available.checked_add_assign(amount)?;
+            }
 
-        if matches!(resolution, DisputeResolution::Chargeback) {
-            // Ensure that this transaction cannot be disputed again
-            self.transactions.remove(&tx);
-            self.locked = true;
-        }
+            Ok(())
+        })?;
 
-        self.held = new_held;
-        self.available = new_available;
+        Ok(AccountChange::Resolution {
+            tx,
+            funds,
+            resolution,
+            redispute: policy,
+        })
+    }
 
-        Ok(())
+    /// Checks an increase of the available funds by the specified amount.
+    fn check_deposit(
+        &self,
+        client: ClientId,
+        tx: TransactionId,
+        amount: CurrencyAmount,
+        policy: FundsPolicy,
+    ) -> Result<AccountChange, TransactionError> {
+        let transaction_type = TransactionType::Deposit { amount };
+        self.check_funds(client, tx, amount, &transaction_type, policy)
     }
 
-    /// Increases the available funds by the specified amount.
-    fn deposit(
-        &mut self,
+    /// Checks a change of the available funds by `amount` (negative for a
+    /// withdrawal) under `policy`, which records the transaction.
+    /// `transaction_type` is the deposit or withdrawal, for errors.
+    fn check_funds(
+        &self,
+        client: ClientId,
         tx: TransactionId,
         amount: CurrencyAmount,
-    ) -> Result<(), TransactionError> {
-        if self.locked {
-            return Err(TransactionError::AccountIsLocked);
+        transaction_type: &TransactionType,
+        policy: FundsPolicy,
+    ) -> Result<AccountChange, TransactionError> {
+        // Before the other checks, as the replay needn't be valid now
+        if policy.replays && self.transactions.get(&tx) == Some(&amount) {
+            return Err(TransactionError::Replayed(tx));
+        }
+
+        if self.lock.is_some() && !policy.allow_locked {
+            return Err(TransactionError::AccountIsLocked {
+                client,
+                transaction_type: transaction_type.clone(),
+            });
+        }
+        // Funds may still be paid into a frozen account, but not out of it
+        if self.frozen && amount.is_negative() {
+            return Err(TransactionError::AccountIsFrozen {
+                client,
+                amount: -amount,
+            });
         }
 
-        let new_available = (self.available + amount)?;
+        let mut new_available = self.available;
+        new_available.checked_add_assign(amount)?;
 
         if new_available.is_negative() {
-            return Err(TransactionError::NotEnoughFunds);
+            return Err(TransactionError::NotEnoughFunds {
+                client,
+                transaction_type: transaction_type.clone(),
+                available: self.available,
+            });
         }
 
-        match self.transactions.entry(tx) {
-            Entry::Occupied(_) => return Err(TransactionError::TransactionAlreadyExists(tx)),
-            Entry::Vacant(entry) => {
-                entry.insert(amount);
+        if let Some(max_balance) = policy.max_balance {
+            let mut new_total = new_available;
+            new_total.checked_add_assign(self.held)?;
+            if new_total > max_balance {
+                return Err(TransactionError::BalanceCapExceeded {
+                    client,
+                    amount,
+                    max_balance,
+                });
             }
         }
 
-        self.available = new_available;
+        if self.transactions.contains_key(&tx) {
+            return Err(TransactionError::TransactionAlreadyExists { client, tx });
+        }
+        let order = policy
+            .limit
+            .map(|(max, action)| self.check_room(client, max, action))
+            .transpose()?;
 
-        Ok(())
+        Ok(AccountChange::Funds {
+            tx,
+            amount,
+            available: new_available,
+            order,
+        })
     }
 
-    /// Reduces the available funds by the specified amount.
-    fn withdraw(
-        &mut self,
+    /// Checks there is room for another transaction with at most `max`,
+    /// returning the oldest transactions which aren't disputed to evict if
+    /// `action` allows it. Nothing is evicted unless enough room can be made.
+    fn check_room(
+        &self,
+        client: ClientId,
+        max: usize,
+        action: TransactionLimitAction,
+    ) -> Result<OrderChange, TransactionError> {
+        let record = action == TransactionLimitAction::EvictOldest;
+        // The order isn't known (e.g. for an account restored from a
+        // snapshot), so the transactions are taken to be in ID order
+        let rebuild = record && self.order.is_empty() && !self.transactions.is_empty();
+        let mut change = OrderChange {
+            rebuild,
+            evicted: Vec::new(),
+            record,
+        };
+        if self.transactions.len() < max {
+            return Ok(change);
+        }
+        if action == TransactionLimitAction::Reject {
+            return Err(TransactionError::TransactionLimitReached { client });
+        }
+
+        let needed = self.transactions.len() + 1 - max;
+        let evictable = |(index, tx): (usize, &TransactionId)| {
+            (self.transactions.contains_key(tx) && !self.active_disputes.contains(tx))
+                .then_some(index)
+        };
+        change.evicted = if rebuild {
+            let order = self.transaction_order();
+            order
+                .iter()
+                .enumerate()
+                .filter_map(evictable)
+                .take(needed)
+                .collect()
+        } else {
+            self.order
+                .iter()
+                .enumerate()
+                .filter_map(evictable)
+                .take(needed)
+                .collect()
+        };
+        if change.evicted.len() < needed {
+            return Err(TransactionError::TransactionLimitReached { client });
+        }
+        Ok(change)
+    }
+
+    /// The IDs of the transactions in ID order, for an account whose order
+    /// isn't known.
+    fn transaction_order(&self) -> Vec<TransactionId> {
+        let mut order: Vec<_> = self.transactions.keys().copied().collect();
+        order.sort_unstable();
+        order
+    }
+
+    /// Checks a reduction of the available funds by the specified amount.
+    fn check_withdrawal(
+        &self,
+        client: ClientId,
         tx: TransactionId,
         amount: CurrencyAmount,
-    ) -> Result<(), TransactionError> {
-        self.deposit(tx, -amount)
+        policy: FundsPolicy,
+    ) -> Result<AccountChange, TransactionError> {
+        // A withdrawal never increases the balance
+        let policy = FundsPolicy {
+            max_balance: None,
+            ..policy
+        };
+        let transaction_type = TransactionType::Withdrawal { amount };
+        self.check_funds(client, tx, -amount, &transaction_type, policy)
     }
 }
 
 /// A description of a specific client account in a generated report.
-#[derive(Serialize, Clone, Debug, Eq, PartialEq)]
+///
+/// When serialized, an account created for a client key has the key in
+/// place of its client ID, so that it is reported as the client was known
+/// upstream.
+#[derive(Clone, Debug, Eq, PartialEq)]
 pub struct ReportEntry {
     /// The ID of the client.
     client: ClientId,
+    /// The key of the client, if its account was created for one.
+    client_key: Option<String>,
     /// The amount of available funds.
     available: CurrencyAmount,
     /// The amount of held (i.e. disputed) funds.
@@ -210,265 +1190,2974 @@ pub struct ReportEntry {
     locked: bool,
 }
 
-/// Transaction processor main struct. Processes a stream of transactions
-/// provided using [`TransactionProcessor::transact`], and then generates
-/// a report on the final state of all accounts using
-/// [`TransactionProcessor::generate_report`]
-pub struct TransactionProcessor {
-    // Store in ClientId order (to make testing/comparing output easier)
-    clients: BTreeMap<ClientId, ClientAccount>,
-}
-
-impl TransactionProcessor {
-    /// Creates a new instance of [`TransactionProcessor`] with no client
-    /// accounts.
-    #[allow(clippy::new_without_default)]
+impl ReportEntry {
+    /// Returns this entry with all amounts formatted to exactly `scale`
+    /// decimal places (see [`CurrencyAmount::with_scale`]).
     #[must_use]
-    pub fn new() -> Self {
+    pub fn with_scale(self, scale: u32) -> Self {
         Self {
-            clients: BTreeMap::new(),
+            available: self.available.with_scale(scale),
+            held: self.held.with_scale(scale),
+            total: self.total.with_scale(scale),
+            ..self
         }
     }
 
-    /// Attempts to apply the specified transaction.
-    ///
-    /// If the client account referenced by the transaction does not exist,
-    /// it will be created.
-    ///
-    /// # Errors
-    ///
-    /// If a transaction fails to be applied, an error will be returned. Since
-    /// transactions are applied atomically, no changes will be made to the
-    /// client account if an error occurs.
-    pub fn transact(&mut self, transaction: &Transaction) -> Result<(), TransactionError> {
-        let client = match self.clients.entry(transaction.client) {
-            btree_map::Entry::Vacant(entry) => entry.insert(ClientAccount::new()),
-            btree_map::Entry::Occupied(entry) => entry.into_mut(),
-        };
-
-        match transaction.transaction_type {
-            TransactionType::Deposit { amount } => client.deposit(transaction.tx, amount),
-            TransactionType::Withdrawal { amount } => client.withdraw(transaction.tx, amount),
-            TransactionType::Dispute => client.create_dispute(transaction.tx),
-            TransactionType::Resolve => {
-                client.resolve_dispute(transaction.tx, DisputeResolution::Resolve)
-            }
-            TransactionType::Chargeback => {
-                client.resolve_dispute(transaction.tx, DisputeResolution::Chargeback)
-            }
+    /// The ID of the client.
+    #[must_use]
+    pub const fn client(&self) -> ClientId {
+        self.client
+    }
+
+    /// The key of the client, if its account was created by a transaction
+    /// with a client key.
+    #[must_use]
+    pub fn client_key(&self) -> Option<&str> {
+        self.client_key.as_deref()
+    }
+
+    /// The amount of available funds.
+    #[must_use]
+    pub const fn available(&self) -> CurrencyAmount {
+        self.available
+    }
+
+    /// The amount of held (i.e. disputed) funds.
+    #[must_use]
+    pub const fn held(&self) -> CurrencyAmount {
+        self.held
+    }
+
+    /// The sum of the available and held funds.
+    #[must_use]
+    pub const fn total(&self) -> CurrencyAmount {
+        self.total
+    }
+
+    /// Whether the account is locked.
+    #[must_use]
+    pub const fn locked(&self) -> bool {
+        self.locked
+    }
+}
+
+#[cfg(feature = "serde")]
+impl Serialize for ReportEntry {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeStruct;
+
+        let mut entry = serializer.serialize_struct("ReportEntry", 5)?;
+        self.serialize_fields(&mut entry)?;
+        entry.end()
+    }
+}
+
+#[cfg(feature = "serde")]
+impl ReportEntry {
+    /// Serializes the fields of the entry into `entry`, e.g. after fields
+    /// stamped on every entry of a report, such as its business date.
+    pub(crate) fn serialize_fields<S: serde::ser::SerializeStruct>(
+        &self,
+        entry: &mut S,
+    ) -> Result<(), S::Error> {
+        match &self.client_key {
+            Some(key) => entry.serialize_field("client", key)?,
+            None => entry.serialize_field("client", &self.client)?,
         }
+        entry.serialize_field("available", &self.available)?;
+        entry.serialize_field("held", &self.held)?;
+        entry.serialize_field("total", &self.total)?;
+        entry.serialize_field("locked", &self.locked)
     }
+}
 
-    /// Generates a report containing details of the state of all client
-    /// accounts.
-    ///
-    /// In the case that the client account total funds cause an overflow,
-    /// that client will be excluded from the report and an error will
-    /// be logged.
-    pub fn generate_report(&self) -> impl Iterator<Item = ReportEntry> + '_ {
+/// The size of a processor's state, from
+/// [`TransactionProcessor::memory_stats`].
+#[cfg(feature = "std")]
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct MemoryStats {
+    clients: usize,
+    transactions: usize,
+    active_disputes: usize,
+    estimated_heap_bytes: usize,
+}
+
+#[cfg(feature = "std")]
+impl MemoryStats {
+    /// The number of client accounts.
+    #[must_use]
+    pub const fn clients(&self) -> usize {
         self.clients
-            .iter()
-            .filter_map(|(client_id, client_account)| match client_account.total() {
-                Ok(total) => Some(ReportEntry {
-                    client: *client_id,
-                    available: client_account.available,
-                    held: client_account.held,
-                    total,
-                    locked: client_account.locked,
-                }),
-                Err(err) => {
-                    log::error!(
-                        "Skipping account {} due to error finding total: {}",
-                        client_id,
-                        err
-                    );
-                    None
-                }
-            })
     }
 
-    /// Convenience method to convert the report generated by
-    /// [`TransactionProcessor::generate_report`] into a `Vec`. Useful
-    /// for testing purposes.
+    /// The number of deposits and withdrawals recorded across all accounts
+    /// (which can be disputed, and so are kept).
     #[must_use]
-    pub fn generate_report_as_vec(&self) -> Vec<ReportEntry> {
-        self.generate_report().collect()
+    pub const fn transactions(&self) -> usize {
+        self.transactions
+    }
+
+    /// The number of disputes which have not yet been resolved or charged
+    /// back, across all accounts.
+    #[must_use]
+    pub const fn active_disputes(&self) -> usize {
+        self.active_disputes
+    }
+
+    /// An estimate of the heap memory used by the accounts, in bytes, from
+    /// the sizes and capacities of their collections. It excludes the
+    /// allocator's own overhead, so the process uses somewhat more, and
+    /// counts accounts shared with clones of the processor in full.
+    #[must_use]
+    pub const fn estimated_heap_bytes(&self) -> usize {
+        self.estimated_heap_bytes
     }
 }
 
-/// A client identifier.
-pub type ClientId = u16;
-/// A transaction identifier.
-pub type TransactionId = u32;
+/// Transaction processor main struct. Processes a stream of transactions
+/// provided using [`TransactionProcessor::transact`], and then generates
+/// a report on the final state of all accounts using
+/// [`TransactionProcessor::generate_report`]
+///
+/// Cloning a processor is cheap, e.g. to try out transactions on a copy for
+/// a simulation, or to process speculatively without touching the original:
+/// the accounts are shared between the clones (copy-on-write), and an
+/// account is only copied when a clone first changes it. So a clone costs
+/// memory in proportion to the number of accounts, and to the transactions
+/// of the accounts it changes, rather than to all the transactions.
+#[cfg(feature = "std")]
+#[derive(Clone)]
+pub struct TransactionProcessor {
+    // Store in ClientId order (to make testing/comparing output easier).
+    // Accounts are shared between clones until they change.
+    clients: BTreeMap<ClientId, Arc<ClientAccount>>,
+    /// Applied to deposit/withdrawal amounts, if set.
+    precision_policy: Option<PrecisionPolicy>,
+    /// If true, report totals saturate rather than excluding the account.
+    saturating_totals: bool,
+    /// If true, exact replays of deposits and withdrawals are skipped.
+    idempotent_replays: bool,
+    /// The maximum total funds of an account, if any.
+    max_balance: Option<CurrencyAmount>,
+    /// The maximum number of transactions of an account, and what to do once
+    /// it is reached, if any.
+    transaction_limit: Option<(usize, TransactionLimitAction)>,
+    /// Whether transactions can be disputed again.
+    redispute_policy: RedisputePolicy,
+    /// Which transactions locked accounts may still process.
+    locked_account_policy: LockedAccountPolicy,
+    /// Which transactions may create accounts.
+    account_creation_policy: AccountCreationPolicy,
+    /// The clients whose accounts may always be created.
+    registered_clients: ClientSet,
+    /// The clients whose accounts were created for a client key, by key.
+    client_keys: HashMap<String, ClientId>,
+    /// The lowest client ID which may be free for a new client key.
+    next_keyed_client: ClientId,
+    /// The changes made to the accounts, for delta reports.
+    changes: ChangeLog,
+    /// The number of transactions space is allocated for in a new account.
+    transactions_per_client: usize,
+    /// The number of new accounts which may still be allocated space for
+    /// `transactions_per_client` transactions, shared with the processors
+    /// like this one.
+    reserved_accounts: Arc<AtomicUsize>,
+}
 
-/// A struct representing a transaction.
-#[derive(Clone, Debug, Eq, PartialEq)]
-pub struct Transaction {
-    /// The client account which the transaction applies to.
-    client: ClientId,
-    /// The transaction ID. In the case of deposits and withdrawals, this
-    /// should be a new transaction ID. In the case of disputes, resolutions,
-    /// and chargebacks, this should be the ID of an existing transaction.
-    tx: TransactionId,
-    /// The type of the transaction, and associated data where relevant.
-    transaction_type: TransactionType,
+/// Tracks which accounts have changed, so that a delta report only visits the
+/// changed accounts.
+#[cfg(feature = "std")]
+#[derive(Clone, Default)]
+struct ChangeLog {
+    /// The number of changes made to the accounts (see [`ReportVersion`]).
+    version: u64,
+    /// The clients whose accounts have changed, with the version of each
+    /// change, in version order. An entry is stale if the account has changed
+    /// again since.
+    clients: Vec<(u64, ClientId)>,
 }
 
-impl Transaction {
-    /// Creates a new [Transaction] instance.
+#[cfg(feature = "std")]
+impl ChangeLog {
+    /// The number of entries below which the log isn't compacted.
+    const MIN_COMPACT_LEN: usize = 1024;
+
+    /// Records that `client`'s `account` has changed.
+    fn record(&mut self, client: ClientId, account: &mut ClientAccount) {
+        self.version += 1;
+        account.version = self.version;
+        self.clients.push((self.version, client));
+    }
+
+    /// Drops the stale entries once they outnumber the `accounts`, so that the
+    /// log doesn't grow with the number of transactions.
+    fn compact(&mut self, accounts: &BTreeMap<ClientId, Arc<ClientAccount>>) {
+        if self.clients.len() <= Self::MIN_COMPACT_LEN.max(accounts.len() * 2) {
+            return;
+        }
+        self.clients = accounts
+            .iter()
+            .filter(|(_, account)| account.version > 0)
+            .map(|(client, account)| (account.version, *client))
+            .collect();
+        self.clients.sort_unstable();
+    }
+}
+
+#[cfg(feature = "std")]
+impl TransactionProcessor {
+    /// Creates a new instance of [`TransactionProcessor`] with no client
+    /// accounts.
+    #[allow(clippy::new_without_default)]
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            clients: BTreeMap::new(),
+            precision_policy: None,
+            saturating_totals: false,
+            idempotent_replays: false,
+            max_balance: None,
+            transaction_limit: None,
+            redispute_policy: RedisputePolicy::default(),
+            locked_account_policy: LockedAccountPolicy::new(),
+            account_creation_policy: AccountCreationPolicy::default(),
+            registered_clients: ClientSet::new([]),
+            client_keys: HashMap::new(),
+            next_keyed_client: ClientId::MIN,
+            changes: ChangeLog::default(),
+            transactions_per_client: 0,
+            reserved_accounts: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    /// Allocates space up front for `clients` accounts, and for
+    /// `transactions_per_client` deposits and withdrawals in each of the first
+    /// `clients` new accounts (up to any
+    /// [`TransactionProcessor::with_transaction_limit`]), so that tables
+    /// aren't repeatedly grown and rehashed while processing a large input.
+    /// Overestimating wastes memory, as the space for each account is
+    /// allocated when it is created. The accounts themselves are kept in a
+    /// B-tree, which has nothing to allocate in advance, so `clients` only
+    /// sizes the log of changed accounts.
     ///
-    /// * `client` - The client account which the transaction applies to.
-    /// * `tx` - The transaction ID. In the case of deposits and withdrawals,
-    ///   this should be a new transaction ID. In the case of disputes,
-    ///   resolutions, and chargebacks, this should be the ID of an existing
-    ///   transaction.
-    /// * `transaction_type` - The type of the transaction, and associated data
-    ///   where relevant.
+    /// Accounts after the first `clients` start empty, so that no more than
+    /// `clients * transactions_per_client` transactions are allocated space
+    /// however many clients there turn out to be. The processors made by
+    /// [`TransactionProcessor::new_like`] (and their clones) share the same
+    /// `clients` accounts.
     #[must_use]
-    pub const fn new(
-        client: ClientId,
-        tx: TransactionId,
-        transaction_type: TransactionType,
-    ) -> Self {
+    pub fn with_capacity(mut self, clients: usize, transactions_per_client: usize) -> Self {
+        self.changes.clients.reserve(clients);
+        self.transactions_per_client = transactions_per_client;
+        self.reserved_accounts = Arc::new(AtomicUsize::new(clients));
+        self
+    }
+
+    /// Limits deposit and withdrawal amounts to
+    /// [`CurrencyAmount::MAX_DECIMAL_PLACES`] decimal places, using the
+    /// specified policy. By default, amounts are used verbatim.
+    ///
+    /// Transactions rejected by [`PrecisionPolicy::Reject`] fail with
+    /// [`CurrencyError::PrecisionLoss`].
+    #[must_use]
+    pub fn with_precision_policy(mut self, policy: PrecisionPolicy) -> Self {
+        self.precision_policy = Some(policy);
+        self
+    }
+
+    /// If enabled, an account whose total funds overflow is reported with a
+    /// saturated total (see [`CurrencyAmount::saturating_add`]) rather than
+    /// being excluded from the report. Useful where an approximate aggregate
+    /// is preferable to missing data.
+    #[must_use]
+    pub fn with_saturating_totals(mut self, saturating_totals: bool) -> Self {
+        self.saturating_totals = saturating_totals;
+        self
+    }
+
+    /// If enabled, a deposit or withdrawal with the same client, ID, type
+    /// and amount as one already applied fails with
+    /// [`TransactionError::Replayed`], and changes nothing, rather than with
+    /// [`TransactionError::TransactionAlreadyExists`]. A transaction which
+    /// reuses an ID with a different type or amount is still rejected.
+    ///
+    /// Useful for inputs delivered at least once, where exact duplicates are
+    /// expected. A replay of a transaction which has been charged back can't
+    /// be recognised, and is rejected as the account is locked.
+    #[must_use]
+    pub fn with_idempotent_replays(mut self, idempotent_replays: bool) -> Self {
+        self.idempotent_replays = idempotent_replays;
+        self
+    }
+
+    /// Limits the total funds of every account to `max_balance`, e.g. for
+    /// regulatory limits on stored-value accounts. A deposit which would take
+    /// an account's total above it fails with
+    /// [`TransactionError::BalanceCapExceeded`]. Withdrawals, disputes and
+    /// their resolutions are unaffected, so an account already above the cap
+    /// (e.g. restored from a snapshot taken with a higher one) can still be
+    /// drawn down. By default, balances are unlimited.
+    #[must_use]
+    pub fn with_max_balance(mut self, max_balance: CurrencyAmount) -> Self {
+        self.max_balance = Some(max_balance);
+        self
+    }
+
+    /// Limits the number of deposits and withdrawals kept for every account
+    /// to `max`, so that a single busy account can't exhaust memory. Once an
+    /// account holds `max` transactions, a new deposit or withdrawal is
+    /// handled according to `action`.
+    ///
+    /// An evicted transaction is forgotten, so can no longer be disputed (nor
+    /// recognised as a duplicate or replay). Transactions of accounts
+    /// restored from a snapshot, or merged from another processor, are
+    /// evicted in ID order. By default, the number of transactions is
+    /// unlimited.
+    #[must_use]
+    pub fn with_transaction_limit(mut self, max: usize, action: TransactionLimitAction) -> Self {
+        self.transaction_limit = Some((max, action));
+        self
+    }
+
+    /// Sets whether a transaction can be disputed again once its dispute has
+    /// been resolved or charged back. By default, only resolved transactions
+    /// can ([`RedisputePolicy::AfterResolve`]).
+    ///
+    /// With [`RedisputePolicy::Never`], the resolved transactions are
+    /// remembered, so that later disputes of them are rejected with
+    /// [`TransactionError::DisputeNotAllowed`]. Transactions resolved under
+    /// another policy are not. With [`RedisputePolicy::Always`], charged back
+    /// transactions are kept, so they can be disputed (and charged back)
+    /// again, and their IDs can't be reused.
+    #[must_use]
+    pub fn with_redispute_policy(mut self, policy: RedisputePolicy) -> Self {
+        self.redispute_policy = policy;
+        self
+    }
+
+    /// Sets which transactions a locked account may still process, e.g. to
+    /// keep accepting deposits so that customer funds aren't bounced. By
+    /// default, only disputes, resolves and chargebacks are. A chargeback
+    /// still locks the account as usual.
+    #[must_use]
+    pub fn with_locked_account_policy(mut self, policy: LockedAccountPolicy) -> Self {
+        self.locked_account_policy = policy;
+        self
+    }
+
+    /// Sets which transactions may create the account of a client who doesn't
+    /// have one yet. By default, any transaction does
+    /// ([`AccountCreationPolicy::AutoCreate`]), which hides typos in client
+    /// IDs. Accounts restored from a snapshot, merged, or created by
+    /// [`TransactionProcessor::lock_account`] already exist.
+    #[must_use]
+    pub fn with_account_creation_policy(mut self, policy: AccountCreationPolicy) -> Self {
+        self.account_creation_policy = policy;
+        self
+    }
+
+    /// Registers clients whose accounts may be created by any transaction,
+    /// whatever the [`AccountCreationPolicy`]. Registering a client doesn't
+    /// create their account, so they aren't reported until their first
+    /// transaction.
+    pub fn register_clients(&mut self, clients: &ClientSet) {
+        self.registered_clients = ClientSet::new(
+            self.registered_clients
+                .ranges()
+                .iter()
+                .chain(clients.ranges())
+                .cloned(),
+        );
+    }
+
+    /// Creates a processor with no client accounts, but the same options as
+    /// this one. Useful for processing independent inputs separately, before
+    /// combining them with [`TransactionProcessor::merge`].
+    #[must_use]
+    pub fn new_like(&self) -> Self {
         Self {
-            client,
-            tx,
-            transaction_type,
+            precision_policy: self.precision_policy,
+            saturating_totals: self.saturating_totals,
+            idempotent_replays: self.idempotent_replays,
+            max_balance: self.max_balance,
+            transaction_limit: self.transaction_limit,
+            redispute_policy: self.redispute_policy,
+            locked_account_policy: self.locked_account_policy,
+            account_creation_policy: self.account_creation_policy,
+            registered_clients: self.registered_clients.clone(),
+            transactions_per_client: self.transactions_per_client,
+            reserved_accounts: Arc::clone(&self.reserved_accounts),
+            ..Self::new()
         }
     }
-}
 
-/// The type of a transaction, and associated data where relevant.
-#[derive(Clone, Debug, Eq, PartialEq)]
-pub enum TransactionType {
-    /// Increases the available funds by the specified amount.
-    Deposit {
-        /// The amount by which to increase the available funds.
-        amount: CurrencyAmount,
-    },
-    /// Reduces the available funds by the specified amount.
-    Withdrawal {
-        /// The amount by which to decrease the avaiable funds.
-        amount: CurrencyAmount,
-    },
-    /// Disputes the specified transaction in the user's account. All changes
-    /// occur atomically.
+    /// Moves all client accounts from `other` into this processor. The
+    /// options of `other` are ignored.
     ///
-    /// This will transfer the value of the transaction from the available
-    /// funds to the held funds, and mark the transaction as disputed.
-    Dispute,
-    /// Resolves a dispute, moving the previously held funds back into
-    /// the available balance.
-    Resolve,
-    /// Performs a chargeback, removing the funds from the held balance,
-    /// and locking the account.
+    /// The result is the same as applying the transactions of both
+    /// processors to one, as long as no client has transactions in both.
+    /// Accounts for client keys (see [`Transaction::with_client_key`]) are
+    /// given the lowest free IDs by each processor, so an account for a key
+    /// in `other` whose ID is taken in this processor is given a new one.
     ///
-    /// After a chargeback is performed, the transaction cannot be disputed
-    /// again.
-    Chargeback,
-}
+    /// # Errors
+    ///
+    /// Returns an error (and makes no changes) if a client has an account in
+    /// both processors, or a client key does (which is reported with its ID
+    /// in this processor). The lowest such client is reported.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "debug", skip_all, fields(clients = other.clients.len()))
+    )]
+    pub fn merge(&mut self, mut other: TransactionProcessor) -> Result<(), MergeError> {
+        let key_conflicts = other
+            .client_keys
+            .keys()
+            .filter_map(|key| self.client_keys.get(key).copied());
+        let client_conflicts = other
+            .clients
+            .iter()
+            .filter(|(client, account)| account.key.is_none() && self.clients.contains_key(client))
+            .map(|(client, _)| *client);
+        if let Some(client) = key_conflicts.chain(client_conflicts).min() {
+            return Err(MergeError::ClientConflict(client));
+        }
 
-#[cfg(test)]
-mod test {
-    use std::str::FromStr;
+        // Every other account whose ID is taken is for a key
+        let mut free = (self.next_keyed_client..=ClientId::MAX).filter(|client| {
+            !self.clients.contains_key(client) && !other.clients.contains_key(client)
+        });
+        let mut renumbered = Vec::new();
+        for client in other
+            .clients
+            .keys()
+            .filter(|client| self.clients.contains_key(client))
+        {
+            match free.next() {
+                Some(free_client) => renumbered.push((*client, free_client)),
+                None => return Err(MergeError::ClientConflict(*client)),
+            }
+        }
+        for (client, free_client) in renumbered {
+            if let Some(account) = other.clients.remove(&client) {
+                if let Some(key) = &account.key {
+                    other.client_keys.insert(key.clone(), free_client);
+                }
+                other.clients.insert(free_client, account);
+            }
+        }
+        self.client_keys.extend(other.client_keys);
+
+        let merged = other.clients.keys().copied().collect::<Vec<_>>();
+        self.clients.extend(other.clients);
+        for client in merged {
+            self.record_change(client);
+        }
+        Ok(())
+    }
+
+    /// Records that a client's account has changed, for delta reports.
+    pub(crate) fn record_change(&mut self, client: ClientId) {
+        if let Some(account) = self.clients.get_mut(&client) {
+            self.changes.record(client, Arc::make_mut(account));
+            self.changes.compact(&self.clients);
+        }
+    }
+
+    /// Attempts to apply the specified transaction.
+    ///
+    /// If the client account referenced by the transaction does not exist,
+    /// it will be created.
+    ///
+    /// # Errors
+    ///
+    /// If a transaction fails to be applied, an error will be returned. Since
+    /// transactions are applied atomically, no changes will be made to the
+    /// client account if an error occurs.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            level = "trace",
+            skip_all,
+            fields(
+                client = transaction.client,
+                tx = transaction.tx,
+                transaction_type = transaction.transaction_type.name(),
+            ),
+            err(Display, level = "debug"),
+        )
+    )]
+    pub fn transact(&mut self, transaction: &Transaction) -> Result<(), TransactionError> {
+        let client_id = match transaction.client_key() {
+            Some(key) => self.keyed_client(key)?,
+            None => transaction.client,
+        };
+        self.transact_for(client_id, transaction)
+    }
+
+    /// Applies a transaction to the account of `client_id`, which is the
+    /// transaction's client unless it has a client key. A new account is
+    /// created for the key.
+    pub(crate) fn transact_for(
+        &mut self,
+        client_id: ClientId,
+        transaction: &Transaction,
+    ) -> Result<(), TransactionError> {
+        self.check_creation(client_id, transaction)?;
+
+        // A new account appears in reports even if the transaction fails
+        let capacity = self.account_capacity();
+        let policy = self.transaction_policy(transaction);
+        let (client, created) = match self.clients.entry(client_id) {
+            btree_map::Entry::Vacant(entry) => {
+                let reserved = self
+                    .reserved_accounts
+                    .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |accounts| {
+                        accounts.checked_sub(1)
+                    })
+                    .is_ok();
+                let capacity = if reserved { capacity } else { 0 };
+                (
+                    entry.insert(Arc::new(ClientAccount::with_capacity(capacity))),
+                    true,
+                )
+            }
+            btree_map::Entry::Occupied(entry) => (entry.into_mut(), false),
+        };
+        let client = Arc::make_mut(client);
+        if let (true, Some(key)) = (created, transaction.client_key()) {
+            client.key = Some(key.to_string());
+            self.client_keys.insert(key.to_string(), client_id);
+            self.next_keyed_client = client_id.saturating_add(1);
+        }
+
+        let result = client.apply(client_id, transaction, policy);
+
+        if created || result.is_ok() {
+            self.changes.record(client_id, client);
+            self.changes.compact(&self.clients);
+        }
+        result
+    }
+
+    /// Checks whether [`TransactionProcessor::transact`] would apply the
+    /// specified transaction, without changing any state: whether the
+    /// client's account may be created, is locked or frozen, has enough
+    /// funds, already has the transaction ID, or has the transaction in the
+    /// right dispute state, and the processor's other limits. For example,
+    /// an API can validate a request before queueing it to be applied later,
+    /// in which case the outcome may differ if other transactions are applied
+    /// first.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error which [`TransactionProcessor::transact`] would
+    /// return, including [`TransactionError::Replayed`] for a replay.
+    pub fn would_succeed(&self, transaction: &Transaction) -> Result<(), TransactionError> {
+        let client_id = match transaction.client_key() {
+            Some(key) => self.keyed_client(key)?,
+            None => transaction.client,
+        };
+        self.check_creation(client_id, transaction)?;
+
+        let policy = self.transaction_policy(transaction);
+        match self.clients.get(&client_id) {
+            Some(client) => client.check(client_id, transaction, policy),
+            None => ClientAccount::new().check(client_id, transaction, policy),
+        }
+        .map(|_| ())
+    }
+
+    /// Checks that the account of `client_id` exists, or may be created by
+    /// the transaction under the account creation policy.
+    fn check_creation(
+        &self,
+        client_id: ClientId,
+        transaction: &Transaction,
+    ) -> Result<(), TransactionError> {
+        let may_create = match self.account_creation_policy {
+            AccountCreationPolicy::AutoCreate => true,
+            AccountCreationPolicy::RejectUnknown => false,
+            AccountCreationPolicy::OnDeposit => {
+                matches!(
+                    transaction.transaction_type,
+                    TransactionType::Deposit { .. }
+                )
+            }
+        };
+        if !may_create
+            && !self.clients.contains_key(&client_id)
+            && !self.registered_clients.contains(client_id)
+        {
+            return Err(TransactionError::UnknownClient(client_id));
+        }
+        Ok(())
+    }
+
+    /// The policies which the transaction is applied to its account under.
+    fn transaction_policy(&self, transaction: &Transaction) -> TransactionPolicy {
+        let allow_locked = self
+            .locked_account_policy
+            .allows(&transaction.transaction_type);
+        TransactionPolicy {
+            precision: self.precision_policy,
+            redispute: self.redispute_policy,
+            funds: FundsPolicy {
+                replays: self.idempotent_replays,
+                max_balance: self.max_balance,
+                limit: self.transaction_limit,
+                allow_locked,
+            },
+        }
+    }
+
+    /// Applies each transaction in turn, like [`TransactionProcessor::transact`],
+    /// giving every one which fails to `rejects`. Replays (see
+    /// [`TransactionProcessor::with_idempotent_replays`]) are skipped without
+    /// being rejected.
+    ///
+    /// Returns a summary of the transactions applied and rejected. Failures
+    /// of the sink are logged.
+    pub fn transact_all(
+        &mut self,
+        transactions: impl IntoIterator<Item = Transaction>,
+        rejects: &mut dyn RejectSink,
+    ) -> BatchResult {
+        let mut summary = BatchResult::new();
+        for transaction in transactions {
+            let result = self.transact(&transaction);
+            summary.record_transaction(&result);
+            match result {
+                Ok(()) | Err(TransactionError::Replayed(_)) => {}
+                Err(err) => {
+                    let row = RejectedRow::transaction_failed(Vec::new(), transaction, err);
+                    if let Err(err) = rejects.reject(row) {
+                        log::error!("Failed to write rejected row: {}", err);
+                    }
+                }
+            }
+        }
+        if let Err(err) = rejects.flush() {
+            log::error!("Failed to write rejected rows: {}", err);
+        }
+        summary
+    }
+
+    /// The number of transactions space is allocated for in a new account.
+    fn account_capacity(&self) -> usize {
+        match self.transaction_limit {
+            Some((max, _)) => self.transactions_per_client.min(max),
+            None => self.transactions_per_client,
+        }
+    }
+
+    /// The client ID of a key's account: the existing one, or the lowest
+    /// free ID for a new key.
+    fn keyed_client(&self, key: &str) -> Result<ClientId, TransactionError> {
+        if let Some(client) = self.client_keys.get(key) {
+            return Ok(*client);
+        }
+        (self.next_keyed_client..=ClientId::MAX)
+            .find(|client| !self.clients.contains_key(client))
+            .ok_or(TransactionError::ClientIdsExhausted)
+    }
+
+    /// The client ID of the account which a transaction applies to: its
+    /// client key's (see [`Transaction::with_client_key`]), or otherwise
+    /// [`Transaction::client`]. Returns `None` if the key has no account.
+    #[must_use]
+    pub fn transaction_client(&self, transaction: &Transaction) -> Option<ClientId> {
+        match transaction.client_key() {
+            Some(key) => self.client_keys.get(key).copied(),
+            None => Some(transaction.client),
+        }
+    }
+
+    /// Generates a report containing details of the state of all client
+    /// accounts.
+    ///
+    /// In the case that the client account total funds cause an overflow,
+    /// that client will be excluded from the report and an error will
+    /// be logged, unless saturating totals are enabled (see
+    /// [`TransactionProcessor::with_saturating_totals`]).
+    ///
+    /// Only accounts matching `filter` are included. The accounts of the
+    /// filter's clients are looked up directly, so a report of a few clients
+    /// is cheap even with a large number of accounts.
+    ///
+    /// Other than [`ReportOrder::ClientId`], the `order` requires the
+    /// included entries to be collected and sorted before the first is
+    /// returned.
+    pub fn generate_report<'a>(
+        &'a self,
+        filter: &'a ReportFilter,
+        order: ReportOrder,
+    ) -> impl Iterator<Item = ReportEntry> + 'a {
+        let entries = filter
+            .client_ranges()
+            .iter()
+            .flat_map(|range| self.clients.range(range.clone()))
+            .filter_map(|(client_id, client_account)| self.report_entry(*client_id, client_account))
+            .filter(|entry| filter.matches(entry));
+
+        // Exactly one of the two is non-empty
+        let mut sorted = Vec::new();
+        let streamed = match order {
+            ReportOrder::ClientId => Some(entries),
+            order => {
+                sorted.extend(entries);
+                order.sort(&mut sorted);
+                None
+            }
+        };
+        streamed.into_iter().flatten().chain(sorted)
+    }
+
+    /// Computes the grand totals of the accounts matching `filter`, as they
+    /// would appear in [`TransactionProcessor::generate_report`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if any of the sums overflows.
+    pub fn report_totals(&self, filter: &ReportFilter) -> Result<ReportTotals, CurrencyError> {
+        let mut totals = ReportTotals::new();
+        for entry in self.generate_report(filter, ReportOrder::ClientId) {
+            totals.add(&entry)?;
+        }
+        Ok(totals)
+    }
+
+    /// Returns the report entry for a single client account, as it would
+    /// appear in [`TransactionProcessor::generate_report`]. Returns `None` if
+    /// the client has no account, or if it would be excluded from the report.
+    #[must_use]
+    pub fn account_report(&self, client: ClientId) -> Option<ReportEntry> {
+        self.clients
+            .get(&client)
+            .and_then(|client_account| self.report_entry(client, client_account))
+    }
+
+    fn report_entry(
+        &self,
+        client_id: ClientId,
+        client_account: &ClientAccount,
+    ) -> Option<ReportEntry> {
+        let total = if self.saturating_totals {
+            Ok(client_account.available.saturating_add(client_account.held))
+        } else {
+            client_account.total()
+        };
+
+        match total {
+            Ok(total) => Some(ReportEntry {
+                client: client_id,
+                client_key: client_account.key.clone(),
+                available: client_account.available,
+                held: client_account.held,
+                total,
+                locked: client_account.lock.is_some(),
+            }),
+            Err(err) => {
+                log::error!(
+                    "Skipping account {} due to error finding total: {}",
+                    client_id,
+                    err
+                );
+                None
+            }
+        }
+    }
+
+    /// Generates a report of every dispute which has not yet been resolved
+    /// or charged back, in client and then transaction ID order.
+    pub fn generate_dispute_report(&self) -> impl Iterator<Item = DisputeEntry> + '_ {
+        self.clients.iter().flat_map(|(client_id, client_account)| {
+            let mut disputes = client_account
+                .active_disputes
+                .iter()
+                .copied()
+                .collect::<Vec<_>>();
+            disputes.sort_unstable();
+
+            disputes.into_iter().filter_map(move |tx| {
+                // Disputed transactions are only removed by a chargeback,
+                // which also ends the dispute
+                let held = *client_account.transactions.get(&tx)?;
+                Some(DisputeEntry::new(*client_id, tx, held))
+            })
+        })
+    }
+
+    /// Generates a report of every locked account, with the reason it was
+    /// locked, in client ID order.
+    pub fn generate_locked_report(&self) -> impl Iterator<Item = LockedEntry> + '_ {
+        self.clients
+            .iter()
+            .filter_map(|(client_id, client_account)| {
+                client_account
+                    .lock
+                    .map(|reason| LockedEntry::new(*client_id, reason))
+            })
+    }
+
+    /// Generates an audit report of every account whose available funds are
+    /// negative (i.e. whose held funds exceed its total), in client ID order,
+    /// so that the shortfall can be recovered. This happens when a deposit
+    /// whose funds have already been withdrawn is disputed (and then perhaps
+    /// charged back).
+    ///
+    /// There is an entry for each active dispute of a deposit on the
+    /// account, in transaction ID order, as any of them may have caused it.
+    /// An account without one has a single entry, naming the chargeback which
+    /// locked it, or no cause at all.
+    pub fn generate_negative_balance_report(
+        &self,
+    ) -> impl Iterator<Item = NegativeBalanceEntry> + '_ {
+        self.clients
+            .iter()
+            .filter(|(_, client_account)| client_account.available.is_negative())
+            .flat_map(|(client_id, client_account)| {
+                let mut causes = client_account
+                    .active_disputes
+                    .iter()
+                    .filter_map(|&tx| {
+                        let held = *client_account.transactions.get(&tx)?;
+                        (held > CurrencyAmount::ZERO)
+                            .then_some(Some(NegativeBalanceCause::Dispute { tx, held }))
+                    })
+                    .collect::<Vec<_>>();
+                causes.sort_unstable_by_key(|cause| cause.map(|cause| cause.tx()));
+                if causes.is_empty() {
+                    causes.push(match client_account.lock {
+                        Some(LockReason::Chargeback(tx)) => {
+                            Some(NegativeBalanceCause::Chargeback(tx))
+                        }
+                        _ => None,
+                    });
+                }
+
+                causes.into_iter().map(move |cause| {
+                    NegativeBalanceEntry::new(
+                        *client_id,
+                        client_account.available,
+                        client_account.held,
+                        cause,
+                    )
+                })
+            })
+    }
+
+    /// Compares `report` (e.g. read from an earlier run's report file) with
+    /// the current state of every account, returning the clients whose
+    /// accounts differ (see [`report::diff_reports`]).
+    pub fn diff_report(&self, report: impl IntoIterator<Item = ReportEntry>) -> Vec<ReportDiff> {
+        report::diff_reports(
+            report,
+            self.generate_report(&ReportFilter::new(), ReportOrder::ClientId),
+        )
+    }
+
+    /// Locks a client's account by administrative action, so that it can no
+    /// longer deposit or withdraw. The account is created if it does not
+    /// exist, so that a client can be locked before their first
+    /// transaction. An account which is already locked keeps the original
+    /// reason.
+    pub fn lock_account(&mut self, client: ClientId) {
+        let (account, created) = match self.clients.entry(client) {
+            btree_map::Entry::Vacant(entry) => (entry.insert(Arc::new(ClientAccount::new())), true),
+            btree_map::Entry::Occupied(entry) => (entry.into_mut(), false),
+        };
+        let account = Arc::make_mut(account);
+        if created || account.lock.is_none() {
+            account.lock = Some(LockReason::Admin);
+            self.changes.record(client, account);
+            self.changes.compact(&self.clients);
+        }
+    }
+
+    /// Freezes a client's account, so that it can no longer withdraw until
+    /// it is unfrozen with [`TransactionProcessor::unfreeze_account`].
+    /// Deposits, disputes, resolves and chargebacks are still applied. The
+    /// account is created if it does not exist, as for
+    /// [`TransactionProcessor::lock_account`]. Freezing is independent of
+    /// locking, so a chargeback still locks a frozen account.
+    pub fn freeze_account(&mut self, client: ClientId) {
+        let (account, created) = match self.clients.entry(client) {
+            btree_map::Entry::Vacant(entry) => (entry.insert(Arc::new(ClientAccount::new())), true),
+            btree_map::Entry::Occupied(entry) => (entry.into_mut(), false),
+        };
+        // An account which is already frozen isn't copied from a clone
+        if !account.frozen {
+            Arc::make_mut(account).frozen = true;
+        }
+        if created {
+            // The new account isn't shared, so this doesn't copy it
+            self.changes.record(client, Arc::make_mut(account));
+            self.changes.compact(&self.clients);
+        }
+    }
+
+    /// Unfreezes a client's account frozen with
+    /// [`TransactionProcessor::freeze_account`], allowing withdrawals again
+    /// (unless it is locked). Returns whether the account was frozen.
+    pub fn unfreeze_account(&mut self, client: ClientId) -> bool {
+        match self.clients.get_mut(&client) {
+            // Only copied from a clone if the flag changes
+            Some(account) if account.frozen => {
+                Arc::make_mut(account).frozen = false;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Whether a client's account is frozen (see
+    /// [`TransactionProcessor::freeze_account`]).
+    #[must_use]
+    pub fn is_frozen(&self, client: ClientId) -> bool {
+        self.clients
+            .get(&client)
+            .is_some_and(|account| account.frozen)
+    }
+
+    /// The current version of the accounts, to pass to
+    /// [`TransactionProcessor::generate_delta_report`] next time. Every
+    /// change to an account advances it.
+    #[must_use]
+    pub const fn report_version(&self) -> ReportVersion {
+        ReportVersion::new(self.changes.version)
+    }
+
+    /// Generates a report of only the accounts which have changed since
+    /// `since` (from [`TransactionProcessor::report_version`] when the last
+    /// report was generated, or [`ReportVersion::INITIAL`] for every
+    /// account), in client ID order.
+    ///
+    /// Changed accounts are tracked as transactions are applied, so this
+    /// only visits the accounts which have changed, however many there are.
+    /// Accounts are excluded as in [`TransactionProcessor::generate_report`].
+    pub fn generate_delta_report(
+        &self,
+        since: ReportVersion,
+    ) -> impl Iterator<Item = ReportEntry> + '_ {
+        let changes = &self.changes.clients;
+        let start = changes.partition_point(|(version, _)| *version <= since.get());
+        let mut clients = changes[start..]
+            .iter()
+            .filter(|(version, client)| {
+                self.clients
+                    .get(client)
+                    .is_some_and(|account| account.version == *version)
+            })
+            .map(|(_, client)| *client)
+            .collect::<Vec<_>>();
+        clients.sort_unstable();
+
+        clients
+            .into_iter()
+            .filter_map(|client| self.account_report(client))
+    }
+
+    /// The number of disputes which have not yet been resolved or charged
+    /// back, across all accounts.
+    #[must_use]
+    pub fn active_disputes(&self) -> usize {
+        self.clients
+            .values()
+            .map(|client_account| client_account.active_disputes.len())
+            .sum()
+    }
+
+    /// Counts the accounts, transactions and disputes held, and estimates
+    /// their heap footprint, e.g. for alerting before a long-running process
+    /// runs out of memory. Every account is visited.
+    #[must_use]
+    pub fn memory_stats(&self) -> MemoryStats {
+        let mut stats = MemoryStats {
+            clients: self.clients.len(),
+            estimated_heap_bytes: self.client_keys.capacity()
+                * (size_of::<(String, ClientId)>() + 1)
+                + self.changes.clients.capacity() * size_of::<(u64, ClientId)>(),
+            ..MemoryStats::default()
+        };
+        stats.estimated_heap_bytes += self.client_keys.keys().map(String::capacity).sum::<usize>();
+        for client_account in self.clients.values() {
+            stats.transactions += client_account.transactions.len();
+            stats.active_disputes += client_account.active_disputes.len();
+            stats.estimated_heap_bytes += client_account.heap_bytes();
+        }
+        stats
+    }
+
+    /// The number of locked accounts.
+    #[must_use]
+    pub fn locked_accounts(&self) -> usize {
+        self.clients
+            .values()
+            .filter(|client_account| client_account.lock.is_some())
+            .count()
+    }
+
+    /// Checks the internal consistency of every account, returning the
+    /// violations found, in client ID order. The list is empty unless there
+    /// is a bug (or the amounts are extreme enough to overflow), so this can
+    /// be run after each batch (e.g. in tests or canaries) to catch one
+    /// early. Every account is visited, so this is as
+    /// slow as generating the whole report.
+    ///
+    /// For each account, the held funds must be the sum of the amounts of
+    /// the active disputes (each of which must be of a recorded
+    /// transaction), the available funds may only be negative if the
+    /// account has active disputes or is locked, and the total funds must not
+    /// overflow.
+    #[must_use]
+    pub fn check_invariants(&self) -> Vec<InvariantViolation> {
+        let mut violations = Vec::new();
+
+        for (&client, account) in &self.clients {
+            let mut amounts = Vec::with_capacity(account.active_disputes.len());
+            for &tx in &account.active_disputes {
+                match account.transactions.get(&tx) {
+                    Some(&amount) => amounts.push(amount),
+                    None => violations
+                        .push(InvariantViolation::UnknownDisputedTransaction { client, tx }),
+                }
+            }
+            let disputed = checked_sum(amounts);
+            if disputed != Some(account.held) {
+                violations.push(InvariantViolation::HeldMismatch {
+                    client,
+                    held: account.held,
+                    disputed,
+                });
+            }
+
+            if account.available.is_negative()
+                && account.active_disputes.is_empty()
+                && account.lock.is_none()
+            {
+                violations.push(InvariantViolation::NegativeAvailable {
+                    client,
+                    available: account.available,
+                });
+            }
+
+            if account.total().is_err() {
+                violations.push(InvariantViolation::TotalOverflow(client));
+            }
+        }
+
+        violations
+    }
+
+    /// Writes the state of every account as pretty-printed JSON, e.g. for
+    /// inspecting or editing it by hand during incident response. Unlike a
+    /// binary snapshot, it is meant to be read by people and other tools:
+    ///
+    /// ```json
+    /// {
+    ///   "version": 1,
+    ///   "accounts": [
+    ///     {
+    ///       "client": 1,
+    ///       "key": null,
+    ///       "available": "-1",
+    ///       "held": "3",
+    ///       "lock": {"reason": "chargeback", "tx": 4},
+    ///       "frozen": false,
+    ///       "transactions": [{"tx": 1, "amount": "3"}, {"tx": 2, "amount": "-1"}],
+    ///       "disputes": [1],
+    ///       "resolved": []
+    ///     }
+    ///   ]
+    /// }
+    /// ```
+    ///
+    /// `version` is [`export::EXPORT_VERSION`]. Accounts are in client ID
+    /// order, with the key they were created for (if any) and their funds as
+    /// strings. `lock` is `null` unless the account is locked, and its
+    /// `reason` is `chargeback` (with the charged back `tx`), `admin` or
+    /// `unknown`. `transactions` are the deposits (with positive amounts) and
+    /// withdrawals (with negative ones) which can still be disputed, and
+    /// `disputes` and `resolved` are the IDs of those under an active
+    /// dispute, and of those which can't be disputed again. As with
+    /// snapshots, options such as
+    /// [`TransactionProcessor::with_precision_policy`] are not included.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the state could not be encoded or written.
+    #[cfg(feature = "jsonl")]
+    pub fn export_json(&self, writer: impl std::io::Write) -> Result<(), export::ExportError> {
+        export::write_state(self, writer)
+    }
+
+    /// Restores a processor from JSON written by
+    /// [`TransactionProcessor::export_json`], possibly edited by hand. Of each
+    /// account, only `client`, `available` and `held` are required; the
+    /// rest default to empty. The processor has default options, which can
+    /// be changed using its builder methods.
+    ///
+    /// Like snapshots, the state is only checked for disputes of unknown
+    /// transactions, and clients or keys which appear more than once. Use
+    /// [`TransactionProcessor::check_invariants`] to check that hand-edited
+    /// balances are consistent with the disputes.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the data is not valid JSON of this structure
+    /// (including a field which isn't part of it, e.g. a misspelt one), was
+    /// written by an unsupported version, or describes an invalid state.
+    #[cfg(feature = "jsonl")]
+    pub fn import_json(reader: impl std::io::Read) -> Result<Self, export::ExportError> {
+        export::read_state(reader)
+    }
+
+    /// Convenience method to convert the report generated by
+    /// [`TransactionProcessor::generate_report`], including every account,
+    /// into a `Vec`. Useful for testing purposes.
+    #[must_use]
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all))]
+    pub fn generate_report_as_vec(&self) -> Vec<ReportEntry> {
+        self.generate_report(&ReportFilter::new(), ReportOrder::ClientId)
+            .collect()
+    }
+}
+
+/// A client identifier. With the `wide-ids` feature, this is a `u64`.
+#[cfg(not(feature = "wide-ids"))]
+pub type ClientId = u16;
+/// A client identifier. Without the `wide-ids` feature, this is a `u16`.
+#[cfg(feature = "wide-ids")]
+pub type ClientId = u64;
+/// A transaction identifier. With the `wide-ids` feature, this is a `u64`.
+#[cfg(not(feature = "wide-ids"))]
+pub type TransactionId = u32;
+/// A transaction identifier. Without the `wide-ids` feature, this is a
+/// `u32`.
+#[cfg(feature = "wide-ids")]
+pub type TransactionId = u64;
+
+/// A struct representing a transaction.
+///
+/// With the `serde` feature, transactions are serialized as a single object
+/// tagged with their type, e.g.
+/// `{"type":"deposit","client":1,"tx":2,"amount":"1.5"}`. Amounts are
+/// represented as strings so that no precision is lost. For the flat
+/// CSV-style representation, see [`flat::FlatTransaction`].
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Transaction {
+    /// The client account which the transaction applies to.
+    client: ClientId,
+    /// The transaction ID. In the case of deposits and withdrawals, this
+    /// should be a new transaction ID. In the case of disputes, resolutions,
+    /// and chargebacks, this should be the ID of an existing transaction.
+    tx: TransactionId,
+    /// The type of the transaction, and associated data where relevant.
+    #[cfg_attr(feature = "serde", serde(flatten))]
+    transaction_type: TransactionType,
+    /// A key identifying this submission of the transaction, if any, so that
+    /// a resubmission can be recognised (see [`idempotency::IdempotencyCache`]).
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    idempotency_key: Option<String>,
+    /// The position of the transaction in its feed, if the feed numbers its
+    /// records (see [`sequence::Sequencer`]).
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    sequence: Option<u64>,
+    /// When the transaction happened, if known (e.g. for
+    /// [`aggregate::Aggregator`]).
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    timestamp: Option<Timestamp>,
+    /// The key of the client, if it is known by a key (such as a UUID)
+    /// rather than an ID (see [`Transaction::with_client_key`]).
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    client_key: Option<String>,
+}
+
+impl Transaction {
+    /// Creates a new [Transaction] instance.
+    ///
+    /// * `client` - The client account which the transaction applies to.
+    /// * `tx` - The transaction ID. In the case of deposits and withdrawals,
+    ///   this should be a new transaction ID. In the case of disputes,
+    ///   resolutions, and chargebacks, this should be the ID of an existing
+    ///   transaction.
+    /// * `transaction_type` - The type of the transaction, and associated data
+    ///   where relevant.
+    #[must_use]
+    pub const fn new(
+        client: ClientId,
+        tx: TransactionId,
+        transaction_type: TransactionType,
+    ) -> Self {
+        Self {
+            client,
+            tx,
+            transaction_type,
+            idempotency_key: None,
+            sequence: None,
+            timestamp: None,
+            client_key: None,
+        }
+    }
+
+    /// Returns this transaction with the specified idempotency key.
+    #[must_use]
+    pub fn with_idempotency_key(self, key: impl Into<String>) -> Self {
+        Self {
+            idempotency_key: Some(key.into()),
+            ..self
+        }
+    }
+
+    /// Returns this transaction with the specified sequence number.
+    #[must_use]
+    pub fn with_sequence(self, sequence: u64) -> Self {
+        Self {
+            sequence: Some(sequence),
+            ..self
+        }
+    }
+
+    /// Returns this transaction with the specified timestamp.
+    #[must_use]
+    pub fn with_timestamp(self, timestamp: Timestamp) -> Self {
+        Self {
+            timestamp: Some(timestamp),
+            ..self
+        }
+    }
+
+    /// Returns this transaction for the client with the specified key, such
+    /// as a UUID, rather than its client ID. The processor gives the key's
+    /// account the lowest client ID without an account when it is first
+    /// seen, and uses that account for every later transaction with the key,
+    /// ignoring [`Transaction::client`]. Reports then show the key in place
+    /// of the ID.
+    #[must_use]
+    pub fn with_client_key(self, key: impl Into<String>) -> Self {
+        Self {
+            client_key: Some(key.into()),
+            ..self
+        }
+    }
+
+    /// The client account which the transaction applies to.
+    #[must_use]
+    pub const fn client(&self) -> ClientId {
+        self.client
+    }
+
+    /// The transaction ID.
+    #[must_use]
+    pub const fn tx(&self) -> TransactionId {
+        self.tx
+    }
+
+    /// The type of the transaction, and associated data where relevant.
+    #[must_use]
+    pub const fn transaction_type(&self) -> &TransactionType {
+        &self.transaction_type
+    }
+
+    /// The key identifying this submission of the transaction, if any.
+    #[must_use]
+    pub fn idempotency_key(&self) -> Option<&str> {
+        self.idempotency_key.as_deref()
+    }
+
+    /// The position of the transaction in its feed, if any.
+    #[must_use]
+    pub const fn sequence(&self) -> Option<u64> {
+        self.sequence
+    }
+
+    /// When the transaction happened, if known.
+    #[must_use]
+    pub const fn timestamp(&self) -> Option<Timestamp> {
+        self.timestamp
+    }
+
+    /// The key of the client, if it is known by a key rather than an ID.
+    #[must_use]
+    pub fn client_key(&self) -> Option<&str> {
+        self.client_key.as_deref()
+    }
+}
+
+/// A human-readable description of the transaction for logs, e.g.
+/// `deposit 1.5 by client 1 (tx 2)` or `dispute by client 1 (tx 2)`. The
+/// client is given by its key, if it has one. With the `redact` feature, the
+/// amount is masked (see [`CurrencyAmount::for_log`]).
+impl Display for Transaction {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        f.write_str(self.transaction_type.name())?;
+        if let TransactionType::Deposit { amount } | TransactionType::Withdrawal { amount } =
+            &self.transaction_type
+        {
+            write!(f, " {}", amount.for_log())?;
+        }
+        match &self.client_key {
+            Some(key) => write!(f, " by client '{}'", key)?,
+            None => write!(f, " by client {}", self.client)?,
+        }
+        write!(f, " (tx {})", self.tx)
+    }
+}
+
+/// The type of a transaction, and associated data where relevant.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(tag = "type", rename_all = "lowercase"))]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum TransactionType {
+    /// Increases the available funds by the specified amount.
+    Deposit {
+        /// The amount by which to increase the available funds.
+        amount: CurrencyAmount,
+    },
+    /// Reduces the available funds by the specified amount.
+    Withdrawal {
+        /// The amount by which to decrease the avaiable funds.
+        amount: CurrencyAmount,
+    },
+    /// Disputes the specified transaction in the user's account. All changes
+    /// occur atomically.
+    ///
+    /// This will transfer the value of the transaction from the available
+    /// funds to the held funds, and mark the transaction as disputed.
+    Dispute,
+    /// Resolves a dispute, moving the previously held funds back into
+    /// the available balance.
+    Resolve,
+    /// Performs a chargeback, removing the funds from the held balance,
+    /// and locking the account.
+    ///
+    /// After a chargeback is performed, the transaction cannot be disputed
+    /// again.
+    Chargeback,
+}
+
+#[cfg(all(test, feature = "std"))]
+mod test {
+    use std::collections::HashSet;
+    use std::str::FromStr;
+    use std::sync::Arc;
+
+    use crate::numeric::{CurrencyError, PrecisionPolicy};
+    use crate::report::{
+        ClientSet, LockedEntry, NegativeBalanceCause, NegativeBalanceEntry, ReportFilter,
+        ReportOrder, ReportVersion,
+    };
+    use crate::{
+        checked_sum, AccountCreationPolicy, ClientId, CurrencyAmount, InvariantViolation,
+        LockReason, LockedAccountPolicy, MergeError, RedisputePolicy, ReportEntry, Transaction,
+        TransactionError, TransactionId, TransactionLimitAction, TransactionProcessor,
+        TransactionType,
+    };
+
+    #[test]
+    fn test_deposit_withdraw() {
+        let mut tp = TransactionProcessor::new();
+
+        assert_eq!(
+            tp.generate_report(&ReportFilter::new(), ReportOrder::ClientId)
+                .count(),
+            0
+        );
+
+        // Try withdrawing some money, should fail
+        assert_eq!(
+            Err(TransactionError::NotEnoughFunds {
+                client: 1,
+                transaction_type: TransactionType::Withdrawal {
+                    amount: CurrencyAmount::from_str("100").unwrap()
+                },
+                available: CurrencyAmount::ZERO,
+            }),
+            tp.transact(&Transaction::new(
+                1,
+                2,
+                TransactionType::Withdrawal {
+                    amount: CurrencyAmount::from_str("100").unwrap()
+                }
+            ))
+        );
+
+        // Account should exist, but contain no money
+        assert_eq!(
+            vec![ReportEntry {
+                client: 1,
+                client_key: None,
+                available: CurrencyAmount::ZERO,
+                held: CurrencyAmount::ZERO,
+                total: CurrencyAmount::ZERO,
+                locked: false
+            }],
+            tp.generate_report_as_vec()
+        );
+
+        let fifty = CurrencyAmount::from_str("50").unwrap();
+
+        // Deposit 50
+        tp.transact(&Transaction::new(
+            1,
+            2,
+            TransactionType::Deposit { amount: fifty },
+        ))
+        .unwrap();
+
+        assert_eq!(
+            vec![ReportEntry {
+                client: 1,
+                client_key: None,
+                available: fifty,
+                held: CurrencyAmount::ZERO,
+                total: fifty,
+                locked: false
+            }],
+            tp.generate_report_as_vec()
+        );
+
+        // Try (and fail) to withdraw 100
+        assert_eq!(
+            Err(TransactionError::NotEnoughFunds {
+                client: 1,
+                transaction_type: TransactionType::Withdrawal {
+                    amount: CurrencyAmount::from_str("100").unwrap()
+                },
+                available: fifty,
+            }),
+            tp.transact(&Transaction::new(
+                1,
+                2,
+                TransactionType::Withdrawal {
+                    amount: CurrencyAmount::from_str("100").unwrap()
+                }
+            ))
+        );
+
+        // Account is unchanged
+        assert_eq!(
+            vec![ReportEntry {
+                client: 1,
+                client_key: None,
+                available: fifty,
+                held: CurrencyAmount::ZERO,
+                total: fifty,
+                locked: false
+            }],
+            tp.generate_report_as_vec()
+        );
+
+        // Withdraw all (50)
+        tp.transact(&Transaction::new(
+            1,
+            3,
+            TransactionType::Withdrawal { amount: fifty },
+        ))
+        .unwrap();
+
+        assert_eq!(
+            vec![ReportEntry {
+                client: 1,
+                client_key: None,
+                available: CurrencyAmount::ZERO,
+                held: CurrencyAmount::ZERO,
+                total: CurrencyAmount::ZERO,
+                locked: false
+            }],
+            tp.generate_report_as_vec()
+        );
+    }
+
+    #[test]
+    fn test_precision_policy() {
+        let deposit = |tx, amount| {
+            Transaction::new(
+                1,
+                tx,
+                TransactionType::Deposit {
+                    amount: CurrencyAmount::from_str(amount).unwrap(),
+                },
+            )
+        };
+
+        let mut tp = TransactionProcessor::new();
+        tp.transact(&deposit(1, "1.00005")).unwrap();
+        assert_eq!(
+            CurrencyAmount::from_str("1.00005").unwrap(),
+            tp.generate_report_as_vec()[0].available
+        );
+
+        let mut tp = TransactionProcessor::new().with_precision_policy(PrecisionPolicy::Reject);
+        assert_eq!(
+            Err(TransactionError::CurrencyError(
+                CurrencyError::PrecisionLoss
+            )),
+            tp.transact(&deposit(1, "1.00005"))
+        );
+        tp.transact(&deposit(2, "1.0001")).unwrap();
+        assert_eq!(
+            CurrencyAmount::from_str("1.0001").unwrap(),
+            tp.generate_report_as_vec()[0].available
+        );
+
+        let mut tp =
+            TransactionProcessor::new().with_precision_policy(PrecisionPolicy::RoundHalfUp);
+        tp.transact(&deposit(1, "1.00005")).unwrap();
+        assert_eq!(
+            CurrencyAmount::from_str("1.0001").unwrap(),
+            tp.generate_report_as_vec()[0].available
+        );
+
+        let mut tp = TransactionProcessor::new().with_precision_policy(PrecisionPolicy::Truncate);
+        tp.transact(&deposit(1, "1.00009")).unwrap();
+        tp.transact(&Transaction::new(
+            1,
+            2,
+            TransactionType::Withdrawal {
+                amount: CurrencyAmount::from_str("0.00009").unwrap(),
+            },
+        ))
+        .unwrap();
+        assert_eq!(
+            CurrencyAmount::from_str("1").unwrap(),
+            tp.generate_report_as_vec()[0].available
+        );
+    }
+
+    #[test]
+    fn test_idempotent_replays() {
+        let deposit = |tx, amount| {
+            Transaction::new(
+                1,
+                tx,
+                TransactionType::Deposit {
+                    amount: CurrencyAmount::from_str(amount).unwrap(),
+                },
+            )
+        };
+        let withdrawal = Transaction::new(
+            1,
+            2,
+            TransactionType::Withdrawal {
+                amount: CurrencyAmount::from_str("1").unwrap(),
+            },
+        );
+
+        let mut tp = TransactionProcessor::new();
+        tp.transact(&deposit(1, "5")).unwrap();
+        assert_eq!(
+            Err(TransactionError::TransactionAlreadyExists { client: 1, tx: 1 }),
+            tp.transact(&deposit(1, "5"))
+        );
+
+        let mut tp = TransactionProcessor::new().with_idempotent_replays(true);
+        tp.transact(&deposit(1, "5")).unwrap();
+        tp.transact(&withdrawal).unwrap();
+        let version = tp.report_version();
+        assert_eq!(
+            Err(TransactionError::Replayed(1)),
+            tp.transact(&deposit(1, "5.00"))
+        );
+        assert_eq!(Err(TransactionError::Replayed(2)), tp.transact(&withdrawal));
+        assert_eq!(version, tp.report_version());
+
+        // A different amount or type is a conflict
+        assert_eq!(
+            Err(TransactionError::TransactionAlreadyExists { client: 1, tx: 1 }),
+            tp.transact(&deposit(1, "6"))
+        );
+        assert_eq!(
+            Err(TransactionError::TransactionAlreadyExists { client: 1, tx: 2 }),
+            tp.transact(&deposit(2, "1"))
+        );
+
+        // Replays are recognised even if they'd now be invalid
+        tp.transact(&Transaction::new(1, 1, TransactionType::Dispute))
+            .unwrap();
+        tp.lock_account(1);
+        assert_eq!(
+            Err(TransactionError::Replayed(1)),
+            tp.transact(&deposit(1, "5"))
+        );
+        assert_eq!(
+            vec![ReportEntry {
+                client: 1,
+                client_key: None,
+                available: CurrencyAmount::from_str("-1").unwrap(),
+                held: CurrencyAmount::from_str("5").unwrap(),
+                total: CurrencyAmount::from_str("4").unwrap(),
+                locked: true,
+            }],
+            tp.generate_report_as_vec()
+        );
+    }
+
+    #[test]
+    fn test_freeze_account() {
+        let amount = |amount| CurrencyAmount::from_str(amount).unwrap();
+        let withdrawal = |tx, value| {
+            Transaction::new(
+                1,
+                tx,
+                TransactionType::Withdrawal {
+                    amount: amount(value),
+                },
+            )
+        };
+
+        let mut tp = TransactionProcessor::new();
+        tp.transact(&Transaction::new(
+            1,
+            1,
+            TransactionType::Deposit {
+                amount: amount("10"),
+            },
+        ))
+        .unwrap();
+        tp.freeze_account(1);
+        assert!(tp.is_frozen(1));
+        assert_eq!(
+            Err(TransactionError::AccountIsFrozen {
+                client: 1,
+                amount: amount("1"),
+            }),
+            tp.transact(&withdrawal(2, "1"))
+        );
+
+        // Deposits and disputes are still applied, and the account isn't
+        // reported as locked
+        tp.transact(&Transaction::new(
+            1,
+            3,
+            TransactionType::Deposit {
+                amount: amount("5"),
+            },
+        ))
+        .unwrap();
+        tp.transact(&Transaction::new(1, 3, TransactionType::Dispute))
+            .unwrap();
+        tp.transact(&Transaction::new(1, 3, TransactionType::Resolve))
+            .unwrap();
+        assert!(!tp.generate_report_as_vec()[0].locked);
+
+        assert!(tp.unfreeze_account(1));
+        let clone = tp.clone();
+        assert!(!tp.unfreeze_account(1));
+        assert!(Arc::ptr_eq(&tp.clients[&1], &clone.clients[&1]));
+        assert_eq!(clone.report_version(), tp.report_version());
+        tp.transact(&withdrawal(2, "1")).unwrap();
+        assert_eq!(amount("14"), tp.generate_report_as_vec()[0].total);
+
+        // A client can be frozen before their first transaction
+        tp.freeze_account(2);
+        assert_eq!(2, tp.generate_report_as_vec().len());
+        assert!(!tp.is_frozen(3));
+    }
+
+    #[test]
+    fn test_locked_account_policy() {
+        let amount = |amount| CurrencyAmount::from_str(amount).unwrap();
+        let deposit = |tx, value| {
+            Transaction::new(
+                1,
+                tx,
+                TransactionType::Deposit {
+                    amount: amount(value),
+                },
+            )
+        };
+        let withdrawal = Transaction::new(
+            1,
+            4,
+            TransactionType::Withdrawal {
+                amount: amount("1"),
+            },
+        );
+
+        let rejected = |transaction: &Transaction| {
+            Err(TransactionError::AccountIsLocked {
+                client: 1,
+                transaction_type: transaction.transaction_type().clone(),
+            })
+        };
+
+        let locked = |policy| {
+            let mut tp = TransactionProcessor::new().with_locked_account_policy(policy);
+            tp.transact(&deposit(1, "10")).unwrap();
+            tp.transact(&deposit(2, "5")).unwrap();
+            tp.lock_account(1);
+            tp
+        };
+
+        // By default, only disputes are processed
+        let mut tp = locked(LockedAccountPolicy::new());
+        assert_eq!(rejected(&deposit(3, "1")), tp.transact(&deposit(3, "1")));
+        assert_eq!(rejected(&withdrawal), tp.transact(&withdrawal));
+        tp.transact(&Transaction::new(1, 2, TransactionType::Dispute))
+            .unwrap();
+
+        let mut tp = locked(
+            LockedAccountPolicy::new()
+                .with_deposits(true)
+                .with_disputes(false),
+        );
+        tp.transact(&deposit(3, "1")).unwrap();
+        assert_eq!(rejected(&withdrawal), tp.transact(&withdrawal));
+        assert_eq!(
+            rejected(&Transaction::new(1, 2, TransactionType::Dispute)),
+            tp.transact(&Transaction::new(1, 2, TransactionType::Dispute))
+        );
+        // The policy applies to processors created like this one
+        assert_eq!(
+            tp.locked_account_policy,
+            tp.new_like().locked_account_policy
+        );
+
+        let mut tp = locked(LockedAccountPolicy::new().with_withdrawals(true));
+        tp.transact(&withdrawal).unwrap();
+        assert_eq!(
+            vec![ReportEntry {
+                client: 1,
+                client_key: None,
+                available: amount("14"),
+                held: CurrencyAmount::ZERO,
+                total: amount("14"),
+                locked: true,
+            }],
+            tp.generate_report_as_vec()
+        );
+    }
+
+    #[test]
+    fn test_account_creation_policy() {
+        let deposit = |client| {
+            Transaction::new(
+                client,
+                TransactionId::from(client),
+                TransactionType::Deposit {
+                    amount: CurrencyAmount::from_str("1").unwrap(),
+                },
+            )
+        };
+        let dispute = |client| {
+            Transaction::new(
+                client,
+                TransactionId::from(client),
+                TransactionType::Dispute,
+            )
+        };
+
+        let mut tp = TransactionProcessor::new()
+            .with_account_creation_policy(AccountCreationPolicy::RejectUnknown);
+        tp.register_clients(&ClientSet::new([1..=2]));
+        tp.register_clients(&ClientSet::new([5..=5]));
+        for client in [1, 2, 5] {
+            tp.transact(&deposit(client)).unwrap();
+        }
+        assert_eq!(
+            Err(TransactionError::UnknownClient(3)),
+            tp.transact(&deposit(3))
+        );
+        // Registered clients are only reported once they have an account,
+        // and are known to processors created like this one
+        assert_eq!(3, tp.generate_report_as_vec().len());
+        tp.new_like().transact(&deposit(5)).unwrap();
+
+        let mut tp = TransactionProcessor::new()
+            .with_account_creation_policy(AccountCreationPolicy::OnDeposit);
+        assert_eq!(
+            Err(TransactionError::UnknownClient(1)),
+            tp.transact(&dispute(1))
+        );
+        assert!(tp.generate_report_as_vec().is_empty());
+        tp.transact(&deposit(1)).unwrap();
+        tp.transact(&dispute(1)).unwrap();
+    }
+
+    #[test]
+    fn test_would_succeed() {
+        let amount = |amount| CurrencyAmount::from_str(amount).unwrap();
+        let deposit = |tx, value| {
+            Transaction::new(
+                1,
+                tx,
+                TransactionType::Deposit {
+                    amount: amount(value),
+                },
+            )
+        };
+        let withdrawal = Transaction::new(
+            1,
+            2,
+            TransactionType::Withdrawal {
+                amount: amount("7"),
+            },
+        );
+        let dispute = |tx| Transaction::new(1, tx, TransactionType::Dispute);
+
+        // Nothing is created for a transaction which would succeed
+        let mut tp = TransactionProcessor::new();
+        assert_eq!(Ok(()), tp.would_succeed(&deposit(1, "5")));
+        assert_eq!(
+            Ok(()),
+            tp.would_succeed(&deposit(1, "5").with_client_key("acme"))
+        );
+        assert!(tp.generate_report_as_vec().is_empty());
+        assert_eq!(
+            None,
+            tp.transaction_client(&dispute(1).with_client_key("acme"))
+        );
+
+        tp.transact(&deposit(1, "5")).unwrap();
+        let report = tp.generate_report_as_vec();
+        assert_eq!(
+            Err(TransactionError::NotEnoughFunds {
+                client: 1,
+                transaction_type: withdrawal.transaction_type().clone(),
+                available: amount("5"),
+            }),
+            tp.would_succeed(&withdrawal)
+        );
+        assert_eq!(
+            Err(TransactionError::TransactionAlreadyExists { client: 1, tx: 1 }),
+            tp.would_succeed(&deposit(1, "1"))
+        );
+        assert_eq!(
+            Err(TransactionError::TransactionDoesNotExist { client: 1, tx: 9 }),
+            tp.would_succeed(&dispute(9))
+        );
+        assert_eq!(
+            Err(TransactionError::DisputeDoesNotExist { client: 1, tx: 1 }),
+            tp.would_succeed(&Transaction::new(1, 1, TransactionType::Resolve))
+        );
+
+        // The checks leave the account as it was
+        assert_eq!(Ok(()), tp.would_succeed(&dispute(1)));
+        assert_eq!(report, tp.generate_report_as_vec());
+        tp.transact(&dispute(1)).unwrap();
+        assert_eq!(
+            Err(TransactionError::DisputeAlreadyExists { client: 1, tx: 1 }),
+            tp.would_succeed(&dispute(1))
+        );
+
+        let tp = TransactionProcessor::new()
+            .with_account_creation_policy(AccountCreationPolicy::RejectUnknown);
+        assert_eq!(
+            Err(TransactionError::UnknownClient(1)),
+            tp.would_succeed(&deposit(1, "5"))
+        );
+    }
+
+    #[test]
+    fn test_error_codes() {
+        let one = CurrencyAmount::from_str("1").unwrap();
+        let errors = [
+            TransactionError::TransactionDoesNotExist { client: 1, tx: 1 },
+            TransactionError::TransactionAlreadyExists { client: 1, tx: 1 },
+            TransactionError::DisputeAlreadyExists { client: 1, tx: 1 },
+            TransactionError::DisputeDoesNotExist { client: 1, tx: 1 },
+            TransactionError::CurrencyError(CurrencyError::OutOfBounds),
+            TransactionError::AccountIsLocked {
+                client: 1,
+                transaction_type: TransactionType::Dispute,
+            },
+            TransactionError::NotEnoughFunds {
+                client: 1,
+                transaction_type: TransactionType::Withdrawal { amount: one },
+                available: CurrencyAmount::ZERO,
+            },
+            TransactionError::Replayed(1),
+            TransactionError::BalanceCapExceeded {
+                client: 1,
+                amount: one,
+                max_balance: CurrencyAmount::ZERO,
+            },
+            TransactionError::TransactionLimitReached { client: 1 },
+            TransactionError::AccountIsFrozen {
+                client: 1,
+                amount: one,
+            },
+            TransactionError::DisputeNotAllowed { client: 1, tx: 1 },
+            TransactionError::UnknownClient(1),
+            TransactionError::ClientIdsExhausted,
+        ];
+        let codes = errors
+            .iter()
+            .map(TransactionError::code)
+            .collect::<HashSet<_>>();
+        assert_eq!(errors.len(), codes.len());
+        assert!(codes
+            .iter()
+            .all(|code| code.chars().all(|c| c.is_ascii_uppercase() || c == '_')));
+        assert_eq!("TX_EXISTS", errors[1].code());
+        assert_eq!("INSUFFICIENT_FUNDS", errors[6].code());
+        assert_eq!(
+            vec![&errors[0], &errors[3]],
+            errors
+                .iter()
+                .filter(|e| e.is_transient())
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_transaction_display() {
+        let amount = if cfg!(feature = "redact") {
+            "<redacted>"
+        } else {
+            "1.5"
+        };
+        let deposit = Transaction::new(
+            1,
+            2,
+            TransactionType::Deposit {
+                amount: CurrencyAmount::from_str("1.5").unwrap(),
+            },
+        );
+        assert_eq!(
+            format!("deposit {} by client 1 (tx 2)", amount),
+            deposit.to_string()
+        );
+        assert!(format!("{:?}", deposit).contains(&format!("value: {}", amount)));
+        assert_eq!(
+            "dispute by client 'acme-7' (tx 2)",
+            Transaction::new(1, 2, TransactionType::Dispute)
+                .with_client_key("acme-7")
+                .to_string()
+        );
+    }
+
+    #[test]
+    fn test_client_keys() {
+        let deposit = |key: &str, tx| {
+            Transaction::new(
+                0,
+                tx,
+                TransactionType::Deposit {
+                    amount: CurrencyAmount::from_str("1").unwrap(),
+                },
+            )
+            .with_client_key(key)
+        };
+        let alice = "3f2b9c1e-6d4a-4f7b-9a8e-1c2d3e4f5a6b";
+
+        // Keys get the lowest IDs without an account, and keep them
+        let mut tp = TransactionProcessor::new();
+        tp.transact(&Transaction::new(
+            1,
+            1,
+            TransactionType::Deposit {
+                amount: CurrencyAmount::from_str("5").unwrap(),
+            },
+        ))
+        .unwrap();
+        tp.transact(&deposit(alice, 2)).unwrap();
+        tp.transact(&deposit("GB33BUKB20201555555555", 3)).unwrap();
+        tp.transact(&deposit(alice, 4)).unwrap();
+        assert_eq!(
+            Err(TransactionError::TransactionAlreadyExists { client: 0, tx: 4 }),
+            tp.transact(&deposit(alice, 4))
+        );
+        assert_eq!(Some(0), tp.transaction_client(&deposit(alice, 5)));
+        assert_eq!(None, tp.transaction_client(&deposit("unknown", 5)));
+
+        let report = tp.generate_report_as_vec();
+        assert_eq!(
+            vec![
+                (0, Some(alice), CurrencyAmount::from_str("2").unwrap()),
+                (1, None, CurrencyAmount::from_str("5").unwrap()),
+                (
+                    2,
+                    Some("GB33BUKB20201555555555"),
+                    CurrencyAmount::from_str("1").unwrap()
+                ),
+            ],
+            report
+                .iter()
+                .map(|entry| (entry.client(), entry.client_key(), entry.total()))
+                .collect::<Vec<_>>()
+        );
+
+        // Keys are merged with their accounts
+        let mut merged = TransactionProcessor::new();
+        merged.merge(tp).unwrap();
+        assert_eq!(
+            Some(2),
+            merged.transaction_client(&deposit("GB33BUKB20201555555555", 5))
+        );
+    }
+
+    #[test]
+    fn test_max_balance() {
+        let amount = |amount| CurrencyAmount::from_str(amount).unwrap();
+        let deposit = |tx, value| {
+            Transaction::new(
+                1,
+                tx,
+                TransactionType::Deposit {
+                    amount: amount(value),
+                },
+            )
+        };
+
+        let mut tp = TransactionProcessor::new().with_max_balance(amount("10"));
+        tp.transact(&deposit(1, "6")).unwrap();
+        tp.transact(&Transaction::new(1, 1, TransactionType::Dispute))
+            .unwrap();
+        // Held funds count towards the balance
+        assert_eq!(
+            Err(TransactionError::BalanceCapExceeded {
+                client: 1,
+                amount: amount("4.01"),
+                max_balance: amount("10"),
+            }),
+            tp.transact(&deposit(2, "4.01"))
+        );
+        tp.transact(&deposit(2, "4")).unwrap();
+
+        // The cap applies to processors created like this one
+        let mut other = tp.new_like();
+        assert_eq!(
+            Err(TransactionError::BalanceCapExceeded {
+                client: 1,
+                amount: amount("11"),
+                max_balance: amount("10"),
+            }),
+            other.transact(&deposit(1, "11"))
+        );
+
+        // Withdrawals are unaffected, and make room for later deposits
+        tp.transact(&Transaction::new(
+            1,
+            3,
+            TransactionType::Withdrawal {
+                amount: amount("3"),
+            },
+        ))
+        .unwrap();
+        tp.transact(&deposit(4, "3")).unwrap();
+        assert_eq!(amount("10"), tp.generate_report_as_vec()[0].total);
+    }
+
+    #[test]
+    fn test_transaction_limit() {
+        let deposit = |tx| {
+            Transaction::new(
+                1,
+                tx,
+                TransactionType::Deposit {
+                    amount: CurrencyAmount::from_str("1").unwrap(),
+                },
+            )
+        };
+        let dispute = |tx| Transaction::new(1, tx, TransactionType::Dispute);
+
+        let mut tp =
+            TransactionProcessor::new().with_transaction_limit(2, TransactionLimitAction::Reject);
+        tp.transact(&deposit(1)).unwrap();
+        tp.transact(&deposit(2)).unwrap();
+        assert_eq!(
+            Err(TransactionError::TransactionLimitReached { client: 1 }),
+            tp.transact(&deposit(3))
+        );
+        // The limit is per account
+        tp.transact(&Transaction::new(
+            2,
+            3,
+            TransactionType::Deposit {
+                amount: CurrencyAmount::from_str("1").unwrap(),
+            },
+        ))
+        .unwrap();
+
+        let mut tp = TransactionProcessor::new()
+            .with_transaction_limit(2, TransactionLimitAction::EvictOldest);
+        tp.transact(&deposit(1)).unwrap();
+        tp.transact(&deposit(2)).unwrap();
+        tp.transact(&dispute(1)).unwrap();
+        // The disputed transaction is kept, and the next oldest evicted
+        tp.transact(&deposit(3)).unwrap();
+        assert_eq!(
+            Err(TransactionError::TransactionDoesNotExist { client: 1, tx: 2 }),
+            tp.transact(&dispute(2))
+        );
+        tp.transact(&dispute(3)).unwrap();
+        // Nothing can be evicted while everything is disputed
+        assert_eq!(
+            Err(TransactionError::TransactionLimitReached { client: 1 }),
+            tp.transact(&deposit(4))
+        );
+        tp.transact(&Transaction::new(1, 1, TransactionType::Resolve))
+            .unwrap();
+        tp.transact(&deposit(4)).unwrap();
+        assert_eq!(
+            Err(TransactionError::TransactionDoesNotExist { client: 1, tx: 1 }),
+            tp.transact(&dispute(1))
+        );
+        // Evicted transactions still count towards the balance
+        assert_eq!(
+            CurrencyAmount::from_str("4").unwrap(),
+            tp.generate_report_as_vec()[0].total
+        );
+    }
 
-    use crate::{
-        CurrencyAmount, ReportEntry, Transaction, TransactionError, TransactionProcessor,
-        TransactionType,
-    };
+    #[test]
+    fn test_check_invariants() {
+        let mut tp = TransactionProcessor::new();
+        let amount = CurrencyAmount::from_str("10").unwrap();
+        for transaction in [
+            Transaction::new(1, 1, TransactionType::Deposit { amount }),
+            Transaction::new(1, 2, TransactionType::Withdrawal { amount }),
+            // Takes the available funds below zero, then locks the account
+            Transaction::new(1, 1, TransactionType::Dispute),
+            Transaction::new(2, 3, TransactionType::Deposit { amount }),
+            Transaction::new(2, 3, TransactionType::Dispute),
+            Transaction::new(1, 1, TransactionType::Chargeback),
+            Transaction::new(3, 5, TransactionType::Deposit { amount }),
+        ] {
+            tp.transact(&transaction).unwrap();
+        }
+        assert_eq!(Vec::<InvariantViolation>::new(), tp.check_invariants());
+
+        let account = Arc::make_mut(tp.clients.get_mut(&2).unwrap());
+        account.held = CurrencyAmount::ZERO;
+        account.active_disputes.insert(4);
+        let account = Arc::make_mut(tp.clients.get_mut(&3).unwrap());
+        account.available = -amount;
+        assert_eq!(
+            vec![
+                InvariantViolation::UnknownDisputedTransaction { client: 2, tx: 4 },
+                InvariantViolation::HeldMismatch {
+                    client: 2,
+                    held: CurrencyAmount::ZERO,
+                    disputed: Some(amount),
+                },
+                InvariantViolation::NegativeAvailable {
+                    client: 3,
+                    available: -amount,
+                },
+            ],
+            tp.check_invariants()
+        );
+    }
 
     #[test]
-    fn test_deposit_withdraw() {
+    fn test_checked_sum() {
+        let large = (CurrencyAmount::MAX - CurrencyAmount::from_str("1").unwrap()).unwrap();
+        let one = CurrencyAmount::from_str("1").unwrap();
+
+        // Adding in order would overflow after the first two amounts
+        assert_eq!(Some(large), checked_sum([large, large, -large]));
+        assert_eq!(Some(-large), checked_sum([-large, -large, large]));
+        assert_eq!(Some(one), checked_sum([large, large, -large, -large, one]));
+        assert_eq!(None, checked_sum([large, large, -one]));
+        assert_eq!(Some(CurrencyAmount::ZERO), checked_sum([]));
+
+        // Disputes of opposing large deposits and withdrawals, which only
+        // fit in the held funds when added in a suitable order
         let mut tp = TransactionProcessor::new();
+        for transaction in [
+            Transaction::new(1, 1, TransactionType::Deposit { amount: large }),
+            Transaction::new(1, 2, TransactionType::Withdrawal { amount: large }),
+            Transaction::new(1, 3, TransactionType::Deposit { amount: large }),
+            Transaction::new(1, 4, TransactionType::Withdrawal { amount: large }),
+            Transaction::new(1, 5, TransactionType::Deposit { amount: large }),
+            Transaction::new(1, 1, TransactionType::Dispute),
+            Transaction::new(1, 2, TransactionType::Dispute),
+            Transaction::new(1, 3, TransactionType::Dispute),
+            Transaction::new(1, 4, TransactionType::Dispute),
+            Transaction::new(1, 5, TransactionType::Dispute),
+        ] {
+            tp.transact(&transaction).unwrap();
+        }
+        assert_eq!(large, tp.account_report(1).unwrap().held);
+        assert_eq!(Vec::<InvariantViolation>::new(), tp.check_invariants());
+    }
 
-        assert_eq!(tp.generate_report().count(), 0);
+    #[test]
+    fn test_saturating_totals() {
+        let large = (CurrencyAmount::MAX - CurrencyAmount::from_str("1").unwrap()).unwrap();
 
-        // Try withdrawing some money, should fail
-        assert_eq!(
-            Err(TransactionError::NotEnoughFunds),
+        let transact_all = |tp: &mut TransactionProcessor| {
+            // Move one large deposit into held funds, then deposit another
+            tp.transact(&Transaction::new(
+                1,
+                1,
+                TransactionType::Deposit { amount: large },
+            ))
+            .unwrap();
+            tp.transact(&Transaction::new(1, 1, TransactionType::Dispute))
+                .unwrap();
             tp.transact(&Transaction::new(
                 1,
                 2,
-                TransactionType::Withdrawal {
-                    amount: CurrencyAmount::from_str("100").unwrap()
-                }
+                TransactionType::Deposit { amount: large },
             ))
+            .unwrap();
+        };
+
+        // available + held no longer fits, so the account is skipped
+        let mut tp = TransactionProcessor::new();
+        transact_all(&mut tp);
+        assert_eq!(
+            vec![InvariantViolation::TotalOverflow(1)],
+            tp.check_invariants()
+        );
+        assert_eq!(
+            0,
+            tp.generate_report(&ReportFilter::new(), ReportOrder::ClientId)
+                .count()
         );
 
-        // Account should exist, but contain no money
+        let mut tp = TransactionProcessor::new().with_saturating_totals(true);
+        transact_all(&mut tp);
         assert_eq!(
             vec![ReportEntry {
                 client: 1,
-                available: CurrencyAmount::ZERO,
-                held: CurrencyAmount::ZERO,
-                total: CurrencyAmount::ZERO,
+                client_key: None,
+                available: large,
+                held: large,
+                total: CurrencyAmount::MAX,
                 locked: false
             }],
             tp.generate_report_as_vec()
         );
+    }
 
-        let fifty = CurrencyAmount::from_str("50").unwrap();
+    #[test]
+    fn test_dispute_resolve_chargeback() {
+        let mut tp = TransactionProcessor::new();
+        let ten = CurrencyAmount::from_str("10").unwrap();
 
-        // Deposit 50
         tp.transact(&Transaction::new(
             1,
-            2,
-            TransactionType::Deposit { amount: fifty },
+            1,
+            TransactionType::Deposit { amount: ten },
         ))
         .unwrap();
 
+        assert_eq!(
+            Err(TransactionError::DisputeDoesNotExist { client: 1, tx: 1 }),
+            tp.transact(&Transaction::new(1, 1, TransactionType::Resolve))
+        );
+
+        tp.transact(&Transaction::new(1, 1, TransactionType::Dispute))
+            .unwrap();
+        assert_eq!(
+            Err(TransactionError::DisputeAlreadyExists { client: 1, tx: 1 }),
+            tp.transact(&Transaction::new(1, 1, TransactionType::Dispute))
+        );
+        assert_eq!(1, tp.active_disputes());
+        assert_eq!(0, tp.locked_accounts());
         assert_eq!(
             vec![ReportEntry {
                 client: 1,
-                available: fifty,
-                held: CurrencyAmount::ZERO,
-                total: fifty,
+                client_key: None,
+                available: CurrencyAmount::ZERO,
+                held: ten,
+                total: ten,
                 locked: false
             }],
             tp.generate_report_as_vec()
         );
 
-        // Try (and fail) to withdraw 100
+        tp.transact(&Transaction::new(1, 1, TransactionType::Resolve))
+            .unwrap();
         assert_eq!(
-            Err(TransactionError::NotEnoughFunds),
-            tp.transact(&Transaction::new(
-                1,
-                2,
-                TransactionType::Withdrawal {
-                    amount: CurrencyAmount::from_str("100").unwrap()
-                }
-            ))
+            vec![ReportEntry {
+                client: 1,
+                client_key: None,
+                available: ten,
+                held: CurrencyAmount::ZERO,
+                total: ten,
+                locked: false
+            }],
+            tp.generate_report_as_vec()
         );
 
-        // Account is unchanged
+        tp.transact(&Transaction::new(1, 1, TransactionType::Dispute))
+            .unwrap();
+        tp.transact(&Transaction::new(1, 1, TransactionType::Chargeback))
+            .unwrap();
         assert_eq!(
             vec![ReportEntry {
                 client: 1,
-                available: fifty,
+                client_key: None,
+                available: CurrencyAmount::ZERO,
                 held: CurrencyAmount::ZERO,
-                total: fifty,
-                locked: false
+                total: CurrencyAmount::ZERO,
+                locked: true
             }],
             tp.generate_report_as_vec()
         );
+        assert_eq!(0, tp.active_disputes());
+        assert_eq!(1, tp.locked_accounts());
 
-        // Withdraw all (50)
-        tp.transact(&Transaction::new(
-            1,
-            3,
-            TransactionType::Withdrawal { amount: fifty },
-        ))
-        .unwrap();
+        assert_eq!(
+            Err(TransactionError::TransactionDoesNotExist { client: 1, tx: 1 }),
+            tp.transact(&Transaction::new(1, 1, TransactionType::Dispute))
+        );
+    }
+
+    #[test]
+    fn test_redispute_policy() {
+        let transaction = |tx, transaction_type| Transaction::new(1, tx, transaction_type);
+        let processor = |policy| {
+            let mut tp = TransactionProcessor::new().with_redispute_policy(policy);
+            for tx in [1, 2] {
+                tp.transact(&transaction(
+                    tx,
+                    TransactionType::Deposit {
+                        amount: CurrencyAmount::from_str("10").unwrap(),
+                    },
+                ))
+                .unwrap();
+            }
+            // Settle the dispute of transaction 1 with a resolve, and of
+            // transaction 2 with a chargeback
+            for (tx, settlement) in [
+                (1, TransactionType::Resolve),
+                (2, TransactionType::Chargeback),
+            ] {
+                tp.transact(&transaction(tx, TransactionType::Dispute))
+                    .unwrap();
+                tp.transact(&transaction(tx, settlement)).unwrap();
+            }
+            tp
+        };
+
+        let mut tp = processor(RedisputePolicy::Never);
+        assert_eq!(
+            Err(TransactionError::DisputeNotAllowed { client: 1, tx: 1 }),
+            tp.transact(&transaction(1, TransactionType::Dispute))
+        );
+        assert_eq!(
+            Err(TransactionError::TransactionDoesNotExist { client: 1, tx: 2 }),
+            tp.transact(&transaction(2, TransactionType::Dispute))
+        );
+        // The policy applies to processors created like this one
+        assert_eq!(RedisputePolicy::Never, tp.new_like().redispute_policy);
 
+        let mut tp = processor(RedisputePolicy::AfterResolve);
+        tp.transact(&transaction(1, TransactionType::Dispute))
+            .unwrap();
+        assert_eq!(
+            Err(TransactionError::TransactionDoesNotExist { client: 1, tx: 2 }),
+            tp.transact(&transaction(2, TransactionType::Dispute))
+        );
+
+        let mut tp = processor(RedisputePolicy::Always);
+        tp.transact(&transaction(1, TransactionType::Dispute))
+            .unwrap();
+        tp.transact(&transaction(2, TransactionType::Dispute))
+            .unwrap();
+        // A charged back transaction can be charged back again
+        tp.transact(&transaction(2, TransactionType::Chargeback))
+            .unwrap();
         assert_eq!(
             vec![ReportEntry {
                 client: 1,
-                available: CurrencyAmount::ZERO,
-                held: CurrencyAmount::ZERO,
+                client_key: None,
+                available: CurrencyAmount::from_str("-10").unwrap(),
+                held: CurrencyAmount::from_str("10").unwrap(),
                 total: CurrencyAmount::ZERO,
-                locked: false
+                locked: true,
             }],
             tp.generate_report_as_vec()
         );
     }
+
+    #[test]
+    fn test_with_capacity() {
+        let deposit = Transaction::new(
+            1,
+            1,
+            TransactionType::Deposit {
+                amount: CurrencyAmount::from_str("1").unwrap(),
+            },
+        );
+        let mut tp = TransactionProcessor::new().with_capacity(10, 500);
+        tp.transact(&deposit).unwrap();
+        assert!(tp.clients[&1].transactions.capacity() >= 500);
+        assert!(tp.changes.clients.capacity() >= 10);
+
+        // Other processors like it get the hint, up to the transaction limit
+        let mut other = tp
+            .new_like()
+            .with_transaction_limit(20, TransactionLimitAction::Reject);
+        other.transact(&deposit).unwrap();
+        assert!((20..500).contains(&other.clients[&1].transactions.capacity()));
+
+        // However many clients there turn out to be, only the first accounts
+        // between all the processors get the space
+        let mut tp = TransactionProcessor::new().with_capacity(10, 500);
+        let mut other = tp.new_like();
+        #[cfg(not(feature = "wide-ids"))]
+        let clients = 1..=1000;
+        #[cfg(feature = "wide-ids")]
+        let clients = 1 << 32..=(1 << 32) + 1000;
+        for client in clients {
+            let tp = if client % 2 == 0 { &mut tp } else { &mut other };
+            tp.transact(&Transaction::new(
+                client,
+                1,
+                TransactionType::Deposit {
+                    amount: CurrencyAmount::from_str("1").unwrap(),
+                },
+            ))
+            .unwrap();
+        }
+        let reserved = [&tp, &other]
+            .iter()
+            .flat_map(|tp| tp.clients.values())
+            .filter(|account| account.transactions.capacity() >= 500)
+            .count();
+        assert_eq!(10, reserved);
+    }
+
+    #[test]
+    fn test_memory_stats() {
+        let mut tp = TransactionProcessor::new();
+        assert_eq!(0, tp.memory_stats().clients());
+        let empty = tp.memory_stats().estimated_heap_bytes();
+
+        let amount = CurrencyAmount::from_str("1").unwrap();
+        for tx in 1..=100 {
+            tp.transact(&Transaction::new(
+                tx as ClientId % 4,
+                tx,
+                TransactionType::Deposit { amount },
+            ))
+            .unwrap();
+        }
+        tp.transact(&Transaction::new(1, 1, TransactionType::Dispute))
+            .unwrap();
+
+        let stats = tp.memory_stats();
+        assert_eq!(4, stats.clients());
+        assert_eq!(100, stats.transactions());
+        assert_eq!(1, stats.active_disputes());
+        assert!(
+            stats.estimated_heap_bytes()
+                > empty + 100 * size_of::<(TransactionId, CurrencyAmount)>()
+        );
+    }
+
+    #[test]
+    fn test_clone() {
+        let deposit = |client, tx| {
+            Transaction::new(
+                client,
+                tx,
+                TransactionType::Deposit {
+                    amount: CurrencyAmount::from_str("5").unwrap(),
+                },
+            )
+        };
+        let mut tp = TransactionProcessor::new().with_idempotent_replays(true);
+        tp.transact(&deposit(1, 1)).unwrap();
+        tp.transact(&deposit(2, 2)).unwrap();
+
+        // The clone has the same accounts and options, sharing the accounts
+        // until one of them changes
+        let mut fork = tp.clone();
+        assert!(tp
+            .clients
+            .values()
+            .zip(fork.clients.values())
+            .all(|(a, b)| Arc::ptr_eq(a, b)));
+        assert_eq!(
+            Err(TransactionError::Replayed(1)),
+            fork.transact(&deposit(1, 1))
+        );
+        fork.transact(&Transaction::new(1, 1, TransactionType::Dispute))
+            .unwrap();
+        fork.transact(&deposit(3, 3)).unwrap();
+        assert!(!Arc::ptr_eq(&tp.clients[&1], &fork.clients[&1]));
+        assert!(Arc::ptr_eq(&tp.clients[&2], &fork.clients[&2]));
+
+        // Neither affects the other
+        tp.transact(&deposit(2, 4)).unwrap();
+        let held = |tp: &TransactionProcessor| {
+            tp.generate_report_as_vec()
+                .iter()
+                .map(|entry| (entry.client, entry.held, entry.total))
+                .collect::<Vec<_>>()
+        };
+        let amount = |amount| CurrencyAmount::from_str(amount).unwrap();
+        assert_eq!(
+            vec![
+                (1, CurrencyAmount::ZERO, amount("5")),
+                (2, CurrencyAmount::ZERO, amount("10"))
+            ],
+            held(&tp)
+        );
+        assert_eq!(
+            vec![
+                (1, amount("5"), amount("5")),
+                (2, CurrencyAmount::ZERO, amount("5")),
+                (3, CurrencyAmount::ZERO, amount("5"))
+            ],
+            held(&fork)
+        );
+        assert_eq!(1, fork.active_disputes());
+        assert_eq!(0, tp.active_disputes());
+    }
+
+    #[test]
+    fn test_merge() {
+        let deposit = |client, tx, amount| {
+            Transaction::new(
+                client,
+                tx,
+                TransactionType::Deposit {
+                    amount: CurrencyAmount::from_str(amount).unwrap(),
+                },
+            )
+        };
+
+        let mut tp = TransactionProcessor::new().with_precision_policy(PrecisionPolicy::Reject);
+        tp.transact(&deposit(2, 1, "2")).unwrap();
+
+        // The new processor has the same options, but no accounts
+        let mut other = tp.new_like();
+        assert_eq!(
+            0,
+            other
+                .generate_report(&ReportFilter::new(), ReportOrder::ClientId)
+                .count()
+        );
+        assert_eq!(
+            Err(TransactionError::CurrencyError(
+                CurrencyError::PrecisionLoss
+            )),
+            other.transact(&deposit(1, 1, "1.00005"))
+        );
+        other.transact(&deposit(3, 1, "3")).unwrap();
+
+        tp.merge(other).unwrap();
+        assert_eq!(
+            vec![1, 2, 3],
+            tp.generate_report(&ReportFilter::new(), ReportOrder::ClientId)
+                .map(|entry| entry.client)
+                .collect::<Vec<_>>()
+        );
+
+        // Conflicting processors are left unchanged
+        let mut other = TransactionProcessor::new();
+        other.transact(&deposit(4, 1, "4")).unwrap();
+        other.transact(&deposit(2, 2, "1")).unwrap();
+        assert_eq!(Err(MergeError::ClientConflict(2)), tp.merge(other));
+        assert_eq!(
+            CurrencyAmount::from_str("2").unwrap(),
+            tp.generate_report_as_vec()[1].available
+        );
+        assert_eq!(
+            3,
+            tp.generate_report(&ReportFilter::new(), ReportOrder::ClientId)
+                .count()
+        );
+
+        // Single accounts can be looked up too
+        assert_eq!(
+            Some(tp.generate_report_as_vec()[1].clone()),
+            tp.account_report(2)
+        );
+        assert_eq!(None, tp.account_report(4));
+    }
+
+    #[test]
+    fn test_merge_client_keys() {
+        let keyed_deposit = |key: &str, tx, amount| {
+            Transaction::new(
+                0,
+                tx,
+                TransactionType::Deposit {
+                    amount: CurrencyAmount::from_str(amount).unwrap(),
+                },
+            )
+            .with_client_key(key)
+        };
+
+        // Both processors give their first key the lowest ID, and the second
+        // processor's also takes the next one
+        let mut tp = TransactionProcessor::new();
+        tp.transact(&keyed_deposit("acct-a", 1, "1")).unwrap();
+        let mut other = tp.new_like();
+        other.transact(&keyed_deposit("acct-b", 1, "2")).unwrap();
+        other.transact(&keyed_deposit("acct-c", 2, "3")).unwrap();
+        tp.transact(&keyed_deposit("acct-d", 2, "4")).unwrap();
+
+        tp.merge(other).unwrap();
+        let report = tp.generate_report_as_vec();
+        assert_eq!(4, report.len());
+        for (key, amount) in [
+            ("acct-a", "1"),
+            ("acct-b", "2"),
+            ("acct-c", "3"),
+            ("acct-d", "4"),
+        ] {
+            let client = tp.transaction_client(&keyed_deposit(key, 3, "1")).unwrap();
+            assert_eq!(
+                CurrencyAmount::from_str(amount).unwrap(),
+                tp.account_report(client).unwrap().available
+            );
+        }
+
+        // Transactions keep applying to the renumbered accounts
+        tp.transact(&keyed_deposit("acct-b", 3, "1")).unwrap();
+        let client = tp
+            .transaction_client(&keyed_deposit("acct-b", 4, "1"))
+            .unwrap();
+        assert_eq!(
+            CurrencyAmount::from_str("3").unwrap(),
+            tp.account_report(client).unwrap().available
+        );
+
+        // The same key can't have an account in both
+        let mut other = tp.new_like();
+        other.transact(&keyed_deposit("acct-c", 1, "1")).unwrap();
+        let client = tp
+            .transaction_client(&keyed_deposit("acct-c", 1, "1"))
+            .unwrap();
+        assert_eq!(Err(MergeError::ClientConflict(client)), tp.merge(other));
+    }
+
+    #[test]
+    fn test_filtered_report() {
+        let mut tp = TransactionProcessor::new();
+        for (client, tx, amount) in [
+            (1, 1, "1"),
+            (2, 2, "2"),
+            (5, 3, "5"),
+            (7, 4, "7"),
+            (8, 5, "0"),
+        ] {
+            tp.transact(&Transaction::new(
+                client,
+                tx,
+                TransactionType::Deposit {
+                    amount: CurrencyAmount::from_str(amount).unwrap(),
+                },
+            ))
+            .unwrap();
+        }
+        tp.transact(&Transaction::new(7, 4, TransactionType::Dispute))
+            .unwrap();
+        tp.transact(&Transaction::new(7, 4, TransactionType::Chargeback))
+            .unwrap();
+
+        let clients = |filter: &ReportFilter| {
+            tp.generate_report(filter, ReportOrder::ClientId)
+                .map(|entry| entry.client)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(vec![1, 2, 5, 7, 8], clients(&ReportFilter::new()));
+        assert_eq!(
+            vec![1, 5, 7],
+            clients(&ReportFilter::new().with_clients(ClientSet::from_str("5-7,1").unwrap()))
+        );
+        assert_eq!(
+            vec![7],
+            clients(&ReportFilter::new().with_locked_only(true))
+        );
+        assert_eq!(
+            vec![1, 2, 5],
+            clients(&ReportFilter::new().with_non_zero_only(true))
+        );
+        assert_eq!(
+            vec![2, 5],
+            clients(
+                &ReportFilter::new()
+                    .with_clients(ClientSet::from_str("2-8").unwrap())
+                    .with_non_zero_only(true)
+            )
+        );
+    }
+
+    #[test]
+    fn test_sorted_report() {
+        let mut tp = TransactionProcessor::new();
+        for (client, tx, amount) in [(1, 1, "5"), (2, 2, "9"), (3, 3, "1"), (4, 4, "5")] {
+            tp.transact(&Transaction::new(
+                client,
+                tx,
+                TransactionType::Deposit {
+                    amount: CurrencyAmount::from_str(amount).unwrap(),
+                },
+            ))
+            .unwrap();
+        }
+        tp.transact(&Transaction::new(3, 3, TransactionType::Dispute))
+            .unwrap();
+
+        let clients = |order| {
+            tp.generate_report(&ReportFilter::new(), order)
+                .map(|entry| entry.client)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(vec![1, 2, 3, 4], clients(ReportOrder::ClientId));
+        // Ties are in client ID order
+        assert_eq!(vec![2, 1, 4, 3], clients(ReportOrder::TotalDescending));
+        assert_eq!(vec![3, 1, 2, 4], clients(ReportOrder::HeldDescending));
+    }
+
+    #[test]
+    fn test_dispute_report() {
+        let mut tp = TransactionProcessor::new();
+        for (client, tx, amount) in [(2, 3, "1"), (2, 1, "2"), (1, 2, "3"), (1, 4, "4")] {
+            tp.transact(&Transaction::new(
+                client,
+                tx,
+                TransactionType::Deposit {
+                    amount: CurrencyAmount::from_str(amount).unwrap(),
+                },
+            ))
+            .unwrap();
+        }
+        for (client, tx) in [(2, 3), (2, 1), (1, 2), (1, 4)] {
+            tp.transact(&Transaction::new(client, tx, TransactionType::Dispute))
+                .unwrap();
+        }
+        tp.transact(&Transaction::new(1, 2, TransactionType::Resolve))
+            .unwrap();
+        tp.transact(&Transaction::new(2, 1, TransactionType::Chargeback))
+            .unwrap();
+
+        // Resolved and charged back disputes are not included
+        assert_eq!(
+            vec![
+                (1, 4, CurrencyAmount::from_str("4").unwrap()),
+                (2, 3, CurrencyAmount::from_str("1").unwrap())
+            ],
+            tp.generate_dispute_report()
+                .map(|entry| (entry.client(), entry.tx(), entry.held()))
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_negative_balance_report() {
+        let amount = |amount| CurrencyAmount::from_str(amount).unwrap();
+        let mut tp = TransactionProcessor::new();
+        for transaction in [
+            // Both deposits of client 1 are disputed once withdrawn
+            Transaction::new(
+                1,
+                1,
+                TransactionType::Deposit {
+                    amount: amount("3"),
+                },
+            ),
+            Transaction::new(
+                1,
+                2,
+                TransactionType::Deposit {
+                    amount: amount("2"),
+                },
+            ),
+            Transaction::new(
+                1,
+                3,
+                TransactionType::Withdrawal {
+                    amount: amount("4"),
+                },
+            ),
+            Transaction::new(1, 2, TransactionType::Dispute),
+            Transaction::new(1, 1, TransactionType::Dispute),
+            // Client 2's disputed deposit is charged back
+            Transaction::new(
+                2,
+                4,
+                TransactionType::Deposit {
+                    amount: amount("1"),
+                },
+            ),
+            Transaction::new(
+                2,
+                5,
+                TransactionType::Withdrawal {
+                    amount: amount("1"),
+                },
+            ),
+            Transaction::new(2, 4, TransactionType::Dispute),
+            Transaction::new(2, 4, TransactionType::Chargeback),
+            // Client 3's dispute leaves enough available
+            Transaction::new(
+                3,
+                6,
+                TransactionType::Deposit {
+                    amount: amount("1"),
+                },
+            ),
+            Transaction::new(
+                3,
+                7,
+                TransactionType::Deposit {
+                    amount: amount("1"),
+                },
+            ),
+            Transaction::new(3, 6, TransactionType::Dispute),
+        ] {
+            tp.transact(&transaction).unwrap();
+        }
+
+        assert_eq!(
+            vec![
+                NegativeBalanceEntry::new(
+                    1,
+                    amount("-4"),
+                    amount("5"),
+                    Some(NegativeBalanceCause::Dispute {
+                        tx: 1,
+                        held: amount("3")
+                    })
+                ),
+                NegativeBalanceEntry::new(
+                    1,
+                    amount("-4"),
+                    amount("5"),
+                    Some(NegativeBalanceCause::Dispute {
+                        tx: 2,
+                        held: amount("2")
+                    })
+                ),
+                NegativeBalanceEntry::new(
+                    2,
+                    amount("-1"),
+                    amount("0"),
+                    Some(NegativeBalanceCause::Chargeback(4))
+                ),
+            ],
+            tp.generate_negative_balance_report().collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_locked_report() {
+        let mut tp = TransactionProcessor::new();
+        for tx in [1, 2] {
+            tp.transact(&Transaction::new(
+                1,
+                tx,
+                TransactionType::Deposit {
+                    amount: CurrencyAmount::from_str("1").unwrap(),
+                },
+            ))
+            .unwrap();
+        }
+        // Locked accounts can still handle disputes
+        for tx in [1, 2] {
+            tp.transact(&Transaction::new(1, tx, TransactionType::Dispute))
+                .unwrap();
+            tp.transact(&Transaction::new(1, tx, TransactionType::Chargeback))
+                .unwrap();
+        }
+
+        // Clients can be locked before they have an account
+        tp.lock_account(3);
+        tp.lock_account(1);
+        assert_eq!(
+            Err(TransactionError::AccountIsLocked {
+                client: 3,
+                transaction_type: TransactionType::Deposit {
+                    amount: CurrencyAmount::from_str("1").unwrap(),
+                },
+            }),
+            tp.transact(&Transaction::new(
+                3,
+                1,
+                TransactionType::Deposit {
+                    amount: CurrencyAmount::from_str("1").unwrap(),
+                },
+            ))
+        );
+
+        // The first reason is kept
+        assert_eq!(
+            vec![
+                LockedEntry::new(1, LockReason::Chargeback(1)),
+                LockedEntry::new(3, LockReason::Admin)
+            ],
+            tp.generate_locked_report().collect::<Vec<_>>()
+        );
+        assert_eq!(2, tp.locked_accounts());
+    }
+
+    #[test]
+    fn test_delta_report() {
+        let deposit = |client, tx| {
+            Transaction::new(
+                client,
+                tx,
+                TransactionType::Deposit {
+                    amount: CurrencyAmount::from_str("1").unwrap(),
+                },
+            )
+        };
+        let clients = |tp: &TransactionProcessor, since| {
+            tp.generate_delta_report(since)
+                .map(|entry| entry.client())
+                .collect::<Vec<_>>()
+        };
+
+        let mut tp = TransactionProcessor::new();
+        for (client, tx) in [(3, 1), (1, 2), (2, 3)] {
+            tp.transact(&deposit(client, tx)).unwrap();
+        }
+        assert_eq!(vec![1, 2, 3], clients(&tp, ReportVersion::INITIAL));
+        let version = tp.report_version();
+        assert!(clients(&tp, version).is_empty());
+
+        // Failed transactions only change new accounts
+        tp.transact(&deposit(3, 4)).unwrap();
+        tp.transact(&deposit(2, 3)).unwrap_err();
+        tp.transact(&Transaction::new(4, 5, TransactionType::Dispute))
+            .unwrap_err();
+        tp.transact(&Transaction::new(1, 2, TransactionType::Dispute))
+            .unwrap();
+        assert_eq!(vec![1, 3, 4], clients(&tp, version));
+
+        // Locking an account which is already locked doesn't change it
+        let version = tp.report_version();
+        tp.lock_account(2);
+        assert_eq!(vec![2], clients(&tp, version));
+        let version = tp.report_version();
+        tp.lock_account(2);
+        assert_eq!(version, tp.report_version());
+
+        // Merged accounts are new
+        let mut other = tp.new_like();
+        other.transact(&deposit(7, 6)).unwrap();
+        tp.merge(other).unwrap();
+        assert_eq!(vec![7], clients(&tp, version));
+        assert_eq!(
+            tp.generate_report_as_vec(),
+            tp.generate_delta_report(ReportVersion::INITIAL)
+                .collect::<Vec<_>>()
+        );
+
+        // Repeated changes to one account are still reported once
+        let version = tp.report_version();
+        for tx in 10..3000 {
+            tp.transact(&deposit(1, tx)).unwrap();
+        }
+        assert_eq!(vec![1], clients(&tp, version));
+        assert_eq!(vec![1, 2, 3, 4, 7], clients(&tp, ReportVersion::INITIAL));
+    }
 }
@@ -4,91 +4,1602 @@
 
 #![deny(missing_docs)]
 
+use std::convert::Infallible;
 use std::fmt::{Display, Formatter};
 use std::fs::File;
-use std::io;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Instant;
 
-use log::LevelFilter;
-use transaction_processor::TransactionProcessor;
+use serde::Serialize;
+use transaction_processor::aggregate::Aggregator;
+use transaction_processor::alert::{
+    BalanceAlert, BalanceMonitor, BalanceThresholds, ALERT_LOG_TARGET,
+};
+use transaction_processor::crypto::SigningWriter;
+use transaction_processor::cutoff::{Cutoff, DatedSink, BUSINESS_DATE_COLUMN};
+use transaction_processor::idempotency::IdempotencyCache;
+#[cfg(feature = "avro")]
+use transaction_processor::io::avro::{self, AvroReader, AvroWriter};
+use transaction_processor::io::checksum::{ChecksumReader, Sha256Digest, SIDECAR_EXTENSION};
+use transaction_processor::io::csv::{CSVWriter, CSVWriterOptions, RejectWriter, ReportReader};
+use transaction_processor::io::jsonl::JsonlWriter;
+#[cfg(feature = "parquet")]
+use transaction_processor::io::parquet::{self, ParquetReader};
+use transaction_processor::io::{decompress_input, open_raw_input, AmountFormat};
+use transaction_processor::ledger::Ledger;
+use transaction_processor::pipeline::{self, PipelineError, PipelineOptions};
+use transaction_processor::reject::{RejectSink, RejectedRow};
+use transaction_processor::report::{self, Statement};
+use transaction_processor::snapshot::{
+    read_checkpoint, read_snapshot_with_positions, write_snapshot_with_positions, Checkpoint,
+    SnapshotError, StreamPositions,
+};
+#[cfg(feature = "encryption")]
+use transaction_processor::snapshot::{
+    read_encrypted_snapshot_with_positions, write_encrypted_snapshot_with_positions, SnapshotKey,
+};
+use transaction_processor::summary::BatchResult;
+use transaction_processor::timestamp::TimestampDate;
+use transaction_processor::{
+    ClientId, ReportEntry, Transaction, TransactionError, TransactionProcessor,
+};
 
-use crate::args::parse_args;
-use crate::csv::{CSVReader, CSVWriter};
+#[cfg(any(feature = "server", feature = "grpc", feature = "kafka"))]
+use crate::args::Service;
+use crate::args::{parse_args, Args, DiffTarget, InputFormat, Mode, OutputFormat};
+use crate::exit_code::ExitCode;
+use crate::output::{AtomicFile, ExistingOutput};
 
 mod args;
-mod csv;
+mod checkpoint;
+mod exit_code;
+mod follow;
+#[cfg(feature = "grpc")]
+mod grpc;
+mod inputs;
+mod journal;
+#[cfg(feature = "kafka")]
+mod kafka;
+mod output;
+mod parallel;
+#[cfg(feature = "server")]
+mod rate_limit;
+#[cfg(feature = "server")]
+mod server;
+#[cfg(feature = "tui")]
+mod tui;
+#[cfg(feature = "webhooks")]
+mod webhook;
 
 fn main() {
-    let args = match parse_args() {
+    let args = match parse_args(std::env::args_os().collect()) {
         Ok(args) => args,
         Err(err) => {
-            log::error!("Error parsing arguments: {}", err);
-            std::process::exit(1);
+            // The logger depends on the arguments, so isn't set up yet
+            eprintln!("Error parsing arguments: {}", err);
+            ExitCode::InvalidArguments.exit();
         }
     };
 
-    let log_level = if args.verbose() {
-        LevelFilter::Info
-    } else {
-        LevelFilter::Off
+    let log_filter = args.log_filter();
+    let mut logger = env_logger::Builder::new();
+    logger.filter_level(log_filter.level);
+    for (module, level) in &log_filter.modules {
+        logger.filter_module(module, *level);
+    }
+    // Log lines are shown in the dashboard while it is open
+    #[cfg(feature = "tui")]
+    let dashboard = args.tui().then(|| {
+        let dashboard = tui::Dashboard::new();
+        logger
+            .target(env_logger::Target::Pipe(Box::new(dashboard.log_writer())))
+            .write_style(env_logger::WriteStyle::Never);
+        dashboard
+    });
+    if let Err(err) = logger.try_init() {
+        eprintln!("Failed to create logger ({}). Continuing anyway.", err);
+    }
+    if args.dry_run() {
+        log::warn!("--dry-run is deprecated, use the validate subcommand instead");
+    }
+
+    #[cfg(feature = "tracing")]
+    if let Some(level) = args.trace_level() {
+        if let Err(err) = tracing_subscriber::fmt()
+            .with_writer(io::stderr)
+            .with_max_level(level)
+            // Each span is printed once, with its duration, when it closes
+            .with_span_events(tracing_subscriber::fmt::format::FmtSpan::CLOSE)
+            .try_init()
+        {
+            eprintln!(
+                "Failed to create tracing subscriber ({}). Continuing anyway.",
+                err
+            );
+        }
+    }
+
+    let mut transaction_processor = TransactionProcessor::new()
+        .with_saturating_totals(args.saturate_totals())
+        .with_idempotent_replays(args.idempotent_replays())
+        .with_redispute_policy(args.redispute_policy())
+        .with_locked_account_policy(args.locked_account_policy())
+        .with_account_creation_policy(args.account_creation_policy());
+
+    if let Some(policy) = args.precision_policy() {
+        transaction_processor = transaction_processor.with_precision_policy(policy);
+    }
+    if let Some(max_balance) = args.max_balance() {
+        transaction_processor = transaction_processor.with_max_balance(max_balance);
+    }
+    if let Some((max, action)) = args.transaction_limit() {
+        transaction_processor = transaction_processor.with_transaction_limit(max, action);
+    }
+    if let Some(clients) = args.registered_clients() {
+        transaction_processor.register_clients(clients);
+    }
+    if let Some((clients, transactions_per_client)) = capacity_hint(args.input_files()) {
+        transaction_processor =
+            transaction_processor.with_capacity(clients, transactions_per_client);
+    }
+
+    let amount_format = match args.amount_locale() {
+        Some(locale) => AmountFormat::Locale(locale),
+        None if args.strict_amounts() => AmountFormat::Strict,
+        None => AmountFormat::Standard,
+    };
+
+    #[cfg(feature = "webhooks")]
+    let (notifier, dispatcher) = match args.webhooks() {
+        [] => (None, None),
+        urls => {
+            let (notifier, dispatcher) = webhook::start(urls.to_vec());
+            (Some(notifier), Some(dispatcher))
+        }
+    };
+    // Waits for pending notifications, once `options` has been dropped
+    #[cfg(feature = "webhooks")]
+    let finish_webhooks = || {
+        if let Some(dispatcher) = dispatcher {
+            dispatcher.finish();
+        }
+    };
+    #[cfg(not(feature = "webhooks"))]
+    let finish_webhooks = || {};
+
+    #[cfg(feature = "webhooks")]
+    let alert_notifier = notifier.clone();
+    let options = ProcessOptions {
+        pipeline: PipelineOptions::new()
+            .with_amount_format(amount_format)
+            .with_output_scale(args.output_scale())
+            .with_report_filter(args.report_filter().clone())
+            .with_report_order(args.report_order())
+            .with_column_mapping(args.column_mapping().cloned())
+            .with_no_header(args.no_header())
+            .with_fail_fast(args.strict())
+            .with_signature_key(args.signature_key().cloned())
+            .with_client_keys(args.client_keys())
+            .with_reorder_window(args.reorder_window()),
+        input_format: args.input_format(),
+        output_format: args.output_format(),
+        csv_output: args.csv_output().clone(),
+        report_summary: args.report_summary().map(Path::to_path_buf),
+        disputes_output: args.disputes_output().map(Path::to_path_buf),
+        locked_output: args.locked_output().map(Path::to_path_buf),
+        negative_output: args.negative_output().map(Path::to_path_buf),
+        omit_output_header: false,
+        omit_rejects_header: false,
+        require_checksums: args.verify_checksum(),
+        #[cfg(feature = "webhooks")]
+        notifier,
+        #[cfg(feature = "tui")]
+        dashboard: dashboard.clone(),
+        statement: match args.mode() {
+            Mode::Statement(client) => Some(Arc::new(Mutex::new(Statement::new(*client)))),
+            _ => None,
+        },
+        // Created by `run`, as it is only written when processing files
+        journal: None,
+        ledger: args
+            .ledger()
+            .map(|path| (path.to_path_buf(), Arc::new(Mutex::new(Ledger::new())))),
+        aggregate: args.aggregate().map(|(path, aggregator)| {
+            (path.to_path_buf(), Arc::new(Mutex::new(aggregator.clone())))
+        }),
+        checkpoint: args.checkpoint().map(|(path, every)| {
+            checkpoint::Checkpointer::new(path.to_path_buf(), every, args.input_files().to_vec())
+        }),
+        // Set by `run`, once the checkpoint has been read
+        resume: None,
+        // Set by `run`, once the carried over transactions have been read
+        settlement: None,
+        idempotency: (args.idempotency_cache() > 0)
+            .then(|| Arc::new(Mutex::new(IdempotencyCache::new(args.idempotency_cache())))),
+        alerts: alert_monitor(
+            BalanceThresholds::new().with_global(args.balance_threshold()),
+            #[cfg(feature = "webhooks")]
+            alert_notifier,
+            #[cfg(feature = "webhooks")]
+            args.output_scale(),
+        ),
+        #[cfg(feature = "encryption")]
+        snapshot_key: args.snapshot_key().cloned(),
+    };
+
+    #[cfg(any(feature = "server", feature = "grpc", feature = "kafka"))]
+    if let Mode::Service(service) = args.mode() {
+        let result = match service {
+            #[cfg(feature = "kafka")]
+            Service::Kafka(kafka) => {
+                kafka::consume_kafka(transaction_processor, kafka, &options, args.output())
+                    .map(|_| ())
+            }
+            #[cfg(feature = "server")]
+            Service::Rest(address, rate_limit) => {
+                server::serve(transaction_processor, *address, *rate_limit, &options)
+            }
+            #[cfg(feature = "grpc")]
+            Service::Grpc(address) => grpc::serve_grpc(transaction_processor, *address, &options),
+        };
+        drop(options);
+        finish_webhooks();
+
+        if let Err(err) = result {
+            log::error!("{}", err);
+            if !log::log_enabled!(log::Level::Error) {
+                eprintln!("Error: {}", err);
+            }
+            ExitCode::from(&err).exit();
+        }
+        return;
+    }
+
+    #[cfg(feature = "tui")]
+    let dashboard = match dashboard.map(tui::start).transpose() {
+        Ok(dashboard) => dashboard,
+        Err(error) => {
+            let err = TransactionProcessorCLIError::FailedToShowDashboard(error);
+            log::error!("{}", err);
+            if !log::log_enabled!(log::Level::Error) {
+                eprintln!("Error: {}", err);
+            }
+            ExitCode::from(&err).exit();
+        }
     };
 
-    if let Err(err) = env_logger::Builder::new()
-        .filter_level(log_level)
-        .try_init()
+    let result = run(&args, transaction_processor, options, &mut io::stdout());
+    finish_webhooks();
+    // The summary and any error are shown once the dashboard is closed
+    #[cfg(feature = "tui")]
+    let result = match dashboard {
+        Some(dashboard) => {
+            // The dashboard may have been closed after the last row was read
+            let interrupted = dashboard.interrupted();
+            if let Err(err) = dashboard.finish() {
+                log::error!("Dashboard failed: {}", err);
+            }
+            match result {
+                Ok(_) if interrupted => Err(TransactionProcessorCLIError::Interrupted),
+                result => result,
+            }
+        }
+        None => result,
+    };
+
+    match result {
+        // When validating, the summary replaces the report
+        Ok(summary) if matches!(args.mode(), Mode::Validate) => println!("{}", summary),
+        // No input files were read
+        Ok(_) if matches!(args.mode(), Mode::Report(_) | Mode::Diff(..)) => {}
+        Ok(summary) => eprintln!("{}", summary),
+        Err(err) => {
+            log::error!("{}", err);
+            // Fatal errors are always shown, even if logging is disabled
+            if !log::log_enabled!(log::Level::Error) {
+                eprintln!("Error: {}", err);
+            }
+            ExitCode::from(&err).exit();
+        }
+    }
+}
+
+/// The typical size of an input row, e.g. `deposit,1234,567890,12.5`, for
+/// estimating the number of rows from the size of the input files.
+const ESTIMATED_ROW_BYTES: u64 = 24;
+
+/// The most clients assumed by [`capacity_hint`]: the number of 16-bit
+/// client IDs. With `wide-ids` there can be more, but only this many
+/// accounts are then allocated space (see
+/// [`TransactionProcessor::with_capacity`]).
+const ESTIMATED_MAX_CLIENTS: u64 = 1 << 16;
+
+/// Estimates the number of clients and transactions per client of the input
+/// files from their total size, for [`TransactionProcessor::with_capacity`].
+/// Inputs which aren't local files (or can't be read) count as empty, and
+/// compressed files are counted at their compressed size.
+///
+/// The rows are assumed to be spread over as many clients as there can be,
+/// so that no more space is allocated in total than for every row, however
+/// few clients there actually are. Returns `None` if the inputs are too
+/// small to be worth it.
+fn capacity_hint(input_files: &[String]) -> Option<(usize, usize)> {
+    let bytes = input_files
+        .iter()
+        .filter_map(|path| std::fs::metadata(path).ok())
+        .filter(|metadata| metadata.is_file())
+        .map(|metadata| metadata.len())
+        .sum::<u64>();
+    let rows = bytes / ESTIMATED_ROW_BYTES;
+    let clients = rows.min(ESTIMATED_MAX_CLIENTS);
+    let transactions_per_client = rows.checked_div(clients)?;
+    if transactions_per_client < 16 {
+        return None;
+    }
+    Some((
+        usize::try_from(clients).ok()?,
+        usize::try_from(transactions_per_client).ok()?,
+    ))
+}
+
+/// Processes the input files, writing the report (and any rejected rows and
+/// summary) to the destinations given in the arguments, or the report to
+/// `stdout` if no output file is given. When validating, the report is
+/// discarded. For the `report` subcommand, the report is of the snapshot
+/// instead.
+fn run(
+    args: &Args,
+    transaction_processor: TransactionProcessor,
+    mut options: ProcessOptions,
+    stdout: &mut (dyn io::Write + Send),
+) -> Result<BatchResult, TransactionProcessorCLIError> {
+    let create_error = |path: &Path| {
+        let path = path.display().to_string();
+        move |error| TransactionProcessorCLIError::FailedToCreateOutput { path, error }
+    };
+
+    let transaction_processor = match (args.resume(), args.cutoff()) {
+        (Some(path), _) => {
+            let (transaction_processor, checkpoint) =
+                read_checkpoint_file(transaction_processor, path, args.input_files())?;
+            options.resume = Some(checkpoint);
+            transaction_processor
+        }
+        (None, Some((cutoff, path))) => {
+            // The first batch starts with no accounts
+            let (transaction_processor, carried) = if path.exists() {
+                read_snapshot_file_with_deferred(transaction_processor, path, &options)?
+            } else {
+                (transaction_processor, Vec::new())
+            };
+            options.settlement = Some(Arc::new(Settlement {
+                path: path.to_path_buf(),
+                carried,
+                cutoff: Mutex::new(cutoff.clone()),
+            }));
+            transaction_processor
+        }
+        (None, None) => transaction_processor,
+    };
+
+    let mut rejects = args
+        .rejects()
+        .map(|path| AtomicFile::create(path, args.existing_output()).map_err(create_error(path)))
+        .transpose()?;
+    options.omit_rejects_header = rejects.as_ref().is_some_and(AtomicFile::is_appending);
+    let rejects_writer = rejects.as_mut().map(|file| file as &mut dyn io::Write);
+
+    options.journal = args
+        .journal()
+        .map(|path| journal::Journal::create(path).map_err(create_error(path)))
+        .transpose()?;
+
+    let signature_file = args
+        .report_signature()
+        .map(|(path, _)| {
+            AtomicFile::create(path, args.existing_output()).map_err(create_error(path))
+        })
+        .transpose()?;
+
+    if let Some(report_interval) = args.follow() {
+        // Fail early if the output file can't be replaced, rather than when
+        // the first report is written
+        if let Some(path) = args.output() {
+            AtomicFile::create(path, args.existing_output()).map_err(create_error(path))?;
+        }
+
+        return follow::follow(
+            transaction_processor,
+            &args.input_files()[0],
+            &options,
+            args.output(),
+            report_interval,
+        );
+    }
+
+    let process = |options: &ProcessOptions, output: &mut (dyn io::Write + Send)| match (
+        args.mode(),
+        args.jobs(),
+    ) {
+        (Mode::Report(path), _) => {
+            let transaction_processor = read_snapshot_file(transaction_processor, path, options)?;
+            write_report(&transaction_processor, options, output)?;
+            Ok(BatchResult::new())
+        }
+        (Mode::Diff(before, target), _) => {
+            write_diff(transaction_processor, before, target, options, output)?;
+            Ok(BatchResult::new())
+        }
+        (_, Some(jobs)) => parallel::process_files_parallel(
+            transaction_processor,
+            args.input_files(),
+            options,
+            output,
+            rejects_writer,
+            jobs,
+        ),
+        (_, None) => process_files(
+            transaction_processor,
+            args.input_files(),
+            options,
+            output,
+            rejects_writer,
+        ),
+    };
+
+    // The report is signed as it is written, if a signature is requested
+    let mut signature = None;
+    let process_signed = |options: &ProcessOptions, output: &mut (dyn io::Write + Send)| match args
+        .report_signature()
     {
-        eprintln!("Failed to create logger ({}). Continuing anyway.", err);
+        Some((_, key)) => {
+            let mut output = SigningWriter::new(output, key);
+            let summary = process(options, &mut output)?;
+            signature = Some(output.signature());
+            Ok(summary)
+        }
+        None => process(options, output),
+    };
+
+    let summary = match args.output() {
+        Some(path) => {
+            let mut output =
+                AtomicFile::create(path, args.existing_output()).map_err(create_error(path))?;
+            options.omit_output_header = output.is_appending();
+
+            let summary = process_signed(&options, &mut output)?;
+            output.commit().map_err(create_error(path))?;
+            summary
+        }
+        None if matches!(args.mode(), Mode::Validate) => process_signed(&options, &mut io::sink())?,
+        None => process_signed(&options, stdout)?,
+    };
+
+    if let (Some(mut file), Some(signature), Some((path, _))) =
+        (signature_file, signature, args.report_signature())
+    {
+        writeln!(file, "{}", signature)
+            .and_then(|()| file.commit())
+            .map_err(create_error(path))?;
+    }
+
+    if let (Some(file), Some(path)) = (rejects, args.rejects()) {
+        file.commit().map_err(create_error(path))?;
+    }
+
+    if let (Some(journal), Some(path)) = (&options.journal, args.journal()) {
+        journal.finish().map_err(create_error(path))?;
+    }
+
+    if let Some(path) = args.summary_json() {
+        let mut file =
+            AtomicFile::create(path, ExistingOutput::Overwrite).map_err(create_error(path))?;
+        serde_json::to_writer_pretty(&mut file, &summary)
+            .map_err(io::Error::from)
+            .and_then(|()| file.commit())
+            .map_err(create_error(path))?;
+    }
+
+    Ok(summary)
+}
+
+/// Completes when the process is interrupted (e.g. with Ctrl-C), to shut
+/// down a server gracefully.
+#[cfg(any(feature = "server", feature = "grpc"))]
+async fn shutdown_signal() {
+    if tokio::signal::ctrl_c().await.is_err() {
+        // Without a signal handler, run until killed
+        std::future::pending::<()>().await;
+    }
+    log::info!("Shutting down");
+}
+
+/// Options controlling how input files are read and how the report is
+/// written.
+#[derive(Clone, Debug, Default)]
+struct ProcessOptions {
+    /// How the input files are read and their transactions applied, and
+    /// which accounts are written in the report.
+    pipeline: PipelineOptions,
+    /// The format of all input files. If not set, the format is detected
+    /// from each file's extension.
+    input_format: Option<InputFormat>,
+    /// The format of the report.
+    output_format: OutputFormat,
+    /// How the report is formatted, if it is CSV.
+    csv_output: CSVWriterOptions,
+    /// The file to write the grand totals of the report to, if any.
+    report_summary: Option<PathBuf>,
+    /// The file to write the active disputes to, if any.
+    disputes_output: Option<PathBuf>,
+    /// The file to write the locked accounts and their lock reasons to, if
+    /// any.
+    locked_output: Option<PathBuf>,
+    /// The file to write the accounts with negative available funds to, if
+    /// any.
+    negative_output: Option<PathBuf>,
+    /// True if the CSV report should not have a header row, because it is
+    /// being appended to an existing report.
+    omit_output_header: bool,
+    /// True if the rejected rows should not have a header row, because they
+    /// are being appended to an existing rejects file.
+    omit_rejects_header: bool,
+    /// True if every input file must have a sidecar checksum file. Otherwise,
+    /// only files with one are verified.
+    require_checksums: bool,
+    /// If set, chargebacks and locked accounts are sent to the webhooks.
+    #[cfg(feature = "webhooks")]
+    notifier: Option<webhook::Notifier>,
+    /// If set, progress is recorded for the `--tui` dashboard.
+    #[cfg(feature = "tui")]
+    dashboard: Option<tui::Dashboard>,
+    /// If set, the client's applied transactions are recorded for the
+    /// `statement` subcommand. It is shared by the threads of `--jobs`.
+    statement: Option<Arc<Mutex<Statement>>>,
+    /// If set, every applied transaction is written to the `--journal`.
+    journal: Option<journal::Journal>,
+    /// If set, every applied transaction is posted to the ledger, which is
+    /// checked and written to the file with the report. It is shared by the
+    /// threads of `--jobs`.
+    ledger: Option<(PathBuf, Arc<Mutex<Ledger>>)>,
+    /// If set, every applied transaction is totalled by period, and the
+    /// totals are written to the file once processing finishes. It is shared
+    /// by the threads of `--jobs`.
+    aggregate: Option<(PathBuf, Arc<Mutex<Aggregator>>)>,
+    /// If set, checkpoints are written as the input files are read.
+    checkpoint: Option<checkpoint::Checkpointer>,
+    /// If set, the rows of the input files before this position are
+    /// skipped, as they were applied before the run was interrupted.
+    resume: Option<Checkpoint>,
+    /// If set, the outcomes of transactions with idempotency keys, so that
+    /// resubmissions aren't applied again. It is shared by the threads of
+    /// `--jobs`.
+    idempotency: Option<Arc<Mutex<IdempotencyCache>>>,
+    /// If set, alerts are raised for transactions which cross the balance
+    /// thresholds. It is shared by the threads of `--jobs`.
+    alerts: Option<Arc<Mutex<AlertMonitor>>>,
+    /// If set, the run is a `--cutoff` settlement batch.
+    settlement: Option<Arc<Settlement>>,
+    /// If set, snapshot files are encrypted with this key when they are
+    /// written, and decrypted with it when they are read.
+    #[cfg(feature = "encryption")]
+    snapshot_key: Option<SnapshotKey>,
+}
+
+/// A settlement batch, which defers the transactions after its cutoff to the
+/// next batch by carrying them over in a snapshot with the accounts.
+#[derive(Debug)]
+struct Settlement {
+    /// The `--carry-over` snapshot, which is replaced once the report has
+    /// been written.
+    path: PathBuf,
+    /// The transactions carried over from the previous batch, which are
+    /// applied (or deferred again) before the input files.
+    carried: Vec<Transaction>,
+    cutoff: Mutex<Cutoff>,
+}
+
+impl Settlement {
+    /// Replaces the `--carry-over` snapshot with the accounts of
+    /// `transaction_processor` and the deferred transactions.
+    fn write_carry_over(
+        &self,
+        transaction_processor: &TransactionProcessor,
+        options: &ProcessOptions,
+    ) -> Result<(), TransactionProcessorCLIError> {
+        let create_error = |error| TransactionProcessorCLIError::FailedToCreateOutput {
+            path: self.path.display().to_string(),
+            error,
+        };
+
+        let cutoff = self.cutoff.lock().unwrap_or_else(PoisonError::into_inner);
+        let mut file =
+            AtomicFile::create(&self.path, ExistingOutput::Overwrite).map_err(create_error)?;
+        encode_snapshot(
+            transaction_processor,
+            cutoff.deferred(),
+            &StreamPositions::new(),
+            options,
+            &mut file,
+        )
+        .map_err(|err| create_error(io::Error::other(err.to_string())))?;
+        file.commit().map_err(create_error)?;
+        log::info!(
+            "Carried {} transactions over to {}",
+            cutoff.deferred().len(),
+            self.path.display()
+        );
+        Ok(())
+    }
+}
+
+/// Logs the alerts of `--alert-floor` and `--alert-held-ceiling`, and sends
+/// them to the webhooks.
+type AlertMonitor = BalanceMonitor<Box<dyn FnMut(BalanceAlert) + Send>>;
+
+/// Creates the monitor raising the alerts of `thresholds`, unless they have
+/// no floor or ceiling. Alerts are logged, and sent to the webhooks of
+/// `notifier` (if any) with `output_scale` decimal places.
+fn alert_monitor(
+    thresholds: BalanceThresholds,
+    #[cfg(feature = "webhooks")] notifier: Option<webhook::Notifier>,
+    #[cfg(feature = "webhooks")] output_scale: Option<u32>,
+) -> Option<Arc<Mutex<AlertMonitor>>> {
+    if thresholds.is_empty() {
+        return None;
     }
+    let observer = move |alert: BalanceAlert| {
+        log::warn!(target: ALERT_LOG_TARGET, "{}", alert);
+        #[cfg(feature = "webhooks")]
+        if let Some(notifier) = &notifier {
+            notifier.alert(&alert, output_scale);
+        }
+    };
+    let observer: Box<dyn FnMut(BalanceAlert) + Send> = Box::new(observer);
+    Some(Arc::new(Mutex::new(BalanceMonitor::new(
+        thresholds, observer,
+    ))))
+}
 
-    if let Err(err) = process_files(args.input_files(), io::stdout()) {
-        log::error!("{}", err);
-        std::process::exit(1);
+impl ProcessOptions {
+    /// The business date which the report is stamped with, if the run is a
+    /// settlement batch.
+    fn business_date(&self) -> Option<TimestampDate> {
+        self.settlement.as_ref().map(|settlement| {
+            settlement
+                .cutoff
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .business_date()
+        })
+    }
+
+    /// Applies a transaction (see [`ProcessOptions::apply`]), unless it has
+    /// already been submitted with the same idempotency key, in which case
+    /// the original outcome is returned.
+    fn transact(
+        &self,
+        transaction_processor: &mut TransactionProcessor,
+        transaction: &Transaction,
+    ) -> Result<(), TransactionError> {
+        match &self.idempotency {
+            // Held while applying, so that a key submitted by two threads at
+            // once is only applied by one
+            Some(cache) if transaction.idempotency_key().is_some() => cache
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .transact_with(
+                    transaction_processor,
+                    transaction,
+                    |transaction_processor, transaction| {
+                        self.apply(transaction_processor, transaction)
+                    },
+                ),
+            _ => self.apply(transaction_processor, transaction),
+        }
+    }
+
+    /// Applies a transaction, sending any webhook notifications it causes,
+    /// posting it to the ledger, totalling it for the aggregates, raising any
+    /// balance alerts, and recording the account in the dashboard, statement
+    /// and journal.
+    fn apply(
+        &self,
+        transaction_processor: &mut TransactionProcessor,
+        transaction: &Transaction,
+    ) -> Result<(), TransactionError> {
+        let apply = |transaction_processor: &mut TransactionProcessor,
+                     transaction: &Transaction| {
+            #[cfg(feature = "webhooks")]
+            if let Some(notifier) = &self.notifier {
+                return notifier.transact(
+                    transaction_processor,
+                    transaction,
+                    self.pipeline.output_scale(),
+                );
+            }
+            transaction_processor.transact(transaction)
+        };
+        let post = |transaction_processor: &mut TransactionProcessor, transaction: &Transaction| {
+            match &self.ledger {
+                Some((_, ledger)) => ledger
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .transact_with(transaction_processor, transaction, apply),
+                None => apply(transaction_processor, transaction),
+            }
+        };
+        let aggregate = |transaction_processor: &mut TransactionProcessor,
+                         transaction: &Transaction| match &self.aggregate {
+            Some((_, aggregator)) => aggregator
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .transact_with(transaction_processor, transaction, post),
+            None => post(transaction_processor, transaction),
+        };
+        let account = |transaction_processor: &TransactionProcessor| {
+            transaction_processor
+                .transaction_client(transaction)
+                .and_then(|client| transaction_processor.account_report(client))
+        };
+        let result = match &self.alerts {
+            Some(monitor) => {
+                let before = account(transaction_processor);
+                let result = aggregate(transaction_processor, transaction);
+                // Only locked to compare the balances, so that the other
+                // threads of --jobs aren't held up
+                if result.is_ok() {
+                    monitor
+                        .lock()
+                        .unwrap_or_else(PoisonError::into_inner)
+                        .check(transaction, before, account(transaction_processor));
+                }
+                result
+            }
+            None => aggregate(transaction_processor, transaction),
+        };
+
+        #[cfg(feature = "tui")]
+        if let (Some(dashboard), Ok(())) = (&self.dashboard, &result) {
+            let account =
+                account(transaction_processor).map(|entry| match self.pipeline.output_scale() {
+                    Some(scale) => entry.with_scale(scale),
+                    None => entry,
+                });
+            dashboard.record_applied(account);
+        }
+
+        if let (Some(statement), Ok(())) = (&self.statement, &result) {
+            statement
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .record(transaction_processor, transaction);
+        }
+
+        if let (Some(journal), Ok(())) = (&self.journal, &result) {
+            journal.record(
+                transaction_processor,
+                transaction,
+                self.pipeline.output_scale(),
+            );
+        }
+
+        // With --strict, a failed transaction ends the run, so it mustn't be
+        // skipped when the run is resumed
+        let ends_run = self.pipeline.fail_fast()
+            && !matches!(result, Ok(()) | Err(TransactionError::Replayed(_)));
+        if let (Some(checkpoint), false) = (&self.checkpoint, ends_run) {
+            checkpoint.after_transaction(transaction_processor);
+        }
+
+        result
     }
 }
 
-/// Processes the list of transactions in the specified files, and outputs
-/// a CSV report to the specified writer.
+/// Processes the list of transactions in the specified files using the
+/// given processor, and outputs the report to the specified writer. Rows
+/// which fail to be read or applied are written to `rejects`, if given.
+///
+/// Returns a summary of the rows read, applied and rejected.
 fn process_files(
+    mut transaction_processor: TransactionProcessor,
     input_files: &[String],
-    output: impl io::Write,
+    options: &ProcessOptions,
+    output: impl io::Write + Send,
+    rejects: Option<&mut dyn io::Write>,
+) -> Result<BatchResult, TransactionProcessorCLIError> {
+    let start = Instant::now();
+    let mut summary = BatchResult::new();
+    let mut rejects = rejects.map(|rejects| {
+        if options.omit_rejects_header {
+            RejectWriter::new_headerless(rejects)
+        } else {
+            RejectWriter::new(rejects)
+        }
+    });
+
+    let (resume_files, resume_rows) = options
+        .resume
+        .as_ref()
+        .map_or((0, 0), |checkpoint| (checkpoint.files(), checkpoint.rows()));
+    if let Some(settlement) = &options.settlement {
+        log::info!(
+            "Applying {} transactions carried over in {}",
+            settlement.carried.len(),
+            settlement.path.display()
+        );
+        let transactions = pipeline::numbered_rows(
+            settlement
+                .carried
+                .iter()
+                .map(|transaction| (Vec::new(), Ok::<_, Infallible>(transaction.clone()))),
+        );
+        apply_transactions(
+            |transaction| options.transact(&mut transaction_processor, transaction),
+            &settlement.path.display().to_string(),
+            transactions,
+            options,
+            0,
+            &mut rejects,
+            &mut summary,
+        )?;
+    }
+    for (index, arg) in input_files.iter().enumerate().skip(resume_files) {
+        let skip_rows = if index == resume_files {
+            resume_rows
+        } else {
+            0
+        };
+        if let Some(checkpoint) = &options.checkpoint {
+            checkpoint.start_file(index, skip_rows);
+        }
+
+        read_file(
+            |transaction| options.transact(&mut transaction_processor, transaction),
+            arg,
+            options,
+            usize::try_from(skip_rows).unwrap_or(usize::MAX),
+            &mut rejects,
+            &mut summary,
+        )?;
+    }
+    if let Some(checkpoint) = &options.checkpoint {
+        checkpoint.finish(&transaction_processor);
+    }
+
+    if let Some(Err(err)) = rejects.as_mut().map(RejectWriter::flush) {
+        log::error!("Failed to write rejected rows: {}", err);
+    }
+
+    write_report(&transaction_processor, options, output)?;
+    if let Some(settlement) = &options.settlement {
+        settlement.write_carry_over(&transaction_processor, options)?;
+    }
+
+    summary.set_elapsed(start.elapsed());
+    Ok(summary)
+}
+
+/// Reads the input file at `path`, applying each transaction using
+/// `transact` (see [`apply_transactions`]) after the first `skip_rows` rows.
+/// Counts the file and its rows in `summary`.
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(level = "info", name = "ingest_file", skip_all, fields(path = path))
+)]
+fn read_file(
+    mut transact: impl FnMut(&Transaction) -> Result<(), TransactionError>,
+    path: &str,
+    options: &ProcessOptions,
+    skip_rows: usize,
+    rejects: &mut Option<RejectWriter<&mut dyn io::Write>>,
+    summary: &mut BatchResult,
 ) -> Result<(), TransactionProcessorCLIError> {
-    let mut transaction_processor = TransactionProcessor::new();
+    log::info!("Reading file {}", path);
+    summary.record_file();
+    #[cfg(feature = "tui")]
+    if let Some(dashboard) = &options.dashboard {
+        dashboard.record_file(path);
+    }
+
+    let open_error = |error| TransactionProcessorCLIError::FailedToOpenFile {
+        path: path.to_string(),
+        error,
+    };
+
+    let checksum = expected_checksum(path, options.require_checksums)?;
+    let mut checksum_handle = None;
+    let mut open_input = || {
+        let reader = open_raw_input(path).map_err(open_error)?;
+        match checksum {
+            Some(expected) => {
+                let reader = ChecksumReader::new(reader, expected);
+                checksum_handle = Some(reader.handle());
+                decompress_input(reader, path)
+            }
+            None => decompress_input(reader, path),
+        }
+        .map_err(open_error)
+    };
 
-    for arg in input_files {
-        log::info!("Reading file {}", arg);
+    // Without a recognised extension, the format is detected from the start
+    // of the (decompressed) file, which is then read from the beginning
+    let mut sniffed = None;
+    let format = match options
+        .input_format
+        .or_else(|| InputFormat::from_extension(path))
+    {
+        Some(format) => format,
+        None => {
+            let mut reader = open_input()?;
+            let format = InputFormat::from_content(reader.fill_buf().map_err(open_error)?);
+            log::debug!("Detected {:?} input in {}", format, path);
+            sniffed = Some(reader);
+            format
+        }
+    };
+    let mut open = || match sniffed.take() {
+        Some(reader) => Ok(reader),
+        None => open_input(),
+    };
 
-        let mut csv_reader = CSVReader::new(File::open(arg).map_err(|error| {
-            TransactionProcessorCLIError::FailedToOpenFile {
-                path: arg.clone(),
+    match format {
+        InputFormat::Csv | InputFormat::Tsv => {
+            let delimiter = format.delimiter().unwrap_or(b',');
+            let mut reader =
+                pipeline::delimited_reader(open()?, path, delimiter, &options.pipeline);
+            let mut warnings = 0;
+
+            apply_transactions(
+                &mut transact,
+                path,
+                pipeline::csv_rows(&mut reader, &mut warnings),
+                options,
+                skip_rows,
+                rejects,
+                summary,
+            )?;
+            summary.record_warnings(warnings);
+        }
+        InputFormat::Jsonl => apply_transactions(
+            &mut transact,
+            path,
+            pipeline::jsonl_rows(&mut pipeline::jsonl_reader(open()?, &options.pipeline)),
+            options,
+            skip_rows,
+            rejects,
+            summary,
+        )?,
+        #[cfg(feature = "avro")]
+        InputFormat::Avro => apply_transactions(
+            &mut transact,
+            path,
+            pipeline::numbered_rows(
+                AvroReader::new(open()?)
+                    .map_err(|err| TransactionProcessorCLIError::InvalidInputFile {
+                        path: path.to_string(),
+                        error: err.to_string(),
+                    })?
+                    .with_amount_format(options.pipeline.amount_format())
+                    .read()
+                    .map(|transaction| (Vec::new(), transaction)),
+            ),
+            options,
+            skip_rows,
+            rejects,
+            summary,
+        )?,
+        #[cfg(feature = "parquet")]
+        InputFormat::Parquet => {
+            // Parquet files are compressed internally, and must be
+            // seekable, so are always read directly (and verified first),
+            // rather than by any reader the format was detected with
+            drop(sniffed);
+            checksum_handle = None;
+            apply_transactions(
+                &mut transact,
+                path,
+                pipeline::numbered_rows(
+                    ParquetReader::new(open_parquet(path, checksum)?)
+                        .map_err(|err| TransactionProcessorCLIError::InvalidInputFile {
+                            path: path.to_string(),
+                            error: err.to_string(),
+                        })?
+                        .with_amount_format(options.pipeline.amount_format())
+                        .read()
+                        .map(|transaction| (Vec::new(), transaction)),
+                ),
+                options,
+                skip_rows,
+                rejects,
+                summary,
+            )?;
+        }
+    }
+
+    match checksum_handle.map(|handle| handle.result()) {
+        None | Some(Some(Ok(()))) => Ok(()),
+        Some(Some(Err(error))) => Err(TransactionProcessorCLIError::ChecksumFailed {
+            path: path.to_string(),
+            error: error.to_string(),
+        }),
+        // The file could not be read to the end (and the error was skipped)
+        Some(None) => Err(TransactionProcessorCLIError::ChecksumFailed {
+            path: path.to_string(),
+            error: "the file could not be read to the end".to_string(),
+        }),
+    }
+}
+
+/// The checksum which the input file at `path` must have, from its sidecar
+/// checksum file (e.g. `input.csv.sha256`). Local files without a sidecar
+/// file are not verified, unless `required` is set.
+fn expected_checksum(
+    path: &str,
+    required: bool,
+) -> Result<Option<Sha256Digest>, TransactionProcessorCLIError> {
+    let sidecar = format!("{}.{}", path, SIDECAR_EXTENSION);
+    if !required && (path.contains("://") || !Path::new(&sidecar).exists()) {
+        return Ok(None);
+    }
+
+    let mut contents = String::new();
+    open_raw_input(&sidecar)
+        .and_then(|mut reader| reader.read_to_string(&mut contents))
+        .map_err(|error| TransactionProcessorCLIError::FailedToOpenFile {
+            path: sidecar.clone(),
+            error,
+        })?;
+
+    Sha256Digest::from_sidecar(&contents)
+        .map(Some)
+        .map_err(|error| TransactionProcessorCLIError::ChecksumFailed {
+            path: sidecar,
+            error: error.to_string(),
+        })
+}
+
+/// Opens a Parquet file, first verifying the whole file against `checksum`
+/// (if given), as Parquet files are not read sequentially.
+#[cfg(feature = "parquet")]
+fn open_parquet(
+    path: &str,
+    checksum: Option<Sha256Digest>,
+) -> Result<File, TransactionProcessorCLIError> {
+    let open_error = |error| TransactionProcessorCLIError::FailedToOpenFile {
+        path: path.to_string(),
+        error,
+    };
+
+    if let Some(expected) = checksum {
+        let mut reader = ChecksumReader::new(File::open(path).map_err(open_error)?, expected);
+        let handle = reader.handle();
+        if io::copy(&mut reader, &mut io::sink()).is_err() {
+            if let Some(Err(error)) = handle.result() {
+                return Err(TransactionProcessorCLIError::ChecksumFailed {
+                    path: path.to_string(),
+                    error: error.to_string(),
+                });
+            }
+        }
+    }
+
+    File::open(path).map_err(open_error)
+}
+
+/// Writes a snapshot of the accounts, the transactions deferred to the next
+/// run and the positions of the streams read, encrypted with
+/// `options.snapshot_key` if it is set.
+#[cfg_attr(not(feature = "encryption"), allow(unused_variables))]
+fn encode_snapshot(
+    transaction_processor: &TransactionProcessor,
+    deferred: &[Transaction],
+    positions: &StreamPositions,
+    options: &ProcessOptions,
+    writer: impl io::Write,
+) -> Result<(), SnapshotError> {
+    #[cfg(feature = "encryption")]
+    if let Some(key) = &options.snapshot_key {
+        return write_encrypted_snapshot_with_positions(
+            transaction_processor,
+            deferred,
+            positions,
+            writer,
+            key,
+        );
+    }
+    write_snapshot_with_positions(transaction_processor, deferred, positions, writer)
+}
+
+/// Reads a snapshot written by [`encode_snapshot`] with the same options.
+#[cfg_attr(not(feature = "encryption"), allow(unused_variables))]
+fn decode_snapshot(
+    reader: impl io::Read,
+    options: &ProcessOptions,
+) -> Result<(TransactionProcessor, Vec<Transaction>, StreamPositions), SnapshotError> {
+    #[cfg(feature = "encryption")]
+    if let Some(key) = &options.snapshot_key {
+        return read_encrypted_snapshot_with_positions(reader, key);
+    }
+    read_snapshot_with_positions(reader)
+}
+
+/// Reads the accounts in the snapshot file at `path` into a processor with the
+/// options of `transaction_processor`.
+fn read_snapshot_file(
+    transaction_processor: TransactionProcessor,
+    path: &Path,
+    options: &ProcessOptions,
+) -> Result<TransactionProcessor, TransactionProcessorCLIError> {
+    read_snapshot_file_with_deferred(transaction_processor, path, options)
+        .map(|(transaction_processor, _)| transaction_processor)
+}
+
+/// Reads the snapshot file at `path` like [`read_snapshot_file`], along with
+/// the transactions deferred to this run.
+fn read_snapshot_file_with_deferred(
+    transaction_processor: TransactionProcessor,
+    path: &Path,
+    options: &ProcessOptions,
+) -> Result<(TransactionProcessor, Vec<Transaction>), TransactionProcessorCLIError> {
+    let read_error = |error: String| TransactionProcessorCLIError::InvalidInputFile {
+        path: path.display().to_string(),
+        error,
+    };
+
+    let file =
+        File::open(path).map_err(|error| TransactionProcessorCLIError::FailedToOpenFile {
+            path: path.display().to_string(),
+            error,
+        })?;
+    let (restored, deferred, _) = decode_snapshot(io::BufReader::new(file), options)
+        .map_err(|err| read_error(err.to_string()))?;
+
+    // The restored processor has default options, so the accounts are moved
+    // into one with the configured options
+    let mut transaction_processor = transaction_processor.new_like();
+    transaction_processor
+        .merge(restored)
+        .map_err(|err| read_error(err.to_string()))?;
+    Ok((transaction_processor, deferred))
+}
+
+/// Writes the report to `output` (replacing the file if it exists), or to
+/// stdout. Used where the report is written repeatedly as the state changes.
+fn replace_report(
+    transaction_processor: &TransactionProcessor,
+    options: &ProcessOptions,
+    output: Option<&Path>,
+) -> Result<(), TransactionProcessorCLIError> {
+    match output {
+        Some(path) => {
+            let create_error = |error| TransactionProcessorCLIError::FailedToCreateOutput {
+                path: path.display().to_string(),
                 error,
+            };
+
+            let mut file =
+                AtomicFile::create(path, ExistingOutput::Overwrite).map_err(create_error)?;
+            write_report(transaction_processor, options, &mut file)?;
+            file.commit().map_err(create_error)
+        }
+        None => write_report(transaction_processor, options, io::stdout()),
+    }
+}
+
+/// Writes the report in the output format.
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(level = "info", skip_all, fields(format = ?options.output_format))
+)]
+fn write_report(
+    transaction_processor: &TransactionProcessor,
+    options: &ProcessOptions,
+    output: impl io::Write + Send,
+) -> Result<(), TransactionProcessorCLIError> {
+    match options.output_format {
+        OutputFormat::Csv => {
+            let csv_output = options
+                .csv_output
+                .clone()
+                .with_headers(options.csv_output.has_headers() && !options.omit_output_header);
+
+            match options.business_date() {
+                Some(business_date) => {
+                    // The business date comes first, even if columns are
+                    // selected
+                    let columns = csv_output.columns().map(|columns| {
+                        std::iter::once(BUSINESS_DATE_COLUMN.to_string())
+                            .chain(columns.iter().cloned())
+                            .collect()
+                    });
+                    let csv_output = csv_output.with_columns(columns);
+                    let writer =
+                        DatedSink::new(CSVWriter::with_options(output, &csv_output), business_date);
+                    pipeline::write_report(transaction_processor, &options.pipeline, writer)
+                        .map(|_| ())
+                }
+                None => {
+                    let writer = CSVWriter::with_options(output, &csv_output);
+                    pipeline::write_report(transaction_processor, &options.pipeline, writer)
+                        .map(|_| ())
+                }
             }
-        })?);
-
-        for transaction in csv_reader.read() {
-            // In a production banking system, it would make sense to
-            // take more drastic action here if an error occurs. This may
-            // include, for example, storing the failed transaction
-            // somewhere for human inspection and resolution.
-            match transaction {
-                Ok(transaction) => {
-                    if let Err(err) = transaction_processor.transact(&transaction) {
-                        log::error!("Got error '{}' processing transaction. Skipping.", err);
+            .map_err(|err| TransactionProcessorCLIError::FailedToWriteReport(err.to_string()))?;
+        }
+        OutputFormat::Jsonl => match options.business_date() {
+            Some(business_date) => pipeline::write_report(
+                transaction_processor,
+                &options.pipeline,
+                DatedSink::new(JsonlWriter::new(output), business_date),
+            )
+            .and_then(|writer| writer.into_inner().into_inner()),
+            None => pipeline::write_report(
+                transaction_processor,
+                &options.pipeline,
+                JsonlWriter::new(output),
+            )
+            .and_then(JsonlWriter::into_inner),
+        }
+        .map(|_| ())
+        .map_err(|err| TransactionProcessorCLIError::FailedToWriteReport(err.to_string()))?,
+        #[cfg(feature = "avro")]
+        OutputFormat::Avro => write_avro_report(transaction_processor, options, output)
+            .map_err(TransactionProcessorCLIError::FailedToWriteReport)?,
+        #[cfg(feature = "parquet")]
+        OutputFormat::Parquet => parquet::write_report(
+            output,
+            &transaction_processor
+                .generate_report(
+                    options.pipeline.report_filter(),
+                    options.pipeline.report_order(),
+                )
+                .collect::<Vec<_>>(),
+            options.pipeline.output_scale(),
+        )
+        .map_err(|err| TransactionProcessorCLIError::FailedToWriteReport(err.to_string()))?,
+        OutputFormat::Snapshot => encode_snapshot(
+            transaction_processor,
+            &[],
+            &StreamPositions::new(),
+            options,
+            output,
+        )
+        .map_err(|err| TransactionProcessorCLIError::FailedToWriteReport(err.to_string()))?,
+        OutputFormat::Statement => {
+            let mut writer = CSVWriter::new(output);
+            if let Some(statement) = &options.statement {
+                let statement = statement.lock().unwrap_or_else(PoisonError::into_inner);
+                for entry in statement.entries() {
+                    let entry = match options.pipeline.output_scale() {
+                        Some(scale) => entry.clone().with_scale(scale),
+                        None => entry.clone(),
+                    };
+
+                    if let Err(err) = writer.write(entry) {
+                        log::error!("Failed to write entry: {}", err);
                     }
                 }
-                Err(err) => {
-                    log::error!("Got error '{}' reading CSV. Skipping transaction.", err);
-                }
             }
         }
     }
 
+    if let Some(path) = &options.report_summary {
+        write_report_summary(transaction_processor, options, path)?;
+    }
+    if let Some(path) = &options.disputes_output {
+        write_dispute_report(transaction_processor, options, path)?;
+    }
+    if let Some(path) = &options.locked_output {
+        replace_csv_file(path, transaction_processor.generate_locked_report())?;
+    }
+    if let Some(path) = &options.negative_output {
+        replace_csv_file(
+            path,
+            transaction_processor
+                .generate_negative_balance_report()
+                .map(|entry| match options.pipeline.output_scale() {
+                    Some(scale) => entry.with_scale(scale),
+                    None => entry,
+                }),
+        )?;
+    }
+    if let Some((path, ledger)) = &options.ledger {
+        let ledger = ledger.lock().unwrap_or_else(PoisonError::into_inner);
+        ledger
+            .check(transaction_processor)
+            .map_err(|err| TransactionProcessorCLIError::LedgerUnbalanced(err.to_string()))?;
+        replace_csv_file(
+            path,
+            ledger
+                .postings()
+                .iter()
+                .map(|posting| match options.pipeline.output_scale() {
+                    Some(scale) => posting.clone().with_scale(scale),
+                    None => posting.clone(),
+                }),
+        )?;
+    }
+    if let Some((path, aggregator)) = &options.aggregate {
+        let entries = aggregator
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .entries()
+            .map_err(|err| {
+                TransactionProcessorCLIError::FailedToWriteReport(format!(
+                    "Failed to sum aggregate totals: {}",
+                    err
+                ))
+            })?;
+        replace_csv_file(
+            path,
+            entries
+                .into_iter()
+                .map(|entry| match options.pipeline.output_scale() {
+                    Some(scale) => entry.with_scale(scale),
+                    None => entry,
+                }),
+        )?;
+    }
+    Ok(())
+}
+
+/// Restores the accounts and the position of an interrupted run from the
+/// checkpoint file at `path`, which must have been written for the same
+/// `input_files`.
+fn read_checkpoint_file(
+    transaction_processor: TransactionProcessor,
+    path: &Path,
+    input_files: &[String],
+) -> Result<(TransactionProcessor, Checkpoint), TransactionProcessorCLIError> {
+    let read_error = |error: String| TransactionProcessorCLIError::InvalidInputFile {
+        path: path.display().to_string(),
+        error,
+    };
+
+    let file =
+        File::open(path).map_err(|error| TransactionProcessorCLIError::FailedToOpenFile {
+            path: path.display().to_string(),
+            error,
+        })?;
+    let (restored, checkpoint) =
+        read_checkpoint(io::BufReader::new(file)).map_err(|err| read_error(err.to_string()))?;
+    if checkpoint.inputs() != input_files {
+        return Err(read_error(format!(
+            "the checkpoint is of a run of different input files ({})",
+            checkpoint.inputs().join(", ")
+        )));
+    }
+
+    match input_files.get(checkpoint.files()) {
+        Some(input) => log::info!("Resuming after row {} of {}", checkpoint.rows(), input),
+        None => log::info!("The checkpointed run had already read every input file"),
+    }
+
+    // As with snapshots, the accounts are moved into a processor with the
+    // configured options
+    let mut transaction_processor = transaction_processor.new_like();
+    transaction_processor
+        .merge(restored)
+        .map_err(|err| read_error(err.to_string()))?;
+    Ok((transaction_processor, checkpoint))
+}
+
+/// Writes the clients whose accounts differ between the report file
+/// `before` and `target`, as CSV.
+fn write_diff(
+    transaction_processor: TransactionProcessor,
+    before: &Path,
+    target: &DiffTarget,
+    options: &ProcessOptions,
+    output: impl io::Write,
+) -> Result<(), TransactionProcessorCLIError> {
+    let before = read_report_file(before)?;
+    let diffs = match target {
+        DiffTarget::Report(after) => report::diff_reports(before, read_report_file(after)?),
+        DiffTarget::Snapshot(path) => {
+            read_snapshot_file(transaction_processor, path, options)?.diff_report(before)
+        }
+    };
+
     let mut writer = CSVWriter::new(output);
+    for diff in diffs {
+        let diff = match options.pipeline.output_scale() {
+            Some(scale) => diff.with_scale(scale),
+            None => diff,
+        };
+        writer
+            .write(diff)
+            .map_err(|err| TransactionProcessorCLIError::FailedToWriteReport(err.to_string()))?;
+    }
+    Ok(())
+}
+
+/// Reads a CSV report file, failing at the first invalid row, as a
+/// comparison with rows missing would be wrong.
+fn read_report_file(path: &Path) -> Result<Vec<ReportEntry>, TransactionProcessorCLIError> {
+    let file =
+        File::open(path).map_err(|error| TransactionProcessorCLIError::FailedToOpenFile {
+            path: path.display().to_string(),
+            error,
+        })?;
+
+    ReportReader::new(io::BufReader::new(file))
+        .read()
+        .collect::<Result<_, _>>()
+        .map_err(|err| TransactionProcessorCLIError::InvalidInputFile {
+            path: path.display().to_string(),
+            error: err.to_string(),
+        })
+}
+
+/// Replaces the file at `path` with the grand totals of the report, as a
+/// single CSV row.
+fn write_report_summary(
+    transaction_processor: &TransactionProcessor,
+    options: &ProcessOptions,
+    path: &Path,
+) -> Result<(), TransactionProcessorCLIError> {
+    let totals = transaction_processor
+        .report_totals(options.pipeline.report_filter())
+        .map_err(|err| {
+            TransactionProcessorCLIError::FailedToWriteReport(format!(
+                "Failed to sum report totals: {}",
+                err
+            ))
+        })?;
+    let totals = match options.pipeline.output_scale() {
+        Some(scale) => totals.with_scale(scale),
+        None => totals,
+    };
+
+    replace_csv_file(path, [totals])
+}
+
+/// Replaces the file at `path` with the active disputes, as CSV.
+fn write_dispute_report(
+    transaction_processor: &TransactionProcessor,
+    options: &ProcessOptions,
+    path: &Path,
+) -> Result<(), TransactionProcessorCLIError> {
+    replace_csv_file(
+        path,
+        transaction_processor
+            .generate_dispute_report()
+            .map(|entry| match options.pipeline.output_scale() {
+                Some(scale) => entry.with_scale(scale),
+                None => entry,
+            }),
+    )
+}
+
+/// Replaces the file at `path` with `records`, as CSV with a header row.
+fn replace_csv_file(
+    path: &Path,
+    records: impl IntoIterator<Item = impl Serialize>,
+) -> Result<(), TransactionProcessorCLIError> {
+    let create_error = |error| TransactionProcessorCLIError::FailedToCreateOutput {
+        path: path.display().to_string(),
+        error,
+    };
+    let mut file = AtomicFile::create(path, ExistingOutput::Overwrite).map_err(create_error)?;
+    let mut writer = CSVWriter::new(&mut file);
+    for record in records {
+        writer
+            .write(record)
+            .map_err(|err| TransactionProcessorCLIError::FailedToWriteReport(err.to_string()))?;
+    }
+    drop(writer);
+    file.commit().map_err(create_error)
+}
+
+/// Writes the report as Avro, using the published report entry schema.
+#[cfg(feature = "avro")]
+fn write_avro_report(
+    transaction_processor: &TransactionProcessor,
+    options: &ProcessOptions,
+    output: impl io::Write,
+) -> Result<(), String> {
+    let schema = avro::report_entry_schema().map_err(|err| err.to_string())?;
+    let mut writer = AvroWriter::new(&schema, output).map_err(|err| err.to_string())?;
+
+    for entry in transaction_processor.generate_report(
+        options.pipeline.report_filter(),
+        options.pipeline.report_order(),
+    ) {
+        let entry = match options.pipeline.output_scale() {
+            Some(scale) => entry.with_scale(scale),
+            None => entry,
+        };
 
-    for entry in transaction_processor.generate_report() {
         if let Err(err) = writer.write(entry) {
             log::error!("Failed to write entry: {}", err);
         }
     }
 
+    writer.finish().map(|_| ()).map_err(|err| err.to_string())
+}
+
+/// Applies each transaction read from the input file at `path` using
+/// `transact` (usually [`TransactionProcessor::transact`]), logging
+/// and skipping any that fail to be read or applied (see
+/// [`pipeline::apply_rows`]). If the options fail fast, the first such
+/// failure is returned as an error instead.
+///
+/// Each transaction is paired with the fields of the row it was read from,
+/// which are written to `rejects` (if given) along with the error if the
+/// transaction is skipped. If a format has no verbatim fields, the fields of
+/// the transaction itself are written instead.
+///
+/// Each row is numbered by its line or position in the input, for errors.
+/// The first `skip_rows` rows are skipped (e.g. when resuming a run). Every
+/// other row is counted in `summary`, and in the `options.checkpoint`.
+fn apply_transactions<E: Display>(
+    transact: impl FnMut(&Transaction) -> Result<(), TransactionError>,
+    path: &str,
+    transactions: impl Iterator<Item = (usize, (Vec<String>, Result<Transaction, E>))>,
+    options: &ProcessOptions,
+    skip_rows: usize,
+    rejects: &mut Option<RejectWriter<&mut dyn io::Write>>,
+    summary: &mut BatchResult,
+) -> Result<(), TransactionProcessorCLIError> {
+    let rows = transactions.skip(skip_rows).inspect(|_| {
+        if let Some(checkpoint) = &options.checkpoint {
+            checkpoint.record_row();
+        }
+    });
+    // With --cutoff, later transactions are deferred rather than applied
+    let mut deferred = 0;
+    let rows = rows.filter(
+        |(_, (_, transaction))| match (&options.settlement, transaction) {
+            (Some(settlement), Ok(transaction)) => {
+                let defer = settlement
+                    .cutoff
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .defer(transaction);
+                deferred += usize::from(defer);
+                !defer
+            }
+            _ => true,
+        },
+    );
+    // Closing the dashboard stops the run at the next row
+    #[cfg(feature = "tui")]
+    let interrupted = || {
+        options
+            .dashboard
+            .as_ref()
+            .is_some_and(tui::Dashboard::interrupted)
+    };
+    #[cfg(feature = "tui")]
+    let rows = rows.take_while(|_| !interrupted());
+    let mut reject = |row: RejectedRow| {
+        #[cfg(feature = "tui")]
+        if let Some(dashboard) = &options.dashboard {
+            dashboard.record_rejected(row.reason().to_string(), row.is_parse_error());
+        }
+        if let Some(Err(err)) = rejects.as_mut().map(|rejects| rejects.reject(row)) {
+            log::error!("Failed to write rejected row: {}", err);
+        }
+    };
+
+    let result = pipeline::apply_rows(
+        transact,
+        path,
+        rows,
+        &options.pipeline,
+        &mut reject,
+        summary,
+    );
+    summary.record_deferred(deferred);
+    result?;
+    #[cfg(feature = "tui")]
+    if interrupted() {
+        return Err(TransactionProcessorCLIError::Interrupted);
+    }
     Ok(())
 }
 
@@ -97,6 +1608,82 @@ fn process_files(
 enum TransactionProcessorCLIError {
     /// One of the specified files could not be opened.
     FailedToOpenFile { path: String, error: io::Error },
+    /// One of the specified files is not in the expected format.
+    InvalidInputFile { path: String, error: String },
+    /// The report could not be written.
+    FailedToWriteReport(String),
+    /// The output file could not be created or moved into place.
+    FailedToCreateOutput { path: String, error: io::Error },
+    /// A file processed in parallel has a client in common with an earlier
+    /// file, so the results cannot be merged.
+    FilesNotIndependent { path: String, client: ClientId },
+    /// An input file does not match its sidecar checksum, or the checksum
+    /// file is invalid.
+    ChecksumFailed { path: String, error: String },
+    /// The REST API or gRPC server could not be started.
+    #[cfg(any(feature = "server", feature = "grpc"))]
+    ServerFailed {
+        address: std::net::SocketAddr,
+        error: io::Error,
+    },
+    /// The Kafka consumer failed.
+    #[cfg(feature = "kafka")]
+    KafkaFailed(String),
+    /// A snapshot could not be read or written.
+    #[cfg(feature = "kafka")]
+    SnapshotFailed { path: String, error: String },
+    /// The books of `--ledger` don't balance, i.e. there is a bug.
+    LedgerUnbalanced(String),
+    /// The `--tui` dashboard could not be shown, e.g. because stderr is not
+    /// a terminal.
+    #[cfg(feature = "tui")]
+    FailedToShowDashboard(io::Error),
+    /// The `--tui` dashboard was closed before processing finished.
+    #[cfg(feature = "tui")]
+    Interrupted,
+    /// A row failed to be read in strict mode. `record` is the 1-based line
+    /// the row starts on in CSV and JSON Lines files, or its 1-based
+    /// position among the rows of other files.
+    InvalidRecord {
+        path: String,
+        record: usize,
+        error: String,
+    },
+    /// A row failed to be applied in strict mode, like
+    /// [`TransactionProcessorCLIError::InvalidRecord`].
+    TransactionFailed {
+        path: String,
+        record: usize,
+        error: String,
+    },
+}
+
+impl From<PipelineError> for TransactionProcessorCLIError {
+    fn from(err: PipelineError) -> Self {
+        match err {
+            PipelineError::InvalidRecord {
+                source,
+                record,
+                error,
+            } => TransactionProcessorCLIError::InvalidRecord {
+                path: source,
+                record,
+                error,
+            },
+            PipelineError::TransactionFailed {
+                source,
+                record,
+                error,
+            } => TransactionProcessorCLIError::TransactionFailed {
+                path: source,
+                record,
+                error,
+            },
+            PipelineError::FailedToWriteReport(err) => {
+                TransactionProcessorCLIError::FailedToWriteReport(err)
+            }
+        }
+    }
 }
 
 impl Display for TransactionProcessorCLIError {
@@ -105,15 +1692,140 @@ impl Display for TransactionProcessorCLIError {
             TransactionProcessorCLIError::FailedToOpenFile { path, error } => {
                 format!("Failed to open '{}': {}", path, error)
             }
+            TransactionProcessorCLIError::InvalidInputFile { path, error } => {
+                format!("Failed to read '{}': {}", path, error)
+            }
+            TransactionProcessorCLIError::FailedToWriteReport(err) => {
+                format!("Failed to write report: {}", err)
+            }
+            TransactionProcessorCLIError::FailedToCreateOutput { path, error } => {
+                format!("Failed to write output file '{}': {}", path, error)
+            }
+            TransactionProcessorCLIError::InvalidRecord {
+                path,
+                record,
+                error,
+            }
+            | TransactionProcessorCLIError::TransactionFailed {
+                path,
+                record,
+                error,
+            } => format!("'{}' record {}: {}", path, record, error),
+            TransactionProcessorCLIError::ChecksumFailed { path, error } => {
+                format!("'{}' failed checksum verification: {}", path, error)
+            }
+            #[cfg(any(feature = "server", feature = "grpc"))]
+            TransactionProcessorCLIError::ServerFailed { address, error } => {
+                format!("Failed to run server on {}: {}", address, error)
+            }
+            #[cfg(feature = "kafka")]
+            TransactionProcessorCLIError::KafkaFailed(err) => format!("Kafka error: {}", err),
+            #[cfg(feature = "kafka")]
+            TransactionProcessorCLIError::SnapshotFailed { path, error } => {
+                format!("Snapshot '{}' failed: {}", path, error)
+            }
+            #[cfg(feature = "tui")]
+            TransactionProcessorCLIError::FailedToShowDashboard(err) => {
+                format!("Failed to show dashboard: {}", err)
+            }
+            #[cfg(feature = "tui")]
+            TransactionProcessorCLIError::Interrupted => {
+                "Interrupted from the dashboard".to_string()
+            }
+            TransactionProcessorCLIError::LedgerUnbalanced(err) => {
+                format!("The ledger does not balance: {}", err)
+            }
+            TransactionProcessorCLIError::FilesNotIndependent { path, client } => format!(
+                "'{}' has transactions for client {}, which also appears in an earlier file, \
+                 so the files cannot be processed in parallel",
+                path, client
+            ),
         })
     }
 }
 
 #[cfg(test)]
 mod test {
+    use std::ffi::OsString;
+    use std::path::PathBuf;
+    use std::str::FromStr;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{mpsc, Arc, Mutex};
+
     use log::LevelFilter;
+    use transaction_processor::alert::{BalanceMonitor, BalanceThreshold, BalanceThresholds};
+    use transaction_processor::crypto::HmacKey;
+    use transaction_processor::cutoff::Cutoff;
+    use transaction_processor::io::checksum::Sha256Digest;
+    use transaction_processor::io::csv::{CSVWriterOptions, LineTerminator, QuoteStyle};
+    use transaction_processor::numeric::{CurrencyAmount, REDACTED_AMOUNT};
+    use transaction_processor::pipeline::PipelineOptions;
+    use transaction_processor::TransactionProcessor;
+
+    use crate::args::{parse_args, Mode};
+    use crate::exit_code::ExitCode;
+    use crate::{
+        capacity_hint, process_files, read_snapshot_file_with_deferred, run, ProcessOptions,
+        Settlement, TransactionProcessorCLIError,
+    };
+
+    /// An amount as it appears in error messages, which is masked with the
+    /// `redact` feature.
+    pub(crate) fn logged(amount: &str) -> &str {
+        if cfg!(feature = "redact") {
+            REDACTED_AMOUNT
+        } else {
+            amount
+        }
+    }
+
+    /// An empty directory for the files written by a test, which is removed
+    /// with them when dropped. Each has its own path, so that tests running
+    /// at the same time never share files.
+    pub(crate) struct TempDir(PathBuf);
+
+    impl TempDir {
+        /// Creates a directory whose name starts with `name`.
+        pub(crate) fn new(name: &str) -> Self {
+            static NEXT: AtomicUsize = AtomicUsize::new(0);
+            let path = std::env::temp_dir().join(format!(
+                "transaction-processor-{}-{}-{}",
+                name,
+                std::process::id(),
+                NEXT.fetch_add(1, Ordering::Relaxed)
+            ));
+            // Left by an earlier run with the same process ID
+            let _ = std::fs::remove_dir_all(&path);
+            std::fs::create_dir_all(&path).unwrap();
+            Self(path)
+        }
 
-    use crate::process_files;
+        /// The path of the file `name` in the directory.
+        pub(crate) fn join(&self, name: &str) -> PathBuf {
+            self.0.join(name)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn test_capacity_hint() {
+        assert_eq!(None, capacity_hint(&["tests/data/missing.csv".to_string()]));
+
+        // A sparse file, as only its size matters
+        let directory = TempDir::new("capacity-hint");
+        let path = directory.join("input.csv");
+        let file = std::fs::File::create(&path).unwrap();
+        file.set_len(1 << 20).unwrap();
+        let input_files = [path.to_string_lossy().into_owned()];
+        assert_eq!(None, capacity_hint(&input_files));
+        file.set_len(64 << 20).unwrap();
+        assert_eq!(Some((1 << 16, 42)), capacity_hint(&input_files));
+    }
 
     #[test]
     fn run_with_test_data() {
@@ -129,7 +1841,14 @@ mod test {
             let expected_output_file = format!("test_data/{:03}_expected.csv", file);
 
             let mut output = Vec::new();
-            process_files(&[input_file], &mut output).unwrap();
+            process_files(
+                TransactionProcessor::new(),
+                &[input_file],
+                &ProcessOptions::default(),
+                &mut output,
+                None,
+            )
+            .unwrap();
 
             let expected = std::fs::read_to_string(expected_output_file)
                 .unwrap()
@@ -138,4 +1857,612 @@ mod test {
             assert_eq!(expected, String::from_utf8_lossy(output.as_slice()));
         }
     }
+
+    #[test]
+    fn run_with_jsonl_test_data() {
+        let mut output = Vec::new();
+        process_files(
+            TransactionProcessor::new(),
+            &["test_data/008_input.jsonl".to_string()],
+            &ProcessOptions::default(),
+            &mut output,
+            None,
+        )
+        .unwrap();
+
+        let expected = std::fs::read_to_string("test_data/008_expected.csv")
+            .unwrap()
+            .replace("\r\n", "\n");
+
+        assert_eq!(expected, String::from_utf8_lossy(output.as_slice()));
+    }
+
+    #[test]
+    fn run_with_detected_formats() {
+        // Files without a recognised extension are detected by their contents
+        let directory = TempDir::new("detect");
+        let tsv = directory.join("a");
+        std::fs::write(&tsv, "type\tclient\ttx\tamount\ndeposit\t1\t1\t2.5\n").unwrap();
+        let jsonl = directory.join("b.txt");
+        std::fs::write(
+            &jsonl,
+            "{\"type\": \"withdrawal\", \"client\": 1, \"tx\": 2, \"amount\": \"1.0\"}\n",
+        )
+        .unwrap();
+
+        let mut output = Vec::new();
+        process_files(
+            TransactionProcessor::new(),
+            &[
+                tsv.to_string_lossy().into_owned(),
+                jsonl.to_string_lossy().into_owned(),
+            ],
+            &ProcessOptions::default(),
+            &mut output,
+            None,
+        )
+        .unwrap();
+
+        assert_eq!(
+            "client,available,held,total,locked\n1,1.5,0,1.5,false\n",
+            String::from_utf8_lossy(output.as_slice())
+        );
+    }
+
+    #[test]
+    fn run_with_balance_alerts() {
+        let directory = TempDir::new("alerts");
+        let input = directory.join("alerts.csv");
+        std::fs::write(
+            &input,
+            "type,client,tx,amount\n\
+             deposit,1,1,5\n\
+             withdrawal,1,2,3\n\
+             dispute,1,1,\n",
+        )
+        .unwrap();
+
+        let amount = |amount| CurrencyAmount::from_str(amount).unwrap();
+        let thresholds = BalanceThresholds::new().with_global(
+            BalanceThreshold::new()
+                .with_floor(amount("3"))
+                .with_held_ceiling(amount("4")),
+        );
+        let (sender, alerts) = mpsc::channel();
+        let observer: Box<dyn FnMut(_) + Send> = Box::new(move |alert| {
+            sender.send(alert).unwrap();
+        });
+        process_files(
+            TransactionProcessor::new(),
+            &[input.to_string_lossy().into_owned()],
+            &ProcessOptions {
+                alerts: Some(Arc::new(Mutex::new(BalanceMonitor::new(
+                    thresholds, observer,
+                )))),
+                ..ProcessOptions::default()
+            },
+            Vec::new(),
+            None,
+        )
+        .unwrap();
+
+        // The dispute takes the available funds further below the floor,
+        // which isn't alerted again
+        assert_eq!(
+            vec![
+                "Available funds of client 1 dropped below 3 to 2 (transaction 2)",
+                "Held funds of client 1 rose above 4 to 5 (transaction 1)",
+            ],
+            alerts
+                .try_iter()
+                .map(|alert| alert.to_string())
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn run_with_cutoff() {
+        let directory = TempDir::new("cutoff");
+        let input = directory.join("day.csv");
+        std::fs::write(
+            &input,
+            "type,client,tx,amount,timestamp\n\
+             deposit,1,1,5,2024-03-01T10:00:00Z\n\
+             deposit,1,2,3,2024-03-01T18:00:00Z\n\
+             dispute,1,2,,\n",
+        )
+        .unwrap();
+        let carry_over = directory.join("carry-over.snapshot");
+
+        // Each batch starts from the accounts and transactions carried over
+        // by the last
+        let run = |transaction_processor, carried, cutoff: &str| {
+            let mut output = Vec::new();
+            let options = ProcessOptions {
+                settlement: Some(Arc::new(Settlement {
+                    path: carry_over.clone(),
+                    carried,
+                    cutoff: Mutex::new(Cutoff::new(cutoff.parse().unwrap())),
+                })),
+                ..ProcessOptions::default()
+            };
+            let summary = process_files(
+                transaction_processor,
+                &[input.to_string_lossy().into_owned()],
+                &options,
+                &mut output,
+                None,
+            )
+            .unwrap();
+            (String::from_utf8(output).unwrap(), summary)
+        };
+
+        let (report, summary) = run(
+            TransactionProcessor::new(),
+            Vec::new(),
+            "2024-03-01T17:00:00Z",
+        );
+        assert_eq!(
+            "business_date,client,available,held,total,locked\n\
+             2024-03-01,1,5,0,5,false\n",
+            report
+        );
+        assert_eq!(2, summary.transactions_deferred());
+
+        std::fs::write(&input, "type,client,tx,amount,timestamp\n").unwrap();
+        let (transaction_processor, carried) = read_snapshot_file_with_deferred(
+            TransactionProcessor::new(),
+            &carry_over,
+            &ProcessOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(2, carried.len());
+        let (report, summary) = run(transaction_processor, carried, "2024-03-02T17:00:00Z");
+        assert_eq!(
+            "business_date,client,available,held,total,locked\n\
+             2024-03-02,1,5,3,8,false\n",
+            report
+        );
+        assert_eq!(2, summary.transactions_applied());
+
+        let (_, carried) = read_snapshot_file_with_deferred(
+            TransactionProcessor::new(),
+            &carry_over,
+            &ProcessOptions::default(),
+        )
+        .unwrap();
+        assert!(carried.is_empty());
+    }
+
+    #[test]
+    fn run_with_column_mapping() {
+        let mut output = Vec::new();
+        process_files(
+            TransactionProcessor::new(),
+            &["test_data/009_input_columns.csv".to_string()],
+            &ProcessOptions {
+                pipeline: PipelineOptions::new().with_column_mapping(Some(
+                    "type=transaction_type,client=client_id,tx=txn_id,amount=value"
+                        .parse()
+                        .unwrap(),
+                )),
+                ..ProcessOptions::default()
+            },
+            &mut output,
+            None,
+        )
+        .unwrap();
+
+        let expected = std::fs::read_to_string("test_data/009_expected.csv")
+            .unwrap()
+            .replace("\r\n", "\n");
+
+        assert_eq!(expected, String::from_utf8_lossy(output.as_slice()));
+    }
+
+    #[test]
+    fn run_with_no_header() {
+        let mut output = Vec::new();
+        process_files(
+            TransactionProcessor::new(),
+            &["test_data/010_input_headerless.csv".to_string()],
+            &ProcessOptions {
+                pipeline: PipelineOptions::new().with_no_header(true),
+                ..ProcessOptions::default()
+            },
+            &mut output,
+            None,
+        )
+        .unwrap();
+
+        let expected = std::fs::read_to_string("test_data/010_expected.csv")
+            .unwrap()
+            .replace("\r\n", "\n");
+
+        assert_eq!(expected, String::from_utf8_lossy(output.as_slice()));
+    }
+
+    #[test]
+    fn run_with_compressed_test_data() {
+        for input_file in [
+            "test_data/011_input.csv.gz",
+            "test_data/011_input.jsonl.zst",
+        ] {
+            let mut output = Vec::new();
+            process_files(
+                TransactionProcessor::new(),
+                &[input_file.to_string()],
+                &ProcessOptions::default(),
+                &mut output,
+                None,
+            )
+            .unwrap();
+
+            let expected = std::fs::read_to_string("test_data/011_expected.csv")
+                .unwrap()
+                .replace("\r\n", "\n");
+
+            assert_eq!(expected, String::from_utf8_lossy(output.as_slice()));
+        }
+    }
+
+    #[test]
+    fn run_with_rejects() {
+        let mut output = Vec::new();
+        let mut rejects = Vec::new();
+        let summary = process_files(
+            TransactionProcessor::new(),
+            &["test_data/005_input.csv".to_string()],
+            &ProcessOptions::default(),
+            &mut output,
+            Some(&mut rejects),
+        )
+        .unwrap();
+
+        assert_eq!(
+            format!(
+                "code,error,type,client,tx,amount\n\
+                 ACCOUNT_LOCKED,Account is locked for client 1 (deposit of {}),deposit,1,300,10\n\
+                 ACCOUNT_LOCKED,Account is locked for client 1 (withdrawal of {}),withdrawal,1,500,5\n",
+                logged("10"),
+                logged("5")
+            ),
+            String::from_utf8_lossy(rejects.as_slice())
+        );
+
+        assert_eq!(1, summary.files_read());
+        assert_eq!(summary.rows_parsed(), summary.transactions_applied() + 2);
+        assert_eq!(Some(&2), summary.rejects().get("account_is_locked"));
+    }
+
+    #[test]
+    fn run_with_idempotent_replays() {
+        let mut output = Vec::new();
+        let mut rejects = Vec::new();
+        let summary = process_files(
+            TransactionProcessor::new().with_idempotent_replays(true),
+            &["test_data/014_input_replays.csv".to_string()],
+            &ProcessOptions::default(),
+            &mut output,
+            Some(&mut rejects),
+        )
+        .unwrap();
+
+        let expected = std::fs::read_to_string("test_data/014_expected.csv")
+            .unwrap()
+            .replace("\r\n", "\n");
+        assert_eq!(expected, String::from_utf8_lossy(output.as_slice()));
+        // Only the conflicting reuse of an ID is rejected
+        assert_eq!(
+            format!(
+                "code,error,type,client,tx,amount\n\
+                 TX_EXISTS,Transaction 1 already exists for client 1,deposit,1,1,6.0\n\
+                 INSUFFICIENT_FUNDS,Not enough funds for client 2 \
+                 (withdrawal of {} with {} available),withdrawal,2,2,1.5\n",
+                logged("1.5"),
+                logged("0")
+            ),
+            String::from_utf8_lossy(rejects.as_slice())
+        );
+        assert_eq!(3, summary.replays_skipped());
+        assert_eq!(2, summary.total_rejects());
+    }
+
+    #[test]
+    fn run_with_reorder_window() {
+        let input = ["test_data/015_input_sequenced.csv".to_string()];
+        let mut output = Vec::new();
+        let summary = process_files(
+            TransactionProcessor::new(),
+            &input,
+            &ProcessOptions {
+                pipeline: PipelineOptions::new().with_reorder_window(2),
+                ..ProcessOptions::default()
+            },
+            &mut output,
+            None,
+        )
+        .unwrap();
+
+        let expected = std::fs::read_to_string("test_data/015_expected.csv")
+            .unwrap()
+            .replace("\r\n", "\n");
+        assert_eq!(expected, String::from_utf8_lossy(output.as_slice()));
+        // The reordered row and the missing one
+        assert_eq!(2, summary.warnings());
+        assert_eq!(0, summary.total_rejects());
+
+        // Without a window, the withdrawal arrives before the deposit
+        let summary = process_files(
+            TransactionProcessor::new(),
+            &input,
+            &ProcessOptions::default(),
+            std::io::sink(),
+            None,
+        )
+        .unwrap();
+        assert_eq!(3, summary.warnings());
+        assert_eq!(1, summary.total_rejects());
+    }
+
+    #[test]
+    fn run_with_output_scale() {
+        let mut output = Vec::new();
+        process_files(
+            TransactionProcessor::new(),
+            &["test_data/002_input.csv".to_string()],
+            &ProcessOptions {
+                pipeline: PipelineOptions::new().with_output_scale(Some(4)),
+                ..ProcessOptions::default()
+            },
+            &mut output,
+            None,
+        )
+        .unwrap();
+
+        assert_eq!(
+            "client,available,held,total,locked\n\
+             1,1.5000,0.0000,1.5000,false\n\
+             2,2.0000,0.0000,2.0000,false\n",
+            String::from_utf8_lossy(output.as_slice())
+        );
+    }
+
+    #[test]
+    fn run_with_csv_output_options() {
+        let mut output = Vec::new();
+        process_files(
+            TransactionProcessor::new(),
+            &["test_data/002_input.csv".to_string()],
+            &ProcessOptions {
+                csv_output: CSVWriterOptions::new()
+                    .with_quote_style(QuoteStyle::Always)
+                    .with_line_terminator(LineTerminator::Crlf)
+                    .with_columns(Some(vec!["total".to_string(), "client".to_string()])),
+                ..ProcessOptions::default()
+            },
+            &mut output,
+            None,
+        )
+        .unwrap();
+
+        assert_eq!(
+            "\"total\",\"client\"\r\n\"1.5\",\"1\"\r\n\"2.0\",\"2\"\r\n",
+            String::from_utf8_lossy(output.as_slice())
+        );
+    }
+
+    #[test]
+    fn run_with_fail_fast() {
+        let mut output = Vec::new();
+        let mut rejects = Vec::new();
+        let result = process_files(
+            TransactionProcessor::new(),
+            &["test_data/002_input.csv".to_string()],
+            &ProcessOptions {
+                pipeline: PipelineOptions::new().with_fail_fast(true),
+                ..ProcessOptions::default()
+            },
+            &mut output,
+            Some(&mut rejects),
+        );
+
+        let not_enough_funds = format!(
+            "Not enough funds for client 2 (withdrawal of {} with {} available)",
+            logged("3.0"),
+            logged("2.0")
+        );
+        match result {
+            Err(TransactionProcessorCLIError::TransactionFailed {
+                path,
+                record,
+                error,
+            }) => {
+                assert_eq!("test_data/002_input.csv", path);
+                assert_eq!(6, record);
+                assert_eq!(not_enough_funds, error);
+            }
+            _ => panic!("Expected transaction failure"),
+        }
+
+        // No report is written, but the failing row is still rejected
+        assert!(output.is_empty());
+        assert_eq!(
+            format!(
+                "code,error,type,client,tx,amount\n\
+                 INSUFFICIENT_FUNDS,{},withdrawal,2,5,3.0\n",
+                not_enough_funds
+            ),
+            String::from_utf8_lossy(rejects.as_slice())
+        );
+    }
+
+    #[cfg(feature = "tui")]
+    #[test]
+    fn run_with_interrupted_dashboard() {
+        let dashboard = crate::tui::Dashboard::new();
+        dashboard.interrupt();
+        let mut output = Vec::new();
+        let result = process_files(
+            TransactionProcessor::new(),
+            &["test_data/002_input.csv".to_string()],
+            &ProcessOptions {
+                dashboard: Some(dashboard),
+                ..ProcessOptions::default()
+            },
+            &mut output,
+            None,
+        );
+
+        // The run stops before the first row, without writing the report
+        assert!(matches!(
+            result,
+            Err(TransactionProcessorCLIError::Interrupted)
+        ));
+        assert!(output.is_empty());
+    }
+
+    #[test]
+    fn run_with_validate() {
+        let args = |arguments: &[&str]| {
+            let program = ["transaction-processor"].iter().chain(arguments);
+            parse_args(program.map(OsString::from).collect()).unwrap()
+        };
+
+        // The inputs are processed as normal, but no report is written
+        for args in [
+            args(&["validate", "test_data/002_input.csv"]),
+            args(&["--dry-run", "test_data/002_input.csv"]),
+        ] {
+            assert!(matches!(args.mode(), Mode::Validate));
+            let mut output = Vec::new();
+            let summary = run(
+                &args,
+                TransactionProcessor::new(),
+                ProcessOptions::default(),
+                &mut output,
+            )
+            .unwrap();
+            assert!(output.is_empty());
+            assert_eq!(4, summary.transactions_applied());
+            assert_eq!(1, summary.total_rejects());
+        }
+
+        // With --strict, the failure exits with its status instead
+        let args = args(&["validate", "--strict", "test_data/002_input.csv"]);
+        let mut output = Vec::new();
+        let err = run(
+            &args,
+            TransactionProcessor::new(),
+            ProcessOptions {
+                pipeline: PipelineOptions::new().with_fail_fast(args.strict()),
+                ..ProcessOptions::default()
+            },
+            &mut output,
+        )
+        .err()
+        .unwrap();
+        assert_eq!(ExitCode::TransactionRejected, ExitCode::from(&err));
+        assert!(output.is_empty());
+    }
+
+    #[test]
+    fn run_with_checksum() {
+        let directory = TempDir::new("checksum");
+        let path = directory.join("input.csv");
+        let sidecar = path.with_extension("csv.sha256");
+        let data = std::fs::read("test_data/002_input.csv").unwrap();
+        std::fs::write(&path, &data).unwrap();
+        let input_files = [path.to_string_lossy().into_owned()];
+
+        let run = |options: &ProcessOptions| {
+            process_files(
+                TransactionProcessor::new(),
+                &input_files,
+                options,
+                Vec::new(),
+                None,
+            )
+        };
+        let required = ProcessOptions {
+            require_checksums: true,
+            ..ProcessOptions::default()
+        };
+
+        // Without a sidecar file, verification is skipped unless required
+        run(&ProcessOptions::default()).unwrap();
+        assert!(matches!(
+            run(&required),
+            Err(TransactionProcessorCLIError::FailedToOpenFile { .. })
+        ));
+
+        std::fs::write(
+            &sidecar,
+            format!("{}  input.csv\n", Sha256Digest::of(&data)),
+        )
+        .unwrap();
+        run(&required).unwrap();
+
+        std::fs::write(&sidecar, Sha256Digest::of(b"other").to_string()).unwrap();
+        assert!(matches!(
+            run(&ProcessOptions::default()),
+            Err(TransactionProcessorCLIError::ChecksumFailed { .. })
+        ));
+    }
+
+    #[test]
+    fn run_with_jsonl_checksum_mismatch() {
+        let directory = TempDir::new("checksum");
+        let path = directory.join("input.jsonl");
+        let sidecar = path.with_extension("jsonl.sha256");
+        std::fs::copy("test_data/008_input.jsonl", &path).unwrap();
+        std::fs::write(&sidecar, Sha256Digest::of(b"other").to_string()).unwrap();
+
+        // The mismatch at the end of the file stops reading it, rather than
+        // being returned again for every following line
+        let mut rejects = Vec::new();
+        assert!(matches!(
+            process_files(
+                TransactionProcessor::new(),
+                &[path.to_string_lossy().into_owned()],
+                &ProcessOptions::default(),
+                Vec::new(),
+                Some(&mut rejects),
+            ),
+            Err(TransactionProcessorCLIError::ChecksumFailed { .. })
+        ));
+        assert_eq!(
+            1,
+            String::from_utf8(rejects)
+                .unwrap()
+                .matches("Checksum mismatch")
+                .count()
+        );
+    }
+
+    #[test]
+    fn run_with_signatures() {
+        let mut output = Vec::new();
+        let summary = process_files(
+            TransactionProcessor::new(),
+            &["test_data/013_input_signed.csv".to_string()],
+            &ProcessOptions {
+                pipeline: PipelineOptions::new()
+                    .with_signature_key(Some(HmacKey::new("test row key").unwrap())),
+                ..ProcessOptions::default()
+            },
+            &mut output,
+            None,
+        )
+        .unwrap();
+
+        // The tampered withdrawal and the unsigned deposit are rejected
+        assert_eq!(3, summary.transactions_applied());
+        assert_eq!(2, summary.total_rejects());
+
+        let expected = std::fs::read_to_string("test_data/013_expected.csv")
+            .unwrap()
+            .replace("\r\n", "\n");
+        assert_eq!(expected, String::from_utf8_lossy(output.as_slice()));
+    }
 }
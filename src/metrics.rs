@@ -0,0 +1,354 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::{Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+use crate::middleware::{Next, TransactionMiddleware};
+use crate::summary::PARSE_ERROR_CATEGORY;
+use crate::{Transaction, TransactionError, TransactionProcessor, TransactionType};
+
+/// The `Content-Type` of [`Metrics::render`]: the Prometheus text exposition
+/// format.
+pub const CONTENT_TYPE: &str = "text/plain; version=0.0.4";
+
+/// The upper bounds (in seconds) of the buckets of the transaction latency
+/// histogram.
+pub const LATENCY_BUCKETS: [f64; 9] = [
+    0.000_001, 0.000_005, 0.000_01, 0.000_05, 0.000_1, 0.000_5, 0.001, 0.005, 0.01,
+];
+
+/// The transaction types, in the order they are rendered.
+const TRANSACTION_TYPES: [&str; 5] = ["deposit", "withdrawal", "dispute", "resolve", "chargeback"];
+
+/// Counters and histograms of the transactions applied to a processor, for
+/// exposing to Prometheus. Transactions are recorded by the caller, as they
+/// are applied; gauges of the processor's state are read when rendering.
+///
+/// All methods take `&self`, so one instance can be shared between threads.
+#[derive(Debug, Default)]
+pub struct Metrics(Mutex<Counters>);
+
+#[derive(Debug, Default)]
+struct Counters {
+    /// Applied transactions, by type.
+    applied: BTreeMap<&'static str, u64>,
+    /// Rejected transactions, by type and error category.
+    rejected: BTreeMap<(&'static str, &'static str), u64>,
+    /// Rows which could not be read as transactions.
+    parse_errors: u64,
+    /// The number of transactions in each latency bucket (not cumulative),
+    /// with one more for those slower than the last bucket.
+    latency_buckets: [u64; LATENCY_BUCKETS.len() + 1],
+    latency_sum: Duration,
+}
+
+impl Metrics {
+    /// Creates metrics with all counters at zero.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records the result of applying a transaction of the given type, and
+    /// how long it took.
+    pub fn record_transaction(
+        &self,
+        transaction_type: &TransactionType,
+        result: &Result<(), TransactionError>,
+        elapsed: Duration,
+    ) {
+        let mut counters = self.counters();
+        match result {
+            Ok(()) => *counters.applied.entry(transaction_type.name()).or_default() += 1,
+            Err(err) => {
+                *counters
+                    .rejected
+                    .entry((transaction_type.name(), err.category()))
+                    .or_default() += 1
+            }
+        }
+
+        let seconds = elapsed.as_secs_f64();
+        let bucket = LATENCY_BUCKETS
+            .iter()
+            .position(|bound| seconds <= *bound)
+            .unwrap_or(LATENCY_BUCKETS.len());
+        counters.latency_buckets[bucket] += 1;
+        counters.latency_sum += elapsed;
+    }
+
+    /// Records a row which could not be read as a transaction.
+    pub fn record_parse_error(&self) {
+        self.counters().parse_errors += 1;
+    }
+
+    /// Renders the metrics in the Prometheus text exposition format (see
+    /// [`CONTENT_TYPE`]), along with gauges of the current state of
+    /// `transaction_processor`.
+    #[must_use]
+    pub fn render(&self, transaction_processor: &TransactionProcessor) -> String {
+        let counters = self.counters();
+        let mut output = String::new();
+
+        // Writing to a String cannot fail
+        let _ = counters.render(&mut output);
+        let _ = render_gauges(transaction_processor, &mut output);
+        output
+    }
+
+    fn counters(&self) -> std::sync::MutexGuard<'_, Counters> {
+        self.0.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Records the outcome of each transaction passed through a
+/// [`crate::middleware::MiddlewareChain`], and how long the rest of the chain
+/// took to apply it.
+impl TransactionMiddleware for &Metrics {
+    fn handle(
+        &mut self,
+        transaction: Transaction,
+        next: &mut dyn Next,
+    ) -> Result<(), TransactionError> {
+        let transaction_type = transaction.transaction_type().clone();
+        let start = Instant::now();
+        let result = next.run(transaction);
+        self.record_transaction(&transaction_type, &result, start.elapsed());
+        result
+    }
+}
+
+impl Counters {
+    fn render(&self, output: &mut String) -> std::fmt::Result {
+        header(
+            output,
+            "transaction_processor_transactions_applied_total",
+            "counter",
+            "Transactions applied, by type.",
+        )?;
+        // Every type is rendered, so that the series exist before the first
+        // transaction of each type
+        for transaction_type in TRANSACTION_TYPES {
+            writeln!(
+                output,
+                "transaction_processor_transactions_applied_total{{type=\"{}\"}} {}",
+                transaction_type,
+                self.applied.get(transaction_type).copied().unwrap_or(0)
+            )?;
+        }
+
+        header(
+            output,
+            "transaction_processor_transactions_rejected_total",
+            "counter",
+            "Transactions rejected, by type and error category.",
+        )?;
+        for ((transaction_type, category), count) in &self.rejected {
+            writeln!(
+                output,
+                "transaction_processor_transactions_rejected_total{{type=\"{}\",error=\"{}\"}} {}",
+                transaction_type, category, count
+            )?;
+        }
+
+        header(
+            output,
+            "transaction_processor_parse_errors_total",
+            "counter",
+            &format!(
+                "Rows which could not be read as transactions (the {} category of the summary).",
+                PARSE_ERROR_CATEGORY
+            ),
+        )?;
+        writeln!(
+            output,
+            "transaction_processor_parse_errors_total {}",
+            self.parse_errors
+        )?;
+
+        header(
+            output,
+            "transaction_processor_transaction_duration_seconds",
+            "histogram",
+            "Time taken to apply each transaction.",
+        )?;
+        let mut cumulative = 0;
+        for (bound, count) in LATENCY_BUCKETS.iter().zip(self.latency_buckets) {
+            cumulative += count;
+            writeln!(
+                output,
+                "transaction_processor_transaction_duration_seconds_bucket{{le=\"{}\"}} {}",
+                bound, cumulative
+            )?;
+        }
+        cumulative += self.latency_buckets[LATENCY_BUCKETS.len()];
+        writeln!(
+            output,
+            "transaction_processor_transaction_duration_seconds_bucket{{le=\"+Inf\"}} {}",
+            cumulative
+        )?;
+        writeln!(
+            output,
+            "transaction_processor_transaction_duration_seconds_sum {}",
+            self.latency_sum.as_secs_f64()
+        )?;
+        writeln!(
+            output,
+            "transaction_processor_transaction_duration_seconds_count {}",
+            cumulative
+        )
+    }
+}
+
+fn render_gauges(
+    transaction_processor: &TransactionProcessor,
+    output: &mut String,
+) -> std::fmt::Result {
+    let memory_stats = transaction_processor.memory_stats();
+    header(
+        output,
+        "transaction_processor_active_disputes",
+        "gauge",
+        "Disputes which have not yet been resolved or charged back.",
+    )?;
+    writeln!(
+        output,
+        "transaction_processor_active_disputes {}",
+        memory_stats.active_disputes()
+    )?;
+
+    header(
+        output,
+        "transaction_processor_accounts",
+        "gauge",
+        "Client accounts.",
+    )?;
+    writeln!(
+        output,
+        "transaction_processor_accounts {}",
+        memory_stats.clients()
+    )?;
+
+    header(
+        output,
+        "transaction_processor_recorded_transactions",
+        "gauge",
+        "Deposits and withdrawals recorded across all accounts.",
+    )?;
+    writeln!(
+        output,
+        "transaction_processor_recorded_transactions {}",
+        memory_stats.transactions()
+    )?;
+
+    header(
+        output,
+        "transaction_processor_estimated_heap_bytes",
+        "gauge",
+        "Estimated heap memory used by the accounts.",
+    )?;
+    writeln!(
+        output,
+        "transaction_processor_estimated_heap_bytes {}",
+        memory_stats.estimated_heap_bytes()
+    )?;
+
+    header(
+        output,
+        "transaction_processor_locked_accounts",
+        "gauge",
+        "Accounts locked by a chargeback.",
+    )?;
+    writeln!(
+        output,
+        "transaction_processor_locked_accounts {}",
+        transaction_processor.locked_accounts()
+    )
+}
+
+fn header(output: &mut String, name: &str, kind: &str, help: &str) -> std::fmt::Result {
+    writeln!(output, "# HELP {} {}", name, help)?;
+    writeln!(output, "# TYPE {} {}", name, kind)
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+    use std::time::Duration;
+
+    use crate::metrics::Metrics;
+    use crate::middleware::MiddlewareChain;
+    use crate::numeric::CurrencyAmount;
+    use crate::{Transaction, TransactionProcessor, TransactionType};
+
+    #[test]
+    fn test_render() {
+        let mut transaction_processor = TransactionProcessor::new();
+        let metrics = Metrics::new();
+        let amount = CurrencyAmount::from_str("1.5").unwrap();
+        let too_much = CurrencyAmount::from_str("3").unwrap();
+
+        for (transaction, elapsed) in [
+            (
+                Transaction::new(1, 1, TransactionType::Deposit { amount }),
+                Duration::from_micros(2),
+            ),
+            (
+                Transaction::new(1, 2, TransactionType::Withdrawal { amount: too_much }),
+                Duration::from_micros(20),
+            ),
+            (
+                Transaction::new(1, 1, TransactionType::Dispute),
+                Duration::from_secs(1),
+            ),
+        ] {
+            let result = transaction_processor.transact(&transaction);
+            metrics.record_transaction(transaction.transaction_type(), &result, elapsed);
+        }
+        metrics.record_parse_error();
+
+        let output = metrics.render(&transaction_processor);
+        for line in [
+            "# TYPE transaction_processor_transactions_applied_total counter",
+            "transaction_processor_transactions_applied_total{type=\"deposit\"} 1",
+            "transaction_processor_transactions_applied_total{type=\"dispute\"} 1",
+            "transaction_processor_transactions_applied_total{type=\"chargeback\"} 0",
+            "transaction_processor_transactions_rejected_total{type=\"withdrawal\",error=\"not_enough_funds\"} 1",
+            "transaction_processor_parse_errors_total 1",
+            "transaction_processor_transaction_duration_seconds_bucket{le=\"0.000005\"} 1",
+            "transaction_processor_transaction_duration_seconds_bucket{le=\"0.00005\"} 2",
+            "transaction_processor_transaction_duration_seconds_bucket{le=\"0.01\"} 2",
+            "transaction_processor_transaction_duration_seconds_bucket{le=\"+Inf\"} 3",
+            "transaction_processor_transaction_duration_seconds_sum 1.000022",
+            "transaction_processor_transaction_duration_seconds_count 3",
+            "transaction_processor_active_disputes 1",
+            "transaction_processor_locked_accounts 0",
+            "transaction_processor_accounts 1",
+            "transaction_processor_recorded_transactions 1",
+        ] {
+            assert!(
+                output.lines().any(|output_line| output_line == line),
+                "Missing {:?} in:\n{}",
+                line,
+                output
+            );
+        }
+        assert!(output
+            .lines()
+            .any(|line| line.starts_with("transaction_processor_estimated_heap_bytes ")));
+
+        // As a middleware, the outcome of each transaction is recorded
+        MiddlewareChain::new()
+            .with(&metrics)
+            .transact(
+                &mut transaction_processor,
+                &Transaction::new(1, 1, TransactionType::Resolve),
+            )
+            .unwrap();
+        assert!(metrics
+            .render(&transaction_processor)
+            .lines()
+            .any(|line| line
+                == "transaction_processor_transactions_applied_total{type=\"resolve\"} 1"));
+    }
+}
@@ -0,0 +1,221 @@
+use crate::{Transaction, TransactionError, TransactionProcessor};
+
+/// The rest of a [`MiddlewareChain`] after a middleware: the middleware after
+/// it, and finally the processor.
+pub trait Next {
+    /// Passes `transaction` on to the rest of the chain, returning its
+    /// outcome.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of a later middleware, or from
+    /// [`TransactionProcessor::transact`].
+    fn run(&mut self, transaction: Transaction) -> Result<(), TransactionError>;
+
+    /// The processor which the chain applies transactions to, e.g. for a
+    /// middleware which looks at the client's account first.
+    fn processor(&self) -> &TransactionProcessor;
+}
+
+/// A step wrapped around [`TransactionProcessor::transact`] by a
+/// [`MiddlewareChain`], for cross-cutting concerns such as deduplication,
+/// enrichment, fees or metrics.
+///
+/// A middleware can change the transaction before passing it on with
+/// [`Next::run`], act on the outcome afterwards, or return an outcome
+/// without passing it on at all. It is implemented for closures taking the
+/// same arguments as [`TransactionMiddleware::handle`].
+pub trait TransactionMiddleware {
+    /// Handles a transaction, usually by passing it on to `next`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the transaction is rejected, by this middleware or
+    /// the rest of the chain.
+    fn handle(
+        &mut self,
+        transaction: Transaction,
+        next: &mut dyn Next,
+    ) -> Result<(), TransactionError>;
+}
+
+impl<F> TransactionMiddleware for F
+where
+    F: FnMut(Transaction, &mut dyn Next) -> Result<(), TransactionError>,
+{
+    fn handle(
+        &mut self,
+        transaction: Transaction,
+        next: &mut dyn Next,
+    ) -> Result<(), TransactionError> {
+        self(transaction, next)
+    }
+}
+
+/// A middleware boxed in a [`MiddlewareChain`].
+type BoxedMiddleware<'a> = Box<dyn TransactionMiddleware + Send + 'a>;
+
+/// Applies transactions to a processor through a chain of
+/// [`TransactionMiddleware`], in the order they were added, so each
+/// middleware wraps those added after it. With no middleware, it is the same
+/// as [`TransactionProcessor::transact`].
+#[derive(Default)]
+pub struct MiddlewareChain<'a> {
+    middleware: Vec<BoxedMiddleware<'a>>,
+}
+
+impl<'a> MiddlewareChain<'a> {
+    /// Creates a chain with no middleware.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a middleware to the end of the chain, so that it handles the
+    /// transactions passed on by those already added.
+    #[must_use]
+    pub fn with(mut self, middleware: impl TransactionMiddleware + Send + 'a) -> Self {
+        self.middleware.push(Box::new(middleware));
+        self
+    }
+
+    /// The number of middleware in the chain.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.middleware.len()
+    }
+
+    /// True if the chain has no middleware.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.middleware.is_empty()
+    }
+
+    /// Passes `transaction` through the chain, and then applies it to
+    /// `transaction_processor`, unless a middleware returns an outcome
+    /// without passing it on.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of a middleware, or from
+    /// [`TransactionProcessor::transact`].
+    pub fn transact(
+        &mut self,
+        transaction_processor: &mut TransactionProcessor,
+        transaction: &Transaction,
+    ) -> Result<(), TransactionError> {
+        Rest {
+            middleware: &mut self.middleware,
+            transaction_processor,
+        }
+        .run(transaction.clone())
+    }
+}
+
+/// The middleware yet to handle a transaction, and the processor.
+struct Rest<'c, 'a> {
+    middleware: &'c mut [BoxedMiddleware<'a>],
+    transaction_processor: &'c mut TransactionProcessor,
+}
+
+impl Next for Rest<'_, '_> {
+    fn run(&mut self, transaction: Transaction) -> Result<(), TransactionError> {
+        match self.middleware.split_first_mut() {
+            Some((middleware, rest)) => middleware.handle(
+                transaction,
+                &mut Rest {
+                    middleware: rest,
+                    transaction_processor: self.transaction_processor,
+                },
+            ),
+            None => self.transaction_processor.transact(&transaction),
+        }
+    }
+
+    fn processor(&self) -> &TransactionProcessor {
+        self.transaction_processor
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+
+    use crate::idempotency::IdempotencyCache;
+    use crate::middleware::{MiddlewareChain, Next};
+    use crate::numeric::{CurrencyAmount, CurrencyError};
+    use crate::{Transaction, TransactionError, TransactionProcessor, TransactionType};
+
+    #[test]
+    fn test_middleware_chain() {
+        let amount = |amount| CurrencyAmount::from_str(amount).unwrap();
+        let deposit = |tx, value| {
+            Transaction::new(
+                1,
+                tx,
+                TransactionType::Deposit {
+                    amount: amount(value),
+                },
+            )
+        };
+
+        // Deposits are keyed by their ID, deduplicated by the cache, and
+        // charged a fee of 0.5 once they get through
+        let mut seen = Vec::new();
+        let mut chain = MiddlewareChain::new()
+            .with(|transaction: Transaction, next: &mut dyn Next| {
+                let key = format!("deposit-{}", transaction.tx());
+                next.run(transaction.with_idempotency_key(key))
+            })
+            .with(IdempotencyCache::default())
+            .with(|transaction: Transaction, next: &mut dyn Next| {
+                seen.push(transaction.tx());
+                let transaction_type = match transaction.transaction_type() {
+                    TransactionType::Deposit { amount: value } if *value <= amount("0.5") => {
+                        return Err(TransactionError::CurrencyError(CurrencyError::OutOfBounds))
+                    }
+                    TransactionType::Deposit { amount: value } => TransactionType::Deposit {
+                        amount: (*value - amount("0.5"))
+                            .map_err(TransactionError::CurrencyError)?,
+                    },
+                    other => other.clone(),
+                };
+                let result = next.run(Transaction::new(
+                    transaction.client(),
+                    transaction.tx(),
+                    transaction_type,
+                ));
+                assert!(next.processor().account_report(1).is_some());
+                result
+            });
+        assert_eq!(3, chain.len());
+
+        let mut transaction_processor = TransactionProcessor::new();
+        chain
+            .transact(&mut transaction_processor, &deposit(1, "3"))
+            .unwrap();
+        chain
+            .transact(&mut transaction_processor, &deposit(1, "3"))
+            .unwrap();
+        assert_eq!(
+            Err(TransactionError::CurrencyError(CurrencyError::OutOfBounds)),
+            chain.transact(&mut transaction_processor, &deposit(2, "0.1"))
+        );
+        drop(chain);
+
+        assert_eq!(vec![1, 2], seen);
+        assert_eq!(
+            amount("2.5"),
+            transaction_processor.account_report(1).unwrap().available
+        );
+
+        // An empty chain applies transactions as they are
+        MiddlewareChain::new()
+            .transact(&mut transaction_processor, &deposit(3, "1"))
+            .unwrap();
+        assert_eq!(
+            amount("3.5"),
+            transaction_processor.account_report(1).unwrap().available
+        );
+    }
+}
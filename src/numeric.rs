@@ -1,23 +1,45 @@
-use std::fmt::{Display, Formatter};
-use std::ops::{Add, Neg, Sub};
-use std::str::FromStr;
+use alloc::string::{String, ToString};
+use core::fmt::{Debug, Display, Formatter};
+use core::iter::Sum;
+use core::ops::{Add, Neg, Sub};
+use core::str::FromStr;
 
-use rust_decimal::Decimal;
+use rust_decimal::{Decimal, RoundingStrategy};
+#[cfg(feature = "serde")]
 use serde::de::Error;
+#[cfg(feature = "serde")]
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
+#[cfg(feature = "wide-amounts")]
+mod wide;
+
+/// The type which amounts are stored as.
+#[cfg(not(feature = "wide-amounts"))]
+type Value = Decimal;
+/// The type which amounts are stored as: a `Decimal`, or a decimal with a
+/// 256-bit mantissa for values which don't fit in one.
+#[cfg(feature = "wide-amounts")]
+type Value = wide::WideDecimal;
+
 /// An error occurred when performing arithmetic operations on
 /// currency amounts.
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub enum CurrencyError {
     /// The result of the calculation would overflow/underflow.
     OutOfBounds,
+    /// The requested number of decimal places is not supported.
+    InvalidScale,
+    /// The amount cannot be represented at the requested number of decimal
+    /// places without rounding.
+    PrecisionLoss,
 }
 
 impl Display for CurrencyError {
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
         f.write_str(match self {
             CurrencyError::OutOfBounds => "Out of bounds",
+            CurrencyError::InvalidScale => "Invalid scale",
+            CurrencyError::PrecisionLoss => "Precision loss",
         })
     }
 }
@@ -27,38 +49,381 @@ impl Display for CurrencyError {
 pub enum CurrencyAmountParseError {
     /// The specified string is not a valid currency amount.
     InvalidNumericValue,
+    /// The amount has more decimal places than
+    /// [`CurrencyAmount::MAX_DECIMAL_PLACES`] and the precision policy
+    /// rejects it.
+    TooManyDecimalPlaces,
 }
 
 impl Display for CurrencyAmountParseError {
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
         f.write_str(match self {
             CurrencyAmountParseError::InvalidNumericValue => "Invalid numeric value",
+            CurrencyAmountParseError::TooManyDecimalPlaces => "Too many decimal places",
         })
     }
 }
 
+/// How to handle amounts with more than [`CurrencyAmount::MAX_DECIMAL_PLACES`]
+/// decimal places.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum PrecisionPolicy {
+    /// Amounts with too many decimal places are an error.
+    Reject,
+    /// Round to the nearest value, with midpoints rounded away from zero.
+    RoundHalfUp,
+    /// Discard the excess decimal places (i.e. round towards zero).
+    Truncate,
+}
+
+/// The digit grouping and decimal separator conventions used when parsing
+/// amounts with [`CurrencyAmount::from_str_with_format`].
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum LocaleFormat {
+    /// `1,234.56`: `,` separates thousands and `.` is the decimal separator.
+    PointDecimal,
+    /// `1.234,56`: `.` separates thousands and `,` is the decimal separator.
+    CommaDecimal,
+}
+
+impl LocaleFormat {
+    /// Returns the thousands separator and decimal separator.
+    const fn separators(self) -> (char, char) {
+        match self {
+            LocaleFormat::PointDecimal => (',', '.'),
+            LocaleFormat::CommaDecimal => ('.', ','),
+        }
+    }
+}
+
+/// What amounts are shown as in `Debug` output and logs with the `redact`
+/// feature (see [`CurrencyAmount::for_log`]).
+pub const REDACTED_AMOUNT: &str = "<redacted>";
+
 /// An amount of money, represented as a decimal number.
 ///
 /// For `x` decimal places of precision, this can handle positive and negative
 /// values with magnitude `(2^96)/(10^x)`. For four decimal places, this is
 /// approximately `2^82`.
 ///
+/// With the `wide-amounts` feature, values beyond that are held exactly with
+/// a 256-bit mantissa instead, which can handle magnitudes up to
+/// `(2^256)/(10^x)`: approximately `10^77` for whole amounts, and `2^242` for
+/// four decimal places. Sums past that still return
+/// [`CurrencyError::OutOfBounds`], as they do without the feature, rather
+/// than being rounded to fit. Values within the range of a `Decimal` behave
+/// exactly as they do without the feature. [`CurrencyAmount::as_decimal`]
+/// rounds wider values to the nearest `Decimal`, or saturates if they are
+/// out of its range.
+///
 /// All arithmetic operations are checked and return a result type.
-#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+///
+/// With the `redact` feature, the `Debug` output masks the value (as
+/// [`REDACTED_AMOUNT`]), so that amounts don't end up in logs. `Display` and
+/// serialization are unchanged, as they are used for output.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub struct CurrencyAmount {
-    value: Decimal,
+    value: Value,
 }
 
 impl CurrencyAmount {
     /// Constant value of `0.0`.
-    pub const ZERO: Self = Self {
-        value: Decimal::ZERO,
-    };
+    pub const ZERO: Self = Self { value: Value::ZERO };
+
+    /// The maximum number of decimal places supported by the underlying
+    /// decimal type.
+    const MAX_SCALE: u32 = 28;
+
+    /// The number of decimal places enforced by a [`PrecisionPolicy`].
+    pub const MAX_DECIMAL_PLACES: u32 = 4;
+
+    /// Parses a currency amount, only accepting plain decimal notation: an
+    /// optional leading `-`, followed by ASCII digits with at most one `.`.
+    ///
+    /// Unlike [`CurrencyAmount::from_str`], this rejects a leading `+`, digit
+    /// separators such as `_`, exponent notation, and any whitespace.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`CurrencyAmountParseError::InvalidNumericValue`] if the string
+    /// is not in plain decimal notation.
+    pub fn from_str_strict(value: &str) -> Result<Self, CurrencyAmountParseError> {
+        let unsigned = value.strip_prefix('-').unwrap_or(value);
+
+        let is_plain_decimal = unsigned.chars().any(|c| c.is_ascii_digit())
+            && unsigned.chars().all(|c| c.is_ascii_digit() || c == '.')
+            && unsigned.matches('.').count() <= 1;
+
+        if !is_plain_decimal {
+            return Err(CurrencyAmountParseError::InvalidNumericValue);
+        }
+
+        Self::from_str(value)
+    }
+
+    /// Parses a currency amount written using the specified locale
+    /// conventions, such as `1,234.56` or `1.234,56`.
+    ///
+    /// Thousands separators are optional, but where present they must
+    /// separate groups of exactly three digits, so that an ambiguous value
+    /// like `1,5` is never silently read as `15`. Otherwise, the same rules as
+    /// [`CurrencyAmount::from_str_strict`] apply.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`CurrencyAmountParseError::InvalidNumericValue`] if the string
+    /// is not a valid amount in the specified format.
+    pub fn from_str_with_format(
+        value: &str,
+        format: LocaleFormat,
+    ) -> Result<Self, CurrencyAmountParseError> {
+        let (thousands_separator, decimal_separator) = format.separators();
+
+        let (sign, unsigned) = match value.strip_prefix('-') {
+            Some(unsigned) => ("-", unsigned),
+            None => ("", value),
+        };
+
+        let (integer, fraction) = match unsigned.split_once(decimal_separator) {
+            Some((integer, fraction)) => (integer, Some(fraction)),
+            None => (unsigned, None),
+        };
+
+        if integer.contains(thousands_separator) {
+            let mut groups = integer.split(thousands_separator);
+            let leading_group_valid = groups
+                .next()
+                .is_some_and(|group| (1..=3).contains(&group.len()));
+
+            if !leading_group_valid || !groups.all(|group| group.len() == 3) {
+                return Err(CurrencyAmountParseError::InvalidNumericValue);
+            }
+        }
+
+        let mut normalized = String::with_capacity(value.len());
+        normalized.push_str(sign);
+        normalized.extend(integer.chars().filter(|c| *c != thousands_separator));
+
+        if let Some(fraction) = fraction {
+            normalized.push('.');
+            normalized.push_str(fraction);
+        }
+
+        Self::from_str_strict(&normalized)
+    }
+
+    /// Parses a currency amount, applying the specified precision policy if
+    /// it has more than [`CurrencyAmount::MAX_DECIMAL_PLACES`] decimal places.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`CurrencyAmountParseError::InvalidNumericValue`] if the string
+    /// is not a valid amount, and
+    /// [`CurrencyAmountParseError::TooManyDecimalPlaces`] if the policy is
+    /// [`PrecisionPolicy::Reject`] and the amount has too many decimal places.
+    pub fn from_str_with_precision(
+        value: &str,
+        policy: PrecisionPolicy,
+    ) -> Result<Self, CurrencyAmountParseError> {
+        Self::from_str(value)?
+            .with_precision(policy)
+            .map_err(|_| CurrencyAmountParseError::TooManyDecimalPlaces)
+    }
+
+    /// Applies the specified precision policy, limiting this amount to
+    /// [`CurrencyAmount::MAX_DECIMAL_PLACES`] decimal places.
+    ///
+    /// Trailing zeros beyond the limit are not considered a loss of
+    /// precision, and such amounts are returned unchanged.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`CurrencyError::PrecisionLoss`] if the policy is
+    /// [`PrecisionPolicy::Reject`] and the amount has too many decimal places.
+    pub fn with_precision(self, policy: PrecisionPolicy) -> Result<Self, CurrencyError> {
+        let strategy = match policy {
+            PrecisionPolicy::Reject | PrecisionPolicy::RoundHalfUp => {
+                RoundingStrategy::MidpointAwayFromZero
+            }
+            PrecisionPolicy::Truncate => RoundingStrategy::ToZero,
+        };
+
+        let rounded = self
+            .value
+            .round_dp_with_strategy(Self::MAX_DECIMAL_PLACES, strategy);
+
+        if rounded == self.value {
+            Ok(self)
+        } else if matches!(policy, PrecisionPolicy::Reject) {
+            Err(CurrencyError::PrecisionLoss)
+        } else {
+            Ok(Self { value: rounded })
+        }
+    }
+
+    /// The largest representable amount.
+    pub const MAX: Self = Self { value: Value::MAX };
+
+    /// The smallest (most negative) representable amount.
+    pub const MIN: Self = Self { value: Value::MIN };
 
     /// Returns true if this value is less than zero.
     #[must_use]
     pub fn is_negative(&self) -> bool {
-        self.value.lt(&Decimal::ZERO)
+        self.value.lt(&Value::ZERO)
+    }
+
+    /// The amount as it should appear in logs: as it is, or as
+    /// [`REDACTED_AMOUNT`] with the `redact` feature.
+    #[must_use]
+    pub fn for_log(&self) -> impl Display + '_ {
+        #[cfg(feature = "redact")]
+        return REDACTED_AMOUNT;
+        #[cfg(not(feature = "redact"))]
+        self
+    }
+
+    /// Creates an amount from an integer number of minor units, where `scale`
+    /// is the number of decimal places in one major unit. For example,
+    /// `from_minor_units(1234, 2)` represents `12.34`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`CurrencyError::InvalidScale`] if `scale` is greater than 28.
+    #[cfg_attr(not(feature = "wide-amounts"), allow(clippy::useless_conversion))]
+    pub fn from_minor_units(units: i64, scale: u32) -> Result<Self, CurrencyError> {
+        Ok(Self {
+            value: Decimal::try_new(units, scale)
+                .map_err(|_| CurrencyError::InvalidScale)?
+                .into(),
+        })
+    }
+
+    /// Converts this amount to an integer number of minor units, where
+    /// `scale` is the number of decimal places in one major unit. For
+    /// example, `12.34` with a scale of `2` is `1234`.
+    ///
+    /// # Errors
+    ///
+    /// * [`CurrencyError::InvalidScale`] if `scale` is greater than 28.
+    /// * [`CurrencyError::PrecisionLoss`] if the amount has more than `scale`
+    ///   significant decimal places.
+    /// * [`CurrencyError::OutOfBounds`] if the result does not fit in an
+    ///   `i64`.
+    pub fn to_minor_units(&self, scale: u32) -> Result<i64, CurrencyError> {
+        if scale > Self::MAX_SCALE {
+            return Err(CurrencyError::InvalidScale);
+        }
+
+        let mut scaled = self.value;
+        scaled.rescale(scale);
+
+        // Rescaling rounds if the value has too many decimal places
+        if scaled != self.value {
+            return Err(CurrencyError::PrecisionLoss);
+        }
+
+        // Rescaling uses a smaller scale if the mantissa would overflow
+        if scaled.scale() != scale {
+            return Err(CurrencyError::OutOfBounds);
+        }
+
+        i64::try_from(scaled.mantissa()).map_err(|_| CurrencyError::OutOfBounds)
+    }
+
+    /// Adds `rhs` to this amount in place. If the result would overflow, an
+    /// error is returned and this amount is left unchanged.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`CurrencyError::OutOfBounds`] on overflow/underflow.
+    pub fn checked_add_assign(&mut self, rhs: Self) -> Result<(), CurrencyError> {
+        *self = (*self + rhs)?;
+        Ok(())
+    }
+
+    /// Subtracts `rhs` from this amount in place. If the result would
+    /// overflow, an error is returned and this amount is left unchanged.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`CurrencyError::OutOfBounds`] on overflow/underflow.
+    pub fn checked_sub_assign(&mut self, rhs: Self) -> Result<(), CurrencyError> {
+        *self = (*self - rhs)?;
+        Ok(())
+    }
+
+    /// Adds two amounts, returning [`CurrencyAmount::MAX`] or
+    /// [`CurrencyAmount::MIN`] instead of an error if the result would
+    /// overflow.
+    #[must_use]
+    pub fn saturating_add(self, rhs: Self) -> Self {
+        // Overflow is only possible if both have the same sign as rhs
+        (self + rhs).unwrap_or(if rhs.is_negative() {
+            Self::MIN
+        } else {
+            Self::MAX
+        })
+    }
+
+    /// Subtracts two amounts, returning [`CurrencyAmount::MAX`] or
+    /// [`CurrencyAmount::MIN`] instead of an error if the result would
+    /// overflow.
+    #[must_use]
+    pub fn saturating_sub(self, rhs: Self) -> Self {
+        self.saturating_add(-rhs)
+    }
+
+    /// Adds together all amounts in the iterator, failing if any intermediate
+    /// result would overflow. An empty iterator sums to [`CurrencyAmount::ZERO`].
+    ///
+    /// # Errors
+    ///
+    /// Returns [`CurrencyError::OutOfBounds`] on overflow/underflow.
+    pub fn checked_sum(amounts: impl IntoIterator<Item = Self>) -> Result<Self, CurrencyError> {
+        amounts
+            .into_iter()
+            .try_fold(Self::ZERO, |total, amount| total + amount)
+    }
+
+    /// Returns this amount formatted with exactly `scale` decimal places,
+    /// padding with zeros or rounding (midpoint away from zero) as required.
+    /// For example, `50` with a scale of `4` is `50.0000`.
+    ///
+    /// The scale is limited to what can be represented, so very large amounts
+    /// or scales greater than 28 may result in fewer decimal places.
+    #[must_use]
+    pub fn with_scale(self, scale: u32) -> Self {
+        let mut value = self.value;
+        value.rescale(scale);
+        Self { value }
+    }
+
+    /// Returns the underlying decimal value. With the `wide-amounts`
+    /// feature, a value which doesn't fit in a `Decimal` is rounded to the
+    /// nearest one, or is `Decimal::MAX` or `Decimal::MIN` if it is out of
+    /// range.
+    #[must_use]
+    pub const fn as_decimal(&self) -> Decimal {
+        #[cfg(feature = "wide-amounts")]
+        return self.value.to_decimal();
+        #[cfg(not(feature = "wide-amounts"))]
+        self.value
+    }
+}
+
+impl From<Decimal> for CurrencyAmount {
+    #[cfg_attr(not(feature = "wide-amounts"), allow(clippy::useless_conversion))]
+    fn from(value: Decimal) -> Self {
+        Self {
+            value: value.into(),
+        }
+    }
+}
+
+impl From<CurrencyAmount> for Decimal {
+    fn from(amount: CurrencyAmount) -> Self {
+        amount.as_decimal()
     }
 }
 
@@ -67,12 +432,13 @@ impl Add for CurrencyAmount {
 
     fn add(self, rhs: Self) -> Self::Output {
         // Always ensure that the addition is safe
-        Ok(Self {
-            value: self
-                .value
-                .checked_add(rhs.value)
-                .ok_or(CurrencyError::OutOfBounds)?,
-        })
+        let value = self
+            .value
+            .checked_add(rhs.value)
+            .ok_or(CurrencyError::OutOfBounds)?;
+        #[cfg(not(feature = "wide-amounts"))]
+        check_exact(value, self, rhs)?;
+        Ok(Self { value })
     }
 }
 
@@ -81,12 +447,45 @@ impl Sub for CurrencyAmount {
 
     fn sub(self, rhs: Self) -> Result<Self, CurrencyError> {
         // Always ensure that the subtraction is safe
-        Ok(Self {
-            value: self
-                .value
-                .checked_sub(rhs.value)
-                .ok_or(CurrencyError::OutOfBounds)?,
-        })
+        let value = self
+            .value
+            .checked_sub(rhs.value)
+            .ok_or(CurrencyError::OutOfBounds)?;
+        #[cfg(not(feature = "wide-amounts"))]
+        check_exact(value, self, rhs)?;
+        Ok(Self { value })
+    }
+}
+
+/// Checks that the result of adding or subtracting two amounts wasn't
+/// rounded. `Decimal` rounds to a smaller scale rather than overflowing, so
+/// e.g. `Decimal::MAX + 0.0001` would otherwise silently lose the `0.0001`.
+/// Adding zero returns the other amount as it is, at its own scale. The wide
+/// sum fails rather than rounding, so it needs no check.
+#[cfg(not(feature = "wide-amounts"))]
+fn check_exact(
+    value: Value,
+    left: CurrencyAmount,
+    right: CurrencyAmount,
+) -> Result<(), CurrencyError> {
+    if !left.value.is_zero()
+        && !right.value.is_zero()
+        && value.scale() < left.value.scale().max(right.value.scale())
+    {
+        return Err(CurrencyError::OutOfBounds);
+    }
+    Ok(())
+}
+
+impl Sum<CurrencyAmount> for Result<CurrencyAmount, CurrencyError> {
+    fn sum<I: Iterator<Item = CurrencyAmount>>(iter: I) -> Self {
+        CurrencyAmount::checked_sum(iter)
+    }
+}
+
+impl<'a> Sum<&'a CurrencyAmount> for Result<CurrencyAmount, CurrencyError> {
+    fn sum<I: Iterator<Item = &'a CurrencyAmount>>(iter: I) -> Self {
+        CurrencyAmount::checked_sum(iter.copied())
     }
 }
 
@@ -105,18 +504,27 @@ impl FromStr for CurrencyAmount {
 
     fn from_str(value: &str) -> Result<Self, CurrencyAmountParseError> {
         Ok(Self {
-            value: Decimal::from_str(value)
+            value: Value::from_str(value)
                 .map_err(|_| CurrencyAmountParseError::InvalidNumericValue)?,
         })
     }
 }
 
 impl Display for CurrencyAmount {
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
         f.write_str(&self.value.to_string())
     }
 }
 
+impl Debug for CurrencyAmount {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("CurrencyAmount")
+            .field("value", &format_args!("{}", self.for_log()))
+            .finish()
+    }
+}
+
+#[cfg(feature = "serde")]
 impl Serialize for CurrencyAmount {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
@@ -126,6 +534,7 @@ impl Serialize for CurrencyAmount {
     }
 }
 
+#[cfg(feature = "serde")]
 impl<'de> Deserialize<'de> for CurrencyAmount {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
     where
@@ -141,7 +550,9 @@ mod test {
     use std::ops::Sub;
     use std::str::FromStr;
 
-    use crate::numeric::CurrencyAmountParseError;
+    use rust_decimal::Decimal;
+
+    use crate::numeric::{CurrencyAmountParseError, CurrencyError, LocaleFormat, PrecisionPolicy};
     use crate::CurrencyAmount;
 
     #[test]
@@ -282,6 +693,7 @@ mod test {
             Err(CurrencyAmountParseError::InvalidNumericValue),
             CurrencyAmount::from_str("..")
         );
+        #[cfg(not(feature = "wide-amounts"))]
         assert_eq!(
             Err(CurrencyAmountParseError::InvalidNumericValue),
             CurrencyAmount::from_str(&i128::MAX.to_string())
@@ -296,6 +708,172 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_parse_strict() {
+        for value in [
+            "12",
+            "-12",
+            "12.",
+            "-12.34",
+            ".34",
+            "-.34",
+            "0",
+            "0012.3400",
+        ] {
+            assert_eq!(
+                CurrencyAmount::from_str(value),
+                CurrencyAmount::from_str_strict(value)
+            );
+        }
+
+        for value in [
+            "+12", "1_000", "1e5", "1E5", " 12", "12 ", "1 2", "", "-", ".", "-.", "--1", "1.2.3",
+            "0x10", "1,5",
+        ] {
+            assert_eq!(
+                Err(CurrencyAmountParseError::InvalidNumericValue),
+                CurrencyAmount::from_str_strict(value),
+                "{}",
+                value
+            );
+        }
+
+        assert!(CurrencyAmount::from_str("+12").is_ok());
+        assert!(CurrencyAmount::from_str("1_000").is_ok());
+    }
+
+    #[test]
+    fn test_parse_with_format() {
+        let parse = |value, format| {
+            CurrencyAmount::from_str_with_format(value, format).map(|v| v.to_string())
+        };
+
+        for (point, comma, expected) in [
+            ("1,234.56", "1.234,56", "1234.56"),
+            ("1234.56", "1234,56", "1234.56"),
+            ("-1,234,567.8", "-1.234.567,8", "-1234567.8"),
+            ("123,456", "123.456", "123456"),
+            ("12", "12", "12"),
+            (".5", ",5", "0.5"),
+        ] {
+            assert_eq!(
+                Ok(expected.to_string()),
+                parse(point, LocaleFormat::PointDecimal)
+            );
+            assert_eq!(
+                Ok(expected.to_string()),
+                parse(comma, LocaleFormat::CommaDecimal)
+            );
+        }
+
+        for value in [
+            "1,5",
+            "1,23.4",
+            "1234,567",
+            ",123",
+            "1,,234",
+            "1,234.5,6",
+            "1.2.3",
+            "+1",
+            "1 234",
+        ] {
+            assert_eq!(
+                Err(CurrencyAmountParseError::InvalidNumericValue),
+                parse(value, LocaleFormat::PointDecimal),
+                "{}",
+                value
+            );
+        }
+
+        assert_eq!(
+            Err(CurrencyAmountParseError::InvalidNumericValue),
+            parse("1.234.5", LocaleFormat::CommaDecimal)
+        );
+        assert_eq!(
+            Err(CurrencyAmountParseError::InvalidNumericValue),
+            parse("1,2,3", LocaleFormat::CommaDecimal)
+        );
+    }
+
+    #[test]
+    fn test_with_scale() {
+        let scaled = |value, scale| {
+            CurrencyAmount::from_str(value)
+                .unwrap()
+                .with_scale(scale)
+                .to_string()
+        };
+
+        assert_eq!("50.0000", scaled("50", 4));
+        assert_eq!("50.1000", scaled("50.1", 4));
+        assert_eq!("-0.5000", scaled("-.5", 4));
+        assert_eq!("1.2346", scaled("1.23455", 4));
+        assert_eq!("-1.2346", scaled("-1.23455", 4));
+        assert_eq!("2", scaled("1.5", 0));
+        assert_eq!("0.00", scaled("0", 2));
+    }
+
+    #[test]
+    fn test_assign() {
+        let mut amount = CurrencyAmount::from_str("1.5").unwrap();
+
+        amount
+            .checked_add_assign(CurrencyAmount::from_str("2").unwrap())
+            .unwrap();
+        assert_eq!(CurrencyAmount::from_str("3.5").unwrap(), amount);
+
+        amount
+            .checked_sub_assign(CurrencyAmount::from_str("4").unwrap())
+            .unwrap();
+        assert_eq!(CurrencyAmount::from_str("-0.5").unwrap(), amount);
+
+        let mut max = CurrencyAmount::MAX;
+        assert_eq!(
+            Err(CurrencyError::OutOfBounds),
+            max.checked_add_assign(CurrencyAmount::from_str("1").unwrap())
+        );
+        assert_eq!(CurrencyAmount::MAX, max);
+
+        let mut min = CurrencyAmount::MIN;
+        assert_eq!(
+            Err(CurrencyError::OutOfBounds),
+            min.checked_sub_assign(CurrencyAmount::from_str("1").unwrap())
+        );
+        assert_eq!(CurrencyAmount::MIN, min);
+
+        // Adding a fraction to a large amount can't round it away
+        let tiny = CurrencyAmount::from_str("0.0001").unwrap();
+        assert_eq!(Err(CurrencyError::OutOfBounds), CurrencyAmount::MAX + tiny);
+        assert_eq!(Err(CurrencyError::OutOfBounds), CurrencyAmount::MAX - tiny);
+    }
+
+    #[test]
+    fn test_saturating() {
+        let one = CurrencyAmount::from_str("1").unwrap();
+        let two = CurrencyAmount::from_str("2").unwrap();
+
+        assert_eq!(
+            CurrencyAmount::from_str("3").unwrap(),
+            one.saturating_add(two)
+        );
+        assert_eq!(-one, one.saturating_sub(two));
+
+        assert_eq!(CurrencyAmount::MAX, CurrencyAmount::MAX.saturating_add(one));
+        assert_eq!(
+            CurrencyAmount::MIN,
+            CurrencyAmount::MIN.saturating_add(-one)
+        );
+        assert_eq!(
+            CurrencyAmount::MAX,
+            CurrencyAmount::MAX.saturating_sub(-one)
+        );
+        assert_eq!(CurrencyAmount::MIN, CurrencyAmount::MIN.saturating_sub(one));
+        assert_eq!(
+            CurrencyAmount::MAX.saturating_sub(one),
+            (CurrencyAmount::MAX - one).unwrap()
+        );
+    }
+
     #[test]
     fn test_add() {
         assert_eq!(
@@ -329,4 +907,166 @@ mod test {
                 - CurrencyAmount::from_str(&2_i128.pow(95).sub(1).to_string()).unwrap()
         );
     }
+
+    #[test]
+    fn test_minor_units() {
+        assert_eq!(
+            "12.34",
+            CurrencyAmount::from_minor_units(1234, 2)
+                .unwrap()
+                .to_string()
+        );
+        assert_eq!(
+            "-0.05",
+            CurrencyAmount::from_minor_units(-5, 2).unwrap().to_string()
+        );
+        assert_eq!(
+            "1234",
+            CurrencyAmount::from_minor_units(1234, 0)
+                .unwrap()
+                .to_string()
+        );
+        assert_eq!(
+            Err(CurrencyError::InvalidScale),
+            CurrencyAmount::from_minor_units(1, 29)
+        );
+
+        assert_eq!(
+            Ok(1234),
+            CurrencyAmount::from_str("12.34").unwrap().to_minor_units(2)
+        );
+        assert_eq!(
+            Ok(123_400),
+            CurrencyAmount::from_str("12.34").unwrap().to_minor_units(4)
+        );
+        assert_eq!(
+            Ok(-50),
+            CurrencyAmount::from_str("-0.5").unwrap().to_minor_units(2)
+        );
+        assert_eq!(
+            Ok(1230),
+            CurrencyAmount::from_str("12.3000")
+                .unwrap()
+                .to_minor_units(2)
+        );
+        assert_eq!(
+            Err(CurrencyError::PrecisionLoss),
+            CurrencyAmount::from_str("12.345")
+                .unwrap()
+                .to_minor_units(2)
+        );
+        assert_eq!(
+            Err(CurrencyError::InvalidScale),
+            CurrencyAmount::from_str("12").unwrap().to_minor_units(29)
+        );
+        assert_eq!(
+            Err(CurrencyError::OutOfBounds),
+            CurrencyAmount::from_str(&i64::MAX.to_string())
+                .unwrap()
+                .to_minor_units(2)
+        );
+    }
+
+    #[test]
+    fn test_decimal_conversion() {
+        let decimal = Decimal::from_str("12.34").unwrap();
+        let amount = CurrencyAmount::from(decimal);
+
+        assert_eq!(CurrencyAmount::from_str("12.34").unwrap(), amount);
+        assert_eq!(decimal, amount.as_decimal());
+        assert_eq!(decimal, Decimal::from(amount));
+    }
+
+    #[test]
+    fn test_sum() {
+        let amounts = vec![
+            CurrencyAmount::from_str("1.5").unwrap(),
+            CurrencyAmount::from_str("2.25").unwrap(),
+            CurrencyAmount::from_str("-0.75").unwrap(),
+        ];
+
+        assert_eq!(
+            Ok(CurrencyAmount::from_str("3").unwrap()),
+            amounts
+                .iter()
+                .sum::<Result<CurrencyAmount, CurrencyError>>()
+        );
+        assert_eq!(
+            Ok(CurrencyAmount::from_str("3").unwrap()),
+            amounts
+                .into_iter()
+                .sum::<Result<CurrencyAmount, CurrencyError>>()
+        );
+        assert_eq!(
+            Ok(CurrencyAmount::ZERO),
+            CurrencyAmount::checked_sum(Vec::new())
+        );
+
+        let max = CurrencyAmount::from(Decimal::MAX);
+        #[cfg(not(feature = "wide-amounts"))]
+        assert_eq!(
+            Err(CurrencyError::OutOfBounds),
+            CurrencyAmount::checked_sum(vec![max, max])
+        );
+        #[cfg(feature = "wide-amounts")]
+        assert_eq!(
+            "158456325028528675187087900670",
+            CurrencyAmount::checked_sum(vec![max, max])
+                .unwrap()
+                .to_string()
+        );
+    }
+
+    #[test]
+    fn test_precision_policy() {
+        let parse = |value, policy| {
+            CurrencyAmount::from_str_with_precision(value, policy).map(|v| v.to_string())
+        };
+
+        assert_eq!(
+            Ok("1.2345".to_string()),
+            parse("1.2345", PrecisionPolicy::Reject)
+        );
+        assert_eq!(
+            Ok("1.23450".to_string()),
+            parse("1.23450", PrecisionPolicy::Reject)
+        );
+        assert_eq!(
+            Err(CurrencyAmountParseError::TooManyDecimalPlaces),
+            parse("1.23456", PrecisionPolicy::Reject)
+        );
+        assert_eq!(
+            Err(CurrencyAmountParseError::InvalidNumericValue),
+            parse("a", PrecisionPolicy::Reject)
+        );
+
+        assert_eq!(
+            Ok("1.2346".to_string()),
+            parse("1.23455", PrecisionPolicy::RoundHalfUp)
+        );
+        assert_eq!(
+            Ok("1.2345".to_string()),
+            parse("1.23454", PrecisionPolicy::RoundHalfUp)
+        );
+        assert_eq!(
+            Ok("-1.2346".to_string()),
+            parse("-1.23455", PrecisionPolicy::RoundHalfUp)
+        );
+
+        assert_eq!(
+            Ok("1.2345".to_string()),
+            parse("1.23459", PrecisionPolicy::Truncate)
+        );
+        assert_eq!(
+            Ok("-1.2345".to_string()),
+            parse("-1.23459", PrecisionPolicy::Truncate)
+        );
+
+        assert_eq!(
+            Err(CurrencyError::PrecisionLoss),
+            CurrencyAmount::from_str("0.00001")
+                .unwrap()
+                .with_precision(PrecisionPolicy::Reject)
+        );
+    }
 }
@@ -0,0 +1,646 @@
+use alloc::string::String;
+use core::cmp::Ordering;
+use core::fmt::{Display, Formatter};
+use core::ops::Neg;
+use core::str::FromStr;
+
+use rust_decimal::{Decimal, RoundingStrategy};
+
+/// The largest scale of a [`WideDecimal`], which is the same as `Decimal`'s.
+const MAX_SCALE: u32 = 28;
+
+/// An unsigned 256-bit integer, as four 64-bit limbs, least significant
+/// first.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+struct U256([u64; 4]);
+
+impl U256 {
+    const ZERO: Self = Self([0; 4]);
+    const MAX: Self = Self([u64::MAX; 4]);
+
+    const fn from_u128(value: u128) -> Self {
+        Self([value as u64, (value >> 64) as u64, 0, 0])
+    }
+
+    const fn is_zero(&self) -> bool {
+        self.0[0] == 0 && self.0[1] == 0 && self.0[2] == 0 && self.0[3] == 0
+    }
+
+    /// True if the value fits in the 96-bit mantissa of a `Decimal`.
+    const fn fits_decimal(&self) -> bool {
+        self.0[3] == 0 && self.0[2] == 0 && self.0[1] >> 32 == 0
+    }
+
+    /// The value, if it fits in a `u128`.
+    const fn to_u128(self) -> Option<u128> {
+        if self.0[3] == 0 && self.0[2] == 0 {
+            Some(self.0[0] as u128 | (self.0[1] as u128) << 64)
+        } else {
+            None
+        }
+    }
+
+    const fn cmp(&self, other: &Self) -> Ordering {
+        let mut index = 4;
+        while index > 0 {
+            index -= 1;
+            if self.0[index] < other.0[index] {
+                return Ordering::Less;
+            }
+            if self.0[index] > other.0[index] {
+                return Ordering::Greater;
+            }
+        }
+        Ordering::Equal
+    }
+
+    const fn checked_add(self, other: Self) -> Option<Self> {
+        let mut limbs = [0; 4];
+        let mut carry = 0;
+        let mut index = 0;
+        while index < 4 {
+            let sum = self.0[index] as u128 + other.0[index] as u128 + carry;
+            limbs[index] = sum as u64;
+            carry = sum >> 64;
+            index += 1;
+        }
+        if carry == 0 {
+            Some(Self(limbs))
+        } else {
+            None
+        }
+    }
+
+    /// Subtracts `other`, which must not be greater than this value.
+    const fn sub(self, other: Self) -> Self {
+        let mut limbs = [0; 4];
+        let mut borrow = false;
+        let mut index = 0;
+        while index < 4 {
+            let (difference, borrowed) = self.0[index].overflowing_sub(other.0[index]);
+            let (difference, borrowed_again) = difference.overflowing_sub(borrow as u64);
+            limbs[index] = difference;
+            borrow = borrowed || borrowed_again;
+            index += 1;
+        }
+        Self(limbs)
+    }
+
+    const fn checked_mul_small(self, factor: u64) -> Option<Self> {
+        let mut limbs = [0; 4];
+        let mut carry = 0;
+        let mut index = 0;
+        while index < 4 {
+            let product = self.0[index] as u128 * factor as u128 + carry;
+            limbs[index] = product as u64;
+            carry = product >> 64;
+            index += 1;
+        }
+        if carry == 0 {
+            Some(Self(limbs))
+        } else {
+            None
+        }
+    }
+
+    /// Divides by `divisor`, returning the quotient and the remainder.
+    const fn div_rem_small(self, divisor: u64) -> (Self, u64) {
+        let mut limbs = [0; 4];
+        let mut remainder = 0;
+        let mut index = 4;
+        while index > 0 {
+            index -= 1;
+            let dividend = (remainder as u128) << 64 | self.0[index] as u128;
+            limbs[index] = (dividend / divisor as u128) as u64;
+            remainder = (dividend % divisor as u128) as u64;
+        }
+        (Self(limbs), remainder)
+    }
+}
+
+/// A decimal value as a 256-bit magnitude, with a sign and a scale of up to
+/// [`MAX_SCALE`].
+#[derive(Clone, Copy, Debug)]
+struct Parts {
+    negative: bool,
+    magnitude: U256,
+    scale: u32,
+}
+
+/// The digits removed by dividing a magnitude by a power of ten: the most
+/// significant one, and whether any of the others are non-zero, which is all
+/// that rounding needs.
+struct Removed {
+    first: u64,
+    rest_nonzero: bool,
+}
+
+impl Parts {
+    /// The parts of a `Decimal`. Negative zero becomes zero.
+    const fn of(value: Decimal) -> Self {
+        let mantissa = value.mantissa();
+        Self {
+            negative: mantissa < 0,
+            magnitude: U256::from_u128(mantissa.unsigned_abs()),
+            scale: value.scale(),
+        }
+    }
+
+    /// Multiplies the magnitude by ten until the scale is `scale`, or returns
+    /// `None` if it would overflow.
+    const fn scaled_up(self, scale: u32) -> Option<Self> {
+        let mut parts = self;
+        while parts.scale < scale {
+            parts.magnitude = match parts.magnitude.checked_mul_small(10) {
+                Some(magnitude) => magnitude,
+                None => return None,
+            };
+            parts.scale += 1;
+        }
+        Some(parts)
+    }
+
+    /// Divides the magnitude by ten until the scale is `scale`, discarding
+    /// the digits removed, which are returned for rounding.
+    const fn truncated(self, scale: u32) -> (Self, Removed) {
+        let mut parts = self;
+        let mut removed = Removed {
+            first: 0,
+            rest_nonzero: false,
+        };
+        while parts.scale > scale {
+            let (quotient, digit) = parts.magnitude.div_rem_small(10);
+            removed.rest_nonzero = removed.rest_nonzero || removed.first != 0;
+            removed.first = digit;
+            parts.magnitude = quotient;
+            parts.scale -= 1;
+        }
+        (parts, removed)
+    }
+
+    /// Reduces the scale to `scale`, rounding with `strategy`.
+    fn rounded(self, scale: u32, strategy: RoundingStrategy) -> Self {
+        let (mut parts, removed) = self.truncated(scale);
+        let inexact = removed.first != 0 || removed.rest_nonzero;
+        let odd = parts.magnitude.0[0] & 1 == 1;
+        #[allow(deprecated)]
+        let round_up = match strategy {
+            RoundingStrategy::MidpointNearestEven | RoundingStrategy::BankersRounding => {
+                removed.first > 5 || (removed.first == 5 && (removed.rest_nonzero || odd))
+            }
+            RoundingStrategy::MidpointAwayFromZero | RoundingStrategy::RoundHalfUp => {
+                removed.first >= 5
+            }
+            RoundingStrategy::MidpointTowardZero | RoundingStrategy::RoundHalfDown => {
+                removed.first > 5 || (removed.first == 5 && removed.rest_nonzero)
+            }
+            RoundingStrategy::ToZero | RoundingStrategy::RoundDown => false,
+            RoundingStrategy::AwayFromZero | RoundingStrategy::RoundUp => inexact,
+            RoundingStrategy::ToNegativeInfinity => inexact && self.negative,
+            RoundingStrategy::ToPositiveInfinity => inexact && !self.negative,
+        };
+        if round_up {
+            // The magnitude has been divided by ten at least once, so this
+            // can't overflow
+            parts.magnitude = match parts.magnitude.checked_add(U256::from_u128(1)) {
+                Some(magnitude) => magnitude,
+                None => U256::MAX,
+            };
+        }
+        parts
+    }
+
+    /// Compares the magnitudes of two values.
+    fn cmp_magnitude(&self, other: &Self) -> Ordering {
+        // If a magnitude overflows when scaled up to the other's scale, it is
+        // the larger one
+        if self.scale < other.scale {
+            match self.scaled_up(other.scale) {
+                Some(parts) => parts.magnitude.cmp(&other.magnitude),
+                None => Ordering::Greater,
+            }
+        } else {
+            match other.scaled_up(self.scale) {
+                Some(parts) => self.magnitude.cmp(&parts.magnitude),
+                None => Ordering::Less,
+            }
+        }
+    }
+
+    fn cmp(&self, other: &Self) -> Ordering {
+        let sign = |parts: &Self| match (parts.magnitude.is_zero(), parts.negative) {
+            (true, _) => 0,
+            (false, true) => -1,
+            (false, false) => 1,
+        };
+        match sign(self).cmp(&sign(other)) {
+            Ordering::Equal if self.negative => other.cmp_magnitude(self),
+            Ordering::Equal => self.cmp_magnitude(other),
+            ordering => ordering,
+        }
+    }
+
+    /// Adds two values exactly, at the larger of their scales, or returns
+    /// `None` if the magnitude of the sum doesn't fit at that scale. Unlike
+    /// `Decimal`, the sum is never rounded to a smaller scale instead. Adding
+    /// zero returns the other value as it is, at its own scale.
+    fn checked_add(self, other: Self) -> Option<Self> {
+        if other.magnitude.is_zero() {
+            return Some(self);
+        }
+        if self.magnitude.is_zero() {
+            return Some(other);
+        }
+        let scale = self.scale.max(other.scale);
+        self.scaled_up(scale)?.add_aligned(other.scaled_up(scale)?)
+    }
+
+    /// Adds two values with the same scale.
+    fn add_aligned(self, other: Self) -> Option<Self> {
+        if self.negative == other.negative {
+            return Some(Self {
+                magnitude: self.magnitude.checked_add(other.magnitude)?,
+                ..self
+            });
+        }
+
+        let (larger, smaller) = match self.magnitude.cmp(&other.magnitude) {
+            Ordering::Less => (other, self),
+            _ => (self, other),
+        };
+        Some(Self {
+            magnitude: larger.magnitude.sub(smaller.magnitude),
+            ..larger
+        })
+    }
+
+    /// Parses a value in plain decimal notation: an optional `-`, and ASCII
+    /// digits with at most one `.`, with up to [`MAX_SCALE`] after it.
+    fn from_plain_str(s: &str) -> Option<Self> {
+        let (negative, unsigned) = match s.strip_prefix('-') {
+            Some(unsigned) => (true, unsigned),
+            None => (false, s),
+        };
+        let (integer, fraction) = unsigned.split_once('.').unwrap_or((unsigned, ""));
+        if integer.is_empty() || fraction.len() > MAX_SCALE as usize {
+            return None;
+        }
+
+        let mut magnitude = U256::ZERO;
+        for digit in integer.bytes().chain(fraction.bytes()) {
+            if !digit.is_ascii_digit() {
+                return None;
+            }
+            magnitude = magnitude
+                .checked_mul_small(10)?
+                .checked_add(U256::from_u128(u128::from(digit - b'0')))?;
+        }
+
+        Some(Self {
+            negative,
+            magnitude,
+            scale: fraction.len() as u32,
+        })
+    }
+
+    /// The `Decimal` with the same value, if there is one.
+    const fn to_exact_decimal(self) -> Option<Decimal> {
+        if !self.magnitude.fits_decimal() || self.scale > MAX_SCALE {
+            return None;
+        }
+        let [low, high, _, _] = self.magnitude.0;
+        Some(Decimal::from_parts(
+            low as u32,
+            (low >> 32) as u32,
+            high as u32,
+            self.negative,
+            self.scale,
+        ))
+    }
+}
+
+/// The backend of [`crate::numeric::CurrencyAmount`] with the `wide-amounts`
+/// feature: a `Decimal` for values which fit in one, which behave exactly as
+/// they would without the feature, or otherwise a decimal with a 256-bit
+/// mantissa. Values which fit in a `Decimal` (if need be, without some
+/// trailing zeros, as `Decimal` arithmetic would give) are always stored as
+/// one.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct WideDecimal(Repr);
+
+#[derive(Clone, Copy, Debug)]
+enum Repr {
+    Narrow(Decimal),
+    Wide(Parts),
+}
+
+impl WideDecimal {
+    pub(crate) const ZERO: Self = Self(Repr::Narrow(Decimal::ZERO));
+
+    pub(crate) const MAX: Self = Self(Repr::Wide(Parts {
+        negative: false,
+        magnitude: U256::MAX,
+        scale: 0,
+    }));
+
+    pub(crate) const MIN: Self = Self(Repr::Wide(Parts {
+        negative: true,
+        magnitude: U256::MAX,
+        scale: 0,
+    }));
+
+    /// The value of `parts`, as a `Decimal` if it fits in one once any
+    /// trailing zeros which don't are removed.
+    const fn from_parts(parts: Parts) -> Self {
+        let mut narrowed = parts;
+        while !narrowed.magnitude.fits_decimal() && narrowed.scale > 0 {
+            let (quotient, digit) = narrowed.magnitude.div_rem_small(10);
+            if digit != 0 {
+                break;
+            }
+            narrowed.magnitude = quotient;
+            narrowed.scale -= 1;
+        }
+        match narrowed.to_exact_decimal() {
+            Some(value) => Self(Repr::Narrow(value)),
+            None => Self(Repr::Wide(parts)),
+        }
+    }
+
+    const fn parts(self) -> Parts {
+        match self.0 {
+            Repr::Narrow(value) => Parts::of(value),
+            Repr::Wide(parts) => parts,
+        }
+    }
+
+    pub(crate) fn checked_add(self, other: Self) -> Option<Self> {
+        if let (Repr::Narrow(left), Repr::Narrow(right)) = (self.0, other.0) {
+            // `Decimal` rounds to a smaller scale rather than overflowing,
+            // which the wide sum doesn't need to
+            match left.checked_add(right) {
+                Some(sum) if sum.scale() == left.scale().max(right.scale()) => {
+                    return Some(Self(Repr::Narrow(sum)))
+                }
+                _ => {}
+            }
+        }
+        self.parts()
+            .checked_add(other.parts())
+            .map(WideDecimal::from_parts)
+    }
+
+    pub(crate) fn checked_sub(self, other: Self) -> Option<Self> {
+        self.checked_add(-other)
+    }
+
+    pub(crate) fn round_dp_with_strategy(&self, dp: u32, strategy: RoundingStrategy) -> Self {
+        match self.0 {
+            Repr::Narrow(value) => Self(Repr::Narrow(value.round_dp_with_strategy(dp, strategy))),
+            Repr::Wide(parts) if parts.scale > dp => {
+                WideDecimal::from_parts(parts.rounded(dp, strategy))
+            }
+            Repr::Wide(_) => *self,
+        }
+    }
+
+    /// Changes the scale like `Decimal::rescale`: rounding the value
+    /// (midpoint away from zero) to a smaller scale, and limiting a larger
+    /// one to what can be represented.
+    pub(crate) fn rescale(&mut self, scale: u32) {
+        let scale = scale.min(MAX_SCALE);
+        *self = match self.0 {
+            Repr::Narrow(mut value) => {
+                value.rescale(scale);
+                Self(Repr::Narrow(value))
+            }
+            Repr::Wide(parts) if parts.scale > scale => WideDecimal::from_parts(
+                parts.rounded(scale, RoundingStrategy::MidpointAwayFromZero),
+            ),
+            Repr::Wide(mut parts) => {
+                while parts.scale < scale {
+                    match parts.scaled_up(parts.scale + 1) {
+                        Some(scaled) => parts = scaled,
+                        None => break,
+                    }
+                }
+                Self(Repr::Wide(parts))
+            }
+        }
+    }
+
+    pub(crate) const fn scale(&self) -> u32 {
+        match self.0 {
+            Repr::Narrow(value) => value.scale(),
+            Repr::Wide(parts) => parts.scale,
+        }
+    }
+
+    /// The mantissa, saturated to the range of an `i128`.
+    pub(crate) fn mantissa(&self) -> i128 {
+        let parts = self.parts();
+        let magnitude = parts
+            .magnitude
+            .to_u128()
+            .and_then(|magnitude| i128::try_from(magnitude).ok())
+            .unwrap_or(i128::MAX);
+        if parts.negative {
+            -magnitude
+        } else {
+            magnitude
+        }
+    }
+
+    /// The nearest `Decimal`: the value rounded to a smaller scale (midpoint
+    /// away from zero) if needed, or `Decimal::MAX` or `Decimal::MIN` if its
+    /// integer part is too large.
+    pub(crate) const fn to_decimal(self) -> Decimal {
+        let parts = match self.0 {
+            Repr::Narrow(value) => return value,
+            Repr::Wide(parts) => parts,
+        };
+
+        let mut scale = parts.scale;
+        while scale > 0 {
+            scale -= 1;
+            let (mut rounded, removed) = parts.truncated(scale);
+            if removed.first >= 5 {
+                rounded.magnitude = match rounded.magnitude.checked_add(U256::from_u128(1)) {
+                    Some(magnitude) => magnitude,
+                    None => U256::MAX,
+                };
+            }
+            if let Some(value) = rounded.to_exact_decimal() {
+                return value;
+            }
+        }
+
+        if parts.negative {
+            Decimal::MIN
+        } else {
+            Decimal::MAX
+        }
+    }
+}
+
+impl From<Decimal> for WideDecimal {
+    fn from(value: Decimal) -> Self {
+        Self(Repr::Narrow(value))
+    }
+}
+
+impl PartialEq for WideDecimal {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for WideDecimal {}
+
+impl PartialOrd for WideDecimal {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for WideDecimal {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self.0, other.0) {
+            (Repr::Narrow(left), Repr::Narrow(right)) => left.cmp(&right),
+            _ => self.parts().cmp(&other.parts()),
+        }
+    }
+}
+
+impl Neg for WideDecimal {
+    type Output = Self;
+
+    fn neg(self) -> Self::Output {
+        Self(match self.0 {
+            Repr::Narrow(value) => Repr::Narrow(-value),
+            Repr::Wide(parts) => Repr::Wide(Parts {
+                negative: !parts.negative,
+                ..parts
+            }),
+        })
+    }
+}
+
+impl FromStr for WideDecimal {
+    type Err = rust_decimal::Error;
+
+    /// Parses a value like `Decimal::from_str`, except that a value in plain
+    /// decimal notation (as written by `Display`) which doesn't fit in a
+    /// `Decimal` is parsed exactly, rather than rounded or rejected.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match Parts::from_plain_str(s).map(WideDecimal::from_parts) {
+            Some(value @ WideDecimal(Repr::Wide(_))) => Ok(value),
+            _ => Decimal::from_str(s).map(|value| WideDecimal(Repr::Narrow(value))),
+        }
+    }
+}
+
+impl Display for WideDecimal {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        let parts = match &self.0 {
+            Repr::Narrow(value) => return Display::fmt(value, f),
+            Repr::Wide(parts) => parts,
+        };
+
+        // The digits, least significant first
+        let mut digits = String::new();
+        let mut magnitude = parts.magnitude;
+        while !magnitude.is_zero() || digits.len() <= parts.scale as usize {
+            let (quotient, digit) = magnitude.div_rem_small(10);
+            digits.push(char::from(b'0' + digit as u8));
+            magnitude = quotient;
+        }
+
+        if parts.negative {
+            f.write_str("-")?;
+        }
+        for (index, digit) in digits.chars().rev().enumerate() {
+            if index == digits.len() - parts.scale as usize {
+                f.write_str(".")?;
+            }
+            write!(f, "{}", digit)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use core::str::FromStr;
+
+    use rust_decimal::{Decimal, RoundingStrategy};
+
+    use crate::numeric::wide::{Repr, WideDecimal};
+
+    #[test]
+    fn test_wide_decimal() {
+        let parse = |value: &str| WideDecimal::from_str(value).unwrap();
+        let max = WideDecimal::from(Decimal::MAX);
+
+        // Past the range of a `Decimal`, values are exact
+        let sum = max.checked_add(parse("0.25")).unwrap();
+        assert_eq!("79228162514264337593543950335.25", sum.to_string());
+        assert_eq!(sum, parse(&sum.to_string()));
+        assert!(sum > max);
+        assert!(-sum < -max);
+        assert_eq!(max, sum.checked_sub(parse("0.25")).unwrap());
+        assert_eq!(
+            "79228162514264337593543950334.75",
+            max.checked_sub(parse("0.25")).unwrap().to_string()
+        );
+        assert!(matches!(
+            sum.checked_sub(parse("1.25")),
+            Some(WideDecimal(Repr::Narrow(_)))
+        ));
+        assert_eq!(None, WideDecimal::MAX.checked_add(parse("1")));
+        // The fraction doesn't fit next to the largest integer part, so the
+        // sum fails rather than being rounded
+        assert_eq!(None, WideDecimal::MAX.checked_sub(parse("0.5")));
+        assert_eq!(
+            Some(WideDecimal::MAX),
+            WideDecimal::MAX.checked_add(parse("0.000"))
+        );
+
+        // Values of different scales are compared by value
+        assert_eq!(
+            parse("158456325028528675187087900670.50"),
+            sum.checked_add(max)
+                .unwrap()
+                .checked_add(parse("0.25"))
+                .unwrap()
+        );
+
+        assert_eq!(
+            parse("79228162514264337593543950335.3"),
+            sum.round_dp_with_strategy(1, RoundingStrategy::MidpointAwayFromZero)
+        );
+        assert_eq!(
+            parse("79228162514264337593543950335.2"),
+            sum.round_dp_with_strategy(1, RoundingStrategy::MidpointNearestEven)
+        );
+        let mut rescaled = sum;
+        rescaled.rescale(4);
+        assert_eq!("79228162514264337593543950335.2500", rescaled.to_string());
+        assert_eq!(
+            792_281_625_142_643_375_935_439_503_352_500,
+            rescaled.mantissa()
+        );
+
+        assert_eq!(Decimal::MAX, sum.to_decimal());
+
+        // `Decimal` would round this sum to 27 decimal places
+        let sum = parse("10")
+            .checked_add(parse("0.0000000000000000000000000005"))
+            .unwrap();
+        assert_eq!("10.0000000000000000000000000005", sum.to_string());
+        assert_eq!(
+            Decimal::from_str("10.000000000000000000000000001").unwrap(),
+            sum.to_decimal()
+        );
+    }
+}
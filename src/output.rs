@@ -0,0 +1,149 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// What to do if the output file already exists.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum ExistingOutput {
+    /// Fail rather than replacing the existing file.
+    #[default]
+    Fail,
+    /// Replace the existing file.
+    Overwrite,
+    /// Keep the existing contents, and write after them.
+    Append,
+}
+
+/// A file which is written to a temporary path alongside the destination,
+/// and only renamed into place by [`AtomicFile::commit`]. If the process
+/// fails (or the file is dropped without being committed), the destination
+/// is left untouched, so a truncated report is never visible.
+pub struct AtomicFile {
+    path: PathBuf,
+    temp_path: PathBuf,
+    file: File,
+    /// True if the file already had contents when it was created.
+    appending: bool,
+    committed: bool,
+}
+
+impl AtomicFile {
+    /// Creates a temporary file for writing to `path`. In
+    /// [`ExistingOutput::Append`] mode, the existing contents of `path` (if
+    /// any) are copied into it first.
+    pub fn create(path: &Path, existing: ExistingOutput) -> io::Result<Self> {
+        let exists = path.try_exists()?;
+        if exists && existing == ExistingOutput::Fail {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                "file already exists (use --overwrite or --append)",
+            ));
+        }
+
+        let file_name = path
+            .file_name()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "not a file path"))?;
+        let mut temp_name = std::ffi::OsString::from(".");
+        temp_name.push(file_name);
+        temp_name.push(format!(".tmp-{}", std::process::id()));
+        let temp_path = path.with_file_name(temp_name);
+
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&temp_path)?;
+
+        let mut atomic_file = Self {
+            path: path.to_path_buf(),
+            temp_path,
+            file: file.try_clone()?,
+            appending: false,
+            committed: false,
+        };
+
+        if exists && existing == ExistingOutput::Append {
+            let copied = io::copy(&mut File::open(path)?, &mut file)?;
+            atomic_file.appending = copied > 0;
+        }
+
+        Ok(atomic_file)
+    }
+
+    /// True if this file is being appended to an existing, non-empty file.
+    pub fn is_appending(&self) -> bool {
+        self.appending
+    }
+
+    /// Flushes the file to disk, and renames it to the destination path.
+    pub fn commit(mut self) -> io::Result<()> {
+        self.file.flush()?;
+        self.file.sync_all()?;
+        fs::rename(&self.temp_path, &self.path)?;
+        self.committed = true;
+        Ok(())
+    }
+}
+
+impl Write for AtomicFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.file.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+impl Drop for AtomicFile {
+    fn drop(&mut self) {
+        if !self.committed {
+            if let Err(err) = fs::remove_file(&self.temp_path) {
+                log::error!(
+                    "Failed to remove temporary file '{}': {}",
+                    self.temp_path.display(),
+                    err
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Write;
+
+    use crate::output::{AtomicFile, ExistingOutput};
+    use crate::test::TempDir;
+
+    #[test]
+    fn test_atomic_file() {
+        let directory = TempDir::new("atomic");
+        let path = directory.join("output.csv");
+
+        let mut file = AtomicFile::create(&path, ExistingOutput::Fail).unwrap();
+        file.write_all(b"first\n").unwrap();
+        assert!(!path.exists());
+        file.commit().unwrap();
+        assert_eq!("first\n", std::fs::read_to_string(&path).unwrap());
+
+        assert!(AtomicFile::create(&path, ExistingOutput::Fail).is_err());
+
+        // Dropping without committing leaves the existing file untouched
+        let mut file = AtomicFile::create(&path, ExistingOutput::Overwrite).unwrap();
+        file.write_all(b"partial").unwrap();
+        drop(file);
+        assert_eq!("first\n", std::fs::read_to_string(&path).unwrap());
+
+        let mut file = AtomicFile::create(&path, ExistingOutput::Append).unwrap();
+        assert!(file.is_appending());
+        file.write_all(b"second\n").unwrap();
+        file.commit().unwrap();
+        assert_eq!("first\nsecond\n", std::fs::read_to_string(&path).unwrap());
+
+        let mut file = AtomicFile::create(&path, ExistingOutput::Overwrite).unwrap();
+        assert!(!file.is_appending());
+        file.write_all(b"third\n").unwrap();
+        file.commit().unwrap();
+        assert_eq!("third\n", std::fs::read_to_string(&path).unwrap());
+    }
+}
@@ -0,0 +1,275 @@
+use std::io;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, PoisonError};
+use std::thread;
+use std::time::Instant;
+
+use transaction_processor::io::csv::RejectWriter;
+use transaction_processor::summary::BatchResult;
+use transaction_processor::{MergeError, TransactionProcessor};
+
+use crate::{read_file, write_report, ProcessOptions, TransactionProcessorCLIError};
+
+/// The outcome of reading one input file with its own processor.
+struct FileResult {
+    transaction_processor: TransactionProcessor,
+    summary: BatchResult,
+    /// The file's rejected rows, without a header row.
+    rejects: Vec<u8>,
+    result: Result<(), TransactionProcessorCLIError>,
+}
+
+/// Processes the input files concurrently on up to `jobs` threads, each file
+/// with a separate processor (created with [`TransactionProcessor::new_like`]),
+/// and merges the results in file order before writing the report.
+///
+/// This gives the same report, rejected rows and summary as processing the
+/// files sequentially, as long as no client has transactions in more than
+/// one file. Otherwise, the first file (in order) which shares a client with
+/// an earlier file is reported as an error. With `options.fail_fast`, the
+/// error from the first failing file (in order) is returned, whichever
+/// thread finds it first.
+pub fn process_files_parallel(
+    transaction_processor: TransactionProcessor,
+    input_files: &[String],
+    options: &ProcessOptions,
+    output: impl io::Write + Send,
+    rejects: Option<&mut dyn io::Write>,
+    jobs: usize,
+) -> Result<BatchResult, TransactionProcessorCLIError> {
+    let start = Instant::now();
+    let collect_rejects = rejects.is_some();
+
+    let next_file = AtomicUsize::new(0);
+    // Files after a failure in fail-fast mode don't need to be read, but
+    // earlier ones do, in case they fail too
+    let first_failure = AtomicUsize::new(usize::MAX);
+    let results: Mutex<Vec<Option<FileResult>>> =
+        Mutex::new(input_files.iter().map(|_| None).collect());
+
+    thread::scope(|scope| {
+        for _ in 0..jobs.clamp(1, input_files.len().max(1)) {
+            scope.spawn(|| loop {
+                let index = next_file.fetch_add(1, Ordering::Relaxed);
+                if index >= input_files.len() || index > first_failure.load(Ordering::Relaxed) {
+                    break;
+                }
+
+                let result = read_separately(
+                    transaction_processor.new_like(),
+                    &input_files[index],
+                    options,
+                    collect_rejects,
+                );
+                if result.result.is_err() {
+                    first_failure.fetch_min(index, Ordering::Relaxed);
+                }
+
+                results.lock().unwrap_or_else(PoisonError::into_inner)[index] = Some(result);
+            });
+        }
+    });
+
+    let mut merged = transaction_processor;
+    let mut summary = BatchResult::new();
+    let mut combined_rejects = Vec::new();
+
+    for (path, result) in input_files
+        .iter()
+        .zip(results.into_inner().unwrap_or_else(PoisonError::into_inner))
+    {
+        // Files are only skipped after a failure, which is returned first
+        let result = match result {
+            Some(result) => result,
+            None => break,
+        };
+
+        summary.merge(&result.summary);
+        combined_rejects.extend(result.rejects);
+
+        if let Err(err) = result.result {
+            write_rejects(rejects, &combined_rejects, options);
+            return Err(err);
+        }
+
+        merged
+            .merge(result.transaction_processor)
+            .map_err(|err| match err {
+                MergeError::ClientConflict(client) => {
+                    TransactionProcessorCLIError::FilesNotIndependent {
+                        path: path.clone(),
+                        client,
+                    }
+                }
+            })?;
+    }
+
+    write_rejects(rejects, &combined_rejects, options);
+    write_report(&merged, options, output)?;
+
+    summary.set_elapsed(start.elapsed());
+    Ok(summary)
+}
+
+/// Reads a single input file into its own processor, buffering any rejected
+/// rows if `collect_rejects` is set.
+fn read_separately(
+    mut transaction_processor: TransactionProcessor,
+    path: &str,
+    options: &ProcessOptions,
+    collect_rejects: bool,
+) -> FileResult {
+    let mut summary = BatchResult::new();
+    let mut rejects = Vec::new();
+
+    let result = {
+        let mut reject_writer = collect_rejects
+            .then(|| RejectWriter::new_headerless(&mut rejects as &mut dyn io::Write));
+        let result = read_file(
+            |transaction| options.transact(&mut transaction_processor, transaction),
+            path,
+            options,
+            0,
+            &mut reject_writer,
+            &mut summary,
+        );
+
+        if let Some(Err(err)) = reject_writer.as_mut().map(RejectWriter::flush) {
+            log::error!("Failed to write rejected rows: {}", err);
+        }
+        result
+    };
+
+    FileResult {
+        transaction_processor,
+        summary,
+        rejects,
+        result,
+    }
+}
+
+/// Writes the rejected rows of all files, with a single header row (unless
+/// they are being appended), if there are any.
+fn write_rejects(rejects: Option<&mut dyn io::Write>, rows: &[u8], options: &ProcessOptions) {
+    let rejects = match rejects {
+        Some(rejects) if !rows.is_empty() => rejects,
+        _ => return,
+    };
+
+    let result = if options.omit_rejects_header {
+        Ok(())
+    } else {
+        let mut writer = RejectWriter::new(&mut *rejects);
+        let result = writer
+            .write_header()
+            .and_then(|()| writer.flush())
+            .map_err(|err| err.to_string());
+        drop(writer);
+        result
+    };
+
+    if let Err(err) = result.and_then(|()| rejects.write_all(rows).map_err(|err| err.to_string())) {
+        log::error!("Failed to write rejected rows: {}", err);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use transaction_processor::pipeline::PipelineOptions;
+    use transaction_processor::TransactionProcessor;
+
+    use crate::parallel::process_files_parallel;
+    use crate::{process_files, ProcessOptions, TransactionProcessorCLIError};
+
+    /// Test files which have no clients in common.
+    const INDEPENDENT_FILES: [&str; 3] = [
+        "test_data/000_input.csv",
+        "test_data/012_input_a.csv",
+        "test_data/012_input_b.csv",
+    ];
+
+    #[test]
+    fn test_matches_sequential() {
+        let input_files: Vec<_> = INDEPENDENT_FILES.iter().map(|f| f.to_string()).collect();
+
+        let mut expected_output = Vec::new();
+        let mut expected_rejects = Vec::new();
+        let expected_summary = process_files(
+            TransactionProcessor::new(),
+            &input_files,
+            &ProcessOptions::default(),
+            &mut expected_output,
+            Some(&mut expected_rejects),
+        )
+        .unwrap();
+
+        for jobs in [1, 2, 8] {
+            let mut output = Vec::new();
+            let mut rejects = Vec::new();
+            let mut summary = process_files_parallel(
+                TransactionProcessor::new(),
+                &input_files,
+                &ProcessOptions::default(),
+                &mut output,
+                Some(&mut rejects),
+                jobs,
+            )
+            .unwrap();
+            summary.set_elapsed(expected_summary.elapsed());
+
+            assert_eq!(expected_output, output);
+            assert_eq!(expected_rejects, rejects);
+            assert_eq!(expected_summary, summary);
+        }
+    }
+
+    #[test]
+    fn test_conflict() {
+        let result = process_files_parallel(
+            TransactionProcessor::new(),
+            &[
+                "test_data/012_input_a.csv".to_string(),
+                "test_data/001_input.csv".to_string(),
+            ],
+            &ProcessOptions::default(),
+            Vec::new(),
+            None,
+            2,
+        );
+
+        match result {
+            Err(TransactionProcessorCLIError::FilesNotIndependent { path, client }) => {
+                assert_eq!("test_data/001_input.csv", path);
+                assert_eq!(1, client);
+            }
+            _ => panic!("Expected a merge conflict"),
+        }
+    }
+
+    #[test]
+    fn test_fail_fast() {
+        // Both files fail, but the first is always reported
+        for _ in 0..10 {
+            let result = process_files_parallel(
+                TransactionProcessor::new(),
+                &[
+                    "test_data/002_input.csv".to_string(),
+                    "test_data/005_input.csv".to_string(),
+                ],
+                &ProcessOptions {
+                    pipeline: PipelineOptions::new().with_fail_fast(true),
+                    ..ProcessOptions::default()
+                },
+                Vec::new(),
+                None,
+                2,
+            );
+
+            assert!(matches!(
+                result,
+                Err(TransactionProcessorCLIError::TransactionFailed { path, .. })
+                    if path == "test_data/002_input.csv"
+            ));
+        }
+    }
+}
@@ -0,0 +1,916 @@
+use std::collections::VecDeque;
+use std::fmt::{Display, Formatter};
+use std::io;
+use std::sync::mpsc::{self, SyncSender};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::thread::{self, JoinHandle};
+
+#[cfg(feature = "crypto")]
+use crate::crypto::HmacKey;
+use crate::io::csv::{CSVReadItem, CSVReader, CSVRecordError, CSVWriter};
+use crate::io::jsonl::{JsonlReader, JsonlReaderError};
+use crate::io::{AmountFormat, ColumnMapping};
+use crate::reject::{RejectSink, RejectedRow};
+use crate::report::{ReportFilter, ReportOrder, ReportSink, ReportWriter};
+use crate::sequence::{Sequenced, Sequencer};
+use crate::source::{SourceError, TransactionSource};
+use crate::summary::BatchResult;
+use crate::{ClientId, ReportEntry, Transaction, TransactionError, TransactionProcessor};
+
+/// The `log` target of rows which fail to be read and warnings about input
+/// rows, so that they can be filtered separately from rejected transactions.
+pub const PARSE_LOG_TARGET: &str = "transaction_processor::parse";
+
+/// The `log` target of transactions which fail to be applied (or which the
+/// CLI's servers reject), and of skipped replays.
+pub const TRANSACTION_LOG_TARGET: &str = "transaction_processor::transactions";
+
+/// The number of transactions which can be waiting to be applied by the
+/// stage started by [`spawn`], by default.
+pub const DEFAULT_CHANNEL_CAPACITY: usize = 1024;
+
+/// The format of an [`Input`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum InputFormat {
+    /// CSV with the columns `type,client,tx,amount`.
+    Csv,
+    /// Tab-separated values, with the same columns as CSV.
+    Tsv,
+    /// One JSON transaction per line.
+    Jsonl,
+}
+
+/// An input to [`process`]: a reader of transactions in a format, with a
+/// name identifying it in logs and errors (e.g. its path).
+#[derive(Debug)]
+pub struct Input<R> {
+    source: String,
+    format: InputFormat,
+    reader: R,
+}
+
+impl<R: io::Read> Input<R> {
+    /// Creates an input reading transactions in `format` from `reader`.
+    pub fn new(source: impl Into<String>, format: InputFormat, reader: R) -> Self {
+        Self {
+            source: source.into(),
+            format,
+            reader,
+        }
+    }
+}
+
+/// Options controlling how [`process`] reads its inputs, applies their
+/// transactions, and writes the report.
+#[derive(Clone, Debug, Default)]
+pub struct PipelineOptions {
+    amount_format: AmountFormat,
+    column_mapping: Option<ColumnMapping>,
+    no_header: bool,
+    #[cfg(feature = "crypto")]
+    signature_key: Option<HmacKey>,
+    client_keys: bool,
+    fail_fast: bool,
+    reorder_window: usize,
+    report_filter: ReportFilter,
+    report_order: ReportOrder,
+    output_scale: Option<u32>,
+}
+
+impl PipelineOptions {
+    /// Creates options for reading standard CSV and JSONL inputs, skipping
+    /// rows which fail to be read or applied, and writing every account in
+    /// the report.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets how amounts are parsed.
+    #[must_use]
+    pub fn with_amount_format(mut self, amount_format: AmountFormat) -> Self {
+        self.amount_format = amount_format;
+        self
+    }
+
+    /// Sets the names of the CSV input columns, if not the standard names.
+    #[must_use]
+    pub fn with_column_mapping(mut self, column_mapping: Option<ColumnMapping>) -> Self {
+        self.column_mapping = column_mapping;
+        self
+    }
+
+    /// Sets whether CSV inputs have no header row.
+    #[must_use]
+    pub fn with_no_header(mut self, no_header: bool) -> Self {
+        self.no_header = no_header;
+        self
+    }
+
+    /// Sets the key every CSV row must be signed with, if any (see
+    /// [`CSVReader::with_signature_key`]).
+    #[cfg(feature = "crypto")]
+    #[must_use]
+    pub fn with_signature_key(mut self, signature_key: Option<HmacKey>) -> Self {
+        self.signature_key = signature_key;
+        self
+    }
+
+    /// Sets whether the client column of CSV inputs holds client keys (see
+    /// [`CSVReader::with_client_keys`]).
+    #[must_use]
+    pub fn with_client_keys(mut self, client_keys: bool) -> Self {
+        self.client_keys = client_keys;
+        self
+    }
+
+    /// Sets whether processing stops at the first row which fails to be read
+    /// or applied, rather than skipping it.
+    #[must_use]
+    pub fn with_fail_fast(mut self, fail_fast: bool) -> Self {
+        self.fail_fast = fail_fast;
+        self
+    }
+
+    /// Sets the number of rows with sequence numbers which may be held back
+    /// in each input to put them back in order (see [`Sequencer`]).
+    #[must_use]
+    pub fn with_reorder_window(mut self, reorder_window: usize) -> Self {
+        self.reorder_window = reorder_window;
+        self
+    }
+
+    /// Sets which accounts are included in the report.
+    #[must_use]
+    pub fn with_report_filter(mut self, report_filter: ReportFilter) -> Self {
+        self.report_filter = report_filter;
+        self
+    }
+
+    /// Sets the order of the accounts in the report.
+    #[must_use]
+    pub fn with_report_order(mut self, report_order: ReportOrder) -> Self {
+        self.report_order = report_order;
+        self
+    }
+
+    /// Sets the number of decimal places of every report amount, if not
+    /// those of the amounts themselves.
+    #[must_use]
+    pub fn with_output_scale(mut self, output_scale: Option<u32>) -> Self {
+        self.output_scale = output_scale;
+        self
+    }
+
+    /// How amounts are parsed.
+    #[must_use]
+    pub fn amount_format(&self) -> AmountFormat {
+        self.amount_format
+    }
+
+    /// Whether processing stops at the first row which fails to be read or
+    /// applied.
+    #[must_use]
+    pub fn fail_fast(&self) -> bool {
+        self.fail_fast
+    }
+
+    /// Which accounts are included in the report.
+    #[must_use]
+    pub fn report_filter(&self) -> &ReportFilter {
+        &self.report_filter
+    }
+
+    /// The order of the accounts in the report.
+    #[must_use]
+    pub fn report_order(&self) -> ReportOrder {
+        self.report_order
+    }
+
+    /// The number of decimal places of every report amount, if set.
+    #[must_use]
+    pub fn output_scale(&self) -> Option<u32> {
+        self.output_scale
+    }
+}
+
+/// Error which ends [`process`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum PipelineError {
+    /// A row failed to be read with [`PipelineOptions::with_fail_fast`].
+    /// `record` is the 1-based line the row starts on for CSV and JSON Lines
+    /// inputs, or its 1-based position among the rows of other inputs.
+    InvalidRecord {
+        /// The name of the input.
+        source: String,
+        /// The line or position of the row.
+        record: usize,
+        /// Why the row could not be read.
+        error: String,
+    },
+    /// A row failed to be applied with [`PipelineOptions::with_fail_fast`],
+    /// like [`PipelineError::InvalidRecord`].
+    TransactionFailed {
+        /// The name of the input.
+        source: String,
+        /// The line or position of the row.
+        record: usize,
+        /// Why the transaction could not be applied.
+        error: String,
+    },
+    /// The report could not be written.
+    FailedToWriteReport(String),
+}
+
+impl Display for PipelineError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PipelineError::InvalidRecord {
+                source,
+                record,
+                error,
+            }
+            | PipelineError::TransactionFailed {
+                source,
+                record,
+                error,
+            } => write!(f, "'{}' record {}: {}", source, record, error),
+            PipelineError::FailedToWriteReport(err) => {
+                write!(f, "Failed to write report: {}", err)
+            }
+        }
+    }
+}
+
+/// Reads the transactions of every input in turn, applying them to
+/// `transaction_processor`, and then writes the report as CSV to `report`.
+/// Rows which fail to be read or applied are given to `rejects`, if given
+/// (e.g. a [`crate::io::csv::RejectWriter`]), which is flushed at the end.
+///
+/// Returns a summary of the rows read, applied and rejected.
+///
+/// # Errors
+///
+/// With [`PipelineOptions::with_fail_fast`], returns an error for the first
+/// row which fails to be read or applied. Otherwise, only fails if the
+/// report can't be written.
+pub fn process<R: io::Read>(
+    transaction_processor: &mut TransactionProcessor,
+    inputs: impl IntoIterator<Item = Input<R>>,
+    options: &PipelineOptions,
+    report: impl io::Write,
+    rejects: Option<&mut dyn RejectSink>,
+) -> Result<BatchResult, PipelineError> {
+    let start = std::time::Instant::now();
+    let mut summary = BatchResult::new();
+    let mut discard = |_: RejectedRow| {};
+    let reject: &mut dyn RejectSink = match rejects {
+        Some(rejects) => rejects,
+        None => &mut discard,
+    };
+
+    for input in inputs {
+        log::info!("Reading {}", input.source);
+        summary.record_file();
+        let transact = |transaction: &Transaction| transaction_processor.transact(transaction);
+        match input.format {
+            InputFormat::Csv | InputFormat::Tsv => {
+                let delimiter = if input.format == InputFormat::Tsv {
+                    b'\t'
+                } else {
+                    b','
+                };
+                let mut reader = delimited_reader(input.reader, &input.source, delimiter, options);
+                let mut warnings = 0;
+                let result = apply_rows(
+                    transact,
+                    &input.source,
+                    csv_rows(&mut reader, &mut warnings),
+                    options,
+                    reject,
+                    &mut summary,
+                );
+                summary.record_warnings(warnings);
+                result?;
+            }
+            InputFormat::Jsonl => apply_rows(
+                transact,
+                &input.source,
+                jsonl_rows(&mut jsonl_reader(io::BufReader::new(input.reader), options)),
+                options,
+                reject,
+                &mut summary,
+            )?,
+        }
+    }
+
+    finish(transaction_processor, options, report, reject)?;
+    summary.set_elapsed(start.elapsed());
+    Ok(summary)
+}
+
+/// Like [`process`], but reads the transactions from any
+/// [`TransactionSource`]s, each with its name, e.g. feeds in formats which
+/// the library can't read itself. The options which configure the readers
+/// (such as the amount format) are left to the sources.
+///
+/// # Errors
+///
+/// As for [`process`].
+pub fn process_sources<N: AsRef<str>, S: TransactionSource>(
+    transaction_processor: &mut TransactionProcessor,
+    sources: impl IntoIterator<Item = (N, S)>,
+    options: &PipelineOptions,
+    report: impl io::Write,
+    rejects: Option<&mut dyn RejectSink>,
+) -> Result<BatchResult, PipelineError> {
+    let start = std::time::Instant::now();
+    let mut summary = BatchResult::new();
+    let mut discard = |_: RejectedRow| {};
+    let reject: &mut dyn RejectSink = match rejects {
+        Some(rejects) => rejects,
+        None => &mut discard,
+    };
+
+    for (source, mut transactions) in sources {
+        let source = source.as_ref();
+        log::info!("Reading {}", source);
+        summary.record_file();
+        let result = apply_rows(
+            |transaction: &Transaction| transaction_processor.transact(transaction),
+            source,
+            source_rows(&mut transactions),
+            options,
+            reject,
+            &mut summary,
+        );
+        summary.record_warnings(transactions.warnings());
+        result?;
+    }
+
+    finish(transaction_processor, options, report, reject)?;
+    summary.set_elapsed(start.elapsed());
+    Ok(summary)
+}
+
+/// Flushes the rejected rows, and writes the report as CSV to `report`, at
+/// the end of [`process`] or [`process_sources`].
+fn finish(
+    transaction_processor: &TransactionProcessor,
+    options: &PipelineOptions,
+    report: impl io::Write,
+    rejects: &mut dyn RejectSink,
+) -> Result<(), PipelineError> {
+    if let Err(err) = rejects.flush() {
+        log::error!("Failed to write rejected rows: {}", err);
+    }
+
+    write_report(transaction_processor, options, CSVWriter::new(report))
+        .and_then(|writer| {
+            writer
+                .into_inner()
+                .map_err(|err| io::Error::other(err.to_string()))
+        })
+        .map(|_| ())
+        .map_err(|err| PipelineError::FailedToWriteReport(err.to_string()))
+}
+
+/// Starts a thread which applies the transactions sent to the returned
+/// channel to `transaction_processor`, in the order they are received, so
+/// that they can be read or parsed on other threads (e.g. one per input
+/// stream). Transactions which fail to be applied are given to `rejects`
+/// (e.g. a `Vec<RejectedRow>`), and replays are skipped, as for
+/// [`TransactionProcessor::transact_all`].
+///
+/// The channel holds up to `capacity` transactions, so sending blocks while
+/// it is full rather than the queue growing without limit. The sender can
+/// be cloned for each thread, and the stage finishes once every sender has
+/// been dropped (see [`PipelineHandle::finish`]).
+pub fn spawn<S: RejectSink + Send + 'static>(
+    transaction_processor: TransactionProcessor,
+    capacity: usize,
+    mut rejects: S,
+) -> (SyncSender<Transaction>, PipelineHandle<S>) {
+    let (sender, receiver) = mpsc::sync_channel::<Transaction>(capacity);
+    let state = Arc::new(Mutex::new(transaction_processor));
+
+    let shared = Arc::clone(&state);
+    let thread = thread::spawn(move || {
+        let start = std::time::Instant::now();
+        let mut summary = BatchResult::new();
+        for transaction in receiver {
+            let result = shared
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .transact(&transaction);
+            summary.record_transaction(&result);
+            match result {
+                Ok(()) | Err(TransactionError::Replayed(_)) => {}
+                Err(err) => {
+                    let row = RejectedRow::transaction_failed(Vec::new(), transaction, err);
+                    if let Err(err) = rejects.reject(row) {
+                        log::error!("Failed to write rejected row: {}", err);
+                    }
+                }
+            }
+        }
+        if let Err(err) = rejects.flush() {
+            log::error!("Failed to write rejected rows: {}", err);
+        }
+        summary.set_elapsed(start.elapsed());
+        (summary, rejects)
+    });
+
+    (sender, PipelineHandle { state, thread })
+}
+
+/// A handle to the stage started by [`spawn`], for reports on the accounts
+/// while it runs, and for its results once it finishes.
+pub struct PipelineHandle<S> {
+    state: Arc<Mutex<TransactionProcessor>>,
+    thread: JoinHandle<(BatchResult, S)>,
+}
+
+impl<S> PipelineHandle<S> {
+    fn transaction_processor(&self) -> MutexGuard<'_, TransactionProcessor> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Returns the report entry for a single client account (see
+    /// [`TransactionProcessor::account_report`]), with the transactions
+    /// applied so far.
+    #[must_use]
+    pub fn account_report(&self, client: ClientId) -> Option<ReportEntry> {
+        self.transaction_processor().account_report(client)
+    }
+
+    /// Generates a report of all client accounts (see
+    /// [`TransactionProcessor::generate_report_as_vec`]), with the
+    /// transactions applied so far.
+    #[must_use]
+    pub fn report(&self) -> Vec<ReportEntry> {
+        self.transaction_processor().generate_report_as_vec()
+    }
+
+    /// Waits for every transaction sent to be applied, returning the
+    /// processor, a summary of the transactions applied and rejected, and
+    /// the reject sink. Blocks until every sender has been dropped.
+    pub fn finish(self) -> (TransactionProcessor, BatchResult, S) {
+        let (summary, rejects) = self
+            .thread
+            .join()
+            .unwrap_or_else(|panic| std::panic::resume_unwind(panic));
+        let transaction_processor = Arc::into_inner(self.state)
+            .expect("the stage has finished")
+            .into_inner()
+            .unwrap_or_else(PoisonError::into_inner);
+        (transaction_processor, summary, rejects)
+    }
+}
+
+/// Creates a reader for a CSV input, configured by the options.
+pub fn csv_reader<R: io::Read>(reader: R, source: &str, options: &PipelineOptions) -> CSVReader<R> {
+    delimited_reader(reader, source, b',', options)
+}
+
+/// Like [`csv_reader`], but for data with fields separated by `delimiter`,
+/// e.g. `b'\t'` for TSV.
+pub fn delimited_reader<R: io::Read>(
+    reader: R,
+    source: &str,
+    delimiter: u8,
+    options: &PipelineOptions,
+) -> CSVReader<R> {
+    let reader = CSVReader::new_delimited(reader, delimiter, !options.no_header);
+
+    let reader = reader
+        .with_amount_format(options.amount_format)
+        .with_source_name(source);
+    #[cfg(feature = "crypto")]
+    let reader = match &options.signature_key {
+        Some(key) => reader.with_signature_key(key.clone()),
+        None => reader,
+    };
+    let reader = if options.client_keys {
+        reader.with_client_keys()
+    } else {
+        reader
+    };
+    match &options.column_mapping {
+        Some(column_mapping) => reader.with_column_mapping(column_mapping.clone()),
+        None => reader,
+    }
+}
+
+/// Returns the rows read from a CSV input with their fields, numbered by
+/// the line each starts on, for [`apply_rows`], logging and counting any
+/// warnings.
+pub fn csv_rows<'a, R: io::Read>(
+    reader: &'a mut CSVReader<R>,
+    warnings: &'a mut usize,
+) -> impl Iterator<Item = (usize, (Vec<String>, Result<Transaction, CSVRecordError>))> + 'a {
+    let mut position = 0;
+    reader
+        .read_with_diagnostics()
+        .filter_map(move |item| match item {
+            CSVReadItem::Record {
+                fields,
+                transaction,
+                line,
+            } => {
+                // Rows whose line is unknown are numbered by position
+                position += 1;
+                let number = line.and_then(|line| usize::try_from(line).ok());
+                Some((number.unwrap_or(position), (fields, transaction)))
+            }
+            CSVReadItem::Diagnostic(diagnostic) => {
+                log::warn!(target: PARSE_LOG_TARGET, "{}", diagnostic);
+                *warnings += 1;
+                None
+            }
+        })
+}
+
+/// Creates a reader for a JSONL input, configured by the options.
+pub fn jsonl_reader<R: io::BufRead>(reader: R, options: &PipelineOptions) -> JsonlReader<R> {
+    JsonlReader::new(reader).with_amount_format(options.amount_format)
+}
+
+/// Returns the rows read from a JSONL input, each numbered by its line and
+/// with the line as its only field, for [`apply_rows`].
+pub fn jsonl_rows<R: io::BufRead>(
+    reader: &mut JsonlReader<R>,
+) -> impl Iterator<Item = (usize, (Vec<String>, Result<Transaction, JsonlReaderError>))> + '_ {
+    reader
+        .read_with_line_numbers()
+        .map(|(number, line, transaction)| (number, (vec![line], transaction)))
+}
+
+/// Returns the rows read from a [`TransactionSource`] with their fields,
+/// numbered by position, for [`apply_rows`].
+pub fn source_rows<S: TransactionSource + ?Sized>(
+    source: &mut S,
+) -> impl Iterator<Item = (usize, (Vec<String>, Result<Transaction, SourceError>))> + '_ {
+    numbered_rows(std::iter::from_fn(|| source.next_row()))
+}
+
+/// Numbers the rows of an input which has no lines (e.g. Avro) by their
+/// 1-based position, for [`apply_rows`].
+pub fn numbered_rows<T>(rows: impl Iterator<Item = T>) -> impl Iterator<Item = (usize, T)> {
+    (1..).zip(rows)
+}
+
+/// Applies the transactions read from the input named `source` using
+/// `transact`, counting them in `summary`. Each row is numbered by the line
+/// it starts on, or by its position in inputs without lines (see
+/// [`PipelineError::InvalidRecord`]), and has its fields verbatim, if known.
+///
+/// Rows with sequence numbers are put back in order within the reorder
+/// window. Rows which fail to be read or applied are given to `rejects`
+/// (which isn't flushed), and end processing with
+/// [`PipelineOptions::with_fail_fast`]. Failures of the sink are logged.
+///
+/// # Errors
+///
+/// With [`PipelineOptions::with_fail_fast`], returns an error for the first
+/// row which fails to be read or applied.
+pub fn apply_rows<E: Display>(
+    mut transact: impl FnMut(&Transaction) -> Result<(), TransactionError>,
+    source: &str,
+    rows: impl Iterator<Item = (usize, (Vec<String>, Result<Transaction, E>))>,
+    options: &PipelineOptions,
+    rejects: &mut dyn RejectSink,
+    summary: &mut BatchResult,
+) -> Result<(), PipelineError> {
+    let mut sequence_warnings = 0;
+
+    for (number, (fields, transaction)) in
+        sequenced_rows(rows, source, options.reorder_window, &mut sequence_warnings)
+    {
+        let row = match transaction {
+            Ok(transaction) => {
+                let result = transact(&transaction);
+                summary.record_transaction(&result);
+
+                match result {
+                    Ok(()) => continue,
+                    Err(TransactionError::Replayed(tx)) => {
+                        log::debug!(
+                            target: TRANSACTION_LOG_TARGET,
+                            "Skipping replay of transaction {} for client {}",
+                            tx,
+                            transaction.client()
+                        );
+                        continue;
+                    }
+                    Err(err) => {
+                        log::error!(
+                            target: TRANSACTION_LOG_TARGET,
+                            "Got error '{}' processing {}. Skipping.",
+                            err,
+                            transaction
+                        );
+
+                        RejectedRow::transaction_failed(fields, transaction, err)
+                    }
+                }
+            }
+            Err(err) => {
+                log::error!(
+                    target: PARSE_LOG_TARGET,
+                    "Got error '{}' reading input. Skipping transaction.",
+                    err
+                );
+                summary.record_parse_error();
+                RejectedRow::parse_error(err.to_string(), fields)
+            }
+        };
+
+        let (parse_error, reason) = (row.is_parse_error(), row.reason().to_string());
+        if let Err(err) = rejects.reject(row) {
+            log::error!("Failed to write rejected row: {}", err);
+        }
+
+        if options.fail_fast {
+            let (source, record) = (source.to_string(), number);
+            return Err(if parse_error {
+                PipelineError::InvalidRecord {
+                    source,
+                    record,
+                    error: reason,
+                }
+            } else {
+                PipelineError::TransactionFailed {
+                    source,
+                    record,
+                    error: reason,
+                }
+            });
+        }
+    }
+
+    summary.record_warnings(sequence_warnings);
+    Ok(())
+}
+
+/// Writes the report of the accounts matching the options' filter, in their
+/// order, to `writer`. Entries which fail to be written are logged and
+/// skipped.
+pub fn write_csv_report<W: io::Write>(
+    transaction_processor: &TransactionProcessor,
+    options: &PipelineOptions,
+    writer: &mut CSVWriter<W>,
+) {
+    if let Err(err) = write_report(transaction_processor, options, writer) {
+        log::error!("Failed to write report: {}", err);
+    }
+}
+
+/// Writes the report of the accounts matching the options' filter, in their
+/// order, to `sink` (e.g. a [`CSVWriter`] or [`crate::io::jsonl::JsonlWriter`]) as the entries
+/// are generated, with a [`ReportWriter`] flushing it every
+/// [`crate::report::DEFAULT_FLUSH_INTERVAL`] entries. Entries which fail to
+/// be written are logged and skipped.
+///
+/// Returns the sink once flushed.
+///
+/// # Errors
+///
+/// Returns an error if the sink could not be flushed at the end.
+pub fn write_report<S: ReportSink>(
+    transaction_processor: &TransactionProcessor,
+    options: &PipelineOptions,
+    sink: S,
+) -> io::Result<S> {
+    let mut writer = ReportWriter::new(sink).with_scale(options.output_scale);
+    for entry in transaction_processor.generate_report(&options.report_filter, options.report_order)
+    {
+        if let Err(err) = writer.write(entry) {
+            log::error!("Failed to write entry: {}", err);
+        }
+    }
+    writer.finish()
+}
+
+/// Passes the transactions with sequence numbers in `rows` through a
+/// [`Sequencer`] with the specified reorder window, so that they're put back
+/// in order where possible. Any gaps or out-of-order rows are logged as
+/// warnings for the input named `source`, and counted in `warnings`. Other
+/// rows are passed on as they are read.
+fn sequenced_rows<'a, E: 'a>(
+    rows: impl Iterator<Item = (usize, (Vec<String>, Result<Transaction, E>))> + 'a,
+    source: &'a str,
+    window: usize,
+    warnings: &'a mut usize,
+) -> impl Iterator<Item = (usize, (Vec<String>, Result<Transaction, E>))> + 'a {
+    let mut rows = rows.fuse();
+    let mut sequencer = Sequencer::new(window);
+    let mut ready = VecDeque::new();
+
+    std::iter::from_fn(move || loop {
+        match ready.pop_front() {
+            Some(Sequenced::Item(row)) => return Some(row),
+            Some(Sequenced::Issue(issue)) => {
+                log::warn!(target: PARSE_LOG_TARGET, "warning: {}: {}", source, issue);
+                *warnings += 1;
+                continue;
+            }
+            None => {}
+        }
+
+        let Some(row) = rows.next() else {
+            let rest = sequencer.finish();
+            if rest.is_empty() {
+                return None;
+            }
+            ready.extend(rest);
+            continue;
+        };
+        match row.1 .1.as_ref().ok().and_then(Transaction::sequence) {
+            Some(sequence) => ready.extend(sequencer.push(sequence, row)),
+            None => return Some(row),
+        }
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+    use std::thread;
+
+    use crate::io::csv::CSVReader;
+    use crate::io::jsonl::JsonlReader;
+    use crate::numeric::CurrencyAmount;
+    use crate::pipeline::{
+        process, process_sources, spawn, Input, InputFormat, PipelineError, PipelineOptions,
+    };
+    use crate::reject::RejectedRow;
+    use crate::source::{IterSource, SourceError, TransactionSource};
+    use crate::{Transaction, TransactionProcessor, TransactionType};
+
+    #[test]
+    fn test_process() {
+        let csv = "type,client,tx,amount\n\
+                   deposit,1,1,5.0\n\
+                   withdrawal,1,2,7.0\n\
+                   deposit,2,3,oops\n";
+        let jsonl = r#"{"type":"deposit","client":2,"tx":4,"amount":"1.5"}"#;
+
+        let mut transaction_processor = TransactionProcessor::new();
+        let mut report = Vec::new();
+        let mut rejects: Vec<RejectedRow> = Vec::new();
+        let summary = process(
+            &mut transaction_processor,
+            [
+                Input::new("a.csv", InputFormat::Csv, csv.as_bytes()),
+                Input::new("b.jsonl", InputFormat::Jsonl, jsonl.as_bytes()),
+            ],
+            &PipelineOptions::new().with_output_scale(Some(2)),
+            &mut report,
+            Some(&mut rejects),
+        )
+        .unwrap();
+
+        assert_eq!(2, summary.files_read());
+        assert_eq!(2, summary.transactions_applied());
+        assert_eq!(2, summary.total_rejects());
+        assert_eq!(
+            "client,available,held,total,locked\n\
+             1,5.00,0.00,5.00,false\n\
+             2,1.50,0.00,1.50,false\n",
+            String::from_utf8(report).unwrap()
+        );
+        assert_eq!(2, rejects.len());
+        assert_eq!("INSUFFICIENT_FUNDS", rejects[0].code());
+        assert_eq!(["withdrawal", "1", "2", "7.0"], rejects[0].fields());
+        assert!(rejects[0].transaction().is_some());
+        assert!(rejects[1].is_parse_error());
+        assert_eq!(None, rejects[1].transaction());
+
+        let result = process(
+            &mut TransactionProcessor::new(),
+            [Input::new("a.csv", InputFormat::Csv, csv.as_bytes())],
+            &PipelineOptions::new().with_fail_fast(true),
+            Vec::new(),
+            None,
+        );
+        assert!(matches!(
+            result,
+            Err(PipelineError::TransactionFailed { record: 3, .. })
+        ));
+
+        // The record is the line the row is on, counting blank lines
+        let jsonl = "{\"type\":\"deposit\",\"client\":1,\"tx\":1,\"amount\":\"1\"}\n\n\
+                     {\"type\":\"withdrawal\",\"client\":1,\"tx\":2,\"amount\":\"2\"}\n";
+        let result = process(
+            &mut TransactionProcessor::new(),
+            [Input::new("b.jsonl", InputFormat::Jsonl, jsonl.as_bytes())],
+            &PipelineOptions::new().with_fail_fast(true),
+            Vec::new(),
+            None,
+        );
+        assert!(matches!(
+            result,
+            Err(PipelineError::TransactionFailed { record: 3, .. })
+        ));
+
+        // TSV inputs have the same columns
+        let tsv = "type\tclient\ttx\tamount\ndeposit\t3\t1\t2.5\n";
+        let mut report = Vec::new();
+        process(
+            &mut TransactionProcessor::new(),
+            [Input::new("c.tsv", InputFormat::Tsv, tsv.as_bytes())],
+            &PipelineOptions::new(),
+            &mut report,
+            None,
+        )
+        .unwrap();
+        assert!(String::from_utf8(report)
+            .unwrap()
+            .ends_with("\n3,2.5,0,2.5,false\n"));
+    }
+
+    #[test]
+    fn test_process_sources() {
+        let csv = "type,client,tx,amount\n\
+                   deposit,1,1,5.0,extra\n\
+                   deposit,1,2,oops\n";
+        let jsonl = r#"{"type":"withdrawal","client":1,"tx":3,"amount":"1.5"}"#;
+        // A feed in some other format, already decoded
+        let feed = IterSource::new(vec![
+            Ok(Transaction::new(
+                2,
+                1,
+                TransactionType::Deposit {
+                    amount: CurrencyAmount::from_str("3").unwrap(),
+                },
+            )),
+            Err(SourceError::new("bad message")),
+        ]);
+
+        let mut transaction_processor = TransactionProcessor::new();
+        let mut report = Vec::new();
+        let mut rejects: Vec<RejectedRow> = Vec::new();
+        let sources: [(&str, Box<dyn TransactionSource>); 3] = [
+            ("a.csv", Box::new(CSVReader::new(csv.as_bytes()))),
+            ("b.jsonl", Box::new(JsonlReader::new(jsonl.as_bytes()))),
+            ("feed", Box::new(feed)),
+        ];
+        let summary = process_sources(
+            &mut transaction_processor,
+            sources,
+            &PipelineOptions::new(),
+            &mut report,
+            Some(&mut rejects),
+        )
+        .unwrap();
+
+        assert_eq!(3, summary.files_read());
+        assert_eq!(3, summary.transactions_applied());
+        assert_eq!(2, summary.total_rejects());
+        assert_eq!(1, summary.warnings());
+        assert_eq!(
+            "client,available,held,total,locked\n\
+             1,3.5,0,3.5,false\n\
+             2,3,0,3,false\n",
+            String::from_utf8(report).unwrap()
+        );
+        assert_eq!(["deposit", "1", "2", "oops"], rejects[0].fields());
+        assert!(rejects[1].is_parse_error());
+        assert!(rejects[1].fields().is_empty());
+    }
+
+    #[test]
+    fn test_spawn() {
+        let (sender, handle) = spawn(TransactionProcessor::new(), 2, Vec::<RejectedRow>::new());
+
+        // Transactions are parsed on other threads, and sent to the stage
+        thread::scope(|scope| {
+            for client in 1..=3 {
+                let sender = sender.clone();
+                scope.spawn(move || {
+                    let csv = format!(
+                        "type,client,tx,amount\n\
+                         deposit,{client},1,5\n\
+                         withdrawal,{client},2,2\n\
+                         withdrawal,{client},3,4\n"
+                    );
+                    for transaction in CSVReader::new(csv.as_bytes()).read() {
+                        sender.send(transaction.unwrap()).unwrap();
+                    }
+                });
+            }
+        });
+        drop(sender);
+
+        let (transaction_processor, summary, rejects) = handle.finish();
+        assert_eq!(6, summary.transactions_applied());
+        assert_eq!(3, summary.total_rejects());
+        assert!(rejects
+            .iter()
+            .all(|row| row.code() == "INSUFFICIENT_FUNDS" && row.fields()[2] == "3"));
+        let report = transaction_processor.generate_report_as_vec();
+        assert_eq!(3, report.len());
+        assert_eq!(CurrencyAmount::from_str("3").unwrap(), report[2].available);
+    }
+}
@@ -0,0 +1,261 @@
+use std::fmt::{Display, Formatter};
+
+use crate::numeric::{CurrencyAmount, CurrencyAmountParseError};
+use crate::{ClientId, TransactionId, TransactionType};
+
+/// The types generated from `proto/transaction_processor.proto`.
+#[allow(missing_docs, clippy::all, clippy::pedantic)]
+mod generated {
+    include!("proto/transaction_processor.rs");
+}
+
+pub use generated::{ReportEntry, Transaction, TransactionType as ProtoTransactionType};
+
+/// The gRPC service generated from `proto/transaction_processor_service.proto`,
+/// with a client (`transaction_processor_service_client`) and a server trait
+/// to implement (`transaction_processor_service_server`).
+#[cfg(feature = "grpc")]
+#[allow(missing_docs, clippy::all, clippy::pedantic)]
+pub mod service {
+    include!("proto/transaction_processor.service.rs");
+}
+
+/// Error returned when a protobuf message is not a valid [`crate::Transaction`]
+/// or [`crate::ReportEntry`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum ProtoConvertError {
+    /// The client ID does not fit in a [`ClientId`].
+    InvalidClient(u64),
+    /// The transaction ID does not fit in a [`TransactionId`].
+    InvalidTransaction(u64),
+    /// The transaction type is unspecified or unknown.
+    InvalidTransactionType(i32),
+    /// A deposit or withdrawal did not specify an amount.
+    MissingAmount,
+    /// An amount could not be parsed.
+    InvalidAmount(CurrencyAmountParseError),
+}
+
+impl Display for ProtoConvertError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&match self {
+            ProtoConvertError::InvalidClient(client) => format!("Invalid client ID {}", client),
+            ProtoConvertError::InvalidTransaction(tx) => format!("Invalid transaction ID {}", tx),
+            ProtoConvertError::InvalidTransactionType(transaction_type) => {
+                format!("Invalid transaction type {}", transaction_type)
+            }
+            ProtoConvertError::MissingAmount => "Missing amount".to_string(),
+            ProtoConvertError::InvalidAmount(err) => format!("Invalid amount: {}", err),
+        })
+    }
+}
+
+fn parse_client(client: u64) -> Result<ClientId, ProtoConvertError> {
+    ClientId::try_from(client).map_err(|_| ProtoConvertError::InvalidClient(client))
+}
+
+fn parse_tx(tx: u64) -> Result<TransactionId, ProtoConvertError> {
+    TransactionId::try_from(tx).map_err(|_| ProtoConvertError::InvalidTransaction(tx))
+}
+
+fn parse_amount(amount: &str) -> Result<CurrencyAmount, ProtoConvertError> {
+    amount.parse().map_err(ProtoConvertError::InvalidAmount)
+}
+
+impl TryFrom<Transaction> for crate::Transaction {
+    type Error = ProtoConvertError;
+
+    fn try_from(value: Transaction) -> Result<Self, Self::Error> {
+        let amount = || {
+            value
+                .amount
+                .as_deref()
+                .ok_or(ProtoConvertError::MissingAmount)
+                .and_then(parse_amount)
+        };
+
+        let transaction_type = match ProtoTransactionType::try_from(value.r#type) {
+            Ok(ProtoTransactionType::Deposit) => TransactionType::Deposit { amount: amount()? },
+            Ok(ProtoTransactionType::Withdrawal) => {
+                TransactionType::Withdrawal { amount: amount()? }
+            }
+            Ok(ProtoTransactionType::Dispute) => TransactionType::Dispute,
+            Ok(ProtoTransactionType::Resolve) => TransactionType::Resolve,
+            Ok(ProtoTransactionType::Chargeback) => TransactionType::Chargeback,
+            Ok(ProtoTransactionType::Unspecified) | Err(_) => {
+                return Err(ProtoConvertError::InvalidTransactionType(value.r#type))
+            }
+        };
+
+        Ok(Self::new(
+            parse_client(value.client)?,
+            parse_tx(value.tx)?,
+            transaction_type,
+        ))
+    }
+}
+
+impl From<crate::Transaction> for Transaction {
+    // The IDs are already `u64` with the `wide-ids` feature
+    #[cfg_attr(feature = "wide-ids", allow(clippy::useless_conversion))]
+    fn from(value: crate::Transaction) -> Self {
+        let (transaction_type, amount) = match value.transaction_type {
+            TransactionType::Deposit { amount } => (ProtoTransactionType::Deposit, Some(amount)),
+            TransactionType::Withdrawal { amount } => {
+                (ProtoTransactionType::Withdrawal, Some(amount))
+            }
+            TransactionType::Dispute => (ProtoTransactionType::Dispute, None),
+            TransactionType::Resolve => (ProtoTransactionType::Resolve, None),
+            TransactionType::Chargeback => (ProtoTransactionType::Chargeback, None),
+        };
+
+        Self {
+            r#type: transaction_type.into(),
+            client: value.client.into(),
+            tx: value.tx.into(),
+            amount: amount.map(|amount| amount.to_string()),
+        }
+    }
+}
+
+impl TryFrom<ReportEntry> for crate::ReportEntry {
+    type Error = ProtoConvertError;
+
+    fn try_from(value: ReportEntry) -> Result<Self, Self::Error> {
+        Ok(Self {
+            client: parse_client(value.client)?,
+            client_key: None,
+            available: parse_amount(&value.available)?,
+            held: parse_amount(&value.held)?,
+            total: parse_amount(&value.total)?,
+            locked: value.locked,
+        })
+    }
+}
+
+impl From<crate::ReportEntry> for ReportEntry {
+    // The IDs are already `u64` with the `wide-ids` feature
+    #[cfg_attr(feature = "wide-ids", allow(clippy::useless_conversion))]
+    fn from(value: crate::ReportEntry) -> Self {
+        Self {
+            client: value.client.into(),
+            available: value.available.to_string(),
+            held: value.held.to_string(),
+            total: value.total.to_string(),
+            locked: value.locked,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+
+    use prost::Message;
+
+    use crate::numeric::CurrencyAmount;
+    use crate::proto::{self, ProtoConvertError, ProtoTransactionType};
+    use crate::report::{ReportFilter, ReportOrder};
+    use crate::{Transaction, TransactionProcessor, TransactionType};
+
+    #[test]
+    fn test_transaction_round_trip() {
+        for transaction in [
+            Transaction::new(
+                1,
+                2,
+                TransactionType::Deposit {
+                    amount: CurrencyAmount::from_str("1.5").unwrap(),
+                },
+            ),
+            Transaction::new(
+                3,
+                4,
+                TransactionType::Withdrawal {
+                    amount: CurrencyAmount::from_str("0.0001").unwrap(),
+                },
+            ),
+            Transaction::new(5, 6, TransactionType::Dispute),
+            Transaction::new(5, 6, TransactionType::Resolve),
+            Transaction::new(5, 6, TransactionType::Chargeback),
+        ] {
+            let encoded = proto::Transaction::from(transaction.clone()).encode_to_vec();
+            let decoded = proto::Transaction::decode(encoded.as_slice()).unwrap();
+
+            assert_eq!(Ok(transaction), Transaction::try_from(decoded));
+        }
+    }
+
+    #[test]
+    fn test_invalid_transactions() {
+        let deposit = proto::Transaction {
+            r#type: ProtoTransactionType::Deposit.into(),
+            client: 1,
+            tx: 1,
+            amount: None,
+        };
+
+        assert_eq!(
+            Err(ProtoConvertError::MissingAmount),
+            Transaction::try_from(deposit.clone())
+        );
+        // Every `u64` is a valid ID with the `wide-ids` feature
+        #[cfg(not(feature = "wide-ids"))]
+        {
+            assert_eq!(
+                Err(ProtoConvertError::InvalidClient(70_000)),
+                Transaction::try_from(proto::Transaction {
+                    client: 70_000,
+                    amount: Some("1".to_string()),
+                    ..deposit.clone()
+                })
+            );
+            assert_eq!(
+                Err(ProtoConvertError::InvalidTransaction(1 << 32)),
+                Transaction::try_from(proto::Transaction {
+                    tx: 1 << 32,
+                    amount: Some("1".to_string()),
+                    ..deposit.clone()
+                })
+            );
+        }
+        assert_eq!(
+            Err(ProtoConvertError::InvalidTransactionType(0)),
+            Transaction::try_from(proto::Transaction {
+                r#type: ProtoTransactionType::Unspecified.into(),
+                ..deposit.clone()
+            })
+        );
+        assert_eq!(
+            Err(ProtoConvertError::InvalidTransactionType(42)),
+            Transaction::try_from(proto::Transaction {
+                r#type: 42,
+                ..deposit
+            })
+        );
+    }
+
+    #[test]
+    fn test_report_entry_round_trip() {
+        let mut transaction_processor = TransactionProcessor::new();
+        transaction_processor
+            .transact(&Transaction::new(
+                1,
+                1,
+                TransactionType::Deposit {
+                    amount: CurrencyAmount::from_str("2.50").unwrap(),
+                },
+            ))
+            .unwrap();
+
+        for entry in
+            transaction_processor.generate_report(&ReportFilter::new(), ReportOrder::ClientId)
+        {
+            let message = proto::ReportEntry::from(entry.clone());
+            assert_eq!("2.50", message.available);
+
+            let decoded = proto::ReportEntry::decode(message.encode_to_vec().as_slice()).unwrap();
+            assert_eq!(Ok(entry), crate::ReportEntry::try_from(decoded));
+        }
+    }
+}
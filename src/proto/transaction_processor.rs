@@ -0,0 +1,74 @@
+// This file is @generated by prost-build.
+/// A single transaction, using the same fields as the CSV input format.
+#[derive(Clone, PartialEq, Eq, Hash, ::prost::Message)]
+pub struct Transaction {
+    #[prost(enumeration = "TransactionType", tag = "1")]
+    pub r#type: i32,
+    /// Client ID, between 0 and 65535 (or any 64-bit value with the wide-ids
+    /// feature). The IDs were uint32, which is encoded the same for values
+    /// which fit.
+    #[prost(uint64, tag = "2")]
+    pub client: u64,
+    /// Transaction ID, between 0 and 4294967295 (or any 64-bit value with the
+    /// wide-ids feature).
+    #[prost(uint64, tag = "3")]
+    pub tx: u64,
+    /// Decimal amount, required for deposits and withdrawals. Stored as a
+    /// string so that no precision is lost.
+    #[prost(string, optional, tag = "4")]
+    pub amount: ::core::option::Option<::prost::alloc::string::String>,
+}
+/// The state of a single client account, as output in the report. Amounts
+/// are decimal strings.
+#[derive(Clone, PartialEq, Eq, Hash, ::prost::Message)]
+pub struct ReportEntry {
+    /// Client ID, as in Transaction.
+    #[prost(uint64, tag = "1")]
+    pub client: u64,
+    #[prost(string, tag = "2")]
+    pub available: ::prost::alloc::string::String,
+    #[prost(string, tag = "3")]
+    pub held: ::prost::alloc::string::String,
+    #[prost(string, tag = "4")]
+    pub total: ::prost::alloc::string::String,
+    #[prost(bool, tag = "5")]
+    pub locked: bool,
+}
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+#[repr(i32)]
+pub enum TransactionType {
+    Unspecified = 0,
+    Deposit = 1,
+    Withdrawal = 2,
+    Dispute = 3,
+    Resolve = 4,
+    Chargeback = 5,
+}
+impl TransactionType {
+    /// String value of the enum field names used in the ProtoBuf definition.
+    ///
+    /// The values are not transformed in any way and thus are considered stable
+    /// (if the ProtoBuf definition does not change) and safe for programmatic use.
+    pub fn as_str_name(&self) -> &'static str {
+        match self {
+            Self::Unspecified => "TRANSACTION_TYPE_UNSPECIFIED",
+            Self::Deposit => "TRANSACTION_TYPE_DEPOSIT",
+            Self::Withdrawal => "TRANSACTION_TYPE_WITHDRAWAL",
+            Self::Dispute => "TRANSACTION_TYPE_DISPUTE",
+            Self::Resolve => "TRANSACTION_TYPE_RESOLVE",
+            Self::Chargeback => "TRANSACTION_TYPE_CHARGEBACK",
+        }
+    }
+    /// Creates an enum from field names used in the ProtoBuf definition.
+    pub fn from_str_name(value: &str) -> ::core::option::Option<Self> {
+        match value {
+            "TRANSACTION_TYPE_UNSPECIFIED" => Some(Self::Unspecified),
+            "TRANSACTION_TYPE_DEPOSIT" => Some(Self::Deposit),
+            "TRANSACTION_TYPE_WITHDRAWAL" => Some(Self::Withdrawal),
+            "TRANSACTION_TYPE_DISPUTE" => Some(Self::Dispute),
+            "TRANSACTION_TYPE_RESOLVE" => Some(Self::Resolve),
+            "TRANSACTION_TYPE_CHARGEBACK" => Some(Self::Chargeback),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,164 @@
+use std::collections::HashMap;
+use std::sync::{Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+use transaction_processor::ClientId;
+
+/// The number of transactions each client may submit, as a token bucket:
+/// up to `burst` at once, refilled at `per_second`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RateLimit {
+    pub per_second: f64,
+    pub burst: f64,
+}
+
+/// The longest wait returned by [`RateLimiter::acquire`], however slowly the
+/// buckets are refilled.
+const MAX_WAIT: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// The fewest buckets which are swept for full ones (see [`Buckets`]).
+const MIN_SWEEP: usize = 1024;
+
+/// Enforces a [`RateLimit`] separately for each client.
+///
+/// A bucket is kept for every client seen recently. A bucket which has been
+/// refilled is the same as a new one, so full buckets are dropped whenever
+/// the number kept has doubled, which bounds the memory used by the number
+/// of clients submitting within the time to refill a bucket.
+pub struct RateLimiter {
+    limit: RateLimit,
+    buckets: Mutex<Buckets>,
+}
+
+struct Buckets {
+    by_client: HashMap<ClientId, Bucket>,
+    /// The number of buckets at which the full ones are next dropped.
+    sweep_at: usize,
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl Bucket {
+    /// The tokens in the bucket at `now`, after refilling it since it was
+    /// last updated.
+    fn tokens_at(&self, now: Instant, limit: RateLimit) -> f64 {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        (self.tokens + elapsed * limit.per_second).min(limit.burst)
+    }
+}
+
+impl RateLimiter {
+    pub fn new(limit: RateLimit) -> Self {
+        Self {
+            limit,
+            buckets: Mutex::new(Buckets {
+                by_client: HashMap::new(),
+                sweep_at: MIN_SWEEP,
+            }),
+        }
+    }
+
+    /// Takes a token for a transaction from `client` at `now`. If the
+    /// client's bucket is empty, returns how long until the next token is
+    /// available instead.
+    pub fn acquire(&self, client: ClientId, now: Instant) -> Result<(), Duration> {
+        let mut buckets = self.buckets.lock().unwrap_or_else(PoisonError::into_inner);
+        let Buckets {
+            by_client,
+            sweep_at,
+        } = &mut *buckets;
+        if by_client.len() >= *sweep_at {
+            by_client.retain(|_, bucket| bucket.tokens_at(now, self.limit) < self.limit.burst);
+            *sweep_at = (by_client.len() * 2).max(MIN_SWEEP);
+        }
+
+        let bucket = by_client.entry(client).or_insert(Bucket {
+            tokens: self.limit.burst,
+            updated: now,
+        });
+        bucket.tokens = bucket.tokens_at(now, self.limit);
+        bucket.updated = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            // A tiny rate could otherwise give a wait too long to represent
+            let wait = (1.0 - bucket.tokens) / self.limit.per_second;
+            Err(Duration::try_from_secs_f64(wait).map_or(MAX_WAIT, |wait| wait.min(MAX_WAIT)))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, Instant};
+
+    use transaction_processor::ClientId;
+
+    use crate::rate_limit::{RateLimit, RateLimiter, MAX_WAIT, MIN_SWEEP};
+
+    #[test]
+    fn test_token_bucket() {
+        let limiter = RateLimiter::new(RateLimit {
+            per_second: 2.0,
+            burst: 3.0,
+        });
+        let start = Instant::now();
+
+        // The bucket starts full
+        for _ in 0..3 {
+            assert_eq!(Ok(()), limiter.acquire(1, start));
+        }
+        assert_eq!(Err(Duration::from_millis(500)), limiter.acquire(1, start));
+
+        // Other clients have their own buckets
+        assert_eq!(Ok(()), limiter.acquire(2, start));
+
+        // Tokens are refilled over time, up to the burst
+        let later = start + Duration::from_millis(500);
+        assert_eq!(Ok(()), limiter.acquire(1, later));
+        assert!(limiter.acquire(1, later).is_err());
+
+        let much_later = later + Duration::from_secs(60);
+        for _ in 0..3 {
+            assert_eq!(Ok(()), limiter.acquire(1, much_later));
+        }
+        assert!(limiter.acquire(1, much_later).is_err());
+    }
+
+    #[test]
+    fn test_tiny_rate() {
+        let limiter = RateLimiter::new(RateLimit {
+            per_second: 1e-300,
+            burst: 1.0,
+        });
+        let now = Instant::now();
+        assert_eq!(Ok(()), limiter.acquire(1, now));
+        assert_eq!(Err(MAX_WAIT), limiter.acquire(1, now));
+    }
+
+    #[test]
+    fn test_full_buckets_dropped() {
+        let limiter = RateLimiter::new(RateLimit {
+            per_second: 1.0,
+            burst: 1.0,
+        });
+        let start = Instant::now();
+        let clients = ClientId::try_from(MIN_SWEEP).unwrap();
+        for client in 0..clients {
+            assert_eq!(Ok(()), limiter.acquire(client, start));
+        }
+
+        // Once they have been refilled, only the newest bucket is kept, and
+        // the others act as new
+        let later = start + Duration::from_secs(1);
+        assert_eq!(Ok(()), limiter.acquire(clients, later));
+        assert_eq!(1, limiter.buckets.lock().unwrap().by_client.len());
+        assert_eq!(Ok(()), limiter.acquire(0, later));
+        assert!(limiter.acquire(0, later).is_err());
+    }
+}
@@ -0,0 +1,202 @@
+use std::io;
+
+use crate::summary::PARSE_ERROR_CODE;
+use crate::{Transaction, TransactionError, TransactionType};
+
+/// A row which failed to be read or applied, and so was skipped.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct RejectedRow {
+    code: &'static str,
+    reason: String,
+    fields: Vec<String>,
+    transaction: Option<Transaction>,
+    error: Option<TransactionError>,
+}
+
+impl RejectedRow {
+    /// A row which could not be read as a transaction, for `reason`.
+    #[cfg(all(feature = "csv", feature = "jsonl"))]
+    pub(crate) fn parse_error(reason: String, fields: Vec<String>) -> Self {
+        Self {
+            code: PARSE_ERROR_CODE,
+            reason,
+            fields,
+            transaction: None,
+            error: None,
+        }
+    }
+
+    /// A transaction which failed to be applied with `error`. If `fields` is
+    /// empty, the transaction's fields in the standard layout are used.
+    pub(crate) fn transaction_failed(
+        fields: Vec<String>,
+        transaction: Transaction,
+        error: TransactionError,
+    ) -> Self {
+        let fields = if fields.is_empty() {
+            transaction_fields(&transaction)
+        } else {
+            fields
+        };
+        Self {
+            code: error.code(),
+            reason: error.to_string(),
+            fields,
+            transaction: Some(transaction),
+            error: Some(error),
+        }
+    }
+
+    /// The stable code for why the row was rejected: the
+    /// [`TransactionError::code`] of the error applying it, or
+    /// [`PARSE_ERROR_CODE`] if it could not be read.
+    #[must_use]
+    pub fn code(&self) -> &'static str {
+        self.code
+    }
+
+    /// Why the row was rejected.
+    #[must_use]
+    pub fn reason(&self) -> &str {
+        &self.reason
+    }
+
+    /// The fields of the row verbatim, or of the transaction in the standard
+    /// layout if the row's fields aren't known (e.g. for binary formats).
+    #[must_use]
+    pub fn fields(&self) -> &[String] {
+        &self.fields
+    }
+
+    /// The transaction which failed to be applied, or `None` if the row
+    /// could not be read.
+    #[must_use]
+    pub fn transaction(&self) -> Option<&Transaction> {
+        self.transaction.as_ref()
+    }
+
+    /// The error applying the transaction, or `None` if the row could not be
+    /// read.
+    #[must_use]
+    pub fn error(&self) -> Option<&TransactionError> {
+        self.error.as_ref()
+    }
+
+    /// True if the row could not be read as a transaction, rather than the
+    /// transaction failing to be applied.
+    #[must_use]
+    pub fn is_parse_error(&self) -> bool {
+        self.code == PARSE_ERROR_CODE
+    }
+}
+
+/// Receives every row which fails to be read or applied, e.g. to quarantine
+/// the failed transactions for someone to inspect. Sinks are given to
+/// [`crate::TransactionProcessor::transact_all`] and the [`crate::pipeline`]
+/// functions.
+///
+/// Implemented for [`crate::io::csv::RejectWriter`], which writes the rows
+/// to a CSV file, for `Vec<RejectedRow>`, which collects them in memory, and
+/// for closures taking a [`RejectedRow`].
+pub trait RejectSink {
+    /// Receives a rejected row.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the row could not be stored. The row is still
+    /// skipped, and the error is logged.
+    fn reject(&mut self, row: RejectedRow) -> io::Result<()>;
+
+    /// Flushes any buffered rows, once no more will be rejected.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the rows could not be stored.
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl RejectSink for Vec<RejectedRow> {
+    fn reject(&mut self, row: RejectedRow) -> io::Result<()> {
+        self.push(row);
+        Ok(())
+    }
+}
+
+impl<F: FnMut(RejectedRow)> RejectSink for F {
+    fn reject(&mut self, row: RejectedRow) -> io::Result<()> {
+        self(row);
+        Ok(())
+    }
+}
+
+/// The fields of a transaction in the standard layout,
+/// `type,client,tx,amount`.
+fn transaction_fields(transaction: &Transaction) -> Vec<String> {
+    let amount = match transaction.transaction_type() {
+        TransactionType::Deposit { amount } | TransactionType::Withdrawal { amount } => {
+            amount.to_string()
+        }
+        _ => String::new(),
+    };
+    vec![
+        transaction.transaction_type().name().to_string(),
+        transaction.client().to_string(),
+        transaction.tx().to_string(),
+        amount,
+    ]
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+
+    use crate::numeric::CurrencyAmount;
+    use crate::reject::RejectedRow;
+    use crate::{Transaction, TransactionError, TransactionProcessor, TransactionType};
+
+    #[test]
+    fn test_transact_all() {
+        let amount = |amount| CurrencyAmount::from_str(amount).unwrap();
+        let transactions = vec![
+            Transaction::new(
+                1,
+                1,
+                TransactionType::Deposit {
+                    amount: amount("5"),
+                },
+            ),
+            Transaction::new(
+                1,
+                2,
+                TransactionType::Withdrawal {
+                    amount: amount("7.5"),
+                },
+            ),
+            Transaction::new(1, 3, TransactionType::Dispute),
+        ];
+
+        let mut rejects: Vec<RejectedRow> = Vec::new();
+        let summary = TransactionProcessor::new().transact_all(transactions.clone(), &mut rejects);
+        assert_eq!(1, summary.transactions_applied());
+        assert_eq!(2, summary.total_rejects());
+
+        assert_eq!(2, rejects.len());
+        assert_eq!("INSUFFICIENT_FUNDS", rejects[0].code());
+        assert_eq!(["withdrawal", "1", "2", "7.5"], rejects[0].fields());
+        assert_eq!(Some(&transactions[1]), rejects[0].transaction());
+        assert_eq!(
+            Some(&TransactionError::TransactionDoesNotExist { client: 1, tx: 3 }),
+            rejects[1].error()
+        );
+        assert_eq!(["dispute", "1", "3", ""], rejects[1].fields());
+        assert!(!rejects[1].is_parse_error());
+
+        // Closures are sinks too
+        let mut codes = Vec::new();
+        TransactionProcessor::new()
+            .transact_all(transactions, &mut |row: RejectedRow| codes.push(row.code()));
+        assert_eq!(vec!["INSUFFICIENT_FUNDS", "TX_NOT_FOUND"], codes);
+    }
+}
@@ -0,0 +1,1250 @@
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+#[cfg(feature = "std")]
+use core::cmp::Reverse;
+use core::fmt::{Display, Formatter};
+use core::ops::RangeInclusive;
+use core::str::FromStr;
+#[cfg(feature = "std")]
+use std::io;
+
+#[cfg(feature = "serde")]
+use serde::ser::SerializeStruct;
+#[cfg(feature = "serde")]
+use serde::{Serialize, Serializer};
+
+use crate::numeric::{CurrencyAmount, CurrencyError};
+#[cfg(feature = "std")]
+use crate::TransactionProcessor;
+use crate::{ClientId, LockReason, ReportEntry, Transaction, TransactionId, TransactionType};
+
+/// A set of client IDs, written as a comma-separated list of IDs and
+/// inclusive ranges, e.g. `1,5-10`.
+///
+/// The ranges are kept sorted and merged, so that a report can look up each
+/// range in turn and still list clients in ID order.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ClientSet {
+    ranges: Vec<RangeInclusive<ClientId>>,
+}
+
+impl ClientSet {
+    /// Creates a set containing all the clients in `ranges`, which may
+    /// overlap and be in any order.
+    #[must_use]
+    pub fn new(ranges: impl IntoIterator<Item = RangeInclusive<ClientId>>) -> Self {
+        let mut sorted = ranges
+            .into_iter()
+            .filter(|range| !range.is_empty())
+            .collect::<Vec<_>>();
+        sorted.sort_by_key(|range| *range.start());
+
+        let mut ranges: Vec<RangeInclusive<ClientId>> = Vec::with_capacity(sorted.len());
+        for range in sorted {
+            match ranges.last_mut() {
+                Some(last) if range.start().saturating_sub(1) <= *last.end() => {
+                    *last = *last.start()..=*last.end().max(range.end());
+                }
+                _ => ranges.push(range),
+            }
+        }
+        Self { ranges }
+    }
+
+    /// Whether `client` is in the set.
+    #[must_use]
+    pub fn contains(&self, client: ClientId) -> bool {
+        self.ranges.iter().any(|range| range.contains(&client))
+    }
+
+    /// The disjoint ranges making up the set, in ascending order.
+    #[must_use]
+    pub fn ranges(&self) -> &[RangeInclusive<ClientId>] {
+        &self.ranges
+    }
+}
+
+/// Error occurring when parsing a string to a [`ClientSet`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum ClientSetParseError {
+    /// An item in the list is not a client ID or a range of client IDs.
+    InvalidClient,
+    /// A range ends before it starts.
+    InvalidRange,
+}
+
+impl Display for ClientSetParseError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        f.write_str(match self {
+            ClientSetParseError::InvalidClient => "Invalid client ID",
+            ClientSetParseError::InvalidRange => "Range ends before it starts",
+        })
+    }
+}
+
+impl FromStr for ClientSet {
+    type Err = ClientSetParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parse_client = |client: &str| {
+            ClientId::from_str(client.trim()).map_err(|_| ClientSetParseError::InvalidClient)
+        };
+
+        let ranges = s
+            .split(',')
+            .map(|item| {
+                let (start, end) = match item.split_once('-') {
+                    Some((start, end)) => (parse_client(start)?, parse_client(end)?),
+                    None => {
+                        let client = parse_client(item)?;
+                        (client, client)
+                    }
+                };
+                if end < start {
+                    return Err(ClientSetParseError::InvalidRange);
+                }
+                Ok(start..=end)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self::new(ranges))
+    }
+}
+
+/// The order of the accounts in a report (see
+/// `TransactionProcessor::generate_report`).
+///
+/// Accounts which compare equal are listed in client ID order.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum ReportOrder {
+    /// Ascending client ID. The report is generated as the accounts are
+    /// iterated, without being collected first.
+    #[default]
+    ClientId,
+    /// Descending total funds, i.e. the largest exposure first.
+    TotalDescending,
+    /// Descending held funds.
+    HeldDescending,
+}
+
+impl ReportOrder {
+    /// Sorts `entries`, which are in client ID order, into this order.
+    #[cfg(feature = "std")]
+    pub(crate) fn sort(self, entries: &mut [ReportEntry]) {
+        // The sort is stable, so ties stay in client ID order
+        match self {
+            ReportOrder::ClientId => {}
+            ReportOrder::TotalDescending => entries.sort_by_key(|entry| Reverse(entry.total())),
+            ReportOrder::HeldDescending => entries.sort_by_key(|entry| Reverse(entry.held())),
+        }
+    }
+}
+
+/// Which accounts are included in a report (see
+/// `TransactionProcessor::generate_report`). The default filter includes
+/// every account.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct ReportFilter {
+    clients: Option<ClientSet>,
+    locked_only: bool,
+    non_zero_only: bool,
+}
+
+impl ReportFilter {
+    /// Every possible client, for a filter without a client set.
+    #[cfg(feature = "std")]
+    const ALL_CLIENTS: &'static [RangeInclusive<ClientId>] = &[ClientId::MIN..=ClientId::MAX];
+
+    /// Creates a filter which includes every account.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only include the accounts of the clients in `clients`.
+    #[must_use]
+    pub fn with_clients(self, clients: ClientSet) -> Self {
+        Self {
+            clients: Some(clients),
+            ..self
+        }
+    }
+
+    /// Whether to only include locked accounts.
+    #[must_use]
+    pub fn with_locked_only(self, locked_only: bool) -> Self {
+        Self {
+            locked_only,
+            ..self
+        }
+    }
+
+    /// Whether to only include accounts with non-zero available or held
+    /// funds.
+    #[must_use]
+    pub fn with_non_zero_only(self, non_zero_only: bool) -> Self {
+        Self {
+            non_zero_only,
+            ..self
+        }
+    }
+
+    /// The ranges of client IDs which may be included, in ascending order.
+    #[cfg(feature = "std")]
+    pub(crate) fn client_ranges(&self) -> &[RangeInclusive<ClientId>] {
+        match &self.clients {
+            Some(clients) => clients.ranges(),
+            None => Self::ALL_CLIENTS,
+        }
+    }
+
+    /// Whether `entry` is included by this filter.
+    #[must_use]
+    pub fn matches(&self, entry: &ReportEntry) -> bool {
+        self.clients
+            .as_ref()
+            .is_none_or(|clients| clients.contains(entry.client()))
+            && (!self.locked_only || entry.locked())
+            && (!self.non_zero_only
+                || entry.available() != CurrencyAmount::ZERO
+                || entry.held() != CurrencyAmount::ZERO)
+    }
+}
+
+/// A point in the history of a processor's accounts, which advances with
+/// every change to them. Used to generate a report of only the accounts
+/// which have changed since an earlier report (see
+/// `TransactionProcessor::generate_delta_report`).
+///
+/// Versions are only meaningful for the processor they came from, and are
+/// not kept by snapshots.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct ReportVersion(u64);
+
+impl ReportVersion {
+    /// The version before any changes, so a delta report since it includes
+    /// every account.
+    pub const INITIAL: Self = Self(0);
+
+    #[cfg(feature = "std")]
+    pub(crate) const fn new(version: u64) -> Self {
+        Self(version)
+    }
+
+    /// The number of changes made to the accounts before this version.
+    #[must_use]
+    pub const fn get(self) -> u64 {
+        self.0
+    }
+}
+
+/// The grand totals of the accounts in a report (see
+/// `TransactionProcessor::report_totals`).
+#[cfg_attr(feature = "serde", derive(Serialize))]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ReportTotals {
+    /// The number of accounts.
+    accounts: usize,
+    /// The number of locked accounts.
+    locked_accounts: usize,
+    /// The sum of the available funds.
+    available: CurrencyAmount,
+    /// The sum of the held funds.
+    held: CurrencyAmount,
+    /// The sum of the total funds.
+    total: CurrencyAmount,
+}
+
+impl ReportTotals {
+    /// Creates empty totals, of no accounts.
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            accounts: 0,
+            locked_accounts: 0,
+            available: CurrencyAmount::ZERO,
+            held: CurrencyAmount::ZERO,
+            total: CurrencyAmount::ZERO,
+        }
+    }
+
+    /// Adds the account in `entry` to the totals.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if any sum overflows, in which case the totals are
+    /// left unchanged.
+    pub fn add(&mut self, entry: &ReportEntry) -> Result<(), CurrencyError> {
+        let mut totals = self.clone();
+        totals.accounts += 1;
+        totals.locked_accounts += usize::from(entry.locked());
+        totals.available.checked_add_assign(entry.available())?;
+        totals.held.checked_add_assign(entry.held())?;
+        totals.total.checked_add_assign(entry.total())?;
+        *self = totals;
+        Ok(())
+    }
+
+    /// Returns these totals with all amounts formatted to exactly `scale`
+    /// decimal places (see [`CurrencyAmount::with_scale`]).
+    #[must_use]
+    pub fn with_scale(self, scale: u32) -> Self {
+        Self {
+            available: self.available.with_scale(scale),
+            held: self.held.with_scale(scale),
+            total: self.total.with_scale(scale),
+            ..self
+        }
+    }
+
+    /// The number of accounts.
+    #[must_use]
+    pub const fn accounts(&self) -> usize {
+        self.accounts
+    }
+
+    /// The number of locked accounts.
+    #[must_use]
+    pub const fn locked_accounts(&self) -> usize {
+        self.locked_accounts
+    }
+
+    /// The sum of the available funds.
+    #[must_use]
+    pub const fn available(&self) -> CurrencyAmount {
+        self.available
+    }
+
+    /// The sum of the held funds.
+    #[must_use]
+    pub const fn held(&self) -> CurrencyAmount {
+        self.held
+    }
+
+    /// The sum of the total funds.
+    #[must_use]
+    pub const fn total(&self) -> CurrencyAmount {
+        self.total
+    }
+}
+
+impl Default for ReportTotals {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A description of an active dispute in a generated dispute report (see
+/// `TransactionProcessor::generate_dispute_report`).
+#[cfg_attr(feature = "serde", derive(Serialize))]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct DisputeEntry {
+    /// The ID of the client.
+    client: ClientId,
+    /// The ID of the disputed transaction.
+    tx: TransactionId,
+    /// The amount held by the dispute: positive for a deposit, negative for
+    /// a withdrawal.
+    held: CurrencyAmount,
+}
+
+impl DisputeEntry {
+    #[cfg(feature = "std")]
+    pub(crate) const fn new(client: ClientId, tx: TransactionId, held: CurrencyAmount) -> Self {
+        Self { client, tx, held }
+    }
+
+    /// Returns this entry with the amount formatted to exactly `scale`
+    /// decimal places (see [`CurrencyAmount::with_scale`]).
+    #[must_use]
+    pub fn with_scale(self, scale: u32) -> Self {
+        Self {
+            held: self.held.with_scale(scale),
+            ..self
+        }
+    }
+
+    /// The ID of the client.
+    #[must_use]
+    pub const fn client(&self) -> ClientId {
+        self.client
+    }
+
+    /// The ID of the disputed transaction.
+    #[must_use]
+    pub const fn tx(&self) -> TransactionId {
+        self.tx
+    }
+
+    /// The amount held by the dispute.
+    #[must_use]
+    pub const fn held(&self) -> CurrencyAmount {
+        self.held
+    }
+}
+
+/// A description of a locked account in a generated locked-accounts report
+/// (see `TransactionProcessor::generate_locked_report`).
+///
+/// It is serialized with the fields `client`, `reason` (the
+/// [`LockReason::name`]) and `tx` (the [`LockReason::tx`], if any).
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct LockedEntry {
+    /// The ID of the client.
+    client: ClientId,
+    /// Why the account is locked.
+    reason: LockReason,
+}
+
+impl LockedEntry {
+    #[cfg(feature = "std")]
+    pub(crate) const fn new(client: ClientId, reason: LockReason) -> Self {
+        Self { client, reason }
+    }
+
+    /// The ID of the client.
+    #[must_use]
+    pub const fn client(&self) -> ClientId {
+        self.client
+    }
+
+    /// Why the account is locked.
+    #[must_use]
+    pub const fn reason(&self) -> LockReason {
+        self.reason
+    }
+}
+
+#[cfg(feature = "serde")]
+impl Serialize for LockedEntry {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut state = serializer.serialize_struct("LockedEntry", 3)?;
+        state.serialize_field("client", &self.client)?;
+        state.serialize_field("reason", self.reason.name())?;
+        state.serialize_field("tx", &self.reason.tx())?;
+        state.end()
+    }
+}
+
+/// What may have taken an account's available funds below zero, in a
+/// negative-balance audit report (see
+/// `TransactionProcessor::generate_negative_balance_report`).
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum NegativeBalanceCause {
+    /// An active dispute of a deposit, whose funds are held although they
+    /// had already been withdrawn.
+    Dispute {
+        /// The ID of the disputed deposit.
+        tx: TransactionId,
+        /// The amount held by the dispute.
+        held: CurrencyAmount,
+    },
+    /// A chargeback of a disputed deposit, which locked the account.
+    Chargeback(TransactionId),
+}
+
+impl NegativeBalanceCause {
+    /// The name of the cause, as used in the report (e.g. `"dispute"`).
+    #[must_use]
+    pub const fn name(&self) -> &'static str {
+        match self {
+            NegativeBalanceCause::Dispute { .. } => "dispute",
+            NegativeBalanceCause::Chargeback(_) => "chargeback",
+        }
+    }
+
+    /// The ID of the disputed (or charged back) transaction.
+    #[must_use]
+    pub const fn tx(&self) -> TransactionId {
+        match self {
+            NegativeBalanceCause::Dispute { tx, .. } | NegativeBalanceCause::Chargeback(tx) => *tx,
+        }
+    }
+}
+
+/// An account with negative available funds in a negative-balance audit
+/// report (see `TransactionProcessor::generate_negative_balance_report`),
+/// with a dispute or chargeback which may have caused it, if any.
+///
+/// It is serialized with the fields `client`, `available`, `held`, `cause`
+/// (the [`NegativeBalanceCause::name`]), `tx` (the
+/// [`NegativeBalanceCause::tx`]) and `disputed` (the amount held by a
+/// dispute), which are empty without a cause.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct NegativeBalanceEntry {
+    /// The ID of the client.
+    client: ClientId,
+    /// The amount of available funds, which is negative.
+    available: CurrencyAmount,
+    /// The amount of held funds.
+    held: CurrencyAmount,
+    /// What may have caused it, if known.
+    cause: Option<NegativeBalanceCause>,
+}
+
+impl NegativeBalanceEntry {
+    #[cfg(feature = "std")]
+    pub(crate) const fn new(
+        client: ClientId,
+        available: CurrencyAmount,
+        held: CurrencyAmount,
+        cause: Option<NegativeBalanceCause>,
+    ) -> Self {
+        Self {
+            client,
+            available,
+            held,
+            cause,
+        }
+    }
+
+    /// Returns this entry with all amounts formatted to exactly `scale`
+    /// decimal places (see [`CurrencyAmount::with_scale`]).
+    #[must_use]
+    pub fn with_scale(self, scale: u32) -> Self {
+        Self {
+            available: self.available.with_scale(scale),
+            held: self.held.with_scale(scale),
+            cause: self.cause.map(|cause| match cause {
+                NegativeBalanceCause::Dispute { tx, held } => NegativeBalanceCause::Dispute {
+                    tx,
+                    held: held.with_scale(scale),
+                },
+                chargeback => chargeback,
+            }),
+            ..self
+        }
+    }
+
+    /// The ID of the client.
+    #[must_use]
+    pub const fn client(&self) -> ClientId {
+        self.client
+    }
+
+    /// The amount of available funds.
+    #[must_use]
+    pub const fn available(&self) -> CurrencyAmount {
+        self.available
+    }
+
+    /// The amount of held funds.
+    #[must_use]
+    pub const fn held(&self) -> CurrencyAmount {
+        self.held
+    }
+
+    /// The dispute or chargeback which may have caused it, if any.
+    #[must_use]
+    pub const fn cause(&self) -> Option<NegativeBalanceCause> {
+        self.cause
+    }
+}
+
+#[cfg(feature = "serde")]
+impl Serialize for NegativeBalanceEntry {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let disputed = match self.cause {
+            Some(NegativeBalanceCause::Dispute { held, .. }) => Some(held),
+            _ => None,
+        };
+        let mut state = serializer.serialize_struct("NegativeBalanceEntry", 6)?;
+        state.serialize_field("client", &self.client)?;
+        state.serialize_field("available", &self.available)?;
+        state.serialize_field("held", &self.held)?;
+        state.serialize_field(
+            "cause",
+            &self.cause.as_ref().map(NegativeBalanceCause::name),
+        )?;
+        state.serialize_field("tx", &self.cause.as_ref().map(NegativeBalanceCause::tx))?;
+        state.serialize_field("disputed", &disputed)?;
+        state.end()
+    }
+}
+
+/// An applied transaction in a client's statement (see [`Statement`]) or a
+/// journal of all transactions, with the account's balances once it was
+/// applied.
+#[cfg_attr(feature = "serde", derive(Serialize))]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct StatementEntry {
+    /// The ID of the client.
+    client: ClientId,
+    /// The ID of the transaction.
+    tx: TransactionId,
+    /// The type of the transaction (see [`TransactionType::name`]).
+    #[cfg_attr(feature = "serde", serde(rename = "type"))]
+    transaction_type: &'static str,
+    /// The amount of a deposit or withdrawal.
+    amount: Option<CurrencyAmount>,
+    /// The available funds after the transaction.
+    available: CurrencyAmount,
+    /// The held funds after the transaction.
+    held: CurrencyAmount,
+    /// The total funds after the transaction.
+    total: CurrencyAmount,
+    /// Whether the account is locked after the transaction.
+    locked: bool,
+}
+
+impl StatementEntry {
+    /// Creates the entry for `transaction`, given the account's state
+    /// (`account`) once it was applied.
+    #[must_use]
+    pub fn new(transaction: &Transaction, account: &ReportEntry) -> Self {
+        let amount = match transaction.transaction_type() {
+            TransactionType::Deposit { amount } | TransactionType::Withdrawal { amount } => {
+                Some(*amount)
+            }
+            TransactionType::Dispute | TransactionType::Resolve | TransactionType::Chargeback => {
+                None
+            }
+        };
+
+        Self {
+            client: account.client(),
+            tx: transaction.tx(),
+            transaction_type: transaction.transaction_type().name(),
+            amount,
+            available: account.available(),
+            held: account.held(),
+            total: account.total(),
+            locked: account.locked(),
+        }
+    }
+
+    /// Returns this entry with all amounts formatted to exactly `scale`
+    /// decimal places (see [`CurrencyAmount::with_scale`]).
+    #[must_use]
+    pub fn with_scale(self, scale: u32) -> Self {
+        Self {
+            amount: self.amount.map(|amount| amount.with_scale(scale)),
+            available: self.available.with_scale(scale),
+            held: self.held.with_scale(scale),
+            total: self.total.with_scale(scale),
+            ..self
+        }
+    }
+
+    /// The ID of the client.
+    #[must_use]
+    pub const fn client(&self) -> ClientId {
+        self.client
+    }
+
+    /// The ID of the transaction.
+    #[must_use]
+    pub const fn tx(&self) -> TransactionId {
+        self.tx
+    }
+
+    /// The type of the transaction (see [`TransactionType::name`]).
+    #[must_use]
+    pub const fn transaction_type(&self) -> &'static str {
+        self.transaction_type
+    }
+
+    /// The amount of a deposit or withdrawal.
+    #[must_use]
+    pub const fn amount(&self) -> Option<CurrencyAmount> {
+        self.amount
+    }
+
+    /// The available funds after the transaction.
+    #[must_use]
+    pub const fn available(&self) -> CurrencyAmount {
+        self.available
+    }
+
+    /// The held funds after the transaction.
+    #[must_use]
+    pub const fn held(&self) -> CurrencyAmount {
+        self.held
+    }
+
+    /// The total funds after the transaction.
+    #[must_use]
+    pub const fn total(&self) -> CurrencyAmount {
+        self.total
+    }
+
+    /// Whether the account is locked after the transaction.
+    #[must_use]
+    pub const fn locked(&self) -> bool {
+        self.locked
+    }
+}
+
+/// A chronological listing of the transactions applied to one client's
+/// account, with the running balances.
+///
+/// A processor only keeps what it needs to apply later transactions, so the
+/// statement must be recorded as the transactions are applied: call
+/// [`Statement::record`] after each successful
+/// [`TransactionProcessor::transact`].
+#[cfg(feature = "std")]
+#[derive(Clone, Debug)]
+pub struct Statement {
+    client: ClientId,
+    entries: Vec<StatementEntry>,
+}
+
+#[cfg(feature = "std")]
+impl Statement {
+    /// Creates an empty statement for `client`.
+    #[must_use]
+    pub fn new(client: ClientId) -> Self {
+        Self {
+            client,
+            entries: Vec::new(),
+        }
+    }
+
+    /// The client the statement is for.
+    #[must_use]
+    pub const fn client(&self) -> ClientId {
+        self.client
+    }
+
+    /// Records `transaction`, which has just been applied to
+    /// `transaction_processor`, if it is for this statement's client.
+    ///
+    /// A transaction whose account is excluded from reports (because its
+    /// total overflows) is not recorded.
+    pub fn record(
+        &mut self,
+        transaction_processor: &TransactionProcessor,
+        transaction: &Transaction,
+    ) {
+        if transaction_processor.transaction_client(transaction) != Some(self.client) {
+            return;
+        }
+        if let Some(account) = transaction_processor.account_report(self.client) {
+            self.entries
+                .push(StatementEntry::new(transaction, &account));
+        }
+    }
+
+    /// The recorded transactions, in the order they were applied.
+    #[must_use]
+    pub fn entries(&self) -> &[StatementEntry] {
+        &self.entries
+    }
+}
+
+/// The number of entries a [`ReportWriter`] writes between flushes, by
+/// default.
+#[cfg(feature = "std")]
+pub const DEFAULT_FLUSH_INTERVAL: usize = 1024;
+
+/// Receives the entries of a report as they are generated, so that a report
+/// can be written without holding all of it in memory. Sinks are given to a
+/// [`ReportWriter`].
+///
+/// Implemented for [`crate::io::csv::CSVWriter`] and
+/// [`crate::io::jsonl::JsonlWriter`], which serialize the entries as CSV
+/// rows and JSON lines, and for `Vec<ReportEntry>`, which collects them in
+/// memory.
+#[cfg(feature = "std")]
+pub trait ReportSink {
+    /// Receives the next entry of the report.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the entry could not be written.
+    fn write_entry(&mut self, entry: ReportEntry) -> io::Result<()>;
+
+    /// Writes out any buffered entries.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the entries could not be written.
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(feature = "std")]
+impl ReportSink for Vec<ReportEntry> {
+    fn write_entry(&mut self, entry: ReportEntry) -> io::Result<()> {
+        self.push(entry);
+        Ok(())
+    }
+}
+
+#[cfg(feature = "std")]
+impl<S: ReportSink + ?Sized> ReportSink for &mut S {
+    fn write_entry(&mut self, entry: ReportEntry) -> io::Result<()> {
+        (**self).write_entry(entry)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        (**self).flush()
+    }
+}
+
+/// Writes report entries to a [`ReportSink`] one at a time, as they are
+/// generated, flushing the sink every `flush_interval` entries. Memory use
+/// doesn't grow with the size of the report, and a reader of the output
+/// sees it grow as it is written rather than all at the end.
+#[cfg(feature = "std")]
+#[derive(Debug)]
+pub struct ReportWriter<S: ReportSink> {
+    sink: S,
+    flush_interval: usize,
+    scale: Option<u32>,
+    /// The entries written since the last flush.
+    unflushed: usize,
+    written: usize,
+}
+
+#[cfg(feature = "std")]
+impl<S: ReportSink> ReportWriter<S> {
+    /// Creates a writer to `sink`, which flushes it every
+    /// [`DEFAULT_FLUSH_INTERVAL`] entries.
+    pub fn new(sink: S) -> Self {
+        Self {
+            sink,
+            flush_interval: DEFAULT_FLUSH_INTERVAL,
+            scale: None,
+            unflushed: 0,
+            written: 0,
+        }
+    }
+
+    /// Sets the number of entries written between flushes (at least one).
+    #[must_use]
+    pub fn with_flush_interval(mut self, flush_interval: usize) -> Self {
+        self.flush_interval = flush_interval.max(1);
+        self
+    }
+
+    /// Sets the number of decimal places the amounts are written with (see
+    /// [`ReportEntry::with_scale`]), if not their own.
+    #[must_use]
+    pub fn with_scale(mut self, scale: Option<u32>) -> Self {
+        self.scale = scale;
+        self
+    }
+
+    /// The number of entries written so far.
+    #[must_use]
+    pub const fn written(&self) -> usize {
+        self.written
+    }
+
+    /// Writes an entry to the sink, flushing it if `flush_interval` entries
+    /// have been written since the last flush.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the entry could not be written, or the sink could
+    /// not be flushed.
+    pub fn write(&mut self, entry: ReportEntry) -> io::Result<()> {
+        let entry = match self.scale {
+            Some(scale) => entry.with_scale(scale),
+            None => entry,
+        };
+        self.sink.write_entry(entry)?;
+        self.written += 1;
+        self.unflushed += 1;
+        if self.unflushed >= self.flush_interval {
+            self.unflushed = 0;
+            self.sink.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Flushes the sink, and returns it.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the sink could not be flushed.
+    pub fn finish(mut self) -> io::Result<S> {
+        self.sink.flush()?;
+        Ok(self.sink)
+    }
+}
+
+/// How a client's account differs between two reports.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ReportChange {
+    /// The account is only in the second report.
+    Added,
+    /// The account is only in the first report.
+    Removed,
+    /// The account's available or held funds, or whether it is locked,
+    /// differ.
+    Changed,
+}
+
+impl ReportChange {
+    /// The name of the change, as used in a diff (e.g. `"changed"`).
+    #[must_use]
+    pub const fn name(&self) -> &'static str {
+        match self {
+            ReportChange::Added => "added",
+            ReportChange::Removed => "removed",
+            ReportChange::Changed => "changed",
+        }
+    }
+}
+
+/// A client whose account differs between two reports (see
+/// [`diff_reports`]), with its entry in each report it is in.
+///
+/// It is serialized with the fields `client`, `change` (the
+/// [`ReportChange::name`]), and `available_before`, `available_after`,
+/// `held_before`, `held_after`, `locked_before` and `locked_after`, which are
+/// empty for the report the account is not in.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ReportDiff {
+    /// The ID of the client.
+    client: ClientId,
+    /// The entry in the first report, if any.
+    before: Option<ReportEntry>,
+    /// The entry in the second report, if any.
+    after: Option<ReportEntry>,
+}
+
+impl ReportDiff {
+    /// Returns this diff with all amounts formatted to exactly `scale`
+    /// decimal places (see [`CurrencyAmount::with_scale`]).
+    #[must_use]
+    pub fn with_scale(self, scale: u32) -> Self {
+        Self {
+            before: self.before.map(|entry| entry.with_scale(scale)),
+            after: self.after.map(|entry| entry.with_scale(scale)),
+            ..self
+        }
+    }
+
+    /// The ID of the client.
+    #[must_use]
+    pub const fn client(&self) -> ClientId {
+        self.client
+    }
+
+    /// How the account differs.
+    #[must_use]
+    pub const fn change(&self) -> ReportChange {
+        match (&self.before, &self.after) {
+            (None, _) => ReportChange::Added,
+            (_, None) => ReportChange::Removed,
+            (Some(_), Some(_)) => ReportChange::Changed,
+        }
+    }
+
+    /// The account's entry in the first report, if it is in it.
+    #[must_use]
+    pub const fn before(&self) -> Option<&ReportEntry> {
+        self.before.as_ref()
+    }
+
+    /// The account's entry in the second report, if it is in it.
+    #[must_use]
+    pub const fn after(&self) -> Option<&ReportEntry> {
+        self.after.as_ref()
+    }
+}
+
+#[cfg(feature = "serde")]
+impl Serialize for ReportDiff {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let (before, after) = (self.before.as_ref(), self.after.as_ref());
+        let mut state = serializer.serialize_struct("ReportDiff", 8)?;
+        state.serialize_field("client", &self.client)?;
+        state.serialize_field("change", self.change().name())?;
+        state.serialize_field("available_before", &before.map(ReportEntry::available))?;
+        state.serialize_field("available_after", &after.map(ReportEntry::available))?;
+        state.serialize_field("held_before", &before.map(ReportEntry::held))?;
+        state.serialize_field("held_after", &after.map(ReportEntry::held))?;
+        state.serialize_field("locked_before", &before.map(ReportEntry::locked))?;
+        state.serialize_field("locked_after", &after.map(ReportEntry::locked))?;
+        state.end()
+    }
+}
+
+/// Compares two reports, returning every client whose available or held
+/// funds, or whether the account is locked, differ (or who is only in one
+/// report), in client ID order.
+///
+/// Amounts are compared by value, so reports written with different
+/// `--output-scale`s match. The totals are not compared, as they follow from
+/// the available and held funds. If a client appears more than once in a
+/// report, the last entry is used.
+pub fn diff_reports(
+    before: impl IntoIterator<Item = ReportEntry>,
+    after: impl IntoIterator<Item = ReportEntry>,
+) -> Vec<ReportDiff> {
+    let by_client = |report: &mut dyn Iterator<Item = ReportEntry>| {
+        report
+            .map(|entry| (entry.client, entry))
+            .collect::<BTreeMap<_, _>>()
+    };
+    let mut before = by_client(&mut before.into_iter());
+    let after = by_client(&mut after.into_iter());
+
+    let mut diffs = Vec::new();
+    for (client, after) in after {
+        let before = before.remove(&client);
+        let unchanged = before.as_ref().is_some_and(|before| {
+            before.available == after.available
+                && before.held == after.held
+                && before.locked == after.locked
+        });
+        if !unchanged {
+            diffs.push(ReportDiff {
+                client,
+                before,
+                after: Some(after),
+            });
+        }
+    }
+    diffs.extend(before.into_iter().map(|(client, before)| ReportDiff {
+        client,
+        before: Some(before),
+        after: None,
+    }));
+    diffs.sort_by_key(ReportDiff::client);
+    diffs
+}
+
+#[cfg(test)]
+mod test {
+    use core::str::FromStr;
+
+    use crate::numeric::{CurrencyAmount, CurrencyError};
+    use crate::report::{
+        diff_reports, ClientSet, ClientSetParseError, ReportChange, ReportFilter, ReportOrder,
+        ReportSink, ReportTotals, ReportWriter, Statement,
+    };
+    use crate::{ReportEntry, Transaction, TransactionProcessor, TransactionType};
+
+    #[test]
+    fn test_parse_client_set() {
+        let clients = ClientSet::from_str("7, 1,5-10,3-3,11").unwrap();
+        assert_eq!(&[1..=1, 3..=3, 5..=11], clients.ranges());
+        assert!(clients.contains(8));
+        assert!(!clients.contains(2));
+        assert!(!clients.contains(12));
+
+        assert_eq!(
+            Err(ClientSetParseError::InvalidRange),
+            ClientSet::from_str("10-5")
+        );
+        for invalid in ["", "1,", "a", "1-", "-1", "1-2-3", "18446744073709551616"] {
+            assert_eq!(
+                Err(ClientSetParseError::InvalidClient),
+                ClientSet::from_str(invalid),
+                "{}",
+                invalid
+            );
+        }
+    }
+
+    #[test]
+    fn test_report_totals() {
+        let entry = |client, available: &str, locked| {
+            let available = CurrencyAmount::from_str(available).unwrap();
+            ReportEntry {
+                client,
+                client_key: None,
+                available,
+                held: CurrencyAmount::ZERO,
+                total: available,
+                locked,
+            }
+        };
+
+        let mut totals = ReportTotals::new();
+        totals.add(&entry(1, "1.5", false)).unwrap();
+        totals.add(&entry(2, "-0.25", true)).unwrap();
+        assert_eq!(2, totals.accounts());
+        assert_eq!(1, totals.locked_accounts());
+        assert_eq!(CurrencyAmount::from_str("1.25").unwrap(), totals.total());
+
+        // Overflowing sums are an error, and leave the totals unchanged
+        let mut max = entry(3, "0", false);
+        max.available = CurrencyAmount::MAX;
+        assert_eq!(Err(CurrencyError::OutOfBounds), totals.add(&max));
+        assert_eq!(2, totals.accounts());
+        assert_eq!(
+            CurrencyAmount::from_str("1.25").unwrap(),
+            totals.available()
+        );
+    }
+
+    #[test]
+    fn test_statement() {
+        let deposit = |client, tx, amount| {
+            Transaction::new(
+                client,
+                tx,
+                TransactionType::Deposit {
+                    amount: CurrencyAmount::from_str(amount).unwrap(),
+                },
+            )
+        };
+
+        let mut transaction_processor = TransactionProcessor::new();
+        let mut statement = Statement::new(1);
+        for transaction in [
+            deposit(1, 1, "5"),
+            deposit(2, 2, "7"),
+            Transaction::new(1, 1, TransactionType::Dispute),
+            Transaction::new(1, 1, TransactionType::Resolve),
+        ] {
+            transaction_processor.transact(&transaction).unwrap();
+            statement.record(&transaction_processor, &transaction);
+        }
+
+        let balances = statement
+            .entries()
+            .iter()
+            .map(|entry| {
+                (
+                    entry.transaction_type(),
+                    entry.available().to_string(),
+                    entry.held().to_string(),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                ("deposit", "5".to_string(), "0".to_string()),
+                ("dispute", "0".to_string(), "5".to_string()),
+                ("resolve", "5".to_string(), "0".to_string())
+            ],
+            balances
+        );
+        assert_eq!(
+            Some(CurrencyAmount::from_str("5").unwrap()),
+            statement.entries()[0].amount()
+        );
+        assert_eq!(None, statement.entries()[1].amount());
+    }
+
+    #[test]
+    fn test_report_writer() {
+        /// Counts the entries written before each flush.
+        #[derive(Default)]
+        struct Flushes {
+            pending: usize,
+            flushes: Vec<usize>,
+        }
+
+        impl ReportSink for Flushes {
+            fn write_entry(&mut self, _: ReportEntry) -> std::io::Result<()> {
+                self.pending += 1;
+                Ok(())
+            }
+
+            fn flush(&mut self) -> std::io::Result<()> {
+                self.flushes.push(std::mem::take(&mut self.pending));
+                Ok(())
+            }
+        }
+
+        let mut transaction_processor = TransactionProcessor::new();
+        for client in 1..=5 {
+            transaction_processor
+                .transact(&Transaction::new(
+                    client,
+                    1,
+                    TransactionType::Deposit {
+                        amount: CurrencyAmount::from_str("1.5").unwrap(),
+                    },
+                ))
+                .unwrap();
+        }
+
+        // The sink is flushed every interval, and at the end
+        let mut writer = ReportWriter::new(Flushes::default()).with_flush_interval(2);
+        for entry in transaction_processor.generate_report_as_vec() {
+            writer.write(entry).unwrap();
+        }
+        assert_eq!(5, writer.written());
+        assert_eq!(vec![2, 2, 1], writer.finish().unwrap().flushes);
+
+        let mut writer = ReportWriter::new(Vec::new()).with_scale(Some(2));
+        for entry in transaction_processor.generate_report_as_vec() {
+            writer.write(entry).unwrap();
+        }
+        let entries = writer.finish().unwrap();
+        assert_eq!(5, entries.len());
+        assert_eq!("1.50", entries[4].available.to_string());
+    }
+
+    #[test]
+    fn test_diff_reports() {
+        let deposit = |client, tx, amount| {
+            Transaction::new(
+                client,
+                tx,
+                TransactionType::Deposit {
+                    amount: CurrencyAmount::from_str(amount).unwrap(),
+                },
+            )
+        };
+
+        let mut transaction_processor = TransactionProcessor::new();
+        for transaction in [deposit(1, 1, "1"), deposit(2, 2, "2"), deposit(3, 3, "3")] {
+            transaction_processor.transact(&transaction).unwrap();
+        }
+        let before = transaction_processor
+            .generate_report(&ReportFilter::new(), ReportOrder::ClientId)
+            .map(|entry| entry.with_scale(2))
+            .collect::<Vec<_>>();
+
+        for transaction in [
+            deposit(4, 4, "4"),
+            Transaction::new(2, 2, TransactionType::Dispute),
+            Transaction::new(3, 3, TransactionType::Dispute),
+            Transaction::new(3, 3, TransactionType::Chargeback),
+        ] {
+            transaction_processor.transact(&transaction).unwrap();
+        }
+        let after = transaction_processor
+            .generate_report(&ReportFilter::new(), ReportOrder::ClientId)
+            .filter(|entry| entry.client() != 1)
+            .collect::<Vec<_>>();
+
+        // Client 1 is only in the first report, and the scales differ
+        let diffs = diff_reports(before.clone(), after.clone());
+        assert_eq!(
+            vec![
+                (1, ReportChange::Removed),
+                (2, ReportChange::Changed),
+                (3, ReportChange::Changed),
+                (4, ReportChange::Added),
+            ],
+            diffs
+                .iter()
+                .map(|diff| (diff.client(), diff.change()))
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(Some(&before[1]), diffs[1].before());
+        assert_eq!(Some(&after[0]), diffs[1].after());
+        assert!(diffs[2].after().unwrap().locked());
+        assert_eq!(None, diffs[3].before());
+
+        assert!(diff_reports(before.clone(), before.clone()).is_empty());
+        assert_eq!(
+            vec![(1, ReportChange::Added)],
+            transaction_processor
+                .diff_report(after)
+                .iter()
+                .map(|diff| (diff.client(), diff.change()))
+                .collect::<Vec<_>>()
+        );
+    }
+}
@@ -0,0 +1,308 @@
+use std::collections::VecDeque;
+
+use crate::{ClientId, Transaction, TransactionError, TransactionProcessor};
+
+/// The number of attempts made by [`RetryQueue::default`].
+pub const DEFAULT_MAX_ATTEMPTS: u32 = 10;
+
+/// The number of transactions parked by [`RetryQueue::default`].
+pub const DEFAULT_CAPACITY: usize = 10_000;
+
+/// A transaction which a [`RetryQueue`] has finished with, and the result of
+/// its last attempt.
+pub type Settled = (Transaction, Result<(), TransactionError>);
+
+/// A transaction waiting to be retried.
+#[derive(Clone, Debug)]
+struct Parked {
+    transaction: Transaction,
+    /// The number of attempts made so far.
+    attempts: u32,
+    /// The error of the last attempt.
+    error: TransactionError,
+}
+
+/// Parks the transactions which fail with a transient error (see
+/// [`TransactionError::is_transient`]), such as a dispute which arrives
+/// before its deposit, and retries them once a later transaction for the
+/// same client has been applied. Useful for streams which don't guarantee
+/// the order of transactions, where such a transaction would otherwise be
+/// dropped.
+///
+/// Parked transactions are retried in the order they arrived, so a dispute
+/// and its resolve which both arrive early are applied in order. A
+/// transaction is given up, and settled with its last error, once it has
+/// been attempted `max_attempts` times, or if it is the oldest parked
+/// transaction when `capacity` more are parked. Any others still parked at
+/// the end of the stream are given up by [`RetryQueue::finish`].
+#[derive(Clone, Debug)]
+pub struct RetryQueue {
+    max_attempts: u32,
+    capacity: usize,
+    /// The parked transactions, oldest first.
+    parked: VecDeque<Parked>,
+}
+
+impl Default for RetryQueue {
+    fn default() -> Self {
+        Self::new(DEFAULT_MAX_ATTEMPTS)
+    }
+}
+
+impl RetryQueue {
+    /// Creates an empty queue which attempts each transaction up to
+    /// `max_attempts` times, including when it arrives, and parks up to
+    /// [`DEFAULT_CAPACITY`] transactions. With one attempt (or zero),
+    /// transactions are never retried.
+    #[must_use]
+    pub fn new(max_attempts: u32) -> Self {
+        Self {
+            max_attempts: max_attempts.max(1),
+            capacity: DEFAULT_CAPACITY,
+            parked: VecDeque::new(),
+        }
+    }
+
+    /// Sets the maximum number of transactions parked at once.
+    #[must_use]
+    pub fn with_capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity;
+        self
+    }
+
+    /// The maximum number of attempts of each transaction.
+    #[must_use]
+    pub const fn max_attempts(&self) -> u32 {
+        self.max_attempts
+    }
+
+    /// The maximum number of transactions parked at once.
+    #[must_use]
+    pub const fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// The number of transactions currently parked.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.parked.len()
+    }
+
+    /// True if no transactions are parked.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.parked.is_empty()
+    }
+
+    /// The transactions currently parked, oldest first.
+    pub fn parked(&self) -> impl Iterator<Item = &Transaction> + '_ {
+        self.parked.iter().map(|parked| &parked.transaction)
+    }
+
+    /// Applies `transaction` to `transaction_processor`, parking it if it
+    /// fails with a transient error, and retrying the parked transactions of
+    /// its client if it is applied.
+    ///
+    /// Returns the transactions settled as a result, in the order they were
+    /// settled: `transaction` itself unless it was parked, any parked
+    /// transactions which were retried and applied or given up, and any
+    /// given up to make room for it.
+    pub fn transact(
+        &mut self,
+        transaction_processor: &mut TransactionProcessor,
+        transaction: Transaction,
+    ) -> Vec<Settled> {
+        self.transact_with(
+            transaction_processor,
+            transaction,
+            TransactionProcessor::transact,
+        )
+    }
+
+    /// Like [`RetryQueue::transact`], but applies the transactions with
+    /// `apply`, which must call [`TransactionProcessor::transact`] (e.g. to
+    /// also post them to a [`crate::ledger::Ledger`]).
+    pub fn transact_with(
+        &mut self,
+        transaction_processor: &mut TransactionProcessor,
+        transaction: Transaction,
+        mut apply: impl FnMut(&mut TransactionProcessor, &Transaction) -> Result<(), TransactionError>,
+    ) -> Vec<Settled> {
+        let mut settled = Vec::new();
+        match apply(transaction_processor, &transaction) {
+            Err(error) if error.is_transient() && self.max_attempts > 1 && self.capacity > 0 => {
+                if self.parked.len() == self.capacity {
+                    settled.extend(self.parked.pop_front().map(Parked::give_up));
+                }
+                self.parked.push_back(Parked {
+                    transaction,
+                    attempts: 1,
+                    error,
+                });
+            }
+            result => {
+                let client = transaction.client();
+                let applied = result.is_ok();
+                settled.push((transaction, result));
+                if applied {
+                    self.retry(client, transaction_processor, &mut apply, &mut settled);
+                }
+            }
+        }
+        settled
+    }
+
+    /// Gives up every parked transaction, e.g. at the end of the stream,
+    /// returning them oldest first with their last errors.
+    pub fn finish(&mut self) -> Vec<Settled> {
+        self.parked.drain(..).map(Parked::give_up).collect()
+    }
+
+    /// Retries the parked transactions of `client` in the order they arrived,
+    /// for as long as any are applied, adding those settled to `settled`.
+    fn retry(
+        &mut self,
+        client: ClientId,
+        transaction_processor: &mut TransactionProcessor,
+        apply: &mut impl FnMut(&mut TransactionProcessor, &Transaction) -> Result<(), TransactionError>,
+        settled: &mut Vec<Settled>,
+    ) {
+        let mut applied = true;
+        while applied {
+            applied = false;
+            let mut index = 0;
+            while index < self.parked.len() {
+                let parked = &mut self.parked[index];
+                if parked.transaction.client() != client {
+                    index += 1;
+                    continue;
+                }
+
+                match apply(transaction_processor, &parked.transaction) {
+                    Err(error) if error.is_transient() => {
+                        parked.attempts += 1;
+                        parked.error = error;
+                        if parked.attempts < self.max_attempts {
+                            index += 1;
+                            continue;
+                        }
+                        settled.extend(self.parked.remove(index).map(Parked::give_up));
+                    }
+                    result => {
+                        applied |= result.is_ok();
+                        if let Some(parked) = self.parked.remove(index) {
+                            settled.push((parked.transaction, result));
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Parked {
+    fn give_up(self) -> Settled {
+        (self.transaction, Err(self.error))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+
+    use crate::numeric::CurrencyAmount;
+    use crate::retry::RetryQueue;
+    use crate::{Transaction, TransactionError, TransactionProcessor, TransactionType};
+
+    #[test]
+    fn test_retry_queue() {
+        let deposit = |client, tx| {
+            Transaction::new(
+                client,
+                tx,
+                TransactionType::Deposit {
+                    amount: CurrencyAmount::from_str("5").unwrap(),
+                },
+            )
+        };
+        let dispute = |client, tx| Transaction::new(client, tx, TransactionType::Dispute);
+        let resolve = |client, tx| Transaction::new(client, tx, TransactionType::Resolve);
+        let mut transaction_processor = TransactionProcessor::new();
+        let mut queue = RetryQueue::new(3);
+
+        // A dispute and resolve arriving before their deposit are parked,
+        // and applied in order once it arrives
+        assert!(queue
+            .transact(&mut transaction_processor, dispute(1, 1))
+            .is_empty());
+        assert!(queue
+            .transact(&mut transaction_processor, resolve(1, 1))
+            .is_empty());
+        assert_eq!(2, queue.len());
+        assert_eq!(
+            vec![
+                (deposit(1, 1), Ok(())),
+                (dispute(1, 1), Ok(())),
+                (resolve(1, 1), Ok(())),
+            ],
+            queue.transact(&mut transaction_processor, deposit(1, 1))
+        );
+        assert!(queue.is_empty());
+
+        // Permanent errors are settled straight away
+        assert_eq!(
+            vec![(
+                deposit(1, 1),
+                Err(TransactionError::TransactionAlreadyExists { client: 1, tx: 1 })
+            )],
+            queue.transact(&mut transaction_processor, deposit(1, 1))
+        );
+
+        // A transaction is only retried for its own client's transactions,
+        // and given up after the maximum number of attempts
+        queue.transact(&mut transaction_processor, dispute(2, 9));
+        queue.transact(&mut transaction_processor, deposit(1, 2));
+        assert_eq!(vec![&dispute(2, 9)], queue.parked().collect::<Vec<_>>());
+        assert_eq!(
+            vec![(deposit(2, 3), Ok(()))],
+            queue.transact(&mut transaction_processor, deposit(2, 3))
+        );
+        assert_eq!(
+            vec![
+                (deposit(2, 4), Ok(())),
+                (
+                    dispute(2, 9),
+                    Err(TransactionError::TransactionDoesNotExist { client: 2, tx: 9 })
+                ),
+            ],
+            queue.transact(&mut transaction_processor, deposit(2, 4))
+        );
+
+        // The oldest transaction is given up to make room, and the rest at
+        // the end of the stream
+        let mut queue = RetryQueue::new(3).with_capacity(1);
+        queue.transact(&mut transaction_processor, dispute(3, 1));
+        let settled = queue.transact(&mut transaction_processor, resolve(3, 1));
+        assert_eq!(
+            vec![dispute(3, 1)],
+            settled.into_iter().map(|s| s.0).collect::<Vec<_>>()
+        );
+        assert_eq!(
+            vec![(
+                resolve(3, 1),
+                Err(TransactionError::TransactionDoesNotExist { client: 3, tx: 1 })
+            )],
+            queue.finish()
+        );
+
+        // With one attempt, nothing is parked
+        let mut queue = RetryQueue::new(1);
+        assert_eq!(
+            1,
+            queue
+                .transact(&mut transaction_processor, dispute(4, 1))
+                .len()
+        );
+        assert!(queue.is_empty());
+    }
+}
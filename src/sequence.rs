@@ -0,0 +1,225 @@
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+
+/// A problem with the order of sequence numbers found by a [`Sequencer`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SequenceIssue {
+    /// No records with the sequence numbers `first..=last` arrived within the
+    /// reorder window.
+    Gap {
+        /// The first missing sequence number.
+        first: u64,
+        /// The last missing sequence number.
+        last: u64,
+    },
+    /// The record arrived after a later one, but within the reorder window,
+    /// so it was put back in order.
+    Reordered {
+        /// The record's sequence number.
+        sequence: u64,
+    },
+    /// The record arrived after records up to `expected` had been passed on,
+    /// so it was passed on out of order. It is either late (and part of an
+    /// earlier [`SequenceIssue::Gap`]), or a duplicate.
+    OutOfOrder {
+        /// The record's sequence number.
+        sequence: u64,
+        /// The next sequence number which was expected.
+        expected: u64,
+    },
+}
+
+impl Display for SequenceIssue {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SequenceIssue::Gap { first, last } if first == last => {
+                write!(f, "Sequence number {} is missing", first)
+            }
+            SequenceIssue::Gap { first, last } => {
+                write!(f, "Sequence numbers {} to {} are missing", first, last)
+            }
+            SequenceIssue::Reordered { sequence } => write!(
+                f,
+                "Sequence number {} arrived out of order, and was reordered",
+                sequence
+            ),
+            SequenceIssue::OutOfOrder { sequence, expected } => write!(
+                f,
+                "Sequence number {} arrived out of order (expected {})",
+                sequence, expected
+            ),
+        }
+    }
+}
+
+/// An item passed on by a [`Sequencer`], or a problem it found.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Sequenced<T> {
+    /// The next item, in sequence order where possible.
+    Item(T),
+    /// A gap or out-of-order record, reported when it is found.
+    Issue(SequenceIssue),
+}
+
+/// Detects gaps and out-of-order records in a feed whose records carry
+/// monotonically increasing sequence numbers (see
+/// [`crate::Transaction::sequence`]), optionally putting records back in
+/// order within a small window.
+///
+/// The feed is expected to start from the first sequence number pushed, and
+/// go up by one per record. With a reorder window of `n`, up to `n` records
+/// are held back while waiting for a missing one; once more are waiting, the
+/// missing records are reported as a [`SequenceIssue::Gap`] and the records
+/// after them are passed on. With no window, every gap is reported as soon
+/// as a later record arrives.
+#[derive(Clone, Debug)]
+pub struct Sequencer<T> {
+    window: usize,
+    /// The sequence number of the next record to pass on, once the first
+    /// record has arrived.
+    next: Option<u64>,
+    /// The records held back, waiting for `next`.
+    pending: BTreeMap<u64, T>,
+}
+
+impl<T> Sequencer<T> {
+    /// Creates a sequencer which holds back up to `window` records.
+    #[must_use]
+    pub fn new(window: usize) -> Self {
+        Self {
+            window,
+            next: None,
+            pending: BTreeMap::new(),
+        }
+    }
+
+    /// Adds the record with the specified sequence number, and returns the
+    /// records which can now be passed on, with any issues found, in order.
+    pub fn push(&mut self, sequence: u64, item: T) -> Vec<Sequenced<T>> {
+        let next = *self.next.get_or_insert(sequence);
+        let mut output = Vec::new();
+
+        if sequence < next || self.pending.contains_key(&sequence) {
+            output.push(Sequenced::Issue(SequenceIssue::OutOfOrder {
+                sequence,
+                expected: next,
+            }));
+            output.push(Sequenced::Item(item));
+            return output;
+        }
+
+        // A record which fills a gap arrived after the records held back
+        if sequence == next && !self.pending.is_empty() {
+            output.push(Sequenced::Issue(SequenceIssue::Reordered { sequence }));
+        }
+        self.pending.insert(sequence, item);
+        self.release(&mut output);
+        while self.pending.len() > self.window {
+            self.skip_gap(&mut output);
+        }
+        output
+    }
+
+    /// Passes on every record held back, reporting the gaps between them.
+    /// Called once the feed has ended.
+    pub fn finish(&mut self) -> Vec<Sequenced<T>> {
+        let mut output = Vec::new();
+        while !self.pending.is_empty() {
+            self.skip_gap(&mut output);
+        }
+        output
+    }
+
+    /// Passes on the held back records from `next` onwards, until the next
+    /// missing one.
+    fn release(&mut self, output: &mut Vec<Sequenced<T>>) {
+        let Some(next) = &mut self.next else {
+            return;
+        };
+        while let Some(item) = self.pending.remove(next) {
+            output.push(Sequenced::Item(item));
+            *next = next.saturating_add(1);
+        }
+    }
+
+    /// Reports the records missing before the first one held back as a gap,
+    /// and passes on the records after it.
+    fn skip_gap(&mut self, output: &mut Vec<Sequenced<T>>) {
+        let (Some(next), Some(first)) = (self.next, self.pending.keys().next().copied()) else {
+            return;
+        };
+        output.push(Sequenced::Issue(SequenceIssue::Gap {
+            first: next,
+            last: first - 1,
+        }));
+        self.next = Some(first);
+        self.release(output);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::sequence::{SequenceIssue, Sequenced, Sequencer};
+
+    fn run(window: usize, sequences: &[u64]) -> Vec<Sequenced<u64>> {
+        let mut sequencer = Sequencer::new(window);
+        let mut output = Vec::new();
+        for sequence in sequences {
+            output.extend(sequencer.push(*sequence, *sequence));
+        }
+        output.extend(sequencer.finish());
+        output
+    }
+
+    #[test]
+    fn test_sequencer() {
+        use Sequenced::{Issue, Item};
+
+        assert_eq!(vec![Item(5), Item(6), Item(7)], run(0, &[5, 6, 7]));
+
+        // Without a window, every gap and late record is reported as found
+        assert_eq!(
+            vec![
+                Item(1),
+                Issue(SequenceIssue::Gap { first: 2, last: 2 }),
+                Item(3),
+                Issue(SequenceIssue::OutOfOrder {
+                    sequence: 2,
+                    expected: 4
+                }),
+                Item(2),
+                Issue(SequenceIssue::OutOfOrder {
+                    sequence: 3,
+                    expected: 4
+                }),
+                Item(3),
+            ],
+            run(0, &[1, 3, 2, 3])
+        );
+
+        // With a window, records are put back in order
+        assert_eq!(
+            vec![
+                Item(1),
+                Issue(SequenceIssue::Reordered { sequence: 2 }),
+                Item(2),
+                Item(3),
+                Item(4),
+            ],
+            run(2, &[1, 3, 4, 2])
+        );
+
+        // A gap is reported once the window is full, or the feed ends
+        assert_eq!(
+            vec![
+                Item(1),
+                Issue(SequenceIssue::Gap { first: 2, last: 3 }),
+                Item(4),
+                Item(5),
+                Issue(SequenceIssue::Gap { first: 6, last: 6 }),
+                Item(7),
+            ],
+            run(1, &[1, 4, 5, 7])
+        );
+    }
+}
@@ -0,0 +1,861 @@
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError, TryLockError};
+use std::time::{Duration, Instant};
+
+use axum::body::Bytes;
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+use axum::extract::{Path, State};
+use axum::http::{header, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use serde::Serialize;
+use tokio::sync::broadcast;
+use transaction_processor::io::jsonl::JsonlReader;
+use transaction_processor::metrics::{self, Metrics};
+use transaction_processor::pipeline::TRANSACTION_LOG_TARGET;
+use transaction_processor::summary::PARSE_ERROR_CODE;
+use transaction_processor::tenant::{TenantId, TenantIdError, TenantProcessor};
+use transaction_processor::{
+    ClientId, ReportEntry, TransactionError, TransactionId, TransactionProcessor,
+};
+
+use crate::rate_limit::{RateLimit, RateLimiter};
+use crate::{shutdown_signal, ProcessOptions, TransactionProcessorCLIError};
+
+/// How many account updates can be queued for a `/stream` subscriber before
+/// it starts missing them.
+const UPDATE_CAPACITY: usize = 1024;
+
+/// The error code of transactions rejected for exceeding the rate limit.
+const RATE_LIMITED_CODE: &str = "RATE_LIMITED";
+
+/// How long `GET /readyz` waits for the shared processor to be free before
+/// reporting that the server isn't ready.
+const READINESS_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// How often `GET /readyz` checks whether the shared processor is free.
+const READINESS_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// The state shared by all requests.
+#[derive(Clone)]
+struct ServerState {
+    transaction_processor: Arc<Mutex<TransactionProcessor>>,
+    /// The isolated processors of the `/tenants/{tenant}` endpoints.
+    tenants: Arc<Mutex<TenantProcessor>>,
+    /// How amounts are parsed and formatted, and how transactions are
+    /// applied.
+    options: ProcessOptions,
+    /// The new state of each account changed by a transaction, in the order
+    /// the transactions were applied.
+    updates: broadcast::Sender<ReportEntry>,
+    metrics: Arc<Metrics>,
+    /// The last transaction applied, for `/readyz`.
+    last_applied: Arc<Mutex<Option<LastApplied>>>,
+    /// Whether the server is ready for requests, for `/readyz`: false until
+    /// the processor has been loaded and the server is listening, and once
+    /// it starts shutting down.
+    ready: Arc<AtomicBool>,
+    rate_limiter: Option<Arc<RateLimiter>>,
+}
+
+impl ServerState {
+    fn transaction_processor(&self) -> MutexGuard<'_, TransactionProcessor> {
+        self.transaction_processor
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn tenants(&self) -> MutexGuard<'_, TenantProcessor> {
+        self.tenants.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn scale(&self, entry: ReportEntry) -> ReportEntry {
+        match self.options.pipeline.output_scale() {
+            Some(scale) => entry.with_scale(scale),
+            None => entry,
+        }
+    }
+
+    fn last_applied(&self) -> MutexGuard<'_, Option<LastApplied>> {
+        self.last_applied
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Applies the transactions in `body`, in the JSON Lines input format,
+    /// to the shared processor (publishing an update for each one applied),
+    /// or to a tenant's.
+    fn apply(&self, tenant: Option<&TenantId>, body: &[u8]) -> SubmitResponse {
+        let received = Instant::now();
+        let mut last_applied = None;
+
+        // Parse everything before taking the lock
+        let transactions: Vec<_> = JsonlReader::new(body)
+            .with_amount_format(self.options.pipeline.amount_format())
+            .read()
+            .collect();
+
+        let mut response = SubmitResponse::default();
+        let (mut shared, mut tenants);
+        let transaction_processor = match tenant {
+            Some(tenant) => {
+                tenants = self.tenants();
+                tenants.tenant_mut(tenant)
+            }
+            None => {
+                shared = self.transaction_processor();
+                &mut *shared
+            }
+        };
+        for (index, transaction) in transactions.into_iter().enumerate() {
+            // The idempotency keys of each tenant are kept apart
+            let transaction = match (tenant, transaction) {
+                (Some(tenant), Ok(transaction)) => match transaction.idempotency_key() {
+                    Some(key) => {
+                        let key = format!("{}/{}", tenant, key);
+                        Ok(transaction.with_idempotency_key(key))
+                    }
+                    None => Ok(transaction),
+                },
+                (_, transaction) => transaction,
+            };
+            let result = match transaction {
+                Ok(transaction) if self.rate_limited(transaction.client(), &mut response) => Err((
+                    RATE_LIMITED_CODE,
+                    format!("Rate limit exceeded for client {}", transaction.client()),
+                )),
+                Ok(transaction) => {
+                    let start = Instant::now();
+                    let result = self.options.transact(transaction_processor, &transaction);
+                    self.metrics.record_transaction(
+                        transaction.transaction_type(),
+                        &result,
+                        start.elapsed(),
+                    );
+                    // A replay is neither applied nor rejected
+                    if let Err(TransactionError::Replayed(_)) = result {
+                        continue;
+                    }
+                    if result.is_ok() {
+                        last_applied = Some((transaction.client(), transaction.tx()));
+                    }
+                    result
+                        .map(|()| transaction.client())
+                        .map_err(|err| (err.code(), err.to_string()))
+                }
+                Err(err) => {
+                    self.metrics.record_parse_error();
+                    Err((PARSE_ERROR_CODE, err.to_string()))
+                }
+            };
+
+            match result {
+                Ok(client) => {
+                    response.applied += 1;
+                    // Sent while holding the lock, so that updates are in
+                    // order. This fails if there are no subscribers.
+                    if tenant.is_none() {
+                        if let Some(entry) = transaction_processor.account_report(client) {
+                            let _ = self.updates.send(self.scale(entry));
+                        }
+                    }
+                }
+                Err((code, error)) => {
+                    log::warn!(
+                        target: TRANSACTION_LOG_TARGET,
+                        "Rejected transaction {}: {}",
+                        index,
+                        error
+                    );
+                    response.rejected.push(Rejection { index, code, error });
+                }
+            }
+        }
+
+        if let Some((client, tx)) = last_applied {
+            *self.last_applied() = Some(LastApplied {
+                client,
+                tx,
+                applied: Instant::now(),
+                lag: received.elapsed(),
+            });
+        }
+        response
+    }
+
+    /// Checks the rate limit (if any) for a transaction from `client`,
+    /// recording in `response` when to retry if it was exceeded.
+    fn rate_limited(&self, client: ClientId, response: &mut SubmitResponse) -> bool {
+        let rate_limiter = match &self.rate_limiter {
+            Some(rate_limiter) => rate_limiter,
+            None => return false,
+        };
+
+        match rate_limiter.acquire(client, Instant::now()) {
+            Ok(()) => false,
+            Err(wait) => {
+                response.retry_after = response.retry_after.max(Some(wait));
+                true
+            }
+        }
+    }
+}
+
+/// The response to `POST /transactions`.
+#[derive(Debug, Default, Eq, PartialEq, Serialize)]
+struct SubmitResponse {
+    /// The number of transactions applied.
+    applied: usize,
+    /// The transactions which failed to be read or applied.
+    rejected: Vec<Rejection>,
+    /// If any transactions exceeded the rate limit, how long until all of
+    /// them could have been accepted.
+    #[serde(skip)]
+    retry_after: Option<Duration>,
+}
+
+/// A transaction rejected by `POST /transactions`.
+#[derive(Debug, Eq, PartialEq, Serialize)]
+struct Rejection {
+    /// The 0-based position of the transaction in the request.
+    index: usize,
+    /// The stable code for the error, e.g. `INSUFFICIENT_FUNDS`.
+    code: &'static str,
+    error: String,
+}
+
+/// A message sent to a `/stream` client.
+#[derive(Debug, Eq, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum StreamEvent {
+    /// The new state of an account, after a transaction was applied.
+    Account(ReportEntry),
+    /// The response to a message of transactions sent by the client.
+    Result(SubmitResponse),
+}
+
+/// The last transaction applied by the server.
+#[derive(Clone, Copy, Debug)]
+struct LastApplied {
+    client: ClientId,
+    tx: TransactionId,
+    applied: Instant,
+    /// The time from its request being received to it being applied.
+    lag: Duration,
+}
+
+/// The response to `GET /readyz`.
+#[derive(Debug, PartialEq, Serialize)]
+struct ReadinessResponse {
+    status: &'static str,
+    /// Where the accounts are stored. They are only held in memory, which
+    /// is always available.
+    storage: &'static str,
+    /// The last transaction applied, if any have been.
+    last_transaction: Option<LastTransaction>,
+    /// For the last request which applied a transaction, the time from it
+    /// being received to its last transaction being applied. This includes
+    /// waiting for other requests, so grows if the server is overloaded.
+    ingestion_lag_seconds: Option<f64>,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+struct LastTransaction {
+    client: ClientId,
+    tx: TransactionId,
+    /// The time since the transaction was applied.
+    seconds_ago: f64,
+}
+
+/// An error response.
+#[derive(Serialize)]
+struct ErrorResponse {
+    error: String,
+}
+
+/// Runs a REST API server on `address` until interrupted, backed by a single
+/// shared processor:
+///
+/// * `POST /transactions` applies the transactions in the body, which uses
+///   the JSON Lines input format (one transaction object per line).
+/// * `GET /accounts/{client}` returns the report entry for one client.
+/// * `GET /report` returns the report for all clients, as a JSON array.
+/// * `GET /stream` accepts a WebSocket (see [`stream`]).
+/// * `GET /metrics` returns Prometheus metrics (see [`Metrics`]).
+/// * `GET /healthz` and `GET /readyz` are liveness and readiness probes.
+/// * `POST /tenants/{tenant}/transactions`,
+///   `GET /tenants/{tenant}/accounts/{client}` and
+///   `GET /tenants/{tenant}/report` are like the endpoints above, for a
+///   tenant's own accounts (see [`TenantProcessor`]). A tenant is created
+///   by its first transactions.
+///
+/// Transactions over `rate_limit` (for their client) are rejected, and
+/// amounts are parsed and formatted according to `options`.
+pub fn serve(
+    transaction_processor: TransactionProcessor,
+    address: SocketAddr,
+    rate_limit: Option<RateLimit>,
+    options: &ProcessOptions,
+) -> Result<(), TransactionProcessorCLIError> {
+    let state = ServerState {
+        tenants: Arc::new(Mutex::new(TenantProcessor::new(
+            transaction_processor.new_like(),
+        ))),
+        transaction_processor: Arc::new(Mutex::new(transaction_processor)),
+        options: options.clone(),
+        updates: broadcast::channel(UPDATE_CAPACITY).0,
+        metrics: Arc::new(Metrics::new()),
+        last_applied: Arc::default(),
+        ready: Arc::default(),
+        rate_limiter: rate_limit.map(|rate_limit| Arc::new(RateLimiter::new(rate_limit))),
+    };
+    let ready = Arc::clone(&state.ready);
+    let server_error = |err: std::io::Error| TransactionProcessorCLIError::ServerFailed {
+        address,
+        error: err,
+    };
+
+    tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .map_err(server_error)?
+        .block_on(async {
+            let listener = tokio::net::TcpListener::bind(address).await?;
+            log::info!("Listening on {}", listener.local_addr()?);
+            ready.store(true, Ordering::Relaxed);
+
+            axum::serve(listener, router(state))
+                .with_graceful_shutdown(async move {
+                    shutdown_signal().await;
+                    ready.store(false, Ordering::Relaxed);
+                })
+                .await
+        })
+        .map_err(server_error)
+}
+
+fn router(state: ServerState) -> Router {
+    Router::new()
+        .route("/transactions", post(submit_transactions))
+        .route("/accounts/{client}", get(get_account))
+        .route("/report", get(get_report))
+        .route("/stream", get(stream))
+        .route("/metrics", get(get_metrics))
+        .route("/healthz", get(get_health))
+        .route("/readyz", get(get_readiness))
+        .route(
+            "/tenants/{tenant}/transactions",
+            post(submit_tenant_transactions),
+        )
+        .route(
+            "/tenants/{tenant}/accounts/{client}",
+            get(get_tenant_account),
+        )
+        .route("/tenants/{tenant}/report", get(get_tenant_report))
+        .with_state(state)
+}
+
+/// Applies each transaction in the body, in order. Responds with `200 OK` if
+/// all were applied, or (after applying the rest) `429 Too Many Requests`
+/// with a `Retry-After` header if any exceeded the rate limit, or
+/// `422 Unprocessable Entity` if any otherwise failed to be read or applied.
+async fn submit_transactions(State(state): State<ServerState>, body: Bytes) -> Response {
+    submit_response(state.apply(None, &body))
+}
+
+/// Applies each transaction in the body to a tenant's accounts, responding
+/// as for `POST /transactions`, or with `400 Bad Request` if the tenant ID
+/// is invalid.
+async fn submit_tenant_transactions(
+    State(state): State<ServerState>,
+    Path(tenant): Path<String>,
+    body: Bytes,
+) -> Response {
+    match tenant_id(&tenant) {
+        Ok(tenant) => submit_response(state.apply(Some(&tenant), &body)),
+        Err(error) => bad_request(error),
+    }
+}
+
+/// The response to a request which submitted transactions.
+fn submit_response(response: SubmitResponse) -> Response {
+    match response.retry_after {
+        Some(wait) => {
+            // Whole seconds, rounded up
+            let retry_after = wait.as_secs() + u64::from(wait.subsec_nanos() > 0);
+            (
+                StatusCode::TOO_MANY_REQUESTS,
+                [(header::RETRY_AFTER, retry_after.to_string())],
+                Json(response),
+            )
+                .into_response()
+        }
+        None if response.rejected.is_empty() => (StatusCode::OK, Json(response)).into_response(),
+        None => (StatusCode::UNPROCESSABLE_ENTITY, Json(response)).into_response(),
+    }
+}
+
+/// Responds with the report entry for a client, or `404 Not Found` if the
+/// client has no account.
+async fn get_account(State(state): State<ServerState>, Path(client): Path<ClientId>) -> Response {
+    let entry = state.transaction_processor().account_report(client);
+    match entry {
+        Some(entry) => Json(state.scale(entry)).into_response(),
+        None => not_found(format!("Client {} not found", client)),
+    }
+}
+
+/// Responds with the report for all clients.
+async fn get_report(State(state): State<ServerState>) -> Json<Vec<ReportEntry>> {
+    let report = state.transaction_processor().generate_report_as_vec();
+    Json(report.into_iter().map(|entry| state.scale(entry)).collect())
+}
+
+/// Responds with the report entry for a client of a tenant, or
+/// `404 Not Found` if the tenant or client has no account.
+async fn get_tenant_account(
+    State(state): State<ServerState>,
+    Path((tenant, client)): Path<(String, ClientId)>,
+) -> Response {
+    let tenant = match tenant_id(&tenant) {
+        Ok(tenant) => tenant,
+        Err(error) => return bad_request(error),
+    };
+    let entry = state
+        .tenants()
+        .tenant(&tenant)
+        .and_then(|transaction_processor| transaction_processor.account_report(client));
+    match entry {
+        Some(entry) => Json(state.scale(entry)).into_response(),
+        None => not_found(format!("Client {} of tenant {} not found", client, tenant)),
+    }
+}
+
+/// Responds with the report for all clients of a tenant, or
+/// `404 Not Found` if the tenant has no accounts.
+async fn get_tenant_report(
+    State(state): State<ServerState>,
+    Path(tenant): Path<String>,
+) -> Response {
+    let tenant = match tenant_id(&tenant) {
+        Ok(tenant) => tenant,
+        Err(error) => return bad_request(error),
+    };
+    let report = state
+        .tenants()
+        .tenant(&tenant)
+        .map(TransactionProcessor::generate_report_as_vec);
+    match report {
+        Some(report) => Json(
+            report
+                .into_iter()
+                .map(|entry| state.scale(entry))
+                .collect::<Vec<_>>(),
+        )
+        .into_response(),
+        None => not_found(format!("Tenant {} not found", tenant)),
+    }
+}
+
+/// Parses the tenant ID of a request, or describes why it is invalid.
+fn tenant_id(tenant: &str) -> Result<TenantId, String> {
+    tenant
+        .parse()
+        .map_err(|err: TenantIdError| format!("Invalid tenant '{}': {}", tenant, err))
+}
+
+/// A `400 Bad Request` response.
+fn bad_request(error: String) -> Response {
+    (StatusCode::BAD_REQUEST, Json(ErrorResponse { error })).into_response()
+}
+
+/// A `404 Not Found` response.
+fn not_found(error: String) -> Response {
+    (StatusCode::NOT_FOUND, Json(ErrorResponse { error })).into_response()
+}
+
+/// Responds with the metrics of all transactions submitted, and of the
+/// current state of the accounts.
+async fn get_metrics(State(state): State<ServerState>) -> Response {
+    let metrics = state.metrics.render(&state.transaction_processor());
+    ([(header::CONTENT_TYPE, metrics::CONTENT_TYPE)], metrics).into_response()
+}
+
+/// Responds with `{"status":"ok"}` while the server is running. This doesn't
+/// wait for other requests, so succeeds even while the server is busy.
+async fn get_health() -> Json<serde_json::Value> {
+    Json(serde_json::json!({ "status": "ok" }))
+}
+
+/// Responds with the state of ingestion once the shared processor can be
+/// used. If the server isn't ready (see [`ServerState::ready`]), or a
+/// request holds the processor for longer than [`READINESS_TIMEOUT`],
+/// responds with `503 Service Unavailable` instead.
+async fn get_readiness(State(state): State<ServerState>) -> Response {
+    // Waits for any request which is applying transactions, without
+    // blocking the executor
+    let deadline = Instant::now() + READINESS_TIMEOUT;
+    let free = loop {
+        let busy = matches!(
+            state.transaction_processor.try_lock(),
+            Err(TryLockError::WouldBlock)
+        );
+        if !busy {
+            break true;
+        }
+        if Instant::now() >= deadline {
+            break false;
+        }
+        tokio::time::sleep(READINESS_POLL_INTERVAL).await;
+    };
+    let ready = free && state.ready.load(Ordering::Relaxed);
+    let last_applied = *state.last_applied();
+
+    let response = Json(ReadinessResponse {
+        status: if ready { "ready" } else { "not_ready" },
+        storage: "memory",
+        last_transaction: last_applied.map(|last_applied| LastTransaction {
+            client: last_applied.client,
+            tx: last_applied.tx,
+            seconds_ago: last_applied.applied.elapsed().as_secs_f64(),
+        }),
+        ingestion_lag_seconds: last_applied.map(|last_applied| last_applied.lag.as_secs_f64()),
+    });
+    if ready {
+        (StatusCode::OK, response).into_response()
+    } else {
+        (StatusCode::SERVICE_UNAVAILABLE, response).into_response()
+    }
+}
+
+/// Upgrades to a WebSocket, which is subscribed to account updates. Each
+/// text or binary message from the client is applied like the body of
+/// `POST /transactions`, and answered with a `result` event. Whenever a
+/// transaction is applied (by any client, over any endpoint), the new state
+/// of its account is pushed to every subscriber as an `account` event, e.g.
+/// `{"type":"account","client":1,"available":"1.5","held":"0","total":"1.5","locked":false}`.
+///
+/// A subscriber which falls too far behind misses the oldest updates. As
+/// every update holds the full state of the account, it can catch up with
+/// `GET /report`.
+async fn stream(ws: WebSocketUpgrade, State(state): State<ServerState>) -> Response {
+    ws.on_upgrade(|socket| handle_stream(socket, state))
+}
+
+async fn handle_stream(mut socket: WebSocket, state: ServerState) {
+    let mut updates = state.updates.subscribe();
+
+    loop {
+        let event = tokio::select! {
+            message = socket.recv() => match message {
+                Some(Ok(Message::Text(text))) => {
+                    StreamEvent::Result(state.apply(None, text.as_bytes()))
+                }
+                Some(Ok(Message::Binary(data))) => StreamEvent::Result(state.apply(None, &data)),
+                // Pings are answered automatically
+                Some(Ok(Message::Ping(_) | Message::Pong(_))) => continue,
+                Some(Ok(Message::Close(_))) | None => break,
+                Some(Err(err)) => {
+                    log::debug!("WebSocket error: {}", err);
+                    break;
+                }
+            },
+            update = updates.recv() => match update {
+                Ok(entry) => StreamEvent::Account(entry),
+                Err(broadcast::error::RecvError::Lagged(missed)) => {
+                    log::warn!("WebSocket subscriber missed {} account updates", missed);
+                    continue;
+                }
+                Err(broadcast::error::RecvError::Closed) => break,
+            },
+        };
+
+        let text = match serde_json::to_string(&event) {
+            Ok(text) => text,
+            Err(err) => {
+                log::error!("Failed to serialize event: {}", err);
+                continue;
+            }
+        };
+        if socket.send(Message::Text(text.into())).await.is_err() {
+            break;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::{Arc, Mutex};
+
+    use axum::body::{to_bytes, Bytes};
+    use axum::extract::{Path, State};
+    use axum::http::{header, StatusCode};
+    use axum::response::{IntoResponse, Response};
+    use tokio::sync::broadcast;
+    use transaction_processor::metrics::Metrics;
+    use transaction_processor::pipeline::PipelineOptions;
+    use transaction_processor::tenant::TenantProcessor;
+    use transaction_processor::TransactionProcessor;
+
+    use crate::rate_limit::{RateLimit, RateLimiter};
+    use crate::server::{
+        get_account, get_metrics, get_readiness, get_report, get_tenant_account, get_tenant_report,
+        submit_tenant_transactions, submit_transactions, ServerState, StreamEvent, SubmitResponse,
+    };
+    use crate::test::logged;
+    use crate::ProcessOptions;
+
+    fn state() -> ServerState {
+        ServerState {
+            transaction_processor: Arc::new(Mutex::new(TransactionProcessor::new())),
+            tenants: Arc::new(Mutex::new(
+                TenantProcessor::new(TransactionProcessor::new()),
+            )),
+            options: ProcessOptions {
+                pipeline: PipelineOptions::new().with_output_scale(Some(2)),
+                ..ProcessOptions::default()
+            },
+            updates: broadcast::channel(16).0,
+            metrics: Arc::new(Metrics::new()),
+            last_applied: Arc::default(),
+            ready: Arc::new(AtomicBool::new(true)),
+            rate_limiter: None,
+        }
+    }
+
+    fn block_on<T>(future: impl std::future::Future<Output = T>) -> T {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .build()
+            .unwrap()
+            .block_on(future)
+    }
+
+    fn body(response: Response) -> (StatusCode, String) {
+        let status = response.status();
+        let body = block_on(to_bytes(response.into_body(), usize::MAX)).unwrap();
+        (status, String::from_utf8(body.to_vec()).unwrap())
+    }
+
+    #[test]
+    fn test_submit_and_report() {
+        let state = state();
+
+        let response = block_on(submit_transactions(
+            State(state.clone()),
+            Bytes::from(
+                "{\"type\":\"deposit\",\"client\":1,\"tx\":1,\"amount\":\"2.5\"}\n\
+                 {\"type\":\"withdrawal\",\"client\":1,\"tx\":2,\"amount\":\"5\"}\n\
+                 {\"type\":\"deposit\",\"client\":2,\"tx\":3,\"amount\":\"1\"}\n",
+            ),
+        ));
+        assert_eq!(
+            (
+                StatusCode::UNPROCESSABLE_ENTITY,
+                format!(
+                    r#"{{"applied":2,"rejected":[{{"index":1,"code":"INSUFFICIENT_FUNDS","error":"Not enough funds for client 1 (withdrawal of {} with {} available)"}}]}}"#,
+                    logged("5"),
+                    logged("2.5")
+                )
+            ),
+            body(response)
+        );
+
+        let response = block_on(get_account(State(state.clone()), Path(1)));
+        assert_eq!(
+            (
+                StatusCode::OK,
+                r#"{"client":1,"available":"2.50","held":"0.00","total":"2.50","locked":false}"#
+                    .to_string()
+            ),
+            body(response)
+        );
+
+        let response = block_on(get_account(State(state.clone()), Path(3)));
+        assert_eq!(StatusCode::NOT_FOUND, response.status());
+
+        let response = block_on(get_report(State(state.clone()))).into_response();
+        let (status, report) = body(response);
+        assert_eq!(StatusCode::OK, status);
+        assert!(report.starts_with(r#"[{"client":1,"#));
+        assert!(report.ends_with(r#""locked":false}]"#));
+
+        let response = block_on(get_metrics(State(state)));
+        let (status, metrics) = body(response);
+        assert_eq!(StatusCode::OK, status);
+        assert!(metrics
+            .contains("transaction_processor_transactions_applied_total{type=\"deposit\"} 2\n"));
+        assert!(metrics.contains(
+            "transaction_processor_transactions_rejected_total{type=\"withdrawal\",error=\"not_enough_funds\"} 1\n"
+        ));
+    }
+
+    #[test]
+    fn test_updates() {
+        let state = state();
+        let mut updates = state.updates.subscribe();
+
+        let response = state.apply(
+            None,
+            b"{\"type\":\"deposit\",\"client\":1,\"tx\":1,\"amount\":\"2.5\"}\n\
+              {\"type\":\"withdrawal\",\"client\":1,\"tx\":2,\"amount\":\"5\"}\n\
+              {\"type\":\"dispute\",\"client\":1,\"tx\":1}\n",
+        );
+        assert_eq!(2, response.applied);
+
+        // Only applied transactions are published
+        let event = StreamEvent::Account(updates.try_recv().unwrap());
+        assert_eq!(
+            r#"{"type":"account","client":1,"available":"2.50","held":"0.00","total":"2.50","locked":false}"#,
+            serde_json::to_string(&event).unwrap()
+        );
+        let event = StreamEvent::Account(updates.try_recv().unwrap());
+        assert_eq!(
+            r#"{"type":"account","client":1,"available":"0.00","held":"2.50","total":"2.50","locked":false}"#,
+            serde_json::to_string(&event).unwrap()
+        );
+        assert!(updates.try_recv().is_err());
+
+        let event = StreamEvent::Result(SubmitResponse {
+            applied: 1,
+            ..SubmitResponse::default()
+        });
+        assert_eq!(
+            r#"{"type":"result","applied":1,"rejected":[]}"#,
+            serde_json::to_string(&event).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_tenants() {
+        let state = state();
+        let mut updates = state.updates.subscribe();
+        let submit = |tenant: &str, transactions: &'static str| {
+            body(block_on(submit_tenant_transactions(
+                State(state.clone()),
+                Path(tenant.to_string()),
+                Bytes::from(transactions),
+            )))
+        };
+
+        // The same transaction is applied separately to each tenant
+        let deposit = "{\"type\":\"deposit\",\"client\":1,\"tx\":1,\"amount\":\"2.5\"}\n";
+        assert_eq!(StatusCode::OK, submit("acme", deposit).0);
+        assert_eq!(StatusCode::OK, submit("globex", deposit).0);
+        assert_eq!(StatusCode::UNPROCESSABLE_ENTITY, submit("acme", deposit).0);
+        assert_eq!(StatusCode::BAD_REQUEST, submit("acme corp", deposit).0);
+
+        let response = block_on(get_tenant_account(
+            State(state.clone()),
+            Path(("globex".to_string(), 1)),
+        ));
+        assert_eq!(
+            (
+                StatusCode::OK,
+                r#"{"client":1,"available":"2.50","held":"0.00","total":"2.50","locked":false}"#
+                    .to_string()
+            ),
+            body(response)
+        );
+        let response = block_on(get_tenant_report(
+            State(state.clone()),
+            Path("initech".to_string()),
+        ));
+        assert_eq!(StatusCode::NOT_FOUND, response.status());
+
+        // Tenants' accounts aren't in the shared report, nor published
+        let response = block_on(get_report(State(state))).into_response();
+        assert_eq!((StatusCode::OK, "[]".to_string()), body(response));
+        assert!(updates.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_readiness() {
+        let state = state();
+        let probe = |state: &ServerState| {
+            let (status, body) = body(block_on(get_readiness(State(state.clone()))));
+            (
+                status,
+                serde_json::from_str::<serde_json::Value>(&body).unwrap(),
+            )
+        };
+
+        let (status, readiness) = probe(&state);
+        assert_eq!(StatusCode::OK, status);
+        assert_eq!(
+            serde_json::json!({
+                "status": "ready",
+                "storage": "memory",
+                "last_transaction": null,
+                "ingestion_lag_seconds": null
+            }),
+            readiness
+        );
+
+        state.apply(
+            None,
+            b"{\"type\":\"deposit\",\"client\":1,\"tx\":1,\"amount\":\"2.5\"}\n\
+              {\"type\":\"deposit\",\"client\":2,\"tx\":3,\"amount\":\"1\"}\n\
+              {\"type\":\"withdrawal\",\"client\":1,\"tx\":2,\"amount\":\"5\"}\n",
+        );
+
+        // The last transaction was rejected, so the one before is reported
+        let (_, readiness) = probe(&state);
+        let last_transaction = &readiness["last_transaction"];
+        assert_eq!(2, last_transaction["client"]);
+        assert_eq!(3, last_transaction["tx"]);
+        assert!(readiness["ingestion_lag_seconds"].as_f64().unwrap() >= 0.0);
+
+        // Before the server is listening, or while a request holds the
+        // processor for too long, it isn't ready
+        state.ready.store(false, Ordering::Relaxed);
+        let (status, readiness) = probe(&state);
+        assert_eq!(StatusCode::SERVICE_UNAVAILABLE, status);
+        assert_eq!("not_ready", readiness["status"]);
+
+        state.ready.store(true, Ordering::Relaxed);
+        let transaction_processor = state.transaction_processor();
+        let (status, _) = probe(&state);
+        assert_eq!(StatusCode::SERVICE_UNAVAILABLE, status);
+        drop(transaction_processor);
+        assert_eq!(StatusCode::OK, probe(&state).0);
+    }
+
+    #[test]
+    fn test_rate_limit() {
+        let state = ServerState {
+            rate_limiter: Some(Arc::new(RateLimiter::new(RateLimit {
+                per_second: 0.5,
+                burst: 1.0,
+            }))),
+            ..state()
+        };
+
+        let response = block_on(submit_transactions(
+            State(state),
+            Bytes::from(
+                "{\"type\":\"deposit\",\"client\":1,\"tx\":1,\"amount\":\"2.5\"}\n\
+                 {\"type\":\"deposit\",\"client\":1,\"tx\":2,\"amount\":\"5\"}\n\
+                 {\"type\":\"deposit\",\"client\":2,\"tx\":3,\"amount\":\"1\"}\n",
+            ),
+        ));
+        assert_eq!(
+            Some("2"),
+            response
+                .headers()
+                .get(header::RETRY_AFTER)
+                .and_then(|value| value.to_str().ok())
+        );
+        assert_eq!(
+            (
+                StatusCode::TOO_MANY_REQUESTS,
+                r#"{"applied":2,"rejected":[{"index":1,"code":"RATE_LIMITED","error":"Rate limit exceeded for client 1"}]}"#
+                    .to_string()
+            ),
+            body(response)
+        );
+    }
+}
@@ -0,0 +1,1267 @@
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+#[cfg(feature = "encryption")]
+use std::fmt::Debug;
+use std::fmt::{Display, Formatter};
+use std::io;
+#[cfg(feature = "encryption")]
+use std::path::Path;
+#[cfg(feature = "encryption")]
+use std::str::FromStr;
+use std::sync::Arc;
+
+#[cfg(feature = "encryption")]
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+#[cfg(feature = "encryption")]
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use serde::{Deserialize, Serialize};
+
+use crate::numeric::CurrencyAmount;
+use crate::{
+    ClientAccount, ClientId, LockReason, Transaction, TransactionId, TransactionProcessor,
+};
+
+/// Identifies a snapshot file, and precedes the format version.
+const MAGIC: &[u8; 4] = b"TXPS";
+
+/// The version of the snapshot encoding written by [`write_snapshot`]. This
+/// must be incremented whenever the encoded state changes shape, so that
+/// older snapshots are rejected (or migrated) rather than misread.
+pub const SNAPSHOT_VERSION: u16 = 7;
+
+/// Identifies a checkpoint file, and precedes the checkpoint format version.
+const CHECKPOINT_MAGIC: &[u8; 4] = b"TXPC";
+
+/// The version of the checkpoint encoding written by [`write_checkpoint`].
+/// Like [`SNAPSHOT_VERSION`], this must be incremented whenever the encoded
+/// state changes shape.
+pub const CHECKPOINT_VERSION: u16 = 4;
+
+/// Identifies an encrypted snapshot file, and precedes the encryption format
+/// version.
+const ENCRYPTED_MAGIC: &[u8; 4] = b"TXPE";
+
+/// The version of the encryption envelope written by
+/// [`write_encrypted_snapshot`], which is independent of the version of the
+/// snapshot inside it.
+#[cfg(feature = "encryption")]
+pub const ENCRYPTION_VERSION: u16 = 1;
+
+/// The length of the ChaCha20-Poly1305 nonce which follows the header of an
+/// encrypted snapshot.
+#[cfg(feature = "encryption")]
+const NONCE_LEN: usize = 12;
+
+/// Error returned when writing or reading a snapshot.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum SnapshotError {
+    /// The snapshot could not be encoded or written.
+    WriteError(String),
+    /// The snapshot could not be read or decoded.
+    ReadError(String),
+    /// The data does not start with the snapshot header.
+    InvalidHeader,
+    /// The snapshot was written by an unsupported version of the encoding.
+    UnsupportedVersion(u16),
+    /// The snapshot decoded successfully, but describes an impossible state
+    /// (e.g. a dispute of a transaction which does not exist).
+    InvalidState(String),
+    /// The snapshot is encrypted, so must be read with
+    /// `read_encrypted_snapshot`.
+    Encrypted,
+    /// The data is a checkpoint, so must be read with [`read_checkpoint`].
+    Checkpoint,
+    /// An encryption key is not valid, or could not be loaded.
+    InvalidKey(String),
+    /// An encrypted snapshot could not be decrypted, because the key is
+    /// wrong or the snapshot has been modified.
+    DecryptionFailed,
+}
+
+impl Display for SnapshotError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&match self {
+            SnapshotError::WriteError(err) => format!("Snapshot write error: {}", err),
+            SnapshotError::ReadError(err) => format!("Snapshot read error: {}", err),
+            SnapshotError::InvalidHeader => "Not a snapshot file".to_string(),
+            SnapshotError::UnsupportedVersion(version) => {
+                format!("Unsupported snapshot version {}", version)
+            }
+            SnapshotError::InvalidState(err) => format!("Invalid snapshot state: {}", err),
+            SnapshotError::Encrypted => "The snapshot is encrypted".to_string(),
+            SnapshotError::Checkpoint => "The snapshot is a checkpoint".to_string(),
+            SnapshotError::InvalidKey(err) => format!("Invalid snapshot key: {}", err),
+            SnapshotError::DecryptionFailed => {
+                "Failed to decrypt snapshot: the key is wrong, or the snapshot has been modified"
+                    .to_string()
+            }
+        })
+    }
+}
+
+/// The encoded state of a single client account. Collections are sorted so
+/// that the same state always produces the same snapshot.
+#[derive(Serialize, Deserialize)]
+struct AccountState {
+    client: ClientId,
+    available: CurrencyAmount,
+    held: CurrencyAmount,
+    lock: Option<LockReason>,
+    frozen: bool,
+    transactions: Vec<(TransactionId, CurrencyAmount)>,
+    active_disputes: Vec<TransactionId>,
+    resolved: Vec<TransactionId>,
+    key: Option<String>,
+}
+
+/// The position reached in each stream of transactions which a snapshot was
+/// taken from, by the stream's name: for example, the offset of the next
+/// message in each partition of a Kafka topic. A consumer restored from the
+/// snapshot can skip the transactions before these positions, as they are
+/// already in the accounts.
+pub type StreamPositions = BTreeMap<String, u64>;
+
+/// The encoded contents of a snapshot: the accounts, any transactions
+/// deferred to the next run (see [`write_snapshot_with_deferred`]), and the
+/// positions of the streams they were read from (see
+/// [`write_snapshot_with_positions`]).
+#[derive(Serialize, Deserialize)]
+struct SnapshotState {
+    accounts: Vec<AccountState>,
+    deferred: Vec<Transaction>,
+    positions: StreamPositions,
+}
+
+/// The encoded contents of a version 6 snapshot, which did not record the
+/// positions of streams.
+#[derive(Deserialize)]
+struct SnapshotStateV6 {
+    accounts: Vec<AccountState>,
+    deferred: Vec<Transaction>,
+}
+
+/// The encoded state of a client account in version 4 snapshots (and
+/// version 3 checkpoints), which did not record client keys.
+#[derive(Deserialize)]
+struct AccountStateV4 {
+    client: ClientId,
+    available: CurrencyAmount,
+    held: CurrencyAmount,
+    lock: Option<LockReason>,
+    frozen: bool,
+    transactions: Vec<(TransactionId, CurrencyAmount)>,
+    active_disputes: Vec<TransactionId>,
+    resolved: Vec<TransactionId>,
+}
+
+impl From<AccountStateV4> for AccountState {
+    fn from(account: AccountStateV4) -> Self {
+        Self {
+            client: account.client,
+            available: account.available,
+            held: account.held,
+            lock: account.lock,
+            frozen: account.frozen,
+            transactions: account.transactions,
+            active_disputes: account.active_disputes,
+            resolved: account.resolved,
+            key: None,
+        }
+    }
+}
+
+/// The encoded state of a client account in version 3 snapshots (and
+/// version 2 checkpoints), which did not record the resolved transactions
+/// which can't be disputed again.
+#[derive(Deserialize)]
+struct AccountStateV3 {
+    client: ClientId,
+    available: CurrencyAmount,
+    held: CurrencyAmount,
+    lock: Option<LockReason>,
+    frozen: bool,
+    transactions: Vec<(TransactionId, CurrencyAmount)>,
+    active_disputes: Vec<TransactionId>,
+}
+
+impl From<AccountStateV3> for AccountState {
+    fn from(account: AccountStateV3) -> Self {
+        Self {
+            client: account.client,
+            available: account.available,
+            held: account.held,
+            lock: account.lock,
+            frozen: account.frozen,
+            transactions: account.transactions,
+            active_disputes: account.active_disputes,
+            resolved: Vec::new(),
+            key: None,
+        }
+    }
+}
+
+/// The encoded state of a client account in version 2 snapshots (and
+/// version 1 checkpoints), which did not record whether it is frozen.
+#[derive(Deserialize)]
+struct AccountStateV2 {
+    client: ClientId,
+    available: CurrencyAmount,
+    held: CurrencyAmount,
+    lock: Option<LockReason>,
+    transactions: Vec<(TransactionId, CurrencyAmount)>,
+    active_disputes: Vec<TransactionId>,
+}
+
+impl From<AccountStateV2> for AccountState {
+    fn from(account: AccountStateV2) -> Self {
+        Self {
+            client: account.client,
+            available: account.available,
+            held: account.held,
+            lock: account.lock,
+            frozen: false,
+            transactions: account.transactions,
+            active_disputes: account.active_disputes,
+            resolved: Vec::new(),
+            key: None,
+        }
+    }
+}
+
+/// The encoded state of a client account in version 1 snapshots, which only
+/// recorded whether the account is locked.
+#[derive(Deserialize)]
+struct AccountStateV1 {
+    client: ClientId,
+    available: CurrencyAmount,
+    held: CurrencyAmount,
+    locked: bool,
+    transactions: Vec<(TransactionId, CurrencyAmount)>,
+    active_disputes: Vec<TransactionId>,
+}
+
+impl From<AccountStateV1> for AccountState {
+    fn from(account: AccountStateV1) -> Self {
+        Self {
+            client: account.client,
+            available: account.available,
+            held: account.held,
+            lock: account.locked.then_some(LockReason::Unknown),
+            frozen: false,
+            transactions: account.transactions,
+            active_disputes: account.active_disputes,
+            resolved: Vec::new(),
+            key: None,
+        }
+    }
+}
+
+impl AccountState {
+    fn new(client: ClientId, account: &ClientAccount) -> Self {
+        let mut transactions: Vec<_> = account
+            .transactions
+            .iter()
+            .map(|(tx, amount)| (*tx, *amount))
+            .collect();
+        transactions.sort_unstable_by_key(|(tx, _)| *tx);
+
+        let mut active_disputes: Vec<_> = account.active_disputes.iter().copied().collect();
+        active_disputes.sort_unstable();
+
+        let mut resolved: Vec<_> = account.resolved.iter().copied().collect();
+        resolved.sort_unstable();
+
+        Self {
+            client,
+            available: account.available,
+            held: account.held,
+            lock: account.lock,
+            frozen: account.frozen,
+            transactions,
+            active_disputes,
+            resolved,
+            key: account.key.clone(),
+        }
+    }
+
+    fn into_account(self) -> Result<ClientAccount, SnapshotError> {
+        let transactions: HashMap<_, _> = self.transactions.into_iter().collect();
+        let active_disputes: HashSet<_> = self.active_disputes.into_iter().collect();
+
+        if let Some(tx) = active_disputes
+            .iter()
+            .find(|tx| !transactions.contains_key(tx))
+        {
+            return Err(SnapshotError::InvalidState(format!(
+                "Client {} disputes unknown transaction {}",
+                self.client, tx
+            )));
+        }
+
+        Ok(ClientAccount {
+            available: self.available,
+            held: self.held,
+            transactions,
+            active_disputes,
+            resolved: self.resolved.into_iter().collect(),
+            order: VecDeque::new(),
+            lock: self.lock,
+            frozen: self.frozen,
+            key: self.key,
+            version: 0,
+        })
+    }
+}
+
+/// Writes the state of all client accounts as a compact, versioned binary
+/// snapshot (a short header followed by MessagePack).
+///
+/// Only account state is included. Options such as
+/// [`TransactionProcessor::with_precision_policy`] are not part of the
+/// snapshot, and must be applied again after restoring.
+///
+/// # Errors
+///
+/// Returns an error if the snapshot could not be encoded or written.
+pub fn write_snapshot(
+    transaction_processor: &TransactionProcessor,
+    writer: impl io::Write,
+) -> Result<(), SnapshotError> {
+    write_snapshot_with_deferred(transaction_processor, &[], writer)
+}
+
+/// Writes a snapshot like [`write_snapshot`], which also carries the
+/// transactions in `deferred` over to the next run, e.g. those after the
+/// cutoff of a settlement batch (see [`crate::cutoff::Cutoff`]). They are
+/// restored by [`read_snapshot_with_deferred`], in the same order.
+///
+/// # Errors
+///
+/// Returns an error if the snapshot could not be encoded or written.
+pub fn write_snapshot_with_deferred(
+    transaction_processor: &TransactionProcessor,
+    deferred: &[Transaction],
+    writer: impl io::Write,
+) -> Result<(), SnapshotError> {
+    write_snapshot_with_positions(
+        transaction_processor,
+        deferred,
+        &StreamPositions::new(),
+        writer,
+    )
+}
+
+/// Writes a snapshot like [`write_snapshot_with_deferred`], which also
+/// records how far the streams of transactions it was taken from had been
+/// read. They are restored by [`read_snapshot_with_positions`], so that a
+/// consumer can resume from exactly the state in the snapshot.
+///
+/// # Errors
+///
+/// Returns an error if the snapshot could not be encoded or written.
+pub fn write_snapshot_with_positions(
+    transaction_processor: &TransactionProcessor,
+    deferred: &[Transaction],
+    positions: &StreamPositions,
+    mut writer: impl io::Write,
+) -> Result<(), SnapshotError> {
+    writer
+        .write_all(MAGIC)
+        .and_then(|_| writer.write_all(&SNAPSHOT_VERSION.to_be_bytes()))
+        .map_err(|err| SnapshotError::WriteError(format!("{}", err)))?;
+
+    let state = SnapshotState {
+        accounts: account_states(transaction_processor),
+        deferred: deferred.to_vec(),
+        positions: positions.clone(),
+    };
+    rmp_serde::encode::write(&mut writer, &state)
+        .map_err(|err| SnapshotError::WriteError(format!("{}", err)))
+}
+
+/// The encoded state of every client account, in client ID order.
+fn account_states(transaction_processor: &TransactionProcessor) -> Vec<AccountState> {
+    transaction_processor
+        .clients
+        .iter()
+        .map(|(client, account)| AccountState::new(*client, account))
+        .collect()
+}
+
+/// Restores a processor, with default options, from the encoded accounts.
+fn restore_accounts(accounts: Vec<AccountState>) -> Result<TransactionProcessor, SnapshotError> {
+    let mut clients = BTreeMap::new();
+    let mut client_keys = HashMap::new();
+    for account in accounts {
+        let client = account.client;
+        if let Some(key) = &account.key {
+            if client_keys.insert(key.clone(), client).is_some() {
+                return Err(SnapshotError::InvalidState(format!(
+                    "Client key {} appears more than once",
+                    key
+                )));
+            }
+        }
+        if clients
+            .insert(client, Arc::new(account.into_account()?))
+            .is_some()
+        {
+            return Err(SnapshotError::InvalidState(format!(
+                "Client {} appears more than once",
+                client
+            )));
+        }
+    }
+
+    let restored_clients = clients.keys().copied().collect::<Vec<_>>();
+    let mut transaction_processor = TransactionProcessor {
+        clients,
+        client_keys,
+        ..TransactionProcessor::new()
+    };
+    // Every restored account is new to the processor
+    for client in restored_clients {
+        transaction_processor.record_change(client);
+    }
+    Ok(transaction_processor)
+}
+
+/// Restores a processor from a snapshot written by [`write_snapshot`]. The
+/// processor has default options, which can be changed using its builder
+/// methods. Any deferred transactions in the snapshot are ignored; use
+/// [`read_snapshot_with_deferred`] to restore them too.
+///
+/// # Errors
+///
+/// Returns an error if the data is not a snapshot, was written by an
+/// unsupported version, or describes an invalid state.
+pub fn read_snapshot(reader: impl io::Read) -> Result<TransactionProcessor, SnapshotError> {
+    read_snapshot_with_deferred(reader).map(|(transaction_processor, _)| transaction_processor)
+}
+
+/// Restores a processor like [`read_snapshot`], along with the transactions
+/// deferred by [`write_snapshot_with_deferred`]. Snapshots written by
+/// [`write_snapshot`], or by earlier versions, have none.
+///
+/// # Errors
+///
+/// Returns an error if the data is not a snapshot, was written by an
+/// unsupported version, or describes an invalid state.
+pub fn read_snapshot_with_deferred(
+    reader: impl io::Read,
+) -> Result<(TransactionProcessor, Vec<Transaction>), SnapshotError> {
+    read_snapshot_with_positions(reader)
+        .map(|(transaction_processor, deferred, _)| (transaction_processor, deferred))
+}
+
+/// Restores a processor like [`read_snapshot_with_deferred`], along with the
+/// stream positions recorded by [`write_snapshot_with_positions`]. Snapshots
+/// written otherwise, or by earlier versions, have none.
+///
+/// # Errors
+///
+/// Returns an error if the data is not a snapshot, was written by an
+/// unsupported version, or describes an invalid state.
+pub fn read_snapshot_with_positions(
+    mut reader: impl io::Read,
+) -> Result<(TransactionProcessor, Vec<Transaction>, StreamPositions), SnapshotError> {
+    let mut header = [0; 6];
+    reader
+        .read_exact(&mut header)
+        .map_err(|_| SnapshotError::InvalidHeader)?;
+
+    if &header[..4] == ENCRYPTED_MAGIC {
+        return Err(SnapshotError::Encrypted);
+    }
+    if &header[..4] == CHECKPOINT_MAGIC {
+        return Err(SnapshotError::Checkpoint);
+    }
+    if &header[..4] != MAGIC {
+        return Err(SnapshotError::InvalidHeader);
+    }
+
+    let read_error = |err: rmp_serde::decode::Error| SnapshotError::ReadError(format!("{}", err));
+    let (accounts, deferred): (Vec<AccountState>, _) =
+        match u16::from_be_bytes([header[4], header[5]]) {
+            SNAPSHOT_VERSION => {
+                let state: SnapshotState =
+                    rmp_serde::decode::from_read(reader).map_err(read_error)?;
+                return Ok((
+                    restore_accounts(state.accounts)?,
+                    state.deferred,
+                    state.positions,
+                ));
+            }
+            // Version 6 snapshots had no stream positions
+            6 => {
+                let state: SnapshotStateV6 =
+                    rmp_serde::decode::from_read(reader).map_err(read_error)?;
+                (state.accounts, state.deferred)
+            }
+            // Version 5 snapshots had no deferred transactions
+            5 => (
+                rmp_serde::decode::from_read(reader).map_err(read_error)?,
+                Vec::new(),
+            ),
+            // Accounts are migrated without client keys
+            4 => (
+                rmp_serde::decode::from_read::<_, Vec<AccountStateV4>>(reader)
+                    .map_err(read_error)?
+                    .into_iter()
+                    .map(AccountState::from)
+                    .collect(),
+                Vec::new(),
+            ),
+            // Resolved transactions are migrated as open to dispute again
+            3 => (
+                rmp_serde::decode::from_read::<_, Vec<AccountStateV3>>(reader)
+                    .map_err(read_error)?
+                    .into_iter()
+                    .map(AccountState::from)
+                    .collect(),
+                Vec::new(),
+            ),
+            // Accounts are migrated as not frozen
+            2 => (
+                rmp_serde::decode::from_read::<_, Vec<AccountStateV2>>(reader)
+                    .map_err(read_error)?
+                    .into_iter()
+                    .map(AccountState::from)
+                    .collect(),
+                Vec::new(),
+            ),
+            // Locked accounts are migrated with an unknown reason
+            1 => (
+                rmp_serde::decode::from_read::<_, Vec<AccountStateV1>>(reader)
+                    .map_err(read_error)?
+                    .into_iter()
+                    .map(AccountState::from)
+                    .collect(),
+                Vec::new(),
+            ),
+            version => return Err(SnapshotError::UnsupportedVersion(version)),
+        };
+
+    Ok((
+        restore_accounts(accounts)?,
+        deferred,
+        StreamPositions::new(),
+    ))
+}
+
+/// How far a batch run had got through its input files when a checkpoint
+/// was written: every row of the first [`Checkpoint::files`] inputs, and the
+/// first [`Checkpoint::rows`] rows of the next, had been applied (or
+/// rejected).
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Checkpoint {
+    /// The input files of the run, in the order they are read.
+    inputs: Vec<String>,
+    /// The number of inputs which had been read to the end.
+    files: usize,
+    /// The number of rows which had been read from the next input.
+    rows: u64,
+}
+
+impl Checkpoint {
+    /// Creates a checkpoint of a run of `inputs`, which has read the first
+    /// `files` of them, and `rows` rows of the next.
+    #[must_use]
+    pub fn new(inputs: Vec<String>, files: usize, rows: u64) -> Self {
+        Self {
+            inputs,
+            files,
+            rows,
+        }
+    }
+
+    /// The input files of the run, in the order they are read.
+    #[must_use]
+    pub fn inputs(&self) -> &[String] {
+        &self.inputs
+    }
+
+    /// The number of inputs which had been read to the end.
+    #[must_use]
+    pub const fn files(&self) -> usize {
+        self.files
+    }
+
+    /// The number of rows which had been read from the next input.
+    #[must_use]
+    pub const fn rows(&self) -> u64 {
+        self.rows
+    }
+}
+
+/// The encoded contents of a checkpoint.
+#[derive(Serialize, Deserialize)]
+struct CheckpointState {
+    inputs: Vec<String>,
+    files: usize,
+    rows: u64,
+    accounts: Vec<AccountState>,
+}
+
+/// The encoded contents of a version 1, 2 or 3 checkpoint, with the same
+/// accounts as version 2, 3 or 4 snapshots respectively.
+#[derive(Deserialize)]
+struct LegacyCheckpointState<A> {
+    inputs: Vec<String>,
+    files: usize,
+    rows: u64,
+    accounts: Vec<A>,
+}
+
+impl<A: Into<AccountState>> From<LegacyCheckpointState<A>> for CheckpointState {
+    fn from(state: LegacyCheckpointState<A>) -> Self {
+        Self {
+            inputs: state.inputs,
+            files: state.files,
+            rows: state.rows,
+            accounts: state.accounts.into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
+/// Writes the state of all client accounts with the position of the run
+/// (see [`Checkpoint`]), so that an interrupted run can be resumed with
+/// [`read_checkpoint`]. The encoding is like [`write_snapshot`]'s, with a
+/// different header.
+///
+/// # Errors
+///
+/// Returns an error if the checkpoint could not be encoded or written.
+pub fn write_checkpoint(
+    transaction_processor: &TransactionProcessor,
+    checkpoint: &Checkpoint,
+    mut writer: impl io::Write,
+) -> Result<(), SnapshotError> {
+    writer
+        .write_all(CHECKPOINT_MAGIC)
+        .and_then(|_| writer.write_all(&CHECKPOINT_VERSION.to_be_bytes()))
+        .map_err(|err| SnapshotError::WriteError(format!("{}", err)))?;
+
+    let state = CheckpointState {
+        inputs: checkpoint.inputs.clone(),
+        files: checkpoint.files,
+        rows: checkpoint.rows,
+        accounts: account_states(transaction_processor),
+    };
+    rmp_serde::encode::write(&mut writer, &state)
+        .map_err(|err| SnapshotError::WriteError(format!("{}", err)))
+}
+
+/// Restores a processor and the position of its run from a checkpoint
+/// written by [`write_checkpoint`]. The processor has default options, as
+/// with [`read_snapshot`].
+///
+/// # Errors
+///
+/// Returns an error if the data is not a checkpoint, was written by an
+/// unsupported version, or describes an invalid state.
+pub fn read_checkpoint(
+    mut reader: impl io::Read,
+) -> Result<(TransactionProcessor, Checkpoint), SnapshotError> {
+    let mut header = [0; 6];
+    reader
+        .read_exact(&mut header)
+        .map_err(|_| SnapshotError::InvalidHeader)?;
+
+    if &header[..4] != CHECKPOINT_MAGIC {
+        return Err(SnapshotError::InvalidHeader);
+    }
+    let read_error = |err: rmp_serde::decode::Error| SnapshotError::ReadError(format!("{}", err));
+    let state: CheckpointState = match u16::from_be_bytes([header[4], header[5]]) {
+        CHECKPOINT_VERSION => rmp_serde::decode::from_read(reader).map_err(read_error)?,
+        3 => rmp_serde::decode::from_read::<_, LegacyCheckpointState<AccountStateV4>>(reader)
+            .map_err(read_error)?
+            .into(),
+        2 => rmp_serde::decode::from_read::<_, LegacyCheckpointState<AccountStateV3>>(reader)
+            .map_err(read_error)?
+            .into(),
+        1 => rmp_serde::decode::from_read::<_, LegacyCheckpointState<AccountStateV2>>(reader)
+            .map_err(read_error)?
+            .into(),
+        version => return Err(SnapshotError::UnsupportedVersion(version)),
+    };
+
+    if state.files > state.inputs.len() || (state.files == state.inputs.len() && state.rows > 0) {
+        return Err(SnapshotError::InvalidState(format!(
+            "The checkpoint is past the end of its {} inputs",
+            state.inputs.len()
+        )));
+    }
+
+    let checkpoint = Checkpoint::new(state.inputs, state.files, state.rows);
+    Ok((restore_accounts(state.accounts)?, checkpoint))
+}
+
+/// A 256-bit key for encrypting snapshots. The key is not shown when
+/// debug-formatted.
+#[cfg(feature = "encryption")]
+#[derive(Clone)]
+pub struct SnapshotKey([u8; 32]);
+
+#[cfg(feature = "encryption")]
+impl SnapshotKey {
+    /// Generates a new random key.
+    #[must_use]
+    pub fn generate() -> Self {
+        Self(ChaCha20Poly1305::generate_key(&mut OsRng).into())
+    }
+
+    /// Creates a key from its raw bytes.
+    #[must_use]
+    pub fn from_bytes(key: [u8; 32]) -> Self {
+        Self(key)
+    }
+
+    /// Loads a key from an environment variable, containing the key as 64
+    /// hex digits.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the variable is not set, or is not a valid key.
+    pub fn from_env(variable: &str) -> Result<Self, SnapshotError> {
+        std::env::var(variable)
+            .map_err(|err| SnapshotError::InvalidKey(format!("{}: {}", variable, err)))?
+            .trim()
+            .parse()
+    }
+
+    /// Loads a key from a file, containing the key as 64 hex digits (with
+    /// optional surrounding whitespace).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file could not be read, or is not a valid key.
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, SnapshotError> {
+        let path = path.as_ref();
+        std::fs::read_to_string(path)
+            .map_err(|err| SnapshotError::InvalidKey(format!("{}: {}", path.display(), err)))?
+            .trim()
+            .parse()
+    }
+
+    /// The key as 64 lowercase hex digits, as accepted by
+    /// [`SnapshotKey::from_str`].
+    #[must_use]
+    pub fn to_hex(&self) -> String {
+        self.0.iter().map(|byte| format!("{:02x}", byte)).collect()
+    }
+}
+
+#[cfg(feature = "encryption")]
+impl FromStr for SnapshotKey {
+    type Err = SnapshotError;
+
+    /// Parses a key from 64 hex digits (in either case).
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || SnapshotError::InvalidKey("expected 64 hex digits".to_string());
+        if s.len() != 64 || !s.is_ascii() {
+            return Err(invalid());
+        }
+
+        let mut key = [0; 32];
+        for (index, byte) in key.iter_mut().enumerate() {
+            // The string is ASCII, so any index is a character boundary
+            *byte = u8::from_str_radix(&s[index * 2..index * 2 + 2], 16).map_err(|_| invalid())?;
+        }
+
+        Ok(Self(key))
+    }
+}
+
+#[cfg(feature = "encryption")]
+impl Debug for SnapshotKey {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str("SnapshotKey(..)")
+    }
+}
+
+/// The header of an encrypted snapshot, which is also authenticated.
+#[cfg(feature = "encryption")]
+fn encrypted_header() -> [u8; 6] {
+    let mut header = [0; 6];
+    header[..4].copy_from_slice(ENCRYPTED_MAGIC);
+    header[4..].copy_from_slice(&ENCRYPTION_VERSION.to_be_bytes());
+    header
+}
+
+/// Like [`write_snapshot`], but encrypts the snapshot with
+/// ChaCha20-Poly1305, so that the account balances it contains are
+/// protected at rest. The snapshot is preceded by its own header and a
+/// random nonce, and can only be read (with [`read_encrypted_snapshot`]) if
+/// it has not been modified.
+///
+/// # Errors
+///
+/// Returns an error if the snapshot could not be encoded, encrypted or
+/// written.
+#[cfg(feature = "encryption")]
+pub fn write_encrypted_snapshot(
+    transaction_processor: &TransactionProcessor,
+    writer: impl io::Write,
+    key: &SnapshotKey,
+) -> Result<(), SnapshotError> {
+    write_encrypted_snapshot_with_deferred(transaction_processor, &[], writer, key)
+}
+
+/// Writes an encrypted snapshot like [`write_encrypted_snapshot`], which also
+/// carries the transactions in `deferred` over to the next run, like
+/// [`write_snapshot_with_deferred`].
+///
+/// # Errors
+///
+/// Returns an error if the snapshot could not be encoded, encrypted or
+/// written.
+#[cfg(feature = "encryption")]
+pub fn write_encrypted_snapshot_with_deferred(
+    transaction_processor: &TransactionProcessor,
+    deferred: &[Transaction],
+    writer: impl io::Write,
+    key: &SnapshotKey,
+) -> Result<(), SnapshotError> {
+    write_encrypted_snapshot_with_positions(
+        transaction_processor,
+        deferred,
+        &StreamPositions::new(),
+        writer,
+        key,
+    )
+}
+
+/// Writes an encrypted snapshot like [`write_encrypted_snapshot_with_deferred`],
+/// which also records the positions of streams, like
+/// [`write_snapshot_with_positions`].
+///
+/// # Errors
+///
+/// Returns an error if the snapshot could not be encoded, encrypted or
+/// written.
+#[cfg(feature = "encryption")]
+pub fn write_encrypted_snapshot_with_positions(
+    transaction_processor: &TransactionProcessor,
+    deferred: &[Transaction],
+    positions: &StreamPositions,
+    mut writer: impl io::Write,
+    key: &SnapshotKey,
+) -> Result<(), SnapshotError> {
+    let mut snapshot = Vec::new();
+    write_snapshot_with_positions(transaction_processor, deferred, positions, &mut snapshot)?;
+
+    let header = encrypted_header();
+    let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+    let ciphertext = ChaCha20Poly1305::new(Key::from_slice(&key.0))
+        .encrypt(
+            &nonce,
+            Payload {
+                msg: &snapshot,
+                aad: &header,
+            },
+        )
+        .map_err(|_| SnapshotError::WriteError("Failed to encrypt snapshot".to_string()))?;
+
+    writer
+        .write_all(&header)
+        .and_then(|()| writer.write_all(&nonce))
+        .and_then(|()| writer.write_all(&ciphertext))
+        .map_err(|err| SnapshotError::WriteError(format!("{}", err)))
+}
+
+/// Restores a processor from a snapshot written by
+/// [`write_encrypted_snapshot`], like [`read_snapshot`].
+///
+/// # Errors
+///
+/// Returns an error if the data is not an encrypted snapshot, could not be
+/// decrypted with the key (including if it has been modified), or does not
+/// contain a valid snapshot.
+#[cfg(feature = "encryption")]
+pub fn read_encrypted_snapshot(
+    reader: impl io::Read,
+    key: &SnapshotKey,
+) -> Result<TransactionProcessor, SnapshotError> {
+    read_encrypted_snapshot_with_deferred(reader, key)
+        .map(|(transaction_processor, _)| transaction_processor)
+}
+
+/// Restores a processor like [`read_encrypted_snapshot`], along with the
+/// transactions deferred by [`write_encrypted_snapshot_with_deferred`].
+///
+/// # Errors
+///
+/// Returns an error if the data is not an encrypted snapshot, could not be
+/// decrypted with the key (including if it has been modified), or does not
+/// contain a valid snapshot.
+#[cfg(feature = "encryption")]
+pub fn read_encrypted_snapshot_with_deferred(
+    reader: impl io::Read,
+    key: &SnapshotKey,
+) -> Result<(TransactionProcessor, Vec<Transaction>), SnapshotError> {
+    read_encrypted_snapshot_with_positions(reader, key)
+        .map(|(transaction_processor, deferred, _)| (transaction_processor, deferred))
+}
+
+/// Restores a processor like [`read_encrypted_snapshot_with_deferred`], along
+/// with the stream positions recorded by
+/// [`write_encrypted_snapshot_with_positions`].
+///
+/// # Errors
+///
+/// Returns an error if the data is not an encrypted snapshot, could not be
+/// decrypted with the key (including if it has been modified), or does not
+/// contain a valid snapshot.
+#[cfg(feature = "encryption")]
+pub fn read_encrypted_snapshot_with_positions(
+    mut reader: impl io::Read,
+    key: &SnapshotKey,
+) -> Result<(TransactionProcessor, Vec<Transaction>, StreamPositions), SnapshotError> {
+    let mut header = [0; 6];
+    reader
+        .read_exact(&mut header)
+        .map_err(|_| SnapshotError::InvalidHeader)?;
+
+    if &header[..4] != ENCRYPTED_MAGIC {
+        return Err(SnapshotError::InvalidHeader);
+    }
+
+    let version = u16::from_be_bytes([header[4], header[5]]);
+    if version != ENCRYPTION_VERSION {
+        return Err(SnapshotError::UnsupportedVersion(version));
+    }
+
+    let mut nonce = [0; NONCE_LEN];
+    let mut ciphertext = Vec::new();
+    reader
+        .read_exact(&mut nonce)
+        .and_then(|()| reader.read_to_end(&mut ciphertext))
+        .map_err(|err| SnapshotError::ReadError(format!("{}", err)))?;
+
+    let snapshot = ChaCha20Poly1305::new(Key::from_slice(&key.0))
+        .decrypt(
+            Nonce::from_slice(&nonce),
+            Payload {
+                msg: &ciphertext,
+                aad: &header,
+            },
+        )
+        .map_err(|_| SnapshotError::DecryptionFailed)?;
+
+    read_snapshot_with_positions(snapshot.as_slice())
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+
+    use crate::numeric::CurrencyAmount;
+    use crate::report::LockedEntry;
+    use crate::snapshot::{
+        account_states, read_checkpoint, read_snapshot, read_snapshot_with_deferred,
+        read_snapshot_with_positions, write_checkpoint, write_snapshot,
+        write_snapshot_with_deferred, write_snapshot_with_positions, Checkpoint, SnapshotError,
+        StreamPositions, MAGIC, SNAPSHOT_VERSION,
+    };
+    use crate::timestamp::Timestamp;
+    use crate::{
+        ClientId, LockReason, RedisputePolicy, Transaction, TransactionError, TransactionId,
+        TransactionProcessor, TransactionType,
+    };
+
+    fn deposit(client: ClientId, tx: TransactionId, amount: &str) -> Transaction {
+        Transaction::new(
+            client,
+            tx,
+            TransactionType::Deposit {
+                amount: CurrencyAmount::from_str(amount).unwrap(),
+            },
+        )
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut transaction_processor =
+            TransactionProcessor::new().with_redispute_policy(RedisputePolicy::Never);
+        for transaction in [
+            deposit(1, 1, "10.5"),
+            Transaction::new(1, 1, TransactionType::Dispute),
+            Transaction::new(1, 1, TransactionType::Resolve),
+            deposit(1, 2, "2"),
+            Transaction::new(1, 2, TransactionType::Dispute),
+            deposit(2, 3, "3"),
+            Transaction::new(2, 3, TransactionType::Dispute),
+            Transaction::new(2, 3, TransactionType::Chargeback),
+            deposit(0, 4, "4").with_client_key("acme-7"),
+        ] {
+            transaction_processor.transact(&transaction).unwrap();
+        }
+        transaction_processor.freeze_account(1);
+
+        let mut snapshot = Vec::new();
+        write_snapshot(&transaction_processor, &mut snapshot).unwrap();
+
+        let mut restored = read_snapshot(snapshot.as_slice()).unwrap();
+        assert_eq!(
+            transaction_processor.generate_report_as_vec(),
+            restored.generate_report_as_vec()
+        );
+        assert_eq!(
+            vec![LockedEntry::new(2, LockReason::Chargeback(3))],
+            restored.generate_locked_report().collect::<Vec<_>>()
+        );
+        assert!(restored.is_frozen(1));
+        assert!(!restored.is_frozen(2));
+        assert_eq!(
+            Some(0),
+            restored.transaction_client(&deposit(9, 5, "1").with_client_key("acme-7"))
+        );
+
+        // Transaction history and disputes are restored too
+        assert_eq!(
+            Err(TransactionError::TransactionAlreadyExists { client: 1, tx: 1 }),
+            restored.transact(&deposit(1, 1, "1"))
+        );
+        assert_eq!(
+            Ok(()),
+            restored.transact(&Transaction::new(1, 2, TransactionType::Resolve))
+        );
+        assert_eq!(
+            Err(TransactionError::DisputeNotAllowed { client: 1, tx: 1 }),
+            restored.transact(&Transaction::new(1, 1, TransactionType::Dispute))
+        );
+        assert_eq!(
+            Err(TransactionError::AccountIsLocked {
+                client: 2,
+                transaction_type: deposit(2, 4, "1").transaction_type().clone(),
+            }),
+            restored.transact(&deposit(2, 4, "1"))
+        );
+
+        // The encoding is deterministic
+        let mut snapshot_again = Vec::new();
+        write_snapshot(
+            &read_snapshot(snapshot.as_slice()).unwrap(),
+            &mut snapshot_again,
+        )
+        .unwrap();
+        assert_eq!(snapshot, snapshot_again);
+
+        // Deferred transactions are carried over with the accounts
+        let deferred = vec![
+            deposit(3, 6, "1.25")
+                .with_timestamp(Timestamp::from_str("2024-03-02T09:00:00Z").unwrap())
+                .with_idempotency_key("k-6"),
+            Transaction::new(3, 6, TransactionType::Dispute),
+        ];
+        let mut snapshot = Vec::new();
+        write_snapshot_with_deferred(&transaction_processor, &deferred, &mut snapshot).unwrap();
+        let (restored, restored_deferred) =
+            read_snapshot_with_deferred(snapshot.as_slice()).unwrap();
+        assert_eq!(
+            transaction_processor.generate_report_as_vec(),
+            restored.generate_report_as_vec()
+        );
+        assert_eq!(deferred, restored_deferred);
+        assert!(read_snapshot_with_deferred(snapshot_again.as_slice())
+            .unwrap()
+            .1
+            .is_empty());
+
+        // So are the positions of the streams they were read from
+        let positions = StreamPositions::from([("transactions/0".to_string(), 42)]);
+        let mut snapshot = Vec::new();
+        write_snapshot_with_positions(&transaction_processor, &deferred, &positions, &mut snapshot)
+            .unwrap();
+        let (_, restored_deferred, restored_positions) =
+            read_snapshot_with_positions(snapshot.as_slice()).unwrap();
+        assert_eq!(deferred, restored_deferred);
+        assert_eq!(positions, restored_positions);
+
+        // Version 6 snapshots encode the same without the positions
+        let mut snapshot = MAGIC.to_vec();
+        snapshot.extend_from_slice(&6u16.to_be_bytes());
+        rmp_serde::encode::write(
+            &mut snapshot,
+            &(account_states(&transaction_processor), &deferred),
+        )
+        .unwrap();
+        let (restored, restored_deferred, restored_positions) =
+            read_snapshot_with_positions(snapshot.as_slice()).unwrap();
+        assert_eq!(
+            transaction_processor.generate_report_as_vec(),
+            restored.generate_report_as_vec()
+        );
+        assert_eq!(deferred, restored_deferred);
+        assert!(restored_positions.is_empty());
+    }
+
+    #[test]
+    fn test_version_1_snapshot() {
+        // Version 1 accounts only have a locked flag, and encode the same as
+        // a tuple of their fields
+        let mut snapshot = MAGIC.to_vec();
+        snapshot.extend_from_slice(&1_u16.to_be_bytes());
+        let amount = CurrencyAmount::from_str("1.5").unwrap();
+        let accounts = vec![
+            (
+                1_u16,
+                amount,
+                CurrencyAmount::ZERO,
+                true,
+                vec![(1_u32, amount)],
+                Vec::<u32>::new(),
+            ),
+            (
+                2,
+                amount,
+                CurrencyAmount::ZERO,
+                false,
+                vec![(1, amount)],
+                Vec::new(),
+            ),
+        ];
+        rmp_serde::encode::write(&mut snapshot, &accounts).unwrap();
+
+        let restored = read_snapshot(snapshot.as_slice()).unwrap();
+        assert_eq!(2, restored.generate_report_as_vec().len());
+        assert_eq!(
+            vec![LockedEntry::new(1, LockReason::Unknown)],
+            restored.generate_locked_report().collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_checkpoint_round_trip() {
+        let mut transaction_processor = TransactionProcessor::new();
+        transaction_processor
+            .transact(&deposit(1, 1, "10.5"))
+            .unwrap();
+        let checkpoint = Checkpoint::new(vec!["a.csv".to_string(), "b.csv".to_string()], 1, 42);
+
+        let mut data = Vec::new();
+        write_checkpoint(&transaction_processor, &checkpoint, &mut data).unwrap();
+        let (restored, restored_checkpoint) = read_checkpoint(data.as_slice()).unwrap();
+        assert_eq!(
+            transaction_processor.generate_report_as_vec(),
+            restored.generate_report_as_vec()
+        );
+        assert_eq!(checkpoint, restored_checkpoint);
+
+        // Checkpoints and snapshots can't be mistaken for each other
+        assert_eq!(
+            Err(SnapshotError::Checkpoint),
+            read_snapshot(data.as_slice()).map(|_| ())
+        );
+        let mut snapshot = Vec::new();
+        write_snapshot(&transaction_processor, &mut snapshot).unwrap();
+        assert_eq!(
+            Err(SnapshotError::InvalidHeader),
+            read_checkpoint(snapshot.as_slice()).map(|_| ())
+        );
+
+        let mut data = Vec::new();
+        let past_end = Checkpoint::new(vec!["a.csv".to_string()], 1, 1);
+        write_checkpoint(&transaction_processor, &past_end, &mut data).unwrap();
+        assert!(matches!(
+            read_checkpoint(data.as_slice()),
+            Err(SnapshotError::InvalidState(_))
+        ));
+    }
+
+    #[test]
+    fn test_invalid_snapshots() {
+        assert_eq!(
+            Err(SnapshotError::InvalidHeader),
+            read_snapshot(&b"client,available"[..]).map(|_| ())
+        );
+        assert_eq!(
+            Err(SnapshotError::InvalidHeader),
+            read_snapshot(&b"TX"[..]).map(|_| ())
+        );
+
+        let mut snapshot = Vec::new();
+        write_snapshot(&TransactionProcessor::new(), &mut snapshot).unwrap();
+        snapshot[4..6].copy_from_slice(&(SNAPSHOT_VERSION + 1).to_be_bytes());
+        assert_eq!(
+            Err(SnapshotError::UnsupportedVersion(SNAPSHOT_VERSION + 1)),
+            read_snapshot(snapshot.as_slice()).map(|_| ())
+        );
+
+        snapshot[4..6].copy_from_slice(&SNAPSHOT_VERSION.to_be_bytes());
+        snapshot.truncate(6);
+        assert!(matches!(
+            read_snapshot(snapshot.as_slice()),
+            Err(SnapshotError::ReadError(_))
+        ));
+    }
+
+    #[cfg(feature = "encryption")]
+    #[test]
+    fn test_encrypted_round_trip() {
+        use crate::snapshot::{read_encrypted_snapshot, write_encrypted_snapshot, SnapshotKey};
+
+        let mut transaction_processor = TransactionProcessor::new();
+        transaction_processor
+            .transact(&deposit(1, 1, "10.5"))
+            .unwrap();
+
+        let key = SnapshotKey::generate();
+        let mut snapshot = Vec::new();
+        write_encrypted_snapshot(&transaction_processor, &mut snapshot, &key).unwrap();
+
+        let restored = read_encrypted_snapshot(snapshot.as_slice(), &key).unwrap();
+        assert_eq!(
+            transaction_processor.generate_report_as_vec(),
+            restored.generate_report_as_vec()
+        );
+
+        // Encrypting the same state twice uses different nonces
+        let mut snapshot_again = Vec::new();
+        write_encrypted_snapshot(&transaction_processor, &mut snapshot_again, &key).unwrap();
+        assert_ne!(snapshot, snapshot_again);
+
+        // The key round trips through hex
+        let parsed: SnapshotKey = key.to_hex().parse().unwrap();
+        assert!(read_encrypted_snapshot(snapshot.as_slice(), &parsed).is_ok());
+        assert_eq!("SnapshotKey(..)", format!("{:?}", key));
+    }
+
+    #[cfg(feature = "encryption")]
+    #[test]
+    fn test_invalid_encrypted_snapshots() {
+        use crate::snapshot::{read_encrypted_snapshot, write_encrypted_snapshot, SnapshotKey};
+
+        let key = SnapshotKey::from_bytes([7; 32]);
+        let mut snapshot = Vec::new();
+        write_encrypted_snapshot(&TransactionProcessor::new(), &mut snapshot, &key).unwrap();
+
+        assert_eq!(
+            Err(SnapshotError::Encrypted),
+            read_snapshot(snapshot.as_slice()).map(|_| ())
+        );
+        assert_eq!(
+            Err(SnapshotError::DecryptionFailed),
+            read_encrypted_snapshot(snapshot.as_slice(), &SnapshotKey::generate()).map(|_| ())
+        );
+
+        let last = snapshot.len() - 1;
+        snapshot[last] ^= 1;
+        assert_eq!(
+            Err(SnapshotError::DecryptionFailed),
+            read_encrypted_snapshot(snapshot.as_slice(), &key).map(|_| ())
+        );
+
+        let mut plain = Vec::new();
+        write_snapshot(&TransactionProcessor::new(), &mut plain).unwrap();
+        assert_eq!(
+            Err(SnapshotError::InvalidHeader),
+            read_encrypted_snapshot(plain.as_slice(), &key).map(|_| ())
+        );
+
+        assert!(matches!(
+            "abcd".parse::<SnapshotKey>(),
+            Err(SnapshotError::InvalidKey(_))
+        ));
+    }
+}
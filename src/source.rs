@@ -0,0 +1,127 @@
+use std::fmt::{Display, Formatter};
+
+use crate::Transaction;
+
+/// Error returned by a [`TransactionSource`] for a row which could not be
+/// read as a transaction. Reading carries on with the next row.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SourceError(String);
+
+impl SourceError {
+    /// Creates an error with a message saying why the row could not be read.
+    pub fn new(message: impl Into<String>) -> Self {
+        Self(message.into())
+    }
+
+    /// Why the row could not be read.
+    #[must_use]
+    pub fn message(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Display for SourceError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// A feed of transactions, such as a file being read or a message queue,
+/// which [`crate::pipeline::process_sources`] can apply transactions from.
+/// It is implemented by the CSV and JSONL readers, and can be implemented
+/// for feeds in other formats, e.g. proprietary ones.
+///
+/// Like an iterator, each call returns the next transaction, or `None` once
+/// the feed has ended. A row which can't be read is returned as an error,
+/// and doesn't end the feed.
+pub trait TransactionSource {
+    /// Reads the next transaction.
+    fn next(&mut self) -> Option<Result<Transaction, SourceError>>;
+
+    /// Like [`TransactionSource::next`], but also returns the fields of the
+    /// row verbatim, e.g. for recording rejected rows. By default, there are
+    /// no fields.
+    fn next_row(&mut self) -> Option<(Vec<String>, Result<Transaction, SourceError>)> {
+        self.next().map(|transaction| (Vec::new(), transaction))
+    }
+
+    /// The number of warnings logged about the rows read so far, which did
+    /// not prevent them being read (e.g. extra fields). Defaults to zero.
+    fn warnings(&self) -> usize {
+        0
+    }
+}
+
+impl<S: TransactionSource + ?Sized> TransactionSource for &mut S {
+    fn next(&mut self) -> Option<Result<Transaction, SourceError>> {
+        (**self).next()
+    }
+
+    fn next_row(&mut self) -> Option<(Vec<String>, Result<Transaction, SourceError>)> {
+        (**self).next_row()
+    }
+
+    fn warnings(&self) -> usize {
+        (**self).warnings()
+    }
+}
+
+impl<S: TransactionSource + ?Sized> TransactionSource for Box<S> {
+    fn next(&mut self) -> Option<Result<Transaction, SourceError>> {
+        (**self).next()
+    }
+
+    fn next_row(&mut self) -> Option<(Vec<String>, Result<Transaction, SourceError>)> {
+        (**self).next_row()
+    }
+
+    fn warnings(&self) -> usize {
+        (**self).warnings()
+    }
+}
+
+/// A [`TransactionSource`] yielding the transactions of an iterator, e.g.
+/// those already decoded from a feed.
+#[derive(Clone, Debug)]
+pub struct IterSource<I>(I);
+
+impl<I: Iterator<Item = Result<Transaction, SourceError>>> IterSource<I> {
+    /// Creates a source yielding the transactions of `transactions`.
+    pub fn new(transactions: impl IntoIterator<IntoIter = I>) -> Self {
+        Self(transactions.into_iter())
+    }
+}
+
+impl<I: Iterator<Item = Result<Transaction, SourceError>>> TransactionSource for IterSource<I> {
+    fn next(&mut self) -> Option<Result<Transaction, SourceError>> {
+        self.0.next()
+    }
+}
+
+#[cfg(all(test, feature = "csv"))]
+mod test {
+    use crate::io::csv::CSVReader;
+    use crate::source::{IterSource, SourceError, TransactionSource};
+    use crate::{Transaction, TransactionType};
+
+    #[test]
+    fn test_transaction_source() {
+        let dispute = Transaction::new(1, 2, TransactionType::Dispute);
+        let mut source = IterSource::new([Ok(dispute.clone()), Err(SourceError::new("bad"))]);
+        assert_eq!(Some((Vec::new(), Ok(dispute))), source.next_row());
+        assert_eq!(Some(Err(SourceError::new("bad"))), source.next());
+        assert_eq!(None, source.next());
+
+        // The CSV reader yields its rows with their fields, and counts the
+        // warnings it logs
+        let mut source = CSVReader::new("type,client,tx,note\ndispute,1,2,x\n".as_bytes());
+        let (fields, transaction) = source.next_row().unwrap();
+        assert_eq!(vec!["dispute", "1", "2", "x"], fields);
+        assert_eq!(
+            Transaction::new(1, 2, TransactionType::Dispute),
+            transaction.unwrap()
+        );
+        assert_eq!(None, source.next());
+        assert_eq!(1, source.warnings());
+    }
+}
@@ -0,0 +1,294 @@
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+use std::time::Duration;
+
+#[cfg(feature = "serde")]
+use serde::{Serialize, Serializer};
+
+use crate::TransactionError;
+
+/// The category recorded for rows which could not be read as transactions.
+pub const PARSE_ERROR_CATEGORY: &str = "parse_error";
+
+/// The error code (see [`TransactionError::code`]) of rows which could not be
+/// read as transactions.
+pub const PARSE_ERROR_CODE: &str = "PARSE_ERROR";
+
+/// Summary of a batch of input files processed by a [`TransactionProcessor`]:
+/// how many rows were read and applied, and why the rest were rejected.
+///
+/// [`TransactionProcessor`]: crate::TransactionProcessor
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct BatchResult {
+    files_read: usize,
+    rows_parsed: usize,
+    transactions_applied: usize,
+    replays_skipped: usize,
+    transactions_deferred: usize,
+    rejects: BTreeMap<&'static str, usize>,
+    warnings: usize,
+    #[cfg_attr(
+        feature = "serde",
+        serde(rename = "elapsed_seconds", serialize_with = "serialize_seconds")
+    )]
+    elapsed: Duration,
+}
+
+impl BatchResult {
+    /// Creates an empty summary.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records that an input file has been read.
+    pub fn record_file(&mut self) {
+        self.files_read += 1;
+    }
+
+    /// Records a row which could not be read as a transaction.
+    pub fn record_parse_error(&mut self) {
+        self.record_reject(PARSE_ERROR_CATEGORY);
+    }
+
+    /// Records the result of applying a successfully parsed transaction.
+    pub fn record_transaction(&mut self, result: &Result<(), TransactionError>) {
+        self.rows_parsed += 1;
+        match result {
+            Ok(()) => self.transactions_applied += 1,
+            Err(TransactionError::Replayed(_)) => self.replays_skipped += 1,
+            Err(err) => self.record_reject(err.category()),
+        }
+    }
+
+    /// Records successfully parsed transactions which were deferred to a
+    /// later batch, rather than applied (see [`crate::cutoff::Cutoff`]).
+    pub fn record_deferred(&mut self, count: usize) {
+        self.rows_parsed += count;
+        self.transactions_deferred += count;
+    }
+
+    /// Records warnings about recoverable issues in the input, which did not
+    /// prevent rows being read.
+    pub fn record_warnings(&mut self, count: usize) {
+        self.warnings += count;
+    }
+
+    /// Adds the counts from another summary, e.g. for files processed
+    /// separately. The elapsed time is not changed.
+    pub fn merge(&mut self, other: &BatchResult) {
+        self.files_read += other.files_read;
+        self.rows_parsed += other.rows_parsed;
+        self.transactions_applied += other.transactions_applied;
+        self.replays_skipped += other.replays_skipped;
+        self.transactions_deferred += other.transactions_deferred;
+        for (category, count) in &other.rejects {
+            *self.rejects.entry(category).or_default() += count;
+        }
+        self.warnings += other.warnings;
+    }
+
+    /// Sets the time taken to process the batch.
+    pub fn set_elapsed(&mut self, elapsed: Duration) {
+        self.elapsed = elapsed;
+    }
+
+    /// The number of input files read.
+    pub fn files_read(&self) -> usize {
+        self.files_read
+    }
+
+    /// The number of rows which were successfully read as transactions,
+    /// whether or not they could then be applied.
+    pub fn rows_parsed(&self) -> usize {
+        self.rows_parsed
+    }
+
+    /// The number of transactions which were applied.
+    pub fn transactions_applied(&self) -> usize {
+        self.transactions_applied
+    }
+
+    /// The number of transactions which were skipped as replays of ones
+    /// already applied (see [`TransactionError::Replayed`]). These are not
+    /// counted as rejected.
+    pub fn replays_skipped(&self) -> usize {
+        self.replays_skipped
+    }
+
+    /// The number of transactions which were deferred to a later batch.
+    /// These are counted as parsed, but neither applied nor rejected.
+    pub fn transactions_deferred(&self) -> usize {
+        self.transactions_deferred
+    }
+
+    /// The number of rejected rows for each error category. Rows which could
+    /// not be read are counted under [`PARSE_ERROR_CATEGORY`], and rejected
+    /// transactions under [`TransactionError::category`].
+    pub fn rejects(&self) -> &BTreeMap<&'static str, usize> {
+        &self.rejects
+    }
+
+    /// The total number of rejected rows.
+    pub fn total_rejects(&self) -> usize {
+        self.rejects.values().sum()
+    }
+
+    /// The number of warnings about recoverable issues in the input.
+    pub fn warnings(&self) -> usize {
+        self.warnings
+    }
+
+    /// The time taken to process the batch.
+    pub fn elapsed(&self) -> Duration {
+        self.elapsed
+    }
+
+    fn record_reject(&mut self, category: &'static str) {
+        *self.rejects.entry(category).or_default() += 1;
+    }
+}
+
+impl Display for BatchResult {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Files read: {}", self.files_read)?;
+        writeln!(f, "Rows parsed: {}", self.rows_parsed)?;
+        writeln!(f, "Transactions applied: {}", self.transactions_applied)?;
+        // Only with idempotent replays, so not shown otherwise
+        if self.replays_skipped > 0 {
+            writeln!(f, "Replays skipped: {}", self.replays_skipped)?;
+        }
+        // Only with a cutoff, so not shown otherwise
+        if self.transactions_deferred > 0 {
+            writeln!(f, "Deferred: {}", self.transactions_deferred)?;
+        }
+        writeln!(f, "Rejected: {}", self.total_rejects())?;
+        for (category, count) in &self.rejects {
+            writeln!(f, "  {}: {}", category, count)?;
+        }
+        writeln!(f, "Warnings: {}", self.warnings)?;
+        write!(f, "Elapsed: {:?}", self.elapsed)
+    }
+}
+
+#[cfg(feature = "serde")]
+fn serialize_seconds<S: Serializer>(elapsed: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_f64(elapsed.as_secs_f64())
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use crate::summary::BatchResult;
+    use crate::{CurrencyAmount, TransactionError, TransactionType};
+
+    fn not_enough_funds() -> TransactionError {
+        TransactionError::NotEnoughFunds {
+            client: 1,
+            transaction_type: TransactionType::Withdrawal {
+                amount: CurrencyAmount::ZERO,
+            },
+            available: CurrencyAmount::ZERO,
+        }
+    }
+
+    fn account_is_locked() -> TransactionError {
+        TransactionError::AccountIsLocked {
+            client: 1,
+            transaction_type: TransactionType::Dispute,
+        }
+    }
+
+    #[test]
+    fn test_batch_result() {
+        let mut result = BatchResult::new();
+        result.record_file();
+        result.record_transaction(&Ok(()));
+        result.record_transaction(&Ok(()));
+        result.record_transaction(&Err(not_enough_funds()));
+        result.record_transaction(&Err(account_is_locked()));
+        result.record_transaction(&Err(account_is_locked()));
+        result.record_transaction(&Err(TransactionError::Replayed(1)));
+        result.record_deferred(1);
+        result.record_parse_error();
+        result.record_warnings(3);
+        result.set_elapsed(Duration::from_millis(1500));
+
+        assert_eq!(1, result.files_read());
+        assert_eq!(7, result.rows_parsed());
+        assert_eq!(2, result.transactions_applied());
+        assert_eq!(1, result.transactions_deferred());
+        assert_eq!(4, result.total_rejects());
+        assert_eq!(3, result.warnings());
+        assert_eq!(
+            vec![
+                ("account_is_locked", 2),
+                ("not_enough_funds", 1),
+                ("parse_error", 1)
+            ],
+            result
+                .rejects()
+                .iter()
+                .map(|(category, count)| (*category, *count))
+                .collect::<Vec<_>>()
+        );
+
+        assert_eq!(
+            "Files read: 1\n\
+             Rows parsed: 7\n\
+             Transactions applied: 2\n\
+             Replays skipped: 1\n\
+             Deferred: 1\n\
+             Rejected: 4\n  \
+             account_is_locked: 2\n  \
+             not_enough_funds: 1\n  \
+             parse_error: 1\n\
+             Warnings: 3\n\
+             Elapsed: 1.5s",
+            result.to_string()
+        );
+    }
+
+    #[test]
+    fn test_merge() {
+        let mut result = BatchResult::new();
+        result.record_file();
+        result.record_transaction(&Ok(()));
+        result.record_transaction(&Err(not_enough_funds()));
+        result.record_transaction(&Err(TransactionError::Replayed(1)));
+        result.set_elapsed(Duration::from_secs(1));
+
+        let mut other = BatchResult::new();
+        other.record_file();
+        other.record_transaction(&Err(not_enough_funds()));
+        other.record_parse_error();
+        other.record_warnings(1);
+        other.set_elapsed(Duration::from_secs(5));
+
+        result.merge(&other);
+        assert_eq!(2, result.files_read());
+        assert_eq!(4, result.rows_parsed());
+        assert_eq!(1, result.transactions_applied());
+        assert_eq!(1, result.replays_skipped());
+        assert_eq!(3, result.total_rejects());
+        assert_eq!(Some(&2), result.rejects().get("not_enough_funds"));
+        assert_eq!(Some(&1), result.rejects().get("parse_error"));
+        assert_eq!(1, result.warnings());
+        assert_eq!(Duration::from_secs(1), result.elapsed());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_batch_result_json() {
+        let mut result = BatchResult::new();
+        result.record_file();
+        result.record_transaction(&Err(not_enough_funds()));
+        result.set_elapsed(Duration::from_millis(250));
+
+        assert_eq!(
+            r#"{"files_read":1,"rows_parsed":1,"transactions_applied":0,"replays_skipped":0,"transactions_deferred":0,"rejects":{"not_enough_funds":1},"warnings":0,"elapsed_seconds":0.25}"#,
+            serde_json::to_string(&result).unwrap()
+        );
+    }
+}
@@ -0,0 +1,211 @@
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+use crate::{Transaction, TransactionError, TransactionProcessor};
+
+/// The longest tenant ID accepted by [`TenantId::from_str`].
+pub const MAX_TENANT_ID_LENGTH: usize = 64;
+
+/// The ID of a tenant of a [`TenantProcessor`]: 1 to
+/// [`MAX_TENANT_ID_LENGTH`] ASCII letters, digits, `-`, `_` or `.`, so that
+/// it can be used in URLs and file names as it is.
+#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct TenantId(String);
+
+impl TenantId {
+    /// The ID as a string.
+    #[must_use]
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+/// The reason a string is not a valid [`TenantId`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum TenantIdError {
+    /// The ID is empty.
+    Empty,
+    /// The ID is longer than [`MAX_TENANT_ID_LENGTH`].
+    TooLong,
+    /// The ID contains a character other than an ASCII letter, digit, `-`,
+    /// `_` or `.`.
+    InvalidCharacter,
+}
+
+impl Display for TenantIdError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            TenantIdError::Empty => "Tenant ID is empty",
+            TenantIdError::TooLong => "Tenant ID is too long",
+            TenantIdError::InvalidCharacter => "Tenant ID contains an invalid character",
+        })
+    }
+}
+
+impl FromStr for TenantId {
+    type Err = TenantIdError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.is_empty() {
+            return Err(TenantIdError::Empty);
+        }
+        if s.len() > MAX_TENANT_ID_LENGTH {
+            return Err(TenantIdError::TooLong);
+        }
+        if !s
+            .bytes()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, b'-' | b'_' | b'.'))
+        {
+            return Err(TenantIdError::InvalidCharacter);
+        }
+        Ok(Self(s.to_string()))
+    }
+}
+
+impl Display for TenantId {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// Hosts fully isolated processors keyed by tenant, so that one instance
+/// can serve several customers. Each tenant has its own accounts and
+/// transaction IDs, so a transaction is only ever visible to its own
+/// tenant, and reports are generated per tenant.
+///
+/// Every tenant's processor is created with
+/// [`TransactionProcessor::new_like`] a template, so has the template's
+/// options, but none of its accounts.
+pub struct TenantProcessor {
+    template: TransactionProcessor,
+    tenants: BTreeMap<TenantId, TransactionProcessor>,
+}
+
+impl TenantProcessor {
+    /// Creates a processor with no tenants, whose tenants have the options
+    /// of `template`.
+    #[must_use]
+    pub fn new(template: TransactionProcessor) -> Self {
+        Self {
+            template: template.new_like(),
+            tenants: BTreeMap::new(),
+        }
+    }
+
+    /// Applies a transaction to a tenant's accounts (see
+    /// [`TransactionProcessor::transact`]), creating the tenant if it
+    /// doesn't exist.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of the tenant's processor if the transaction fails
+    /// to be applied.
+    pub fn transact(
+        &mut self,
+        tenant: &TenantId,
+        transaction: &Transaction,
+    ) -> Result<(), TransactionError> {
+        self.tenant_mut(tenant).transact(transaction)
+    }
+
+    /// The processor of a tenant, if it exists.
+    #[must_use]
+    pub fn tenant(&self, tenant: &TenantId) -> Option<&TransactionProcessor> {
+        self.tenants.get(tenant)
+    }
+
+    /// The processor of a tenant, which is created if it doesn't exist.
+    pub fn tenant_mut(&mut self, tenant: &TenantId) -> &mut TransactionProcessor {
+        let template = &self.template;
+        self.tenants
+            .entry(tenant.clone())
+            .or_insert_with(|| template.new_like())
+    }
+
+    /// Removes a tenant, returning its processor if it existed.
+    pub fn remove_tenant(&mut self, tenant: &TenantId) -> Option<TransactionProcessor> {
+        self.tenants.remove(tenant)
+    }
+
+    /// Every tenant and its processor, in tenant ID order.
+    pub fn tenants(&self) -> impl Iterator<Item = (&TenantId, &TransactionProcessor)> {
+        self.tenants.iter()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+
+    use crate::numeric::{CurrencyAmount, PrecisionPolicy};
+    use crate::tenant::{TenantId, TenantIdError, TenantProcessor, MAX_TENANT_ID_LENGTH};
+    use crate::{Transaction, TransactionError, TransactionProcessor, TransactionType};
+
+    #[test]
+    fn test_tenant_id() {
+        assert_eq!(
+            "acme-1.eu_west",
+            TenantId::from_str("acme-1.eu_west").unwrap().as_str()
+        );
+        assert_eq!(Err(TenantIdError::Empty), TenantId::from_str(""));
+        assert_eq!(
+            Err(TenantIdError::InvalidCharacter),
+            TenantId::from_str("a/b")
+        );
+        assert_eq!(
+            Err(TenantIdError::TooLong),
+            TenantId::from_str(&"a".repeat(MAX_TENANT_ID_LENGTH + 1))
+        );
+    }
+
+    #[test]
+    fn test_isolation() {
+        let deposit = |tx| {
+            Transaction::new(
+                1,
+                tx,
+                TransactionType::Deposit {
+                    amount: CurrencyAmount::from_str("1.23456").unwrap(),
+                },
+            )
+        };
+        let acme = TenantId::from_str("acme").unwrap();
+        let globex = TenantId::from_str("globex").unwrap();
+
+        let mut template =
+            TransactionProcessor::new().with_precision_policy(PrecisionPolicy::Truncate);
+        template.transact(&deposit(1)).unwrap();
+        let mut tenants = TenantProcessor::new(template);
+        assert!(tenants.tenant(&acme).is_none());
+
+        // Tenants have the template's options, but not its accounts, and
+        // don't see each other's transactions
+        tenants.transact(&acme, &deposit(1)).unwrap();
+        tenants.transact(&globex, &deposit(1)).unwrap();
+        assert_eq!(
+            Err(TransactionError::TransactionAlreadyExists { client: 1, tx: 1 }),
+            tenants.transact(&acme, &deposit(1))
+        );
+        tenants
+            .transact(&globex, &Transaction::new(1, 1, TransactionType::Dispute))
+            .unwrap();
+
+        let report = |tenant| tenants.tenant(tenant).unwrap().generate_report_as_vec();
+        assert_eq!(
+            CurrencyAmount::from_str("1.2345").unwrap(),
+            report(&acme)[0].available()
+        );
+        assert_eq!(CurrencyAmount::ZERO, report(&globex)[0].available());
+        assert_eq!(
+            vec![&acme, &globex],
+            tenants
+                .tenants()
+                .map(|(tenant, _)| tenant)
+                .collect::<Vec<_>>()
+        );
+
+        assert!(tenants.remove_tenant(&acme).is_some());
+        assert!(tenants.tenant(&acme).is_none());
+    }
+}
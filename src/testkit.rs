@@ -0,0 +1,327 @@
+use std::collections::{HashMap, VecDeque};
+use std::io;
+
+use crate::flat::FlatTransaction;
+use crate::io::csv::{CSVWriter, CSVWriterError};
+use crate::numeric::CurrencyAmount;
+use crate::{ClientId, Transaction, TransactionId, TransactionType};
+
+/// The number of decimal places of generated amounts.
+const AMOUNT_SCALE: u32 = 2;
+
+/// How many of the latest deposits may be disputed.
+const DISPUTABLE_DEPOSITS: usize = 1024;
+
+/// A small, fast pseudo-random number generator (SplitMix64). Unlike a
+/// generator from a library, its output for a seed never changes, so neither
+/// do the fixtures generated from it.
+#[derive(Clone, Debug)]
+struct SplitMix64 {
+    state: u64,
+}
+
+impl SplitMix64 {
+    fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// A number in `0..n`, which must not be zero.
+    fn below(&mut self, n: u64) -> u64 {
+        self.next_u64() % n
+    }
+
+    /// True with probability `p`.
+    fn chance(&mut self, p: f64) -> bool {
+        ((self.next_u64() >> 11) as f64 / (1_u64 << 53) as f64) < p
+    }
+}
+
+/// The funds of a generated client account, in minor units.
+#[derive(Clone, Copy, Debug, Default)]
+struct Balance {
+    available: i64,
+    held: i64,
+    locked: bool,
+}
+
+/// Synthesizes realistic streams of transactions, e.g. for benchmarks and
+/// load tests, as an iterator of [`Transaction`]s (see also
+/// [`StreamGenerator::write_csv`]).
+///
+/// The stream is determined by the seed and the options, so the same
+/// fixture can be regenerated instead of being stored. Every transaction is
+/// accepted by a [`crate::TransactionProcessor`] with the default options:
+///
+/// * Deposits have amounts of up to [`StreamGenerator::with_max_deposit`] with
+///   two decimal places, and transaction IDs counting up from 1.
+/// * Withdrawals never exceed the client's available funds.
+/// * Disputes refer to one of the latest deposits whose amount is still
+///   available, and are later either resolved or charged back.
+/// * Once an account is charged back, and so locked, it has no more
+///   transactions. The stream ends when every account is locked, or the
+///   transaction IDs run out.
+#[derive(Clone, Debug)]
+pub struct StreamGenerator {
+    rng: SplitMix64,
+    clients: ClientId,
+    withdrawal_ratio: f64,
+    dispute_probability: f64,
+    chargeback_ratio: f64,
+    max_deposit: i64,
+    next_tx: Option<TransactionId>,
+    balances: HashMap<ClientId, Balance>,
+    /// The latest deposits which may be disputed, as the client, transaction
+    /// ID and amount.
+    deposits: VecDeque<(ClientId, TransactionId, i64)>,
+    /// The disputed deposits which haven't been resolved or charged back.
+    disputes: Vec<(ClientId, TransactionId, i64)>,
+}
+
+impl StreamGenerator {
+    /// Creates a generator of transactions for 100 clients, a fifth of whose
+    /// deposits and withdrawals are withdrawals, and with a 1% probability
+    /// of each transaction being a dispute, a tenth of which are charged
+    /// back. Deposits are up to 1000.
+    #[must_use]
+    pub fn new(seed: u64) -> Self {
+        Self {
+            rng: SplitMix64 { state: seed },
+            clients: 100,
+            withdrawal_ratio: 0.2,
+            dispute_probability: 0.01,
+            chargeback_ratio: 0.1,
+            max_deposit: 1000 * 10_i64.pow(AMOUNT_SCALE),
+            next_tx: Some(1),
+            balances: HashMap::new(),
+            deposits: VecDeque::new(),
+            disputes: Vec::new(),
+        }
+    }
+
+    /// Sets the number of clients, whose IDs are 1 to `clients` (at least
+    /// one).
+    #[must_use]
+    pub fn with_clients(mut self, clients: ClientId) -> Self {
+        self.clients = clients.max(1);
+        self
+    }
+
+    /// Sets the fraction of deposits and withdrawals which are withdrawals,
+    /// from 0 to 1. A withdrawal is replaced by a deposit if the client has
+    /// no available funds.
+    #[must_use]
+    pub fn with_withdrawal_ratio(mut self, withdrawal_ratio: f64) -> Self {
+        self.withdrawal_ratio = withdrawal_ratio.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Sets the probability of each transaction being a dispute, from 0 to 1.
+    /// Disputes are resolved or charged back at the same rate, so a
+    /// probability above 0.5 is the same as 0.5.
+    #[must_use]
+    pub fn with_dispute_probability(mut self, dispute_probability: f64) -> Self {
+        self.dispute_probability = dispute_probability.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Sets the fraction of disputes which are charged back rather than
+    /// resolved, from 0 to 1.
+    #[must_use]
+    pub fn with_chargeback_ratio(mut self, chargeback_ratio: f64) -> Self {
+        self.chargeback_ratio = chargeback_ratio.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Sets the largest deposit, which is rounded to two decimal
+    /// places, and is at least `0.01`.
+    #[must_use]
+    pub fn with_max_deposit(mut self, max_deposit: CurrencyAmount) -> Self {
+        self.max_deposit = max_deposit
+            .with_scale(AMOUNT_SCALE)
+            .to_minor_units(AMOUNT_SCALE)
+            .unwrap_or(i64::MAX)
+            .max(1);
+        self
+    }
+
+    /// Writes `count` transactions (or fewer, if the stream ends) as CSV,
+    /// with the header row `type,client,tx,amount`, returning the number of
+    /// transactions written.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a transaction could not be written.
+    pub fn write_csv(self, count: u64, writer: impl io::Write) -> Result<u64, CSVWriterError> {
+        let mut writer = CSVWriter::new(writer);
+        let mut written = 0;
+        for transaction in self.take(usize::try_from(count).unwrap_or(usize::MAX)) {
+            writer.write(FlatTransaction::from(transaction))?;
+            written += 1;
+        }
+        writer.into_inner()?;
+        Ok(written)
+    }
+
+    /// A random client whose account isn't locked, if there is one.
+    #[cfg_attr(feature = "wide-ids", allow(clippy::useless_conversion))]
+    fn unlocked_client(&mut self) -> Option<ClientId> {
+        let clients = u64::from(self.clients);
+        let start = self.rng.below(clients);
+        (0..clients)
+            .map(|offset| {
+                ClientId::try_from((start + offset) % clients + 1)
+                    .expect("client IDs are at most the number of clients")
+            })
+            .find(|client| !self.balances.get(client).is_some_and(|b| b.locked))
+    }
+
+    fn balance(&mut self, client: ClientId) -> &mut Balance {
+        self.balances.entry(client).or_default()
+    }
+
+    fn amount(units: i64) -> CurrencyAmount {
+        CurrencyAmount::from_minor_units(units, AMOUNT_SCALE)
+            .expect("generated amounts have a valid scale")
+    }
+
+    /// Disputes one of the latest deposits, if its amount is available.
+    fn dispute(&mut self) -> Option<Transaction> {
+        let index = self.rng.below(self.deposits.len() as u64) as usize;
+        let (client, tx, amount) = self.deposits[index];
+        let balance = self.balance(client);
+        if balance.locked || balance.available < amount {
+            return None;
+        }
+        balance.available -= amount;
+        balance.held += amount;
+        self.deposits.swap_remove_back(index);
+        self.disputes.push((client, tx, amount));
+        Some(Transaction::new(client, tx, TransactionType::Dispute))
+    }
+
+    /// Resolves or charges back one of the open disputes.
+    fn settle(&mut self) -> Transaction {
+        let index = self.rng.below(self.disputes.len() as u64) as usize;
+        let (client, tx, amount) = self.disputes.swap_remove(index);
+        let chargeback = self.rng.chance(self.chargeback_ratio);
+        let balance = self.balance(client);
+        balance.held -= amount;
+        if chargeback {
+            balance.locked = true;
+            self.deposits
+                .retain(|(deposit_client, _, _)| *deposit_client != client);
+            Transaction::new(client, tx, TransactionType::Chargeback)
+        } else {
+            balance.available += amount;
+            Transaction::new(client, tx, TransactionType::Resolve)
+        }
+    }
+}
+
+impl Iterator for StreamGenerator {
+    type Item = Transaction;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if !self.disputes.is_empty() && self.rng.chance(self.dispute_probability) {
+            return Some(self.settle());
+        }
+        if !self.deposits.is_empty() && self.rng.chance(self.dispute_probability) {
+            if let Some(dispute) = self.dispute() {
+                return Some(dispute);
+            }
+        }
+
+        let tx = self.next_tx?;
+        let client = self.unlocked_client()?;
+        self.next_tx = tx.checked_add(1);
+
+        let withdrawal = self.rng.chance(self.withdrawal_ratio);
+        let available = self.balance(client).available;
+        if withdrawal && available > 0 {
+            let amount = self.rng.below(available as u64) as i64 + 1;
+            self.balance(client).available -= amount;
+            return Some(Transaction::new(
+                client,
+                tx,
+                TransactionType::Withdrawal {
+                    amount: Self::amount(amount),
+                },
+            ));
+        }
+
+        let amount = self.rng.below(self.max_deposit as u64) as i64 + 1;
+        let balance = self.balance(client);
+        let Some(available) = balance.available.checked_add(amount) else {
+            // Too much to hold (with a very large maximum)
+            return self.next();
+        };
+        balance.available = available;
+        if self.deposits.len() == DISPUTABLE_DEPOSITS {
+            self.deposits.pop_front();
+        }
+        self.deposits.push_back((client, tx, amount));
+        Some(Transaction::new(
+            client,
+            tx,
+            TransactionType::Deposit {
+                amount: Self::amount(amount),
+            },
+        ))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::io::csv::CSVReader;
+    use crate::testkit::StreamGenerator;
+    use crate::{Transaction, TransactionProcessor, TransactionType};
+
+    #[test]
+    fn test_stream_generator() {
+        let generator = || {
+            StreamGenerator::new(7)
+                .with_clients(20)
+                .with_withdrawal_ratio(0.4)
+                .with_dispute_probability(0.2)
+                .with_chargeback_ratio(0.25)
+        };
+        let transactions = generator().take(500).collect::<Vec<_>>();
+        assert_eq!(transactions, generator().take(500).collect::<Vec<_>>());
+        assert_ne!(
+            transactions,
+            StreamGenerator::new(8).take(500).collect::<Vec<_>>()
+        );
+
+        // Every transaction is accepted, and the stream has every type
+        let mut transaction_processor = TransactionProcessor::new();
+        for transaction in &transactions {
+            transaction_processor.transact(transaction).unwrap();
+        }
+        let has = |f: fn(&Transaction) -> bool| transactions.iter().any(f);
+        assert!(has(|t| matches!(
+            t.transaction_type(),
+            TransactionType::Withdrawal { .. }
+        )));
+        assert!(has(|t| matches!(
+            t.transaction_type(),
+            TransactionType::Resolve
+        )));
+        assert!(has(|t| matches!(
+            t.transaction_type(),
+            TransactionType::Chargeback
+        )));
+        assert!(transactions.iter().all(|t| (1..=20).contains(&t.client())));
+
+        let mut csv = Vec::new();
+        assert_eq!(500, generator().write_csv(500, &mut csv).unwrap());
+        let read = CSVReader::new(csv.as_slice())
+            .read()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(transactions, read);
+    }
+}
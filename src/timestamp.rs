@@ -0,0 +1,368 @@
+use core::fmt::{Display, Formatter};
+use core::num::NonZeroU64;
+use core::str::FromStr;
+
+#[cfg(feature = "serde")]
+use alloc::string::{String, ToString};
+#[cfg(feature = "serde")]
+use serde::de::Error;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// The number of seconds in a day.
+const SECONDS_PER_DAY: i64 = 86_400;
+
+/// Error occurring when parsing a string to a timestamp.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum TimestampParseError {
+    /// The string is not a date, a date and time, or a number of seconds.
+    InvalidFormat,
+    /// A field of the date or time is out of range (e.g. the 13th month), or
+    /// the timestamp is before the year 0 or after the year 9999.
+    OutOfRange,
+}
+
+impl Display for TimestampParseError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        f.write_str(match self {
+            TimestampParseError::InvalidFormat => "Invalid format",
+            TimestampParseError::OutOfRange => "Out of range",
+        })
+    }
+}
+
+/// A point in time, to the second, in UTC.
+///
+/// Timestamps are parsed from RFC 3339 dates and times (e.g.
+/// `2024-03-01T12:30:00Z`, or with an offset such as `+01:00`, which is
+/// converted to UTC), dates (`2024-03-01`, i.e. midnight UTC), or whole
+/// seconds since the Unix epoch (`1709296200`). A date and time without an
+/// offset is taken to be UTC, and fractions of a second are ignored. They
+/// are formatted in RFC 3339, in UTC.
+///
+/// With the `serde` feature, timestamps are represented as strings.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct Timestamp {
+    /// Seconds since 1970-01-01T00:00:00Z.
+    seconds: i64,
+}
+
+impl Timestamp {
+    /// The earliest timestamp, `0000-01-01T00:00:00Z`.
+    pub const MIN: Self = Self {
+        seconds: -62_167_219_200,
+    };
+    /// The latest timestamp, `9999-12-31T23:59:59Z`.
+    pub const MAX: Self = Self {
+        seconds: 253_402_300_799,
+    };
+
+    /// Creates a timestamp from the number of seconds since the Unix epoch,
+    /// if it is between [`Timestamp::MIN`] and [`Timestamp::MAX`].
+    #[must_use]
+    pub const fn from_unix_seconds(seconds: i64) -> Option<Self> {
+        if seconds < Self::MIN.seconds || seconds > Self::MAX.seconds {
+            None
+        } else {
+            Some(Self { seconds })
+        }
+    }
+
+    /// The number of seconds since the Unix epoch.
+    #[must_use]
+    pub const fn unix_seconds(&self) -> i64 {
+        self.seconds
+    }
+
+    /// Returns the start of the period of `period_seconds` seconds (counted
+    /// from the Unix epoch) which this timestamp falls in, e.g. the start of
+    /// its day for 86,400. A period which starts before [`Timestamp::MIN`]
+    /// is truncated to it.
+    #[must_use]
+    pub const fn truncate(self, period_seconds: NonZeroU64) -> Self {
+        // Every period fits in an i128, however long
+        let seconds = self.seconds as i128;
+        let start = seconds - seconds.rem_euclid(period_seconds.get() as i128);
+        if start < Self::MIN.seconds as i128 {
+            Self::MIN
+        } else {
+            Self {
+                seconds: start as i64,
+            }
+        }
+    }
+
+    /// The date of the timestamp, as a displayable `YYYY-MM-DD`.
+    #[must_use]
+    pub const fn date(&self) -> TimestampDate {
+        TimestampDate(*self)
+    }
+}
+
+/// The date of a [`Timestamp`], which is displayed as `YYYY-MM-DD`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct TimestampDate(Timestamp);
+
+impl Display for TimestampDate {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        let (year, month, day) = civil_from_days(self.0.seconds.div_euclid(SECONDS_PER_DAY));
+        write!(f, "{:04}-{:02}-{:02}", year, month, day)
+    }
+}
+
+impl Display for Timestamp {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        let seconds = self.seconds.rem_euclid(SECONDS_PER_DAY);
+        write!(
+            f,
+            "{}T{:02}:{:02}:{:02}Z",
+            self.date(),
+            seconds / 3600,
+            seconds / 60 % 60,
+            seconds % 60
+        )
+    }
+}
+
+impl FromStr for Timestamp {
+    type Err = TimestampParseError;
+
+    fn from_str(value: &str) -> Result<Self, TimestampParseError> {
+        if !value.is_empty() && value.bytes().all(|byte| byte.is_ascii_digit()) {
+            return value
+                .parse()
+                .ok()
+                .and_then(Self::from_unix_seconds)
+                .ok_or(TimestampParseError::OutOfRange);
+        }
+
+        let (date, time) = match value.get(..10).zip(value.get(10..)) {
+            Some(split) => split,
+            None => return Err(TimestampParseError::InvalidFormat),
+        };
+        let days = parse_date(date)?;
+        let seconds = match time.as_bytes() {
+            [] => 0,
+            [b'T' | b't' | b' ', ..] => parse_time(&time[1..])?,
+            _ => return Err(TimestampParseError::InvalidFormat),
+        };
+
+        Self::from_unix_seconds(days * SECONDS_PER_DAY + seconds)
+            .ok_or(TimestampParseError::OutOfRange)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl Serialize for Timestamp {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> Deserialize<'de> for Timestamp {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let value = String::deserialize(deserializer)?;
+        Self::from_str(&value).map_err(D::Error::custom)
+    }
+}
+
+/// Parses a number made up of exactly `length` ASCII digits.
+fn parse_digits(value: &str, length: usize) -> Result<i64, TimestampParseError> {
+    if value.len() != length || !value.bytes().all(|byte| byte.is_ascii_digit()) {
+        return Err(TimestampParseError::InvalidFormat);
+    }
+    value
+        .parse()
+        .map_err(|_| TimestampParseError::InvalidFormat)
+}
+
+/// Parses a `YYYY-MM-DD` date to the number of days since the Unix epoch.
+fn parse_date(date: &str) -> Result<i64, TimestampParseError> {
+    let (year, month, day) = match date.as_bytes() {
+        [_, _, _, _, b'-', _, _, b'-', _, _] => (
+            parse_digits(&date[..4], 4)?,
+            parse_digits(&date[5..7], 2)?,
+            parse_digits(&date[8..], 2)?,
+        ),
+        _ => return Err(TimestampParseError::InvalidFormat),
+    };
+
+    let leap_year = year % 4 == 0 && (year % 100 != 0 || year % 400 == 0);
+    let days_in_month = match month {
+        2 if leap_year => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        1..=12 => 31,
+        _ => return Err(TimestampParseError::OutOfRange),
+    };
+    if day < 1 || day > days_in_month {
+        return Err(TimestampParseError::OutOfRange);
+    }
+    Ok(days_from_civil(year, month, day))
+}
+
+/// Parses a `HH:MM[:SS[.fraction]][offset]` time to the number of seconds
+/// since midnight UTC, which may be outside of the day given the offset.
+fn parse_time(time: &str) -> Result<i64, TimestampParseError> {
+    if !time.is_ascii() {
+        return Err(TimestampParseError::InvalidFormat);
+    }
+    let offset_start = time.find(['Z', 'z', '+', '-']).unwrap_or(time.len());
+    let (time, offset) = time.split_at(offset_start);
+    // Fractions of a second are ignored
+    let time = time.split_once('.').map_or(time, |(time, _)| time);
+
+    let (hours, minutes, seconds) = match time.len() {
+        5 => (&time[..2], &time[3..5], None),
+        8 => (&time[..2], &time[3..5], Some(&time[6..])),
+        _ => return Err(TimestampParseError::InvalidFormat),
+    };
+    if time.as_bytes()[2] != b':' || (seconds.is_some() && time.as_bytes()[5] != b':') {
+        return Err(TimestampParseError::InvalidFormat);
+    }
+    let (hours, minutes) = (parse_digits(hours, 2)?, parse_digits(minutes, 2)?);
+    let seconds = seconds.map_or(Ok(0), |seconds| parse_digits(seconds, 2))?;
+    if hours > 23 || minutes > 59 || seconds > 59 {
+        return Err(TimestampParseError::OutOfRange);
+    }
+
+    let offset = match offset.as_bytes() {
+        [] | [b'Z' | b'z'] => 0,
+        [sign @ (b'+' | b'-'), _, _, b':', _, _] => {
+            let (hours, minutes) = (
+                parse_digits(&offset[1..3], 2)?,
+                parse_digits(&offset[4..], 2)?,
+            );
+            if hours > 23 || minutes > 59 {
+                return Err(TimestampParseError::OutOfRange);
+            }
+            let offset = hours * 3600 + minutes * 60;
+            if *sign == b'-' {
+                -offset
+            } else {
+                offset
+            }
+        }
+        _ => return Err(TimestampParseError::InvalidFormat),
+    };
+
+    // A time ahead of UTC is converted by going back by the offset
+    Ok(hours * 3600 + minutes * 60 + seconds - offset)
+}
+
+/// Converts a date in the proleptic Gregorian calendar to the number of days
+/// since the Unix epoch.
+const fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let month_index = if month > 2 { month - 3 } else { month + 9 };
+    let day_of_year = (153 * month_index + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// Converts a number of days since the Unix epoch to a date in the
+/// proleptic Gregorian calendar, as `(year, month, day)`.
+const fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400;
+    (if month <= 2 { year + 1 } else { year }, month, day)
+}
+
+#[cfg(test)]
+mod test {
+    use alloc::string::ToString;
+    use core::num::NonZeroU64;
+    use core::str::FromStr;
+
+    use crate::timestamp::{Timestamp, TimestampParseError};
+
+    #[test]
+    fn test_parse_timestamp() {
+        let parse = |value| Timestamp::from_str(value).map(|timestamp| timestamp.unix_seconds());
+
+        assert_eq!(Ok(0), parse("1970-01-01"));
+        assert_eq!(Ok(1_709_296_200), parse("2024-03-01T12:30:00Z"));
+        assert_eq!(Ok(1_709_296_200), parse("2024-03-01 12:30:00"));
+        assert_eq!(Ok(1_709_296_200), parse("2024-03-01T12:30"));
+        assert_eq!(Ok(1_709_296_200), parse("2024-03-01t12:30:00.999z"));
+        assert_eq!(Ok(1_709_296_200), parse("2024-03-01T13:30:00+01:00"));
+        assert_eq!(Ok(1_709_296_200), parse("2024-03-01T02:00:00-10:30"));
+        assert_eq!(Ok(1_709_296_200), parse("1709296200"));
+        assert_eq!(Ok(-86_400), parse("1969-12-31"));
+        assert_eq!(Ok(Timestamp::MIN.unix_seconds()), parse("0000-01-01"));
+        assert_eq!(Ok(951_782_400), parse("2000-02-29"));
+
+        assert_eq!(Err(TimestampParseError::OutOfRange), parse("2023-02-29"));
+        assert_eq!(Err(TimestampParseError::OutOfRange), parse("2024-13-01"));
+        assert_eq!(
+            Err(TimestampParseError::OutOfRange),
+            parse("2024-03-01T24:00")
+        );
+        assert_eq!(
+            Err(TimestampParseError::OutOfRange),
+            parse("99999999999999999999")
+        );
+        for value in [
+            "",
+            "-1",
+            "2024-3-01",
+            "2024/03/01",
+            "2024-03-01T",
+            "2024-03-01T12",
+            "2024-03-01T12:30:00+01",
+            "2024-03-01Z",
+            "yesterday",
+        ] {
+            assert_eq!(Err(TimestampParseError::InvalidFormat), parse(value));
+        }
+    }
+
+    #[test]
+    fn test_display_timestamp() {
+        let timestamp = Timestamp::from_str("2024-02-29T23:59:59+00:00").unwrap();
+        assert_eq!("2024-02-29T23:59:59Z", timestamp.to_string());
+        assert_eq!("2024-02-29", timestamp.date().to_string());
+        let period = |seconds| NonZeroU64::new(seconds).unwrap();
+        assert_eq!(
+            "2024-02-29T23:00:00Z",
+            timestamp.truncate(period(3600)).to_string()
+        );
+        assert_eq!(0, timestamp.truncate(period(u64::MAX)).unix_seconds());
+        assert_eq!("0000-01-01T00:00:00Z", Timestamp::MIN.to_string());
+        assert_eq!("9999-12-31T23:59:59Z", Timestamp::MAX.to_string());
+        assert_eq!(
+            "1969-12-31T00:00:00Z",
+            Timestamp::from_unix_seconds(-1)
+                .unwrap()
+                .truncate(period(86_400))
+                .to_string()
+        );
+        assert_eq!(
+            Timestamp::MIN,
+            Timestamp::from_unix_seconds(-1)
+                .unwrap()
+                .truncate(period(u64::MAX))
+        );
+    }
+}
@@ -0,0 +1,436 @@
+use std::collections::{HashMap, VecDeque};
+use std::io::{self, IsTerminal, Write};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use ratatui::backend::CrosstermBackend;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use ratatui::crossterm::execute;
+use ratatui::crossterm::terminal::{
+    disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen,
+};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Modifier, Style};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, Paragraph, Row, Table, Wrap};
+use ratatui::{Frame, Terminal};
+use transaction_processor::{ClientId, ReportEntry};
+
+/// How often the dashboard is redrawn.
+const REDRAW_INTERVAL: Duration = Duration::from_millis(250);
+
+/// How many recent errors and log lines are kept for display.
+const MAX_RECENT: usize = 100;
+
+/// The live state of a run, shown by the `--tui` dashboard. Updated by the
+/// threads processing files (through [`crate::ProcessOptions`]), and read by
+/// the thread drawing the dashboard.
+#[derive(Clone, Debug, Default)]
+pub struct Dashboard(Arc<Mutex<State>>);
+
+#[derive(Debug, Default)]
+struct State {
+    /// False until the dashboard is shown, and once it has been closed, so
+    /// that log messages go to stderr instead.
+    active: bool,
+    finished: bool,
+    /// Whether the dashboard was closed before the run finished.
+    interrupted: bool,
+    started: Option<Instant>,
+    files_read: usize,
+    current_file: Option<String>,
+    applied: usize,
+    parse_errors: usize,
+    transaction_errors: usize,
+    /// The latest report entry of every account which has had a
+    /// transaction applied.
+    accounts: HashMap<ClientId, ReportEntry>,
+    recent_errors: VecDeque<String>,
+    log: VecDeque<String>,
+}
+
+impl Dashboard {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records that a file has started being read.
+    pub fn record_file(&self, path: &str) {
+        let mut state = self.state();
+        state.files_read += 1;
+        state.current_file = Some(path.to_string());
+    }
+
+    /// Records a transaction which was applied, and the resulting state of
+    /// its account.
+    pub fn record_applied(&self, account: Option<ReportEntry>) {
+        let mut state = self.state();
+        state.applied += 1;
+        if let Some(account) = account {
+            state.accounts.insert(account.client(), account);
+        }
+    }
+
+    /// Records a row which failed to be read (if `parse_error`) or applied.
+    pub fn record_rejected(&self, reason: String, parse_error: bool) {
+        let mut state = self.state();
+        if parse_error {
+            state.parse_errors += 1;
+        } else {
+            state.transaction_errors += 1;
+        }
+        push_recent(&mut state.recent_errors, reason);
+    }
+
+    /// Whether the dashboard was closed before the run finished, in which
+    /// case the run stops with [`crate::exit_code::ExitCode::Interrupted`].
+    pub fn interrupted(&self) -> bool {
+        self.state().interrupted
+    }
+
+    /// Records that the dashboard was closed before the run finished.
+    pub fn interrupt(&self) {
+        self.state().interrupted = true;
+    }
+
+    /// A writer for the logger, which shows each line in the dashboard while
+    /// it is open, and writes it to stderr otherwise.
+    pub fn log_writer(&self) -> LogWriter {
+        LogWriter(self.clone())
+    }
+
+    fn state(&self) -> MutexGuard<'_, State> {
+        self.0.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+fn push_recent(recent: &mut VecDeque<String>, line: String) {
+    if recent.len() == MAX_RECENT {
+        recent.pop_front();
+    }
+    recent.push_back(line);
+}
+
+/// See [`Dashboard::log_writer`].
+pub struct LogWriter(Dashboard);
+
+impl Write for LogWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut state = self.0.state();
+        if !state.active {
+            return io::stderr().write(buf);
+        }
+
+        for line in String::from_utf8_lossy(buf).lines() {
+            push_recent(&mut state.log, line.to_string());
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        io::stderr().flush()
+    }
+}
+
+/// The thread drawing the dashboard.
+pub struct DashboardThread {
+    dashboard: Dashboard,
+    finish: mpsc::Sender<()>,
+    thread: thread::JoinHandle<io::Result<()>>,
+}
+
+impl DashboardThread {
+    /// Whether the dashboard has been closed before the run finished.
+    pub fn interrupted(&self) -> bool {
+        self.dashboard.interrupted()
+    }
+
+    /// Shows that the run has finished, and waits for the dashboard to be
+    /// closed with `q`.
+    pub fn finish(self) -> io::Result<()> {
+        self.dashboard.state().finished = true;
+        // This fails if the thread has already stopped with an error
+        let _ = self.finish.send(());
+        self.thread
+            .join()
+            .unwrap_or_else(|_| Err(io::Error::other("Dashboard thread panicked")))
+    }
+}
+
+/// Shows the dashboard on stderr (so that the report can still be written
+/// to stdout), redrawing it until [`DashboardThread::finish`] is called.
+///
+/// Pressing `q`, `Esc` or `Ctrl-C` closes the dashboard. Before the run has
+/// finished, this interrupts the run (see [`Dashboard::interrupted`]), as
+/// the terminal doesn't send `SIGINT` while the dashboard is open.
+pub fn start(dashboard: Dashboard) -> io::Result<DashboardThread> {
+    if !io::stderr().is_terminal() {
+        return Err(io::Error::other("stderr is not a terminal"));
+    }
+
+    enable_raw_mode()?;
+    if let Err(err) = execute!(io::stderr(), EnterAlternateScreen) {
+        let _ = disable_raw_mode();
+        return Err(err);
+    }
+    let terminal = Terminal::new(CrosstermBackend::new(io::stderr()));
+    {
+        let mut state = dashboard.state();
+        state.active = true;
+        state.started = Some(Instant::now());
+    }
+
+    let (finish, finished) = mpsc::channel();
+    let thread = {
+        let dashboard = dashboard.clone();
+        thread::spawn(move || {
+            let result =
+                terminal.and_then(|mut terminal| run(&dashboard, &mut terminal, &finished));
+            restore(&dashboard);
+            if let Ok(false) = result {
+                dashboard.interrupt();
+            }
+            result.map(|_| ())
+        })
+    };
+
+    Ok(DashboardThread {
+        dashboard,
+        finish,
+        thread,
+    })
+}
+
+/// Draws the dashboard until it is closed. Returns whether the run had
+/// finished.
+fn run(
+    dashboard: &Dashboard,
+    terminal: &mut Terminal<CrosstermBackend<io::Stderr>>,
+    finished: &mpsc::Receiver<()>,
+) -> io::Result<bool> {
+    let mut run_finished = false;
+    loop {
+        terminal.draw(|frame| draw(frame, &dashboard.state()))?;
+
+        if event::poll(REDRAW_INTERVAL)? {
+            if let Event::Key(key) = event::read()? {
+                let ctrl_c =
+                    key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL);
+                if key.kind == KeyEventKind::Press
+                    && (matches!(key.code, KeyCode::Char('q') | KeyCode::Esc) || ctrl_c)
+                {
+                    return Ok(run_finished);
+                }
+            }
+        }
+
+        if !run_finished {
+            match finished.recv_timeout(Duration::ZERO) {
+                Ok(()) | Err(RecvTimeoutError::Disconnected) => run_finished = true,
+                Err(RecvTimeoutError::Timeout) => {}
+            }
+        }
+    }
+}
+
+fn restore(dashboard: &Dashboard) {
+    dashboard.state().active = false;
+    if let Err(err) = disable_raw_mode().and_then(|()| execute!(io::stderr(), LeaveAlternateScreen))
+    {
+        eprintln!("Failed to restore the terminal: {}", err);
+    }
+}
+
+fn draw(frame: &mut Frame, state: &State) {
+    let [top, accounts, errors, log] = Layout::vertical([
+        Constraint::Length(9),
+        Constraint::Min(5),
+        Constraint::Length(8),
+        Constraint::Length(6),
+    ])
+    .areas(frame.area());
+    let [counts, locked] =
+        Layout::horizontal([Constraint::Percentage(50), Constraint::Percentage(50)]).areas(top);
+
+    draw_counts(frame, counts, state);
+    draw_locked(frame, locked, state);
+    draw_top_accounts(frame, accounts, state);
+    draw_recent(frame, errors, "Recent errors", &state.recent_errors);
+    draw_recent(frame, log, "Log", &state.log);
+}
+
+fn draw_counts(frame: &mut Frame, area: Rect, state: &State) {
+    let elapsed = state
+        .started
+        .map(|started| started.elapsed())
+        .unwrap_or_default();
+    let rows = state.applied + state.parse_errors + state.transaction_errors;
+    let rate = if elapsed.as_secs_f64() > 0.0 {
+        rows as f64 / elapsed.as_secs_f64()
+    } else {
+        0.0
+    };
+    let title = if state.finished {
+        " Finished (press q to exit) "
+    } else {
+        " Processing (press q to stop) "
+    };
+
+    let lines = vec![
+        Line::from(format!("Files read: {}", state.files_read)),
+        Line::from(format!(
+            "Current file: {}",
+            state.current_file.as_deref().unwrap_or("-")
+        )),
+        Line::from(format!("Rows: {} ({:.0}/s)", rows, rate)),
+        Line::from(format!("Transactions applied: {}", state.applied)),
+        Line::from(format!("Parse errors: {}", state.parse_errors)),
+        Line::from(format!(
+            "Transactions rejected: {}",
+            state.transaction_errors
+        )),
+        Line::from(format!("Elapsed: {:.1?}", elapsed)),
+    ];
+    frame.render_widget(
+        Paragraph::new(lines).block(Block::bordered().title(title)),
+        area,
+    );
+}
+
+fn draw_locked(frame: &mut Frame, area: Rect, state: &State) {
+    let mut locked: Vec<_> = state
+        .accounts
+        .values()
+        .filter(|entry| entry.locked())
+        .map(|entry| entry.client())
+        .collect();
+    locked.sort_unstable();
+
+    let clients = locked
+        .iter()
+        .map(ClientId::to_string)
+        .collect::<Vec<_>>()
+        .join(", ");
+    frame.render_widget(
+        Paragraph::new(clients)
+            .wrap(Wrap { trim: true })
+            .block(Block::bordered().title(format!(" Locked accounts ({}) ", locked.len()))),
+        area,
+    );
+}
+
+fn draw_top_accounts(frame: &mut Frame, area: Rect, state: &State) {
+    // Borders and the header row
+    let count = usize::from(area.height.saturating_sub(3));
+    let mut accounts: Vec<_> = state.accounts.values().collect();
+    accounts.sort_unstable_by(|a, b| b.total().cmp(&a.total()).then(a.client().cmp(&b.client())));
+
+    let rows = accounts.into_iter().take(count).map(|entry| {
+        Row::new([
+            entry.client().to_string(),
+            entry.available().to_string(),
+            entry.held().to_string(),
+            entry.total().to_string(),
+            entry.locked().to_string(),
+        ])
+    });
+    let header = Row::new(["client", "available", "held", "total", "locked"])
+        .style(Style::default().add_modifier(Modifier::BOLD));
+    frame.render_widget(
+        Table::new(rows, [Constraint::Ratio(1, 5); 5])
+            .header(header)
+            .block(Block::bordered().title(" Top accounts by total ")),
+        area,
+    );
+}
+
+/// Draws the most recent lines which fit.
+fn draw_recent(frame: &mut Frame, area: Rect, title: &str, recent: &VecDeque<String>) {
+    let count = usize::from(area.height.saturating_sub(2));
+    let lines: Vec<_> = recent
+        .iter()
+        .skip(recent.len().saturating_sub(count))
+        .map(|line| Line::from(line.as_str()))
+        .collect();
+    frame.render_widget(
+        Paragraph::new(lines).block(Block::bordered().title(format!(" {} ", title))),
+        area,
+    );
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+
+    use ratatui::backend::TestBackend;
+    use ratatui::Terminal;
+    use transaction_processor::numeric::CurrencyAmount;
+    use transaction_processor::{
+        Transaction, TransactionId, TransactionProcessor, TransactionType,
+    };
+
+    use crate::tui::{draw, Dashboard};
+
+    #[test]
+    fn test_draw() {
+        let dashboard = Dashboard::new();
+        let mut transaction_processor = TransactionProcessor::new();
+        dashboard.record_file("transactions.csv");
+        for (client, amount) in [(1, "1.5"), (2, "20"), (3, "3")] {
+            let amount = CurrencyAmount::from_str(amount).unwrap();
+            transaction_processor
+                .transact(&Transaction::new(
+                    client,
+                    TransactionId::from(client),
+                    TransactionType::Deposit { amount },
+                ))
+                .unwrap();
+            dashboard.record_applied(transaction_processor.account_report(client));
+        }
+        for transaction_type in [TransactionType::Dispute, TransactionType::Chargeback] {
+            transaction_processor
+                .transact(&Transaction::new(3, 3, transaction_type))
+                .unwrap();
+            dashboard.record_applied(transaction_processor.account_report(3));
+        }
+        dashboard.record_rejected("Not enough funds".to_string(), false);
+        dashboard.record_rejected("Missing amount".to_string(), true);
+
+        let mut terminal = Terminal::new(TestBackend::new(80, 30)).unwrap();
+        terminal
+            .draw(|frame| draw(frame, &dashboard.state()))
+            .unwrap();
+        let lines: Vec<String> = terminal
+            .backend()
+            .buffer()
+            .content
+            .chunks(80)
+            .map(|line| line.iter().map(|cell| cell.symbol()).collect())
+            .collect();
+        let screen = lines.join("\n");
+
+        for text in [
+            "Files read: 1",
+            "Current file: transactions.csv",
+            "Transactions applied: 5",
+            "Parse errors: 1",
+            "Transactions rejected: 1",
+            "Locked accounts (1)",
+            "Not enough funds",
+            "Missing amount",
+        ] {
+            assert!(screen.contains(text), "Missing {:?} in:\n{}", text, screen);
+        }
+
+        // Accounts are ordered by total, largest first
+        let header = lines
+            .iter()
+            .position(|line| line.contains("client"))
+            .unwrap();
+        let position = |text: &str| lines[header..].iter().position(|line| line.contains(text));
+        assert!(position("│2 ") < position("│1 "));
+        assert!(position("│1 ") < position("│3 "));
+    }
+}
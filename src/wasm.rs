@@ -0,0 +1,123 @@
+use wasm_bindgen::prelude::*;
+
+use crate::io::{AmountFormat, RawEntry};
+use crate::{ReportEntry, TransactionProcessor};
+
+/// A [`TransactionProcessor`] for JavaScript, exported as
+/// `TransactionProcessor`.
+///
+/// ```js
+/// const processor = new TransactionProcessor();
+/// processor.transact({ type: "deposit", client: 1, tx: 1, amount: "1.5" });
+/// console.log(processor.reportJson());
+/// ```
+#[wasm_bindgen(js_name = TransactionProcessor)]
+pub struct WasmTransactionProcessor(TransactionProcessor);
+
+#[wasm_bindgen(js_class = TransactionProcessor)]
+impl WasmTransactionProcessor {
+    /// Creates a processor with no client accounts.
+    #[wasm_bindgen(constructor)]
+    #[allow(clippy::new_without_default)]
+    #[must_use]
+    pub fn new() -> Self {
+        Self(TransactionProcessor::new())
+    }
+
+    /// Applies a transaction given as an object in the JSON Lines input
+    /// format, e.g. `{type: "deposit", client: 1, tx: 2, amount: "1.5"}`.
+    /// Amounts must be strings, so that no precision is lost.
+    ///
+    /// # Errors
+    ///
+    /// Throws an `Error` with the reason if the object is not a valid
+    /// transaction, or the transaction could not be applied.
+    pub fn transact(&mut self, transaction: JsValue) -> Result<(), JsError> {
+        let entry: RawEntry = serde_wasm_bindgen::from_value(transaction).map_err(|err| {
+            // The message is that of a JavaScript `Error`
+            let message = err.to_string();
+            JsError::new(message.strip_prefix("Error: ").unwrap_or(&message))
+        })?;
+        self.apply(entry).map_err(|err| JsError::new(&err))
+    }
+
+    /// Like [`WasmTransactionProcessor::transact`], but with the transaction
+    /// as a JSON string.
+    ///
+    /// # Errors
+    ///
+    /// Throws an `Error` with the reason if the string is not a valid
+    /// transaction, or the transaction could not be applied.
+    #[wasm_bindgen(js_name = transactJson)]
+    pub fn transact_json(&mut self, transaction: &str) -> Result<(), JsError> {
+        self.transact_json_inner(transaction)
+            .map_err(|err| JsError::new(&err))
+    }
+
+    /// The report for all clients, as a JSON array of objects with the same
+    /// fields as the CSV report, e.g.
+    /// `[{"client":1,"available":"1.5","held":"0","total":"1.5","locked":false}]`.
+    #[wasm_bindgen(js_name = reportJson)]
+    #[must_use]
+    pub fn report_json(&self) -> String {
+        report_json(&self.0.generate_report_as_vec())
+    }
+}
+
+impl WasmTransactionProcessor {
+    fn transact_json_inner(&mut self, transaction: &str) -> Result<(), String> {
+        let entry: RawEntry = serde_json::from_str(transaction).map_err(|err| err.to_string())?;
+        self.apply(entry)
+    }
+
+    fn apply(&mut self, entry: RawEntry) -> Result<(), String> {
+        let transaction = entry
+            .into_transaction(AmountFormat::Standard)
+            .map_err(|err| err.to_string())?;
+        self.0.transact(&transaction).map_err(|err| err.to_string())
+    }
+}
+
+fn report_json(report: &[ReportEntry]) -> String {
+    // Report entries only contain strings, numbers and booleans, so cannot
+    // fail to serialize
+    serde_json::to_string(report).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod test {
+    use crate::wasm::WasmTransactionProcessor;
+
+    // Functions taking a `JsValue` only work on wasm32, so the JSON versions
+    // are tested natively
+    #[test]
+    fn test_transact_json() {
+        let mut processor = WasmTransactionProcessor::new();
+        processor
+            .transact_json_inner(r#"{"type":"deposit","client":1,"tx":1,"amount":"1.5"}"#)
+            .unwrap();
+        let amounts = if cfg!(feature = "redact") {
+            ("<redacted>", "<redacted>")
+        } else {
+            ("2", "1.5")
+        };
+        assert_eq!(
+            Err(format!(
+                "Not enough funds for client 1 (withdrawal of {} with {} available)",
+                amounts.0, amounts.1
+            )),
+            processor
+                .transact_json_inner(r#"{"type":"withdrawal","client":1,"tx":2,"amount":"2"}"#)
+        );
+        assert_eq!(
+            Err("Missing amount".to_string()),
+            processor.transact_json_inner(r#"{"type":"deposit","client":1,"tx":3}"#)
+        );
+        assert!(processor.transact_json_inner("{}").is_err());
+
+        assert_eq!(
+            r#"[{"client":1,"available":"1.5","held":"0","total":"1.5","locked":false}]"#,
+            processor.report_json()
+        );
+    }
+}
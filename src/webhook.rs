@@ -0,0 +1,330 @@
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+use serde::Serialize;
+use transaction_processor::alert::BalanceAlert;
+use transaction_processor::{
+    ReportEntry, Transaction, TransactionError, TransactionId, TransactionProcessor,
+    TransactionType,
+};
+
+/// How many times each notification is sent to a webhook before giving up.
+const MAX_ATTEMPTS: u32 = 5;
+
+/// How long to wait before the first retry. The wait doubles after every
+/// further attempt.
+const INITIAL_BACKOFF: Duration = Duration::from_millis(500);
+
+/// How long to wait for a connection to a webhook.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How long to wait for a webhook's response, so that one which accepts the
+/// connection but never responds doesn't stall delivery.
+const READ_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A notification sent to the webhooks.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct Notification {
+    event: Event,
+    /// The transaction which caused the event.
+    tx: TransactionId,
+    /// The state of the account after the transaction.
+    account: ReportEntry,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum Event {
+    /// A chargeback was applied.
+    Chargeback,
+    /// An account which was unlocked became locked.
+    AccountLocked,
+    /// A transaction took the account's available funds below the
+    /// `--alert-floor`.
+    AvailableBelowFloor,
+    /// A transaction took the account's held funds above the
+    /// `--alert-held-ceiling`.
+    HeldAboveCeiling,
+}
+
+/// Sends notifications to the webhooks from a background thread, so that
+/// slow or failing webhooks don't hold up processing.
+#[derive(Clone, Debug)]
+pub struct Notifier(mpsc::Sender<Notification>);
+
+impl Notifier {
+    /// Applies a transaction, notifying the webhooks if it was a chargeback
+    /// or locked the account. Report amounts are written with `output_scale`
+    /// decimal places, if set.
+    pub fn transact(
+        &self,
+        transaction_processor: &mut TransactionProcessor,
+        transaction: &Transaction,
+        output_scale: Option<u32>,
+    ) -> Result<(), TransactionError> {
+        let was_locked = transaction_processor
+            .transaction_client(transaction)
+            .and_then(|client| transaction_processor.account_report(client))
+            .is_some_and(|entry| entry.locked());
+
+        transaction_processor.transact(transaction)?;
+
+        let account = match transaction_processor
+            .transaction_client(transaction)
+            .and_then(|client| transaction_processor.account_report(client))
+        {
+            Some(entry) => match output_scale {
+                Some(scale) => entry.with_scale(scale),
+                None => entry,
+            },
+            // The account's total overflowed, which has already been logged
+            None => return Ok(()),
+        };
+
+        if matches!(transaction.transaction_type(), TransactionType::Chargeback) {
+            self.notify(Event::Chargeback, transaction.tx(), account.clone());
+        }
+        if account.locked() && !was_locked {
+            self.notify(Event::AccountLocked, transaction.tx(), account);
+        }
+
+        Ok(())
+    }
+
+    /// Notifies the webhooks of a balance alert. Report amounts are written
+    /// with `output_scale` decimal places, if set.
+    pub fn alert(&self, alert: &BalanceAlert, output_scale: Option<u32>) {
+        let event = match alert {
+            BalanceAlert::AvailableBelowFloor { .. } => Event::AvailableBelowFloor,
+            BalanceAlert::HeldAboveCeiling { .. } => Event::HeldAboveCeiling,
+        };
+        let account = match output_scale {
+            Some(scale) => alert.account().clone().with_scale(scale),
+            None => alert.account().clone(),
+        };
+        self.notify(event, alert.tx(), account);
+    }
+
+    fn notify(&self, event: Event, tx: TransactionId, account: ReportEntry) {
+        let notification = Notification { event, tx, account };
+        // This only fails if the dispatcher thread has panicked
+        if self.0.send(notification).is_err() {
+            log::error!("Failed to queue webhook notification");
+        }
+    }
+}
+
+/// The background thread which delivers notifications.
+pub struct Dispatcher(thread::JoinHandle<()>);
+
+impl Dispatcher {
+    /// Waits for every queued notification to be delivered (or to fail).
+    /// Every [`Notifier`] must have been dropped, or this never returns.
+    pub fn finish(self) {
+        if self.0.join().is_err() {
+            log::error!("Webhook dispatcher panicked");
+        }
+    }
+}
+
+/// Starts delivering notifications to the webhook `urls`, as JSON in a
+/// `POST` request, e.g.
+/// `{"event":"chargeback","tx":2,"account":{"client":1,"available":"0","held":"0","total":"0","locked":true}}`.
+///
+/// Notifications are delivered in order. Failed requests (other than client
+/// errors, which are not retried) are retried [`MAX_ATTEMPTS`] times with
+/// exponential backoff, after which the notification is dropped. A webhook
+/// whose last notification was dropped is only tried once per notification,
+/// until one succeeds, so that a webhook which is down doesn't delay the
+/// rest.
+pub fn start(urls: Vec<String>) -> (Notifier, Dispatcher) {
+    let (sender, receiver) = mpsc::channel::<Notification>();
+
+    let thread = thread::spawn(move || {
+        let agent = ureq::AgentBuilder::new()
+            .timeout_connect(CONNECT_TIMEOUT)
+            .timeout_read(READ_TIMEOUT)
+            .build();
+        let mut failing = vec![false; urls.len()];
+
+        for notification in receiver {
+            let body = match serde_json::to_string(&notification) {
+                Ok(body) => body,
+                Err(err) => {
+                    log::error!("Failed to serialize webhook notification: {}", err);
+                    continue;
+                }
+            };
+
+            for (url, failing) in urls.iter().zip(&mut failing) {
+                let attempts = if *failing { 1 } else { MAX_ATTEMPTS };
+                *failing = !deliver(&agent, url, &body, attempts);
+            }
+        }
+    });
+
+    (Notifier(sender), Dispatcher(thread))
+}
+
+/// Posts `body` to `url` with `agent`, trying up to `attempts` times.
+/// Returns true if it was delivered.
+fn deliver(agent: &ureq::Agent, url: &str, body: &str, attempts: u32) -> bool {
+    let mut backoff = INITIAL_BACKOFF;
+
+    for attempt in 1..=attempts {
+        let error = match agent
+            .post(url)
+            .set("Content-Type", "application/json")
+            .send_string(body)
+        {
+            Ok(_) => return true,
+            // Client errors won't succeed by retrying, except these
+            Err(ureq::Error::Status(status, _))
+                if (400..500).contains(&status) && status != 408 && status != 429 =>
+            {
+                log::error!("Webhook {} rejected notification with {}", url, status);
+                return false;
+            }
+            Err(err) => err,
+        };
+
+        if attempt == attempts {
+            log::error!(
+                "Failed to deliver notification to webhook {} after {} attempts: {}",
+                url,
+                attempts,
+                error
+            );
+        } else {
+            log::warn!(
+                "Failed to deliver notification to webhook {}: {}",
+                url,
+                error
+            );
+            thread::sleep(backoff);
+            backoff *= 2;
+        }
+    }
+
+    false
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::str::FromStr;
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    use transaction_processor::alert::BalanceAlert;
+    use transaction_processor::numeric::CurrencyAmount;
+    use transaction_processor::{Transaction, TransactionProcessor, TransactionType};
+
+    use crate::webhook::{deliver, start};
+
+    /// Accepts `count` requests, responding to the first `failures` with an
+    /// error, and returns the bodies of the rest.
+    fn serve(listener: TcpListener, count: usize, failures: usize) -> Vec<String> {
+        let mut bodies = Vec::new();
+        for index in 0..count {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream);
+
+            let mut content_length = 0;
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                if let Some(length) = line.to_ascii_lowercase().strip_prefix("content-length:") {
+                    content_length = length.trim().parse().unwrap();
+                }
+                if line == "\r\n" {
+                    break;
+                }
+            }
+            let mut body = vec![0; content_length];
+            reader.read_exact(&mut body).unwrap();
+
+            let status = if index < failures {
+                "503 Service Unavailable"
+            } else {
+                bodies.push(String::from_utf8(body).unwrap());
+                "200 OK"
+            };
+            write!(
+                reader.get_mut(),
+                "HTTP/1.1 {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                status
+            )
+            .unwrap();
+        }
+        bodies
+    }
+
+    #[test]
+    fn test_notifications() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        // The first delivery is retried once
+        let server = thread::spawn(move || serve(listener, 4, 1));
+
+        let (notifier, dispatcher) = start(vec![url]);
+        let mut transaction_processor = TransactionProcessor::new();
+        let amount = CurrencyAmount::from_str("1.5").unwrap();
+        for transaction in [
+            Transaction::new(1, 1, TransactionType::Deposit { amount }),
+            Transaction::new(1, 1, TransactionType::Dispute),
+            Transaction::new(1, 1, TransactionType::Chargeback),
+        ] {
+            notifier
+                .transact(&mut transaction_processor, &transaction, Some(2))
+                .unwrap();
+        }
+        notifier.alert(
+            &BalanceAlert::AvailableBelowFloor {
+                tx: 1,
+                account: transaction_processor.account_report(1).unwrap(),
+                floor: amount,
+            },
+            Some(2),
+        );
+        drop(notifier);
+        dispatcher.finish();
+
+        let account =
+            r#"{"client":1,"available":"0.00","held":"0.00","total":"0.00","locked":true}"#;
+        assert_eq!(
+            vec![
+                format!(r#"{{"event":"chargeback","tx":1,"account":{}}}"#, account),
+                format!(
+                    r#"{{"event":"account_locked","tx":1,"account":{}}}"#,
+                    account
+                ),
+                format!(
+                    r#"{{"event":"available_below_floor","tx":1,"account":{}}}"#,
+                    account
+                ),
+            ],
+            server.join().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_read_timeout() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        // The connection is accepted, but never responded to
+        thread::spawn(move || {
+            let _stream = listener.accept().unwrap();
+            thread::sleep(Duration::from_secs(5));
+        });
+
+        let agent = ureq::AgentBuilder::new()
+            .timeout_read(Duration::from_millis(100))
+            .build();
+        let start = Instant::now();
+        assert!(!deliver(&agent, &url, "{}", 1));
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+}
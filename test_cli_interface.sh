@@ -12,4 +12,18 @@ cargo run -- transactions.csv > accounts.csv
 
 cmp --silent accounts.csv test_data/002_expected.csv
 
-rm transactions.csv accounts.csv
\ No newline at end of file
+# The report is signed with the key from --report-key-file, or from the
+# environment
+printf 'test report key' > report.key
+
+cargo run -- transactions.csv --report-signature accounts.sig --report-key-file report.key > accounts.csv
+
+cmp --silent accounts.csv test_data/002_expected.csv
+cmp --silent accounts.sig test_data/002_expected.sig
+
+rm accounts.sig
+TRANSACTION_PROCESSOR_REPORT_KEY='test report key' cargo run -- transactions.csv --report-signature accounts.sig > accounts.csv
+
+cmp --silent accounts.sig test_data/002_expected.sig
+
+rm transactions.csv accounts.csv accounts.sig report.key
\ No newline at end of file